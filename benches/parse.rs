@@ -0,0 +1,128 @@
+//! Times parsing a generated multi-megabyte JSON document, and compares the ways of allocating
+//! that many nodes into an [`Arena`].  Run with `cargo bench`.
+//!
+//! This doesn't use a benchmarking framework: each measurement is just the fastest of a few runs.
+
+use sapling::arena::Arena;
+use sapling::ast::json::{Dialect, JSON};
+use sapling::ast::Ast;
+use std::time::{Duration, Instant};
+
+/// The number of times that each measurement is repeated
+const RUNS: usize = 5;
+/// The size of the generated document, in bytes
+const DOCUMENT_SIZE: usize = 8 << 20;
+
+/// Generates a pretty-printed array of records, which is at least `size` bytes long
+fn generate_document(size: usize) -> String {
+    let mut text = "[\n".to_string();
+    let mut i = 0;
+    while text.len() < size {
+        if i > 0 {
+            text.push_str(",\n");
+        }
+        text.push_str(&format!(
+            concat!(
+                "  {{\n",
+                "    \"id\": {},\n",
+                "    \"name\": \"item {}\",\n",
+                "    \"tags\": [\"red\", \"green\", {}],\n",
+                "    \"active\": {},\n",
+                "    \"position\": {{ \"x\": {}.5, \"y\": -{}e3, \"z\": null }}\n",
+                "  }}"
+            ),
+            i,
+            i,
+            i % 7,
+            i % 2 == 0,
+            i % 100,
+            i % 13,
+        ));
+        i += 1;
+    }
+    text.push_str("\n]\n");
+    text
+}
+
+/// Runs `f` [`RUNS`] times, returning the fastest time along with the result of the last run
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut fastest = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let value = f();
+        fastest = fastest.min(start.elapsed());
+        result = Some(value);
+    }
+    (fastest, result.unwrap())
+}
+
+fn report(name: &str, duration: Duration) {
+    println!("{:<40} {:>10.2?}", name, duration);
+}
+
+/// Prints the number of bytes that a tree takes up, in MiB
+fn report_memory(bytes: usize) {
+    println!("  {:.1} MiB of memory", bytes as f64 / (1 << 20) as f64);
+}
+
+fn main() {
+    let text = generate_document(DOCUMENT_SIZE);
+    println!("Document: {} bytes", text.len());
+
+    let (duration, (node_count, bytes)) = time(|| {
+        let arena = Arena::new();
+        let root = JSON::parse(&text, &arena).unwrap();
+        assert_eq!(root.node_count(), arena.len());
+        (arena.len(), arena.bytes_allocated())
+    });
+    let megabytes = text.len() as f64 / (1 << 20) as f64;
+    report("parse", duration);
+    println!(
+        "  {} nodes, {:.1} bytes/node, {:.1} MiB/s",
+        node_count,
+        text.len() as f64 / node_count as f64,
+        megabytes / duration.as_secs_f64()
+    );
+    report_memory(bytes);
+
+    // Large-file mode only makes nodes for the root and its children, and keeps one copy of the
+    // text for the rest
+    let (duration, (lazy_node_count, lazy_bytes)) = time(|| {
+        let arena = Arena::new();
+        JSON::parse_large(&text, &arena, Dialect::JSON).unwrap();
+        (arena.len(), arena.bytes_allocated() + text.len())
+    });
+    report("parse in large-file mode", duration);
+    println!(
+        "  {} nodes, {:.1} MiB/s",
+        lazy_node_count,
+        megabytes / duration.as_secs_f64()
+    );
+    report_memory(lazy_bytes);
+
+    // Allocate the same number of nodes in each of the ways that an arena allows
+    let node = || JSON::Null;
+    let (duration, _) = time(|| {
+        let arena = Arena::new();
+        for _ in 0..node_count {
+            arena.alloc(node());
+        }
+        arena.len()
+    });
+    report("alloc, growing the arena", duration);
+    let (duration, _) = time(|| {
+        let arena = Arena::with_capacity(node_count);
+        for _ in 0..node_count {
+            arena.alloc(node());
+        }
+        arena.len()
+    });
+    report("alloc, with_capacity", duration);
+    let (duration, _) = time(|| {
+        let arena = Arena::new();
+        arena.alloc_extend((0..node_count).map(|_| node()));
+        arena.len()
+    });
+    report("alloc_extend", duration);
+}
@@ -0,0 +1,106 @@
+//! Times rendering a large generated JSON tree, and measuring it with and without a
+//! [`SizeCache`].  Run with `cargo bench --bench render`.
+//!
+//! Like `benches/parse.rs`, this doesn't use a benchmarking framework: each measurement is just
+//! the fastest of a few runs.
+
+use sapling::arena::Arena;
+use sapling::ast::display_token::{write_tokens, DisplayToken, IndentKind};
+use sapling::ast::json::{JSONFormat, JSON};
+use sapling::ast::size::SizeCache;
+use sapling::ast::Ast;
+use std::time::{Duration, Instant};
+
+/// The number of times that each measurement is repeated
+const RUNS: usize = 20;
+/// The number of records in the root array
+const RECORDS: usize = 5_000;
+
+/// Generates the text of an array of `records` nested records
+fn generate_document(records: usize) -> String {
+    let records: Vec<String> = (0..records)
+        .map(|i| {
+            format!(
+                concat!(
+                    "{{\"id\": {}, \"name\": \"item {}\", \"tags\": [\"red\", {}, null], ",
+                    "\"children\": [{{\"x\": {}.5, \"y\": [true, false]}}, {{\"z\": \"日本\"}}]}}"
+                ),
+                i,
+                i,
+                i % 7,
+                i % 100
+            )
+        })
+        .collect();
+    format!("[{}]", records.join(", "))
+}
+
+/// Runs `f` [`RUNS`] times, returning the fastest time
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let value = f();
+        fastest = fastest.min(start.elapsed());
+        drop(value);
+    }
+    fastest
+}
+
+fn report(name: &str, duration: Duration) {
+    println!("{:<40} {:>10.2?}", name, duration);
+}
+
+fn main() {
+    let text = generate_document(RECORDS);
+    let arena = Arena::new();
+    let root = JSON::parse(&text, &arena).unwrap();
+    let format = JSONFormat::Pretty {
+        indent: IndentKind::default(),
+        trailing_commas: false,
+    };
+    println!("Tree: {} nodes", root.node_count());
+
+    // Collecting every token first (with tokens owning all of their text) is how trees were
+    // rendered before `DisplayTokens` existed
+    let duration = time(|| {
+        let tokens: Vec<(&JSON, DisplayToken)> = root
+            .display_tokens(&format)
+            .map(|(node, tok)| match tok {
+                DisplayToken::Text(s, c) => (node, DisplayToken::Text(s.into_owned().into(), c)),
+                tok => (node, tok),
+            })
+            .collect();
+        let mut text = String::new();
+        for (_, tok) in tokens {
+            if let DisplayToken::Text(s, _) = tok {
+                text.push_str(&s);
+            }
+        }
+        text
+    });
+    report("render, collecting tokens", duration);
+    let duration = time(|| {
+        let mut text = String::new();
+        write_tokens(root, &mut text, &format).unwrap();
+        text
+    });
+    report("render, streaming tokens", duration);
+
+    let duration = time(|| root.size(&format));
+    report("size, from scratch", duration);
+    let cache = SizeCache::new();
+    cache.size(root, &format);
+    let duration = time(|| cache.size(root, &format));
+    report("size, cached", duration);
+
+    // An edit replaces one child of the root (and so the root itself), after which only the new
+    // nodes need measuring
+    let children = root.children().to_vec();
+    let duration = time(|| {
+        let mut edited = children.clone();
+        edited[RECORDS / 2] = arena.alloc(JSON::Null);
+        cache.size(arena.alloc(JSON::Array(edited)), &format)
+    });
+    report("size, cached, after an edit", duration);
+}
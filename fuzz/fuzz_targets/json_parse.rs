@@ -0,0 +1,22 @@
+//! Parses arbitrary bytes as JSON (in every dialect), which must return either a tree or a
+//! `ParseError` and never panic.  Any tree that is parsed must also be written as text that
+//! parses back to the same tree.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sapling::arena::Arena;
+use sapling::ast::json::{Dialect, JSONFormat, JSON};
+use sapling::ast::Ast;
+
+fuzz_target!(|data: &[u8]| {
+    // Sapling only ever parses text that has been read as UTF-8
+    let text = String::from_utf8_lossy(data);
+    for &dialect in &[Dialect::JSON, Dialect::JSONC] {
+        let arena = Arena::new();
+        if let Ok(root) = JSON::parse_dialect(&text, &arena, dialect) {
+            let written = root.to_text(&JSONFormat::Compact);
+            assert_eq!(JSON::parse_dialect(&written, &arena, dialect), Ok(root));
+        }
+    }
+});
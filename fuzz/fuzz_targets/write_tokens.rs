@@ -0,0 +1,54 @@
+//! Writes arbitrary sequences of `DisplayToken`s as text, including unbalanced `Indent`s and
+//! `Dedent`s and very long text tokens, none of which may panic.
+//!
+//! The first byte picks the indentation, and then each token is read from the bytes that follow
+//! (see `read_token`).
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sapling::ast::display_token::{write_token_stream, DisplayToken, IndentKind, SyntaxCategory};
+
+/// Reads the next token from `bytes`.  The lowest 3 bits of the first byte pick the kind of
+/// token, and text tokens are made of the following `len` bytes, repeated 4096 times if the top
+/// bit of the first byte is set.
+fn read_token(byte: u8, bytes: &mut impl Iterator<Item = u8>) -> DisplayToken {
+    match byte & 0b111 {
+        0 => DisplayToken::Indent,
+        1 | 2 => DisplayToken::Dedent,
+        3 => DisplayToken::Newline,
+        4 => DisplayToken::Whitespace(bytes.next().unwrap_or(0) as usize),
+        _ => {
+            let len = bytes.next().unwrap_or(0) as usize;
+            let text: Vec<u8> = bytes.take(len).collect();
+            let repeats = if byte & 0x80 == 0 { 1 } else { 4096 };
+            let text = String::from_utf8_lossy(&text).repeat(repeats);
+            DisplayToken::Text(text.into(), SyntaxCategory::Default)
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut bytes = data.iter().copied();
+    let indent = match bytes.next() {
+        Some(0) | None => IndentKind::Tabs,
+        Some(n) => IndentKind::Spaces(n as usize % 9),
+    };
+    let mut tokens = Vec::new();
+    while let Some(byte) = bytes.next() {
+        tokens.push(read_token(byte, &mut bytes));
+    }
+
+    // Every newline in the text comes from either a `Newline` token or a text token
+    let expected_lines = tokens
+        .iter()
+        .map(|tok| match tok {
+            DisplayToken::Newline => 1,
+            DisplayToken::Text(text, _) => text.matches('\n').count(),
+            _ => 0,
+        })
+        .sum::<usize>();
+    let mut text = String::new();
+    write_token_stream(tokens, &mut text, indent).unwrap();
+    assert_eq!(text.matches('\n').count(), expected_lines);
+});
@@ -1,7 +1,42 @@
 //! Module containing code for the 'arena' that stores AST nodes.
 
+use std::cell::Cell;
 use typed_arena::Arena as TyArena;
 
+/// A value that can say roughly how many bytes of heap memory it owns, not counting the memory
+/// taken up by the value itself (which is `std::mem::size_of` the value).  References own
+/// nothing, so the nodes that AST nodes refer to aren't counted twice.
+pub trait HeapSize {
+    /// Returns the approximate number of bytes of heap memory owned by this value
+    fn heap_size(&self) -> usize;
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * std::mem::size_of::<T>() + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(T::heap_size).sum()
+    }
+}
+
+/// This means that calling `heap_size` on a `&&Node` measures the reference (giving `0`), so
+/// references to nodes have to be dereferenced first
+impl<T: ?Sized> HeapSize for &T {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
 /// An item that is stored in the [`Arena`].  This allows the [`Arena`] to build on
 /// [`typed_arena::Arena`] by storing extra detail about the nodes stored in the arena.
 #[derive(Debug, Clone)]
@@ -30,6 +65,8 @@ impl<T> Item<T> {
 ///   Nodes **can** exist inside multiple trees at once.
 pub struct Arena<T> {
     base_arena: TyArena<Item<T>>,
+    /// The total [`heap_size`](HeapSize::heap_size) of every node that has been allocated
+    heap_bytes: Cell<usize>,
 }
 
 impl<T> Arena<T> {
@@ -37,13 +74,64 @@ impl<T> Arena<T> {
     pub fn new() -> Arena<T> {
         Arena {
             base_arena: TyArena::new(),
+            heap_bytes: Cell::new(0),
         }
     }
 
+    /// Creates an empty `Arena` with room for `capacity` nodes before it has to allocate any more
+    /// memory
+    pub fn with_capacity(capacity: usize) -> Arena<T> {
+        Arena {
+            base_arena: TyArena::with_capacity(capacity),
+            heap_bytes: Cell::new(0),
+        }
+    }
+
+    /// Makes sure that the next `additional` nodes can be allocated without the `Arena` having to
+    /// allocate any more memory.  This is a hint for when roughly how many nodes will be
+    /// allocated is known in advance (e.g. when parsing a file), so it doesn't change
+    /// [`len`](Arena::len).
+    pub fn reserve(&self, additional: usize) {
+        self.base_arena.reserve_extend(additional);
+    }
+
+    /// Returns the number of nodes that have been allocated in this `Arena`.  Nodes are never
+    /// freed, so this includes every node that has ever been part of a tree.
+    pub fn len(&self) -> usize {
+        self.base_arena.len()
+    }
+
+    /// Returns `true` if no nodes have been allocated in this `Arena`
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the approximate number of bytes of memory used by the nodes in this `Arena`,
+    /// including the memory that they own (like the contents of strings)
+    pub fn bytes_allocated(&self) -> usize {
+        self.len() * std::mem::size_of::<Item<T>>() + self.heap_bytes.get()
+    }
+}
+
+impl<T: HeapSize> Arena<T> {
     /// Add a new node to the `Arena`, and returns an immutable reference to its final location.
     pub fn alloc(&self, node: T) -> &T {
+        self.heap_bytes
+            .set(self.heap_bytes.get() + node.heap_size());
         &self.base_arena.alloc(Item::new(node)).node
     }
+
+    /// Adds every node produced by an iterator to the `Arena`, returning references to them in
+    /// the same order.  The nodes are stored next to each other, and the space for them is
+    /// reserved up front if the iterator knows how long it is.
+    pub fn alloc_extend(&self, nodes: impl IntoIterator<Item = T>) -> Vec<&T> {
+        let items = self.base_arena.alloc_extend(nodes.into_iter().map(|node| {
+            self.heap_bytes
+                .set(self.heap_bytes.get() + node.heap_size());
+            Item::new(node)
+        }));
+        items.iter().map(|item| &item.node).collect()
+    }
 }
 
 impl<T> Default for Arena<T> {
@@ -51,3 +139,62 @@ impl<T> Default for Arena<T> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Arena, HeapSize, Item};
+
+    #[test]
+    fn heap_size() {
+        assert_eq!(String::new().heap_size(), 0);
+        assert_eq!(String::with_capacity(10).heap_size(), 10);
+        let strings = vec!["ab".to_string(), String::new()];
+        assert_eq!(
+            strings.heap_size(),
+            strings.capacity() * std::mem::size_of::<String>() + 2
+        );
+        // References don't own what they point to
+        let s = "abc".to_string();
+        assert_eq!([&s, &s].heap_size(), 0);
+        let refs: Vec<&String> = Vec::with_capacity(4);
+        assert_eq!(refs.heap_size(), 4 * std::mem::size_of::<&String>());
+    }
+
+    #[test]
+    fn statistics() {
+        let arena = Arena::new();
+        assert!(arena.is_empty());
+        assert_eq!(arena.bytes_allocated(), 0);
+        arena.alloc("hello".to_string());
+        arena.alloc(String::new());
+        assert_eq!(arena.len(), 2);
+        assert_eq!(
+            arena.bytes_allocated(),
+            2 * std::mem::size_of::<Item<String>>() + 5
+        );
+    }
+
+    #[test]
+    fn bulk_allocation() {
+        let arena = Arena::with_capacity(4);
+        assert!(arena.is_empty());
+        let first = arena.alloc("a".to_string());
+        let strings = arena.alloc_extend((0..10).map(|i| i.to_string()));
+        assert_eq!(strings.len(), 10);
+        assert!(strings
+            .iter()
+            .enumerate()
+            .all(|(i, s)| **s == i.to_string()));
+        assert!(arena.alloc_extend(Vec::new()).is_empty());
+        arena.reserve(100);
+        let last = arena.alloc("b".to_string());
+        // Every reference stays valid while the arena grows
+        assert_eq!((first.as_str(), last.as_str()), ("a", "b"));
+        assert_eq!(arena.len(), 12);
+        let heap_bytes = 2 + strings.iter().map(|&s| s.heap_size()).sum::<usize>();
+        assert_eq!(
+            arena.bytes_allocated(),
+            12 * std::mem::size_of::<Item<String>>() + heap_bytes
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Printing of trees as text coloured with ANSI escape sequences, which is how `--print` shows a
+//! file without starting the editor.
+
+use super::display_token::{DisplayToken, IndentKind, RenderStyle};
+use super::Ast;
+use crate::config::color::{ansi_style_escape, Style};
+use crate::config::ColorScheme;
+use std::io::Write;
+
+/// The escape sequence that puts the terminal's colours and effects back to normal
+const RESET: &str = "\x1b[0m";
+
+/// Writes the text of the tree under `root` (followed by a newline), with every
+/// [`Text`](DisplayToken::Text) token styled by `color_scheme` (which logs a warning about any
+/// category that it has no style for, see [`ColorScheme::resolve`]).  If `color_scheme` is `None`,
+/// then the text is written without any escape sequences, and so is exactly what
+/// [`to_text`](Ast::to_text) would give.
+pub fn write_ansi<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    format_style: &Node::FormatStyle,
+    color_scheme: Option<&ColorScheme>,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let indent = match format_style.indent() {
+        IndentKind::Spaces(n) => " ".repeat(n),
+        IndentKind::Tabs => "\t".to_string(),
+    };
+    let mut indent_depth = 0usize;
+    // The style that the terminal is currently writing in, so that consecutive tokens of the same
+    // style don't repeat its escape sequence
+    let mut current_style: Option<Style> = None;
+    for (_, tok) in root.display_tokens(format_style) {
+        match tok {
+            DisplayToken::Text(text, category) => {
+                if let Some(color_scheme) = color_scheme {
+                    let style = color_scheme.resolve(category);
+                    if current_style != Some(style) {
+                        // Effects and backgrounds can only be turned off by resetting everything
+                        if current_style.is_some_and(|s| !s.is_plain()) {
+                            write!(out, "{}", RESET)?;
+                        }
+                        write!(out, "{}", ansi_style_escape(style))?;
+                        current_style = Some(style);
+                    }
+                }
+                write!(out, "{}", text)?;
+            }
+            DisplayToken::Whitespace(n) => write!(out, "{}", " ".repeat(n))?,
+            DisplayToken::Newline => write!(out, "\n{}", indent.repeat(indent_depth))?,
+            DisplayToken::Indent => indent_depth += 1,
+            DisplayToken::Dedent => indent_depth = indent_depth.saturating_sub(1),
+        }
+    }
+    // Don't leave the terminal writing in the last token's style
+    if current_style.is_some() {
+        write!(out, "{}", RESET)?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_ansi;
+    use crate::arena::Arena;
+    use crate::ast::display_token::IndentKind;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::test_json::{Rng, TestJSON};
+    use crate::ast::Ast;
+    use crate::config::{default_color_scheme, ColorScheme};
+
+    /// Returns the text that `write_ansi` writes for a tree
+    fn ansi<'arena>(
+        root: &'arena JSON<'arena>,
+        format_style: &JSONFormat,
+        color_scheme: Option<&ColorScheme>,
+    ) -> String {
+        let mut out = Vec::new();
+        write_ansi(root, format_style, color_scheme, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Removes every escape sequence from some text
+    fn strip_escapes(text: &str) -> String {
+        let mut stripped = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // Every escape that we write ends with an `m`
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                stripped.push(c);
+            }
+        }
+        stripped
+    }
+
+    #[test]
+    fn colored() {
+        let arena = Arena::new();
+        let root = JSON::parse(r#"[[true], "a"]"#, &arena).unwrap();
+        assert_eq!(
+            ansi(root, &JSONFormat::Compact, Some(&default_color_scheme())),
+            // White punctuation, light red constants and light yellow strings.  Tokens of the
+            // same colour as the one before them don't need another escape.
+            "\x1b[37m[[\x1b[91mtrue\x1b[37m],\x1b[93m\"a\"\x1b[37m]\x1b[0m\n"
+        );
+        // Effects have to be reset before the next token
+        let (bold, _) = ColorScheme::from_toml("[colors]\nconst = \"bold red\"").unwrap();
+        assert_eq!(
+            ansi(root, &JSONFormat::Compact, Some(&bold)),
+            "\x1b[37m[[\x1b[31m\x1b[1mtrue\x1b[0m\x1b[37m],\x1b[93m\"a\"\x1b[37m]\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn uncolored() {
+        let arena = Arena::new();
+        let root = JSON::parse(r#"{"a": [1, {}]}"#, &arena).unwrap();
+        let format_style = JSONFormat::Pretty {
+            indent: IndentKind::Tabs,
+            trailing_commas: false,
+        };
+        assert_eq!(
+            ansi(root, &format_style, None),
+            "{\n\t\"a\": [\n\t\t1,\n\t\t{}\n\t]\n}\n"
+        );
+    }
+
+    #[test]
+    fn text_matches_to_text() {
+        let mut rng = Rng::new(0xa251);
+        let color_scheme = default_color_scheme();
+        for _ in 0..100 {
+            let arena = Arena::new();
+            let root = TestJSON::random(&mut rng, 4).add_to_arena(&arena);
+            let pretty = JSONFormat::Pretty {
+                indent: IndentKind::Spaces(2),
+                trailing_commas: true,
+            };
+            for format_style in &[JSONFormat::Compact, pretty] {
+                let expected = root.to_text(format_style) + "\n";
+                assert_eq!(ansi(root, format_style, None), expected);
+                let colored = ansi(root, format_style, Some(&color_scheme));
+                assert_eq!(strip_escapes(&colored), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,581 @@
+//! Finding the differences between two versions of a tree.
+//!
+//! Every edit copies only the nodes between the root and the change, so two versions of a tree
+//! share every subtree that wasn't changed.  The diff uses this: subtrees which are the same node
+//! in both trees are skipped without being looked at, so finding the changes takes time in
+//! proportion to how much changed rather than to the size of the trees.  Trees which don't share
+//! any nodes (e.g. two files) can be compared too, but every node of them is looked at.
+
+use super::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+use std::ops::Range;
+
+/// The largest number of pairs of children that are compared when aligning the children of a
+/// node.  Beyond this, children are paired up in order instead, which is always correct but can
+/// report a moved child as a run of replacements.
+const MAX_ALIGNMENT_PAIRS: usize = 10_000;
+
+/// One difference between two versions of a tree.  The paths of deleted nodes are paths in the
+/// old tree, whereas the paths of inserted and replaced nodes are paths in the new tree.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TreeEdit {
+    /// A node (and its subtree) was added at this path of the new tree
+    Inserted(CursorPath),
+    /// The node at this path of the old tree was removed (along with its subtree)
+    Deleted(CursorPath),
+    /// The node at this path of the new tree replaced a node which was different enough that
+    /// their children weren't compared (e.g. a number was replaced with a string)
+    Replaced(CursorPath),
+}
+
+impl TreeEdit {
+    /// Returns the path of the node that this edit applies to
+    pub fn path(&self) -> &CursorPath {
+        match self {
+            TreeEdit::Inserted(path) | TreeEdit::Deleted(path) | TreeEdit::Replaced(path) => path,
+        }
+    }
+}
+
+impl std::fmt::Display for TreeEdit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeEdit::Inserted(path) => write!(f, "inserted {}", path),
+            TreeEdit::Deleted(path) => write!(f, "deleted {}", path),
+            TreeEdit::Replaced(path) => write!(f, "replaced {}", path),
+        }
+    }
+}
+
+/// Returns the differences between the tree under `old` and the tree under `new`, in the order
+/// that they appear in the trees (see [`diff_nodes`]).
+pub fn diff<'arena, Node: Ast<'arena>>(old: &'arena Node, new: &'arena Node) -> Vec<TreeEdit> {
+    diff_nodes(old, new)
+        .into_iter()
+        .map(|(edit, _)| edit)
+        .collect()
+}
+
+/// Returns the differences between the tree under `old` and the tree under `new`, along with
+/// the node of the new tree that each difference affects.  That node is the inserted or
+/// replacing node, or (for a deletion) the new version of the node whose child was deleted.
+///
+/// Children are matched up between the trees by being the same node, so a child which moves
+/// shows up as a deletion and an insertion.  Children which aren't the same node (or part of an
+/// unchanged run) are matched up with children that are the same apart from their own children
+/// (and have the same keys), and any that are still left over are paired up in order.  A pair
+/// is only reported as a replacement if the nodes themselves differ (not counting their
+/// children); otherwise the pair's children are compared in turn.
+pub fn diff_nodes<'arena, Node: Ast<'arena>>(
+    old: &'arena Node,
+    new: &'arena Node,
+) -> Vec<(TreeEdit, &'arena Node)> {
+    differences(old, new)
+        .into_iter()
+        .map(|d| (d.edit, d.new))
+        .collect()
+}
+
+/// One difference between two trees (see [`differences`]), along with where it is in both of
+/// them
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Difference<'arena, Node> {
+    pub edit: TreeEdit,
+    /// The node of the old tree that the difference affects: the deleted or replaced node, or
+    /// (for an insertion) the old version of the node that the child was inserted into
+    pub old: &'arena Node,
+    /// The path of `old` in the old tree
+    pub old_path: CursorPath,
+    /// The node of the new tree that the difference affects (see [`diff_nodes`])
+    pub new: &'arena Node,
+    /// The path of `new` in the new tree
+    pub new_path: CursorPath,
+}
+
+/// Returns the differences between the tree under `old` and the tree under `new`, in the order
+/// that they appear in the trees, finding them in the same way as [`diff_nodes`].  Each one says
+/// which node it affects in both trees, so the trees can be shown side by side.
+pub fn differences<'arena, Node: Ast<'arena>>(
+    old: &'arena Node,
+    new: &'arena Node,
+) -> Vec<Difference<'arena, Node>> {
+    let mut differences = Vec::new();
+    // The work still to do, in reverse order.  Differences are only added once every difference
+    // before them has been found, so that they come out in the order of the trees.
+    let mut stack = vec![Work::Compare {
+        old,
+        new,
+        old_path: CursorPath::root(),
+        new_path: CursorPath::root(),
+    }];
+    while let Some(work) = stack.pop() {
+        let (old, new, old_path, new_path) = match work {
+            Work::Found(difference) => {
+                differences.push(difference);
+                continue;
+            }
+            Work::Compare {
+                old,
+                new,
+                old_path,
+                new_path,
+            } => (old, new, old_path, new_path),
+        };
+        if std::ptr::eq(old, new) {
+            continue;
+        }
+        if !same_node(old, new) {
+            stack.push(Work::Found(Difference {
+                edit: TreeEdit::Replaced(new_path.clone()),
+                old,
+                old_path,
+                new,
+                new_path,
+            }));
+            continue;
+        }
+        let child_path = |path: &CursorPath, index: usize| {
+            let mut path = path.clone();
+            path.push(index);
+            path
+        };
+        let mut work = Vec::new();
+        for step in align(old.children(), new.children()) {
+            work.push(match step {
+                Step::Same => continue,
+                Step::Pair(i, j) => Work::Compare {
+                    old: old.children()[i],
+                    new: new.children()[j],
+                    old_path: child_path(&old_path, i),
+                    new_path: child_path(&new_path, j),
+                },
+                Step::Delete(i) => {
+                    let path = child_path(&old_path, i);
+                    Work::Found(Difference {
+                        edit: TreeEdit::Deleted(path.clone()),
+                        old: old.children()[i],
+                        old_path: path,
+                        new,
+                        new_path: new_path.clone(),
+                    })
+                }
+                Step::Insert(j) => {
+                    let path = child_path(&new_path, j);
+                    Work::Found(Difference {
+                        edit: TreeEdit::Inserted(path.clone()),
+                        old,
+                        old_path: old_path.clone(),
+                        new: new.children()[j],
+                        new_path: path,
+                    })
+                }
+            });
+        }
+        stack.extend(work.into_iter().rev());
+    }
+    differences
+}
+
+/// Something that [`differences`] still has to do
+enum Work<'arena, Node> {
+    /// Compare two nodes, which are at the given paths in their trees
+    Compare {
+        old: &'arena Node,
+        new: &'arena Node,
+        old_path: CursorPath,
+        new_path: CursorPath,
+    },
+    /// Add a difference to the diff
+    Found(Difference<'arena, Node>),
+}
+
+/// How one child of an old node corresponds to the children of a new node
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Step {
+    /// The next old child is also the next new child
+    Same,
+    /// The old child at the first index became the new child at the second index
+    Pair(usize, usize),
+    /// The old child at this index was removed
+    Delete(usize),
+    /// The new child at this index was added
+    Insert(usize),
+}
+
+/// Works out how the children of an old node became the children of a new node.  Children which
+/// are the same node are matched up first, and then the children between them are matched up
+/// with children which are the same apart from their own children (see [`same_node`]) and have
+/// the same keys (see [`same_keys`]), so that e.g. the fields of two objects are matched by key.
+fn align<'arena, Node: Ast<'arena>>(old: &[&'arena Node], new: &[&'arena Node]) -> Vec<Step> {
+    let same = |i: usize, j: usize| std::ptr::eq(old[i], new[j]);
+    // Only a few children usually change, so skip the unchanged children at either end
+    let shortest = old.len().min(new.len());
+    let prefix = (0..shortest).take_while(|&i| same(i, i)).count();
+    let suffix = (0..shortest - prefix)
+        .take_while(|&i| same(old.len() - 1 - i, new.len() - 1 - i))
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    let mut steps = vec![Step::Same; prefix];
+    let (mut i, mut j) = (prefix, prefix);
+    for (anchor_i, anchor_j) in common_subsequence(i..old_end, j..new_end, same) {
+        align_changed(old, new, i..anchor_i, j..anchor_j, &mut steps);
+        steps.push(Step::Same);
+        i = anchor_i + 1;
+        j = anchor_j + 1;
+    }
+    align_changed(old, new, i..old_end, j..new_end, &mut steps);
+    steps.extend(std::iter::repeat_n(Step::Same, suffix));
+    steps
+}
+
+/// Adds the [`Step`]s for a run of old children which became a run of new children, none of
+/// which are the same node
+fn align_changed<'arena, Node: Ast<'arena>>(
+    old: &[&'arena Node],
+    new: &[&'arena Node],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+    steps: &mut Vec<Step>,
+) {
+    let similar = |i: usize, j: usize| same_node(old[i], new[j]) && same_keys(old[i], new[j]);
+    let (mut i, mut j) = (old_range.start, new_range.start);
+    let anchors = common_subsequence(old_range.clone(), new_range.clone(), similar);
+    let end = (old_range.end, new_range.end);
+    for (anchor_i, anchor_j) in anchors.into_iter().chain(std::iter::once(end)) {
+        // Pair up the children between the anchors, then whatever is left over was deleted or
+        // inserted
+        while i < anchor_i && j < anchor_j {
+            steps.push(Step::Pair(i, j));
+            i += 1;
+            j += 1;
+        }
+        steps.extend((i..anchor_i).map(Step::Delete));
+        steps.extend((j..anchor_j).map(Step::Insert));
+        if (anchor_i, anchor_j) != end {
+            steps.push(Step::Pair(anchor_i, anchor_j));
+        }
+        i = anchor_i + 1;
+        j = anchor_j + 1;
+    }
+}
+
+/// Returns the `(old, new)` index pairs of the longest common subsequence of two ranges of
+/// children, where `equal` says whether two children match.  Nothing is matched if this would
+/// mean comparing more than [`MAX_ALIGNMENT_PAIRS`] pairs of children.
+fn common_subsequence(
+    old: Range<usize>,
+    new: Range<usize>,
+    equal: impl Fn(usize, usize) -> bool,
+) -> Vec<(usize, usize)> {
+    let (old_count, new_count) = (old.len(), new.len());
+    if old_count * new_count > MAX_ALIGNMENT_PAIRS {
+        return Vec::new();
+    }
+    let matches = |i: usize, j: usize| equal(old.start + i, new.start + j);
+    // `lengths[i][j]` is the length of the LCS of the children after `i` and `j`
+    let mut lengths = vec![vec![0; new_count + 1]; old_count + 1];
+    for i in (0..old_count).rev() {
+        for j in (0..new_count).rev() {
+            lengths[i][j] = if matches(i, j) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old_count && j < new_count {
+        if matches(i, j) {
+            pairs.push((old.start + i, new.start + j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Returns `true` if two nodes are the same apart from their children (e.g. both are JSON arrays,
+/// or both are the string `"a"`), without comparing their descendants
+fn same_node<'arena, Node: Ast<'arena>>(a: &'arena Node, b: &'arena Node) -> bool {
+    if let (Some(a_shell), Some(b_shell)) = (without_children(a), without_children(b)) {
+        return a_shell == b_shell;
+    }
+    // Nodes whose children can't be removed (e.g. JSON fields) always have the same number of
+    // children, so give both the same children.  A leaf is used, so comparing them is cheap.
+    if a.children().len() != b.children().len() {
+        return false;
+    }
+    let mut leaf = b;
+    while let Some(&child) = leaf.children().first() {
+        leaf = child;
+    }
+    let (mut a_shell, mut b_shell) = (a.clone(), b.clone());
+    a_shell.children_mut().fill(leaf);
+    b_shell.children_mut().fill(leaf);
+    a_shell == b_shell
+}
+
+/// Returns `true` if two nodes have the same children where they have keys (e.g. two JSON
+/// fields with the same key), which are leaves and so are cheap to compare
+fn same_keys<'arena, Node: Ast<'arena>>(a: &'arena Node, b: &'arena Node) -> bool {
+    let (a_children, b_children) = (a.children(), b.children());
+    (0..a_children.len())
+        .filter(|&i| a.is_key_child(i) || b.is_key_child(i))
+        .all(|i| b_children.get(i).is_some_and(|&key| key == a_children[i]))
+}
+
+/// Returns a copy of `node` with all of its children removed, or `None` if they can't be removed
+fn without_children<'arena, Node: Ast<'arena>>(node: &'arena Node) -> Option<Node> {
+    let mut shell = node.clone();
+    let len = node.children().len();
+    // Nodes with no children don't expect to be asked to delete any
+    if len > 0 {
+        shell.delete_children(0..len).ok()?;
+    }
+    Some(shell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, diff_nodes, differences, Difference, TreeEdit};
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    fn path(indices: &[usize]) -> CursorPath {
+        CursorPath::from_vec(indices.to_vec())
+    }
+
+    /// Makes a new version of `node` whose children are picked from its old children (by index)
+    /// or are new nodes
+    fn rebuild<'arena>(
+        arena: &'arena Arena<JSON<'arena>>,
+        node: &'arena JSON<'arena>,
+        children: Vec<Result<usize, &'arena JSON<'arena>>>,
+    ) -> &'arena JSON<'arena> {
+        let children = children
+            .into_iter()
+            .map(|c| c.map_or_else(|new| new, |i| node.children()[i]))
+            .collect();
+        arena.alloc(JSON::Array(children))
+    }
+
+    #[test]
+    fn unchanged() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::Array(vec![J::Null])]).add_to_arena(&arena);
+        assert_eq!(diff(root, root), vec![]);
+        // Equal trees which don't share any nodes have no differences either
+        let copy = J::Array(vec![J::True, J::Array(vec![J::Null])]).add_to_arena(&arena);
+        assert_eq!(diff(root, copy), vec![]);
+    }
+
+    #[test]
+    fn insertions() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::True, J::False]).add_to_arena(&arena);
+        let null = arena.alloc(JSON::Null);
+        let new = rebuild(&arena, old, vec![Ok(0), Err(null), Ok(1), Err(null)]);
+        assert_eq!(
+            diff_nodes(old, new),
+            vec![
+                (TreeEdit::Inserted(path(&[1])), null),
+                (TreeEdit::Inserted(path(&[3])), null)
+            ]
+        );
+        // Inserting into an empty node
+        let empty = arena.alloc(JSON::Array(vec![]));
+        let new = rebuild(&arena, empty, vec![Err(null)]);
+        assert_eq!(diff(empty, new), vec![TreeEdit::Inserted(path(&[0]))]);
+    }
+
+    #[test]
+    fn deletions() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::True, J::False, J::Null, J::True]).add_to_arena(&arena);
+        let new = rebuild(&arena, old, vec![Ok(1), Ok(3)]);
+        // The deleted nodes affect the new version of their parent
+        assert_eq!(
+            diff_nodes(old, new),
+            vec![
+                (TreeEdit::Deleted(path(&[0])), new),
+                (TreeEdit::Deleted(path(&[2])), new)
+            ]
+        );
+        let empty = rebuild(&arena, old, vec![]);
+        assert_eq!(diff(old, empty).len(), 4);
+    }
+
+    #[test]
+    fn moves() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::True, J::False, J::Null]).add_to_arena(&arena);
+        let new = rebuild(&arena, old, vec![Ok(1), Ok(2), Ok(0)]);
+        assert_eq!(
+            diff(old, new),
+            vec![
+                TreeEdit::Deleted(path(&[0])),
+                TreeEdit::Inserted(path(&[2]))
+            ]
+        );
+        // Swapping two children keeps one of them in place
+        let new = rebuild(&arena, old, vec![Ok(0), Ok(2), Ok(1)]);
+        assert_eq!(
+            diff(old, new),
+            vec![
+                TreeEdit::Deleted(path(&[1])),
+                TreeEdit::Inserted(path(&[2]))
+            ]
+        );
+    }
+
+    #[test]
+    fn replacements() {
+        let arena = Arena::new();
+        let old = J::Array(vec![
+            J::Number("1".to_string()),
+            J::Array(vec![J::Str("a".to_string()), J::Null]),
+            J::Object(vec![("key".to_string(), J::True)]),
+        ])
+        .add_to_arena(&arena);
+        // Replace the number with a different number, and the string with an array
+        let inner = old.children()[1];
+        let array = arena.alloc(JSON::Array(vec![]));
+        let number = arena.alloc(JSON::Number("2".to_string()));
+        let new_inner = rebuild(&arena, inner, vec![Err(array), Ok(1)]);
+        let new = rebuild(&arena, old, vec![Err(number), Err(new_inner), Ok(2)]);
+        assert_eq!(
+            diff_nodes(old, new),
+            vec![
+                (TreeEdit::Replaced(path(&[0])), number),
+                (TreeEdit::Replaced(path(&[1, 0])), array)
+            ]
+        );
+
+        // Changing the value of a field only replaces the value, even though the field (which
+        // always has two children) is a new node
+        let object = old.children()[2];
+        let field = object.children()[0];
+        let null = arena.alloc(JSON::Null);
+        let new_field = arena.alloc(JSON::Field([field.children()[0], null]));
+        let new_object = arena.alloc(JSON::Object(vec![new_field]));
+        let new = rebuild(&arena, old, vec![Ok(0), Ok(1), Err(new_object)]);
+        assert_eq!(diff(old, new), vec![TreeEdit::Replaced(path(&[2, 0, 1]))]);
+        // Replacing the key of a field with a different string
+        let key = arena.alloc(JSON::Str("other".to_string()));
+        let new_field = arena.alloc(JSON::Field([key, field.children()[1]]));
+        let new_object = arena.alloc(JSON::Object(vec![new_field]));
+        let new = rebuild(&arena, old, vec![Ok(0), Ok(1), Err(new_object)]);
+        assert_eq!(diff(old, new), vec![TreeEdit::Replaced(path(&[2, 0, 0]))]);
+    }
+
+    #[test]
+    fn edits_are_in_order() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::Array(vec![J::Null]), J::True, J::False]).add_to_arena(&arena);
+        let inner = old.children()[0];
+        let null = arena.alloc(JSON::Null);
+        let new_inner = rebuild(&arena, inner, vec![Ok(0), Err(null)]);
+        let new = rebuild(&arena, old, vec![Err(new_inner), Ok(2), Err(null)]);
+        assert_eq!(
+            diff(old, new),
+            vec![
+                TreeEdit::Inserted(path(&[0, 1])),
+                TreeEdit::Deleted(path(&[1])),
+                TreeEdit::Inserted(path(&[2])),
+            ]
+        );
+        assert_eq!(TreeEdit::Deleted(path(&[0])).to_string(), "deleted 0");
+        assert_eq!(
+            TreeEdit::Replaced(CursorPath::root()).to_string(),
+            "replaced root"
+        );
+    }
+
+    #[test]
+    fn many_children() {
+        let arena = Arena::new();
+        let children: Vec<&JSON> = (0..200)
+            .map(|i| arena.alloc(JSON::Number(i.to_string())))
+            .collect();
+        let old = arena.alloc(JSON::Array(children.clone()));
+        // Unchanged children at the ends are skipped
+        let null = arena.alloc(JSON::Null);
+        let mut new_children = children.clone();
+        new_children.insert(100, null);
+        let new = arena.alloc(JSON::Array(new_children));
+        assert_eq!(diff(old, new), vec![TreeEdit::Inserted(path(&[100]))]);
+        // Too many children have changed to align them, so they're paired up in order
+        let reversed = arena.alloc(JSON::Array(children.into_iter().rev().collect()));
+        assert_eq!(diff(old, reversed).len(), 200);
+        assert!(diff(old, reversed)
+            .iter()
+            .enumerate()
+            .all(|(i, edit)| edit == &TreeEdit::Replaced(path(&[i]))));
+    }
+
+    #[test]
+    fn fields_are_matched_by_key() {
+        let arena = Arena::new();
+        let field = |key: &str, value: J| (key.to_string(), value);
+        let old = J::Object(vec![
+            field("a", J::True),
+            field("b", J::Null),
+            field("c", J::False),
+        ])
+        .add_to_arena(&arena);
+        // Parsed separately, so no nodes are shared
+        let new = J::Object(vec![
+            field("b", J::Null),
+            field("c", J::True),
+            field("d", J::Null),
+        ])
+        .add_to_arena(&arena);
+        assert_eq!(
+            diff(old, new),
+            vec![
+                TreeEdit::Deleted(path(&[0])),
+                TreeEdit::Replaced(path(&[1, 1])),
+                TreeEdit::Inserted(path(&[2])),
+            ]
+        );
+    }
+
+    #[test]
+    fn differences_in_both_trees() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::True, J::Array(vec![J::Null])]).add_to_arena(&arena);
+        let new = J::Array(vec![J::Array(vec![J::Null, J::False])]).add_to_arena(&arena);
+        let [old_true, old_inner] = [old.children()[0], old.children()[1]];
+        let new_inner = new.children()[0];
+        assert_eq!(
+            differences(old, new),
+            vec![
+                // A deletion is at the deleted node in the old tree, and at its parent in the new
+                Difference {
+                    edit: TreeEdit::Deleted(path(&[0])),
+                    old: old_true,
+                    old_path: path(&[0]),
+                    new,
+                    new_path: path(&[]),
+                },
+                // An insertion is the other way round
+                Difference {
+                    edit: TreeEdit::Inserted(path(&[0, 1])),
+                    old: old_inner,
+                    old_path: path(&[1]),
+                    new: new_inner.children()[1],
+                    new_path: path(&[0, 1]),
+                },
+            ]
+        );
+    }
+}
@@ -1,13 +1,155 @@
 use super::Ast;
+use crate::config::options::Options;
+use crate::language::Language;
+use std::borrow::Cow;
+use std::fmt;
 
-/// How many spaces corespond to one indentation level
-const INDENT_WIDTH: usize = 4;
+/// How many spaces correspond to one indentation level, if no other width is specified
+pub const DEFAULT_INDENT_WIDTH: usize = 4;
+/// How many columns of the screen a tab character occupies, if no other width is specified
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// The characters that make up one level of indentation
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum IndentKind {
+    /// Every indentation level is some number of spaces.  This can be `0`, in which case
+    /// [`Indent`](DisplayToken::Indent) and [`Dedent`](DisplayToken::Dedent) have no effect.
+    Spaces(usize),
+    /// Every indentation level is one tab character
+    Tabs,
+}
+
+impl IndentKind {
+    /// Returns the [`char`] used for indentation, and how many of them make up one level
+    fn char_and_count(&self) -> (char, usize) {
+        match self {
+            IndentKind::Spaces(n) => (' ', *n),
+            IndentKind::Tabs => ('\t', 1),
+        }
+    }
+
+    /// Returns how many columns of the screen one indentation level occupies, if each tab
+    /// character occupies `tab_width` columns
+    pub fn columns(&self, tab_width: usize) -> usize {
+        match self {
+            IndentKind::Spaces(n) => *n,
+            IndentKind::Tabs => tab_width,
+        }
+    }
+}
+
+impl Default for IndentKind {
+    fn default() -> Self {
+        IndentKind::Spaces(DEFAULT_INDENT_WIDTH)
+    }
+}
+
+/// The ways that a tree can be spread over lines, which are chosen with `:set format=<name>`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FormatKind {
+    /// Everything is on one line, with no whitespace that isn't needed
+    Compact,
+    /// Every child of a node is put on its own (indented) line
+    Pretty,
+    /// Nodes are kept on one line if they fit within `max_width` columns, and are pretty printed
+    /// otherwise
+    Fit { max_width: usize },
+}
+
+/// The language-independent parts of a [`FormatStyle`](Ast::FormatStyle), which are needed to
+/// turn a stream of [`DisplayToken`]s into text (or onto the screen).
+pub trait RenderStyle: Clone {
+    /// The characters that make up one indentation level
+    fn indent(&self) -> IndentKind;
+    /// Changes the characters that make up one indentation level.  Styles which never indent
+    /// anything can ignore this.
+    fn set_indent(&mut self, indent: IndentKind);
+    /// Changes how the tree is spread over lines, keeping the current indentation (if the new
+    /// kind of style has any)
+    fn set_kind(&mut self, kind: FormatKind);
+    /// Applies the [`Options`] that only some languages have a use for (like `trailing_commas` or
+    /// `self_closing`) to this style, for trees written in `language`.  Styles of languages without any such
+    /// options can leave this out.
+    fn apply_options(&mut self, _options: &Options, _language: Language) {}
+}
+
+/// The category that a piece of text belongs to, which determines how it will be syntax
+/// highlighted.  These roughly follow Vim's highlight groups (`const`, `literal`, `comment`,
+/// `ident`, etc.), and [`Default`](SyntaxCategory::Default) is used for anything that isn't
+/// special (like punctuation).  The editor also draws some things that aren't part of the text
+/// (like search matches and the line numbers) in the styles of their own categories.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SyntaxCategory {
+    Default,
+    Literal,
+    Const,
+    Comment,
+    Ident,
+    Keyword,
+    Preproc,
+    Type,
+    Special,
+    Error,
+    /// The matches of the current search
+    Search,
+    /// Nodes which have been inserted since the tree was last saved, or in the new side of a diff
+    DiffAdded,
+    /// Nodes which have lost children since the tree was last saved, or in the old side of a diff
+    DiffRemoved,
+    /// Nodes which are replaced between the sides of a diff
+    DiffChanged,
+    /// The background of the nodes selected in visual mode
+    Selection,
+    /// The line numbers in the gutter
+    Gutter,
+    /// The brackets of the nodes around the cursor
+    Ancestor,
+    /// A category that only some languages use (see
+    /// [`Ast::CATEGORIES`](super::Ast::CATEGORIES)), by its name.  This should never be the name
+    /// of one of the other categories.
+    Custom(&'static str),
+}
+
+impl SyntaxCategory {
+    /// Returns the name of this category, which it's given styles by in the `[colors]` table of
+    /// the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            SyntaxCategory::Default => "default",
+            SyntaxCategory::Literal => "literal",
+            SyntaxCategory::Const => "const",
+            SyntaxCategory::Comment => "comment",
+            SyntaxCategory::Ident => "ident",
+            SyntaxCategory::Keyword => "keyword",
+            SyntaxCategory::Preproc => "preproc",
+            SyntaxCategory::Type => "type",
+            SyntaxCategory::Special => "special",
+            SyntaxCategory::Error => "error",
+            SyntaxCategory::Search => "search",
+            SyntaxCategory::DiffAdded => "diff-added",
+            SyntaxCategory::DiffRemoved => "diff-removed",
+            SyntaxCategory::DiffChanged => "diff-changed",
+            SyntaxCategory::Selection => "selection",
+            SyntaxCategory::Gutter => "gutter",
+            SyntaxCategory::Ancestor => "ancestor",
+            SyntaxCategory::Custom(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
 
 /// A single piece of a node that can be rendered to the screen
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum DisplayToken {
-    /// Some text should be rendered to the screen
-    Text(String),
+    /// Some text should be rendered to the screen, highlighted according to its [`SyntaxCategory`].
+    /// Text that never changes (like punctuation or keywords) can be borrowed, so that it doesn't
+    /// have to be allocated every time the tree is rendered.
+    Text(Cow<'static, str>, SyntaxCategory),
     /// Add some number of spaces worth of whitespace
     Whitespace(usize),
     /// Put the next token onto a new line
@@ -26,45 +168,284 @@ pub enum RecTok<'arena, Node> {
     Child(&'arena Node),
 }
 
-/// Write a stream of display tokens to a string
+/// A lazy iterator over the [`DisplayToken`]s of a tree, along with the node that generated each
+/// token.  Created by [`Ast::display_tokens`].
+///
+/// The children of each node are expanded with an explicit stack rather than recursion, so only
+/// the tokens of the nodes between the root and the current token are stored at once.
+pub struct DisplayTokens<'f, 'arena, Node: Ast<'arena>> {
+    format_style: &'f Node::FormatStyle,
+    /// The nodes between the root and the node whose tokens are being generated, along with each
+    /// node's remaining tokens
+    stack: Vec<(&'arena Node, std::vec::IntoIter<RecTok<'arena, Node>>)>,
+}
+
+impl<'f, 'arena, Node: Ast<'arena>> DisplayTokens<'f, 'arena, Node> {
+    /// Creates an iterator over the tokens of the tree under `root`
+    pub(super) fn new(root: &'arena Node, format_style: &'f Node::FormatStyle) -> Self {
+        DisplayTokens {
+            format_style,
+            stack: vec![(root, root.display_tokens_rec(format_style).into_iter())],
+        }
+    }
+}
+
+impl<'f, 'arena, Node: Ast<'arena>> Iterator for DisplayTokens<'f, 'arena, Node> {
+    type Item = (&'arena Node, DisplayToken);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, tokens) = self.stack.last_mut()?;
+            match tokens.next() {
+                Some(RecTok::Tok(tok)) => return Some((*node, tok)),
+                Some(RecTok::Child(child)) => {
+                    let child_tokens = child.display_tokens_rec(self.format_style);
+                    self.stack.push((child, child_tokens.into_iter()));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<'f, 'arena, Node: Ast<'arena>> std::iter::FusedIterator for DisplayTokens<'f, 'arena, Node> {}
+
+/// Write the display tokens of a tree to `out`.  The text is written a token at a time, so it's
+/// never all in memory at once unless `out` keeps it (like a [`String`] does).
 pub fn write_tokens<'arena, Node: Ast<'arena>>(
     root: &'arena Node,
-    string: &mut String,
+    out: &mut (impl fmt::Write + ?Sized),
     format_style: &Node::FormatStyle,
-) {
-    let mut indentation_string = String::new();
+) -> fmt::Result {
+    write_token_stream(
+        root.display_tokens(format_style).map(|(_, tok)| tok),
+        out,
+        format_style.indent(),
+    )
+}
+
+/// Write the text of some [`DisplayToken`]s to `out`, indenting with `indent`.  A
+/// [`Dedent`](DisplayToken::Dedent) that doesn't match an [`Indent`](DisplayToken::Indent) is a
+/// bug in the [`Ast`] that generated it, but is logged and ignored rather than corrupting the
+/// rest of the text.  Any sequence of tokens can be written, so this is also what the
+/// `write_tokens` fuzz target (in `fuzz/`) exercises.
+pub fn write_token_stream(
+    tokens: impl IntoIterator<Item = DisplayToken>,
+    out: &mut (impl fmt::Write + ?Sized),
+    indent: IndentKind,
+) -> fmt::Result {
+    let (indent_char, indent_count) = indent.char_and_count();
+    let mut indent_depth = 0usize;
 
     // Process the token string
-    for (_id, tok) in root.display_tokens(format_style) {
+    for tok in tokens {
         match tok {
-            DisplayToken::Text(s) => {
+            DisplayToken::Text(s, _) => {
                 // Push the string we've been given
-                string.push_str(&s);
+                out.write_str(&s)?;
             }
             DisplayToken::Whitespace(n) => {
                 // Push 'n' many spaces
                 for _ in 0..n {
-                    string.push(' ');
+                    out.write_char(' ')?;
                 }
             }
             DisplayToken::Newline => {
                 // Push a newline and keep indentation
-                string.push('\n');
-                string.push_str(&indentation_string);
-            }
-            DisplayToken::Indent => {
-                // Add `INDENT_WIDTH` spaces to the indentation_string
-                for _ in 0..INDENT_WIDTH {
-                    indentation_string.push(' ');
+                out.write_char('\n')?;
+                for _ in 0..indent_depth * indent_count {
+                    out.write_char(indent_char)?;
                 }
             }
-            DisplayToken::Dedent => {
-                // Remove `INDENT_WIDTH` spaces to the indentation_string
-                for _ in 0..INDENT_WIDTH {
-                    let popped_char = indentation_string.pop();
-                    debug_assert_eq!(popped_char, Some(' '));
-                }
+            DisplayToken::Indent => indent_depth += 1,
+            DisplayToken::Dedent => match indent_depth.checked_sub(1) {
+                Some(depth) => indent_depth = depth,
+                None => log::warn!("Ignoring a Dedent that doesn't match any Indent"),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// The ways that the [`DisplayToken`]s of a node can fail to indent and dedent in matching pairs
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum TokenStreamError {
+    /// The node emitted a [`Dedent`](DisplayToken::Dedent) without a matching
+    /// [`Indent`](DisplayToken::Indent) before it
+    UnmatchedDedent { name: String },
+    /// The node emitted some [`Indent`](DisplayToken::Indent)s that it never dedented
+    UnclosedIndent { name: String, levels: usize },
+}
+
+impl std::fmt::Display for TokenStreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenStreamError::UnmatchedDedent { name } => {
+                write!(f, "{} dedents without a matching indent", name)
+            }
+            TokenStreamError::UnclosedIndent { name, levels } => {
+                write!(f, "{} leaves {} indentation level(s) open", name, levels)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenStreamError {}
+
+/// Checks that every node in the tree under `root` balances its own
+/// [`Indent`](DisplayToken::Indent)s and [`Dedent`](DisplayToken::Dedent)s, returning an error
+/// naming the first node that doesn't.  Checking each node separately (rather than the whole
+/// token stream) means that the error points at the node that is actually at fault.
+pub fn validate_token_stream<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    format_style: &Node::FormatStyle,
+) -> Result<(), TokenStreamError> {
+    for node in root.iter_pre_order() {
+        match unclosed_indents(&node.display_tokens_rec(format_style)) {
+            Some(0) => {}
+            Some(levels) => {
+                return Err(TokenStreamError::UnclosedIndent {
+                    name: node.display_name(),
+                    levels,
+                })
+            }
+            None => {
+                return Err(TokenStreamError::UnmatchedDedent {
+                    name: node.display_name(),
+                })
             }
         }
     }
+    Ok(())
+}
+
+/// Returns how many indentation levels are left open at the end of some tokens, or `None` if the
+/// tokens ever dedent further than they have indented
+fn unclosed_indents<Node>(tokens: &[RecTok<'_, Node>]) -> Option<usize> {
+    let mut depth = 0usize;
+    for tok in tokens {
+        match tok {
+            RecTok::Tok(DisplayToken::Indent) => depth += 1,
+            RecTok::Tok(DisplayToken::Dedent) => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    Some(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        unclosed_indents, validate_token_stream, write_token_stream, DisplayToken, IndentKind,
+        RecTok, SyntaxCategory,
+    };
+    use crate::arena::Arena;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::test_json::{Rng, TestJSON};
+    use crate::ast::Ast;
+    use std::borrow::Cow;
+
+    #[test]
+    fn static_text_is_borrowed() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![TestJSON::True, TestJSON::Str("x".to_string())])
+            .add_to_arena(&arena);
+        let texts: Vec<Cow<'static, str>> = root
+            .display_tokens(&JSONFormat::Compact)
+            .filter_map(|(_, tok)| match tok {
+                DisplayToken::Text(s, _) => Some(s),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, vec!["[", "true", ",", r#""x""#, "]"]);
+        // Only the string's text has to be allocated
+        let borrowed = texts.iter().map(|s| matches!(s, Cow::Borrowed(_)));
+        assert_eq!(
+            borrowed.collect::<Vec<_>>(),
+            vec![true, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn deep_tree() {
+        // Build the tree directly in the arena, since anything recursive would overflow the stack
+        let arena = Arena::new();
+        let mut node = arena.alloc(JSON::True);
+        for _ in 0..100_000 {
+            node = arena.alloc(JSON::Array(vec![node]));
+        }
+        assert_eq!(node.display_tokens(&JSONFormat::Compact).count(), 200_001);
+        let text = node.to_text(&JSONFormat::Compact);
+        assert_eq!(text.len(), 200_004);
+        assert_eq!(&text[99_995..100_009], "[[[[[true]]]]]");
+    }
+
+    #[test]
+    fn unbalanced_tokens() {
+        use DisplayToken::*;
+        let text = |tokens: Vec<DisplayToken>| {
+            let mut string = String::new();
+            write_token_stream(tokens, &mut string, IndentKind::Spaces(2)).unwrap();
+            string
+        };
+        let a = || Text("a".into(), SyntaxCategory::Default);
+        assert_eq!(
+            text(vec![a(), Indent, Newline, a(), Dedent, Newline, a()]),
+            "a\n  a\na"
+        );
+        // Extra dedents are ignored, rather than removing indentation that isn't there
+        assert_eq!(
+            text(vec![
+                Dedent,
+                a(),
+                Indent,
+                Newline,
+                a(),
+                Dedent,
+                Dedent,
+                Newline,
+                a()
+            ]),
+            "a\n  a\na"
+        );
+        assert_eq!(text(vec![Indent, Indent, Newline, a()]), "\n    a");
+
+        // Long runs of unbalanced tokens and huge text tokens are written like any others
+        let long_text = "日".repeat(1 << 20);
+        let mut tokens = vec![Dedent; 10_000];
+        tokens.extend(vec![Indent; 10_000]);
+        tokens.extend(vec![
+            Text(long_text.clone().into(), SyntaxCategory::Default),
+            Newline,
+            a(),
+        ]);
+        let written = text(tokens);
+        assert_eq!(written.len(), long_text.len() + 1 + 10_000 * 2 + 1);
+        assert!(written.starts_with(&long_text) && written.ends_with("  a"));
+
+        let tok = |t| RecTok::<()>::Tok(t);
+        assert_eq!(unclosed_indents(&[tok(Indent), tok(Dedent)]), Some(0));
+        assert_eq!(unclosed_indents(&[tok(Indent), tok(Indent)]), Some(2));
+        assert_eq!(
+            unclosed_indents(&[tok(Indent), tok(Dedent), tok(Dedent)]),
+            None
+        );
+        assert_eq!(unclosed_indents(&[tok(Dedent), tok(Indent)]), None);
+    }
+
+    #[test]
+    fn json_tokens_are_balanced() {
+        let mut rng = Rng::new(1);
+        let format = JSONFormat::Pretty {
+            indent: IndentKind::default(),
+            trailing_commas: false,
+        };
+        for _ in 0..100 {
+            let arena = Arena::new();
+            let root = TestJSON::random(&mut rng, 4).add_to_arena(&arena);
+            assert_eq!(validate_token_stream(root, &format), Ok(()));
+        }
+    }
 }
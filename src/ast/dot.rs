@@ -0,0 +1,157 @@
+//! Export of trees as [Graphviz](https://graphviz.org) DOT graphs, which can be turned into
+//! pictures of the tree (e.g. with `dot -Tpng tree.dot -o tree.png`).
+
+use super::Ast;
+use crate::config::color::{debug_color, to_hex};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+
+/// Escapes a string so that it can go between the `"`s of a DOT label
+fn escape_label(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a DOT digraph with one vertex for every node under `root` (labelled with its
+/// [`display_name`](Ast::display_name)), and edges from every node to its children.  Vertices
+/// are numbered in pre-order, and the children of each vertex are drawn in order.  If `colored`
+/// is `true`, then every vertex is filled with the colour that debug highlighting would give its
+/// node.
+pub fn write_dot<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    out: &mut impl Write,
+    colored: bool,
+) -> std::io::Result<()> {
+    writeln!(out, "digraph ast {{")?;
+    writeln!(out, "    ordering=out;")?;
+    // The ids of the vertices whose children are still to be written, along with their
+    // children.  Ids are given out in pre-order, so a vertex's first child has the next id after
+    // it.
+    let mut next_id = 0;
+    let mut stack = vec![(None, std::slice::from_ref(&root).iter())];
+    while let Some((parent_id, children)) = stack.last_mut() {
+        let parent_id = *parent_id;
+        let node = match children.next() {
+            Some(node) => node,
+            None => {
+                stack.pop();
+                continue;
+            }
+        };
+        let id = next_id;
+        next_id += 1;
+        write!(
+            out,
+            "    n{} [label=\"{}\"",
+            id,
+            escape_label(&node.display_name())
+        )?;
+        if colored {
+            let mut hasher = DefaultHasher::new();
+            node.hash(&mut hasher);
+            if let Some(hex) = to_hex(debug_color(hasher.finish())) {
+                write!(out, ", style=filled, fillcolor=\"{}\"", hex)?;
+            }
+        }
+        writeln!(out, "];")?;
+        if let Some(parent_id) = parent_id {
+            writeln!(out, "    n{} -> n{};", parent_id, id)?;
+        }
+        stack.push((Some(id), node.children().iter()));
+    }
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_label;
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use crate::ast::Ast;
+
+    /// Returns the DOT graph of a tree
+    fn dot<'arena>(root: &'arena JSON<'arena>, colored: bool) -> String {
+        let mut out = Vec::new();
+        root.write_dot(&mut out, colored).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn golden_file() {
+        let arena = Arena::new();
+        let root = JSON::parse(
+            r#"{"name": "sapling", "tags": ["tree", true, null]}"#,
+            &arena,
+        )
+        .unwrap();
+        assert_eq!(
+            dot(root, false),
+            include_str!("../../resources/golden/json.dot")
+        );
+    }
+
+    #[test]
+    fn single_node() {
+        let arena = Arena::new();
+        let root = JSON::parse("42", &arena).unwrap();
+        assert_eq!(
+            dot(root, false),
+            "digraph ast {\n    ordering=out;\n    n0 [label=\"42\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        for (label, expected) in &[
+            ("array", "array"),
+            (r#""quoted""#, r#"\"quoted\""#),
+            (r"back\slash", r"back\\slash"),
+            ("two\nlines", r"two\nlines"),
+            ("crlf\r\n", r"crlf\n"),
+            ("ünïcode", "ünïcode"),
+        ] {
+            assert_eq!(escape_label(label), *expected);
+        }
+        // A string's display name contains quotes, which mustn't end the label
+        let arena = Arena::new();
+        let root = JSON::parse(r#"["a\"b"]"#, &arena).unwrap();
+        assert!(dot(root, false).contains(r#"n1 [label="\"a\\\"b\""];"#));
+    }
+
+    #[test]
+    fn colors() {
+        let arena = Arena::new();
+        let root = JSON::parse("[true, true, false]", &arena).unwrap();
+        let text = dot(root, true);
+        let fill_colors: Vec<&str> = text
+            .lines()
+            .filter(|line| line.contains("[label="))
+            .map(|line| line.split("fillcolor=").nth(1).unwrap())
+            .collect();
+        assert_eq!(fill_colors.len(), 4);
+        // Equal nodes are always the same colour
+        assert_eq!(fill_colors[1], fill_colors[2]);
+        assert!(fill_colors.iter().all(|c| c.starts_with("\"#")));
+        // Colouring doesn't change the structure of the graph
+        let uncolored = dot(root, false);
+        assert_eq!(
+            text.lines()
+                .filter(|l| l.contains("->"))
+                .collect::<Vec<_>>(),
+            uncolored
+                .lines()
+                .filter(|l| l.contains("->"))
+                .collect::<Vec<_>>()
+        );
+    }
+}
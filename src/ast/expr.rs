@@ -0,0 +1,1118 @@
+//! A small language of arithmetic expressions (like `-(x + 1) * 2`), which is mainly useful for
+//! demos and tests.
+//!
+//! Parentheses aren't stored in the tree: every operator knows how tightly it binds, and the
+//! operands that would otherwise be read back as part of a different expression are put in
+//! parentheses when they're rendered.  This means that replacing a node with an operator that
+//! binds less tightly never changes what the rest of the expression means.
+
+use super::display_token::{
+    DisplayToken, FormatKind, IndentKind, RecTok, RenderStyle, SyntaxCategory,
+};
+use super::size::Size;
+use super::{Ast, DeleteError, InsertError};
+use crate::arena::{Arena, HeapSize};
+
+/// The ways that expressions can be formatted.  Expressions are always on one line, so there's
+/// no indentation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ExprFormat {
+    /// No whitespace at all, e.g. `-(x+1)*2`
+    Compact,
+    /// A space on either side of every binary operator, e.g. `-(x + 1) * 2`
+    Pretty,
+}
+
+impl RenderStyle for ExprFormat {
+    fn indent(&self) -> IndentKind {
+        IndentKind::Spaces(0)
+    }
+
+    fn set_indent(&mut self, _indent: IndentKind) {
+        // Nothing is ever indented
+    }
+
+    fn set_kind(&mut self, kind: FormatKind) {
+        *self = match kind {
+            FormatKind::Compact => ExprFormat::Compact,
+            // An expression doesn't get any narrower when it's split over lines, so it's
+            // formatted the same way whether or not it fits
+            FormatKind::Pretty | FormatKind::Fit { .. } => ExprFormat::Pretty,
+        };
+    }
+}
+
+/// The binary operators, all of which are left-associative
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    /// Returns the operator that's written as `c`, if there is one
+    fn from_char(c: char) -> Option<BinOp> {
+        match c {
+            '+' => Some(BinOp::Add),
+            '-' => Some(BinOp::Sub),
+            '*' => Some(BinOp::Mul),
+            '/' => Some(BinOp::Div),
+            _ => None,
+        }
+    }
+
+    /// The text that this operator is written as
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BinOp::Add => "addition",
+            BinOp::Sub => "subtraction",
+            BinOp::Mul => "multiplication",
+            BinOp::Div => "division",
+        }
+    }
+
+    /// How tightly this operator binds its operands (see [`Expr::precedence`])
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 1,
+            BinOp::Mul | BinOp::Div => 2,
+        }
+    }
+}
+
+const CHAR_NEG: char = 'n';
+const CHAR_VARIABLE: char = 'v';
+/// The chars of the binary operators, which create an operation of that kind
+const OPERATOR_CHARS: &[char] = &['+', '-', '*', '/'];
+
+/// The precedence of a negation, which binds more tightly than any binary operator
+const NEG_PRECEDENCE: u8 = 3;
+/// The precedence of integers and variables, which never need parentheses
+const ATOM_PRECEDENCE: u8 = 4;
+
+/// What's rendered in place of an operand that hasn't been inserted yet
+const PLACEHOLDER: &str = "?";
+
+/// The category used for integers
+const CATEGORY_LITERAL: SyntaxCategory = SyntaxCategory::Literal;
+/// The category used for variables
+const CATEGORY_IDENT: SyntaxCategory = SyntaxCategory::Ident;
+/// The category used for operators and parentheses
+const CATEGORY_DEFAULT: SyntaxCategory = SyntaxCategory::Default;
+/// The category used for missing operands and variables without a valid name
+const CATEGORY_ERROR: SyntaxCategory = SyntaxCategory::Error;
+
+/// Returns `true` if `c` can be part of the name of a variable
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Returns `true` if `name` would be read back as a variable of that name, i.e. it's made of
+/// ASCII letters, digits and `_`s and doesn't start with a digit
+fn is_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit()) && name.chars().all(is_name_char)
+}
+
+/// The sapling representation of an arithmetic expression
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum Expr<'arena> {
+    /// A non-negative integer, stored as the digits it's written with.  Negative numbers are
+    /// negations of integers.
+    Integer(String),
+    /// A variable, like `x`
+    Variable(String),
+    /// A binary operation.  This has up to two operands, where the ones that haven't been
+    /// inserted yet are rendered as `?` (so an operation with one operand is `a + ?`).
+    Binary(BinOp, Vec<&'arena Expr<'arena>>),
+    /// A unary minus, which has up to one operand
+    Neg(Vec<&'arena Expr<'arena>>),
+}
+
+impl Expr<'_> {
+    /// Return an iterator over all the possible chars that could represent expressions.  Any
+    /// digit will create an integer starting with that digit.
+    fn all_expression_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new(
+            OPERATOR_CHARS
+                .iter()
+                .copied()
+                .chain([CHAR_NEG, CHAR_VARIABLE].iter().copied())
+                .chain('0'..='9'),
+        )
+    }
+
+    /// How tightly this expression binds, which is higher for operators that are applied first.
+    /// An operand needs parentheses if it binds less tightly than its parent.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(op, _) => op.precedence(),
+            Expr::Neg(_) => NEG_PRECEDENCE,
+            Expr::Integer(_) | Expr::Variable(_) => ATOM_PRECEDENCE,
+        }
+    }
+
+    /// Returns the most operands that this expression can have
+    fn max_operands(&self) -> usize {
+        match self {
+            Expr::Binary(..) => 2,
+            Expr::Neg(_) => 1,
+            Expr::Integer(_) | Expr::Variable(_) => 0,
+        }
+    }
+
+    /// Returns `true` if the operand of this expression at `index` has to be put in parentheses
+    /// to be read back as the same tree.  Because every binary operator is left-associative, a
+    /// right operand needs parentheses if it binds as tightly as its parent (e.g. `a - (b - c)`).
+    fn needs_parens(&self, index: usize, operand: &Expr) -> bool {
+        match self {
+            Expr::Binary(op, _) => {
+                operand.precedence() < op.precedence()
+                    || (index == 1 && operand.precedence() == op.precedence())
+            }
+            Expr::Neg(_) => operand.precedence() < NEG_PRECEDENCE,
+            Expr::Integer(_) | Expr::Variable(_) => false,
+        }
+    }
+}
+
+impl Default for Expr<'_> {
+    fn default() -> Expr<'static> {
+        Expr::Integer("0".to_string())
+    }
+}
+
+impl HeapSize for Expr<'_> {
+    fn heap_size(&self) -> usize {
+        match self {
+            Expr::Integer(text) | Expr::Variable(text) => text.heap_size(),
+            Expr::Binary(_, operands) | Expr::Neg(operands) => operands.heap_size(),
+        }
+    }
+}
+
+impl<'arena> Ast<'arena> for Expr<'arena> {
+    type FormatStyle = ExprFormat;
+
+    const TOKEN_CATEGORIES: &'static [SyntaxCategory] = &[
+        CATEGORY_LITERAL,
+        CATEGORY_IDENT,
+        CATEGORY_DEFAULT,
+        CATEGORY_ERROR,
+    ];
+
+    /* FORMATTING FUNCTIONS */
+
+    fn display_tokens_rec(
+        &'arena self,
+        format_style: &Self::FormatStyle,
+    ) -> Vec<RecTok<'arena, Self>> {
+        let text =
+            |text: &'static str, category| RecTok::Tok(DisplayToken::Text(text.into(), category));
+        let (operator, operands) = match self {
+            Expr::Integer(digits) => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    digits.clone().into(),
+                    CATEGORY_LITERAL,
+                ))]
+            }
+            Expr::Variable(name) => {
+                let category = if is_name(name) {
+                    CATEGORY_IDENT
+                } else {
+                    CATEGORY_ERROR
+                };
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    name.clone().into(),
+                    category,
+                ))];
+            }
+            Expr::Binary(op, operands) => (op.symbol(), operands),
+            Expr::Neg(operands) => ("-", operands),
+        };
+
+        let mut tokens = Vec::with_capacity(9);
+        for i in 0..self.max_operands() {
+            // The operator goes before the only operand of a negation, and between the operands
+            // of a binary operation
+            if i == self.max_operands() - 1 {
+                let is_binary = i > 0;
+                if is_binary && *format_style == ExprFormat::Pretty {
+                    tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                }
+                tokens.push(text(operator, CATEGORY_DEFAULT));
+                if is_binary && *format_style == ExprFormat::Pretty {
+                    tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                }
+            }
+            match operands.get(i) {
+                Some(operand) if self.needs_parens(i, operand) => {
+                    tokens.push(text("(", CATEGORY_DEFAULT));
+                    tokens.push(RecTok::Child(*operand));
+                    tokens.push(text(")", CATEGORY_DEFAULT));
+                }
+                Some(operand) => tokens.push(RecTok::Child(*operand)),
+                None => tokens.push(text(PLACEHOLDER, CATEGORY_ERROR)),
+            }
+        }
+        tokens
+    }
+
+    fn size_from_children(&self, format_style: &Self::FormatStyle, child_sizes: &[Size]) -> Size {
+        let operands = match self {
+            Expr::Integer(text) | Expr::Variable(text) => return Size::from(text.as_str()),
+            Expr::Binary(_, operands) | Expr::Neg(operands) => operands,
+        };
+        // The operator of every expression is a single char
+        let mut size = Size::line(1);
+        if matches!(self, Expr::Binary(..)) && *format_style == ExprFormat::Pretty {
+            size += Size::line(2);
+        }
+        for (i, (operand, &child_size)) in operands.iter().zip(child_sizes).enumerate() {
+            if self.needs_parens(i, operand) {
+                size += Size::line(1) + child_size + Size::line(1);
+            } else {
+                size += child_size;
+            }
+        }
+        for _ in operands.len()..self.max_operands() {
+            size += Size::from(PLACEHOLDER);
+        }
+        size
+    }
+
+    /* DEBUG VIEW FUNCTIONS */
+
+    fn children<'s>(&'s self) -> &'s [&'arena Expr<'arena>] {
+        match self {
+            Expr::Binary(_, operands) | Expr::Neg(operands) => operands,
+            Expr::Integer(_) | Expr::Variable(_) => &[],
+        }
+    }
+
+    fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena Expr<'arena>] {
+        match self {
+            Expr::Binary(_, operands) | Expr::Neg(operands) => operands,
+            Expr::Integer(_) | Expr::Variable(_) => &mut [],
+        }
+    }
+
+    fn insert_child(
+        &mut self,
+        new_node: &'arena Self,
+        _arena: &'arena Arena<Self>,
+        index: usize,
+    ) -> Result<(), InsertError> {
+        let len = self.children().len();
+        if index > len {
+            return Err(InsertError::IndexOutOfRange { len, index });
+        }
+        let max_children = self.max_operands();
+        match self {
+            Expr::Binary(_, operands) | Expr::Neg(operands) if operands.len() < max_children => {
+                operands.insert(index, new_node);
+                Ok(())
+            }
+            _ => Err(InsertError::TooManyChildren {
+                kind: self.node_kind(),
+                max_children,
+            }),
+        }
+    }
+
+    fn delete_children(&mut self, range: std::ops::Range<usize>) -> Result<(), DeleteError> {
+        match self {
+            // Deleting an operand leaves a placeholder for it, so any of them can be deleted
+            Expr::Binary(_, operands) | Expr::Neg(operands) => {
+                if range.end <= operands.len() {
+                    operands.drain(range);
+                    Ok(())
+                } else {
+                    Err(DeleteError::IndexOutOfRange {
+                        len: operands.len(),
+                        index: range.end - 1,
+                    })
+                }
+            }
+            // Integers and variables have no children, so there's nothing that could be selected
+            // for deletion
+            Expr::Integer(_) | Expr::Variable(_) => unreachable!(),
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match self {
+            Expr::Integer(digits) => digits.clone(),
+            Expr::Variable(name) if !name.is_empty() => name.clone(),
+            _ => self.node_kind().to_string(),
+        }
+    }
+
+    fn node_kind(&self) -> &'static str {
+        match self {
+            Expr::Integer(_) => "integer",
+            Expr::Variable(_) => "variable",
+            Expr::Binary(op, _) => op.name(),
+            Expr::Neg(_) => "negation",
+        }
+    }
+
+    fn fold_summary(&self) -> String {
+        match self {
+            Expr::Binary(op, _) => format!("… {} …", op.symbol()),
+            Expr::Neg(_) => "-…".to_string(),
+            _ => self.display_name(),
+        }
+    }
+
+    /* AST EDITING FUNCTIONS */
+
+    fn replace_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        Self::all_expression_chars()
+    }
+
+    fn from_char(&self, c: char) -> Option<Self> {
+        match c {
+            CHAR_NEG => Some(Expr::Neg(vec![])),
+            CHAR_VARIABLE => Some(Expr::Variable(String::new())),
+            '0'..='9' => Some(Expr::Integer(c.to_string())),
+            _ => BinOp::from_char(c).map(|op| Expr::Binary(op, vec![])),
+        }
+    }
+
+    fn is_valid_child(&self, index: usize, _child: &Self) -> bool {
+        // Any expression can be an operand
+        index < self.max_operands()
+    }
+
+    fn wrap_in(&'arena self, c: char, arena: &'arena Arena<Self>) -> Option<&'arena Self> {
+        // The new operator takes this node as its first operand, so wrapping `x` in `+` makes
+        // `x + ?`
+        match c {
+            CHAR_NEG => Some(arena.alloc(Expr::Neg(vec![self]))),
+            _ => BinOp::from_char(c).map(|op| arena.alloc(Expr::Binary(op, vec![self]))),
+        }
+    }
+
+    fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            Expr::Binary(..) | Expr::Neg(_) => Self::all_expression_chars(),
+            Expr::Integer(_) | Expr::Variable(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    fn push_char(&self, c: char) -> Option<Self> {
+        match self {
+            Expr::Integer(digits) if c.is_ascii_digit() => {
+                Some(Expr::Integer(format!("{}{}", digits, c)))
+            }
+            _ => None,
+        }
+    }
+
+    fn pop_char(&self) -> Option<Self> {
+        match self {
+            Expr::Integer(digits) if digits.len() > 1 => {
+                Some(Expr::Integer(digits[..digits.len() - 1].to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn replace_text(&self, pattern: &str, replacement: &str) -> Option<Self> {
+        let text = self.text()?;
+        if pattern.is_empty() || !text.contains(pattern) {
+            return None;
+        }
+        self.with_text(&text.replace(pattern, replacement))
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Expr::Variable(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn with_text(&self, text: &str) -> Option<Self> {
+        match self {
+            // Anything that couldn't be part of a name would be read back as something else
+            Expr::Variable(_) => Some(Expr::Variable(
+                text.chars().filter(|&c| is_name_char(c)).collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn text_offset(&self, index: usize) -> usize {
+        index
+    }
+}
+
+/* ===== PARSING ===== */
+
+/// The maximum depth of nested parentheses, and of the tree of nodes, that [`Expr::parse`] will
+/// accept, so that a pathological input like `((((((...` or `1-1-1-1-...` can't make a tree
+/// that overflows the stack
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// The different ways that parsing an expression can fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// We expected to find some specific thing, but found either a different char or the end of
+    /// the input (if `found` is `None`)
+    Expected {
+        expected: &'static str,
+        found: Option<char>,
+    },
+    /// The input contains parentheses nested more than [`MAX_NESTING_DEPTH`] levels deep, or
+    /// its nodes (e.g. a long chain of operators) would be nested deeper than that
+    TooDeeplyNested,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Expected {
+                expected,
+                found: Some(c),
+            } => write!(f, "expected {}, found {:?}", expected, c),
+            ParseErrorKind::Expected {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of input", expected),
+            ParseErrorKind::TooDeeplyNested => write!(
+                f,
+                "expressions are nested more than {} levels deep",
+                MAX_NESTING_DEPTH
+            ),
+        }
+    }
+}
+
+/// An error generated when parsing an expression, along with the location of the error.  Both
+/// `line` and `column` start from 1, and `column` is measured in [`char`]s.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A node that has been parsed, along with its height (the number of levels of nodes below it)
+type Parsed<'arena> = (&'arena Expr<'arena>, usize);
+
+/// A recursive-descent (precedence climbing) expression parser, which allocates the nodes it
+/// parses into an [`Arena`].
+struct Parser<'t, 'arena> {
+    arena: &'arena Arena<Expr<'arena>>,
+    chars: std::iter::Peekable<std::str::Chars<'t>>,
+    /// The line of the next char to be consumed
+    line: usize,
+    /// The column of the next char to be consumed
+    column: usize,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    fn new(text: &'t str, arena: &'arena Arena<Expr<'arena>>) -> Self {
+        Parser {
+            arena,
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Creates an error of a given kind at the current position
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    /// Creates an error saying that we expected something other than the next char
+    fn expected(&mut self, expected: &'static str) -> ParseError {
+        let found = self.peek();
+        self.error(ParseErrorKind::Expected { expected, found })
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    /// Consumes the next char, keeping track of the position in the text
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    /// Parses the whole text, which must be exactly one expression
+    fn parse_root(&mut self) -> Result<&'arena Expr<'arena>, ParseError> {
+        let (root, _) = self.parse_binary(1, 0)?;
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(root),
+            Some(_) => Err(self.expected("an operator")),
+        }
+    }
+
+    /// Allocates a node whose tallest child is `child_height` levels tall, returning it along
+    /// with its own height (or an error if that's more than [`MAX_NESTING_DEPTH`])
+    fn alloc_parent(
+        &self,
+        expr: Expr<'arena>,
+        child_height: usize,
+    ) -> Result<Parsed<'arena>, ParseError> {
+        if child_height >= MAX_NESTING_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeeplyNested));
+        }
+        Ok((self.arena.alloc(expr), child_height + 1))
+    }
+
+    /// Parses a sequence of operations whose operators all have at least `min_precedence`.  Each
+    /// operation becomes the left operand of the next one, so a chain of operators makes a tree
+    /// as deep as the chain is long.
+    fn parse_binary(
+        &mut self,
+        min_precedence: u8,
+        depth: usize,
+    ) -> Result<Parsed<'arena>, ParseError> {
+        let (mut left, mut height) = if min_precedence > BinOp::Mul.precedence() {
+            self.parse_unary(depth)?
+        } else {
+            self.parse_binary(min_precedence + 1, depth)?
+        };
+        loop {
+            self.skip_whitespace();
+            let op = match self.peek().and_then(BinOp::from_char) {
+                Some(op) if op.precedence() == min_precedence => op,
+                _ => return Ok((left, height)),
+            };
+            self.next();
+            // Parsing the right operand at a higher precedence makes operators left-associative
+            let (right, right_height) = if min_precedence >= BinOp::Mul.precedence() {
+                self.parse_unary(depth)?
+            } else {
+                self.parse_binary(min_precedence + 1, depth)?
+            };
+            (left, height) = self.alloc_parent(
+                Expr::Binary(op, vec![left, right]),
+                height.max(right_height),
+            )?;
+        }
+    }
+
+    /// Parses a negation, integer, variable or parenthesized expression
+    fn parse_unary(&mut self, depth: usize) -> Result<Parsed<'arena>, ParseError> {
+        self.skip_whitespace();
+        let expr = match self.peek() {
+            Some('-') | Some('(') if depth >= MAX_NESTING_DEPTH => {
+                return Err(self.error(ParseErrorKind::TooDeeplyNested));
+            }
+            Some('-') => {
+                self.next();
+                let (inner, height) = self.parse_unary(depth + 1)?;
+                return self.alloc_parent(Expr::Neg(vec![inner]), height);
+            }
+            Some('(') => {
+                self.next();
+                let inner = self.parse_binary(1, depth + 1)?;
+                self.skip_whitespace();
+                return match self.next() {
+                    Some(')') => Ok(inner),
+                    _ => Err(self.expected("an operator or ')'")),
+                };
+            }
+            Some(c) if c.is_ascii_digit() => Expr::Integer(self.take_while(|c| c.is_ascii_digit())),
+            Some(c) if is_name_char(c) => Expr::Variable(self.take_while(is_name_char)),
+            _ => return Err(self.expected("an expression")),
+        };
+        Ok((self.arena.alloc(expr), 0))
+    }
+
+    /// Consumes all the chars up to the first one that doesn't match `predicate`
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|&c| predicate(c)) {
+            text.push(c);
+            self.next();
+        }
+        text
+    }
+}
+
+impl<'arena> Expr<'arena> {
+    /// Parses the text of an expression, allocating all the nodes into a given [`Arena`] and
+    /// returning a reference to the root node.
+    pub fn parse(
+        text: &str,
+        arena: &'arena Arena<Expr<'arena>>,
+    ) -> Result<&'arena Expr<'arena>, ParseError> {
+        Parser::new(text, arena).parse_root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::display_token::{DisplayToken, FormatKind, RenderStyle, SyntaxCategory};
+    use super::super::size::Size;
+    use super::super::test_json::Rng;
+    use super::{BinOp, Expr, ExprFormat, ParseError, ParseErrorKind, MAX_NESTING_DEPTH};
+    use crate::arena::Arena;
+    use crate::ast::{Ast, DeleteError, InsertError};
+
+    /// Evaluates an expression with wrapping arithmetic, where every variable is the length of
+    /// its name.  Returns `None` if the expression is incomplete or divides by zero.
+    fn evaluate(expr: &Expr) -> Option<i64> {
+        match expr {
+            Expr::Integer(digits) => digits.parse().ok(),
+            Expr::Variable(name) => Some(name.len() as i64),
+            Expr::Neg(operands) => Some(evaluate(operands.first()?)?.wrapping_neg()),
+            Expr::Binary(op, operands) => {
+                let left = evaluate(operands.first()?)?;
+                let right = evaluate(operands.get(1)?)?;
+                match op {
+                    BinOp::Add => Some(left.wrapping_add(right)),
+                    BinOp::Sub => Some(left.wrapping_sub(right)),
+                    BinOp::Mul => Some(left.wrapping_mul(right)),
+                    BinOp::Div => left.checked_div(right),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn to_text() {
+        let arena = Arena::new();
+        let root = Expr::parse(" - ( x+1 )*2\n/ y_1", &arena).unwrap();
+        assert_eq!(root.to_text(&ExprFormat::Compact), "-(x+1)*2/y_1");
+        assert_eq!(root.to_text(&ExprFormat::Pretty), "-(x + 1) * 2 / y_1");
+        // Operands that haven't been inserted yet are placeholders
+        let one = arena.alloc(Expr::Integer("1".to_string()));
+        let partial = Expr::Binary(BinOp::Sub, vec![one]);
+        assert_eq!(partial.to_text(&ExprFormat::Pretty), "1 - ?");
+        assert_eq!(Expr::Neg(vec![]).to_text(&ExprFormat::Pretty), "-?");
+        assert_eq!(Expr::default().to_text(&ExprFormat::Pretty), "0");
+    }
+
+    #[test]
+    fn parentheses() {
+        // Only the parentheses that change how the expression is read are kept
+        for (text, expected_text) in &[
+            ("a + b + c", "a + b + c"),
+            ("(a + b) + c", "a + b + c"),
+            ("a + (b + c)", "a + (b + c)"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("a - (b + c)", "a - (b + c)"),
+            ("(a * b) + c", "a * b + c"),
+            ("a * (b + c)", "a * (b + c)"),
+            ("(a + b) * c", "(a + b) * c"),
+            ("a / (b * c)", "a / (b * c)"),
+            ("a * b / c", "a * b / c"),
+            ("-(a * b)", "-(a * b)"),
+            ("(-a) * b", "-a * b"),
+            ("a - -b", "a - -b"),
+            ("--(a)", "--a"),
+            ("((((1))))", "1"),
+        ] {
+            let arena = Arena::new();
+            let root = Expr::parse(text, &arena).unwrap();
+            assert_eq!(
+                root.to_text(&ExprFormat::Pretty),
+                *expected_text,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn syntax_categories() {
+        let arena = Arena::new();
+        let unnamed = arena.alloc(Expr::Variable(String::new()));
+        let x = arena.alloc(Expr::Variable("x".to_string()));
+        let one = arena.alloc(Expr::Integer("1".to_string()));
+        let sum = arena.alloc(Expr::Binary(BinOp::Add, vec![x, one]));
+        let neg = arena.alloc(Expr::Neg(vec![sum]));
+        let root = arena.alloc(Expr::Binary(BinOp::Mul, vec![neg, unnamed]));
+        let root = arena.alloc(Expr::Binary(BinOp::Div, vec![root]));
+        let categories: Vec<(String, SyntaxCategory)> = root
+            .display_tokens(&ExprFormat::Compact)
+            .filter_map(|(_, tok)| match tok {
+                DisplayToken::Text(text, category) => Some((text.into_owned(), category)),
+                _ => None,
+            })
+            .collect();
+        let expected = [
+            ("-", SyntaxCategory::Default),
+            ("(", SyntaxCategory::Default),
+            ("x", SyntaxCategory::Ident),
+            ("+", SyntaxCategory::Default),
+            ("1", SyntaxCategory::Literal),
+            (")", SyntaxCategory::Default),
+            ("*", SyntaxCategory::Default),
+            // Variables without a name are errors, as are missing operands
+            ("", SyntaxCategory::Error),
+            ("/", SyntaxCategory::Default),
+            ("?", SyntaxCategory::Error),
+        ];
+        assert_eq!(
+            categories,
+            expected
+                .iter()
+                .map(|&(text, category)| (text.to_string(), category))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_structure() {
+        let arena = Arena::new();
+        let root = Expr::parse("1 - 2 * -x - 3", &arena).unwrap();
+        let integer = |digits: &str| Expr::Integer(digits.to_string());
+        let (one, two, three) = (integer("1"), integer("2"), integer("3"));
+        let x = Expr::Variable("x".to_string());
+        let neg = Expr::Neg(vec![&x]);
+        let product = Expr::Binary(BinOp::Mul, vec![&two, &neg]);
+        let left = Expr::Binary(BinOp::Sub, vec![&one, &product]);
+        assert_eq!(*root, Expr::Binary(BinOp::Sub, vec![&left, &three]));
+        // `x` is the length of its name, which is 1
+        let x_value = 1;
+        assert_eq!(evaluate(root), Some(1 - 2 * -x_value - 3));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line, column, kind) in &[
+            (
+                "",
+                1,
+                1,
+                ParseErrorKind::Expected {
+                    expected: "an expression",
+                    found: None,
+                },
+            ),
+            (
+                "1 +\n* 2",
+                2,
+                1,
+                ParseErrorKind::Expected {
+                    expected: "an expression",
+                    found: Some('*'),
+                },
+            ),
+            (
+                "(1 + 2",
+                1,
+                7,
+                ParseErrorKind::Expected {
+                    expected: "an operator or ')'",
+                    found: None,
+                },
+            ),
+            (
+                "1 2",
+                1,
+                3,
+                ParseErrorKind::Expected {
+                    expected: "an operator",
+                    found: Some('2'),
+                },
+            ),
+            (
+                "2x",
+                1,
+                2,
+                ParseErrorKind::Expected {
+                    expected: "an operator",
+                    found: Some('x'),
+                },
+            ),
+            (
+                "a + é",
+                1,
+                5,
+                ParseErrorKind::Expected {
+                    expected: "an expression",
+                    found: Some('é'),
+                },
+            ),
+        ] {
+            let arena = Arena::new();
+            assert_eq!(
+                Expr::parse(text, &arena),
+                Err(ParseError {
+                    line: *line,
+                    column: *column,
+                    kind: kind.clone()
+                }),
+                "parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let arena = Arena::new();
+        for (open, close) in &[("(", ")"), ("-", "")] {
+            let text = open.repeat(MAX_NESTING_DEPTH) + "1" + &close.repeat(MAX_NESTING_DEPTH);
+            assert!(Expr::parse(&text, &arena).is_ok());
+            let text =
+                open.repeat(MAX_NESTING_DEPTH + 1) + "1" + &close.repeat(MAX_NESTING_DEPTH + 1);
+            assert_eq!(
+                Expr::parse(&text, &arena).unwrap_err().kind,
+                ParseErrorKind::TooDeeplyNested
+            );
+        }
+        // Each operator in a chain nests the operators before it one level deeper
+        let text = vec!["1"; MAX_NESTING_DEPTH + 1].join("-");
+        assert_eq!(
+            Expr::parse(&text, &arena).unwrap().depth(),
+            MAX_NESTING_DEPTH
+        );
+        for op in ["-", "*", "+"] {
+            let text = vec!["1"; 60_000].join(op);
+            assert_eq!(
+                Expr::parse(&text, &arena).unwrap_err(),
+                ParseError {
+                    line: 1,
+                    column: 2 * MAX_NESTING_DEPTH + 4,
+                    kind: ParseErrorKind::TooDeeplyNested
+                }
+            );
+        }
+        // Negations count towards the depth as well
+        let text = format!("-{}", vec!["1"; MAX_NESTING_DEPTH + 1].join("*"));
+        assert_eq!(
+            Expr::parse(&text, &arena).unwrap_err().kind,
+            ParseErrorKind::TooDeeplyNested
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let mut format = ExprFormat::Compact;
+        format.set_kind(FormatKind::Fit { max_width: 10 });
+        assert_eq!(format, ExprFormat::Pretty);
+        format.set_kind(FormatKind::Compact);
+        assert_eq!(format, ExprFormat::Compact);
+    }
+
+    /// Generates a pseudo-random (and complete) expression which is at most `max_depth` levels
+    /// deep
+    fn random<'arena>(
+        rng: &mut Rng,
+        max_depth: usize,
+        arena: &'arena Arena<Expr<'arena>>,
+    ) -> &'arena Expr<'arena> {
+        const NAMES: &[&str] = &["x", "y", "_a1", "long_name"];
+        const OPS: &[BinOp] = &[BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div];
+        let kinds = if max_depth == 0 { 2 } else { 4 };
+        let node = match rng.below(kinds) {
+            0 => Expr::Integer((rng.next_u64() % 1000).to_string()),
+            1 => Expr::Variable(NAMES[rng.below(NAMES.len())].to_string()),
+            2 => Expr::Neg(vec![random(rng, max_depth - 1, arena)]),
+            _ => Expr::Binary(
+                OPS[rng.below(OPS.len())],
+                vec![
+                    random(rng, max_depth - 1, arena),
+                    random(rng, max_depth - 1, arena),
+                ],
+            ),
+        };
+        arena.alloc(node)
+    }
+
+    #[test]
+    fn size_matches_text() {
+        // The size of a node should always be the size of the text that it's rendered as, and
+        // the text should parse back into the same tree
+        let mut rng = Rng::new(0);
+        for _ in 0..500 {
+            let arena = Arena::new();
+            let root = random(&mut rng, 5, &arena);
+            for format in &[ExprFormat::Compact, ExprFormat::Pretty] {
+                let text = root.to_text(format);
+                assert_eq!(root.size(format), Size::from(text.as_str()), "{}", text);
+                assert_eq!(Expr::parse(&text, &arena), Ok(root), "{}", text);
+            }
+        }
+    }
+
+    /// Returns a copy of `root` with the node at `path` replaced by `new_node`
+    fn replace_at<'arena>(
+        root: &'arena Expr<'arena>,
+        path: &[usize],
+        new_node: &'arena Expr<'arena>,
+        arena: &'arena Arena<Expr<'arena>>,
+    ) -> &'arena Expr<'arena> {
+        match path.split_first() {
+            None => new_node,
+            Some((&index, rest)) => {
+                let mut parent = root.clone();
+                let child = parent.children()[index];
+                parent.children_mut()[index] = replace_at(child, rest, new_node, arena);
+                arena.alloc(parent)
+            }
+        }
+    }
+
+    #[test]
+    fn replacing_keeps_meaning() {
+        // Replacing an operand with an operator that binds less tightly adds parentheses around
+        // it, so the rest of the expression still means the same thing
+        let arena = Arena::new();
+        let root = Expr::parse("a * b - c", &arena).unwrap();
+        let sum = Expr::parse("x + y", &arena).unwrap();
+        let replaced = replace_at(root, &[0, 1], sum, &arena);
+        assert_eq!(replaced.to_text(&ExprFormat::Pretty), "a * (x + y) - c");
+        let replaced = replace_at(root, &[1], sum, &arena);
+        assert_eq!(replaced.to_text(&ExprFormat::Pretty), "a * b - (x + y)");
+
+        // The same is true of any replacement in any expression: the text of the new tree is
+        // read back as the new tree, and so evaluates to the same value
+        let mut rng = Rng::new(1);
+        for _ in 0..500 {
+            let arena = Arena::new();
+            let root = random(&mut rng, 4, &arena);
+            let mut path = Vec::new();
+            let mut node = root;
+            while !node.children().is_empty() && rng.below(3) > 0 {
+                let index = rng.below(node.children().len());
+                path.push(index);
+                node = node.children()[index];
+            }
+            let new_node = random(&mut rng, 2, &arena);
+            let replaced = replace_at(root, &path, new_node, &arena);
+            for format in &[ExprFormat::Compact, ExprFormat::Pretty] {
+                let text = replaced.to_text(format);
+                let reparsed = Expr::parse(&text, &arena).unwrap();
+                assert_eq!(reparsed, replaced, "{}", text);
+                assert_eq!(evaluate(reparsed), evaluate(replaced), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn editing_atoms() {
+        let integer = Expr::default().from_char('4').unwrap();
+        let integer = integer.push_char('2').unwrap();
+        assert_eq!(integer, Expr::Integer("42".to_string()));
+        assert_eq!(integer.push_char('.'), None);
+        assert_eq!(integer.pop_char(), Some(Expr::Integer("4".to_string())));
+        assert_eq!(Expr::Integer("4".to_string()).pop_char(), None);
+        assert_eq!(integer.text(), None);
+
+        // Variables are typed with `i`, and can only contain chars that could be in a name
+        let variable = Expr::default().from_char('v').unwrap();
+        assert_eq!(variable.display_name(), "variable");
+        let variable = variable.with_text("my var-2").unwrap();
+        assert_eq!(variable, Expr::Variable("myvar2".to_string()));
+        assert_eq!(variable.text_offset(2), 2);
+        assert_eq!(
+            variable.replace_text("var", "x"),
+            Some(Expr::Variable("myx2".to_string()))
+        );
+        assert_eq!(variable.replace_text("z", "x"), None);
+        // A name can't start with a digit, but that's only an error once it's rendered
+        let digits = Expr::Variable("1a".to_string());
+        let tokens: Vec<_> = digits.display_tokens(&ExprFormat::Pretty).collect();
+        assert_eq!(
+            tokens,
+            vec![(
+                &digits,
+                DisplayToken::Text("1a".into(), SyntaxCategory::Error)
+            )]
+        );
+    }
+
+    #[test]
+    fn structure() {
+        let arena = Arena::new();
+        let x = arena.alloc(Expr::Variable("x".to_string()));
+        let one = arena.alloc(Expr::Integer("1".to_string()));
+        // Operators are created without operands, which are then inserted
+        let mut sum = Expr::default().from_char('+').unwrap();
+        assert_eq!(sum, Expr::Binary(BinOp::Add, vec![]));
+        assert!(sum.is_valid_child(1, x));
+        assert!(!sum.is_valid_child(2, x));
+        sum.insert_child(one, &arena, 0).unwrap();
+        sum.insert_child(x, &arena, 0).unwrap();
+        assert_eq!(sum.to_text(&ExprFormat::Pretty), "x + 1");
+        assert_eq!(
+            sum.insert_child(one, &arena, 2),
+            Err(InsertError::TooManyChildren {
+                kind: "addition",
+                max_children: 2,
+            })
+        );
+        assert_eq!(
+            x.clone().insert_child(one, &arena, 0),
+            Err(InsertError::TooManyChildren {
+                kind: "variable",
+                max_children: 0,
+            })
+        );
+        assert_eq!(sum.fold_summary(), "… + …");
+        assert_eq!(sum.unwrap_index(), None);
+        // Swapping the operands is allowed, even though it changes the value
+        sum.move_child(1, 0).unwrap();
+        assert_eq!(sum.to_text(&ExprFormat::Pretty), "1 + x");
+
+        // Deleting an operand leaves a placeholder, and the remaining operand can be unwrapped
+        assert_eq!(
+            sum.delete_children(1..3),
+            Err(DeleteError::IndexOutOfRange { len: 2, index: 2 })
+        );
+        sum.delete_child(0).unwrap();
+        assert_eq!(sum.to_text(&ExprFormat::Pretty), "x + ?");
+        assert_eq!(sum.unwrap_index(), Some(0));
+
+        // Wrapping a node in an operator makes it the first operand
+        let wrapped = x.wrap_in('*', &arena).unwrap();
+        assert_eq!(wrapped.to_text(&ExprFormat::Compact), "x*?");
+        let negated = wrapped.wrap_in('n', &arena).unwrap();
+        assert_eq!(negated.to_text(&ExprFormat::Compact), "-(x*?)");
+        assert_eq!(negated.fold_summary(), "-…");
+        assert_eq!(x.wrap_in('v', &arena), None);
+        for c in x.replace_chars() {
+            assert!(negated.is_valid_child(0, &x.from_char(c).unwrap()));
+        }
+        assert!(negated.is_insert_char('7'));
+        assert!(!x.is_insert_char('+'));
+    }
+}
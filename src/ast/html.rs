@@ -0,0 +1,219 @@
+//! Export of trees as syntax highlighted HTML, which looks like the tree does in the editor.
+
+use super::display_token::{DisplayToken, IndentKind, RenderStyle};
+use super::Ast;
+use crate::config::color::to_hex;
+use crate::config::ColorScheme;
+use std::io::Write;
+
+/// Escapes the characters of `text` that have special meanings in HTML
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a stylesheet which gives the text of every [`SyntaxCategory`] (including the ones of
+/// the scheme's language) its style from `color_scheme`.  The text is on a black background,
+/// like in most terminals.  Categories whose colour is the terminal's default colour don't get a
+/// `color`, so they're the colour of the surrounding page.
+///
+/// [`SyntaxCategory`]: super::display_token::SyntaxCategory
+fn write_stylesheet(color_scheme: &ColorScheme, out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out, "<style>")?;
+    writeln!(out, "pre.sapling {{ background-color: #000000; }}")?;
+    for category in color_scheme.categories() {
+        let style = color_scheme.style(category);
+        let mut rules = Vec::new();
+        if let Some(hex) = to_hex(style.fg) {
+            rules.push(format!("color: {};", hex));
+        }
+        if let Some(hex) = style.bg.and_then(to_hex) {
+            rules.push(format!("background-color: {};", hex));
+        }
+        for (on, rule) in &[
+            (style.bold, "font-weight: bold;"),
+            (style.dim, "opacity: 0.5;"),
+            (style.italic, "font-style: italic;"),
+            (style.underline, "text-decoration: underline;"),
+        ] {
+            if *on {
+                rules.push(rule.to_string());
+            }
+        }
+        if !rules.is_empty() {
+            writeln!(out, ".sapling-{} {{ {} }}", category, rules.join(" "))?;
+        }
+    }
+    writeln!(out, "</style>")
+}
+
+/// Writes the tree under `root` as a `<pre>` element (preceded by its stylesheet), where the
+/// text of every [`Text`](DisplayToken::Text) token is in a `<span>` whose class is
+/// `sapling-<category>`.  Apart from the tags and escapes, the text is exactly what
+/// [`to_text`](Ast::to_text) would give.
+pub fn write_html<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    format_style: &Node::FormatStyle,
+    color_scheme: &ColorScheme,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    write_stylesheet(color_scheme, out)?;
+    write!(out, "<pre class=\"sapling\">")?;
+    let indent = match format_style.indent() {
+        IndentKind::Spaces(n) => " ".repeat(n),
+        IndentKind::Tabs => "\t".to_string(),
+    };
+    let mut indent_depth = 0usize;
+    for (_, tok) in root.display_tokens(format_style) {
+        match tok {
+            DisplayToken::Text(text, category) => write!(
+                out,
+                "<span class=\"sapling-{}\">{}</span>",
+                category,
+                escape_html(&text)
+            )?,
+            DisplayToken::Whitespace(n) => write!(out, "{}", " ".repeat(n))?,
+            DisplayToken::Newline => write!(out, "\n{}", indent.repeat(indent_depth))?,
+            DisplayToken::Indent => indent_depth += 1,
+            DisplayToken::Dedent => indent_depth = indent_depth.saturating_sub(1),
+        }
+    }
+    writeln!(out, "</pre>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, write_html};
+    use crate::arena::Arena;
+    use crate::ast::display_token::IndentKind;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::test_json::{Rng, TestJSON};
+    use crate::ast::Ast;
+    use crate::config::{default_color_scheme, ColorScheme};
+
+    /// Returns the HTML of a tree, using the default colour scheme
+    fn html<'arena>(root: &'arena JSON<'arena>, format_style: &JSONFormat) -> String {
+        let mut out = Vec::new();
+        write_html(root, format_style, &default_color_scheme(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Removes the stylesheet, tags and escapes from some HTML, leaving the text that it shows
+    fn shown_text(html: &str) -> String {
+        let pre = &html[html.find("<pre").unwrap()..];
+        let mut text = String::new();
+        let mut in_tag = false;
+        for c in pre.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                c if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&amp;", "&")
+    }
+
+    #[test]
+    fn golden_file() {
+        let arena = Arena::new();
+        let root = JSON::parse(
+            r#"{"name": "<sapling & co>", "version": 1.5, "tags": ["tree", true, null], "empty": {}}"#,
+            &arena,
+        )
+        .unwrap();
+        let format_style = JSONFormat::Pretty {
+            indent: IndentKind::Spaces(4),
+            trailing_commas: false,
+        };
+        assert_eq!(
+            html(root, &format_style),
+            include_str!("../../resources/golden/json.html")
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        for (text, expected) in &[
+            ("plain", "plain"),
+            ("<a href=\"x\">", "&lt;a href=&quot;x&quot;&gt;"),
+            ("&amp;", "&amp;amp;"),
+            ("'single'", "'single'"),
+        ] {
+            assert_eq!(escape_html(text), *expected);
+        }
+    }
+
+    #[test]
+    fn stylesheet() {
+        let arena = Arena::new();
+        let root = JSON::parse("[]", &arena).unwrap();
+        let text = html(root, &JSONFormat::Compact);
+        assert!(text.starts_with("<style>\n"));
+        assert!(text.contains(".sapling-default { color: #e5e5e5; }\n"));
+        assert!(text.contains(".sapling-const { color: #ff0000; }\n"));
+        // Colours from the config file are used
+        let overrides = ColorScheme::from_toml("[colors]\nconst = \"#123abc\"\ncomment = \"7\"")
+            .unwrap()
+            .0;
+        let mut out = Vec::new();
+        write_html(root, &JSONFormat::Compact, &overrides, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains(".sapling-const { color: #123abc; }\n"));
+        assert!(text.contains(".sapling-comment { color: #e5e5e5; }\n"));
+    }
+
+    #[test]
+    fn indentation() {
+        let arena = Arena::new();
+        let root = JSON::parse(r#"[[1], {"a": [true]}]"#, &arena).unwrap();
+        for indent in &[
+            IndentKind::Spaces(2),
+            IndentKind::Spaces(0),
+            IndentKind::Tabs,
+        ] {
+            let format_style = JSONFormat::Pretty {
+                indent: *indent,
+                trailing_commas: true,
+            };
+            let text = html(root, &format_style);
+            assert_eq!(
+                shown_text(&text),
+                root.to_text(&format_style) + "\n",
+                "{:?}",
+                indent
+            );
+        }
+    }
+
+    #[test]
+    fn shown_text_matches_text() {
+        let mut rng = Rng::new(0x5a91);
+        for _ in 0..100 {
+            let arena = Arena::new();
+            let root = TestJSON::random(&mut rng, 4).add_to_arena(&arena);
+            let pretty = JSONFormat::Pretty {
+                indent: IndentKind::default(),
+                trailing_commas: false,
+            };
+            for format_style in &[JSONFormat::Compact, pretty] {
+                assert_eq!(
+                    shown_text(&html(root, format_style)),
+                    root.to_text(format_style) + "\n"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Iterators over every node in a tree.
+//!
+//! These are implemented with an explicit stack rather than recursion, so that walking even very
+//! deep trees can't overflow the call stack.  They are also lazy: each call to `next` only
+//! does as much work as is needed to find the next node.
+
+use super::Ast;
+
+/// An iterator over a node and all its descendants in pre-order (i.e. every node comes before
+/// its children).  Created by [`Ast::iter_pre_order`].
+#[derive(Debug, Clone)]
+pub struct PreOrderIter<'arena, Node: Ast<'arena>> {
+    /// The node that will be yielded next, if it hasn't been yielded yet
+    root: Option<&'arena Node>,
+    /// The children of each ancestor of the most recently yielded node (including its own
+    /// children), which haven't been yielded yet
+    stack: Vec<std::slice::Iter<'arena, &'arena Node>>,
+}
+
+impl<'arena, Node: Ast<'arena>> PreOrderIter<'arena, Node> {
+    /// Creates a new iterator starting at `root`
+    pub(super) fn new(root: &'arena Node) -> Self {
+        PreOrderIter {
+            root: Some(root),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns the depth (below the root of the iteration) of the node that was most recently
+    /// returned by [`next`](Iterator::next)
+    pub fn depth(&self) -> usize {
+        self.stack.len().saturating_sub(1)
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Iterator for PreOrderIter<'arena, Node> {
+    type Item = &'arena Node;
+
+    fn next(&mut self) -> Option<&'arena Node> {
+        if let Some(root) = self.root.take() {
+            self.stack.push(root.children().iter());
+            return Some(root);
+        }
+        loop {
+            let child = match self.stack.last_mut()?.next() {
+                Some(child) => *child,
+                None => {
+                    // Every child of this node has been yielded, so move back up the tree
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            self.stack.push(child.children().iter());
+            return Some(child);
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> std::iter::FusedIterator for PreOrderIter<'arena, Node> {}
+
+/// An iterator over a node and all its descendants in post-order (i.e. every node comes after
+/// its children).  Created by [`Ast::iter_post_order`].
+#[derive(Debug, Clone)]
+pub struct PostOrderIter<'arena, Node: Ast<'arena>> {
+    /// The ancestors of the next node to be yielded, along with the children of each of them
+    /// which haven't been visited yet
+    stack: Vec<(&'arena Node, std::slice::Iter<'arena, &'arena Node>)>,
+}
+
+impl<'arena, Node: Ast<'arena>> PostOrderIter<'arena, Node> {
+    /// Creates a new iterator starting at `root`
+    pub(super) fn new(root: &'arena Node) -> Self {
+        PostOrderIter {
+            stack: vec![(root, root.children().iter())],
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Iterator for PostOrderIter<'arena, Node> {
+    type Item = &'arena Node;
+
+    fn next(&mut self) -> Option<&'arena Node> {
+        loop {
+            let (node, children) = self.stack.last_mut()?;
+            match children.next() {
+                // Descend into the child, since it and its descendants have to be yielded first
+                Some(&child) => {
+                    self.stack.push((child, child.children().iter()));
+                }
+                // All of this node's descendants have been yielded, so it's this node's turn
+                None => {
+                    let node = *node;
+                    self.stack.pop();
+                    return Some(node);
+                }
+            }
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> std::iter::FusedIterator for PostOrderIter<'arena, Node> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+
+    #[test]
+    fn order() {
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::Array(vec![J::True, J::False]),
+            J::Null,
+            J::Object(vec![("k".to_string(), J::Array(vec![]))]),
+        ])
+        .add_to_arena(&arena);
+        let names = |nodes: Vec<&JSON>| -> Vec<String> {
+            nodes.into_iter().map(|n| n.display_name()).collect()
+        };
+
+        assert_eq!(
+            names(root.iter_pre_order().collect()),
+            vec!["array", "array", "true", "false", "null", "object", "field", "\"k\"", "array"]
+        );
+        assert_eq!(
+            names(root.iter_post_order().collect()),
+            vec!["true", "false", "array", "null", "\"k\"", "array", "field", "object", "array"]
+        );
+        assert_eq!(root.node_count(), 9);
+        assert_eq!(root.depth(), 3);
+        assert_eq!(root.children()[1].node_count(), 1);
+        assert_eq!(root.children()[1].depth(), 0);
+    }
+
+    #[test]
+    fn deep_tree() {
+        // Build the tree directly in the arena, since anything recursive would overflow the stack
+        let arena = Arena::new();
+        let mut node = arena.alloc(JSON::True);
+        for _ in 0..100_000 {
+            node = arena.alloc(JSON::Array(vec![node]));
+        }
+        assert_eq!(node.node_count(), 100_001);
+        assert_eq!(node.depth(), 100_000);
+        assert_eq!(node.iter_post_order().next(), Some(&JSON::True));
+        assert_eq!(node.iter_pre_order().last(), Some(&JSON::True));
+    }
+
+    #[test]
+    fn lazy() {
+        let arena = Arena::new();
+        let leaf = arena.alloc(JSON::Array(vec![arena.alloc(JSON::Null)]));
+        let root = arena.alloc(JSON::Array(vec![leaf; 1_000_000]));
+        // Taking a few nodes only visits the nodes along the way, rather than queuing up all the
+        // children of the root
+        let mut iter = root.iter_pre_order();
+        assert_eq!(iter.by_ref().take(3).count(), 3);
+        assert_eq!(iter.depth(), 2);
+        assert_eq!(iter.stack.len(), 3);
+        let mut iter = root.iter_post_order();
+        assert_eq!(iter.by_ref().take(3).count(), 3);
+        assert_eq!(iter.stack.len(), 2);
+    }
+}
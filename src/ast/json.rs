@@ -1,16 +1,116 @@
-use super::display_token::{DisplayToken, RecTok};
-use super::size::Size;
-use super::{Ast, DeleteError, InsertError};
-use crate::arena::Arena;
+use super::display_token::{
+    write_token_stream, DisplayToken, FormatKind, IndentKind, RecTok, RenderStyle, SyntaxCategory,
+};
+use super::owned::OwnedNode;
+use super::size::{self, Size};
+use super::{Ast, DeleteError, InsertError, ValidationError};
+use crate::arena::{Arena, HeapSize};
+use crate::config::options::Options;
+use crate::language::Language;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+pub mod query;
+pub mod schema;
 
 /// An enum to hold the different ways that a JSON AST can be formatted
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub enum JSONFormat {
-    /// The most compact representation, has minimal whitespace.
-    /// E.g. `[{"foo": true, "bar": false}, true]`
+    /// The most compact representation, which has no whitespace at all (other than inside
+    /// strings).  E.g. `[{"foo":true,"bar":false},true]`
     Compact,
-    /// A prettified representation, with pretty indenting and every element on a newline.
-    Pretty,
+    /// A prettified representation, with every element on a newline and each level of nesting
+    /// indented by `indent`.  If `trailing_commas` is `true`, then the last element of every
+    /// array or object is followed by a comma too (which only [JSONC](Dialect::JSONC) allows).
+    Pretty {
+        indent: IndentKind,
+        trailing_commas: bool,
+    },
+    /// Like `Pretty`, except that arrays and objects which fit within `max_width` columns when
+    /// written on one line are kept on one line (e.g. `{"a": [1, 2], "b": true}`).  Whether a
+    /// node fits doesn't depend on how far it's indented, so that every node is laid out the same
+    /// way wherever it is in the tree (which lets its size be cached).
+    Fit {
+        indent: IndentKind,
+        max_width: usize,
+        trailing_commas: bool,
+    },
+}
+
+impl JSONFormat {
+    /// Returns `true` if the last element of every array or object that's spread over many
+    /// lines should be followed by a comma
+    fn has_trailing_commas(&self) -> bool {
+        match self {
+            JSONFormat::Compact => false,
+            JSONFormat::Pretty {
+                trailing_commas, ..
+            }
+            | JSONFormat::Fit {
+                trailing_commas, ..
+            } => *trailing_commas,
+        }
+    }
+}
+
+impl RenderStyle for JSONFormat {
+    fn indent(&self) -> IndentKind {
+        match self {
+            // Compact JSON never generates any indentation
+            JSONFormat::Compact => IndentKind::Spaces(0),
+            JSONFormat::Pretty { indent, .. } | JSONFormat::Fit { indent, .. } => *indent,
+        }
+    }
+
+    fn set_indent(&mut self, new_indent: IndentKind) {
+        if let JSONFormat::Pretty { indent, .. } | JSONFormat::Fit { indent, .. } = self {
+            *indent = new_indent;
+        }
+    }
+
+    fn set_kind(&mut self, kind: FormatKind) {
+        let indent = match *self {
+            JSONFormat::Pretty { indent, .. } | JSONFormat::Fit { indent, .. } => indent,
+            JSONFormat::Compact => IndentKind::default(),
+        };
+        let trailing_commas = self.has_trailing_commas();
+        *self = match kind {
+            FormatKind::Compact => JSONFormat::Compact,
+            FormatKind::Pretty => JSONFormat::Pretty {
+                indent,
+                trailing_commas,
+            },
+            FormatKind::Fit { max_width } => JSONFormat::Fit {
+                indent,
+                max_width,
+                trailing_commas,
+            },
+        };
+    }
+
+    fn apply_options(&mut self, options: &Options, language: Language) {
+        let new_trailing_commas = options.trailing_commas && language.allows_trailing_commas();
+        if let JSONFormat::Pretty {
+            trailing_commas, ..
+        }
+        | JSONFormat::Fit {
+            trailing_commas, ..
+        } = self
+        {
+            *trailing_commas = new_trailing_commas;
+        }
+    }
+}
+
+/// The variants of JSON that can be parsed
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Dialect {
+    /// Plain JSON, as defined by RFC 8259
+    JSON,
+    /// JSON with comments (as used by VS Code's settings and `tsconfig.json`), which allows `//`
+    /// and `/* */` comments and a comma after the last element of an array or object
+    JSONC,
 }
 
 const CHAR_TRUE: char = 't';
@@ -19,6 +119,135 @@ const CHAR_NULL: char = 'n';
 const CHAR_ARRAY: char = 'a';
 const CHAR_OBJECT: char = 'o';
 const CHAR_STRING: char = 's';
+const CHAR_LINE_COMMENT: char = '/';
+const CHAR_BLOCK_COMMENT: char = '*';
+
+/// The category used for the JSON constants `true`, `false` and `null`
+const CATEGORY_CONST: SyntaxCategory = SyntaxCategory::Const;
+/// The category used for JSON strings
+const CATEGORY_LITERAL: SyntaxCategory = SyntaxCategory::Literal;
+/// The category used for all the punctuation in JSON (brackets, commas and colons)
+const CATEGORY_DEFAULT: SyntaxCategory = SyntaxCategory::Default;
+/// The category used for the escape sequences inside JSON strings (e.g. `\n`), so that they
+/// stand out from the text around them
+const CATEGORY_SPECIAL: SyntaxCategory = SyntaxCategory::Special;
+/// The category used for comments
+const CATEGORY_COMMENT: SyntaxCategory = SyntaxCategory::Comment;
+/// The category used for numbers that are only partially typed (e.g. `-` or `1e`), and so aren't
+/// yet valid JSON
+const CATEGORY_ERROR: SyntaxCategory = SyntaxCategory::Error;
+
+/// How much of a valid JSON number a given string represents
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NumberValidity {
+    /// The string is a complete, valid JSON number (e.g. `0`, `-1.5` or `1e-9`)
+    Complete,
+    /// The string isn't a valid JSON number, but could be made into one by appending more chars
+    /// (e.g. `-`, `1.` or `1e-`)
+    Incomplete,
+    /// No amount of appending chars will make this string into a valid JSON number (e.g. `01`,
+    /// `1.e5` or `--1`)
+    Invalid,
+}
+
+/// Returns the escape sequence that a char has to be written as inside a JSON string, or `None`
+/// if it can be written as it is.  Following RFC 8259, quotes and backslashes are escaped, and
+/// so is every control char (using `\n` and `\t` for newlines and tabs, and `\u00XX` for the
+/// rest) so that they can't garble the screen.
+fn escape_char(c: char) -> Option<String> {
+    match c {
+        '"' => Some(r#"\""#.to_string()),
+        '\\' => Some(r"\\".to_string()),
+        '\n' => Some(r"\n".to_string()),
+        '\t' => Some(r"\t".to_string()),
+        _ if c.is_control() => Some(format!("\\u{:04x}", c as u32)),
+        _ => None,
+    }
+}
+
+/// Escapes the contents of a JSON string so that it can be written between quotes
+pub(crate) fn escape_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match escape_char(c) {
+            Some(escape) => escaped.push_str(&escape),
+            None => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Generates the tokens of a JSON string (including its quotes), where the escape sequences are
+/// separate tokens so that they can be highlighted differently to the rest of the string
+fn string_tokens<'arena>(string: &str) -> Vec<RecTok<'arena, JSON<'arena>>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::from('"');
+    for c in string.chars() {
+        match escape_char(c) {
+            Some(escape) => {
+                if !literal.is_empty() {
+                    let text = std::mem::take(&mut literal);
+                    tokens.push(RecTok::Tok(DisplayToken::Text(
+                        text.into(),
+                        CATEGORY_LITERAL,
+                    )));
+                }
+                tokens.push(RecTok::Tok(DisplayToken::Text(
+                    escape.into(),
+                    CATEGORY_SPECIAL,
+                )));
+            }
+            None => literal.push(c),
+        }
+    }
+    literal.push('"');
+    tokens.push(RecTok::Tok(DisplayToken::Text(
+        literal.into(),
+        CATEGORY_LITERAL,
+    )));
+    tokens
+}
+
+/// Determines how much of a valid JSON number a given string is.  This implements the grammar
+/// `-? (0 | [1-9][0-9]*) (\.[0-9]+)? ([eE][+-]?[0-9]+)?` as a state machine.
+pub fn number_validity(string: &str) -> NumberValidity {
+    /// The states of the state machine, named after the last thing that was read
+    #[derive(Copy, Clone, Eq, PartialEq)]
+    enum State {
+        Start,
+        Minus,
+        Zero,
+        Int,
+        Dot,
+        Frac,
+        Exp,
+        ExpSign,
+        ExpInt,
+    }
+
+    let mut state = State::Start;
+    for c in string.chars() {
+        state = match (state, c) {
+            (State::Start, '-') => State::Minus,
+            (State::Start, '0') | (State::Minus, '0') => State::Zero,
+            (State::Start, '1'..='9') | (State::Minus, '1'..='9') => State::Int,
+            (State::Int, '0'..='9') => State::Int,
+            (State::Zero, '.') | (State::Int, '.') => State::Dot,
+            (State::Dot, '0'..='9') | (State::Frac, '0'..='9') => State::Frac,
+            (State::Zero, 'e') | (State::Zero, 'E') => State::Exp,
+            (State::Int, 'e') | (State::Int, 'E') => State::Exp,
+            (State::Frac, 'e') | (State::Frac, 'E') => State::Exp,
+            (State::Exp, '+') | (State::Exp, '-') => State::ExpSign,
+            (State::Exp, '0'..='9') | (State::ExpSign, '0'..='9') => State::ExpInt,
+            (State::ExpInt, '0'..='9') => State::ExpInt,
+            _ => return NumberValidity::Invalid,
+        };
+    }
+    match state {
+        State::Zero | State::Int | State::Frac | State::ExpInt => NumberValidity::Complete,
+        _ => NumberValidity::Incomplete,
+    }
+}
 
 /// The sapling representation of the AST for a subset of JSON (where all values are either 'true'
 /// or 'false', and keys only contain ASCII).
@@ -30,23 +259,107 @@ pub enum JSON<'arena> {
     False,
     /// The JSON value 'null'.  Corresponds to the string `null`.
     Null,
+    /// A JSON number.  This is stored as the text that represents it, so that numbers like `1.0`
+    /// and `1` are preserved exactly.  The contents must always be either a complete or an
+    /// incomplete JSON number (see [`number_validity`]).
+    Number(String),
     /// A JSON array of multiple values.
     /// Corresponds to a string `[<v1>, <v2>, ...]` where `v1`, `v2`, ... are JSON values.
     Array(Vec<&'arena JSON<'arena>>),
     /// A JSON object, represented as a map of [`String`]s to more JSON values.
     /// Corresponds to a string `{"<key1>": <v1>, "<key2>": <v2>, ...}` where `<key1>`, `<key2>`,
     /// ... are the keys, and `<v1>`, `<v2>`, ... are the corresponding JSON values.  The `Ref`s
-    /// contained inside this must be [`Field`](JSON::Field)s.
+    /// contained inside this must be [`Field`](JSON::Field)s or comments.
     Object(Vec<&'arena JSON<'arena>>),
     /// A JSON object field.  The first `Ref` must be a [`Str`](JSON::Str), and the second is any
     /// JSON object
     Field([&'arena JSON<'arena>; 2]),
     /// A JSON string
     Str(String),
+    /// A `//` comment, which contains everything after the `//` up to the end of the line.  Like
+    /// every comment, this can only be a child of an array, an object or a
+    /// [`Document`](JSON::Document) (see [`Dialect::JSONC`]).
+    LineComment(String),
+    /// A `/* */` comment, which contains everything between the `/*` and the `*/`.  Any lines
+    /// after the first have their leading whitespace removed, and are indented by one space
+    /// (so that the `*`s of a comment like `/*\n * foo\n */` line up).
+    BlockComment(String),
+    /// An array or object in a large file, which has only been checked to be valid rather than
+    /// parsed into nodes (see [`LARGE_FILE_BYTES`]).  It has no children until it's parsed with
+    /// [`Ast::parse_unparsed`], so nothing inside it can be edited.
+    Unparsed(UnparsedText),
+    /// A [JSONC](Dialect::JSONC) file with comments before or after its root value, which keeps
+    /// those comments outside the value.  The `Ref`s are comments, then the root value, then more
+    /// comments, each on a line of its own.  This is only ever the root of a tree, and files
+    /// without comments around their root value don't have one.
+    Document(Vec<&'arena JSON<'arena>>),
+}
+
+/// The text of an [`Unparsed`](JSON::Unparsed) array or object, which is a range of the text of
+/// the whole file.  Every unparsed node shares the file's text, so it's only stored once.
+#[derive(Clone)]
+pub struct UnparsedText {
+    source: Arc<str>,
+    byte_range: Range<usize>,
+    dialect: Dialect,
+}
+
+impl UnparsedText {
+    /// Returns the text of the array or object, from its opening bracket to its closing bracket
+    pub fn as_str(&self) -> &str {
+        &self.source[self.byte_range.clone()]
+    }
+
+    /// Returns the range of bytes of the file's text that the array or object takes up
+    pub fn byte_range(&self) -> Range<usize> {
+        self.byte_range.clone()
+    }
+
+    /// Returns the summary that is rendered in place of the array or object, e.g.
+    /// `[… 1024 bytes, not parsed yet]`
+    fn summary(&self) -> String {
+        let (open, close) = if self.as_str().starts_with('[') {
+            ('[', ']')
+        } else {
+            ('{', '}')
+        };
+        format!(
+            "{}… {} bytes, not parsed yet{}",
+            open,
+            self.byte_range.len(),
+            close
+        )
+    }
+}
+
+// The file's text is far too big to print or compare, so only the node's own text is used
+impl std::fmt::Debug for UnparsedText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UnparsedText")
+            .field("byte_range", &self.byte_range)
+            .field("dialect", &self.dialect)
+            .finish()
+    }
+}
+
+impl PartialEq for UnparsedText {
+    fn eq(&self, other: &Self) -> bool {
+        self.dialect == other.dialect && self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for UnparsedText {}
+
+impl std::hash::Hash for UnparsedText {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+        self.dialect.hash(state);
+    }
 }
 
 impl JSON<'_> {
-    /// Return an iterator over all the possible chars that could represent JSON nodes
+    /// Return an iterator over all the possible chars that could represent JSON nodes.  Any digit
+    /// will create a number starting with that digit.
     fn all_object_chars() -> Box<dyn Iterator<Item = char>> {
         Box::new(
             [
@@ -58,9 +371,16 @@ impl JSON<'_> {
                 CHAR_STRING,
             ]
             .iter()
-            .copied(),
+            .copied()
+            .chain('0'..='9')
+            .chain(std::iter::once('-')),
         )
     }
+
+    /// Return an iterator over the chars that create comments
+    fn comment_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new([CHAR_LINE_COMMENT, CHAR_BLOCK_COMMENT].iter().copied())
+    }
 }
 
 impl Default for JSON<'_> {
@@ -69,217 +389,283 @@ impl Default for JSON<'_> {
     }
 }
 
+impl HeapSize for JSON<'_> {
+    fn heap_size(&self) -> usize {
+        match self {
+            JSON::True | JSON::False | JSON::Null => 0,
+            JSON::Array(children) | JSON::Object(children) | JSON::Document(children) => {
+                children.heap_size()
+            }
+            // The key and value are in the arena, so the array owns no memory
+            JSON::Field(_) => 0,
+            // The file's text is shared by every unparsed node, so it doesn't belong to any one
+            JSON::Unparsed(_) => 0,
+            JSON::Number(text)
+            | JSON::Str(text)
+            | JSON::LineComment(text)
+            | JSON::BlockComment(text) => text.heap_size(),
+        }
+    }
+}
+
 impl<'arena> Ast<'arena> for JSON<'arena> {
     type FormatStyle = JSONFormat;
 
+    const TOKEN_CATEGORIES: &'static [SyntaxCategory] = &[
+        CATEGORY_CONST,
+        CATEGORY_LITERAL,
+        CATEGORY_DEFAULT,
+        CATEGORY_SPECIAL,
+        CATEGORY_COMMENT,
+        CATEGORY_ERROR,
+    ];
+
     /* FORMATTING FUNCTIONS */
 
     fn display_tokens_rec(
         &'arena self,
         format_style: &Self::FormatStyle,
     ) -> Vec<RecTok<'arena, Self>> {
-        let is_pretty = format_style == &JSONFormat::Pretty;
-        match self {
-            JSON::True => vec![RecTok::Tok(DisplayToken::Text("true".to_string()))],
-            JSON::False => vec![RecTok::Tok(DisplayToken::Text("false".to_string()))],
-            JSON::Null => vec![RecTok::Tok(DisplayToken::Text("null".to_string()))],
-            JSON::Str(string) => vec![RecTok::Tok(DisplayToken::Text(format!(r#""{}""#, string)))],
-            JSON::Field([key, value]) => vec![
-                RecTok::Child(key),
-                RecTok::Tok(DisplayToken::Text(": ".to_string())),
-                RecTok::Child(value),
-            ],
-            JSON::Array(children) => {
-                // Special case: if this array is empty, render it as '[]'
-                if children.is_empty() {
-                    return vec![RecTok::Tok(DisplayToken::Text("[]".to_string()))];
-                }
-
-                let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(6 + 3 * children.len());
-                // Push some initial tokens
-                tokens.push(RecTok::Tok(DisplayToken::Text("[".to_string())));
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Indent));
-                    tokens.push(RecTok::Tok(DisplayToken::Newline));
-                }
-                // Push the children, delimited by commas
-                let mut is_first_child = true;
-                for c in children {
-                    // Push the delimiting
-                    if !is_first_child {
-                        tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
-                        if is_pretty {
-                            tokens.push(RecTok::Tok(DisplayToken::Newline));
-                        } else {
-                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
-                        }
+        // Everything but compact JSON has a space after every ',' and ':' that isn't followed by
+        // a newline
+        let is_spaced = !matches!(format_style, JSONFormat::Compact);
+        let (children, open, close, empty) = match self {
+            JSON::True => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    "true".into(),
+                    CATEGORY_CONST,
+                ))]
+            }
+            JSON::False => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    "false".into(),
+                    CATEGORY_CONST,
+                ))]
+            }
+            JSON::Null => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    "null".into(),
+                    CATEGORY_CONST,
+                ))]
+            }
+            JSON::Number(number) => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    number.clone().into(),
+                    match number_validity(number) {
+                        NumberValidity::Complete => CATEGORY_LITERAL,
+                        _ => CATEGORY_ERROR,
+                    },
+                ))]
+            }
+            JSON::Str(string) => return string_tokens(string),
+            JSON::LineComment(text) => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    format!("//{}", text).into(),
+                    CATEGORY_COMMENT,
+                ))]
+            }
+            JSON::BlockComment(text) => return block_comment_tokens(text),
+            JSON::Unparsed(text) => {
+                return vec![RecTok::Tok(DisplayToken::Text(
+                    text.summary().into(),
+                    CATEGORY_COMMENT,
+                ))]
+            }
+            JSON::Field([key, value]) => {
+                return vec![
+                    RecTok::Child(key),
+                    RecTok::Tok(DisplayToken::Text(
+                        if is_spaced { ": " } else { ":" }.into(),
+                        CATEGORY_DEFAULT,
+                    )),
+                    RecTok::Child(value),
+                ]
+            }
+            // Every child of a document is on a line of its own, so that no comment can run into
+            // the root value
+            JSON::Document(children) => {
+                let mut tokens = Vec::with_capacity(2 * children.len());
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        tokens.push(RecTok::Tok(DisplayToken::Newline));
                     }
-                    is_first_child = false;
-                    // Push the single child
-                    tokens.push(RecTok::Child(c));
-                }
-                // Push the closing bracket
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Dedent));
-                    tokens.push(RecTok::Tok(DisplayToken::Newline));
+                    tokens.push(RecTok::Child(*child));
                 }
-                tokens.push(RecTok::Tok(DisplayToken::Text("]".to_string())));
-                // Return the token stream
-                tokens
+                return tokens;
             }
-            JSON::Object(fields) => {
-                // Special case: if this object is empty, render it as '{}'
-                if fields.is_empty() {
-                    return vec![RecTok::Tok(DisplayToken::Text("{}".to_string()))];
-                }
+            JSON::Array(children) => (children, "[", "]", "[]"),
+            JSON::Object(fields) => (fields, "{", "}", "{}"),
+        };
 
-                let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(6 + 3 * fields.len());
-                // Push some initial tokens
-                tokens.push(RecTok::Tok(DisplayToken::Text("{".to_string())));
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Indent));
-                    tokens.push(RecTok::Tok(DisplayToken::Newline));
-                }
-                // Push the children, delimited by commas
-                let mut is_first_child = true;
-                for f in fields {
-                    // Push the delimiting
-                    if !is_first_child {
-                        tokens.push(RecTok::Tok(DisplayToken::Text(",".to_string())));
-                        if is_pretty {
-                            tokens.push(RecTok::Tok(DisplayToken::Newline));
-                        } else {
-                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
-                        }
-                    }
-                    is_first_child = false;
-                    // Push the single child
-                    tokens.push(RecTok::Child(f));
-                }
-                // Push the closing bracket
-                if is_pretty {
-                    tokens.push(RecTok::Tok(DisplayToken::Dedent));
+        // Special case: if this array or object is empty, render it as '[]' or '{}'
+        if children.is_empty() {
+            return vec![RecTok::Tok(DisplayToken::Text(
+                empty.into(),
+                CATEGORY_DEFAULT,
+            ))];
+        }
+
+        let is_broken = self.is_broken(format_style);
+        let trailing_commas = is_broken && format_style.has_trailing_commas();
+        let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(6 + 3 * children.len());
+        // Push some initial tokens
+        tokens.push(RecTok::Tok(DisplayToken::Text(
+            open.into(),
+            CATEGORY_DEFAULT,
+        )));
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Indent));
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        for (i, child) in children.iter().enumerate() {
+            // Separate each child from the one before it.  A line comment runs to the end of its
+            // line, so anything after one has to go on a new line.
+            if i > 0 {
+                if is_broken || children[i - 1].is_line_comment() {
                     tokens.push(RecTok::Tok(DisplayToken::Newline));
+                } else if is_spaced {
+                    tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
                 }
-                tokens.push(RecTok::Tok(DisplayToken::Text("}".to_string())));
-                // Return the token stream
-                tokens
             }
+            // Push the single child, and the comma after it (if it's a value)
+            tokens.push(RecTok::Child(child));
+            if Self::has_comma(children, i, trailing_commas) {
+                tokens.push(RecTok::Tok(DisplayToken::Text(
+                    ",".into(),
+                    CATEGORY_DEFAULT,
+                )));
+            }
+        }
+        // Push the closing bracket
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Dedent));
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        } else if Self::ends_with_line_comment(children) {
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
         }
+        tokens.push(RecTok::Tok(DisplayToken::Text(
+            close.into(),
+            CATEGORY_DEFAULT,
+        )));
+        // Return the token stream
+        tokens
     }
 
-    fn size(&self, format_style: &Self::FormatStyle) -> Size {
-        match format_style {
-            JSONFormat::Pretty => {
-                match self {
-                    JSON::True => Size::new(0, 4),  // same as Size::from("true")
-                    JSON::False => Size::new(0, 5), // same as Size::from("false")
-                    JSON::Null => Size::new(0, 4),  // same as Size::from("null")
-                    JSON::Str(string) => {
-                        Size::new(0, 1) + Size::from(string.as_str()) + Size::new(0, 1)
-                    }
-                    JSON::Field([key, value]) => {
-                        key.size(format_style) + Size::new(0, 2) + value.size(format_style)
-                    }
-                    JSON::Object(fields) => {
-                        // Special case: if the object is empty, then it will be rendered as "{}",
-                        // which only takes up one line
-                        if fields.is_empty() {
-                            return Size::new(0, 2); // same as Size::from("{}")
-                        }
-                        /* For an object, we are only interested in how many lines are occupied -
-                         * the last line will always just be "}" */
-                        // We initialise this to 1 because the opening '{' occupies its own line.
-                        let mut number_of_lines = 1;
-                        for f in fields {
-                            // The `+ 1` accounts for the extra newline char generated between
-                            // every field.
-                            number_of_lines += f.size(format_style).lines() + 1;
-                        }
-                        Size::new(number_of_lines, 1)
-                    }
-                    JSON::Array(children) => {
-                        // Special case: if the array is empty, then it will be rendered as "[]",
-                        // which only takes up one line
-                        if children.is_empty() {
-                            return Size::new(0, 2); // same as Size::from("[]");
-                        }
-                        /* For an array, we are only interested in how many lines are occupied -
-                         * the last line will always just be "]" */
-                        // We initialise this to 1 because the opening '[' occupies its own line.
-                        let mut number_of_lines = 1;
-                        for c in children {
-                            // The `+ 1` accounts for the extra newline char generated between
-                            // every child.
-                            number_of_lines += c.size(format_style).lines() + 1;
-                        }
-                        Size::new(number_of_lines, 1)
+    fn size_from_children(&self, format_style: &Self::FormatStyle, child_sizes: &[Size]) -> Size {
+        let is_spaced = !matches!(format_style, JSONFormat::Compact);
+        let children = match self {
+            JSON::True => return Size::line(4),  // same as Size::from("true")
+            JSON::False => return Size::line(5), // same as Size::from("false")
+            JSON::Null => return Size::line(4),  // same as Size::from("null")
+            JSON::Number(number) => return Size::from(number.as_str()),
+            // Every escape sequence is printable ASCII, so escaped strings are always one line
+            JSON::Str(string) => {
+                return Size::line(1) + Size::from(escape_string(string).as_str()) + Size::line(1)
+            }
+            JSON::LineComment(text) => return Size::line(2) + Size::from(text.as_str()),
+            JSON::BlockComment(text) => return Size::from(block_comment_text(text).as_str()),
+            JSON::Unparsed(text) => return Size::from(text.summary().as_str()),
+            // The key and value are separated by ": ", or just ":" in compact JSON
+            JSON::Field(_) => {
+                let colon = if is_spaced { 2 } else { 1 };
+                return child_sizes[0] + Size::line(colon) + child_sizes[1];
+            }
+            JSON::Document(_) => {
+                let mut size = size::ZERO;
+                for (i, &child_size) in child_sizes.iter().enumerate() {
+                    if i > 0 {
+                        size += Size::newline();
                     }
+                    size += child_size;
                 }
+                return size;
             }
-            JSONFormat::Compact => {
-                match self {
-                    JSON::True => Size::new(0, 4),  // same as Size::from("true")
-                    JSON::False => Size::new(0, 5), // same as Size::from("false")
-                    JSON::Null => Size::new(0, 4),  // same as Size::from("null")
-                    JSON::Str(string) => {
-                        Size::new(0, 1) + Size::from(string.as_str()) + Size::new(0, 1)
-                    }
-                    JSON::Field([key, value]) => {
-                        key.size(format_style) + Size::new(0, 2) + value.size(format_style)
-                    }
-                    JSON::Object(fields) => {
-                        // Size accumulator - starts with just the size of "{"
-                        let mut size = Size::new(0, 1);
-                        // Append all the children, and put ", " between all of them
-                        let mut is_first_child = true;
-                        for f in fields {
-                            // If we're not on the first child, add a ", "
-                            if !is_first_child {
-                                size += Size::new(0, 2);
-                            }
-                            is_first_child = false;
-                            size += f.size(format_style);
-                        }
-                        // Append one more char for "}" to the end, and return
-                        size + Size::new(0, 1)
-                    }
-                    JSON::Array(children) => {
-                        // Size accumulator - starts with just the size of "["
-                        let mut size = Size::new(0, 1);
-                        // Append all the children, and put ", " between all of them
-                        let mut is_first_child = true;
-                        for c in children {
-                            // If we're not on the first child, add a ", "
-                            if !is_first_child {
-                                size += Size::new(0, 2);
-                            }
-                            is_first_child = false;
-                            size += c.size(format_style);
-                        }
-                        // Append one more char for "]" to the end, and return
-                        size + Size::new(0, 1)
+            JSON::Array(children) | JSON::Object(children) => children,
+        };
+
+        // Special case: if the node is empty, then it will be rendered as "[]" or "{}", which
+        // only takes up one line
+        if children.is_empty() {
+            return Size::line(2);
+        }
+        let is_broken = self.is_broken(format_style);
+        let trailing_commas = is_broken && format_style.has_trailing_commas();
+        // How far each child is indented when they're on lines of their own.  Like `Size::from`,
+        // this counts tabs as a single column.
+        let indent_columns = format_style.indent().columns(1);
+        // Size accumulator - starts with just the size of the opening bracket
+        let mut size = Size::line(1);
+        for (i, &child_size) in child_sizes.iter().enumerate() {
+            if is_broken {
+                // Every child goes on its own indented line
+                size += Size::newline() + child_size.indented(indent_columns);
+            } else {
+                if i > 0 {
+                    if children[i - 1].is_line_comment() {
+                        size += Size::newline();
+                    } else if is_spaced {
+                        size += Size::line(1);
                     }
                 }
+                size += child_size;
+            }
+            if Self::has_comma(children, i, trailing_commas) {
+                size += Size::line(1);
             }
         }
+        // The closing bracket goes on its own line when the children do
+        if is_broken || Self::ends_with_line_comment(children) {
+            size += Size::newline();
+        }
+        size + Size::line(1)
+    }
+
+    fn write_text_to(
+        &'arena self,
+        out: &mut dyn std::fmt::Write,
+        format_style: &Self::FormatStyle,
+    ) -> std::fmt::Result {
+        // Unparsed nodes are written as the text that they were loaded from, rather than as the
+        // summary that's rendered in their place
+        let tokens = self.display_tokens(format_style).flat_map(|(node, tok)| {
+            let (tok, text) = match node {
+                JSON::Unparsed(text) => (None, Some(unparsed_text_tokens(text.as_str()))),
+                _ => (Some(tok), None),
+            };
+            tok.into_iter().chain(text.into_iter().flatten())
+        });
+        write_token_stream(tokens, out, format_style.indent())
     }
 
     /* DEBUG VIEW FUNCTIONS */
 
     fn children<'s>(&'s self) -> &'s [&'arena JSON<'arena>] {
         match self {
-            JSON::True | JSON::False | JSON::Null | JSON::Str(_) => &[],
-            JSON::Array(children) => &children,
-            JSON::Object(fields) => &fields,
+            JSON::True
+            | JSON::False
+            | JSON::Null
+            | JSON::Number(_)
+            | JSON::Str(_)
+            | JSON::LineComment(_)
+            | JSON::BlockComment(_)
+            | JSON::Unparsed(_) => &[],
+            JSON::Array(children) | JSON::Document(children) => children,
+            JSON::Object(fields) => fields,
             JSON::Field(key_value) => &key_value[..],
         }
     }
 
     fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena JSON<'arena>] {
         match self {
-            JSON::True | JSON::False | JSON::Null | JSON::Str(_) => &mut [],
-            JSON::Array(children) => children,
+            JSON::True
+            | JSON::False
+            | JSON::Null
+            | JSON::Number(_)
+            | JSON::Str(_)
+            | JSON::LineComment(_)
+            | JSON::BlockComment(_)
+            | JSON::Unparsed(_) => &mut [],
+            JSON::Array(children) | JSON::Document(children) => children,
             JSON::Object(fields) => fields,
             JSON::Field(key_value) => &mut key_value[..],
         }
@@ -291,18 +677,40 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
         arena: &'arena Arena<Self>,
         index: usize,
     ) -> Result<(), InsertError> {
+        let len = self.children().len();
+        if index > len {
+            return Err(InsertError::IndexOutOfRange { len, index });
+        }
+        // A document is only ever the root of a tree
+        if let JSON::Document(_) = new_node {
+            return Err(InsertError::InvalidChild {
+                parent_kind: self.node_kind(),
+                child_kind: new_node.node_kind(),
+            });
+        }
         match self {
-            JSON::True | JSON::False | JSON::Null | JSON::Str(_) => {
-                Err(InsertError::TooManyChildren {
-                    name: self.display_name(),
-                    max_children: 0,
-                })
-            }
+            JSON::True
+            | JSON::False
+            | JSON::Null
+            | JSON::Number(_)
+            | JSON::Str(_)
+            | JSON::LineComment(_)
+            | JSON::BlockComment(_)
+            | JSON::Unparsed(_) => Err(InsertError::TooManyChildren {
+                kind: self.node_kind(),
+                max_children: 0,
+            }),
             JSON::Field(_) => Err(InsertError::TooManyChildren {
-                name: self.display_name(),
+                kind: self.node_kind(),
                 max_children: 2,
             }),
             JSON::Object(fields) => {
+                // If we're given a whole field (e.g. one that's been pasted) or a comment, then it
+                // can be added straight to the object
+                if matches!(new_node, JSON::Field(_)) || new_node.is_comment() {
+                    fields.insert(index, new_node);
+                    return Ok(());
+                }
                 /* Inserting into an object is a special case, since we need to allocate more
                  * objects in order to preserve the validity of the tree. */
                 // Allocate an empty string to act as the key
@@ -314,43 +722,70 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
                 Ok(())
             }
             JSON::Array(children) => {
+                // Fields can only exist inside objects
+                if let JSON::Field(_) = new_node {
+                    return Err(InsertError::InvalidChild {
+                        parent_kind: self.node_kind(),
+                        child_kind: new_node.node_kind(),
+                    });
+                }
+                children.insert(index, new_node);
+                Ok(())
+            }
+            // A document has exactly one value, so only comments can be added to it
+            JSON::Document(children) => {
+                if !new_node.is_comment() {
+                    return Err(InsertError::InvalidChild {
+                        parent_kind: "document",
+                        child_kind: new_node.node_kind(),
+                    });
+                }
                 children.insert(index, new_node);
                 Ok(())
             }
         }
     }
 
-    fn delete_child(&mut self, index: usize) -> Result<(), DeleteError> {
+    fn has_reorderable_children(&self) -> bool {
+        // Swapping the key and value of a field would make the key something other than a string
+        !matches!(self, JSON::Field(_))
+    }
+
+    fn delete_children(&mut self, range: std::ops::Range<usize>) -> Result<(), DeleteError> {
         match self {
-            JSON::True | JSON::False | JSON::Null | JSON::Str(_) => {
+            JSON::True
+            | JSON::False
+            | JSON::Null
+            | JSON::Number(_)
+            | JSON::Str(_)
+            | JSON::LineComment(_)
+            | JSON::BlockComment(_)
+            | JSON::Unparsed(_) => {
                 // We shouldn't be able to delete the child of a node with no children - this would
                 // require first selecting the non-existent child, which should be caught by the
                 // cursor path code.
                 unreachable!();
             }
             JSON::Field(_) => Err(DeleteError::TooFewChildren {
-                name: self.display_name(),
+                kind: self.node_kind(),
                 min_children: 2,
             }),
-            JSON::Object(fields) => {
-                if index < fields.len() {
-                    fields.remove(index);
-                    Ok(())
-                } else {
-                    Err(DeleteError::IndexOutOfRange {
-                        len: fields.len(),
-                        index,
-                    })
-                }
+            JSON::Document(children) if range.contains(&document_value(children)) => {
+                Err(DeleteError::RequiredChild {
+                    kind: "document",
+                    child_kind: "value",
+                })
             }
-            JSON::Array(children) => {
-                if index < children.len() {
-                    children.remove(index);
+            // Objects and arrays (and the comments of documents) can be deleted freely, so we
+            // only need to check that the children exist
+            JSON::Object(children) | JSON::Array(children) | JSON::Document(children) => {
+                if range.end <= children.len() {
+                    children.drain(range);
                     Ok(())
                 } else {
                     Err(DeleteError::IndexOutOfRange {
                         len: children.len(),
-                        index,
+                        index: range.end - 1,
                     })
                 }
             }
@@ -359,20 +794,60 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
 
     fn display_name(&self) -> String {
         match self {
-            JSON::True => "true".to_string(),
-            JSON::False => "false".to_string(),
-            JSON::Null => "null".to_string(),
-            JSON::Array(_) => "array".to_string(),
-            JSON::Object(_) => "object".to_string(),
-            JSON::Field(_) => "field".to_string(),
-            JSON::Str(content) => format!(r#""{}""#, content),
+            JSON::Number(number) => number.clone(),
+            JSON::Str(content) => format!(r#""{}""#, escape_string(content)),
+            JSON::LineComment(_) | JSON::BlockComment(_) => "comment".to_string(),
+            JSON::Unparsed(text) if text.as_str().starts_with('[') => "array".to_string(),
+            JSON::Unparsed(_) => "object".to_string(),
+            _ => self.node_kind().to_string(),
+        }
+    }
+
+    fn node_kind(&self) -> &'static str {
+        match self {
+            JSON::True => "true",
+            JSON::False => "false",
+            JSON::Null => "null",
+            JSON::Number(_) => "number",
+            JSON::Array(_) => "array",
+            JSON::Object(_) => "object",
+            JSON::Field(_) => "field",
+            JSON::Str(_) => "string",
+            JSON::LineComment(_) => "line-comment",
+            JSON::BlockComment(_) => "block-comment",
+            JSON::Unparsed(_) => "unparsed",
+            JSON::Document(_) => "document",
+        }
+    }
+
+    fn fold_summary(&self) -> String {
+        // Pluralise a count of some noun
+        let count = |n: usize, noun: &str| match n {
+            1 => format!("1 {}", noun),
+            _ => format!("{} {}s", n, noun),
+        };
+        // Comments aren't counted as fields or items
+        let values = |children: &[&JSON]| children.iter().filter(|c| !c.is_comment()).count();
+        match self {
+            JSON::Object(fields) => format!("{{…}} ({})", count(values(fields), "field")),
+            JSON::Array(items) => format!("[…] ({})", count(values(items), "item")),
+            JSON::Field([key, _]) => format!("{}: …", key.display_name()),
+            JSON::Unparsed(text) => text.summary(),
+            _ => self.display_name(),
         }
     }
 
     /* AST EDITING FUNCTIONS */
 
     fn replace_chars(&self) -> Box<dyn Iterator<Item = char>> {
-        Self::all_object_chars()
+        // Comments can only be swapped for the other kind of comment, because values can go in
+        // places (like the value of a field) that comments can't.  Replacing a document would
+        // throw away its comments.
+        match self {
+            JSON::Document(_) => Box::new(std::iter::empty()),
+            _ if self.is_comment() => Self::comment_chars(),
+            _ => Self::all_object_chars(),
+        }
     }
 
     fn from_char(&self, c: char) -> Option<Self> {
@@ -383,52 +858,1219 @@ impl<'arena> Ast<'arena> for JSON<'arena> {
             CHAR_ARRAY => Some(JSON::Array(vec![])),
             CHAR_OBJECT => Some(JSON::Object(vec![])),
             CHAR_STRING => Some(JSON::Str("".to_string())),
+            // `-` starts a negative number, which is unfinished until a digit is typed into it
+            '0'..='9' | '-' => Some(JSON::Number(c.to_string())),
+            // Swapping between line and block comments keeps the text of the comment
+            CHAR_LINE_COMMENT => Some(JSON::LineComment(self.comment_text().replace('\n', " "))),
+            CHAR_BLOCK_COMMENT => Some(JSON::BlockComment(self.comment_text().to_string())),
+            _ => None,
+        }
+    }
+
+    fn char_description(&self, c: char) -> Option<String> {
+        let node = self.from_char(c)?;
+        // Listed apart from the digits, so that the help box doesn't show `0-9-`
+        Some(match c {
+            '-' => "negative number".to_string(),
+            _ => node.node_kind().replace('-', " "),
+        })
+    }
+
+    fn is_valid_child(&self, index: usize, child: &Self) -> bool {
+        match (self, child) {
+            (JSON::True, _)
+            | (JSON::False, _)
+            | (JSON::Null, _)
+            | (JSON::Number(_), _)
+            | (JSON::Str(_), _)
+            | (JSON::LineComment(_), _)
+            | (JSON::BlockComment(_), _)
+            | (JSON::Unparsed(_), _) => false,
+            // A document is only ever the root of a tree
+            (_, JSON::Document(_)) => false,
+            // The value of a document can't be swapped for a comment (or vice versa)
+            (JSON::Document(children), _) => {
+                (index == document_value(children)) != child.is_comment()
+                    && !matches!(child, JSON::Field(_))
+            }
+            // Comments can go anywhere in an array or object, but not inside a field
+            (JSON::Object(_), JSON::LineComment(_) | JSON::BlockComment(_))
+            | (JSON::Array(_), JSON::LineComment(_) | JSON::BlockComment(_)) => true,
+            (JSON::Field(_), JSON::LineComment(_) | JSON::BlockComment(_)) => false,
+            // Objects can only contain fields, and fields can only be inside objects
+            (JSON::Object(_), JSON::Field(_)) => true,
+            (JSON::Object(_), _) | (JSON::Array(_), JSON::Field(_)) => false,
+            (JSON::Array(_), _) => true,
+            // The key of a field must be a string, and the value can be anything but a field
+            (JSON::Field(_), JSON::Str(_)) if index == 0 => true,
+            (JSON::Field(_), JSON::Field(_)) => false,
+            (JSON::Field(_), _) => index == 1,
+        }
+    }
+
+    fn unwrap_index(&self) -> Option<usize> {
+        match self {
+            // Unwrapping a field gives its value
+            JSON::Field(_) => Some(1),
+            _ if self.children().len() == 1 => Some(0),
+            _ => None,
+        }
+    }
+
+    fn wrap_in(&'arena self, c: char, arena: &'arena Arena<Self>) -> Option<&'arena Self> {
+        // Fields can only be children of objects, and wrapping a field in an object would give an
+        // object that can't take the field's place.  Comments can't be wrapped either, since an
+        // array containing only a comment would take the comment's place as a value.
+        if matches!(self, JSON::Field(_) | JSON::Document(_)) || self.is_comment() {
+            return None;
+        }
+        match c {
+            CHAR_ARRAY => Some(arena.alloc(JSON::Array(vec![self]))),
+            CHAR_OBJECT => {
+                let key = arena.alloc(JSON::Str("".to_string()));
+                let field = arena.alloc(JSON::Field([key, self]));
+                Some(arena.alloc(JSON::Object(vec![field])))
+            }
             _ => None,
         }
     }
 
     fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
         match self {
-            JSON::True | JSON::False | JSON::Null | JSON::Field(_) | JSON::Str(_) => {
-                Box::new(std::iter::empty())
+            JSON::True
+            | JSON::False
+            | JSON::Null
+            | JSON::Number(_)
+            | JSON::Field(_)
+            | JSON::Str(_)
+            | JSON::LineComment(_)
+            | JSON::BlockComment(_)
+            | JSON::Unparsed(_) => Box::new(std::iter::empty()),
+            JSON::Object(_) | JSON::Array(_) => {
+                Box::new(Self::all_object_chars().chain(Self::comment_chars()))
+            }
+            JSON::Document(_) => Self::comment_chars(),
+        }
+    }
+
+    fn push_char(&self, c: char) -> Option<Self> {
+        let number = match self {
+            JSON::Number(number) => number,
+            _ => return None,
+        };
+        let new_number = match (c, number.chars().last()) {
+            // Typing '-' directly after an exponent sets the sign of the exponent
+            ('-', Some('e')) | ('-', Some('E')) => format!("{}-", number),
+            // Otherwise, '-' toggles the sign of the whole number (because we can only append
+            // chars, there would otherwise be no way to make a number negative)
+            ('-', _) => match number.strip_prefix('-') {
+                // A lone `-` can't lose its sign without leaving no number at all
+                Some("") => return None,
+                Some(positive) => positive.to_string(),
+                None => format!("-{}", number),
+            },
+            _ => format!("{}{}", number, c),
+        };
+        match number_validity(&new_number) {
+            NumberValidity::Invalid => None,
+            _ => Some(JSON::Number(new_number)),
+        }
+    }
+
+    fn pop_char(&self) -> Option<Self> {
+        match self {
+            // Removing chars from a valid number prefix always leaves a valid number prefix, so
+            // the only thing we need to prevent is creating an empty number
+            JSON::Number(number) if number.chars().count() > 1 => {
+                let mut new_number = number.clone();
+                new_number.pop();
+                Some(JSON::Number(new_number))
+            }
+            _ => None,
+        }
+    }
+
+    fn replace_text(&self, pattern: &str, replacement: &str) -> Option<Self> {
+        match self {
+            JSON::Str(contents) if !pattern.is_empty() && contents.contains(pattern) => {
+                Some(JSON::Str(contents.replace(pattern, replacement)))
             }
-            JSON::Object(_) | JSON::Array(_) => Self::all_object_chars(),
+            _ => None,
+        }
+    }
+
+    fn is_key_child(&self, index: usize) -> bool {
+        matches!(self, JSON::Field(_)) && index == 0
+    }
+
+    fn is_comment(&self) -> bool {
+        matches!(self, JSON::LineComment(_) | JSON::BlockComment(_))
+    }
+
+    fn document_value(&self) -> Option<usize> {
+        match self {
+            JSON::Document(children) => Some(document_value(children)),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            JSON::Str(contents) | JSON::LineComment(contents) | JSON::BlockComment(contents) => {
+                Some(contents)
+            }
+            _ => None,
+        }
+    }
+
+    fn with_text(&self, text: &str) -> Option<Self> {
+        match self {
+            JSON::Str(_) => Some(JSON::Str(text.to_string())),
+            // Comments can't contain the text that would end them early
+            JSON::LineComment(_) => Some(JSON::LineComment(text.replace('\n', " "))),
+            JSON::BlockComment(_) => Some(JSON::BlockComment(text.replace("*/", "* /"))),
+            _ => None,
+        }
+    }
+
+    fn to_owned_node(&self) -> Option<OwnedNode> {
+        let text = match self {
+            JSON::True | JSON::False | JSON::Null => None,
+            JSON::Array(_) | JSON::Object(_) | JSON::Field(_) | JSON::Document(_) => None,
+            JSON::Number(text)
+            | JSON::Str(text)
+            | JSON::LineComment(text)
+            | JSON::BlockComment(text) => Some(text.clone()),
+            JSON::Unparsed(text) => Some(text.as_str().to_string()),
+        };
+        Some(OwnedNode::new(self.node_kind(), text))
+    }
+
+    fn from_owned_node(node: &OwnedNode, children: &[&'arena Self]) -> Option<Self> {
+        let text = node.text.clone();
+        let node = match (node.kind.as_str(), children) {
+            ("true", []) => JSON::True,
+            ("false", []) => JSON::False,
+            ("null", []) => JSON::Null,
+            ("number", []) => match number_validity(text.as_deref()?) {
+                NumberValidity::Invalid => return None,
+                _ => JSON::Number(text?),
+            },
+            ("array", _) => JSON::Array(children.to_vec()),
+            ("object", _) => JSON::Object(children.to_vec()),
+            ("field", &[key, value]) => JSON::Field([key, value]),
+            // A document needs exactly one value, which can't be a field
+            ("document", _)
+                if children.iter().filter(|c| !c.is_comment()).count() == 1
+                    && !matches!(
+                        children[document_value(children)],
+                        JSON::Field(_) | JSON::Document(_)
+                    ) =>
+            {
+                JSON::Document(children.to_vec())
+            }
+            ("string", []) => JSON::Str(text?),
+            // Comments can't contain the text that ends them
+            ("line-comment", []) => JSON::LineComment(text.filter(|t| !t.contains('\n'))?),
+            ("block-comment", []) => JSON::BlockComment(text.filter(|t| !t.contains("*/"))?),
+            // The dialect isn't saved, so the text is parsed as JSONC (which allows everything
+            // that JSON does)
+            ("unparsed", []) => {
+                let text = text.filter(|t| t.starts_with('[') || t.starts_with('{'))?;
+                JSON::Unparsed(UnparsedText {
+                    byte_range: 0..text.len(),
+                    source: Arc::from(text),
+                    dialect: Dialect::JSONC,
+                })
+            }
+            _ => return None,
+        };
+        Some(node)
+    }
+
+    fn validate(&self) -> Vec<ValidationError> {
+        let fields = match self {
+            JSON::Object(fields) => fields,
+            // Numbers which are still being typed (like `1e`) aren't valid JSON
+            JSON::Number(number) if number_validity(number) == NumberValidity::Incomplete => {
+                return vec![ValidationError::Unfinished {
+                    text: number.clone(),
+                }]
+            }
+            _ => return Vec::new(),
+        };
+        // The index of the first field with each key
+        let mut first_indices: HashMap<&str, usize> = HashMap::new();
+        let mut errors = Vec::new();
+        for (index, field) in fields.iter().enumerate() {
+            let key = match field {
+                JSON::Field([key, _]) => key,
+                _ => continue,
+            };
+            let text = key.text().unwrap_or_default();
+            match first_indices.get(text) {
+                Some(&first_index) => errors.push(ValidationError::DuplicateKey {
+                    key: key.display_name(),
+                    index,
+                    first_index,
+                }),
+                None => {
+                    first_indices.insert(text, index);
+                }
+            }
+        }
+        errors
+    }
+
+    fn parse_unparsed(&self, arena: &'arena Arena<Self>) -> Option<Result<&'arena Self, String>> {
+        let text = match self {
+            JSON::Unparsed(text) => text,
+            _ => return None,
+        };
+        // The node's contents are parsed in large-file mode too, so any arrays or objects inside
+        // it are left unparsed until they're unfolded in turn
+        let mut parser = Parser::lazy(&text.source, text.byte_range(), arena, text.dialect);
+        Some(
+            parser
+                .parse_value(0, &mut Vec::new())
+                .map_err(|e| e.to_string()),
+        )
+    }
+
+    fn is_unparsed(&self) -> bool {
+        matches!(self, JSON::Unparsed(_))
+    }
+
+    fn text_offset(&self, index: usize) -> usize {
+        match self {
+            // Skip the opening quote, and the extra `\` of every escaped char
+            JSON::Str(contents) => 1 + escape_string(&contents[..index]).len(),
+            // Skip the `//` or `/*`.  Every newline in a block comment is replaced by the space
+            // that starts the next line, so the rest of the text is the same length as it's
+            // rendered.
+            JSON::LineComment(_) | JSON::BlockComment(_) => 2 + index,
+            _ => 0,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::super::size::Size;
-    use super::super::test_json::TestJSON;
-    use super::JSONFormat;
-    use crate::arena::Arena;
-    use crate::ast::Ast;
+/* ===== PARSING ===== */
 
-    #[test]
-    fn to_text() {
-        for (tree, expected_compact_string, expected_pretty_string, tree_string) in &[
-            (TestJSON::True, "true", "true", "true"),
-            (TestJSON::False, "false", "false", "false"),
-            (TestJSON::Array(vec![]), "[]", "[]", "array"),
-            (TestJSON::Object(vec![]), "{}", "{}", "object"),
-            (
-                TestJSON::Array(vec![TestJSON::True, TestJSON::False]),
-                "[true, false]",
-                "[
-    true,
-    false
-]",
-                "array
-  true
-  false",
-            ),
+/// The maximum depth of nested arrays/objects that [`JSON::parse`] will accept.  Without this
+/// limit, a pathological input like `[[[[[...` could overflow the stack.  This is the same limit
+/// that `serde_json` uses.
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// Roughly how many bytes of text [`JSON::parse`] expects each node to take up, which it
+/// uses to estimate how many nodes to make room for.  Guessing too few only means that the arena
+/// grows during the parse, whereas guessing too many wastes memory, so this errs on the high side.
+const BYTES_PER_NODE: usize = 16;
+
+/// Text with at least this many bytes is parsed in large-file mode, where only the root and the
+/// values directly inside it are parsed into nodes.  Every array or object below that is only
+/// checked to be valid, and becomes an [`Unparsed`](JSON::Unparsed) node which is parsed once
+/// it's unfolded.  This makes opening a huge file take a fraction of the time and memory.
+pub const LARGE_FILE_BYTES: usize = 16 << 20;
+
+/// Arrays and objects which are shorter than this many bytes are parsed even in large-file mode,
+/// since an [`Unparsed`](JSON::Unparsed) node wouldn't save much on the nodes that they'd make
+const MIN_UNPARSED_BYTES: usize = 64;
+
+/// The different ways that parsing JSON can fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// We expected to find some specific thing, but found either a different char or the end of
+    /// the input (if `found` is `None`)
+    Expected {
+        expected: &'static str,
+        found: Option<char>,
+    },
+    /// A backslash in a string was followed by a char that isn't a valid escape
+    InvalidEscape(char),
+    /// A `\u` escape was malformed, or described an invalid codepoint (e.g. an unpaired surrogate)
+    InvalidUnicodeEscape,
+    /// A string contained an unescaped control char (like a raw newline)
+    ControlCharInString(char),
+    /// Some text looked like a number, but isn't a valid JSON number (e.g. `01` or `1.`)
+    InvalidNumber(String),
+    /// The input contains arrays/objects nested more than [`MAX_NESTING_DEPTH`] levels deep
+    TooDeeplyNested,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Expected {
+                expected,
+                found: Some(c),
+            } => write!(f, "expected {}, found {:?}", expected, c),
+            ParseErrorKind::Expected {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of input", expected),
+            ParseErrorKind::InvalidEscape(c) => write!(f, "invalid escape sequence '\\{}'", c),
+            ParseErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape sequence"),
+            ParseErrorKind::ControlCharInString(c) => {
+                write!(f, "unescaped control character {:?} in string", c)
+            }
+            ParseErrorKind::InvalidNumber(n) => write!(f, "invalid number '{}'", n),
+            ParseErrorKind::TooDeeplyNested => write!(
+                f,
+                "arrays/objects are nested more than {} levels deep",
+                MAX_NESTING_DEPTH
+            ),
+        }
+    }
+}
+
+/// An error generated when parsing JSON text, along with the location of the error.  Both
+/// `line` and `column` start from 1, and `column` is measured in [`char`]s.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A recursive-descent JSON parser, which allocates the nodes it parses into an [`Arena`].
+struct Parser<'t, 'arena> {
+    arena: &'arena Arena<JSON<'arena>>,
+    dialect: Dialect,
+    chars: std::iter::Peekable<std::str::Chars<'t>>,
+    /// The line of the next char to be consumed
+    line: usize,
+    /// The column of the next char to be consumed
+    column: usize,
+    /// The byte offset of the next char to be consumed
+    offset: usize,
+    /// In large-file mode, the whole text of the file, along with the offset of the text being
+    /// parsed within it (see [`LARGE_FILE_BYTES`])
+    source: Option<(&'t Arc<str>, usize)>,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    fn new(text: &'t str, arena: &'arena Arena<JSON<'arena>>, dialect: Dialect) -> Self {
+        Parser {
+            arena,
+            dialect,
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+            offset: 0,
+            source: None,
+        }
+    }
+
+    /// Creates a parser for the text of `source` in `byte_range`, in large-file mode
+    fn lazy(
+        source: &'t Arc<str>,
+        byte_range: Range<usize>,
+        arena: &'arena Arena<JSON<'arena>>,
+        dialect: Dialect,
+    ) -> Self {
+        Parser {
+            source: Some((source, byte_range.start)),
+            ..Parser::new(&source[byte_range], arena, dialect)
+        }
+    }
+
+    /* UTILITY FUNCTIONS */
+
+    /// Generates a [`ParseError`] at the location of the next char
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    /// Generates a [`ParseErrorKind::Expected`] error, using the next char as the char that was
+    /// found instead
+    fn expected(&mut self, expected: &'static str) -> ParseError {
+        let found = self.peek();
+        self.error(ParseErrorKind::Expected { expected, found })
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    /// Consumes the next char, keeping track of the line and column numbers
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Consumes the next char if it's equal to `c`, otherwise returns an error
+    fn expect(&mut self, c: char, expected: &'static str) -> Result<(), ParseError> {
+        if self.peek() == Some(c) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.expected(expected))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+            self.next();
+        }
+    }
+
+    /// Skips whitespace and, if the dialect allows them, parses the comments among it into
+    /// `comments`
+    fn skip_trivia(&mut self, comments: &mut Vec<&'arena JSON<'arena>>) -> Result<(), ParseError> {
+        loop {
+            self.skip_whitespace();
+            if self.dialect != Dialect::JSONC || self.peek() != Some('/') {
+                return Ok(());
+            }
+            let comment = self.parse_comment()?;
+            comments.push(self.arena.alloc(comment));
+        }
+    }
+
+    /// Skips whitespace and, if the dialect allows them, comments (without making nodes for them)
+    fn skip_comments(&mut self) -> Result<(), ParseError> {
+        loop {
+            self.skip_whitespace();
+            if self.dialect != Dialect::JSONC || self.peek() != Some('/') {
+                return Ok(());
+            }
+            self.parse_comment()?;
+        }
+    }
+
+    /// Returns `true` if the next char is `close`, and the dialect allows a comma after the last
+    /// element of an array or object (which has just been consumed)
+    fn is_trailing_comma(&mut self, close: char) -> bool {
+        self.dialect == Dialect::JSONC && self.peek() == Some(close)
+    }
+
+    /* PARSING FUNCTIONS */
+
+    /// Parses an entire document, making sure that there's nothing other than whitespace (or
+    /// comments) after the root value.  If there are comments before or after the root, then
+    /// they're kept around it in a [`Document`](JSON::Document).
+    fn parse_document(&mut self) -> Result<&'arena JSON<'arena>, ParseError> {
+        let mut children = Vec::new();
+        let root = self.parse_value(0, &mut children)?;
+        children.push(root);
+        self.skip_trivia(&mut children)?;
+        if self.peek().is_some() {
+            return Err(self.expected("end of input"));
+        }
+        if children.len() == 1 {
+            return Ok(root);
+        }
+        Ok(self.arena.alloc(JSON::Document(children)))
+    }
+
+    /// Parses a single JSON value (with any leading whitespace), and allocates it in the arena.
+    /// Any comments before the value are added to `comments`.
+    fn parse_value(
+        &mut self,
+        depth: usize,
+        comments: &mut Vec<&'arena JSON<'arena>>,
+    ) -> Result<&'arena JSON<'arena>, ParseError> {
+        self.skip_trivia(comments)?;
+        let node = match self.peek() {
+            Some('t') => self.parse_keyword("true", JSON::True)?,
+            Some('f') => self.parse_keyword("false", JSON::False)?,
+            Some('n') => self.parse_keyword("null", JSON::Null)?,
+            Some('"') => JSON::Str(self.parse_string()?),
+            Some('-') | Some('0'..='9') => self.parse_number()?,
+            // In large-file mode, only the root's contents are parsed
+            Some('[') | Some('{') if depth > 0 && self.source.is_some() => {
+                return self.parse_lazily(depth + 1)
+            }
+            Some('[') => self.parse_array(depth + 1)?,
+            Some('{') => self.parse_object(depth + 1)?,
+            _ => return Err(self.expected("a JSON value")),
+        };
+        Ok(self.arena.alloc(node))
+    }
+
+    /// Parses one of the keywords `true`, `false` or `null`
+    fn parse_keyword(
+        &mut self,
+        keyword: &'static str,
+        node: JSON<'arena>,
+    ) -> Result<JSON<'arena>, ParseError> {
+        for c in keyword.chars() {
+            if self.peek() != Some(c) {
+                return Err(self.expected(keyword));
+            }
+            self.next();
+        }
+        Ok(node)
+    }
+
+    fn parse_number(&mut self) -> Result<JSON<'arena>, ParseError> {
+        // Consume every char that could be part of a number, then check that they form a valid
+        // number.  The error is reported at the start of the number.
+        let (line, column) = (self.line, self.column);
+        let mut number = String::new();
+        while let Some(c @ '0'..='9') | Some(c @ '-') | Some(c @ '+') | Some(c @ '.')
+        | Some(c @ 'e') | Some(c @ 'E') = self.peek()
+        {
+            number.push(c);
+            self.next();
+        }
+        match number_validity(&number) {
+            NumberValidity::Complete => Ok(JSON::Number(number)),
+            _ => Err(ParseError {
+                line,
+                column,
+                kind: ParseErrorKind::InvalidNumber(number),
+            }),
+        }
+    }
+
+    /// Parses a string literal (including its quotes), returning its unescaped contents
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let mut string = String::new();
+        self.scan_string(|c| string.push(c))?;
+        Ok(string)
+    }
+
+    /// Parses a string literal (including its quotes), passing each char of its unescaped
+    /// contents to `push`
+    fn scan_string(&mut self, mut push: impl FnMut(char)) -> Result<(), ParseError> {
+        self.expect('"', "'\"'")?;
+        loop {
+            match self.peek() {
+                None => return Err(self.expected("'\"'")),
+                Some('"') => {
+                    self.next();
+                    return Ok(());
+                }
+                Some('\\') => {
+                    self.next();
+                    push(self.parse_escape()?);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error(ParseErrorKind::ControlCharInString(c)));
+                }
+                Some(c) => {
+                    self.next();
+                    push(c);
+                }
+            }
+        }
+    }
+
+    /// Parses the part of an escape sequence after the `\`
+    fn parse_escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                self.next();
+                return self.parse_unicode_escape();
+            }
+            Some(c) => return Err(self.error(ParseErrorKind::InvalidEscape(c))),
+            None => return Err(self.expected("an escape sequence")),
+        };
+        self.next();
+        Ok(c)
+    }
+
+    /// Parses a `//` or `/* */` comment
+    fn parse_comment(&mut self) -> Result<JSON<'arena>, ParseError> {
+        self.expect('/', "'/'")?;
+        match self.peek() {
+            Some('/') => {
+                self.next();
+                let mut text = String::new();
+                while let Some(c) = self.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    text.push(c);
+                    self.next();
+                }
+                // Don't keep the '\r' of a '\r\n' line ending
+                if text.ends_with('\r') {
+                    text.pop();
+                }
+                Ok(JSON::LineComment(text))
+            }
+            Some('*') => {
+                self.next();
+                let mut text = String::new();
+                loop {
+                    match self.next() {
+                        None => return Err(self.expected("'*/'")),
+                        Some('*') if self.peek() == Some('/') => {
+                            self.next();
+                            break;
+                        }
+                        Some(c) => text.push(c),
+                    }
+                }
+                let lines: Vec<&str> = block_comment_lines(&text).collect();
+                Ok(JSON::BlockComment(lines.join("\n")))
+            }
+            _ => Err(self.expected("'/' or '*'")),
+        }
+    }
+
+    /// Parses the hex digits of a `\uXXXX` escape, including the second half of a surrogate
+    /// pair if the first escape is a high surrogate.
+    fn parse_unicode_escape(&mut self) -> Result<char, ParseError> {
+        let first = self.parse_hex4()?;
+        let codepoint = match first {
+            // High surrogate, which must be followed by an escaped low surrogate
+            0xD800..=0xDBFF => {
+                if self.peek() != Some('\\') {
+                    return Err(self.error(ParseErrorKind::InvalidUnicodeEscape));
+                }
+                self.next();
+                if self.peek() != Some('u') {
+                    return Err(self.error(ParseErrorKind::InvalidUnicodeEscape));
+                }
+                self.next();
+                let second = self.parse_hex4()?;
+                if !(0xDC00..=0xDFFF).contains(&second) {
+                    return Err(self.error(ParseErrorKind::InvalidUnicodeEscape));
+                }
+                0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+            }
+            // Unpaired low surrogate
+            0xDC00..=0xDFFF => return Err(self.error(ParseErrorKind::InvalidUnicodeEscape)),
+            _ => first,
+        };
+        std::char::from_u32(codepoint)
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidUnicodeEscape))
+    }
+
+    /// Parses exactly 4 hex digits
+    fn parse_hex4(&mut self) -> Result<u32, ParseError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error(ParseErrorKind::InvalidUnicodeEscape))?;
+            self.next();
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<JSON<'arena>, ParseError> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeeplyNested));
+        }
+        self.expect('[', "'['")?;
+        let mut children = Vec::new();
+        // Special case: empty arrays
+        self.skip_trivia(&mut children)?;
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(JSON::Array(children));
+        }
+        loop {
+            let value = self.parse_value(depth, &mut children)?;
+            children.push(value);
+            self.skip_trivia(&mut children)?;
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                    self.skip_trivia(&mut children)?;
+                    if self.is_trailing_comma(']') {
+                        self.next();
+                        return Ok(JSON::Array(children));
+                    }
+                }
+                Some(']') => {
+                    self.next();
+                    return Ok(JSON::Array(children));
+                }
+                _ => return Err(self.expected("',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<JSON<'arena>, ParseError> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeeplyNested));
+        }
+        self.expect('{', "'{'")?;
+        let mut fields = Vec::new();
+        // Special case: empty objects
+        self.skip_trivia(&mut fields)?;
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(JSON::Object(fields));
+        }
+        loop {
+            // Parse the key and the ':'
+            self.skip_trivia(&mut fields)?;
+            if self.peek() != Some('"') {
+                return Err(self.expected("a string as an object key"));
+            }
+            let key = self.arena.alloc(JSON::Str(self.parse_string()?));
+            // Comments inside a field can't stay there, so they go before the field
+            self.skip_trivia(&mut fields)?;
+            self.expect(':', "':'")?;
+            // Parse the value, and combine it with the key to make a field
+            let value = self.parse_value(depth, &mut fields)?;
+            fields.push(self.arena.alloc(JSON::Field([key, value])));
+            // Parse the delimiter
+            self.skip_trivia(&mut fields)?;
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                    self.skip_trivia(&mut fields)?;
+                    if self.is_trailing_comma('}') {
+                        self.next();
+                        return Ok(JSON::Object(fields));
+                    }
+                }
+                Some('}') => {
+                    self.next();
+                    return Ok(JSON::Object(fields));
+                }
+                _ => return Err(self.expected("',' or '}'")),
+            }
+        }
+    }
+
+    /* LARGE-FILE MODE */
+
+    /// Checks that the array or object at the next char is valid, and makes an
+    /// [`Unparsed`](JSON::Unparsed) node for it rather than parsing what's inside it (unless it's
+    /// very short)
+    fn parse_lazily(&mut self, depth: usize) -> Result<&'arena JSON<'arena>, ParseError> {
+        let (source, base) = self.source.expect("only used in large-file mode");
+        let start = self.offset;
+        self.skip_container(depth)?;
+        let byte_range = base + start..base + self.offset;
+        if byte_range.len() < MIN_UNPARSED_BYTES {
+            // The text has just been checked, so parsing it again can't fail
+            let mut parser = Parser::new(&source[byte_range], self.arena, self.dialect);
+            return parser.parse_value(depth - 1, &mut Vec::new());
+        }
+        Ok(self.arena.alloc(JSON::Unparsed(UnparsedText {
+            source: Arc::clone(source),
+            byte_range,
+            dialect: self.dialect,
+        })))
+    }
+
+    /// Consumes an array or object, checking that it's valid in the same way as
+    /// [`parse_array`](Parser::parse_array) and [`parse_object`](Parser::parse_object) but
+    /// without allocating any nodes
+    fn skip_container(&mut self, depth: usize) -> Result<(), ParseError> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeeplyNested));
+        }
+        let (is_object, close) = match self.next() {
+            Some('{') => (true, '}'),
+            _ => (false, ']'),
+        };
+        // Special case: empty arrays and objects
+        self.skip_comments()?;
+        if self.peek() == Some(close) {
+            self.next();
+            return Ok(());
+        }
+        loop {
+            if is_object {
+                self.skip_comments()?;
+                if self.peek() != Some('"') {
+                    return Err(self.expected("a string as an object key"));
+                }
+                self.scan_string(|_| {})?;
+                self.skip_comments()?;
+                self.expect(':', "':'")?;
+            }
+            self.skip_value(depth)?;
+            self.skip_comments()?;
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                    self.skip_comments()?;
+                    if self.is_trailing_comma(close) {
+                        self.next();
+                        return Ok(());
+                    }
+                }
+                Some(c) if c == close => {
+                    self.next();
+                    return Ok(());
+                }
+                _ if is_object => return Err(self.expected("',' or '}'")),
+                _ => return Err(self.expected("',' or ']'")),
+            }
+        }
+    }
+
+    /// Consumes a single JSON value (with any leading whitespace), checking that it's valid in
+    /// the same way as [`parse_value`](Parser::parse_value)
+    fn skip_value(&mut self, depth: usize) -> Result<(), ParseError> {
+        self.skip_comments()?;
+        match self.peek() {
+            Some('t') => self.parse_keyword("true", JSON::True).map(drop),
+            Some('f') => self.parse_keyword("false", JSON::False).map(drop),
+            Some('n') => self.parse_keyword("null", JSON::Null).map(drop),
+            Some('"') => self.scan_string(|_| {}),
+            Some('-') | Some('0'..='9') => self.parse_number().map(drop),
+            Some('[') | Some('{') => self.skip_container(depth + 1),
+            _ => Err(self.expected("a JSON value")),
+        }
+    }
+}
+
+/// Returns the tokens which write the text of an [`Unparsed`](JSON::Unparsed) array or object as it
+/// was in the file.  Each line keeps its indentation relative to the line of the closing bracket,
+/// so the text is indented to wherever the node is now.
+fn unparsed_text_tokens(text: &str) -> Vec<DisplayToken> {
+    let lines: Vec<&str> = text
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    let last_line = lines[lines.len() - 1];
+    let base_indent = &last_line[..last_line.len() - last_line.trim_start().len()];
+    let mut tokens = Vec::with_capacity(2 * lines.len());
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = if i == 0 {
+            line
+        } else {
+            tokens.push(DisplayToken::Newline);
+            line.strip_prefix(base_indent)
+                .unwrap_or_else(|| line.trim_start())
+        };
+        tokens.push(DisplayToken::Text(
+            line.to_string().into(),
+            CATEGORY_DEFAULT,
+        ));
+    }
+    tokens
+}
+
+/// Returns the index of the root value among the children of a [`Document`](JSON::Document),
+/// which comes after the comments before it
+fn document_value(children: &[&JSON]) -> usize {
+    children
+        .iter()
+        .take_while(|child| child.is_comment())
+        .count()
+}
+
+/// Returns the lines of a block comment's text, with the leading whitespace removed from every
+/// line after the first (and any `\r`s from the ends of lines)
+fn block_comment_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split('\n').enumerate().map(|(i, line)| {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if i == 0 {
+            line
+        } else {
+            line.trim_start()
+        }
+    })
+}
+
+/// Returns how a block comment is rendered (ignoring indentation): every line after the first is
+/// indented by one space, so that `*`s at the start of each line line up under the `/*`
+fn block_comment_text(text: &str) -> String {
+    let lines: Vec<&str> = block_comment_lines(text).collect();
+    format!("/*{}*/", lines.join("\n "))
+}
+
+/// Generates the tokens of a block comment, with a [`Newline`](DisplayToken::Newline) between
+/// every line so that they're all indented to the comment's level
+fn block_comment_tokens<'arena>(text: &str) -> Vec<RecTok<'arena, JSON<'arena>>> {
+    let text = block_comment_text(text);
+    let mut tokens = Vec::new();
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        tokens.push(RecTok::Tok(DisplayToken::Text(
+            line.to_string().into(),
+            CATEGORY_COMMENT,
+        )));
+    }
+    tokens
+}
+
+/// How many columns the ", " between two children (or the ": " of a field) takes up when a node
+/// is written on one line
+const INLINE_SEPARATOR_WIDTH: usize = 2;
+
+impl<'arena> JSON<'arena> {
+    /// Returns the text of this node if it's a comment, or an empty string if it isn't
+    fn comment_text(&self) -> &str {
+        match self {
+            JSON::LineComment(text) | JSON::BlockComment(text) => text,
+            _ => "",
+        }
+    }
+
+    /// Returns `true` if this node is a `//` comment, which means that nothing else can go on
+    /// the same line after it
+    fn is_line_comment(&self) -> bool {
+        matches!(self, JSON::LineComment(_))
+    }
+
+    /// Returns `true` if the last of the children of an array or object is a line comment
+    fn ends_with_line_comment(children: &[&JSON]) -> bool {
+        children.last().is_some_and(|child| child.is_line_comment())
+    }
+
+    /// Returns `true` if the child at `index` of an array or object is followed by a comma.  Every
+    /// value except the last one is, and so is the last one if `trailing_commas` is `true`.
+    /// Comments are never followed by commas.
+    fn has_comma(children: &[&JSON], index: usize, trailing_commas: bool) -> bool {
+        !children[index].is_comment()
+            && (trailing_commas || children[index + 1..].iter().any(|c| !c.is_comment()))
+    }
+
+    /// Returns `true` if each child of this array or object should be put on a line of its own
+    fn is_broken(&self, format_style: &JSONFormat) -> bool {
+        match format_style {
+            JSONFormat::Compact => false,
+            JSONFormat::Pretty { .. } => true,
+            JSONFormat::Fit { max_width, .. } => self.inline_width(*max_width).is_none(),
+        }
+    }
+
+    /// Returns how many columns this node would take up if it was written on one line with a
+    /// space after every ',' and ':', or `None` if that would be more than `limit`.  Counting
+    /// stops as soon as the limit is passed, so only a little more than `limit` columns worth of
+    /// the node is ever visited.
+    fn inline_width(&self, limit: usize) -> Option<usize> {
+        let mut width = match self {
+            JSON::Array(children) | JSON::Object(children) => {
+                // Every child but the first has a space before it, and every value but the last
+                // has a comma after it
+                let commas = (0..children.len())
+                    .filter(|&i| Self::has_comma(children, i, false))
+                    .count();
+                2 + children.len().saturating_sub(1) + commas
+            }
+            JSON::Field(_) => INLINE_SEPARATOR_WIDTH,
+            // Nothing can go after a line comment on the same line
+            JSON::LineComment(_) => return None,
+            JSON::BlockComment(text) if text.contains('\n') => return None,
+            // Unparsed nodes are written as the text that they were loaded from, which could be
+            // spread over many lines
+            JSON::Unparsed(_) => return None,
+            // Other leaves are always on one line
+            leaf => {
+                let width = leaf
+                    .size_from_children(&JSONFormat::Compact, &[])
+                    .max_width();
+                return Some(width).filter(|&width| width <= limit);
+            }
+        };
+        for child in self.children() {
+            width += child.inline_width(limit.checked_sub(width)?)?;
+        }
+        Some(width).filter(|&width| width <= limit)
+    }
+
+    /// Parses a string of JSON text, allocating all the nodes into a given [`Arena`] and returning
+    /// a reference to the root node.
+    pub fn parse(
+        text: &str,
+        arena: &'arena Arena<JSON<'arena>>,
+    ) -> Result<&'arena JSON<'arena>, ParseError> {
+        Self::parse_dialect(text, arena, Dialect::JSON)
+    }
+
+    /// Parses a string of text in the given [`Dialect`] of JSON, in the same way as
+    /// [`JSON::parse`].  Text of at least [`LARGE_FILE_BYTES`] is parsed in large-file mode.
+    pub fn parse_dialect(
+        text: &str,
+        arena: &'arena Arena<JSON<'arena>>,
+        dialect: Dialect,
+    ) -> Result<&'arena JSON<'arena>, ParseError> {
+        if text.len() >= LARGE_FILE_BYTES {
+            return Self::parse_large(text, arena, dialect);
+        }
+        // Make room for the nodes up front, rather than growing the arena during the parse
+        arena.reserve(text.len() / BYTES_PER_NODE);
+        Parser::new(text, arena, dialect).parse_document()
+    }
+
+    /// Parses a string of text in large-file mode (see [`LARGE_FILE_BYTES`]), however long it is
+    pub fn parse_large(
+        text: &str,
+        arena: &'arena Arena<JSON<'arena>>,
+        dialect: Dialect,
+    ) -> Result<&'arena JSON<'arena>, ParseError> {
+        let source: Arc<str> = Arc::from(text);
+        Parser::lazy(&source, 0..source.len(), arena, dialect).parse_document()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::display_token::{
+        DisplayToken, FormatKind, IndentKind, RenderStyle, SyntaxCategory,
+    };
+    use super::super::size::Size;
+    use super::super::test_json::{Rng, TestJSON};
+    use super::{
+        number_validity, Dialect, JSONFormat, NumberValidity, ParseError, ParseErrorKind, JSON,
+        MAX_NESTING_DEPTH,
+    };
+    use crate::arena::{Arena, HeapSize};
+    use crate::ast::owned::OwnedNode;
+    use crate::ast::{Ast, DeleteError, InsertError, ValidationError};
+
+    #[test]
+    fn insert_errors() {
+        let arena = Arena::new();
+        let root = JSON::parse(r#"[{"a": true}, null]"#, &arena).unwrap();
+        let (object, null) = (root.children()[0], root.children()[1]);
+        let field = object.children()[0];
+        let insert = |parent, child, index| {
+            let mut parent = JSON::clone(parent);
+            let result = parent.insert_child(child, &arena, index);
+            result.map(|()| parent.to_text(&JSONFormat::Compact))
+        };
+        // Fields can only go into objects, and other values are given a key when they're inserted
+        // into an object
+        assert_eq!(
+            insert(root, field, 0),
+            Err(InsertError::InvalidChild {
+                parent_kind: "array",
+                child_kind: "field",
+            })
+        );
+        assert_eq!(
+            insert(object, null, 1),
+            Ok(r#"{"a":true,"":null}"#.to_string())
+        );
+        // Fields already have both their key and their value, and leaves can't have children
+        assert_eq!(
+            insert(field, field.children()[0], 1),
+            Err(InsertError::TooManyChildren {
+                kind: "field",
+                max_children: 2,
+            })
+        );
+        assert_eq!(
+            insert(null, null, 0),
+            Err(InsertError::TooManyChildren {
+                kind: "null",
+                max_children: 0,
+            })
+        );
+        // Children can be inserted anywhere up to the end, but not past it
+        assert!(insert(root, null, 2).is_ok());
+        for parent in [root, object] {
+            assert_eq!(
+                insert(parent, null, 3),
+                Err(InsertError::IndexOutOfRange {
+                    len: parent.children().len(),
+                    index: 3,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn to_text() {
+        for (tree, expected_compact_string, expected_pretty_string, tree_string) in &[
+            (TestJSON::True, "true", "true", "true"),
+            (TestJSON::False, "false", "false", "false"),
+            (TestJSON::Null, "null", "null", "null"),
+            (
+                TestJSON::Number("-1.50e+3".to_string()),
+                "-1.50e+3",
+                "-1.50e+3",
+                "-1.50e+3",
+            ),
+            (
+                TestJSON::Array(vec![
+                    TestJSON::Number("1".to_string()),
+                    TestJSON::Number("1.0".to_string()),
+                ]),
+                "[1,1.0]",
+                "[
+    1,
+    1.0
+]",
+                "array
+  1
+  1.0",
+            ),
+            (TestJSON::Array(vec![]), "[]", "[]", "array"),
+            (TestJSON::Object(vec![]), "{}", "{}", "object"),
+            (
+                TestJSON::Array(vec![TestJSON::True, TestJSON::False]),
+                "[true,false]",
+                "[
+    true,
+    false
+]",
+                "array
+  true
+  false",
+            ),
+            (
+                TestJSON::Array(vec![TestJSON::Null, TestJSON::Object(vec![])]),
+                "[null,{}]",
+                "[
+    null,
+    {}
+]",
+                "array
+  null
+  object",
+            ),
+            (
+                TestJSON::Object(vec![
+                    ("foo".to_string(), TestJSON::Null),
+                    ("bar".to_string(), TestJSON::False),
+                ]),
+                r#"{"foo":null,"bar":false}"#,
+                r#"{
+    "foo": null,
+    "bar": false
+}"#,
+                r#"object
+  field
+    "foo"
+    null
+  field
+    "bar"
+    false"#,
+            ),
             (
                 TestJSON::Object(vec![
                     ("foo".to_string(), TestJSON::True),
                     ("bar".to_string(), TestJSON::False),
                 ]),
-                r#"{"foo": true, "bar": false}"#,
+                r#"{"foo":true,"bar":false}"#,
                 r#"{
     "foo": true,
     "bar": false
@@ -452,7 +2094,7 @@ mod tests {
                     ]),
                     TestJSON::True,
                 ]),
-                r#"[{"foos": [false, true, false], "bar": false}, true]"#,
+                r#"[{"foos":[false,true,false],"bar":false},true]"#,
                 r#"[
     {
         "foos": [
@@ -490,16 +2132,838 @@ mod tests {
                 Size::from(*expected_compact_string)
             );
             // Test pretty string
-            let pretty_string = root.to_text(&JSONFormat::Pretty);
+            let pretty = JSONFormat::Pretty {
+                indent: IndentKind::default(),
+                trailing_commas: false,
+            };
+            let pretty_string = root.to_text(&pretty);
             assert_eq!(pretty_string, *expected_pretty_string);
-            assert_eq!(
-                root.size(&JSONFormat::Pretty),
-                Size::from(*expected_pretty_string)
-            );
+            assert_eq!(root.size(&pretty), Size::from(*expected_pretty_string));
             // Test debug tree view
             let mut s = String::new();
             root.write_tree_view(&mut s);
             assert_eq!(s, *tree_string);
         }
     }
+
+    #[test]
+    fn number_validity_table() {
+        for (string, expected_validity) in &[
+            ("", NumberValidity::Incomplete),
+            ("-", NumberValidity::Incomplete),
+            ("0", NumberValidity::Complete),
+            ("-0", NumberValidity::Complete),
+            ("1234567890", NumberValidity::Complete),
+            ("-12", NumberValidity::Complete),
+            ("1.", NumberValidity::Incomplete),
+            ("1.05", NumberValidity::Complete),
+            ("0.5", NumberValidity::Complete),
+            ("1e", NumberValidity::Incomplete),
+            ("1e-", NumberValidity::Incomplete),
+            ("1e-9", NumberValidity::Complete),
+            ("1E+10", NumberValidity::Complete),
+            ("2.5e3", NumberValidity::Complete),
+            // Leading zeros are not allowed
+            ("01", NumberValidity::Invalid),
+            ("-00", NumberValidity::Invalid),
+            // Other malformed numbers
+            ("--1", NumberValidity::Invalid),
+            ("+1", NumberValidity::Invalid),
+            (".5", NumberValidity::Invalid),
+            ("1.e5", NumberValidity::Invalid),
+            ("1e5.0", NumberValidity::Invalid),
+            ("1e--5", NumberValidity::Invalid),
+            ("1-", NumberValidity::Invalid),
+            ("1a", NumberValidity::Invalid),
+        ] {
+            assert_eq!(
+                number_validity(string),
+                *expected_validity,
+                "validity of {:?}",
+                string
+            );
+        }
+    }
+
+    #[test]
+    fn number_push_pop_char() {
+        /// Types a sequence of chars into a number, returning `None` if any char is rejected
+        fn type_chars(start: &str, chars: &str) -> Option<String> {
+            let mut node = JSON::Number(start.to_string());
+            for c in chars.chars() {
+                node = node.push_char(c)?;
+            }
+            Some(node.display_name())
+        }
+
+        assert_eq!(type_chars("1", "23"), Some("123".to_string()));
+        assert_eq!(type_chars("1", ".5e-9"), Some("1.5e-9".to_string()));
+        // '-' toggles the sign unless it's directly after an exponent
+        assert_eq!(type_chars("1", "-"), Some("-1".to_string()));
+        assert_eq!(type_chars("1", "--"), Some("1".to_string()));
+        assert_eq!(type_chars("1", "e-"), Some("1e-".to_string()));
+        // A number started with `-` is finished by typing digits, and can't lose its only char
+        assert_eq!(type_chars("-", "5"), Some("-5".to_string()));
+        assert_eq!(type_chars("-", "-"), None);
+        // Leading zeros are rejected
+        assert_eq!(type_chars("0", "1"), None);
+        assert_eq!(type_chars("1", ".."), None);
+        assert_eq!(type_chars("1", "x"), None);
+        // Only numbers accept chars
+        assert_eq!(JSON::True.push_char('1'), None);
+
+        // Popping chars removes the last char, but never empties the number
+        assert_eq!(
+            JSON::Number("-1".to_string()).pop_char(),
+            Some(JSON::Number("-".to_string()))
+        );
+        assert_eq!(JSON::Number("7".to_string()).pop_char(), None);
+        assert_eq!(JSON::Null.pop_char(), None);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let arena = Arena::new();
+        let root = JSON::parse(
+            r#"{"id": 1, "x": {"id": 2}, "id": 3, "y": 4, "id": 5}"#,
+            &arena,
+        )
+        .unwrap();
+        let duplicate = |index, first_index| ValidationError::DuplicateKey {
+            key: r#""id""#.to_string(),
+            index,
+            first_index,
+        };
+        // Keys only clash with the other keys of the same object
+        assert_eq!(root.validate(), vec![duplicate(2, 0), duplicate(4, 0)]);
+        assert_eq!(duplicate(2, 0).children(), vec![0, 2]);
+        assert!(root.children()[1].validate().is_empty());
+        assert!(JSON::parse("[1, 1]", &arena).unwrap().validate().is_empty());
+    }
+
+    #[test]
+    fn unfinished_numbers() {
+        let unfinished = JSON::Number("1e-".to_string()).validate();
+        assert_eq!(
+            unfinished,
+            vec![ValidationError::Unfinished {
+                text: "1e-".to_string()
+            }]
+        );
+        assert!(unfinished[0].prevents_writing());
+        assert!(JSON::Number("1e-9".to_string()).validate().is_empty());
+    }
+
+    #[test]
+    fn string_text() {
+        let string = JSON::Str(r#"a"b\"#.to_string());
+        assert_eq!(string.text(), Some(r#"a"b\"#));
+        assert_eq!(string.display_name(), r#""a\"b\\""#);
+        assert_eq!(string.node_kind(), "string");
+        // The text cursor skips over the opening quote and every escaping `\`
+        assert_eq!(string.text_offset(0), 1);
+        assert_eq!(string.text_offset(1), 2);
+        assert_eq!(string.text_offset(2), 4);
+        assert_eq!(string.text_offset(4), 7);
+        assert_eq!(
+            string.with_text("日本"),
+            Some(JSON::Str("日本".to_string()))
+        );
+        // Only strings have text
+        assert_eq!(JSON::Number("1".to_string()).text(), None);
+        assert_eq!(JSON::Null.with_text("x"), None);
+    }
+
+    #[test]
+    fn indentation() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![("a".to_string(), TestJSON::Array(vec![]))]),
+        ])
+        .add_to_arena(&arena);
+        for (indent, expected_string) in &[
+            (IndentKind::Spaces(0), "[\ntrue,\n{\n\"a\": []\n}\n]"),
+            (
+                IndentKind::Spaces(2),
+                "[\n  true,\n  {\n    \"a\": []\n  }\n]",
+            ),
+            (
+                IndentKind::Spaces(4),
+                "[\n    true,\n    {\n        \"a\": []\n    }\n]",
+            ),
+            (IndentKind::Tabs, "[\n\ttrue,\n\t{\n\t\t\"a\": []\n\t}\n]"),
+        ] {
+            let format = JSONFormat::Pretty {
+                indent: *indent,
+                trailing_commas: false,
+            };
+            assert_eq!(root.to_text(&format), *expected_string);
+            assert_eq!(root.size(&format), Size::from(*expected_string));
+        }
+        // Changing the indent of a style changes how the tree is printed
+        let mut format = JSONFormat::Pretty {
+            indent: IndentKind::Spaces(4),
+            trailing_commas: false,
+        };
+        format.set_indent(IndentKind::Spaces(0));
+        assert_eq!(root.to_text(&format), "[\ntrue,\n{\n\"a\": []\n}\n]");
+        let mut format = JSONFormat::Compact;
+        format.set_indent(IndentKind::Tabs);
+        assert!(format == JSONFormat::Compact);
+        // Switching between the styles
+        format.set_kind(FormatKind::Pretty);
+        format.set_indent(IndentKind::Tabs);
+        assert_eq!(
+            root.to_text(&format),
+            "[\n\ttrue,\n\t{\n\t\t\"a\": []\n\t}\n]"
+        );
+        format.set_kind(FormatKind::Fit { max_width: 80 });
+        assert_eq!(format.indent(), IndentKind::Tabs);
+        assert_eq!(root.to_text(&format), r#"[true, {"a": []}]"#);
+        format.set_kind(FormatKind::Compact);
+        assert_eq!(root.to_text(&format), r#"[true,{"a":[]}]"#);
+        assert_eq!(root.size(&format), Size::line(15));
+    }
+
+    #[test]
+    fn syntax_categories() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::Null,
+            TestJSON::Str("x".to_string()),
+            TestJSON::Number("12".to_string()),
+            TestJSON::Number("1e".to_string()),
+            TestJSON::Str("a\nb\"".to_string()),
+        ])
+        .add_to_arena(&arena);
+        let texts: Vec<(String, SyntaxCategory)> = root
+            .display_tokens(&JSONFormat::Compact)
+            .filter_map(|(_, tok)| match tok {
+                DisplayToken::Text(s, category) => Some((s.into_owned(), category)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("[".to_string(), SyntaxCategory::Default),
+                ("null".to_string(), SyntaxCategory::Const),
+                (",".to_string(), SyntaxCategory::Default),
+                (r#""x""#.to_string(), SyntaxCategory::Literal),
+                (",".to_string(), SyntaxCategory::Default),
+                ("12".to_string(), SyntaxCategory::Literal),
+                (",".to_string(), SyntaxCategory::Default),
+                ("1e".to_string(), SyntaxCategory::Error),
+                (",".to_string(), SyntaxCategory::Default),
+                // Escape sequences stand out from the rest of the string
+                (r#""a"#.to_string(), SyntaxCategory::Literal),
+                (r"\n".to_string(), SyntaxCategory::Special),
+                ("b".to_string(), SyntaxCategory::Literal),
+                (r#"\""#.to_string(), SyntaxCategory::Special),
+                (r#"""#.to_string(), SyntaxCategory::Literal),
+                ("]".to_string(), SyntaxCategory::Default),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        for text in &[
+            "true",
+            "false",
+            "null",
+            "0",
+            "-12.5e+3",
+            r#""""#,
+            r#""hello world""#,
+            r#""say \"hi\" \\ bye""#,
+            r#""lines\nand\ttabs \u0000\u001f\u007f""#,
+            "[]",
+            "{}",
+            "[1,1.0,-0,2e10]",
+            r#"{"a":1,"b":[true,false,null],"c":{"d":{}}}"#,
+            r#"[{"foos":[false,true,false],"bar":false},true]"#,
+            r#"{"nested":[[[[[]]]]],"unicode":"日本語 🎉"}"#,
+        ] {
+            let arena = Arena::new();
+            let root = JSON::parse(text, &arena).unwrap();
+            // Compact formatting should exactly reproduce our (compactly formatted) inputs
+            assert_eq!(root.to_text(&JSONFormat::Compact), *text);
+            // Pretty formatting should reparse to the same tree
+            let pretty_text = root.to_text(&JSONFormat::Pretty {
+                indent: IndentKind::default(),
+                trailing_commas: false,
+            });
+            let reparsed_root = JSON::parse(&pretty_text, &arena).unwrap();
+            assert_eq!(root, reparsed_root);
+        }
+    }
+
+    #[test]
+    fn parse_structure() {
+        let arena = Arena::new();
+        let root = JSON::parse(
+            " \n\t{ \"key\" :[ true ,null\r\n,\"\\\"esc\\u0041pes\\\\\\n\\ud83c\\udf89\"] }  \n",
+            &arena,
+        )
+        .unwrap();
+        assert_eq!(
+            TestJSON::Object(vec![(
+                "key".to_string(),
+                TestJSON::Array(vec![
+                    TestJSON::True,
+                    TestJSON::Null,
+                    TestJSON::Str("\"escApes\\\n🎉".to_string())
+                ])
+            )]),
+            root
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line, column, kind) in &[
+            (
+                "",
+                1,
+                1,
+                ParseErrorKind::Expected {
+                    expected: "a JSON value",
+                    found: None,
+                },
+            ),
+            (
+                "[true,\n  fals]",
+                2,
+                7,
+                ParseErrorKind::Expected {
+                    expected: "false",
+                    found: Some(']'),
+                },
+            ),
+            (
+                "[1 2]",
+                1,
+                4,
+                ParseErrorKind::Expected {
+                    expected: "',' or ']'",
+                    found: Some('2'),
+                },
+            ),
+            (
+                "{\"a\": 1,}",
+                1,
+                9,
+                ParseErrorKind::Expected {
+                    expected: "a string as an object key",
+                    found: Some('}'),
+                },
+            ),
+            (
+                "{\"a\" 1}",
+                1,
+                6,
+                ParseErrorKind::Expected {
+                    expected: "':'",
+                    found: Some('1'),
+                },
+            ),
+            (
+                "[\n\n 012]",
+                3,
+                2,
+                ParseErrorKind::InvalidNumber("012".to_string()),
+            ),
+            ("\"\\x\"", 1, 3, ParseErrorKind::InvalidEscape('x')),
+            ("\"\\ud83c\"", 1, 8, ParseErrorKind::InvalidUnicodeEscape),
+            ("\"\\u12\"", 1, 6, ParseErrorKind::InvalidUnicodeEscape),
+            ("\"a\nb\"", 1, 3, ParseErrorKind::ControlCharInString('\n')),
+            (
+                "\"abc",
+                1,
+                5,
+                ParseErrorKind::Expected {
+                    expected: "'\"'",
+                    found: None,
+                },
+            ),
+            (
+                "true false",
+                1,
+                6,
+                ParseErrorKind::Expected {
+                    expected: "end of input",
+                    found: Some('f'),
+                },
+            ),
+        ] {
+            let arena = Arena::new();
+            assert_eq!(
+                JSON::parse(text, &arena),
+                Err(ParseError {
+                    line: *line,
+                    column: *column,
+                    kind: kind.clone()
+                }),
+                "parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let arena = Arena::new();
+        // Nesting up to the limit is fine ...
+        let text = "[".repeat(MAX_NESTING_DEPTH) + &"]".repeat(MAX_NESTING_DEPTH);
+        assert!(JSON::parse(&text, &arena).is_ok());
+        // ... but any deeper is an error rather than a stack overflow
+        let text = "[".repeat(MAX_NESTING_DEPTH + 1) + &"]".repeat(MAX_NESTING_DEPTH + 1);
+        assert_eq!(
+            JSON::parse(&text, &arena).unwrap_err().kind,
+            ParseErrorKind::TooDeeplyNested
+        );
+    }
+
+    /// A cheap version of the `json_parse` fuzz target (in `fuzz/`): parsing any text must
+    /// return either a tree or an error, and never panic
+    #[test]
+    fn parse_hostile_input() {
+        const FRAGMENTS: &[&str] = &[
+            "{",
+            "}",
+            "[",
+            "]",
+            ",",
+            ":",
+            "\"",
+            "\\",
+            "\\u",
+            "\\uD800",
+            "\\uDC00",
+            "\\ud83d\\ude00",
+            "0",
+            "1",
+            "-",
+            ".",
+            "e",
+            "+",
+            "99999999999999999999",
+            "true",
+            "tru",
+            "null",
+            "//",
+            "/*",
+            "*/",
+            "\n",
+            "\r",
+            " ",
+            "\t",
+            "\u{0}",
+            "\u{1f}",
+            "\u{7f}",
+            "\u{feff}",
+            "é",
+            "日",
+        ];
+        let mut rng = Rng::new(0xf022);
+        for i in 0..20_000 {
+            let text: String = if i % 2 == 0 {
+                // Arbitrary sequences of JSON-ish fragments
+                (0..rng.below(30))
+                    .map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())])
+                    .collect()
+            } else {
+                // Valid JSON with a few fragments inserted and chars removed
+                let arena = Arena::new();
+                let root = TestJSON::random(&mut rng, 4).add_to_arena(&arena);
+                let mut text: Vec<char> = root.to_text(&JSONFormat::Compact).chars().collect();
+                for _ in 0..rng.below(4) {
+                    let index = rng.below(text.len() + 1);
+                    if index < text.len() && rng.below(2) == 0 {
+                        text.remove(index);
+                    } else {
+                        let fragment = FRAGMENTS[rng.below(FRAGMENTS.len())];
+                        text.splice(index..index, fragment.chars());
+                    }
+                }
+                text.into_iter().collect()
+            };
+            for dialect in &[Dialect::JSON, Dialect::JSONC] {
+                let arena = Arena::new();
+                match JSON::parse_dialect(&text, &arena, *dialect) {
+                    // Anything that parses has to be written as text that parses to the same tree
+                    Ok(root) => {
+                        let written = root.to_text(&JSONFormat::Compact);
+                        let reparsed = JSON::parse_dialect(&written, &arena, *dialect);
+                        assert_eq!(
+                            reparsed,
+                            Ok(root),
+                            "{:?} was written as {:?}",
+                            text,
+                            written
+                        );
+                    }
+                    Err(e) => {
+                        assert!(e.line >= 1 && e.column >= 1, "{:?}: {}", text, e);
+                        assert!(e.line <= text.lines().count() + 1, "{:?}: {}", text, e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_jsonc() {
+        let text = "// config\n{\n  /* the\n   * name */ \"a\" /* in */ : 1, // one\n  \"b\": [true,],\n}\n";
+        let arena = Arena::new();
+        let root = JSON::parse_dialect(text, &arena, Dialect::JSONC).unwrap();
+        let comment = |text: &str| JSON::LineComment(text.to_string());
+        let block = |text: &str| JSON::BlockComment(text.to_string());
+        // Comments outside the root stay outside it, and comments inside fields are moved into
+        // the nearest object
+        assert_eq!(root.children().len(), 2);
+        assert_eq!(root.children()[0], &comment(" config"));
+        assert_eq!(root.document_value(), Some(1));
+        let object = root.children()[1];
+        let children: Vec<JSON> = object.children().iter().map(|c| (*c).clone()).collect();
+        assert_eq!(children.len(), 5);
+        assert_eq!(children[0], block(" the\n* name "));
+        assert_eq!(children[1], block(" in "));
+        assert_eq!(children[3], comment(" one"));
+        assert_eq!(
+            TestJSON::Array(vec![TestJSON::True]),
+            object.children()[4].children()[1]
+        );
+        assert_eq!(
+            root.to_text(&JSONFormat::Pretty {
+                indent: IndentKind::Spaces(2),
+                trailing_commas: true,
+            }),
+            "// config\n{\n  /* the\n   * name */\n  /* in */\n  \"a\": 1,\n  // one\n  \"b\": [\n    true,\n  ],\n}"
+        );
+        // A line comment always ends its line, even in compact JSON
+        assert_eq!(
+            root.to_text(&JSONFormat::Compact),
+            "// config\n{/* the\n * name *//* in */\"a\":1,// one\n\"b\":[true]}"
+        );
+        let fit = JSONFormat::Fit {
+            indent: IndentKind::Spaces(2),
+            max_width: 80,
+            trailing_commas: true,
+        };
+        let inline = JSON::parse_dialect("[1, /* two */ 2,]", &arena, Dialect::JSONC).unwrap();
+        assert_eq!(inline.to_text(&fit), "[1, /* two */ 2]");
+        // Line comments never fit on one line, but trailing commas are only added to broken nodes
+        assert_eq!(
+            root.to_text(&fit),
+            "// config\n{\n  /* the\n   * name */\n  /* in */\n  \"a\": 1,\n  // one\n  \"b\": [true],\n}"
+        );
+        // The rendered text parses back to the same tree
+        for format in &[JSONFormat::Compact, fit] {
+            let text = root.to_text(format);
+            assert_eq!(root.size(format), Size::from(text.as_str()));
+            let reparsed = JSON::parse_dialect(&text, &arena, Dialect::JSONC);
+            assert_eq!(reparsed, Ok(root));
+        }
+
+        // Plain JSON allows neither comments nor trailing commas
+        assert!(JSON::parse("[1] // one", &arena).is_err());
+        assert!(JSON::parse("[1,]", &arena).is_err());
+        for (text, kind) in &[
+            (
+                "[/* one",
+                ParseErrorKind::Expected {
+                    expected: "'*/'",
+                    found: None,
+                },
+            ),
+            (
+                "[/ one",
+                ParseErrorKind::Expected {
+                    expected: "'/' or '*'",
+                    found: Some(' '),
+                },
+            ),
+            (
+                "[,]",
+                ParseErrorKind::Expected {
+                    expected: "a JSON value",
+                    found: Some(','),
+                },
+            ),
+        ] {
+            let error = JSON::parse_dialect(text, &arena, Dialect::JSONC).unwrap_err();
+            assert_eq!(error.kind, *kind, "parsing {:?}", text);
+        }
+    }
+
+    #[test]
+    fn parse_large_file() {
+        let pretty = JSONFormat::Pretty {
+            indent: IndentKind::default(),
+            trailing_commas: false,
+        };
+        let eager_arena = Arena::new();
+        let text = JSON::parse_dialect(
+            r#"[{"name": "a record which is long enough to be left unparsed", "tags": [1, 2]},
+                /* short */ [true], {"nested": {"deeper": ["which is also long enough", null]}}]"#,
+            &eager_arena,
+            Dialect::JSONC,
+        )
+        .unwrap()
+        .to_text(&pretty);
+        let eager = JSON::parse_dialect(&text, &eager_arena, Dialect::JSONC).unwrap();
+        let arena = Arena::new();
+        let root = JSON::parse_large(&text, &arena, Dialect::JSONC).unwrap();
+        // Only the root's children are parsed, and long arrays and objects are left unparsed
+        let kinds: Vec<_> = root
+            .children()
+            .iter()
+            .map(|child| matches!(child, JSON::Unparsed(_)))
+            .collect();
+        assert_eq!(kinds, vec![true, false, false, true]);
+        assert_eq!(root.children()[0].display_name(), "object");
+        assert_eq!(root.children()[0].children(), &[] as &[&JSON]);
+        // Unparsed nodes are written as the text that they were parsed from
+        assert_eq!(root.to_text(&pretty), text);
+        assert_eq!(
+            JSON::Array(vec![root]).to_text(&pretty),
+            eager_arena.alloc(JSON::Array(vec![eager])).to_text(&pretty)
+        );
+        // Parsing an unparsed node gives the same tree as parsing everything at once, except that
+        // its own arrays and objects are left unparsed in turn
+        let record = root.children()[0].parse_unparsed(&arena).unwrap().unwrap();
+        assert_eq!(record, eager.children()[0]);
+        let nested = root.children()[3].parse_unparsed(&arena).unwrap().unwrap();
+        assert!(matches!(
+            nested.children()[0].children()[1],
+            JSON::Unparsed(_)
+        ));
+        assert_eq!(
+            nested.to_text(&pretty),
+            eager.children()[3].to_text(&pretty)
+        );
+        assert_eq!(JSON::True.parse_unparsed(&arena), None);
+
+        // Invalid text is an error in large-file mode too, even where it wouldn't be parsed
+        for text in &[
+            "[[1, 2}]",
+            "[{\"a\": [1, \"\\x\"]}]",
+            "[{\"a\" 1}]",
+            "[[[[1] ]]",
+        ] {
+            assert_eq!(
+                JSON::parse_large(text, &arena, Dialect::JSON),
+                JSON::parse(text, &arena),
+                "parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn comment_text() {
+        let comment = JSON::LineComment(" one".to_string());
+        assert_eq!(comment.text(), Some(" one"));
+        assert_eq!(comment.text_offset(1), 3);
+        // Line comments can't contain newlines, and block comments can't be closed early
+        assert_eq!(
+            comment.with_text("a\nb"),
+            Some(JSON::LineComment("a b".to_string()))
+        );
+        assert_eq!(
+            JSON::BlockComment(String::new()).with_text("a */ b"),
+            Some(JSON::BlockComment("a * / b".to_string()))
+        );
+        // Comments can go in arrays and objects, but not between a key and its value
+        assert!(JSON::Array(vec![]).is_valid_child(0, &comment));
+        assert!(JSON::Object(vec![]).is_valid_child(0, &JSON::BlockComment(String::new())));
+        assert!(!JSON::Field([&JSON::Null, &JSON::Null]).is_valid_child(1, &comment));
+        assert_eq!(
+            JSON::Array(vec![&comment, &JSON::Null, &comment]).fold_summary(),
+            "[…] (1 item)"
+        );
+    }
+
+    #[test]
+    fn documents() {
+        let arena = Arena::new();
+        let pretty = JSONFormat::Pretty {
+            indent: IndentKind::Spaces(2),
+            trailing_commas: false,
+        };
+        // Comments before and after the root stay outside it, even if it isn't an array or object
+        let text = "// before\n/* also before */\n{\n  // inside\n  \"a\": 1\n}\n// after";
+        let root = JSON::parse_dialect(text, &arena, Dialect::JSONC).unwrap();
+        assert_eq!(root.node_kind(), "document");
+        assert_eq!(root.document_value(), Some(2));
+        assert_eq!(root.children()[2].children().len(), 2);
+        assert_eq!(root.to_text(&pretty), text);
+        let scalar = JSON::parse_dialect("1 // one", &arena, Dialect::JSONC).unwrap();
+        assert_eq!(scalar.to_text(&JSONFormat::Compact), "1\n// one");
+        // Files without comments around their root don't have a document
+        let plain = JSON::parse_dialect("{/* inside */}", &arena, Dialect::JSONC).unwrap();
+        assert_eq!(plain.document_value(), None);
+        for format in &[JSONFormat::Compact, pretty] {
+            let text = root.to_text(format);
+            assert_eq!(root.size(format), Size::from(text.as_str()));
+            assert_eq!(JSON::parse_dialect(&text, &arena, Dialect::JSONC), Ok(root));
+        }
+
+        // Only comments can be added to a document, and its value can't be deleted
+        let comment = arena.alloc(JSON::LineComment(String::new()));
+        let mut document = root.clone();
+        document.insert_child(comment, &arena, 3).unwrap();
+        assert_eq!(document.document_value(), Some(2));
+        assert_eq!(
+            document.insert_child(&JSON::Null, &arena, 0),
+            Err(InsertError::InvalidChild {
+                parent_kind: "document",
+                child_kind: "null",
+            })
+        );
+        assert_eq!(
+            document.delete_children(1..3),
+            Err(DeleteError::RequiredChild {
+                kind: "document",
+                child_kind: "value",
+            })
+        );
+        document.delete_children(0..2).unwrap();
+        assert_eq!(document.document_value(), Some(0));
+        assert!(document.is_valid_child(0, &JSON::Null));
+        assert!(!document.is_valid_child(0, comment));
+        assert!(!document.is_valid_child(1, &JSON::Null));
+        assert_eq!(document.replace_chars().count(), 0);
+        // A document can't go inside anything
+        assert!(!JSON::Array(vec![]).is_valid_child(0, root));
+        assert_eq!(
+            JSON::Array(vec![]).insert_child(root, &arena, 0),
+            Err(InsertError::InvalidChild {
+                parent_kind: "array",
+                child_kind: "document",
+            })
+        );
+        assert_eq!(root.wrap_in('a', &arena), None);
+        // A document needs exactly one value
+        let owned = root.to_owned_node().unwrap();
+        let children = root.children();
+        assert_eq!(JSON::from_owned_node(&owned, children), Some(root.clone()));
+        assert_eq!(JSON::from_owned_node(&owned, &children[..2]), None);
+        assert_eq!(
+            JSON::from_owned_node(&OwnedNode::new("document", None), &[comment, root]),
+            None
+        );
+    }
+
+    #[test]
+    fn fit_to_width() {
+        let arena = Arena::new();
+        let root = TestJSON::Object(vec![
+            (
+                "a".to_string(),
+                TestJSON::Array(vec![TestJSON::True, TestJSON::Null]),
+            ),
+            (
+                "b".to_string(),
+                TestJSON::Object(vec![("c".to_string(), TestJSON::False)]),
+            ),
+        ])
+        .add_to_arena(&arena);
+        let fit = |max_width| JSONFormat::Fit {
+            indent: IndentKind::Spaces(2),
+            max_width,
+            trailing_commas: false,
+        };
+        for (max_width, expected_text) in &[
+            // Everything fits on one line, with nothing to spare
+            (500, r#"{"a": [true, null], "b": {"c": false}}"#),
+            (38, r#"{"a": [true, null], "b": {"c": false}}"#),
+            // One column too few breaks the root, but its children still fit
+            (37, "{\n  \"a\": [true, null],\n  \"b\": {\"c\": false}\n}"),
+            // Neither the key nor the indentation before a node count towards its width
+            (12, "{\n  \"a\": [true, null],\n  \"b\": {\"c\": false}\n}"),
+            (
+                11,
+                "{\n  \"a\": [\n    true,\n    null\n  ],\n  \"b\": {\n    \"c\": false\n  }\n}",
+            ),
+            (
+                0,
+                "{\n  \"a\": [\n    true,\n    null\n  ],\n  \"b\": {\n    \"c\": false\n  }\n}",
+            ),
+        ] {
+            let format = fit(*max_width);
+            assert_eq!(root.to_text(&format), *expected_text);
+            assert_eq!(root.size(&format), Size::from(*expected_text));
+        }
+        // Empty containers are never broken
+        let empty = TestJSON::Array(vec![TestJSON::Object(vec![])]).add_to_arena(&arena);
+        assert_eq!(empty.to_text(&fit(3)), "[\n  {}\n]");
+        assert_eq!(empty.to_text(&fit(4)), "[{}]");
+    }
+
+    #[test]
+    fn heap_size() {
+        let arena = Arena::new();
+        let text = "s".repeat(100);
+        let root = TestJSON::Object(vec![(text.clone(), TestJSON::Str(text))]).add_to_arena(&arena);
+        // Only the object's vec and the strings own any memory, since fields only point to
+        // their key and value
+        let field = root.children()[0];
+        assert_eq!(field.heap_size(), 0);
+        let strings: usize = field.children().iter().map(|&s| s.heap_size()).sum();
+        assert!(strings >= 200);
+        assert_eq!(root.heap_size(), std::mem::size_of::<&JSON>());
+        assert_eq!(JSON::True.heap_size(), 0);
+        assert_eq!(
+            arena.bytes_allocated(),
+            4 * std::mem::size_of::<JSON>() + strings + root.heap_size()
+        );
+    }
+
+    #[test]
+    fn size_matches_text() {
+        // The size of a node should always be the size of the text that it's rendered as
+        let formats = [
+            JSONFormat::Compact,
+            JSONFormat::Pretty {
+                indent: IndentKind::Spaces(2),
+                trailing_commas: false,
+            },
+            JSONFormat::Pretty {
+                indent: IndentKind::default(),
+                trailing_commas: false,
+            },
+            JSONFormat::Pretty {
+                indent: IndentKind::Tabs,
+                trailing_commas: false,
+            },
+            JSONFormat::Fit {
+                indent: IndentKind::default(),
+                max_width: 0,
+                trailing_commas: false,
+            },
+            JSONFormat::Fit {
+                indent: IndentKind::Tabs,
+                max_width: 20,
+                trailing_commas: false,
+            },
+            JSONFormat::Fit {
+                indent: IndentKind::Spaces(2),
+                max_width: 60,
+                trailing_commas: false,
+            },
+        ];
+        let mut rng = Rng::new(0);
+        for _ in 0..500 {
+            let arena = Arena::new();
+            let root = TestJSON::random(&mut rng, 4).add_to_arena(&arena);
+            for format in &formats {
+                let text = root.to_text(format);
+                assert_eq!(root.size(format), Size::from(text.as_str()), "{}", text);
+            }
+        }
+    }
 }
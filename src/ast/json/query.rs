@@ -0,0 +1,588 @@
+//! Queries which find nodes by where they are in the tree, written in a subset of
+//! [JSONPath](https://www.rfc-editor.org/rfc/rfc9535).  A query starts at the root (`$`, which
+//! can be left out) and is followed by any number of steps:
+//! - `.key` or `['key']`: the value of the field called `key`
+//! - `[2]`: the third value of an array (or `[-1]` for the last one)
+//! - `.*` or `[*]`: every value of an array or object
+//! - `..` before any of the above (e.g. `..key`): the same, but for the node and everything
+//!   inside it
+//!
+//! So `$.store.book[2].title` is the title of the third book in the store, and `$..price` is
+//! every price anywhere in the tree.
+//!
+//! Queries only look at [`children`](Ast::children), so they can be run on the tree of any
+//! language.  A child with a [key child](Ast::is_key_child) is a field, which is named by the
+//! [`text`](Ast::text) of its key and stands for its other child.  Any other child is numbered by
+//! where it is among its siblings, not counting [comments](Ast::is_comment).  A root which only
+//! holds comments around a [value](Ast::document_value) stands for that value.
+
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// Which of the values inside a node one step of a [`Query`] chooses
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Selector {
+    /// The value of the field with this key
+    Key(String),
+    /// The value at this index, counting from the end if it's negative
+    Index(isize),
+    /// Every value
+    Wildcard,
+}
+
+/// One step of a [`Query`], which chooses some values inside each node that the previous steps
+/// found
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Step {
+    /// If `true` (from `..`), the values are chosen from inside every node underneath the nodes
+    /// found so far, as well as from inside those nodes
+    pub descendants: bool,
+    pub selector: Selector,
+}
+
+/// A parsed query, such as `$.store.book[2].title`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+/// Why a query couldn't be parsed
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct QueryError {
+    /// How many chars into the query the problem was found
+    pub index: usize,
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at char {}", self.reason, self.index + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl Query {
+    /// Parses a query, such as `$.store.book[2].title`
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let mut parser = Parser {
+            chars: text.trim().chars().collect(),
+            index: 0,
+        };
+        parser.eat('$');
+        let mut steps = Vec::new();
+        while let Some(c) = parser.peek() {
+            let step = match c {
+                '[' => Step {
+                    descendants: false,
+                    selector: parser.bracket()?,
+                },
+                '.' => {
+                    parser.index += 1;
+                    let descendants = parser.eat('.');
+                    let selector = match parser.peek() {
+                        Some('[') if descendants => parser.bracket()?,
+                        _ => parser.name()?,
+                    };
+                    Step {
+                        descendants,
+                        selector,
+                    }
+                }
+                _ => return Err(parser.error("expected '.' or '['")),
+            };
+            steps.push(step);
+        }
+        Ok(Query { steps })
+    }
+
+    /// Returns the steps of this query, in order
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Returns the paths of every node in the tree under `root` that this query finds, in
+    /// pre-order.  A node is only given once, even if it's found in more than one way.
+    pub fn evaluate<'arena, Node: Ast<'arena>>(&self, root: &'arena Node) -> Vec<CursorPath> {
+        let mut found = vec![root_value(root)];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for (path, node) in found {
+                if step.descendants {
+                    for (path, node) in descendants(path, node) {
+                        select(&step.selector, &path, node, &mut next);
+                    }
+                } else {
+                    select(&step.selector, &path, node, &mut next);
+                }
+            }
+            next.sort_by(|(a, _), (b, _)| a.cmp(b));
+            next.dedup_by(|(a, _), (b, _)| a == b);
+            found = next;
+        }
+        found.into_iter().map(|(path, _)| path).collect()
+    }
+}
+
+/// Writes the query in its shortest form, e.g. `$.store.book[2]['first name']`
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "$")?;
+        for step in &self.steps {
+            if step.descendants {
+                write!(f, "..")?;
+            }
+            match &step.selector {
+                Selector::Key(key) if is_plain_key(key) => {
+                    write!(f, "{}{}", if step.descendants { "" } else { "." }, key)?
+                }
+                Selector::Key(key) => write!(f, "[{}]", quote_key(key))?,
+                Selector::Index(index) => write!(f, "[{}]", index)?,
+                Selector::Wildcard if step.descendants => write!(f, "*")?,
+                Selector::Wildcard => write!(f, ".*")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path of the node at `path` of the tree under `root`, written as a query which
+/// finds only that node (e.g. `$.store.book[2].title`).  The key of a field has the same path as
+/// the field's value, and a comment has the same path as its parent.
+pub fn path_string<'arena, Node: Ast<'arena>>(root: &'arena Node, path: &CursorPath) -> String {
+    let mut query = Query { steps: Vec::new() };
+    let (mut node_path, mut node) = root_value(root);
+    loop {
+        let values = values(&node_path, node);
+        let is_inside = |value: &Value<Node>| {
+            let mut child_path = node_path.clone();
+            child_path.push(value.index);
+            path.starts_with(&child_path)
+        };
+        let (position, value) = match values.iter().enumerate().find(|(_, v)| is_inside(v)) {
+            Some(found) => found,
+            None => break,
+        };
+        let selector = match value.key {
+            Some(key) => Selector::Key(key.to_string()),
+            None => {
+                let index = values[..position]
+                    .iter()
+                    .filter(|v| v.key.is_none())
+                    .count();
+                Selector::Index(index as isize)
+            }
+        };
+        query.steps.push(Step {
+            descendants: false,
+            selector,
+        });
+        node_path = value.path.clone();
+        node = value.node;
+    }
+    query.to_string()
+}
+
+/// Returns the path and node of the value that the root of a tree stands for
+fn root_value<'arena, Node: Ast<'arena>>(root: &'arena Node) -> (CursorPath, &'arena Node) {
+    match root.document_value() {
+        Some(index) => (CursorPath::from_vec(vec![index]), root.children()[index]),
+        None => (CursorPath::root(), root),
+    }
+}
+
+/// One of the values inside a node: either the value of a field, which is named by the field's
+/// key, or a value that isn't in a field
+pub(super) struct Value<'arena, Node> {
+    /// The index of the child that the value is (or the field that it's in)
+    pub index: usize,
+    pub key: Option<&'arena str>,
+    pub path: CursorPath,
+    pub node: &'arena Node,
+}
+
+/// Returns the values inside `node` (whose path is `path`), in order
+pub(super) fn values<'arena, Node: Ast<'arena>>(
+    path: &CursorPath,
+    node: &'arena Node,
+) -> Vec<Value<'arena, Node>> {
+    let mut values = Vec::new();
+    for (i, &child) in node.children().iter().enumerate() {
+        if child.is_comment() {
+            continue;
+        }
+        let mut path = path.clone();
+        path.push(i);
+        let grandchildren = child.children();
+        let key = (0..grandchildren.len())
+            .find(|&j| child.is_key_child(j))
+            .and_then(|j| grandchildren[j].text());
+        let value = match key {
+            // A field stands for its value (or for itself, if it somehow has no value)
+            Some(_) => match (0..grandchildren.len())
+                .find(|&j| !child.is_key_child(j) && !grandchildren[j].is_comment())
+            {
+                Some(j) => {
+                    path.push(j);
+                    grandchildren[j]
+                }
+                None => child,
+            },
+            None => child,
+        };
+        values.push(Value {
+            index: i,
+            key,
+            path,
+            node: value,
+        });
+    }
+    values
+}
+
+/// Returns `node` (whose path is `path`) and every value underneath it, in pre-order
+fn descendants<'arena, Node: Ast<'arena>>(
+    path: CursorPath,
+    node: &'arena Node,
+) -> Vec<(CursorPath, &'arena Node)> {
+    let mut descendants = Vec::new();
+    // Pushing values in reverse means that they get popped in order
+    let mut stack = vec![(path, node)];
+    while let Some((path, node)) = stack.pop() {
+        for value in values(&path, node).into_iter().rev() {
+            stack.push((value.path, value.node));
+        }
+        descendants.push((path, node));
+    }
+    descendants
+}
+
+/// Adds the values inside `node` (whose path is `path`) that `selector` chooses to `found`
+fn select<'arena, Node: Ast<'arena>>(
+    selector: &Selector,
+    path: &CursorPath,
+    node: &'arena Node,
+    found: &mut Vec<(CursorPath, &'arena Node)>,
+) {
+    let values = values(path, node);
+    let chosen: Vec<Value<Node>> = match selector {
+        Selector::Wildcard => values,
+        Selector::Key(key) => values
+            .into_iter()
+            .filter(|v| v.key == Some(key.as_str()))
+            .collect(),
+        Selector::Index(index) => {
+            let mut elements: Vec<_> = values.into_iter().filter(|v| v.key.is_none()).collect();
+            let len = elements.len() as isize;
+            let index = if *index < 0 { len + index } else { *index };
+            if (0..len).contains(&index) {
+                vec![elements.swap_remove(index as usize)]
+            } else {
+                Vec::new()
+            }
+        }
+    };
+    found.extend(chosen.into_iter().map(|v| (v.path, v.node)));
+}
+
+/// Returns `true` if a key can be written after a `.` in a query, rather than being quoted
+fn is_plain_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Writes a key in single quotes, escaping any `'` or `\` inside it
+fn quote_key(key: &str) -> String {
+    format!("'{}'", key.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// The state of parsing a query
+struct Parser {
+    chars: Vec<char>,
+    /// How many chars have been parsed
+    index: usize,
+}
+
+impl Parser {
+    /// Returns the next char, without parsing it
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    /// Parses `c` if it's the next char, returning `true` if it was
+    fn eat(&mut self, c: char) -> bool {
+        let is_next = self.peek() == Some(c);
+        if is_next {
+            self.index += 1;
+        }
+        is_next
+    }
+
+    /// Skips over any whitespace
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.index += 1;
+        }
+    }
+
+    /// Returns an error at the next char
+    fn error(&self, reason: &'static str) -> QueryError {
+        QueryError {
+            index: self.index,
+            reason,
+        }
+    }
+
+    /// Parses the key (or `*`) after a `.`
+    fn name(&mut self) -> Result<Selector, QueryError> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let start = self.index;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !".[]'\"*".contains(c))
+        {
+            self.index += 1;
+        }
+        if self.index == start {
+            return Err(self.error("expected a key or '*'"));
+        }
+        Ok(Selector::Key(
+            self.chars[start..self.index].iter().collect(),
+        ))
+    }
+
+    /// Parses a selector in square brackets, e.g. `[2]`, `['key']` or `[*]`
+    fn bracket(&mut self) -> Result<Selector, QueryError> {
+        self.eat('[');
+        self.skip_whitespace();
+        let selector = match self.peek() {
+            Some('*') => {
+                self.index += 1;
+                Selector::Wildcard
+            }
+            Some(quote @ ('\'' | '"')) => {
+                self.index += 1;
+                Selector::Key(self.quoted(quote)?)
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => Selector::Index(self.index_number()?),
+            _ => return Err(self.error("expected a key, an index or '*'")),
+        };
+        self.skip_whitespace();
+        if !self.eat(']') {
+            return Err(self.error("expected ']'"));
+        }
+        Ok(selector)
+    }
+
+    /// Parses the rest of a key that started with `quote`, up to and including the closing quote
+    fn quoted(&mut self, quote: char) -> Result<String, QueryError> {
+        let mut key = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("expected the end of the key")),
+                Some(c) if c == quote => break,
+                Some('\\') => {
+                    self.index += 1;
+                    match self.peek() {
+                        Some(c) if c == quote || c == '\\' => key.push(c),
+                        _ => return Err(self.error("expected a quote or '\\' after '\\'")),
+                    }
+                }
+                Some(c) => key.push(c),
+            }
+            self.index += 1;
+        }
+        self.index += 1;
+        Ok(key)
+    }
+
+    /// Parses an index, which may be negative
+    fn index_number(&mut self) -> Result<isize, QueryError> {
+        let start = self.index;
+        self.eat('-');
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.index += 1;
+        }
+        let text: String = self.chars[start..self.index].iter().collect();
+        text.parse().map_err(|_| QueryError {
+            index: start,
+            reason: "expected an index",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path_string, Query, QueryError, Selector, Step};
+    use crate::arena::Arena;
+    use crate::ast::json::{Dialect, JSONFormat, JSON};
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    const STORE: &str = r#"{
+        "store": {
+            "book": [
+                {"title": "Sayings", "price": 8},
+                {"title": "Sword", "price": 12},
+                {"title": "Moby Dick", "isbn": "0-553"}
+            ],
+            "bicycle": {"color": "red", "price": 19}
+        },
+        "first name": "Ann"
+    }"#;
+
+    #[test]
+    fn parse() {
+        let step = |descendants: bool, selector: Selector| Step {
+            descendants,
+            selector,
+        };
+        let key = |key: &str| Selector::Key(key.to_string());
+        let steps = |text: &str| Query::parse(text).map(|q| q.steps().to_vec());
+
+        assert_eq!(steps("$"), Ok(vec![]));
+        assert_eq!(steps(""), Ok(vec![]));
+        assert_eq!(
+            steps("$.store.book[2].title"),
+            Ok(vec![
+                step(false, key("store")),
+                step(false, key("book")),
+                step(false, Selector::Index(2)),
+                step(false, key("title")),
+            ])
+        );
+        // `$` can be left out, like in jq
+        assert_eq!(steps(".store"), Ok(vec![step(false, key("store"))]));
+        assert_eq!(
+            steps("$..book[ -1 ]..*"),
+            Ok(vec![
+                step(true, key("book")),
+                step(false, Selector::Index(-1)),
+                step(true, Selector::Wildcard),
+            ])
+        );
+        assert_eq!(
+            steps(r#"$['first name']["a\"b"][*]..['x']"#),
+            Ok(vec![
+                step(false, key("first name")),
+                step(false, key("a\"b")),
+                step(false, Selector::Wildcard),
+                step(true, key("x")),
+            ])
+        );
+
+        let error = |index: usize, reason: &'static str| Err(QueryError { index, reason });
+        assert_eq!(steps("store"), error(0, "expected '.' or '['"));
+        assert_eq!(steps("$."), error(2, "expected a key or '*'"));
+        assert_eq!(steps("$..").map_err(|e| e.index), Err(3));
+        assert_eq!(
+            steps("$[true]"),
+            error(2, "expected a key, an index or '*'")
+        );
+        assert_eq!(steps("$[2"), error(3, "expected ']'"));
+        assert_eq!(steps("$['key]"), error(7, "expected the end of the key"));
+        assert_eq!(steps("$[-]"), error(2, "expected an index"));
+        assert_eq!(
+            Query::parse("$.*x").unwrap_err().to_string(),
+            "expected '.' or '[' at char 4"
+        );
+    }
+
+    #[test]
+    fn display() {
+        for (text, shortest) in &[
+            ("", "$"),
+            ("$.store.book[2].title", "$.store.book[2].title"),
+            (".store['book'][-1]", "$.store.book[-1]"),
+            ("$..['price'].*", "$..price.*"),
+            ("$..[*]", "$..*"),
+            (r#"$["first name"]['it\'s']"#, r"$['first name']['it\'s']"),
+        ] {
+            let query = Query::parse(text).unwrap();
+            assert_eq!(query.to_string(), *shortest);
+            assert_eq!(Query::parse(shortest), Ok(query));
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let arena = Arena::new();
+        let root = JSON::parse(STORE, &arena).unwrap();
+        let found = |text: &str| -> Vec<String> {
+            let query = Query::parse(text).unwrap();
+            query
+                .evaluate(root)
+                .iter()
+                .map(|path| path.cursor(root).to_text(&JSONFormat::Compact))
+                .collect()
+        };
+
+        assert_eq!(found("$.store.book[2].title"), vec![r#""Moby Dick""#]);
+        assert_eq!(found("$.store.book[-1].isbn"), vec![r#""0-553""#]);
+        assert_eq!(found("$['first name']"), vec![r#""Ann""#]);
+        assert_eq!(found("$.store.book[*].price"), vec!["8", "12"]);
+        assert_eq!(found("$..price"), vec!["8", "12", "19"]);
+        assert_eq!(found("$.store.bicycle.*"), vec![r#""red""#, "19"]);
+        assert_eq!(found("$..book[0].title"), vec![r#""Sayings""#]);
+        assert_eq!(found("$..*").len(), 15);
+        // Nodes found more than once are only given once
+        assert_eq!(found("$..*..title").len(), 3);
+        assert_eq!(found("$").len(), 1);
+        // Nothing is found in the wrong kind of node, or past the ends of arrays
+        assert!(found("$.store[0]").is_empty());
+        assert!(found("$.store.book.title").is_empty());
+        assert!(found("$.store.book[3]").is_empty());
+        assert!(found("$.store.book[-4]").is_empty());
+        assert!(found("$.missing").is_empty());
+    }
+
+    #[test]
+    fn comments() {
+        let arena = Arena::new();
+        let root = JSON::parse_dialect(
+            "[// first\n1, /* second */ 2, {\"a\": [3]}]",
+            &arena,
+            Dialect::JSONC,
+        )
+        .unwrap();
+        let query = |text: &str| Query::parse(text).unwrap().evaluate(root);
+
+        // Comments aren't counted when indexing arrays
+        assert_eq!(query("$[1]"), vec![CursorPath::from_vec(vec![3])]);
+        assert_eq!(query("$[*]").len(), 3);
+        assert_eq!(query("$..*").len(), 5);
+
+        // Every path can be written as a query which finds it again
+        for path in query("$..*") {
+            let text = path_string(root, &path);
+            assert_eq!(query(&text), vec![path]);
+        }
+        assert_eq!(
+            path_string(root, &CursorPath::from_vec(vec![4, 0, 1, 0])),
+            "$[2].a[0]"
+        );
+        // Keys and comments give the paths of what they belong to
+        assert_eq!(
+            path_string(root, &CursorPath::from_vec(vec![4, 0, 0])),
+            "$[2].a"
+        );
+        assert_eq!(path_string(root, &CursorPath::from_vec(vec![0])), "$");
+        assert_eq!(path_string(root, &CursorPath::root()), "$");
+
+        // Queries start from the value of a document with comments around it
+        let root = JSON::parse_dialect("// config\n{\"a\": [3]}", &arena, Dialect::JSONC).unwrap();
+        let path = CursorPath::from_vec(vec![1, 0, 1, 0]);
+        assert_eq!(
+            Query::parse("$.a[0]").unwrap().evaluate(root),
+            vec![path.clone()]
+        );
+        assert_eq!(path_string(root, &path), "$.a[0]");
+        assert_eq!(
+            Query::parse("$").unwrap().evaluate(root),
+            vec![CursorPath::from_vec(vec![1])]
+        );
+    }
+}
@@ -0,0 +1,581 @@
+//! [JSON Schemas](https://json-schema.org/), which describe what a JSON document should contain.
+//! Only part of draft-07 is supported: `type`, `required`, `properties`, `additionalProperties`,
+//! `items`, `enum`, `minimum` and `maximum`.  Other keywords (like `title` or `$schema`) are
+//! ignored.
+//!
+//! Like [queries](super::query), schemas are checked against any tree by walking its values, and
+//! the type of each node is given by its [`node_kind`](Ast::node_kind).  Each keyword only
+//! depends on a node and its children, so each subtree can be checked against its part of the
+//! schema on its own (see [`Validation`](crate::editable_tree::validate::Validation), which uses
+//! this to only check the nodes that an edit has changed).
+
+use super::query::values;
+use super::{escape_string, JSON};
+use crate::arena::Arena;
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// The types that a schema's `type` can require a value to be
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Type {
+    Object,
+    Array,
+    String,
+    Number,
+    /// A number without a fractional part
+    Integer,
+    Boolean,
+    Null,
+}
+
+impl Type {
+    /// Every type, in the order they're listed to the user
+    pub const ALL: [Type; 7] = [
+        Type::Object,
+        Type::Array,
+        Type::String,
+        Type::Number,
+        Type::Integer,
+        Type::Boolean,
+        Type::Null,
+    ];
+
+    /// Returns the name of this type, as it's written in a schema
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Object => "object",
+            Type::Array => "array",
+            Type::String => "string",
+            Type::Number => "number",
+            Type::Integer => "integer",
+            Type::Boolean => "boolean",
+            Type::Null => "null",
+        }
+    }
+}
+
+/// A parsed JSON Schema, or one part of one (e.g. the schema of one property)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    /// If `true`, nothing matches the schema (which is written as `false`)
+    never: bool,
+    /// The types that a value can be, if the schema says
+    types: Option<Vec<Type>>,
+    /// The keys that an object must have
+    required: Vec<String>,
+    /// The schemas of the values of the fields with these keys
+    properties: Vec<(String, Schema)>,
+    /// The schema of the values of fields that aren't in `properties`
+    additional_properties: Option<Box<Schema>>,
+    /// The schema of every element of an array
+    items: Option<Box<Schema>>,
+    /// The values that are allowed, written by [`canonical_text`]
+    enum_values: Option<Vec<String>>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+}
+
+/// The ways that a node can fail to match a [`Schema`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SchemaError {
+    /// Nothing is allowed where the node is (because its schema is `false`)
+    NotAllowed,
+    /// The node isn't any of the `expected` types
+    WrongType {
+        expected: Vec<&'static str>,
+        found: &'static str,
+    },
+    /// The node is an object without a field that's required
+    MissingProperty { key: String },
+    /// The child at `index` is a field that isn't allowed (because `additionalProperties` is
+    /// `false`)
+    UnexpectedProperty { key: String, index: usize },
+    /// The node isn't one of the values listed in the schema's `enum`
+    NotInEnum { value: String },
+    /// The node is a number below the `minimum`
+    TooSmall { value: String, minimum: String },
+    /// The node is a number above the `maximum`
+    TooLarge { value: String, maximum: String },
+}
+
+impl SchemaError {
+    /// Returns the indices of the children which cause this problem, which is none of them if
+    /// the problem is the node itself
+    pub fn children(&self) -> Vec<usize> {
+        match self {
+            SchemaError::UnexpectedProperty { index, .. } => vec![*index],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::NotAllowed => write!(f, "The schema doesn't allow anything here"),
+            SchemaError::WrongType { expected, found } => {
+                write!(f, "Expected {}, but found {}", expected.join(" or "), found)
+            }
+            SchemaError::MissingProperty { key } => {
+                write!(
+                    f,
+                    "Missing the required property \"{}\"",
+                    escape_string(key)
+                )
+            }
+            SchemaError::UnexpectedProperty { key, .. } => {
+                write!(f, "The property \"{}\" isn't allowed", escape_string(key))
+            }
+            SchemaError::NotInEnum { value } => {
+                write!(f, "{} isn't one of the allowed values", value)
+            }
+            SchemaError::TooSmall { value, minimum } => {
+                write!(f, "{} is less than the minimum of {}", value, minimum)
+            }
+            SchemaError::TooLarge { value, maximum } => {
+                write!(f, "{} is more than the maximum of {}", value, maximum)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl Schema {
+    /// Parses the text of a schema, returning why not if it isn't a valid schema
+    pub fn parse(text: &str) -> Result<Schema, String> {
+        let arena = Arena::new();
+        let root = JSON::parse(text, &arena).map_err(|e| e.to_string())?;
+        Schema::from_node(root, "")
+    }
+
+    /// Reads a schema from the JSON value `node`, which is at the JSON Pointer `pointer` of the
+    /// whole schema (so that errors can say where they are)
+    fn from_node(node: &JSON, pointer: &str) -> Result<Schema, String> {
+        let fields = match node {
+            JSON::True => return Ok(Schema::default()),
+            JSON::False => {
+                return Ok(Schema {
+                    never: true,
+                    ..Schema::default()
+                })
+            }
+            JSON::Object(fields) => fields,
+            _ => {
+                return Err(format!(
+                    "{}: a schema must be an object or a boolean",
+                    pointer
+                ))
+            }
+        };
+        let mut schema = Schema::default();
+        for field in fields.iter() {
+            let (key, value) = match field {
+                JSON::Field([key, value]) => (key.text().unwrap_or_default(), *value),
+                _ => continue,
+            };
+            let pointer = format!("{}/{}", pointer, key);
+            let invalid = |expected: &str| format!("{}: expected {}", pointer, expected);
+            match key {
+                "type" => {
+                    let names = match value {
+                        JSON::Array(names) => names.iter().collect(),
+                        _ => vec![&value],
+                    };
+                    let types = names
+                        .iter()
+                        .map(|name| {
+                            Type::ALL
+                                .iter()
+                                .copied()
+                                .find(|t| name.text() == Some(t.name()))
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| invalid("a type or an array of types"))?;
+                    schema.types = Some(types);
+                }
+                "required" => {
+                    let keys = match value {
+                        JSON::Array(keys) => keys.iter().map(|k| k.text().map(str::to_string)),
+                        _ => return Err(invalid("an array of strings")),
+                    };
+                    schema.required = keys
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| invalid("an array of strings"))?;
+                }
+                "properties" => {
+                    let properties = match value {
+                        JSON::Object(properties) => properties,
+                        _ => return Err(invalid("an object")),
+                    };
+                    for property in properties.iter() {
+                        if let JSON::Field([key, value]) = property {
+                            let key = key.text().unwrap_or_default();
+                            let property_pointer = format!("{}/{}", pointer, key);
+                            let property = Schema::from_node(value, &property_pointer)?;
+                            schema.properties.push((key.to_string(), property));
+                        }
+                    }
+                }
+                "additionalProperties" => {
+                    schema.additional_properties =
+                        Some(Box::new(Schema::from_node(value, &pointer)?))
+                }
+                "items" => schema.items = Some(Box::new(Schema::from_node(value, &pointer)?)),
+                "enum" => {
+                    let values = match value {
+                        JSON::Array(values) => values,
+                        _ => return Err(invalid("an array")),
+                    };
+                    let values = values.iter().map(|&v| canonical_text(v)).collect();
+                    schema.enum_values = Some(values);
+                }
+                "minimum" | "maximum" => {
+                    let number = match value {
+                        JSON::Number(text) => text.parse().ok(),
+                        _ => None,
+                    };
+                    let number = number.ok_or_else(|| invalid("a number"))?;
+                    if key == "minimum" {
+                        schema.minimum = Some(number);
+                    } else {
+                        schema.maximum = Some(number);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Returns every way that `node` doesn't match this schema.  Only the node itself (and the
+    /// keys of its fields) are checked, not the values inside it.
+    pub fn check<'arena, Node: Ast<'arena>>(&self, node: &'arena Node) -> Vec<SchemaError> {
+        // A document is checked by checking its value
+        if node.document_value().is_some() {
+            return Vec::new();
+        }
+        if self.never {
+            return vec![SchemaError::NotAllowed];
+        }
+        // Nodes which aren't values (like the unparsed parts of large files) can't be checked
+        let (found, number) = match value_type(node) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        let mut errors = Vec::new();
+        if let Some(types) = &self.types {
+            let matches = |t: &Type| match t {
+                Type::Integer => number.is_some_and(|n| n.fract() == 0.0),
+                Type::Number => found == Type::Number,
+                t => *t == found,
+            };
+            if !types.iter().any(matches) {
+                let found = match found {
+                    Type::Number if number.is_some_and(|n| n.fract() == 0.0) => Type::Integer,
+                    found => found,
+                };
+                // Nothing else is worth checking in a node of the wrong type
+                return vec![SchemaError::WrongType {
+                    expected: types.iter().map(|t| t.name()).collect(),
+                    found: found.name(),
+                }];
+            }
+        }
+        if let Some(enum_values) = &self.enum_values {
+            let value = canonical_text(node);
+            if !enum_values.contains(&value) {
+                errors.push(SchemaError::NotInEnum { value });
+            }
+        }
+        if let Some(number) = number {
+            let value = || canonical_text(node);
+            match (self.minimum, self.maximum) {
+                (Some(minimum), _) if number < minimum => errors.push(SchemaError::TooSmall {
+                    value: value(),
+                    minimum: minimum.to_string(),
+                }),
+                (_, Some(maximum)) if number > maximum => errors.push(SchemaError::TooLarge {
+                    value: value(),
+                    maximum: maximum.to_string(),
+                }),
+                _ => {}
+            }
+        }
+        if found == Type::Object {
+            let values = values(&CursorPath::root(), node);
+            for key in &self.required {
+                if !values.iter().any(|v| v.key == Some(key.as_str())) {
+                    errors.push(SchemaError::MissingProperty { key: key.clone() });
+                }
+            }
+            if self.additional_properties.as_ref().is_some_and(|s| s.never) {
+                for value in &values {
+                    let key = value.key.unwrap_or_default();
+                    if !self.properties.iter().any(|(k, _)| k == key) {
+                        errors.push(SchemaError::UnexpectedProperty {
+                            key: key.to_string(),
+                            index: value.index,
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Returns the values inside `node` that have to match part of this schema, with their paths
+    /// (relative to `node`) and the parts of the schema that they have to match
+    pub fn children<'arena, 's, Node: Ast<'arena>>(
+        &'s self,
+        node: &'arena Node,
+    ) -> Vec<(CursorPath, &'arena Node, &'s Schema)> {
+        if let Some(index) = node.document_value() {
+            let path = CursorPath::from_vec(vec![index]);
+            return vec![(path, node.children()[index], self)];
+        }
+        let is_object = match value_type(node) {
+            Some((Type::Object, _)) => true,
+            Some((Type::Array, _)) => false,
+            _ => return Vec::new(),
+        };
+        let mut children = Vec::new();
+        for value in values(&CursorPath::root(), node) {
+            let schema = match value.key {
+                Some(key) if is_object => self
+                    .properties
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, schema)| schema)
+                    // Fields that aren't allowed at all are pointed out by `check`
+                    .or_else(|| self.additional_properties.as_deref().filter(|s| !s.never)),
+                None if !is_object => self.items.as_deref(),
+                _ => None,
+            };
+            if let Some(schema) = schema {
+                children.push((value.path, value.node, schema));
+            }
+        }
+        children
+    }
+}
+
+/// Returns the type of the value `node`, along with its value if it's a number.  Returns `None`
+/// if the node isn't a JSON value.
+fn value_type<'arena, Node: Ast<'arena>>(node: &'arena Node) -> Option<(Type, Option<f64>)> {
+    Some(match node.node_kind() {
+        "object" => (Type::Object, None),
+        "array" => (Type::Array, None),
+        "string" => (Type::String, None),
+        "true" | "false" => (Type::Boolean, None),
+        "null" => (Type::Null, None),
+        "number" => {
+            let text = node.to_owned_node().and_then(|n| n.text);
+            (Type::Number, text.and_then(|t| t.parse().ok()))
+        }
+        _ => return None,
+    })
+}
+
+/// Writes the value `node` on one line, in a form which is the same for every way of writing
+/// the same value: numbers are written in their shortest form, and the fields of objects are
+/// sorted by key.  This is how `enum` decides whether values are the same.
+fn canonical_text<'arena, Node: Ast<'arena>>(node: &'arena Node) -> String {
+    match value_type(node) {
+        Some((Type::String, _)) => format!("\"{}\"", escape_string(node.text().unwrap_or(""))),
+        Some((Type::Number, Some(number))) => number.to_string(),
+        Some((Type::Array, _)) => {
+            let values = values(&CursorPath::root(), node);
+            let elements: Vec<_> = values.iter().map(|v| canonical_text(v.node)).collect();
+            format!("[{}]", elements.join(","))
+        }
+        Some((Type::Object, _)) => {
+            let mut fields: Vec<_> = values(&CursorPath::root(), node)
+                .iter()
+                .map(|v| {
+                    let key = escape_string(v.key.unwrap_or_default());
+                    format!("\"{}\":{}", key, canonical_text(v.node))
+                })
+                .collect();
+            fields.sort();
+            format!("{{{}}}", fields.join(","))
+        }
+        _ => node
+            .to_owned_node()
+            .and_then(|n| n.text)
+            .unwrap_or_else(|| node.node_kind().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_text, Schema, SchemaError};
+    use crate::arena::Arena;
+    use crate::ast::json::{Dialect, JSON};
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    const SCHEMA: &str = r#"{
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "required": ["name", "port"],
+        "properties": {
+            "name": {"type": "string"},
+            "port": {"type": "integer", "minimum": 1, "maximum": 65535},
+            "mode": {"enum": ["fast", "safe", {"custom": [1, 2]}]},
+            "tags": {"type": "array", "items": {"type": ["string", "null"]}},
+            "extra": true
+        },
+        "additionalProperties": false
+    }"#;
+
+    /// Returns what's wrong with each node of `text` that part of [`SCHEMA`] applies to, by path
+    fn errors(text: &str) -> Vec<(String, SchemaError)> {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let arena = Arena::new();
+        let root = JSON::parse_dialect(text, &arena, Dialect::JSONC).unwrap();
+        let mut errors = Vec::new();
+        let mut stack = vec![(CursorPath::root(), root, &schema)];
+        while let Some((path, node, schema)) = stack.pop() {
+            for error in schema.check(node) {
+                errors.push((path.clone(), error));
+            }
+            for (child_path, child, child_schema) in schema.children(node) {
+                let mut full_path = path.clone();
+                for &index in child_path.iter() {
+                    full_path.push(index);
+                }
+                stack.push((full_path, child, child_schema));
+            }
+        }
+        errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let errors = errors.into_iter();
+        errors
+            .map(|(path, error)| (path.to_string(), error))
+            .collect()
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(errors(r#"{"name": "a", "port": 80}"#), vec![]);
+        assert_eq!(
+            errors(
+                r#"{"name": "a", "port": 8.0e1, "mode": {"custom": [1, 2.0]}, "tags": ["x", null],
+                "extra": [{}]}"#
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |path: &str, error: SchemaError| (path.to_string(), error);
+        assert_eq!(
+            errors(r#"{"name": 3, "tags": [true], "other": 1}"#),
+            vec![
+                error(
+                    "root",
+                    SchemaError::MissingProperty {
+                        key: "port".to_string()
+                    }
+                ),
+                error(
+                    "root",
+                    SchemaError::UnexpectedProperty {
+                        key: "other".to_string(),
+                        index: 2
+                    }
+                ),
+                error(
+                    "0.1",
+                    SchemaError::WrongType {
+                        expected: vec!["string"],
+                        found: "integer"
+                    }
+                ),
+                error(
+                    "1.1.0",
+                    SchemaError::WrongType {
+                        expected: vec!["string", "null"],
+                        found: "boolean"
+                    }
+                ),
+            ]
+        );
+        let messages = |text: &str| -> Vec<String> {
+            errors(text).iter().map(|(_, e)| e.to_string()).collect()
+        };
+        assert_eq!(
+            messages(r#"{"name": "a", "port": 0, "mode": "slow"}"#),
+            vec![
+                "0 is less than the minimum of 1",
+                r#""slow" isn't one of the allowed values"#,
+            ]
+        );
+        assert_eq!(
+            messages(r#"{"name": "a", "port": 1.5e5}"#),
+            vec!["150000 is more than the maximum of 65535"]
+        );
+        assert_eq!(
+            messages(r#"{"name": "a", "port": 2.5}"#),
+            vec!["Expected integer, but found number"]
+        );
+        assert_eq!(messages("[]"), vec!["Expected object, but found array"]);
+        // The comments around the root don't change what the schema applies to
+        assert_eq!(
+            errors("// config\n{\"name\": 3, \"port\": 1}"),
+            vec![error(
+                "1.0.1",
+                SchemaError::WrongType {
+                    expected: vec!["string"],
+                    found: "integer"
+                }
+            )]
+        );
+
+        // Schemas written as `false` don't allow anything
+        let schema = Schema::parse(r#"{"items": false}"#).unwrap();
+        let arena = Arena::new();
+        let root = JSON::parse("[1]", &arena).unwrap();
+        let children = schema.children(root);
+        assert_eq!(children.len(), 1);
+        assert_eq!(
+            children[0].2.check(children[0].1),
+            vec![SchemaError::NotAllowed]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        for (schema, error) in &[
+            ("[]", ": a schema must be an object or a boolean"),
+            (
+                r#"{"type": "text"}"#,
+                "/type: expected a type or an array of types",
+            ),
+            (
+                r#"{"required": [1]}"#,
+                "/required: expected an array of strings",
+            ),
+            (r#"{"minimum": "1"}"#, "/minimum: expected a number"),
+            (
+                r#"{"properties": {"a": {"items": 1}}}"#,
+                "/properties/a/items: a schema must be an object or a boolean",
+            ),
+        ] {
+            assert_eq!(Schema::parse(schema), Err(error.to_string()));
+        }
+        assert!(Schema::parse("{").is_err());
+    }
+
+    #[test]
+    fn canonical() {
+        let arena = Arena::new();
+        let text = |json: &str| canonical_text(JSON::parse(json, &arena).unwrap());
+        assert_eq!(
+            text(r#"{"b": [1.0, "x\n"], "a": null}"#),
+            r#"{"a":null,"b":[1,"x\n"]}"#
+        );
+        assert_eq!(text("true"), "true");
+        assert_eq!(text("-2.50"), "-2.5");
+    }
+}
@@ -0,0 +1,507 @@
+//! JSON Patches ([RFC 6902](https://www.rfc-editor.org/rfc/rfc6902)), which describe the changes
+//! between two versions of a JSON document as a list of operations.
+//!
+//! A patch is made from the [differences](super::diff::differences) between two trees.  Every
+//! `replace` comes first (at its path in the old tree), then every `remove` from the last to the
+//! first (so each one's path in the old tree is still correct when it's applied), and then every
+//! `add` from the first to the last (at its path in the new tree, which is correct once
+//! everything before it has been added).  Fields that change key are removed and added again.
+//!
+//! Patches are made from any tree whose fields have [key children](Ast::is_key_child), but are
+//! only meaningful for JSON (not, for example, JSONC with comments in its arrays).
+
+use super::json::{escape_string, JSON};
+use super::Ast;
+use crate::arena::Arena;
+use crate::ast::diff::{differences, TreeEdit};
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// One operation of a JSON Patch.  Paths are [JSON Pointers](https://www.rfc-editor.org/rfc/rfc6901)
+/// and values are the text of JSON values.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum PatchOp {
+    Add { path: String, value: String },
+    Remove { path: String },
+    Replace { path: String, value: String },
+}
+
+impl std::fmt::Display for PatchOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (op, path, value) = match self {
+            PatchOp::Add { path, value } => ("add", path, Some(value)),
+            PatchOp::Remove { path } => ("remove", path, None),
+            PatchOp::Replace { path, value } => ("replace", path, Some(value)),
+        };
+        write!(f, r#"{{"op": "{}", "path": "{}""#, op, escape_string(path))?;
+        if let Some(value) = value {
+            write!(f, r#", "value": {}"#, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Returns the JSON Patch that turns the tree under `old` into the tree under `new`.  Values are
+/// written in `format_style`, which should be compact so that each operation fits on a line.
+pub fn diff_patch<'arena, Node: Ast<'arena>>(
+    old: &'arena Node,
+    new: &'arena Node,
+    format_style: &Node::FormatStyle,
+) -> Vec<PatchOp> {
+    let value = |node: &'arena Node| value_of(node).to_text(format_style);
+    let (mut replaces, mut removes, mut adds) = (Vec::new(), Vec::new(), Vec::new());
+    // The path in the new tree of the last field whose key changed, which is added again whole
+    let mut rekeyed: Option<CursorPath> = None;
+    for difference in differences(old, new) {
+        if rekeyed
+            .as_ref()
+            .is_some_and(|field| difference.new_path.starts_with(field))
+        {
+            continue;
+        }
+        match difference.edit {
+            TreeEdit::Deleted(path) => removes.push(PatchOp::Remove {
+                path: pointer(old, &path),
+            }),
+            TreeEdit::Inserted(path) => adds.push(PatchOp::Add {
+                path: pointer(new, &path),
+                value: value(difference.new),
+            }),
+            TreeEdit::Replaced(path) => {
+                let (mut old_field, mut new_field) = (difference.old_path.clone(), path);
+                // A changed key changes where the whole field is
+                let key_index = new_field.pop();
+                old_field.pop();
+                let parent = new_field.cursor(new);
+                if key_index.is_some_and(|i| parent.is_key_child(i)) {
+                    removes.push(PatchOp::Remove {
+                        path: pointer(old, &old_field),
+                    });
+                    adds.push(PatchOp::Add {
+                        path: pointer(new, &new_field),
+                        value: value(parent),
+                    });
+                    rekeyed = Some(new_field);
+                } else {
+                    replaces.push(PatchOp::Replace {
+                        path: pointer(old, &difference.old_path),
+                        value: value(difference.new),
+                    });
+                }
+            }
+        }
+    }
+    removes.reverse();
+    replaces.into_iter().chain(removes).chain(adds).collect()
+}
+
+/// Writes a JSON Patch as a JSON array, with one operation on each line
+pub fn write_patch(patch: &[PatchOp]) -> String {
+    if patch.is_empty() {
+        return "[]\n".to_string();
+    }
+    let ops: Vec<String> = patch.iter().map(|op| format!("  {}", op)).collect();
+    format!("[\n{}\n]\n", ops.join(",\n"))
+}
+
+/// Returns the value that a node stands for: the value of a field, or the node itself
+fn value_of<'arena, Node: Ast<'arena>>(node: &'arena Node) -> &'arena Node {
+    let children = node.children();
+    if key_of(node).is_none() {
+        return node;
+    }
+    (0..children.len())
+        .find(|&i| !node.is_key_child(i) && !children[i].is_comment())
+        .map_or(node, |i| children[i])
+}
+
+/// Returns the key of a field, if `node` is one
+fn key_of<'arena, Node: Ast<'arena>>(node: &'arena Node) -> Option<&'arena str> {
+    let children = node.children();
+    let index = (0..children.len()).find(|&i| node.is_key_child(i))?;
+    children[index].text()
+}
+
+/// Escapes one step of a JSON Pointer, so that it can contain `~` and `/`
+pub fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Reverses [`escape_token`]
+pub fn unescape_token(token: &str) -> String {
+    // `~01` is `~1` escaped, so `~1` has to be unescaped first
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// Returns the JSON Pointer of the node at `path` of the tree under `root`.  Fields are named by
+/// their keys, so a field and its value have the same pointer.
+fn pointer<'arena, Node: Ast<'arena>>(root: &'arena Node, path: &CursorPath) -> String {
+    let mut pointer = String::new();
+    let mut node = root;
+    for &index in path.iter() {
+        let child = node.children()[index];
+        // The value of a field is where the field is, and the value of a document is the whole
+        // document
+        if key_of(node).is_none() && node.document_value() != Some(index) {
+            pointer.push('/');
+            match key_of(child) {
+                Some(key) => pointer.push_str(&escape_token(key)),
+                None => pointer.push_str(&index.to_string()),
+            }
+        }
+        node = child;
+    }
+    pointer
+}
+
+/* ===== APPLYING PATCHES ===== */
+
+/// The reasons that a JSON Patch can't be applied
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum PatchError {
+    /// The patch isn't valid JSON, or isn't an array of operations
+    Invalid(String),
+    /// The path of an operation doesn't exist in the document that it's applied to
+    NoSuchPath(String),
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Invalid(reason) => write!(f, "Invalid patch: {}", reason),
+            PatchError::NoSuchPath(path) => write!(f, "Nothing is at '{}'", path),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Applies the JSON Patch written in `patch` to the JSON tree under `root`, allocating the new
+/// tree's nodes into `arena`.  This supports the `add`, `remove` and `replace` operations (which
+/// are all that [`diff_patch`] makes).  Fields added to an object go after the existing ones.
+pub fn apply_patch<'arena>(
+    root: &'arena JSON<'arena>,
+    patch: &str,
+    arena: &'arena Arena<JSON<'arena>>,
+) -> Result<&'arena JSON<'arena>, PatchError> {
+    let invalid = |reason: &str| PatchError::Invalid(reason.to_string());
+    let patch = JSON::parse(patch, arena).map_err(|e| PatchError::Invalid(e.to_string()))?;
+    let ops = match patch {
+        JSON::Array(ops) => ops,
+        _ => return Err(invalid("a patch must be an array")),
+    };
+    let mut root = root;
+    for op in ops {
+        let member = |name: &str| match op {
+            JSON::Object(fields) => fields.iter().find_map(|field| match field {
+                JSON::Field([key, value]) if key.text() == Some(name) => Some(*value),
+                _ => None,
+            }),
+            _ => None,
+        };
+        let name = member("op").and_then(|op| op.text());
+        let path = member("path")
+            .and_then(|path| path.text())
+            .ok_or_else(|| invalid("every operation needs a path"))?;
+        let tokens: Vec<String> = match path {
+            "" => Vec::new(),
+            _ => match path.strip_prefix('/') {
+                Some(rest) => rest.split('/').map(unescape_token).collect(),
+                None => return Err(invalid("paths must start with '/'")),
+            },
+        };
+        let change = match name {
+            Some("add") | Some("replace") => {
+                let value = member("value").ok_or_else(|| invalid("missing value"))?;
+                match name {
+                    Some("add") => Change::Add(value),
+                    _ => Change::Replace(value),
+                }
+            }
+            Some("remove") => Change::Remove,
+            _ => return Err(invalid("the only operations are add, remove and replace")),
+        };
+        root = apply_change(root, &tokens, change, arena)
+            .ok_or_else(|| PatchError::NoSuchPath(path.to_string()))?;
+    }
+    Ok(root)
+}
+
+/// One patch operation, not counting its path
+#[derive(Debug, Copy, Clone)]
+enum Change<'arena> {
+    Add(&'arena JSON<'arena>),
+    Remove,
+    Replace(&'arena JSON<'arena>),
+}
+
+/// Makes a change at the end of a path of pointer tokens, returning the new version of `node`
+/// (or `None` if the path doesn't exist)
+fn apply_change<'arena>(
+    node: &'arena JSON<'arena>,
+    tokens: &[String],
+    change: Change<'arena>,
+    arena: &'arena Arena<JSON<'arena>>,
+) -> Option<&'arena JSON<'arena>> {
+    // The comments around the value of a document stay where they are
+    if let JSON::Document(children) = node {
+        let index = node.document_value()?;
+        let mut children = children.clone();
+        children[index] = apply_change(children[index], tokens, change, arena)?;
+        return Some(arena.alloc(JSON::Document(children)));
+    }
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        // Adding or replacing the whole document gives the new value
+        None => match change {
+            Change::Add(value) | Change::Replace(value) => return Some(value),
+            Change::Remove => return None,
+        },
+    };
+    let new_node = match node {
+        JSON::Array(elements) => {
+            let mut elements = elements.clone();
+            let len = elements.len();
+            let index = match token.as_str() {
+                "-" => len,
+                _ => token.parse().ok()?,
+            };
+            match (rest.is_empty(), change) {
+                (true, Change::Add(value)) if index <= len => elements.insert(index, value),
+                (true, Change::Remove) if index < len => {
+                    elements.remove(index);
+                }
+                (true, Change::Replace(value)) if index < len => elements[index] = value,
+                (true, _) => return None,
+                (false, _) => {
+                    let element = elements.get_mut(index)?;
+                    *element = apply_change(element, rest, change, arena)?;
+                }
+            }
+            JSON::Array(elements)
+        }
+        JSON::Object(fields) => {
+            let mut fields = fields.clone();
+            let index = fields
+                .iter()
+                .position(|field| key_of(*field) == Some(token.as_str()));
+            match (rest.is_empty(), change, index) {
+                (true, Change::Remove, Some(i)) => {
+                    fields.remove(i);
+                }
+                (true, Change::Add(value) | Change::Replace(value), Some(i)) => {
+                    fields[i] = arena.alloc(JSON::Field([fields[i].children()[0], value]));
+                }
+                (true, Change::Add(value), None) => {
+                    let key = arena.alloc(JSON::Str(token.clone()));
+                    fields.push(arena.alloc(JSON::Field([key, value])));
+                }
+                (false, _, Some(i)) => {
+                    let [key, value] = [fields[i].children()[0], fields[i].children()[1]];
+                    let value = apply_change(value, rest, change, arena)?;
+                    fields[i] = arena.alloc(JSON::Field([key, value]));
+                }
+                _ => return None,
+            }
+            JSON::Object(fields)
+        }
+        _ => return None,
+    };
+    Some(arena.alloc(new_node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_patch, diff_patch, escape_token, unescape_token, write_patch, PatchOp};
+    use crate::arena::Arena;
+    use crate::ast::json::{Dialect, JSONFormat, JSON};
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::DAG;
+    use crate::testing::{random_json, Bounds, Edit, Rng};
+
+    /// Returns the text of a JSON tree with the fields of every object sorted by key, since the
+    /// order of fields doesn't matter to JSON Patch
+    fn canonical<'arena>(node: &'arena JSON<'arena>) -> String {
+        match node {
+            JSON::Array(elements) => {
+                let elements: Vec<_> = elements.iter().map(|e| canonical(e)).collect();
+                format!("[{}]", elements.join(","))
+            }
+            JSON::Object(fields) => {
+                let mut fields: Vec<_> = fields.iter().map(|f| canonical(f)).collect();
+                fields.sort();
+                format!("{{{}}}", fields.join(","))
+            }
+            JSON::Field([key, value]) => format!("{}:{}", canonical(key), canonical(value)),
+            _ => node.to_text(&JSONFormat::Compact),
+        }
+    }
+
+    /// Makes the patch between two documents and checks that applying it to the first gives the
+    /// second, returning the patch
+    fn round_trip(old: &str, new: &str) -> Vec<PatchOp> {
+        let arena = Arena::new();
+        let old = JSON::parse_dialect(old, &arena, Dialect::JSONC).unwrap();
+        let new = JSON::parse_dialect(new, &arena, Dialect::JSONC).unwrap();
+        let patch = diff_patch(old, new, &JSONFormat::Compact);
+        let patched = apply_patch(old, &write_patch(&patch), &arena).unwrap();
+        assert_eq!(
+            canonical(patched),
+            canonical(new),
+            "{}",
+            write_patch(&patch)
+        );
+        patch
+    }
+
+    fn add(path: &str, value: &str) -> PatchOp {
+        PatchOp::Add {
+            path: path.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn remove(path: &str) -> PatchOp {
+        PatchOp::Remove {
+            path: path.to_string(),
+        }
+    }
+
+    fn replace(path: &str, value: &str) -> PatchOp {
+        PatchOp::Replace {
+            path: path.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn operations() {
+        assert_eq!(round_trip("[1, 2]", "[1, 2]"), vec![]);
+        assert_eq!(
+            round_trip(r#"{"a": 1, "b": [true]}"#, r#"{"a": 2, "b": [true, null]}"#),
+            vec![replace("/a", "2"), add("/b/1", "null")]
+        );
+        assert_eq!(
+            round_trip(r#"{"a": 1, "b": 2}"#, r#"{"b": 2, "c": {"d": []}}"#),
+            vec![remove("/a"), add("/c", r#"{"d":[]}"#)]
+        );
+        assert_eq!(
+            round_trip("[1]", r#"{"a": 1}"#),
+            vec![replace("", r#"{"a":1}"#)]
+        );
+        // A field whose key changed is moved, along with its value
+        assert_eq!(
+            round_trip(r#"{"a": [1]}"#, r#"{"b": [1, 2]}"#),
+            vec![remove("/a"), add("/b", "[1,2]")]
+        );
+        // The value of a document with comments around it is the whole document
+        assert_eq!(
+            round_trip("// one\n{\"a\": 1}", "// one\n{\"a\": 2}"),
+            vec![replace("/a", "2")]
+        );
+        assert_eq!(
+            round_trip("// one\n[1]", "// one\ntrue"),
+            vec![replace("", "true")]
+        );
+    }
+
+    #[test]
+    fn array_positions() {
+        // Replaces and removes use the original positions (3 is replaced by `true`, and then 1 is
+        // removed), whereas adds use the final positions
+        assert_eq!(
+            round_trip("[0, 1, 2, 3, 4]", "[0, 2, true, 4, false]"),
+            vec![replace("/3", "true"), remove("/1"), add("/4", "false")]
+        );
+        assert_eq!(
+            round_trip("[0, 1, 2, 3, 4]", "[0, 2, 4]"),
+            vec![remove("/3"), remove("/1")]
+        );
+        round_trip("[[1, 2], 3, [4]]", "[[2], [4, 5], 6]");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_token("a/b~c"), "a~1b~0c");
+        assert_eq!(unescape_token("a~1b~0c"), "a/b~c");
+        // `~01` is an escaped `~1`, not an escaped `~/`
+        assert_eq!(unescape_token("~01"), "~1");
+        let patch = round_trip(
+            r#"{"a/b": 1, "m~n": {"~1": 2}, "\"q\"": 3}"#,
+            r#"{"a/b": 2, "m~n": {"~1": 3}, "\"q\"": 4}"#,
+        );
+        assert_eq!(
+            patch,
+            vec![
+                replace("/a~1b", "2"),
+                replace("/m~0n/~01", "3"),
+                replace("/\"q\"", "4")
+            ]
+        );
+        assert_eq!(
+            write_patch(&patch[2..]),
+            "[\n  {\"op\": \"replace\", \"path\": \"/\\\"q\\\"\", \"value\": 4}\n]\n"
+        );
+    }
+
+    #[test]
+    fn invalid_patches() {
+        let arena = Arena::new();
+        let root = JSON::parse(r#"{"a": [1]}"#, &arena).unwrap();
+        for (patch, message) in [
+            ("{}", "Invalid patch: a patch must be an array"),
+            (
+                r#"[{"op": "move", "path": ""}]"#,
+                "Invalid patch: the only operations are add, remove and replace",
+            ),
+            (
+                r#"[{"op": "add", "path": "a"}]"#,
+                "Invalid patch: paths must start with '/'",
+            ),
+            (
+                r#"[{"op": "remove", "path": "/a/1"}]"#,
+                "Nothing is at '/a/1'",
+            ),
+            (
+                r#"[{"op": "replace", "path": "/b", "value": 1}]"#,
+                "Nothing is at '/b'",
+            ),
+        ] {
+            let error = apply_patch(root, patch, &arena).unwrap_err();
+            assert_eq!(error.to_string(), message, "{}", patch);
+        }
+        let patch = r#"[{"op": "add", "path": "/a/-", "value": 2}]"#;
+        let patched = apply_patch(root, patch, &arena).unwrap();
+        assert_eq!(patched.to_text(&JSONFormat::Compact), r#"{"a":[1,2]}"#);
+    }
+
+    #[test]
+    fn random_edits() {
+        let mut rng = Rng::new(66);
+        let bounds = Bounds {
+            max_depth: 4,
+            max_nodes: 40,
+        };
+        let is_valid = |root: &JSON| root.iter_pre_order().all(|n| n.validate().is_empty());
+        let mut checked = 0;
+        for _ in 0..300 {
+            let arena = Arena::new();
+            let old = random_json(&mut rng, &arena, bounds);
+            let mut tree = DAG::new(&arena, old, CursorPath::root());
+            for _ in 0..5 {
+                let _ = Edit::random(&mut rng, tree.root()).apply(&mut tree);
+            }
+            let new = tree.root();
+            // Duplicate keys don't have JSON Pointers, and incomplete numbers can't be parsed
+            let new_text = new.to_text(&JSONFormat::Compact);
+            if !is_valid(old) || !is_valid(new) || JSON::parse(&new_text, &arena).is_err() {
+                continue;
+            }
+            let patch = write_patch(&diff_patch(old, new, &JSONFormat::Compact));
+            let patched = apply_patch(old, &patch, &arena).unwrap();
+            assert_eq!(canonical(patched), canonical(new), "{}", patch);
+            checked += 1;
+        }
+        assert!(checked > 100, "{}", checked);
+    }
+}
@@ -1,30 +1,84 @@
 //! A module to contain Rust representations of ASTs in a format that sapling can work with.
 
+pub mod ansi;
+pub mod diff;
 pub mod display_token;
+pub mod dot;
+pub mod expr;
+pub mod html;
+pub mod iter;
 pub mod json;
+pub mod json_patch;
+pub mod owned;
+pub mod sexpr;
 pub mod size;
+pub mod stats;
 pub mod test_json;
+pub mod toml;
+pub mod xml;
 
 use std::error::Error;
 
-use crate::arena::Arena;
-use display_token::{write_tokens, DisplayToken, RecTok};
+use crate::arena::{Arena, HeapSize};
+use crate::editable_tree::compact::Compactor;
+use display_token::{write_tokens, DisplayTokens, RecTok, RenderStyle, SyntaxCategory};
+use iter::{PostOrderIter, PreOrderIter};
+use json::schema::SchemaError;
+use owned::{OwnedNode, OwnedTree, OwnedWriter};
 use size::Size;
 
 /// The possible ways an insertion could fail
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum InsertError {
     /// Inserting the node would cause the child count to exceed the limit for that node type
-    TooManyChildren { name: String, max_children: usize },
+    TooManyChildren {
+        kind: &'static str,
+        max_children: usize,
+    },
+    /// The node being inserted isn't of a type that can be a child of the parent (e.g. a JSON
+    /// field can't be inserted into an array)
+    InvalidChild {
+        parent_kind: &'static str,
+        child_kind: &'static str,
+    },
+    /// The node being inserted could be a child of the parent, but not at this index (e.g. a
+    /// TOML key-value pair can't go after the first table of a document, since it would then
+    /// belong to that table)
+    InvalidPosition {
+        parent_kind: &'static str,
+        child_kind: &'static str,
+        index: usize,
+    },
+    /// The index to insert at is past the end of the parent's children.  Like
+    /// [`DeleteError::IndexOutOfRange`], this shouldn't happen in practice.
+    IndexOutOfRange { len: usize, index: usize },
 }
 
 impl std::fmt::Display for InsertError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            InsertError::TooManyChildren { name, max_children } => write!(
+            InsertError::TooManyChildren { kind, max_children } => write!(
                 f,
                 "Can't exceed child count limit of {} in {}",
-                max_children, name
+                max_children, kind
+            ),
+            InsertError::InvalidChild {
+                parent_kind,
+                child_kind,
+            } => write!(f, "{} can't be a child of {}", child_kind, parent_kind),
+            InsertError::InvalidPosition {
+                parent_kind,
+                child_kind,
+                index,
+            } => write!(
+                f,
+                "{} can't be inserted at index {} of {}",
+                child_kind, index, parent_kind
+            ),
+            InsertError::IndexOutOfRange { len, index } => write!(
+                f,
+                "Inserting at child index {} is out of range 0..={}",
+                index, len
             ),
         }
     }
@@ -36,34 +90,160 @@ impl Error for InsertError {}
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum DeleteError {
     /// Deleting the requested node(s) would cause the parent to have too few children
-    TooFewChildren { name: String, min_children: usize },
+    TooFewChildren {
+        kind: &'static str,
+        min_children: usize,
+    },
     /// The requsted node doesn't exist.  This shouldn't be able to occur in practice, because it
     /// would require selecting a non-existent node - but nevertheless I don't think Sapling should
     /// panic in this situation.
     IndexOutOfRange { len: usize, index: usize },
+    /// One of the requested nodes can't be deleted, because its parent can't exist without it
+    /// (e.g. the header of a TOML table)
+    RequiredChild {
+        kind: &'static str,
+        child_kind: &'static str,
+    },
 }
 
 impl std::fmt::Display for DeleteError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            DeleteError::TooFewChildren { name, min_children } => write!(
+            DeleteError::TooFewChildren { kind, min_children } => write!(
                 f,
                 "Node type {} can't have fewer than {} children.",
-                name, min_children
+                kind, min_children
             ),
             DeleteError::IndexOutOfRange { len, index } => write!(
                 f,
                 "Deleting child index {} is out of range 0..{}",
                 index, len
             ),
+            DeleteError::RequiredChild { kind, child_kind } => write!(
+                f,
+                "Every {} must have a {}, so it can't be deleted.",
+                kind, child_kind
+            ),
         }
     }
 }
 
+/// The possible ways that moving a child to a different position could fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum MoveError {
+    /// The child would be moved past the first or last of its siblings
+    OutOfRange { len: usize, new_index: isize },
+    /// The children of this node have fixed positions (e.g. the key and value of a JSON field)
+    FixedPositions { kind: &'static str },
+    /// The child can't be at `new_index` (e.g. a TOML key-value pair can't be moved after the
+    /// first table of a document)
+    InvalidPosition {
+        parent_kind: &'static str,
+        child_kind: &'static str,
+        new_index: usize,
+    },
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::OutOfRange { len, new_index } => write!(
+                f,
+                "Can't move child to index {}, which is out of range 0..{}",
+                new_index, len
+            ),
+            MoveError::FixedPositions { kind } => {
+                write!(f, "The children of {} can't be reordered.", kind)
+            }
+            MoveError::InvalidPosition {
+                parent_kind,
+                child_kind,
+                new_index,
+            } => write!(
+                f,
+                "{} can't be moved to index {} of {}",
+                child_kind, new_index, parent_kind
+            ),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
+/// A problem with a node which makes the tree invalid, even though every child is allowed in its
+/// place (see [`Ast::validate`])
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ValidationError {
+    /// The child at `index` has the same key as the earlier child at `first_index` (e.g. two
+    /// fields of a JSON object with the same name)
+    DuplicateKey {
+        key: String,
+        index: usize,
+        first_index: usize,
+    },
+    /// The node's text is only the start of a valid node (e.g. the JSON number `1e`), so the tree
+    /// can't be written until it's finished
+    Unfinished { text: String },
+    /// The node doesn't match the part of the schema that it has to match (see
+    /// [`Schema`](json::schema::Schema))
+    Schema(SchemaError),
+}
+
+impl ValidationError {
+    /// Returns the indices of the children which cause this problem, and so should be
+    /// highlighted.  If there are none, then the node itself is highlighted.
+    pub fn children(&self) -> Vec<usize> {
+        match self {
+            ValidationError::DuplicateKey {
+                index, first_index, ..
+            } => vec![*first_index, *index],
+            ValidationError::Unfinished { .. } => Vec::new(),
+            ValidationError::Schema(error) => error.children(),
+        }
+    }
+
+    /// Returns `true` if the tree can't be written whilst it has this problem, because the text
+    /// wouldn't be valid (and so couldn't be read back in)
+    pub fn prevents_writing(&self) -> bool {
+        matches!(self, ValidationError::Unfinished { .. })
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::DuplicateKey {
+                key,
+                index,
+                first_index,
+            } => write!(
+                f,
+                "Duplicate key {} (child {} has the same key as child {})",
+                key, index, first_index
+            ),
+            ValidationError::Unfinished { text } => write!(f, "{} isn't finished", text),
+            ValidationError::Schema(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
 /// The specification of an AST that sapling can edit
-pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash {
+pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash + HeapSize {
     /// A type parameter that will represent the different ways this AST can be rendered
-    type FormatStyle;
+    type FormatStyle: RenderStyle;
+
+    /// The [`SyntaxCategory`]s that only this language uses (on top of the ones that every
+    /// language shares, which are listed in [`CATEGORIES`](crate::config::CATEGORIES)).  Each
+    /// one is [`Custom`](SyntaxCategory::Custom), and is paired with the shared category whose
+    /// style it has if the colour scheme doesn't give it one.
+    const CATEGORIES: &'static [(SyntaxCategory, SyntaxCategory)] = &[];
+
+    /// Every [`SyntaxCategory`] that the text of this language's
+    /// [`display_tokens`](Ast::display_tokens) is drawn in, so that colour schemes can be checked
+    /// for categories that they have no style for
+    const TOKEN_CATEGORIES: &'static [SyntaxCategory];
 
     /* FORMATTING FUNCTIONS */
 
@@ -74,30 +254,47 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         format_style: &Self::FormatStyle,
     ) -> Vec<RecTok<'arena, Self>>;
 
-    fn display_tokens(
+    /// Returns a lazy iterator over the tokens that make up this node and its descendants, along
+    /// with the node that generated each token
+    fn display_tokens<'f>(
         &'arena self,
-        format_style: &Self::FormatStyle,
-    ) -> Vec<(&'arena Self, DisplayToken)> {
-        let mut tok_pairs: Vec<(&'arena Self, DisplayToken)> = Vec::new();
-        for i in self.display_tokens_rec(format_style) {
-            match i {
-                RecTok::Tok(t) => {
-                    tok_pairs.push((self, t));
-                }
-                RecTok::Child(c) => {
-                    tok_pairs.extend(c.display_tokens(format_style));
-                }
-            }
-        }
-        tok_pairs
+        format_style: &'f Self::FormatStyle,
+    ) -> DisplayTokens<'f, 'arena, Self> {
+        DisplayTokens::new(self, format_style)
     }
 
+    /// Determine the space on the screen occupied by this node, given the [`Size`]s of its
+    /// children (in the same order as [`children`](Ast::children)).  This lets sizes be built
+    /// up from sizes that have already been calculated (see [`SizeCache`](size::SizeCache)).
+    fn size_from_children(&self, format_style: &Self::FormatStyle, child_sizes: &[Size]) -> Size;
+
     /// Determine the space on the screen occupied by this node in an AST
-    fn size(&self, format_style: &Self::FormatStyle) -> Size;
+    fn size(&self, format_style: &Self::FormatStyle) -> Size
+    where
+        Self: 'arena,
+    {
+        let child_sizes: Vec<Size> = self
+            .children()
+            .iter()
+            .map(|c| c.size(format_style))
+            .collect();
+        self.size_from_children(format_style, &child_sizes)
+    }
+
+    /// Write the textual representation of this AST to `out`, a token at a time (so that saving a
+    /// huge tree doesn't need a copy of all of its text, see [`write_tokens`])
+    fn write_text_to(
+        &'arena self,
+        out: &mut dyn std::fmt::Write,
+        format_style: &Self::FormatStyle,
+    ) -> std::fmt::Result {
+        write_tokens(self, out, format_style)
+    }
 
     /// Write the textual representation of this AST to a string
     fn write_text(&'arena self, string: &mut String, format_style: &Self::FormatStyle) {
-        write_tokens(self, string, format_style);
+        // Writing to a `String` can't fail
+        let _ = self.write_text_to(string, format_style);
     }
 
     /// Make a [`String`] representing this AST.
@@ -108,6 +305,19 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         s
     }
 
+    /// Returns the single line of text that is rendered in place of this node when it's folded
+    /// (i.e. collapsed so that its children aren't shown)
+    fn fold_summary(&self) -> String
+    where
+        Self: 'arena,
+    {
+        format!(
+            "{}… ({} children)",
+            self.display_name(),
+            self.children().len()
+        )
+    }
+
     /* DEBUG VIEW FUNCTIONS */
 
     /// Get a slice over the direct children of this node.  This operation is expected to be
@@ -119,9 +329,74 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     /// cheap - it will be used a lot of times without caching the results.
     fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena Self];
 
+    /// Returns an iterator over this node and all of its descendants, where every node comes
+    /// before its children
+    fn iter_pre_order(&'arena self) -> PreOrderIter<'arena, Self> {
+        PreOrderIter::new(self)
+    }
+
+    /// Returns an iterator over this node and all of its descendants, where every node comes
+    /// after its children
+    fn iter_post_order(&'arena self) -> PostOrderIter<'arena, Self> {
+        PostOrderIter::new(self)
+    }
+
+    /// Returns the number of nodes in the tree under this node (including this node)
+    fn node_count(&'arena self) -> usize {
+        self.iter_pre_order().count()
+    }
+
+    /// Returns the number of steps from this node down to its deepest descendant (so a node with
+    /// no children has a depth of 0)
+    fn depth(&'arena self) -> usize {
+        let mut iter = self.iter_pre_order();
+        let mut max_depth = 0;
+        while iter.next().is_some() {
+            max_depth = max_depth.max(iter.depth());
+        }
+        max_depth
+    }
+
+    /// Removes a range of consecutive children from this node, if possible.  If the removal was
+    /// not possible, then we return a custom error type and no children are removed.
+    fn delete_children(&mut self, range: std::ops::Range<usize>) -> Result<(), DeleteError>;
+
     /// Removes the child at a given index from the children of this node, if possible.  If the
     /// removal was not possible, then we return a custom error type.
-    fn delete_child(&mut self, index: usize) -> Result<(), DeleteError>;
+    fn delete_child(&mut self, index: usize) -> Result<(), DeleteError> {
+        self.delete_children(index..index + 1)
+    }
+
+    /// Returns `true` if the children of this node can be reordered without changing their
+    /// meaning (which is false for e.g. the key and value of a JSON field).
+    fn has_reorderable_children(&self) -> bool {
+        true
+    }
+
+    /// Moves the child at `index` so that it ends up at `new_index`, shifting the children in
+    /// between to make room.  If the move isn't possible, then no children are moved.
+    fn move_child(&mut self, index: usize, new_index: isize) -> Result<(), MoveError>
+    where
+        Self: 'arena,
+    {
+        if !self.has_reorderable_children() {
+            return Err(MoveError::FixedPositions {
+                kind: self.node_kind(),
+            });
+        }
+        let children = self.children_mut();
+        let len = children.len();
+        if index >= len || new_index < 0 || new_index as usize >= len {
+            return Err(MoveError::OutOfRange { len, new_index });
+        }
+        let new_index = new_index as usize;
+        if index < new_index {
+            children[index..=new_index].rotate_left(1);
+        } else {
+            children[new_index..=index].rotate_right(1);
+        }
+        Ok(())
+    }
 
     /// Insert a given pre-allocated node as a new child of this node.  This can involve allocating
     /// extra nodes (usually as ancestors of `new_node` but descendants of `self`).  This is
@@ -135,9 +410,59 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         index: usize,
     ) -> Result<(), InsertError>;
 
-    /// Get the display name of this node
+    /// Get the display name of this node, which is its [`node_kind`](Ast::node_kind) unless its
+    /// contents say more about it (e.g. the display name of a JSON string is its text)
     fn display_name(&self) -> String;
 
+    /// Get the name of this type of node (e.g. `"string"`), which unlike the
+    /// [`display_name`](Ast::display_name) is the same for every node of that type.  This is
+    /// what errors about what a type of node can or can't do refer to.
+    fn node_kind(&self) -> &'static str;
+
+    /// Makes a deep copy of this node and all of its descendants, allocating the copies in
+    /// `arena`.  The returned tree is equal to `self`, but shares no nodes with it.
+    fn clone_into_arena(&'arena self, arena: &'arena Arena<Self>) -> &'arena Self {
+        let mut cloned_node = self.clone();
+        for child in cloned_node.children_mut() {
+            *child = child.clone_into_arena(arena);
+        }
+        arena.alloc(cloned_node)
+    }
+
+    /// Makes a deep copy of this node and all of its descendants in `target`, in the same way as
+    /// [`clone_into_arena`](Ast::clone_into_arena).  Unlike `clone_into_arena`, this works on
+    /// trees of any depth, and nodes that appear more than once in the tree are only copied once
+    /// (so the copy shares nodes in exactly the same way as the original).  To copy several trees
+    /// while keeping the nodes that they share, use a [`Compactor`].
+    fn clone_subtree_into(&'arena self, target: &'arena Arena<Self>) -> &'arena Self {
+        Compactor::new(target).copy(self)
+    }
+
+    /// Copies the tree under this node into an [`OwnedTree`], which can be serialized.  Returns
+    /// `None` if this type of tree can't be serialized (see [`to_owned_node`](Ast::to_owned_node)).
+    fn to_owned_tree(&'arena self) -> Option<OwnedTree> {
+        let mut writer = OwnedWriter::new();
+        let root = writer.add(self).ok()?;
+        Some(OwnedTree {
+            nodes: writer.finish(),
+            root,
+        })
+    }
+
+    /// Returns this node (without its children) as an [`OwnedNode`], or `None` if this type of
+    /// tree can't be serialized.  By default, trees can't be serialized.
+    fn to_owned_node(&self) -> Option<OwnedNode> {
+        None
+    }
+
+    /// Rebuilds a node from what [`to_owned_node`](Ast::to_owned_node) returned for it, given its
+    /// (rebuilt) children.  Returns `None` if the node isn't valid, or if this type of tree can't
+    /// be serialized.  The children don't have to be checked with
+    /// [`is_valid_child`](Ast::is_valid_child), since that's done by the caller.
+    fn from_owned_node(_node: &OwnedNode, _children: &[&'arena Self]) -> Option<Self> {
+        None
+    }
+
     fn write_tree_view_recursive(
         &'arena self,
         string: &mut String,
@@ -177,6 +502,17 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
         s
     }
 
+    /// Writes a [Graphviz](https://graphviz.org) DOT graph of the tree under this node, with one
+    /// vertex per node (see [`dot::write_dot`]).  If `colored` is `true`, then each vertex is
+    /// the colour that debug highlighting gives its node.
+    fn write_dot(
+        &'arena self,
+        out: &mut impl std::io::Write,
+        colored: bool,
+    ) -> std::io::Result<()> {
+        dot::write_dot(self, out, colored)
+    }
+
     /* AST EDITING FUNCTIONS */
 
     /// Generate an iterator over the possible shorthand [`char`]s that a user could type to replace
@@ -191,8 +527,34 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     /// Generate a new node from a [`char`] that a user typed.  If `c` is an element of
     /// [`get_replace_chars`](ASTSpec::replace_chars), this must return [`Some`] node,
     /// if it isn't, then this should return [`None`].
+    #[allow(clippy::wrong_self_convention)]
     fn from_char(&self, c: char) -> Option<Self>;
 
+    /// Returns whether or not `child` could be the child of this node at a given `index`.  This is
+    /// used to check edits that move whole subtrees around, like unwrapping a node.
+    fn is_valid_child(&self, index: usize, child: &Self) -> bool;
+
+    /// Returns the index of the child that should take this node's place when this node is
+    /// unwrapped, or [`None`] if this node can't be unwrapped.  By default, only nodes with exactly
+    /// one child can be unwrapped.
+    fn unwrap_index(&self) -> Option<usize>
+    where
+        Self: 'arena,
+    {
+        if self.children().len() == 1 {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Allocate a new node (represented by the [`char`] `c`) which contains this node as its only
+    /// child, so that the new node can replace this one in the tree.  This can involve allocating
+    /// extra nodes between the new parent and `self` (e.g. wrapping a JSON value in an object
+    /// requires a field).  This returns [`None`] if `c` doesn't represent a node that could contain
+    /// `self`, or if the new node couldn't take the place of `self`.
+    fn wrap_in(&'arena self, c: char, arena: &'arena Arena<Self>) -> Option<&'arena Self>;
+
     /// Generate an iterator over the possible shorthand [`char`]s that a user could type to insert
     /// other nodes into this one
     fn insert_chars(&self) -> Box<dyn Iterator<Item = char>>;
@@ -201,4 +563,96 @@ pub trait Ast<'arena>: std::fmt::Debug + Clone + Eq + Default + std::hash::Hash
     fn is_insert_char(&self, c: char) -> bool {
         self.insert_chars().any(|x| x == c)
     }
+
+    /// Returns a short description of the node that typing `c` creates (e.g. `"string"`), which
+    /// is shown in the help box.  By default, this is the [`node_kind`](Ast::node_kind) of the
+    /// node made by [`from_char`](Ast::from_char).
+    fn char_description(&self, c: char) -> Option<String> {
+        self.from_char(c)
+            .map(|node| node.node_kind().replace('-', " "))
+    }
+
+    /// Generate a copy of this node with `c` appended to its textual contents (e.g. typing a
+    /// digit whilst a number is selected).  This returns [`None`] if this node has no textual
+    /// contents, or if appending `c` could never produce a valid node.  By default, nodes have no
+    /// textual contents.
+    fn push_char(&self, _c: char) -> Option<Self> {
+        None
+    }
+
+    /// The inverse of [`push_char`](Ast::push_char): generate a copy of this node with the last
+    /// [`char`] of its textual contents removed.  This returns [`None`] if this node has no
+    /// textual contents, or if removing a [`char`] would leave the node empty.
+    fn pop_char(&self) -> Option<Self> {
+        None
+    }
+
+    /// Generate a copy of this node with every occurrence of `pattern` in its textual contents
+    /// replaced with `replacement` (e.g. to rename a string).  This returns [`None`] if this node
+    /// has no textual contents that can be replaced, or if `pattern` doesn't occur in them.  By
+    /// default, nodes have no replaceable contents.
+    fn replace_text(&self, _pattern: &str, _replacement: &str) -> Option<Self> {
+        None
+    }
+
+    /// Returns `true` if the child at `index` is a key which names a value, rather than a value
+    /// itself (e.g. the key of a JSON field).  By default, no children are keys.
+    fn is_key_child(&self, _index: usize) -> bool {
+        false
+    }
+
+    /// Returns `true` if this node is a comment, which [queries](json::query) skip over rather
+    /// than counting as one of its parent's values.  By default, no nodes are comments.
+    fn is_comment(&self) -> bool {
+        false
+    }
+
+    /// If this node only holds comments around a single value (like a JSONC file with comments
+    /// before its root value), returns the index of that value.  [Queries](json::query),
+    /// [schemas](json::schema::Schema) and [patches](json_patch) treat such a node as its value.
+    /// By default, nodes aren't like this.
+    fn document_value(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the textual contents of this node that the user can type into (e.g. the contents
+    /// of a JSON string), or [`None`] if it has none.  By default, nodes have no such contents.
+    fn text(&self) -> Option<&str> {
+        None
+    }
+
+    /// Generate a copy of this node with its [`text`](Ast::text) replaced by `text`.  This
+    /// returns [`None`] if this node has no text to replace.
+    fn with_text(&self, _text: &str) -> Option<Self> {
+        None
+    }
+
+    /// If this node stands in for some text that hasn't been parsed yet (like the arrays and
+    /// objects of a large JSON file), parses that text into nodes in `arena` and returns the
+    /// root of them.  Returns [`None`] for every other node, which is the default.
+    fn parse_unparsed(&self, _arena: &'arena Arena<Self>) -> Option<Result<&'arena Self, String>> {
+        None
+    }
+
+    /// Returns `true` if this node stands in for some text that hasn't been parsed yet (see
+    /// [`parse_unparsed`](Ast::parse_unparsed)).  Nothing inside such a node can be searched or
+    /// replaced until it's parsed.
+    fn is_unparsed(&self) -> bool {
+        false
+    }
+
+    /// Returns the byte offset into this node's rendered text at which the char at byte `index`
+    /// of its [`text`](Ast::text) is drawn (or where a char appended to the text would be, if
+    /// `index` is the length of the text).  This is how the text cursor is placed whilst the
+    /// text is being typed.
+    fn text_offset(&self, _index: usize) -> usize {
+        0
+    }
+
+    /// Checks this node for problems that make the tree invalid (e.g. a JSON object with two
+    /// fields of the same name).  Only this node is checked, not its descendants.  By default,
+    /// nodes have no problems.
+    fn validate(&self) -> Vec<ValidationError> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,352 @@
+//! Trees that own their nodes instead of borrowing them from an [`Arena`], so that they can be
+//! serialized (e.g. to save the editor's session when Sapling exits).
+//!
+//! An [`OwnedTree`] stores its nodes in a list, and each node refers to its children by their
+//! indices in that list.  Children always come before their parents, so a tree can be rebuilt
+//! in one pass over the list (and can't contain cycles).  Nodes which are shared by several
+//! parents (or several trees, with an [`OwnedWriter`]) are only stored once, and are shared again
+//! when the tree is rebuilt.
+
+use super::Ast;
+use crate::arena::Arena;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// One node of an [`OwnedTree`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct OwnedNode {
+    /// The type of the node (e.g. `"array"`), as given by [`Ast::to_owned_node`]
+    pub kind: String,
+    /// The contents of the node that aren't its children (e.g. the text of a number), if it has
+    /// any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The indices of the node's children in the list of nodes, which are all smaller than the
+    /// index of the node itself
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<usize>,
+}
+
+impl OwnedNode {
+    /// Creates an `OwnedNode` which doesn't have its children yet
+    pub fn new(kind: &str, text: Option<String>) -> Self {
+        OwnedNode {
+            kind: kind.to_string(),
+            text,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// A tree whose nodes are stored in a list rather than an [`Arena`] (see [`Ast::to_owned_tree`])
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct OwnedTree {
+    /// The index of the root in `nodes`.  This comes first, since TOML can't have values after
+    /// tables.
+    pub root: usize,
+    pub nodes: Vec<OwnedNode>,
+}
+
+impl OwnedTree {
+    /// Rebuilds the tree, allocating its nodes in `arena`
+    pub fn into_arena<'arena, Node: Ast<'arena>>(
+        &self,
+        arena: &'arena Arena<Node>,
+    ) -> Result<&'arena Node, OwnedTreeError> {
+        OwnedReader::new(&self.nodes, arena)?.get(self.root)
+    }
+}
+
+/// The reasons that an [`OwnedTree`] can't be rebuilt
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum OwnedTreeError {
+    /// The nodes of this type of tree can't be serialized (see [`Ast::to_owned_node`])
+    Unsupported,
+    /// A node (or the root) refers to a node which isn't before it in the list
+    InvalidIndex { index: usize },
+    /// The node at `index` isn't a valid node (e.g. its kind doesn't exist, or it has the wrong
+    /// children)
+    InvalidNode { index: usize, kind: String },
+}
+
+impl std::fmt::Display for OwnedTreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnedTreeError::Unsupported => write!(f, "this language can't be serialized"),
+            OwnedTreeError::InvalidIndex { index } => write!(f, "no node comes before {}", index),
+            OwnedTreeError::InvalidNode { index, kind } => {
+                write!(f, "node {} isn't a valid '{}'", index, kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OwnedTreeError {}
+
+/// Writes trees into one list of [`OwnedNode`]s, so that the nodes the trees share are only
+/// written once
+#[derive(Debug, Clone)]
+pub struct OwnedWriter<'arena, Node: Ast<'arena>> {
+    nodes: Vec<OwnedNode>,
+    /// The number of nodes that didn't need writing (see [`OwnedWriter::with_existing`]), which
+    /// come before the written nodes
+    existing: usize,
+    /// The index of every node that has been written, indexed by its address
+    indices: HashMap<*const Node, usize>,
+    marker: PhantomData<&'arena Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> OwnedWriter<'arena, Node> {
+    /// Creates an `OwnedWriter` which hasn't written any nodes
+    pub fn new() -> Self {
+        Self::with_existing(&[])
+    }
+
+    /// Creates an `OwnedWriter` which treats the nodes in `existing` as already written, at the
+    /// indices `0..existing.len()`.  The nodes it writes start at the index after them, and nodes
+    /// under `existing` are never written.  This is how the [swap file](crate::editor::swap)
+    /// only writes the nodes that an edit created.
+    pub fn with_existing(existing: &[&'arena Node]) -> Self {
+        let mut indices = HashMap::new();
+        for (index, &node) in existing.iter().enumerate() {
+            indices.entry(node as *const Node).or_insert(index);
+        }
+        OwnedWriter {
+            nodes: Vec::new(),
+            existing: existing.len(),
+            indices,
+            marker: PhantomData,
+        }
+    }
+
+    /// Writes every node of the tree under `root` that hasn't already been written, returning
+    /// the index of `root`.  The tree is walked with an explicit stack, so this works on trees of
+    /// any depth.
+    pub fn add(&mut self, root: &'arena Node) -> Result<usize, OwnedTreeError> {
+        // Like compaction, every node is visited once to push its children and then once more to
+        // write it
+        let mut stack = vec![(root, false)];
+        while let Some((node, children_written)) = stack.pop() {
+            if self.indices.contains_key(&(node as *const Node)) {
+                continue;
+            }
+            if !children_written {
+                stack.push((node, true));
+                stack.extend(node.children().iter().rev().map(|&child| (child, false)));
+                continue;
+            }
+            let mut owned = node.to_owned_node().ok_or(OwnedTreeError::Unsupported)?;
+            owned.children = node
+                .children()
+                .iter()
+                .map(|&child| self.indices[&(child as *const Node)])
+                .collect();
+            self.indices
+                .insert(node as *const Node, self.existing + self.nodes.len());
+            self.nodes.push(owned);
+        }
+        Ok(self.indices[&(root as *const Node)])
+    }
+
+    /// Returns every node that has been written (without any existing nodes)
+    pub fn finish(self) -> Vec<OwnedNode> {
+        self.nodes
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Default for OwnedWriter<'arena, Node> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rebuilds every node of a list of [`OwnedNode`]s (which may contain many trees), so that the
+/// trees can be picked out by the indices of their roots
+#[derive(Debug, Clone)]
+pub struct OwnedReader<'arena, Node: Ast<'arena>> {
+    nodes: Vec<&'arena Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> OwnedReader<'arena, Node> {
+    /// Rebuilds every node in `nodes`, allocating them in `arena`.  Every node is checked to be
+    /// a valid node with valid children.
+    pub fn new(nodes: &[OwnedNode], arena: &'arena Arena<Node>) -> Result<Self, OwnedTreeError> {
+        Self::with_existing(Vec::new(), nodes, arena)
+    }
+
+    /// Like [`OwnedReader::new`], but the nodes in `existing` (which have already been built)
+    /// come before the nodes in `nodes`, so that they can be `nodes`' children.  This reads what
+    /// [`OwnedWriter::with_existing`] writes.
+    pub fn with_existing(
+        existing: Vec<&'arena Node>,
+        nodes: &[OwnedNode],
+        arena: &'arena Arena<Node>,
+    ) -> Result<Self, OwnedTreeError> {
+        let mut built = existing;
+        built.reserve(nodes.len());
+        for owned in nodes {
+            let index = built.len();
+            let children = owned
+                .children
+                .iter()
+                .map(|&i| built.get(i).copied())
+                .collect::<Option<Vec<_>>>()
+                .ok_or(OwnedTreeError::InvalidIndex { index })?;
+            let invalid = || OwnedTreeError::InvalidNode {
+                index,
+                kind: owned.kind.clone(),
+            };
+            let node = Node::from_owned_node(owned, &children).ok_or_else(invalid)?;
+            if !children
+                .iter()
+                .enumerate()
+                .all(|(i, child)| node.is_valid_child(i, child))
+            {
+                return Err(invalid());
+            }
+            built.push(arena.alloc(node));
+        }
+        Ok(OwnedReader { nodes: built })
+    }
+
+    /// Returns the node that was at `index` of the list
+    pub fn get(&self, index: usize) -> Result<&'arena Node, OwnedTreeError> {
+        self.nodes
+            .get(index)
+            .copied()
+            .ok_or(OwnedTreeError::InvalidIndex { index })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OwnedNode, OwnedReader, OwnedTree, OwnedTreeError, OwnedWriter};
+    use crate::arena::Arena;
+    use crate::ast::json::{Dialect, JSON};
+    use crate::ast::sexpr::SExpr;
+    use crate::ast::Ast;
+    use crate::testing::{random_json, Bounds, Rng};
+
+    #[test]
+    fn round_trips() {
+        let mut rng = Rng::new(67);
+        for max_nodes in 1..200 {
+            let arena = Arena::new();
+            let bounds = Bounds {
+                max_depth: 6,
+                max_nodes,
+            };
+            let root = random_json(&mut rng, &arena, bounds);
+            let owned = root.to_owned_tree().unwrap();
+            assert_eq!(owned.nodes.len(), root.node_count());
+            let new_arena = Arena::new();
+            let copy: &JSON = owned.into_arena(&new_arena).unwrap();
+            assert_eq!(copy, root);
+            // Serializing and deserializing the owned tree keeps it the same
+            let text = toml::to_string(&owned).unwrap();
+            assert_eq!(toml::from_str::<OwnedTree>(&text).unwrap(), owned);
+        }
+    }
+
+    #[test]
+    fn comments_and_sharing() {
+        let arena = Arena::new();
+        let text = "[/* a */ {\"b\": [1.50, \"\\n\"]}, // c\n null]";
+        let root = JSON::parse_dialect(text, &arena, Dialect::JSONC).unwrap();
+        let shared = arena.alloc(JSON::Array(vec![root, root]));
+        let other = arena.alloc(JSON::Array(vec![root.children()[1]]));
+        let mut writer = OwnedWriter::new();
+        let indices = [writer.add(shared).unwrap(), writer.add(other).unwrap()];
+        let nodes = writer.finish();
+        // Only `shared` and `other` were written on top of the nodes of `root`
+        assert_eq!(nodes.len(), root.node_count() + 2);
+
+        let new_arena = Arena::new();
+        let reader = OwnedReader::new(&nodes, &new_arena).unwrap();
+        let [new_shared, new_other] = indices.map(|i| reader.get(i).unwrap());
+        assert_eq!((new_shared, new_other), (shared, other));
+        assert!(std::ptr::eq(
+            new_shared.children()[0],
+            new_shared.children()[1]
+        ));
+        assert!(std::ptr::eq(
+            new_shared.children()[0].children()[1],
+            new_other.children()[0]
+        ));
+    }
+
+    #[test]
+    fn deep_trees() {
+        let arena = Arena::new();
+        let mut node = arena.alloc(JSON::Null);
+        for _ in 0..100_000 {
+            node = arena.alloc(JSON::Array(vec![node]));
+        }
+        let owned = node.to_owned_tree().unwrap();
+        let new_arena = Arena::new();
+        let copy: &JSON = owned.into_arena(&new_arena).unwrap();
+        assert_eq!(copy.depth(), 100_000);
+    }
+
+    #[test]
+    fn invalid_trees() {
+        let node = |kind: &str, text: Option<&str>, children: Vec<usize>| OwnedNode {
+            kind: kind.to_string(),
+            text: text.map(str::to_string),
+            children,
+        };
+        let build = |nodes: Vec<OwnedNode>, root| {
+            let tree = OwnedTree { nodes, root };
+            tree.into_arena::<JSON>(&Arena::new()).map(|_| ())
+        };
+        let invalid = |index, kind: &str| {
+            Err(OwnedTreeError::InvalidNode {
+                index,
+                kind: kind.to_string(),
+            })
+        };
+        let null = || node("null", None, vec![]);
+        // Children must come before their parents
+        assert_eq!(
+            build(vec![node("array", None, vec![1]), null()], 0),
+            Err(OwnedTreeError::InvalidIndex { index: 0 })
+        );
+        assert_eq!(
+            build(vec![null()], 1),
+            Err(OwnedTreeError::InvalidIndex { index: 1 })
+        );
+        assert_eq!(
+            build(vec![node("nothing", None, vec![])], 0),
+            invalid(0, "nothing")
+        );
+        assert_eq!(build(vec![node("number", Some("1."), vec![])], 0), Ok(()));
+        assert_eq!(
+            build(vec![node("number", Some("x"), vec![])], 0),
+            invalid(0, "number")
+        );
+        assert_eq!(
+            build(vec![node("string", None, vec![])], 0),
+            invalid(0, "string")
+        );
+        // Fields have a string key and one value, and can only be in objects
+        let field = |children| {
+            vec![
+                null(),
+                node("string", Some("a"), vec![]),
+                node("field", None, children),
+            ]
+        };
+        assert_eq!(build(field(vec![1, 0]), 2), Ok(()));
+        assert_eq!(build(field(vec![0, 1]), 2), invalid(2, "field"));
+        assert_eq!(build(field(vec![1]), 2), invalid(2, "field"));
+        let mut nodes = field(vec![1, 0]);
+        nodes.push(node("array", None, vec![2]));
+        assert_eq!(build(nodes, 3), invalid(3, "array"));
+
+        // Trees that can't be serialized (yet) say so
+        let arena = Arena::new();
+        let sexpr = SExpr::parse("(a b)", &arena).unwrap();
+        assert_eq!(sexpr.to_owned_tree(), None);
+    }
+}
@@ -0,0 +1,1311 @@
+//! The S-expressions that Lisp-like languages (e.g. Common Lisp and Scheme) are written in.
+
+use super::display_token::{
+    DisplayToken, FormatKind, IndentKind, RecTok, RenderStyle, SyntaxCategory,
+};
+use super::json::{number_validity, NumberValidity};
+use super::size::{self, Size};
+use super::{Ast, DeleteError, InsertError};
+use crate::arena::{Arena, HeapSize};
+
+/// The ways that S-expressions can be formatted
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SExprFormat {
+    /// Every expression is on one line, with a single space between the items of every list
+    Compact,
+    /// Lists that contain other (non-empty) lists are spread over many lines, with every item
+    /// after the first on its own line and indented by `indent`.  For example:
+    /// ```text
+    /// (define
+    ///   (square x)
+    ///   (* x x))
+    /// ```
+    Pretty { indent: IndentKind },
+    /// Like `Pretty`, except that lists are kept on one line if they fit within `max_width`
+    /// columns (and spread over many lines if they don't).  Like JSON's
+    /// [`Fit`](super::json::JSONFormat::Fit), whether a list fits doesn't depend on how far it's
+    /// indented.
+    Fit {
+        indent: IndentKind,
+        max_width: usize,
+    },
+}
+
+impl RenderStyle for SExprFormat {
+    fn indent(&self) -> IndentKind {
+        match self {
+            SExprFormat::Compact => IndentKind::Spaces(0),
+            SExprFormat::Pretty { indent } | SExprFormat::Fit { indent, .. } => *indent,
+        }
+    }
+
+    fn set_indent(&mut self, new_indent: IndentKind) {
+        if let SExprFormat::Pretty { indent } | SExprFormat::Fit { indent, .. } = self {
+            *indent = new_indent;
+        }
+    }
+
+    fn set_kind(&mut self, kind: FormatKind) {
+        let indent = match *self {
+            SExprFormat::Pretty { indent } | SExprFormat::Fit { indent, .. } => indent,
+            SExprFormat::Compact => IndentKind::default(),
+        };
+        *self = match kind {
+            FormatKind::Compact => SExprFormat::Compact,
+            FormatKind::Pretty => SExprFormat::Pretty { indent },
+            FormatKind::Fit { max_width } => SExprFormat::Fit { indent, max_width },
+        };
+    }
+}
+
+const CHAR_LIST: char = '(';
+const CHAR_SYMBOL: char = 's';
+const CHAR_STRING: char = '"';
+const CHAR_COMMENT: char = ';';
+
+/// The category used for symbols
+const CATEGORY_IDENT: SyntaxCategory = SyntaxCategory::Ident;
+/// The category used for strings and numbers
+const CATEGORY_LITERAL: SyntaxCategory = SyntaxCategory::Literal;
+/// The category used for parentheses
+const CATEGORY_DEFAULT: SyntaxCategory = SyntaxCategory::Default;
+/// The category used for escape sequences inside strings and symbols
+const CATEGORY_SPECIAL: SyntaxCategory = SyntaxCategory::Special;
+/// The category used for comments
+const CATEGORY_COMMENT: SyntaxCategory = SyntaxCategory::Comment;
+/// The category used for numbers that are only partially typed (e.g. `-` or `1e`)
+const CATEGORY_ERROR: SyntaxCategory = SyntaxCategory::Error;
+
+/// The chars which end a symbol that isn't written between `|`s
+const DELIMITERS: &[char] = &['(', ')', '"', ';', '|', '\\'];
+
+/// Returns `true` if `text` is a complete number.  S-expressions use the same syntax for numbers
+/// as JSON, so anything else (like `1/2` or `+5`) is read as a symbol.
+fn is_number(text: &str) -> bool {
+    number_validity(text) == NumberValidity::Complete
+}
+
+/// Returns `true` if a symbol has to be written between `|`s, because it would otherwise be read
+/// as something other than a single symbol (e.g. if it's empty, looks like a number or contains
+/// whitespace)
+fn needs_bars(symbol: &str) -> bool {
+    symbol.is_empty()
+        || is_number(symbol)
+        || symbol
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || DELIMITERS.contains(&c))
+}
+
+/// Returns the escape sequence that a char has to be written as when it's between `quote`s (which
+/// are `"` for strings and `|` for symbols), or `None` if it can be written as it is.  Control
+/// chars are escaped so that they can't garble the screen, using R7RS's `\xXX;` for the ones
+/// without a name.
+fn escape_char(c: char, quote: char) -> Option<String> {
+    match c {
+        '\\' => Some(r"\\".to_string()),
+        '\n' => Some(r"\n".to_string()),
+        '\t' => Some(r"\t".to_string()),
+        _ if c == quote => Some(format!("\\{}", quote)),
+        _ if c.is_control() => Some(format!("\\x{:x};", c as u32)),
+        _ => None,
+    }
+}
+
+/// Escapes some text so that it can be written between `quote`s
+fn escape(text: &str, quote: char) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match escape_char(c, quote) {
+            Some(escape) => escaped.push_str(&escape),
+            None => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns how a symbol is written: as it is, or between `|`s if it has to be
+fn symbol_text(symbol: &str) -> String {
+    if needs_bars(symbol) {
+        format!("|{}|", escape(symbol, '|'))
+    } else {
+        symbol.to_string()
+    }
+}
+
+/// Generates the tokens of some text between `quote`s, where the escape sequences are separate
+/// tokens so that they can be highlighted differently to the rest of the text
+fn quoted_tokens<'arena>(
+    text: &str,
+    quote: char,
+    category: SyntaxCategory,
+) -> Vec<RecTok<'arena, SExpr<'arena>>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::from(quote);
+    for c in text.chars() {
+        match escape_char(c, quote) {
+            Some(escape) => {
+                if !literal.is_empty() {
+                    let text = std::mem::take(&mut literal);
+                    tokens.push(RecTok::Tok(DisplayToken::Text(text.into(), category)));
+                }
+                tokens.push(RecTok::Tok(DisplayToken::Text(
+                    escape.into(),
+                    CATEGORY_SPECIAL,
+                )));
+            }
+            None => literal.push(c),
+        }
+    }
+    literal.push(quote);
+    tokens.push(RecTok::Tok(DisplayToken::Text(literal.into(), category)));
+    tokens
+}
+
+/// The sapling representation of an S-expression
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum SExpr<'arena> {
+    /// The top level of a file, which is a sequence of expressions.  This is always the root of
+    /// the tree, and can't be inside anything else.
+    Program(Vec<&'arena SExpr<'arena>>),
+    /// A list of any number of expressions.  Corresponds to a string `(<e1> <e2> ...)`.
+    List(Vec<&'arena SExpr<'arena>>),
+    /// A symbol (like `define` or `+`).  Symbols that couldn't otherwise be read back as the same
+    /// symbol (e.g. ones containing spaces) are written between `|`s, like `|hello world|`.
+    Symbol(String),
+    /// A string, which is written between `"`s
+    Str(String),
+    /// A number.  Like [`JSON::Number`](super::json::JSON::Number), this is stored as the text
+    /// that represents it, which must always be either a complete or an incomplete number.
+    Number(String),
+    /// A `;` comment, which contains everything after the `;` up to the end of the line.  Like
+    /// every other expression, comments can go anywhere in a list or program.
+    Comment(String),
+}
+
+impl SExpr<'_> {
+    /// Return an iterator over all the possible chars that could represent S-expressions.  Any
+    /// digit will create a number starting with that digit.
+    fn all_expression_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new(
+            [CHAR_LIST, CHAR_SYMBOL, CHAR_STRING, CHAR_COMMENT]
+                .iter()
+                .copied()
+                .chain('0'..='9'),
+        )
+    }
+
+    /// Returns `true` if this is a list with at least one item
+    fn is_non_empty_list(&self) -> bool {
+        matches!(self, SExpr::List(items) if !items.is_empty())
+    }
+
+    /// Returns `true` if this is a comment, which always ends its line
+    fn is_comment(&self) -> bool {
+        matches!(self, SExpr::Comment(_))
+    }
+
+    /// Returns `true` if this is a list which will be spread over many lines when
+    /// rendered in `format_style`
+    fn is_broken(&self, format_style: &SExprFormat) -> bool {
+        let items = match self {
+            SExpr::List(items) if !items.is_empty() => items,
+            _ => return false,
+        };
+        match format_style {
+            SExprFormat::Compact => false,
+            // Breaking lists that contain comments keeps every item after a comment indented
+            SExprFormat::Pretty { .. } => items
+                .iter()
+                .any(|item| item.is_non_empty_list() || item.is_comment()),
+            SExprFormat::Fit { max_width, .. } => self.inline_width(*max_width).is_none(),
+        }
+    }
+
+    /// Returns how many columns this expression would occupy if it was written on one line, or
+    /// `None` if it can't be written on one line within `limit` columns (which is always true of
+    /// comments, since they end their line).  This stops measuring as soon as the limit is
+    /// exceeded, so that the whole tree isn't measured for every list.
+    fn inline_width(&self, limit: usize) -> Option<usize> {
+        let mut width = match self {
+            // Every item but the first has a space before it
+            SExpr::List(items) => 2 + items.len().saturating_sub(1),
+            SExpr::Program(_) | SExpr::Comment(_) => return None,
+            // Atoms are always on one line
+            atom => {
+                let width = atom
+                    .size_from_children(&SExprFormat::Compact, &[])
+                    .max_width();
+                return Some(width).filter(|&width| width <= limit);
+            }
+        };
+        for child in self.children() {
+            width += child.inline_width(limit.checked_sub(width)?)?;
+        }
+        Some(width).filter(|&width| width <= limit)
+    }
+
+    /// Returns `true` if the item of `items` at `index` should start on a new line, which is true
+    /// of every item after a comment and, if the list is broken, every item except a leading atom
+    /// (which stays next to the opening parenthesis)
+    fn starts_line(items: &[&SExpr], index: usize, is_broken: bool) -> bool {
+        match index {
+            0 => is_broken && matches!(items[0], SExpr::List(_)),
+            _ => is_broken || items[index - 1].is_comment(),
+        }
+    }
+}
+
+impl Default for SExpr<'_> {
+    fn default() -> SExpr<'static> {
+        SExpr::Program(vec![])
+    }
+}
+
+impl HeapSize for SExpr<'_> {
+    fn heap_size(&self) -> usize {
+        match self {
+            SExpr::Program(children) | SExpr::List(children) => children.heap_size(),
+            SExpr::Symbol(text) | SExpr::Str(text) | SExpr::Number(text) | SExpr::Comment(text) => {
+                text.heap_size()
+            }
+        }
+    }
+}
+
+impl<'arena> Ast<'arena> for SExpr<'arena> {
+    type FormatStyle = SExprFormat;
+
+    const TOKEN_CATEGORIES: &'static [SyntaxCategory] = &[
+        CATEGORY_IDENT,
+        CATEGORY_LITERAL,
+        CATEGORY_DEFAULT,
+        CATEGORY_SPECIAL,
+        CATEGORY_COMMENT,
+        CATEGORY_ERROR,
+    ];
+
+    /* FORMATTING FUNCTIONS */
+
+    fn display_tokens_rec(
+        &'arena self,
+        format_style: &Self::FormatStyle,
+    ) -> Vec<RecTok<'arena, Self>> {
+        let text =
+            |text: String, category| vec![RecTok::Tok(DisplayToken::Text(text.into(), category))];
+        let items = match self {
+            SExpr::Symbol(symbol) if needs_bars(symbol) => {
+                return quoted_tokens(symbol, '|', CATEGORY_IDENT)
+            }
+            SExpr::Symbol(symbol) => return text(symbol.clone(), CATEGORY_IDENT),
+            SExpr::Str(string) => return quoted_tokens(string, '"', CATEGORY_LITERAL),
+            SExpr::Number(number) => {
+                let category = match number_validity(number) {
+                    NumberValidity::Complete => CATEGORY_LITERAL,
+                    _ => CATEGORY_ERROR,
+                };
+                return text(number.clone(), category);
+            }
+            SExpr::Comment(comment) => return text(format!(";{}", comment), CATEGORY_COMMENT),
+            SExpr::Program(expressions) => {
+                // Top-level expressions go on lines of their own, unless everything is compact
+                let mut tokens = Vec::with_capacity(2 * expressions.len());
+                for (i, expression) in expressions.iter().enumerate() {
+                    if i > 0 {
+                        if expressions[i - 1].is_comment()
+                            || !matches!(format_style, SExprFormat::Compact)
+                        {
+                            tokens.push(RecTok::Tok(DisplayToken::Newline));
+                        } else {
+                            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                        }
+                    }
+                    tokens.push(RecTok::Child(*expression));
+                }
+                return tokens;
+            }
+            SExpr::List(items) => items,
+        };
+
+        let is_broken = self.is_broken(format_style);
+        let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(5 + 2 * items.len());
+        tokens.push(RecTok::Tok(DisplayToken::Text(
+            "(".into(),
+            CATEGORY_DEFAULT,
+        )));
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Indent));
+        }
+        for (i, item) in items.iter().enumerate() {
+            if Self::starts_line(items, i, is_broken) {
+                tokens.push(RecTok::Tok(DisplayToken::Newline));
+            } else if i > 0 {
+                tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+            }
+            tokens.push(RecTok::Child(*item));
+        }
+        // Lisp puts closing parentheses at the end of the last line, unless that line ends in a
+        // comment
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Dedent));
+        }
+        if items.last().is_some_and(|item| item.is_comment()) {
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        tokens.push(RecTok::Tok(DisplayToken::Text(
+            ")".into(),
+            CATEGORY_DEFAULT,
+        )));
+        tokens
+    }
+
+    fn size_from_children(&self, format_style: &Self::FormatStyle, child_sizes: &[Size]) -> Size {
+        let items = match self {
+            SExpr::Symbol(symbol) => return Size::from(symbol_text(symbol).as_str()),
+            // Every escape sequence is printable ASCII, so escaped strings are always one line
+            SExpr::Str(string) => {
+                return Size::line(1) + Size::from(escape(string, '"').as_str()) + Size::line(1)
+            }
+            SExpr::Number(number) => return Size::from(number.as_str()),
+            SExpr::Comment(comment) => return Size::line(1) + Size::from(comment.as_str()),
+            SExpr::Program(expressions) => {
+                let is_compact = matches!(format_style, SExprFormat::Compact);
+                let mut size = size::ZERO;
+                for (i, &child_size) in child_sizes.iter().enumerate() {
+                    if i > 0 {
+                        if expressions[i - 1].is_comment() || !is_compact {
+                            size += Size::newline();
+                        } else {
+                            size += Size::line(1);
+                        }
+                    }
+                    size += child_size;
+                }
+                return size;
+            }
+            SExpr::List(items) => items,
+        };
+
+        let is_broken = self.is_broken(format_style);
+        // How far each item is indented when it's on a line of its own, counting tabs as a
+        // single column (like `Size::from`)
+        let indent_columns = if is_broken {
+            format_style.indent().columns(1)
+        } else {
+            0
+        };
+        let mut size = Size::line(1);
+        for (i, &child_size) in child_sizes.iter().enumerate() {
+            if Self::starts_line(items, i, is_broken) {
+                size += Size::newline() + child_size.indented(indent_columns);
+            } else if i > 0 {
+                size += Size::line(1) + child_size;
+            } else {
+                size += child_size;
+            }
+        }
+        if items.last().is_some_and(|item| item.is_comment()) {
+            size += Size::newline();
+        }
+        size + Size::line(1)
+    }
+
+    /* DEBUG VIEW FUNCTIONS */
+
+    fn children<'s>(&'s self) -> &'s [&'arena SExpr<'arena>] {
+        match self {
+            SExpr::Program(items) | SExpr::List(items) => items,
+            SExpr::Symbol(_) | SExpr::Str(_) | SExpr::Number(_) | SExpr::Comment(_) => &[],
+        }
+    }
+
+    fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena SExpr<'arena>] {
+        match self {
+            SExpr::Program(items) | SExpr::List(items) => items,
+            SExpr::Symbol(_) | SExpr::Str(_) | SExpr::Number(_) | SExpr::Comment(_) => &mut [],
+        }
+    }
+
+    fn insert_child(
+        &mut self,
+        new_node: &'arena Self,
+        _arena: &'arena Arena<Self>,
+        index: usize,
+    ) -> Result<(), InsertError> {
+        let len = self.children().len();
+        if index > len {
+            return Err(InsertError::IndexOutOfRange { len, index });
+        }
+        if !self.is_valid_child(index, new_node) {
+            return Err(match self {
+                SExpr::Program(_) | SExpr::List(_) => InsertError::InvalidChild {
+                    parent_kind: self.node_kind(),
+                    child_kind: new_node.node_kind(),
+                },
+                _ => InsertError::TooManyChildren {
+                    kind: self.node_kind(),
+                    max_children: 0,
+                },
+            });
+        }
+        match self {
+            SExpr::Program(items) | SExpr::List(items) => items.insert(index, new_node),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn delete_children(&mut self, range: std::ops::Range<usize>) -> Result<(), DeleteError> {
+        match self {
+            // Lists and programs can have any number of children, so we only need to check that
+            // the children exist
+            SExpr::Program(items) | SExpr::List(items) => {
+                if range.end <= items.len() {
+                    items.drain(range);
+                    Ok(())
+                } else {
+                    Err(DeleteError::IndexOutOfRange {
+                        len: items.len(),
+                        index: range.end - 1,
+                    })
+                }
+            }
+            // Atoms have no children, so there's nothing that could be selected for deletion
+            SExpr::Symbol(_) | SExpr::Str(_) | SExpr::Number(_) | SExpr::Comment(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match self {
+            SExpr::Symbol(symbol) => symbol_text(symbol),
+            SExpr::Str(string) => format!("\"{}\"", escape(string, '"')),
+            SExpr::Number(number) => number.clone(),
+            _ => self.node_kind().to_string(),
+        }
+    }
+
+    fn node_kind(&self) -> &'static str {
+        match self {
+            SExpr::Program(_) => "program",
+            SExpr::List(_) => "list",
+            SExpr::Symbol(_) => "symbol",
+            SExpr::Str(_) => "string",
+            SExpr::Number(_) => "number",
+            SExpr::Comment(_) => "comment",
+        }
+    }
+
+    fn fold_summary(&self) -> String {
+        match self {
+            SExpr::List(items) => {
+                let count = items.iter().filter(|item| !item.is_comment()).count();
+                match count {
+                    1 => "(…) (1 item)".to_string(),
+                    _ => format!("(…) ({} items)", count),
+                }
+            }
+            _ => self.display_name(),
+        }
+    }
+
+    /* AST EDITING FUNCTIONS */
+
+    fn replace_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            // The program is always the root, so it can't be replaced by anything else
+            SExpr::Program(_) => Box::new(std::iter::empty()),
+            _ => Self::all_expression_chars(),
+        }
+    }
+
+    fn from_char(&self, c: char) -> Option<Self> {
+        match c {
+            CHAR_LIST => Some(SExpr::List(vec![])),
+            CHAR_SYMBOL => Some(SExpr::Symbol("".to_string())),
+            CHAR_STRING => Some(SExpr::Str("".to_string())),
+            CHAR_COMMENT => Some(SExpr::Comment("".to_string())),
+            '0'..='9' => Some(SExpr::Number(c.to_string())),
+            _ => None,
+        }
+    }
+
+    fn is_valid_child(&self, _index: usize, child: &Self) -> bool {
+        match self {
+            // Anything but a program can go anywhere in a list or program
+            SExpr::Program(_) | SExpr::List(_) => !matches!(child, SExpr::Program(_)),
+            SExpr::Symbol(_) | SExpr::Str(_) | SExpr::Number(_) | SExpr::Comment(_) => false,
+        }
+    }
+
+    fn unwrap_index(&self) -> Option<usize> {
+        match self {
+            // The program must stay as the root
+            SExpr::List(items) if items.len() == 1 => Some(0),
+            _ => None,
+        }
+    }
+
+    fn wrap_in(&'arena self, c: char, arena: &'arena Arena<Self>) -> Option<&'arena Self> {
+        match (self, c) {
+            (SExpr::Program(_), _) => None,
+            (_, CHAR_LIST) => Some(arena.alloc(SExpr::List(vec![self]))),
+            _ => None,
+        }
+    }
+
+    fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            SExpr::Program(_) | SExpr::List(_) => Self::all_expression_chars(),
+            SExpr::Symbol(_) | SExpr::Str(_) | SExpr::Number(_) | SExpr::Comment(_) => {
+                Box::new(std::iter::empty())
+            }
+        }
+    }
+
+    fn push_char(&self, c: char) -> Option<Self> {
+        let number = match self {
+            SExpr::Number(number) => number,
+            _ => return None,
+        };
+        let new_number = match (c, number.chars().last()) {
+            // Typing '-' directly after an exponent sets the sign of the exponent, and otherwise
+            // toggles the sign of the whole number
+            ('-', Some('e')) | ('-', Some('E')) => format!("{}-", number),
+            ('-', _) => match number.strip_prefix('-') {
+                Some(positive) => positive.to_string(),
+                None => format!("-{}", number),
+            },
+            _ => format!("{}{}", number, c),
+        };
+        match number_validity(&new_number) {
+            NumberValidity::Invalid => None,
+            _ => Some(SExpr::Number(new_number)),
+        }
+    }
+
+    fn pop_char(&self) -> Option<Self> {
+        match self {
+            SExpr::Number(number) if number.chars().count() > 1 => {
+                let mut new_number = number.clone();
+                new_number.pop();
+                Some(SExpr::Number(new_number))
+            }
+            _ => None,
+        }
+    }
+
+    fn replace_text(&self, pattern: &str, replacement: &str) -> Option<Self> {
+        let text = self.text().filter(|_| !self.is_comment())?;
+        if pattern.is_empty() || !text.contains(pattern) {
+            return None;
+        }
+        self.with_text(&text.replace(pattern, replacement))
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            SExpr::Symbol(text) | SExpr::Str(text) | SExpr::Comment(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn with_text(&self, text: &str) -> Option<Self> {
+        match self {
+            SExpr::Symbol(_) => Some(SExpr::Symbol(text.to_string())),
+            SExpr::Str(_) => Some(SExpr::Str(text.to_string())),
+            // A comment can't contain the newline that would end it
+            SExpr::Comment(_) => Some(SExpr::Comment(text.replace('\n', " "))),
+            _ => None,
+        }
+    }
+
+    fn text_offset(&self, index: usize) -> usize {
+        match self {
+            // Skip the opening quote or `|`, and the extra chars of every escape sequence
+            SExpr::Str(string) => 1 + escape(&string[..index], '"').len(),
+            SExpr::Symbol(symbol) if needs_bars(symbol) => 1 + escape(&symbol[..index], '|').len(),
+            SExpr::Symbol(_) => index,
+            SExpr::Comment(_) => 1 + index,
+            _ => 0,
+        }
+    }
+}
+
+/* ===== PARSING ===== */
+
+/// The maximum depth of nested lists that [`SExpr::parse`] will accept, so that a pathological
+/// input like `((((((...` can't overflow the stack
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// The different ways that parsing S-expressions can fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// We expected to find some specific thing, but found either a different char or the end of
+    /// the input (if `found` is `None`)
+    Expected {
+        expected: &'static str,
+        found: Option<char>,
+    },
+    /// A backslash in a string or symbol was followed by a char that isn't a valid escape
+    InvalidEscape(char),
+    /// A `\x` escape was malformed, or described an invalid codepoint
+    InvalidHexEscape,
+    /// The input contains lists nested more than [`MAX_NESTING_DEPTH`] levels deep
+    TooDeeplyNested,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Expected {
+                expected,
+                found: Some(c),
+            } => write!(f, "expected {}, found {:?}", expected, c),
+            ParseErrorKind::Expected {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of input", expected),
+            ParseErrorKind::InvalidEscape(c) => write!(f, "invalid escape sequence '\\{}'", c),
+            ParseErrorKind::InvalidHexEscape => write!(f, "invalid hex escape sequence"),
+            ParseErrorKind::TooDeeplyNested => {
+                write!(
+                    f,
+                    "lists are nested more than {} levels deep",
+                    MAX_NESTING_DEPTH
+                )
+            }
+        }
+    }
+}
+
+/// An error generated when parsing S-expressions, along with the location of the error.  Both
+/// `line` and `column` start from 1, and `column` is measured in [`char`]s.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A recursive-descent S-expression parser, which allocates the nodes it parses into an
+/// [`Arena`].
+struct Parser<'t, 'arena> {
+    arena: &'arena Arena<SExpr<'arena>>,
+    chars: std::iter::Peekable<std::str::Chars<'t>>,
+    /// The line of the next char to be consumed
+    line: usize,
+    /// The column of the next char to be consumed
+    column: usize,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    fn new(text: &'t str, arena: &'arena Arena<SExpr<'arena>>) -> Self {
+        Parser {
+            arena,
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Creates an error of a given kind at the current position
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    /// Creates an error saying that we expected something other than the next char
+    fn expected(&mut self, expected: &'static str) -> ParseError {
+        let found = self.peek();
+        self.error(ParseErrorKind::Expected { expected, found })
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    /// Consumes the next char, keeping track of the position in the text
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    /// Parses every expression up to the end of the text, or the `)` that ends a list
+    fn parse_items(&mut self, depth: usize) -> Result<Vec<&'arena SExpr<'arena>>, ParseError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some(')') => return Ok(items),
+                Some(_) => {
+                    let item = self.parse_expression(depth)?;
+                    items.push(self.arena.alloc(item));
+                }
+            }
+        }
+    }
+
+    /// Parses an entire file, which is any number of expressions
+    fn parse_program(&mut self) -> Result<&'arena SExpr<'arena>, ParseError> {
+        let expressions = self.parse_items(0)?;
+        match self.peek() {
+            None => Ok(self.arena.alloc(SExpr::Program(expressions))),
+            Some(_) => Err(self.expected("an expression")),
+        }
+    }
+
+    /// Parses a single expression, which must start at the next char
+    fn parse_expression(&mut self, depth: usize) -> Result<SExpr<'arena>, ParseError> {
+        match self.peek() {
+            Some('(') => {
+                if depth >= MAX_NESTING_DEPTH {
+                    return Err(self.error(ParseErrorKind::TooDeeplyNested));
+                }
+                self.next();
+                let items = self.parse_items(depth + 1)?;
+                match self.next() {
+                    Some(')') => Ok(SExpr::List(items)),
+                    _ => Err(self.expected("')'")),
+                }
+            }
+            Some('"') => Ok(SExpr::Str(self.parse_quoted('"')?)),
+            Some('|') => Ok(SExpr::Symbol(self.parse_quoted('|')?)),
+            Some(';') => {
+                self.next();
+                let mut comment = String::new();
+                while let Some(c) = self.peek().filter(|&c| c != '\n') {
+                    comment.push(c);
+                    self.next();
+                }
+                // Don't keep the '\r' of a '\r\n' line ending
+                if comment.ends_with('\r') {
+                    comment.pop();
+                }
+                Ok(SExpr::Comment(comment))
+            }
+            _ => {
+                let mut atom = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|&c| !c.is_whitespace() && !DELIMITERS.contains(&c))
+                {
+                    atom.push(c);
+                    self.next();
+                }
+                if atom.is_empty() {
+                    Err(self.expected("an expression"))
+                } else if is_number(&atom) {
+                    Ok(SExpr::Number(atom))
+                } else {
+                    Ok(SExpr::Symbol(atom))
+                }
+            }
+        }
+    }
+
+    /// Parses some text between `quote`s (a string or a `|symbol|`), including the quotes
+    fn parse_quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        self.next();
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\\') => text.push(self.parse_escape(quote)?),
+                Some(c) if c == quote => return Ok(text),
+                Some(c) => text.push(c),
+                None => {
+                    return Err(self.expected(if quote == '"' { "'\"'" } else { "'|'" }));
+                }
+            }
+        }
+    }
+
+    /// Parses the rest of an escape sequence, after the `\`
+    fn parse_escape(&mut self, quote: char) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('x') => {
+                self.next();
+                return self.parse_hex_escape();
+            }
+            Some(c) if c == quote => c,
+            Some(c) => return Err(self.error(ParseErrorKind::InvalidEscape(c))),
+            None => return Err(self.expected("an escape sequence")),
+        };
+        self.next();
+        Ok(c)
+    }
+
+    /// Parses the hex digits and `;` of a `\x41;` escape sequence
+    fn parse_hex_escape(&mut self) -> Result<char, ParseError> {
+        let mut digits = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_hexdigit) {
+            digits.push(c);
+            self.next();
+        }
+        if self.next() != Some(';') {
+            return Err(self.error(ParseErrorKind::InvalidHexEscape));
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(|| self.error(ParseErrorKind::InvalidHexEscape))
+    }
+}
+
+impl<'arena> SExpr<'arena> {
+    /// Parses the text of a file of S-expressions into a [`Program`](SExpr::Program), allocating
+    /// all the nodes into a given [`Arena`] and returning a reference to the root node.
+    pub fn parse(
+        text: &str,
+        arena: &'arena Arena<SExpr<'arena>>,
+    ) -> Result<&'arena SExpr<'arena>, ParseError> {
+        Parser::new(text, arena).parse_program()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::display_token::{
+        DisplayToken, FormatKind, IndentKind, RenderStyle, SyntaxCategory,
+    };
+    use super::super::size::Size;
+    use super::super::test_json::Rng;
+    use super::{ParseError, ParseErrorKind, SExpr, SExprFormat, MAX_NESTING_DEPTH};
+    use crate::arena::Arena;
+    use crate::ast::{Ast, DeleteError, InsertError};
+
+    const PRETTY: SExprFormat = SExprFormat::Pretty {
+        indent: IndentKind::Spaces(2),
+    };
+
+    /// Parses some text, and returns its only top-level expression
+    fn parse_one<'arena>(text: &str, arena: &'arena Arena<SExpr<'arena>>) -> &'arena SExpr<'arena> {
+        let program = SExpr::parse(text, arena).unwrap();
+        assert_eq!(program.children().len(), 1);
+        program.children()[0]
+    }
+
+    #[test]
+    fn to_text() {
+        let arena = Arena::new();
+        let root = SExpr::parse(
+            "(define (square x) (* x x))\n; done\n(display \"hi\")",
+            &arena,
+        )
+        .unwrap();
+        assert_eq!(
+            root.to_text(&SExprFormat::Compact),
+            "(define (square x) (* x x)) ; done\n(display \"hi\")"
+        );
+        assert_eq!(
+            root.to_text(&PRETTY),
+            "(define\n  (square x)\n  (* x x))\n; done\n(display \"hi\")"
+        );
+        // A list at the start of a broken list goes on its own line
+        let lambda = parse_one("((lambda (x) x) 1)", &arena);
+        assert_eq!(
+            lambda.to_text(&PRETTY),
+            "(\n  (lambda\n    (x)\n    x)\n  1)"
+        );
+        // Comments always end their line, even when the list isn't broken
+        let comment = parse_one("(a ; note\nb)", &arena);
+        assert_eq!(comment.to_text(&SExprFormat::Compact), "(a ; note\nb)");
+        assert_eq!(comment.to_text(&PRETTY), "(a\n  ; note\n  b)");
+        let last_comment = parse_one("(a ; note\n)", &arena);
+        assert_eq!(last_comment.to_text(&SExprFormat::Compact), "(a ; note\n)");
+        assert_eq!(last_comment.to_text(&PRETTY), "(a\n  ; note\n)");
+        // Tabs are only used by broken lists
+        let tabs = SExprFormat::Pretty {
+            indent: IndentKind::Tabs,
+        };
+        assert_eq!(
+            parse_one("(a (b) c)", &arena).to_text(&tabs),
+            "(a\n\t(b)\n\tc)"
+        );
+        assert_eq!(parse_one("(a b c)", &arena).to_text(&tabs), "(a b c)");
+        assert_eq!(SExpr::default().to_text(&PRETTY), "");
+    }
+
+    #[test]
+    fn atom_text() {
+        for (atom, text) in &[
+            (SExpr::Symbol("lambda".to_string()), "lambda"),
+            (SExpr::Symbol("hello world".to_string()), "|hello world|"),
+            (SExpr::Symbol("".to_string()), "||"),
+            (SExpr::Symbol("12".to_string()), "|12|"),
+            (SExpr::Symbol("a|b\\".to_string()), r"|a\|b\\|"),
+            (SExpr::Str("say \"hi\"\n".to_string()), r#""say \"hi\"\n""#),
+            (SExpr::Str("|\t\u{1}".to_string()), r#""|\t\x1;""#),
+            (SExpr::Number("-1.5e3".to_string()), "-1.5e3"),
+            (SExpr::Comment(" note".to_string()), "; note"),
+        ] {
+            assert_eq!(atom.to_text(&SExprFormat::Compact), *text);
+            assert_eq!(atom.size(&SExprFormat::Compact), Size::from(*text));
+        }
+        assert_eq!(SExpr::Symbol("a b".to_string()).display_name(), "|a b|");
+        assert_eq!(SExpr::Str("\"".to_string()).display_name(), r#""\"""#);
+    }
+
+    #[test]
+    fn syntax_categories() {
+        let arena = Arena::new();
+        let items = vec![
+            arena.alloc(SExpr::Symbol("f".to_string())),
+            arena.alloc(SExpr::Str("x\n".to_string())),
+            arena.alloc(SExpr::Number("1".to_string())),
+            arena.alloc(SExpr::Number("1e".to_string())),
+            arena.alloc(SExpr::Comment(" c".to_string())),
+        ];
+        let root = arena.alloc(SExpr::List(items));
+        let categories: Vec<(String, SyntaxCategory)> = root
+            .display_tokens(&SExprFormat::Compact)
+            .filter_map(|(_, tok)| match tok {
+                DisplayToken::Text(text, category) => Some((text.into_owned(), category)),
+                _ => None,
+            })
+            .collect();
+        let expected = [
+            ("(", SyntaxCategory::Default),
+            ("f", SyntaxCategory::Ident),
+            ("\"x", SyntaxCategory::Literal),
+            (r"\n", SyntaxCategory::Special),
+            ("\"", SyntaxCategory::Literal),
+            ("1", SyntaxCategory::Literal),
+            ("1e", SyntaxCategory::Error),
+            ("; c", SyntaxCategory::Comment),
+            (")", SyntaxCategory::Default),
+        ];
+        assert_eq!(
+            categories,
+            expected
+                .iter()
+                .map(|&(text, category)| (text.to_string(), category))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        for text in &[
+            "",
+            "a",
+            "()",
+            "(a b c)",
+            "(define (square x) (* x x))",
+            "|hello world| || |12| 12 -1.5e3",
+            r#""a\"b\\c\n\x1;""#,
+            r"(quote |a\|b|)",
+            "1/2 +5 .5 'x #t",
+            "(a ; comment\nb)",
+            "; only a comment",
+            "(((()) ()) (日本語 \"🎉\"))",
+        ] {
+            let arena = Arena::new();
+            let root = SExpr::parse(text, &arena).unwrap();
+            // Compact formatting should exactly reproduce our (compactly formatted) inputs
+            assert_eq!(root.to_text(&SExprFormat::Compact), *text);
+            // Pretty formatting should reparse to the same tree
+            let pretty_text = root.to_text(&PRETTY);
+            assert_eq!(SExpr::parse(&pretty_text, &arena), Ok(root));
+        }
+    }
+
+    #[test]
+    fn parse_structure() {
+        let arena = Arena::new();
+        let root = SExpr::parse(" ( a\t\"b\"\r\n 1 ) ; c\r\n", &arena).unwrap();
+        let a = SExpr::Symbol("a".to_string());
+        let b = SExpr::Str("b".to_string());
+        let one = SExpr::Number("1".to_string());
+        let list = SExpr::List(vec![&a, &b, &one]);
+        let comment = SExpr::Comment(" c".to_string());
+        assert_eq!(*root, SExpr::Program(vec![&list, &comment]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line, column, kind) in &[
+            (
+                "(",
+                1,
+                2,
+                ParseErrorKind::Expected {
+                    expected: "')'",
+                    found: None,
+                },
+            ),
+            (
+                "a\n)",
+                2,
+                1,
+                ParseErrorKind::Expected {
+                    expected: "an expression",
+                    found: Some(')'),
+                },
+            ),
+            (
+                "\"abc",
+                1,
+                5,
+                ParseErrorKind::Expected {
+                    expected: "'\"'",
+                    found: None,
+                },
+            ),
+            (
+                "|ab",
+                1,
+                4,
+                ParseErrorKind::Expected {
+                    expected: "'|'",
+                    found: None,
+                },
+            ),
+            (
+                "a\\b",
+                1,
+                2,
+                ParseErrorKind::Expected {
+                    expected: "an expression",
+                    found: Some('\\'),
+                },
+            ),
+            (r#""\q""#, 1, 3, ParseErrorKind::InvalidEscape('q')),
+            (r#""\x41""#, 1, 7, ParseErrorKind::InvalidHexEscape),
+            (r#""\xd800;""#, 1, 9, ParseErrorKind::InvalidHexEscape),
+        ] {
+            let arena = Arena::new();
+            assert_eq!(
+                SExpr::parse(text, &arena),
+                Err(ParseError {
+                    line: *line,
+                    column: *column,
+                    kind: kind.clone()
+                }),
+                "parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let arena = Arena::new();
+        let text = "(".repeat(MAX_NESTING_DEPTH) + &")".repeat(MAX_NESTING_DEPTH);
+        assert!(SExpr::parse(&text, &arena).is_ok());
+        let text = "(".repeat(MAX_NESTING_DEPTH + 1) + &")".repeat(MAX_NESTING_DEPTH + 1);
+        assert_eq!(
+            SExpr::parse(&text, &arena).unwrap_err().kind,
+            ParseErrorKind::TooDeeplyNested
+        );
+    }
+
+    #[test]
+    fn fit_to_width() {
+        let arena = Arena::new();
+        let root = SExpr::parse("(define (square x) (* x x))", &arena).unwrap();
+        let mut format = PRETTY;
+        for (max_width, expected_text) in &[
+            (27, "(define (square x) (* x x))"),
+            (26, "(define\n  (square x)\n  (* x x))"),
+            (10, "(define\n  (square x)\n  (* x x))"),
+            (9, "(define\n  (square\n    x)\n  (* x x))"),
+        ] {
+            format.set_kind(FormatKind::Fit {
+                max_width: *max_width,
+            });
+            assert_eq!(root.to_text(&format), *expected_text);
+            assert_eq!(root.size(&format), Size::from(*expected_text));
+        }
+        // Lists containing comments never fit on one line
+        let comment = parse_one("(a ; b\n)", &arena);
+        assert_eq!(comment.to_text(&format), "(a\n  ; b\n)");
+        // Changing the kind of style keeps the indentation
+        format.set_indent(IndentKind::Tabs);
+        format.set_kind(FormatKind::Pretty);
+        assert_eq!(
+            format,
+            SExprFormat::Pretty {
+                indent: IndentKind::Tabs
+            }
+        );
+        format.set_kind(FormatKind::Compact);
+        assert_eq!(format.indent(), IndentKind::Spaces(0));
+    }
+
+    /// Generates a pseudo-random expression which is at most `max_depth` levels deep
+    fn random<'arena>(
+        rng: &mut Rng,
+        max_depth: usize,
+        arena: &'arena Arena<SExpr<'arena>>,
+    ) -> &'arena SExpr<'arena> {
+        const CHARS: &[char] = &[
+            'a', ' ', '0', '(', ';', '|', '"', '\\', '\n', '\t', '\u{1}', '日', '\u{301}', '😀',
+        ];
+        let random_string = |rng: &mut Rng| -> String {
+            (0..rng.below(5))
+                .map(|_| CHARS[rng.below(CHARS.len())])
+                .collect()
+        };
+        let kinds = if max_depth == 0 { 4 } else { 5 };
+        let node = match rng.below(kinds) {
+            0 => SExpr::Symbol(random_string(rng)),
+            1 => SExpr::Str(random_string(rng)),
+            2 => SExpr::Number((rng.next_u64() % 100_000).to_string()),
+            3 => SExpr::Comment(random_string(rng).replace('\n', " ")),
+            _ => SExpr::List(
+                (0..rng.below(5))
+                    .map(|_| random(rng, max_depth - 1, arena))
+                    .collect(),
+            ),
+        };
+        arena.alloc(node)
+    }
+
+    #[test]
+    fn size_matches_text() {
+        // The size of a node should always be the size of the text that it's rendered as, and
+        // the text should parse back into the same tree
+        let formats = [
+            SExprFormat::Compact,
+            PRETTY,
+            SExprFormat::Pretty {
+                indent: IndentKind::Tabs,
+            },
+            SExprFormat::Fit {
+                indent: IndentKind::default(),
+                max_width: 0,
+            },
+            SExprFormat::Fit {
+                indent: IndentKind::Spaces(2),
+                max_width: 30,
+            },
+        ];
+        let mut rng = Rng::new(0);
+        for _ in 0..500 {
+            let arena = Arena::new();
+            let expressions = (0..rng.below(3))
+                .map(|_| random(&mut rng, 4, &arena))
+                .collect();
+            let root = arena.alloc(SExpr::Program(expressions));
+            for format in &formats {
+                let text = root.to_text(format);
+                assert_eq!(root.size(format), Size::from(text.as_str()), "{}", text);
+                assert_eq!(SExpr::parse(&text, &arena), Ok(root), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn editing_atoms() {
+        let number = SExpr::Number("1".to_string());
+        let number = number.push_char('.').unwrap().push_char('5').unwrap();
+        assert_eq!(number, SExpr::Number("1.5".to_string()));
+        assert_eq!(
+            number.push_char('-'),
+            Some(SExpr::Number("-1.5".to_string()))
+        );
+        assert_eq!(number.push_char('x'), None);
+        assert_eq!(SExpr::Number("1".to_string()).pop_char(), None);
+        assert_eq!(number.text(), None);
+
+        // Symbols, strings and comments all have text that can be typed into
+        let symbol = SExpr::Symbol("a b".to_string());
+        assert_eq!(symbol.text(), Some("a b"));
+        assert_eq!(symbol.text_offset(1), 2);
+        assert_eq!(SExpr::Symbol("ab".to_string()).text_offset(1), 1);
+        assert_eq!(SExpr::Str("\"x".to_string()).text_offset(1), 3);
+        let comment = SExpr::Comment(String::new());
+        assert_eq!(comment.text_offset(0), 1);
+        assert_eq!(
+            comment.with_text("a\nb"),
+            Some(SExpr::Comment("a b".to_string()))
+        );
+        assert_eq!(
+            SExpr::Symbol("foo-bar".to_string()).replace_text("-", "_"),
+            Some(SExpr::Symbol("foo_bar".to_string()))
+        );
+        assert_eq!(SExpr::Comment("-".to_string()).replace_text("-", "_"), None);
+        assert_eq!(SExpr::Str("x".to_string()).replace_text("-", "_"), None);
+    }
+
+    #[test]
+    fn structure() {
+        let arena = Arena::new();
+        let symbol = arena.alloc(SExpr::Symbol("x".to_string()));
+        let comment = arena.alloc(SExpr::Comment(String::new()));
+        let program = SExpr::default();
+        let mut list = SExpr::List(vec![]);
+        // Anything but a program can go in a list, and atoms can't contain anything
+        assert!(list.is_valid_child(0, symbol));
+        assert!(list.is_valid_child(0, comment));
+        assert!(!list.is_valid_child(0, &program));
+        assert!(!symbol.is_valid_child(0, symbol));
+        assert_eq!(
+            list.insert_child(&program, &arena, 0),
+            Err(InsertError::InvalidChild {
+                parent_kind: "list",
+                child_kind: "program",
+            })
+        );
+        assert_eq!(
+            symbol.clone().insert_child(comment, &arena, 0),
+            Err(InsertError::TooManyChildren {
+                kind: "symbol",
+                max_children: 0,
+            })
+        );
+        list.insert_child(symbol, &arena, 0).unwrap();
+        list.insert_child(comment, &arena, 1).unwrap();
+        assert_eq!(list.fold_summary(), "(…) (1 item)");
+        assert_eq!(
+            list.delete_children(1..3),
+            Err(DeleteError::IndexOutOfRange { len: 2, index: 2 })
+        );
+        list.delete_child(1).unwrap();
+        assert_eq!(list.unwrap_index(), Some(0));
+
+        // Anything can be wrapped in a list, except the program
+        assert_eq!(
+            symbol.wrap_in('(', &arena),
+            Some(&SExpr::List(vec![symbol]))
+        );
+        assert_eq!(symbol.wrap_in('"', &arena), None);
+        let program = arena.alloc(SExpr::Program(vec![symbol]));
+        assert_eq!(program.wrap_in('(', &arena), None);
+        assert_eq!(program.unwrap_index(), None);
+        assert_eq!(program.replace_chars().count(), 0);
+        for c in symbol.replace_chars() {
+            assert!(program.is_valid_child(0, &symbol.from_char(c).unwrap()));
+        }
+        assert!(program.is_insert_char(';'));
+        assert!(!symbol.is_insert_char('('));
+    }
+}
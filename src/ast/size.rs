@@ -1,21 +1,53 @@
-pub const ZERO: Size = Size::new(0, 0);
+use super::Ast;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use unicode_width::UnicodeWidthChar;
+
+pub const ZERO: Size = Size::line(0);
 
 /// A struct used to represent the screen space occupied by a single node of an AST.  This can be
 /// thought of as the size of the bounding box of that node.  The important thing about this is
 /// that it is independent of the text indentation, meaning that if a node gets reused multiple
-/// times in a tree, then it's `Size` will always be the same.
+/// times in a tree, then it's `Size` will always be the same.  Every line is measured as though
+/// the node started at the left edge of the screen.
+///
+/// `Size`s are usually built by adding together the `Size`s of smaller pieces of text (which is
+/// how the [`Size`] of a node is built from the [`Size`]s of its children):
+/// ```text
+/// Size::line(1) + Size::newline() + Size::line(4).indented(4) + Size::newline() + Size::line(1)
+/// ```
+/// is the `Size` of
+/// ```text
+/// [
+///     true
+/// ]
+/// ```
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Size {
     lines: usize,
+    first_line_length: usize,
     last_line_length: usize,
+    max_width: usize,
 }
 
 impl Size {
-    /// Constructs a new `Size` from its parts
-    pub const fn new(lines: usize, last_line_length: usize) -> Size {
+    /// Constructs the `Size` of a single line of text which is `length` columns wide
+    pub const fn line(length: usize) -> Size {
+        Size {
+            lines: 0,
+            first_line_length: length,
+            last_line_length: length,
+            max_width: length,
+        }
+    }
+
+    /// Constructs the `Size` of a single `\n` character
+    pub const fn newline() -> Size {
         Size {
-            lines,
-            last_line_length,
+            lines: 1,
+            first_line_length: 0,
+            last_line_length: 0,
+            max_width: 0,
         }
     }
 
@@ -32,86 +64,362 @@ impl Size {
         self.lines
     }
 
-    /// Returns how many characters long the last line of this `Size` occupies.  For example, the
-    /// last (and only) line of `true` occupies `4` [`char`]s, whereas the last line of
+    /// Returns how many columns of the screen the first line of this `Size` occupies.  For
+    /// example, the first (and only) line of `true` occupies `4` columns, whereas the first line
+    /// of
+    /// ```text
+    /// {
+    ///     "foo": true,
+    ///     "bar": false
+    /// }
+    /// ```
+    /// occupies `1` column.
+    pub fn first_line_length(&self) -> usize {
+        self.first_line_length
+    }
+
+    /// Returns how many columns of the screen the last line of this `Size` occupies.  For
+    /// example, the last (and only) line of `true` occupies `4` columns, whereas the last line of
     /// ```text
     /// {
     ///     "foo": true,
     ///     "bar": false
     /// }
     /// ```
-    /// occupies `1` [`char`].
+    /// occupies `1` column.
     pub fn last_line_length(&self) -> usize {
         self.last_line_length
     }
+
+    /// Returns how many columns of the screen the widest line of this `Size` occupies.  For
+    /// example, the widest line of
+    /// ```text
+    /// {
+    ///     "foo": true,
+    ///     "bar": false
+    /// }
+    /// ```
+    /// is `    "bar": false`, which occupies `16` columns.
+    pub fn max_width(&self) -> usize {
+        self.max_width
+    }
+
+    /// Returns the `Size` that this text would occupy if every one of its lines was indented by
+    /// `columns` columns
+    pub fn indented(self, columns: usize) -> Size {
+        Size {
+            lines: self.lines,
+            first_line_length: self.first_line_length + columns,
+            last_line_length: self.last_line_length + columns,
+            max_width: self.max_width + columns,
+        }
+    }
+}
+
+impl Size {
+    /// Computes the `Size` of a string as it appears on the screen, counting tabs as a single
+    /// column.  Every other char is measured with [`char_widths`], so double-width chars (like
+    /// CJK text and most emoji) occupy two columns, and combining chars occupy none.
+    // Measuring a string can't fail, so this doesn't need to be `FromStr::from_str`
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(string: &str) -> Size {
+        Size::from_str_with_tab_width(string, 1)
+    }
+
+    /// Computes the `Size` of a string as it appears on the screen, where every tab character
+    /// moves to the next multiple of `tab_width` columns.  This assumes that the string starts at
+    /// a tab stop (which is always true of indentation, since it starts at the beginning of a
+    /// line).  A `tab_width` of `0` makes tabs take up no space.
+    pub fn from_str_with_tab_width(string: &str, tab_width: usize) -> Size {
+        let mut size: Option<Size> = None;
+        for line in string.split('\n') {
+            let line_size = Size::line(char_widths(line, tab_width).map(|(_, w)| w).sum());
+            size = Some(match size {
+                Some(size) => size + Size::newline() + line_size,
+                None => line_size,
+            });
+        }
+        // `split` always returns at least one line
+        size.unwrap()
+    }
 }
 
 impl From<&str> for Size {
+    /// Computes the `Size` of a string, counting tabs as a single column (see
+    /// [`Size::from_str`])
     fn from(string: &str) -> Size {
-        let lines = string.chars().filter(|x| *x == '\n').count();
-        let last_line_length = string.chars().rev().take_while(|x| *x != '\n').count();
-        Size::new(lines, last_line_length)
+        Size::from_str(string)
     }
 }
 
+/// The zero width joiner, which joins the chars either side of it into one grapheme (e.g. to
+/// make the family emoji out of the emoji of its members)
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+/// The variation selector which asks for the char before it to be displayed as an emoji
+const EMOJI_PRESENTATION_SELECTOR: char = '\u{FE0F}';
+
+/// Returns the byte index and on-screen width (in columns) of every char in a single line of
+/// text, where tabs move to the next multiple of `tab_width` columns from the start of the line.
+/// Widths follow Unicode's East Asian Width property (like most terminals), so CJK text and most
+/// emoji are two columns wide, and combining chars (like accents) and control chars take up no
+/// space.  Any char joined onto the previous one by a zero width joiner is also zero-width, so
+/// a ZWJ emoji sequence is only as wide as its first emoji, and a single-width char followed by
+/// an emoji presentation selector is displayed as a (double-width) emoji.
+pub fn char_widths(line: &str, tab_width: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut col = 0;
+    // The previous char, along with its width
+    let mut prev = None;
+    line.char_indices().map(move |(i, c)| {
+        let width = match (c, prev) {
+            ('\t', _) if tab_width == 0 => 0,
+            ('\t', _) => tab_width - col % tab_width,
+            (_, Some((ZERO_WIDTH_JOINER, _))) => 0,
+            (EMOJI_PRESENTATION_SELECTOR, Some((_, 1))) => 1,
+            _ => c.width().unwrap_or(0),
+        };
+        col += width;
+        prev = Some((c, width));
+        (i, width)
+    })
+}
+
+/// Returns `true` if `c` is one of the regional indicator symbols, pairs of which make flag emoji
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+/// Returns the byte index of the start of every grapheme in `text`, followed by the length of
+/// `text`.  A grapheme is what the user sees as a single char, so it includes any combining chars
+/// (like accents) and variation selectors after it, and an emoji joined to others by zero width
+/// joiners (or a pair of regional indicators making a flag) is one grapheme.  This is a
+/// simplified version of Unicode's grapheme cluster rules, which covers the text that Sapling can
+/// render.
+pub fn grapheme_boundaries(text: &str) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    // The previous char, along with how many regional indicators are in a row up to it
+    let mut prev: Option<(char, usize)> = None;
+    for (i, c) in text.char_indices() {
+        let indicators = if is_regional_indicator(c) {
+            prev.map_or(0, |(_, n)| n) + 1
+        } else {
+            0
+        };
+        let joins_prev = match prev {
+            None => false,
+            Some(('\r', _)) => c == '\n',
+            Some((ZERO_WIDTH_JOINER, _)) => true,
+            // Every second regional indicator completes a flag
+            Some(_) if indicators > 0 => indicators % 2 == 0,
+            // Combining chars, variation selectors and skin tone modifiers all extend the
+            // grapheme before them
+            Some(_) => {
+                c == ZERO_WIDTH_JOINER
+                    || ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+                    || (!c.is_control() && c.width() == Some(0))
+            }
+        };
+        if !joins_prev {
+            boundaries.push(i);
+        }
+        prev = Some((c, indicators));
+    }
+    boundaries.push(text.len());
+    boundaries
+}
+
 impl std::ops::Add for Size {
     type Output = Size;
 
+    /// Computes the `Size` of two pieces of text, one placed straight after the other.  The last
+    /// line of `self` and the first line of `other` become a single line, much like how `display:
+    /// inline;` works in CSS.
     fn add(self, other: Size) -> Size {
-        if other.lines == 0 {
-            // If `other` only occupies one line, then it should just be stuck onto the last line
-            // of this `Size`.  This is much like how `display: inline;` works in CSS.
-            Size {
-                lines: self.lines,
-                last_line_length: self.last_line_length + other.last_line_length,
-            }
-        } else {
-            // If `other` occupies more than one line, then it doesn't matter how long the last
-            // line of `self` is, because the last line of the combined `Size` will be as long as
-            // the last line of `other`.
-            Size {
-                lines: self.lines + other.lines,
-                last_line_length: other.last_line_length,
-            }
+        let joined_line_length = self.last_line_length + other.first_line_length;
+        Size {
+            lines: self.lines + other.lines,
+            first_line_length: if self.lines == 0 {
+                joined_line_length
+            } else {
+                self.first_line_length
+            },
+            last_line_length: if other.lines == 0 {
+                joined_line_length
+            } else {
+                other.last_line_length
+            },
+            max_width: self.max_width.max(joined_line_length).max(other.max_width),
         }
     }
 }
 
 impl std::ops::AddAssign for Size {
     fn add_assign(&mut self, other: Size) {
-        if other.lines == 0 {
-            // If `other` only occupies one line, then it should just be stuck onto the last line
-            // of this `Size`.  This is much like how `display: inline;` works in CSS.
-            self.last_line_length += other.last_line_length;
-        } else {
-            // If `other` occupies more than one line, then it doesn't matter how long the last
-            // line of `self` is, because the last line of the combined `Size` will be as long as
-            // the last line of `other`.
-            self.lines += other.lines;
-            self.last_line_length = other.last_line_length;
+        *self = *self + other;
+    }
+}
+
+/// A cache of the [`Size`]s of nodes, rendered in a single format style.
+///
+/// Nodes are never modified once they've been created (an edit makes new copies of the edited
+/// node and its ancestors), so a node's size can never change and nodes can be identified by
+/// their address.  This means that entries never need to be invalidated: after an edit, only the
+/// new nodes on the path from the root to the edited node aren't in the cache, and their sizes
+/// are calculated from the (cached) sizes of their children.  The nodes must all be stored in
+/// the same arena, which must outlive the cache, so that no address is ever reused.
+#[derive(Debug, Clone, Default)]
+pub struct SizeCache<'arena, Node: Ast<'arena>> {
+    sizes: RefCell<HashMap<*const Node, Size>>,
+    _arena: std::marker::PhantomData<&'arena Node>,
+}
+
+impl<'arena, Node: Ast<'arena> + 'arena> SizeCache<'arena, Node> {
+    /// Creates an empty `SizeCache`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the [`Size`] of a node, calculating (and caching) the sizes of any of its
+    /// descendants that haven't been cached yet.  The cache must always be used with the same
+    /// `format_style`.
+    pub fn size(&self, node: &'arena Node, format_style: &Node::FormatStyle) -> Size {
+        if let Some(&size) = self.sizes.borrow().get(&(node as *const Node)) {
+            return size;
         }
+        let child_sizes: Vec<Size> = node
+            .children()
+            .iter()
+            .map(|c| self.size(c, format_style))
+            .collect();
+        let size = node.size_from_children(format_style, &child_sizes);
+        self.sizes.borrow_mut().insert(node, size);
+        size
+    }
+
+    /// Returns the number of nodes whose sizes are cached
+    #[cfg(test)]
+    pub fn cached_count(&self) -> usize {
+        self.sizes.borrow().len()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Size;
+    use super::{char_widths, grapheme_boundaries, Size};
+
+    /// Returns the lines, last line length and max width of a `Size`
+    fn parts(size: Size) -> (usize, usize, usize) {
+        (size.lines(), size.last_line_length(), size.max_width())
+    }
 
     #[test]
     fn from_str() {
-        for (string, lines, last_line_length) in &[
-            ("", 0, 0),
-            ("true", 0, 4),
-            ("false", 0, 5),
-            ("\n", 1, 0),
-            ("\n,", 1, 1),
-            ("Some text\n", 1, 0),
-            ("{\n   true\n},", 2, 2),
+        for (string, expected_parts) in &[
+            ("", (0, 0, 0)),
+            ("true", (0, 4, 4)),
+            ("false", (0, 5, 5)),
+            ("\n", (1, 0, 0)),
+            ("\n,", (1, 1, 1)),
+            ("Some text\n", (1, 0, 9)),
+            ("{\n   true\n},", (2, 2, 7)),
+        ] {
+            assert_eq!(parts(Size::from(*string)), *expected_parts);
+        }
+    }
+
+    #[test]
+    fn from_str_with_tab_width() {
+        for (string, tab_width, expected_parts) in &[
+            ("\t", 4, (0, 4, 4)),
+            ("\t\ttrue", 4, (0, 12, 12)),
+            ("ab\tc", 4, (0, 5, 5)),
+            ("abcd\tc", 4, (0, 9, 9)),
+            ("\t{\n\t\t}", 8, (1, 17, 17)),
+            ("\t\t", 0, (0, 0, 0)),
+            ("\ta\t", 1, (0, 3, 3)),
         ] {
-            assert_eq!(Size::from(*string), Size::new(*lines, *last_line_length));
+            assert_eq!(
+                parts(Size::from_str_with_tab_width(string, *tab_width)),
+                *expected_parts
+            );
         }
     }
 
+    #[test]
+    fn unicode_width() {
+        for (string, last_line_length) in &[
+            // Each of these are two columns wide
+            ("日本語", 6),
+            ("\"日本語\"", 8),
+            // Accents can be precomposed, or made by combining chars
+            ("café", 4),
+            ("cafe\u{301}", 4),
+            ("a\u{301}\u{302}\u{303}", 1),
+            ("😀", 2),
+            // A family, and a rainbow flag (which uses a variation selector)
+            ("👨\u{200D}👩\u{200D}👧", 2),
+            ("🏳\u{FE0F}\u{200D}🌈", 2),
+            ("❤\u{FE0F}", 2),
+            ("x👨\u{200D}👩\u{200D}👧y", 4),
+        ] {
+            assert_eq!(
+                Size::from_str(string),
+                Size::line(*last_line_length),
+                "{}",
+                string
+            );
+        }
+        assert_eq!(parts(Size::from_str("日本\n語")), (1, 2, 4));
+        assert_eq!(
+            char_widths("a\t日\u{301}", 4).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 3), (2, 2), (5, 0)]
+        );
+    }
+
+    #[test]
+    fn graphemes() {
+        for (string, expected_boundaries) in &[
+            ("", vec![0]),
+            ("abc", vec![0, 1, 2, 3]),
+            ("café", vec![0, 1, 2, 3, 5]),
+            ("cafe\u{301}!", vec![0, 1, 2, 3, 6, 7]),
+            ("日本", vec![0, 3, 6]),
+            ("\r\n\n", vec![0, 2, 3]),
+            // A family, a thumbs up with a skin tone and a heart with a variation selector
+            ("👨\u{200D}👩\u{200D}👧x", vec![0, 18, 19]),
+            ("👍\u{1F3FD}👍", vec![0, 8, 12]),
+            ("❤\u{FE0F}a", vec![0, 6, 7]),
+            // Two flags, then a lone regional indicator
+            ("🇬🇧🇫🇷🇬", vec![0, 8, 16, 20]),
+        ] {
+            assert_eq!(
+                grapheme_boundaries(string),
+                *expected_boundaries,
+                "{}",
+                string
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let array = Size::line(1)
+            + Size::newline()
+            + Size::from("true,").indented(4)
+            + Size::newline()
+            + Size::from("{\n    \"a\": 1\n}").indented(4)
+            + Size::newline()
+            + Size::line(1);
+        assert_eq!(
+            array,
+            Size::from("[\n    true,\n    {\n        \"a\": 1\n    }\n]")
+        );
+        assert_eq!(parts(array), (5, 1, 14));
+        assert_eq!(array.first_line_length(), 1);
+        assert_eq!(Size::newline() + Size::newline(), Size::from("\n\n"));
+        assert_eq!(Size::line(3) + super::ZERO, Size::line(3));
+        assert_eq!(super::ZERO + Size::from("a\nbc"), Size::from("a\nbc"));
+    }
+
     #[test]
     fn add() {
         let tests: &[&[&str]] = &[
@@ -125,7 +433,11 @@ mod tests {
             let mut total_size_add_assign = super::ZERO;
             let mut full_string = String::new();
             for s in *strings {
-                total_size_add = total_size_add + Size::from(*s);
+                // We want to explicitly test `Add` here, so don't let clippy turn this into `+=`
+                #[allow(clippy::assign_op_pattern)]
+                {
+                    total_size_add = total_size_add + Size::from(*s);
+                }
                 total_size_add_assign += Size::from(*s);
                 full_string.push_str(s);
             }
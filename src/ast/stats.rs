@@ -0,0 +1,93 @@
+//! Statistics about the shape of a tree, which are shown by `:stats tree`.
+
+use super::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+use std::collections::BTreeMap;
+
+/// A summary of the structure of a tree
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TreeStats {
+    /// How many nodes of each [`node_kind`](Ast::node_kind) the tree has
+    pub kinds: BTreeMap<&'static str, usize>,
+    /// How many nodes the tree has
+    pub nodes: usize,
+    /// The number of steps from the root down to its deepest descendant
+    pub depth: usize,
+    /// The path to the node with the most children, along with how many children it has.  If
+    /// several nodes have the same number of children, the first of them is chosen.
+    pub widest: (CursorPath, usize),
+}
+
+impl TreeStats {
+    /// Walks the tree under `root`, counting its nodes
+    pub fn of<'arena, Node: Ast<'arena>>(root: &'arena Node) -> TreeStats {
+        let mut stats = TreeStats {
+            kinds: BTreeMap::new(),
+            nodes: 0,
+            depth: 0,
+            widest: (CursorPath::root(), root.children().len()),
+        };
+        // The path to the last node that was visited.  Nodes are visited in pre-order, so the
+        // parent of each node is always the last node visited at the level above it.
+        let mut path = Vec::new();
+        let mut stack = vec![(root, 0usize, 0)];
+        while let Some((node, depth, index)) = stack.pop() {
+            path.truncate(depth.saturating_sub(1));
+            if depth > 0 {
+                path.push(index);
+            }
+            *stats.kinds.entry(node.node_kind()).or_insert(0) += 1;
+            stats.nodes += 1;
+            stats.depth = stats.depth.max(depth);
+            let children = node.children();
+            if children.len() > stats.widest.1 {
+                stats.widest = (CursorPath::from_vec(path.clone()), children.len());
+            }
+            for (i, child) in children.iter().enumerate().rev() {
+                stack.push((child, depth + 1, i));
+            }
+        }
+        stats
+    }
+
+    /// Returns each kind of node along with how many there are, with the most common first
+    pub fn kinds_by_count(&self) -> Vec<(&'static str, usize)> {
+        let mut kinds: Vec<_> = self.kinds.iter().map(|(k, n)| (*k, *n)).collect();
+        kinds.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        kinds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TreeStats;
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    #[test]
+    fn tree_stats() {
+        let arena = Arena::new();
+        let root = JSON::parse(r#"[{"a": "x", "b": "y"}, [1, 2, [3, 4, 5]], "z"]"#, &arena);
+        let stats = TreeStats::of(root.unwrap());
+        assert_eq!(stats.nodes, 16);
+        assert_eq!(stats.depth, 3);
+        assert_eq!(stats.widest, (CursorPath::root(), 3));
+        assert_eq!(
+            stats.kinds_by_count(),
+            vec![
+                ("number", 5),
+                ("string", 5),
+                ("array", 3),
+                ("field", 2),
+                ("object", 1)
+            ]
+        );
+
+        // The first of the widest nodes is chosen
+        let root = JSON::parse("[[1], [[2, 3, 4, 5], [6, 7, 8, 9]]]", &arena).unwrap();
+        let stats = TreeStats::of(root);
+        assert_eq!(stats.widest, (CursorPath::from_vec(vec![1, 0]), 4));
+        assert_eq!(stats.depth, 3);
+    }
+}
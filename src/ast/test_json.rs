@@ -7,6 +7,7 @@ pub enum TestJSON {
     True,
     False,
     Null,
+    Number(String),
     Array(Vec<TestJSON>),
     Object(Vec<(String, TestJSON)>),
     Str(String),
@@ -20,6 +21,7 @@ impl TestJSON {
             TestJSON::True => arena.alloc(JSON::True),
             TestJSON::False => arena.alloc(JSON::False),
             TestJSON::Null => arena.alloc(JSON::Null),
+            TestJSON::Number(n) => arena.alloc(JSON::Number(n.clone())),
             TestJSON::Str(s) => arena.alloc(JSON::Str(s.clone())),
             TestJSON::Array(children) => {
                 let mut child_vec: Vec<&'arena JSON<'arena>> = Vec::with_capacity(children.len());
@@ -49,6 +51,7 @@ impl PartialEq<&JSON<'_>> for TestJSON {
             (TestJSON::True, JSON::True) => true,
             (TestJSON::False, JSON::False) => true,
             (TestJSON::Null, JSON::Null) => true,
+            (TestJSON::Number(n1), JSON::Number(n2)) => n1 == n2,
             (TestJSON::Array(test_children), JSON::Array(children)) => test_children == children,
             (TestJSON::Object(test_fields), JSON::Object(fields)) => {
                 test_fields.len() == fields.len()
@@ -65,3 +68,71 @@ impl PartialEq<&JSON<'_>> for TestJSON {
         }
     }
 }
+
+/// A small deterministic pseudo-random number generator (xorshift64*), so that tests can
+/// generate random trees without needing any extra dependencies
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+#[cfg(any(test, feature = "testing"))]
+impl Rng {
+    /// Creates a new `Rng`.  The same seed always produces the same sequence of numbers.
+    pub fn new(seed: u64) -> Rng {
+        // The state of a xorshift generator must never be zero
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Returns the next pseudo-random `u64`
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a pseudo-random number in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Generates a pseudo-random string of up to 5 chars, drawn from a set of chars that are awkward
+/// to measure or escape
+#[cfg(any(test, feature = "testing"))]
+pub fn random_string(rng: &mut Rng) -> String {
+    const CHARS: &[char] = &[
+        'a', 'Z', ' ', '0', ',', 'é', '日', '語', '😀', '\u{301}', '"', '\\', '\n', '\t', '\u{1}',
+    ];
+    (0..rng.below(6))
+        .map(|_| CHARS[rng.below(CHARS.len())])
+        .collect()
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl TestJSON {
+    /// Generates a pseudo-random tree which is at most `max_depth` levels deep.  Strings contain
+    /// a mixture of ASCII, wide chars, combining chars and chars that have to be escaped, so that
+    /// anything which measures text gets tested properly.
+    pub fn random(rng: &mut Rng, max_depth: usize) -> TestJSON {
+        // Only leaves can be generated at the maximum depth
+        let kinds = if max_depth == 0 { 5 } else { 7 };
+        match rng.below(kinds) {
+            0 => TestJSON::True,
+            1 => TestJSON::False,
+            2 => TestJSON::Null,
+            3 => TestJSON::Number((rng.next_u64() % 100_000).to_string()),
+            4 => TestJSON::Str(random_string(rng)),
+            5 => TestJSON::Array(
+                (0..rng.below(4))
+                    .map(|_| TestJSON::random(rng, max_depth - 1))
+                    .collect(),
+            ),
+            _ => TestJSON::Object(
+                (0..rng.below(4))
+                    .map(|_| (random_string(rng), TestJSON::random(rng, max_depth - 1)))
+                    .collect(),
+            ),
+        }
+    }
+}
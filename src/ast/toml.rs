@@ -0,0 +1,2627 @@
+//! TOML documents (like `Cargo.toml`), following version 1.0 of the TOML spec.
+//!
+//! The tree mirrors the syntax of a file rather than the data it describes: the
+//! [`Document`](TOML::Document) holds the key-value pairs before the first table header, followed
+//! by every table (so a sub-table like `[dependencies.serde]` is a table of its own, whose header
+//! is the dotted key `dependencies.serde`).  This way, every node can be rendered without knowing
+//! anything about its ancestors.
+
+use super::display_token::{
+    DisplayToken, FormatKind, IndentKind, RecTok, RenderStyle, SyntaxCategory,
+};
+use super::size::{self, Size};
+use super::{Ast, DeleteError, InsertError, MoveError, ValidationError};
+use crate::arena::{Arena, HeapSize};
+use crate::config::options::Options;
+use crate::language::Language;
+use std::collections::HashMap;
+
+/// The ways that TOML documents can be formatted
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TOMLFormat {
+    /// Every array is on one line, and there are no spaces around `=` or after `,`.  E.g.
+    /// `[package]\nname="sapling"\nkeywords=["editor","vim"]`
+    Compact,
+    /// Arrays which contain comments or (non-empty) arrays or inline tables have every element on
+    /// its own line, indented by `indent`, and every table header has a blank line before it.
+    /// If `trailing_commas` is `true`, then the last element of every array that's spread over
+    /// many lines is followed by a comma too.
+    Pretty {
+        indent: IndentKind,
+        trailing_commas: bool,
+    },
+    /// Like `Pretty`, except that arrays are kept on one line if they fit within `max_width`
+    /// columns (and spread over many lines if they don't).  Like JSON's
+    /// [`Fit`](super::json::JSONFormat::Fit), whether an array fits doesn't depend on how far
+    /// it's indented.
+    Fit {
+        indent: IndentKind,
+        max_width: usize,
+        trailing_commas: bool,
+    },
+}
+
+impl TOMLFormat {
+    /// Returns `true` if the last element of every array that's spread over many lines should be
+    /// followed by a comma
+    fn has_trailing_commas(&self) -> bool {
+        match self {
+            TOMLFormat::Compact => false,
+            TOMLFormat::Pretty {
+                trailing_commas, ..
+            }
+            | TOMLFormat::Fit {
+                trailing_commas, ..
+            } => *trailing_commas,
+        }
+    }
+}
+
+impl RenderStyle for TOMLFormat {
+    fn indent(&self) -> IndentKind {
+        match self {
+            TOMLFormat::Compact => IndentKind::Spaces(0),
+            TOMLFormat::Pretty { indent, .. } | TOMLFormat::Fit { indent, .. } => *indent,
+        }
+    }
+
+    fn set_indent(&mut self, new_indent: IndentKind) {
+        if let TOMLFormat::Pretty { indent, .. } | TOMLFormat::Fit { indent, .. } = self {
+            *indent = new_indent;
+        }
+    }
+
+    fn set_kind(&mut self, kind: FormatKind) {
+        let indent = match *self {
+            TOMLFormat::Pretty { indent, .. } | TOMLFormat::Fit { indent, .. } => indent,
+            TOMLFormat::Compact => IndentKind::default(),
+        };
+        let trailing_commas = self.has_trailing_commas();
+        *self = match kind {
+            FormatKind::Compact => TOMLFormat::Compact,
+            FormatKind::Pretty => TOMLFormat::Pretty {
+                indent,
+                trailing_commas,
+            },
+            FormatKind::Fit { max_width } => TOMLFormat::Fit {
+                indent,
+                max_width,
+                trailing_commas,
+            },
+        };
+    }
+
+    fn apply_options(&mut self, options: &Options, _language: Language) {
+        if let TOMLFormat::Pretty {
+            trailing_commas, ..
+        }
+        | TOMLFormat::Fit {
+            trailing_commas, ..
+        } = self
+        {
+            *trailing_commas = options.trailing_commas;
+        }
+    }
+}
+
+const CHAR_STRING: char = 's';
+const CHAR_TRUE: char = 't';
+const CHAR_FALSE: char = 'f';
+const CHAR_ARRAY: char = 'a';
+const CHAR_INLINE_TABLE: char = 'o';
+const CHAR_TABLE: char = 'T';
+const CHAR_ARRAY_TABLE: char = 'A';
+const CHAR_COMMENT: char = '#';
+
+/// The category used for keys, which only TOML has (and which looks like `ident` unless the
+/// colour scheme gives it a style)
+const CATEGORY_KEY: SyntaxCategory = SyntaxCategory::Custom("key");
+/// The category used for `true` and `false`
+const CATEGORY_CONST: SyntaxCategory = SyntaxCategory::Const;
+/// The category used for strings and numbers
+const CATEGORY_LITERAL: SyntaxCategory = SyntaxCategory::Literal;
+/// The category used for punctuation
+const CATEGORY_DEFAULT: SyntaxCategory = SyntaxCategory::Default;
+/// The category used for escape sequences inside strings
+const CATEGORY_SPECIAL: SyntaxCategory = SyntaxCategory::Special;
+/// The category used for comments
+const CATEGORY_COMMENT: SyntaxCategory = SyntaxCategory::Comment;
+/// The category used for numbers and keys that aren't valid (e.g. `1e` whilst it's being typed)
+const CATEGORY_ERROR: SyntaxCategory = SyntaxCategory::Error;
+
+/// The two kinds of TOML number
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum NumberKind {
+    Integer,
+    Float,
+}
+
+/// Returns `true` if `text` is a non-empty sequence of digits in base `radix`, where any two
+/// digits can be separated by a single underscore (like `1_000`)
+fn is_digits(text: &str, radix: u32) -> bool {
+    !text.is_empty()
+        && !text.starts_with('_')
+        && !text.ends_with('_')
+        && !text.contains("__")
+        && text.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+/// Returns the kind of number that `text` is, or `None` if it isn't a valid TOML number.  This
+/// covers decimal integers (which can't have leading zeros), hex, octal and binary integers
+/// (like `0xff`), and floats with a fractional part, an exponent or both (as well as `inf` and
+/// `nan`).
+fn number_kind(text: &str) -> Option<NumberKind> {
+    let unsigned = text.strip_prefix(&['+', '-'][..]).unwrap_or(text);
+    if unsigned == "inf" || unsigned == "nan" {
+        return Some(NumberKind::Float);
+    }
+    // Only decimal numbers can have a sign
+    for &(prefix, radix) in &[("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = text.strip_prefix(prefix) {
+            return Some(NumberKind::Integer).filter(|_| is_digits(digits, radix));
+        }
+    }
+    let (mantissa, exponent) = match unsigned.find(&['e', 'E'][..]) {
+        Some(i) => (&unsigned[..i], Some(&unsigned[i + 1..])),
+        None => (unsigned, None),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], Some(&mantissa[i + 1..])),
+        None => (mantissa, None),
+    };
+    let is_valid = is_digits(int, 10)
+        && (int == "0" || !int.starts_with('0'))
+        && frac.is_none_or(|frac| is_digits(frac, 10))
+        && exponent
+            .is_none_or(|exp| is_digits(exp.strip_prefix(&['+', '-'][..]).unwrap_or(exp), 10));
+    match (is_valid, frac.is_some() || exponent.is_some()) {
+        (false, _) => None,
+        (true, false) => Some(NumberKind::Integer),
+        (true, true) => Some(NumberKind::Float),
+    }
+}
+
+/// Returns `true` if `c` can be part of a key that isn't quoted
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Returns `true` if `key` can be written without quotes
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(is_bare_key_char)
+}
+
+/// Returns the escape sequence that a char has to be written as inside a TOML string, or `None`
+/// if it can be written as it is.  Quotes and backslashes are escaped, and so is every control
+/// char (using `\n`, `\t` and `\r` where they have names, and `\uXXXX` for the rest).
+fn escape_char(c: char) -> Option<String> {
+    match c {
+        '"' => Some(r#"\""#.to_string()),
+        '\\' => Some(r"\\".to_string()),
+        '\n' => Some(r"\n".to_string()),
+        '\t' => Some(r"\t".to_string()),
+        '\r' => Some(r"\r".to_string()),
+        _ if c.is_control() => Some(format!("\\u{:04X}", c as u32)),
+        _ => None,
+    }
+}
+
+/// Escapes the contents of a TOML string so that it can be written between `"`s
+fn escape_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match escape_char(c) {
+            Some(escape) => escaped.push_str(&escape),
+            None => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Generates the tokens of a TOML string (including its quotes), where the escape sequences are
+/// separate tokens so that they can be highlighted differently to the rest of the string
+fn string_tokens<'arena>(string: &str) -> Vec<RecTok<'arena, TOML<'arena>>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::from('"');
+    for c in string.chars() {
+        match escape_char(c) {
+            Some(escape) => {
+                if !literal.is_empty() {
+                    let text = std::mem::take(&mut literal);
+                    tokens.push(RecTok::Tok(DisplayToken::Text(
+                        text.into(),
+                        CATEGORY_LITERAL,
+                    )));
+                }
+                tokens.push(RecTok::Tok(DisplayToken::Text(
+                    escape.into(),
+                    CATEGORY_SPECIAL,
+                )));
+            }
+            None => literal.push(c),
+        }
+    }
+    literal.push('"');
+    tokens.push(RecTok::Tok(DisplayToken::Text(
+        literal.into(),
+        CATEGORY_LITERAL,
+    )));
+    tokens
+}
+
+/// Returns the text of a (possibly dotted) key made of `segments`, where each segment is bare if
+/// it can be and quoted otherwise (e.g. `dependencies."my crate"`)
+fn key_text(segments: &[String]) -> String {
+    let segments: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            if is_bare_key(segment) {
+                segment.clone()
+            } else {
+                format!("\"{}\"", escape_string(segment))
+            }
+        })
+        .collect();
+    segments.join(".")
+}
+
+/// Splits the text of a key into its dotted segments (removing any quotes), or returns `None` if
+/// the text isn't a valid key
+fn key_segments(text: &str) -> Option<Vec<String>> {
+    let mut parser = Parser::new(text);
+    let segments = parser.parse_key().ok()?;
+    Some(segments).filter(|_| parser.peek().is_none())
+}
+
+/// Returns the text that a key is rendered as.  An empty key (e.g. one that's just been
+/// inserted) isn't valid TOML, so it's shown as the empty quoted key `""` until it's typed into.
+fn rendered_key(text: &str) -> &str {
+    if text.is_empty() {
+        r#""""#
+    } else {
+        text
+    }
+}
+
+/// The sapling representation of a TOML document
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum TOML<'arena> {
+    /// A whole TOML file, which is the implicit table at the root of every document.  This is
+    /// always the root of the tree, and can't be inside anything else.  Its
+    /// [`Pair`](TOML::Pair)s and [`Comment`](TOML::Comment)s must all come before its tables,
+    /// since anything after a table header belongs to that table.
+    Document(Vec<&'arena TOML<'arena>>),
+    /// A table, which corresponds to a string `[<key>]` followed by the table's key-value pairs
+    /// (one per line).  The first `Ref`s are the [`Comment`](TOML::Comment)s on the lines just
+    /// before the header (so that they stay with it), followed by the [`Key`](TOML::Key) in the
+    /// header, and the rest are [`Pair`](TOML::Pair)s and [`Comment`](TOML::Comment)s.
+    Table(Vec<&'arena TOML<'arena>>),
+    /// One entry of an array of tables, which is exactly like a [`Table`](TOML::Table) except
+    /// that its header is `[[<key>]]`
+    ArrayTable(Vec<&'arena TOML<'arena>>),
+    /// A key-value pair, like `name = "sapling"`.  The first `Ref` must be a
+    /// [`Key`](TOML::Key), and the second is a value.
+    Pair([&'arena TOML<'arena>; 2]),
+    /// The key of a pair or the header of a table.  This is stored as the text of the key, so a
+    /// dotted key like `package.name` is a single node.  Segments that can't be bare are quoted,
+    /// like `"my crate"`.
+    Key(String),
+    /// A string.  However it was written in the file, this is always written as a basic
+    /// (single-line, `"`-quoted) string.
+    Str(String),
+    /// An integer.  Like [`JSON::Number`](super::json::JSON::Number), this is stored as the
+    /// text that represents it, so that numbers like `0xff` and `1_000` are preserved exactly.
+    Integer(String),
+    /// A floating point number (like `1.5`, `1e9` or `inf`), which is stored as its text
+    Float(String),
+    /// Either `true` or `false`
+    Boolean(bool),
+    /// An array of values, which can also contain comments.  Corresponds to a string
+    /// `[<v1>, <v2>, ...]`.
+    Array(Vec<&'arena TOML<'arena>>),
+    /// An inline table, which is always written on one line.  Corresponds to a string
+    /// `{ <key1> = <v1>, <key2> = <v2>, ... }`, and all the `Ref`s must be
+    /// [`Pair`](TOML::Pair)s.
+    InlineTable(Vec<&'arena TOML<'arena>>),
+    /// A `#` comment, which contains everything after the `#` up to the end of the line.  A
+    /// comment at the end of a line of the file becomes a comment of its own, on the next line.
+    Comment(String),
+}
+
+impl<'arena> TOML<'arena> {
+    /// Return an iterator over all the possible chars that could represent TOML values.  Any
+    /// digit will create an integer starting with that digit.
+    fn value_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new(
+            [
+                CHAR_STRING,
+                CHAR_TRUE,
+                CHAR_FALSE,
+                CHAR_ARRAY,
+                CHAR_INLINE_TABLE,
+            ]
+            .iter()
+            .copied()
+            .chain('0'..='9'),
+        )
+    }
+
+    /// Return an iterator over the chars that create the kinds of table
+    fn table_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new([CHAR_TABLE, CHAR_ARRAY_TABLE].iter().copied())
+    }
+
+    /// Returns the integer or float that `text` is (or is a prefix of, whilst it's being typed),
+    /// or `None` if no amount of typing could turn it into a number
+    fn number(text: String) -> Option<Self> {
+        let kind = number_kind(&text).or_else(|| number_kind(&format!("{}0", text)))?;
+        Some(match kind {
+            NumberKind::Integer => TOML::Integer(text),
+            NumberKind::Float => TOML::Float(text),
+        })
+    }
+
+    /// Returns `true` if this node is a value, which can be the value of a
+    /// [`Pair`](TOML::Pair) or an element of an array
+    fn is_value(&self) -> bool {
+        matches!(
+            self,
+            TOML::Str(_)
+                | TOML::Integer(_)
+                | TOML::Float(_)
+                | TOML::Boolean(_)
+                | TOML::Array(_)
+                | TOML::InlineTable(_)
+        )
+    }
+
+    /// Returns `true` if this node is a table or an entry of an array of tables
+    fn is_table(&self) -> bool {
+        matches!(self, TOML::Table(_) | TOML::ArrayTable(_))
+    }
+
+    /// Returns `true` if this node is a table with a header
+    fn has_header(&self) -> bool {
+        let children = self.children();
+        self.is_table() && matches!(children.get(header_index(children)), Some(TOML::Key(_)))
+    }
+
+    /// Returns `true` if this node is an array or inline table with at least one child
+    fn is_non_empty_container(&self) -> bool {
+        matches!(self, TOML::Array(c) | TOML::InlineTable(c) if !c.is_empty())
+    }
+
+    /// Returns `true` if the children of this node are in an order that TOML allows: the header
+    /// of a table must come before everything but the comments about it, and none of the pairs
+    /// or comments of a document can come after a table
+    fn is_well_ordered(&self) -> bool {
+        match self {
+            TOML::Document(children) => children
+                .windows(2)
+                .all(|pair| !pair[0].is_table() || pair[1].is_table()),
+            TOML::Table(_) | TOML::ArrayTable(_) => self.has_header(),
+            _ => true,
+        }
+    }
+
+    /// Returns `true` if the child at `index` of an array is followed by a comma.  Every value
+    /// except the last one is, and so is the last one if `trailing_commas` is `true`.  Comments
+    /// are never followed by commas.
+    fn has_comma(children: &[&TOML], index: usize, trailing_commas: bool) -> bool {
+        !children[index].is_comment()
+            && (trailing_commas || children[index + 1..].iter().any(|c| !c.is_comment()))
+    }
+
+    /// Returns `true` if this is an array which will have each element on a line of its own
+    /// when rendered in `format_style`
+    fn is_broken(&self, format_style: &TOMLFormat) -> bool {
+        let items = match self {
+            TOML::Array(items) if !items.is_empty() => items,
+            _ => return false,
+        };
+        match format_style {
+            TOMLFormat::Compact => false,
+            TOMLFormat::Pretty { .. } => items
+                .iter()
+                .any(|item| item.is_non_empty_container() || item.is_comment()),
+            TOMLFormat::Fit { max_width, .. } => self.inline_width(*max_width).is_none(),
+        }
+    }
+
+    /// Returns how many columns this node would occupy if it was written on one line with a
+    /// space after every `,`, or `None` if that would be more than `limit` columns (which is
+    /// always true of comments and tables, since they end their lines).  This stops measuring as
+    /// soon as the limit is exceeded.
+    fn inline_width(&self, limit: usize) -> Option<usize> {
+        let mut width = match self {
+            TOML::Array(items) => {
+                let commas = (0..items.len())
+                    .filter(|&i| Self::has_comma(items, i, false))
+                    .count();
+                2 + items.len().saturating_sub(1) + commas
+            }
+            TOML::InlineTable(pairs) if pairs.is_empty() => 2,
+            // `{ ` and ` }` around the pairs, which are separated by `, `
+            TOML::InlineTable(pairs) => 4 + 2 * (pairs.len() - 1),
+            TOML::Pair(_) => 3,
+            TOML::Document(_) | TOML::Table(_) | TOML::ArrayTable(_) | TOML::Comment(_) => {
+                return None
+            }
+            // Other leaves are always on one line
+            leaf => {
+                let width = leaf
+                    .size_from_children(&TOMLFormat::Compact, &[])
+                    .max_width();
+                return Some(width).filter(|&width| width <= limit);
+            }
+        };
+        for child in self.children() {
+            width += child.inline_width(limit.checked_sub(width)?)?;
+        }
+        Some(width).filter(|&width| width <= limit)
+    }
+
+    /// Returns `true` if a document's child at `index` should have a blank line before it, which
+    /// is true of every table except the first line of the file.  Compact formatting only keeps
+    /// the blank lines after comments, which would otherwise be read as comments about the table.
+    fn has_blank_line(children: &[&TOML], index: usize, format_style: &TOMLFormat) -> bool {
+        index > 0
+            && children[index].is_table()
+            && (!matches!(format_style, TOMLFormat::Compact) || {
+                let previous = children[index - 1];
+                previous.is_comment()
+                    || (previous.is_table()
+                        && previous.children().last().is_some_and(|c| c.is_comment()))
+            })
+    }
+
+    /// Returns the (unquoted) segments of a key, which is how keys are compared.  Keys that
+    /// aren't valid are compared by their text.
+    fn key_segments(&self) -> Vec<String> {
+        let text = rendered_key(self.text().unwrap_or_default());
+        key_segments(text).unwrap_or_else(|| vec![text.to_string()])
+    }
+
+    /// Moves the child at `index` to `new_index` (like [`Ast::move_child`]), without checking
+    /// that the new order is valid
+    fn rotate_children(&mut self, index: usize, new_index: isize) -> Result<(), MoveError> {
+        let children = self.children_mut();
+        let len = children.len();
+        if index >= len || new_index < 0 || new_index as usize >= len {
+            return Err(MoveError::OutOfRange { len, new_index });
+        }
+        let new_index = new_index as usize;
+        if index < new_index {
+            children[index..=new_index].rotate_left(1);
+        } else {
+            children[new_index..=index].rotate_right(1);
+        }
+        Ok(())
+    }
+}
+
+impl Default for TOML<'_> {
+    fn default() -> TOML<'static> {
+        TOML::Document(vec![])
+    }
+}
+
+impl HeapSize for TOML<'_> {
+    fn heap_size(&self) -> usize {
+        match self {
+            TOML::Document(children)
+            | TOML::Table(children)
+            | TOML::ArrayTable(children)
+            | TOML::Array(children)
+            | TOML::InlineTable(children) => children.heap_size(),
+            TOML::Pair(_) | TOML::Boolean(_) => 0,
+            TOML::Key(text)
+            | TOML::Str(text)
+            | TOML::Integer(text)
+            | TOML::Float(text)
+            | TOML::Comment(text) => text.heap_size(),
+        }
+    }
+}
+
+impl<'arena> Ast<'arena> for TOML<'arena> {
+    type FormatStyle = TOMLFormat;
+
+    const TOKEN_CATEGORIES: &'static [SyntaxCategory] = &[
+        CATEGORY_KEY,
+        CATEGORY_CONST,
+        CATEGORY_LITERAL,
+        CATEGORY_DEFAULT,
+        CATEGORY_SPECIAL,
+        CATEGORY_COMMENT,
+        CATEGORY_ERROR,
+    ];
+
+    const CATEGORIES: &'static [(SyntaxCategory, SyntaxCategory)] =
+        &[(CATEGORY_KEY, SyntaxCategory::Ident)];
+
+    /* FORMATTING FUNCTIONS */
+
+    fn display_tokens_rec(
+        &'arena self,
+        format_style: &Self::FormatStyle,
+    ) -> Vec<RecTok<'arena, Self>> {
+        let is_spaced = !matches!(format_style, TOMLFormat::Compact);
+        let text =
+            |text: String, category| vec![RecTok::Tok(DisplayToken::Text(text.into(), category))];
+        let punctuation =
+            |text: &'static str| RecTok::Tok(DisplayToken::Text(text.into(), CATEGORY_DEFAULT));
+        let items = match self {
+            TOML::Key(key) => {
+                let category = match key_segments(rendered_key(key)) {
+                    Some(_) => CATEGORY_KEY,
+                    None => CATEGORY_ERROR,
+                };
+                return text(rendered_key(key).to_string(), category);
+            }
+            TOML::Str(string) => return string_tokens(string),
+            TOML::Integer(number) | TOML::Float(number) => {
+                let is_valid = match number_kind(number) {
+                    Some(NumberKind::Integer) => matches!(self, TOML::Integer(_)),
+                    Some(NumberKind::Float) => matches!(self, TOML::Float(_)),
+                    None => false,
+                };
+                let category = if is_valid {
+                    CATEGORY_LITERAL
+                } else {
+                    CATEGORY_ERROR
+                };
+                return text(number.clone(), category);
+            }
+            TOML::Boolean(value) => return text(value.to_string(), CATEGORY_CONST),
+            TOML::Comment(comment) => return text(format!("#{}", comment), CATEGORY_COMMENT),
+            TOML::Pair([key, value]) => {
+                return vec![
+                    RecTok::Child(key),
+                    punctuation(if is_spaced { " = " } else { "=" }),
+                    RecTok::Child(value),
+                ]
+            }
+            TOML::Document(children) => {
+                // Everything in a document is on its own line, and tables are separated by blank
+                // lines (unless everything is compact)
+                let mut tokens = Vec::with_capacity(3 * children.len());
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        tokens.push(RecTok::Tok(DisplayToken::Newline));
+                    }
+                    if Self::has_blank_line(children, i, format_style) {
+                        tokens.push(RecTok::Tok(DisplayToken::Newline));
+                    }
+                    tokens.push(RecTok::Child(*child));
+                }
+                return tokens;
+            }
+            TOML::Table(children) | TOML::ArrayTable(children) => {
+                let (open, close) = match self {
+                    TOML::Table(_) => ("[", "]"),
+                    _ => ("[[", "]]"),
+                };
+                let header = header_index(children);
+                let mut tokens = Vec::with_capacity(2 + 2 * children.len());
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        tokens.push(RecTok::Tok(DisplayToken::Newline));
+                    }
+                    if i == header {
+                        tokens.push(punctuation(open));
+                        tokens.push(RecTok::Child(*child));
+                        tokens.push(punctuation(close));
+                    } else {
+                        tokens.push(RecTok::Child(*child));
+                    }
+                }
+                return tokens;
+            }
+            TOML::InlineTable(pairs) => {
+                if pairs.is_empty() {
+                    return vec![punctuation("{}")];
+                }
+                let mut tokens = Vec::with_capacity(2 + 2 * pairs.len());
+                tokens.push(punctuation(if is_spaced { "{ " } else { "{" }));
+                for (i, pair) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        tokens.push(punctuation(if is_spaced { ", " } else { "," }));
+                    }
+                    tokens.push(RecTok::Child(*pair));
+                }
+                tokens.push(punctuation(if is_spaced { " }" } else { "}" }));
+                return tokens;
+            }
+            TOML::Array(items) => items,
+        };
+
+        if items.is_empty() {
+            return vec![punctuation("[]")];
+        }
+        let is_broken = self.is_broken(format_style);
+        let trailing_commas = is_broken && format_style.has_trailing_commas();
+        let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(6 + 3 * items.len());
+        tokens.push(punctuation("["));
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Indent));
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        for (i, item) in items.iter().enumerate() {
+            // A comment runs to the end of its line, so anything after one has to go on a new
+            // line
+            if i > 0 {
+                if is_broken || items[i - 1].is_comment() {
+                    tokens.push(RecTok::Tok(DisplayToken::Newline));
+                } else if is_spaced {
+                    tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+                }
+            }
+            tokens.push(RecTok::Child(*item));
+            if Self::has_comma(items, i, trailing_commas) {
+                tokens.push(punctuation(","));
+            }
+        }
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Dedent));
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        } else if items.last().is_some_and(|item| item.is_comment()) {
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        tokens.push(punctuation("]"));
+        tokens
+    }
+
+    fn size_from_children(&self, format_style: &Self::FormatStyle, child_sizes: &[Size]) -> Size {
+        let is_spaced = !matches!(format_style, TOMLFormat::Compact);
+        let items = match self {
+            TOML::Key(key) => return Size::from(rendered_key(key)),
+            // Every escape sequence is printable ASCII, so escaped strings are always one line
+            TOML::Str(string) => {
+                return Size::line(1) + Size::from(escape_string(string).as_str()) + Size::line(1)
+            }
+            TOML::Integer(number) | TOML::Float(number) => return Size::from(number.as_str()),
+            TOML::Boolean(true) => return Size::line(4), // same as Size::from("true")
+            TOML::Boolean(false) => return Size::line(5), // same as Size::from("false")
+            TOML::Comment(comment) => return Size::line(1) + Size::from(comment.as_str()),
+            // The key and value are separated by " = ", or just "=" in compact TOML
+            TOML::Pair(_) => {
+                let equals = if is_spaced { 3 } else { 1 };
+                return child_sizes[0] + Size::line(equals) + child_sizes[1];
+            }
+            TOML::Document(children) => {
+                let mut size = size::ZERO;
+                for (i, &child_size) in child_sizes.iter().enumerate() {
+                    if i > 0 {
+                        size += Size::newline();
+                    }
+                    if Self::has_blank_line(children, i, format_style) {
+                        size += Size::newline();
+                    }
+                    size += child_size;
+                }
+                return size;
+            }
+            TOML::Table(children) | TOML::ArrayTable(children) => {
+                let bracket = Size::line(if matches!(self, TOML::Table(_)) { 1 } else { 2 });
+                let header = header_index(children);
+                let mut size = size::ZERO;
+                for (i, &child_size) in child_sizes.iter().enumerate() {
+                    if i > 0 {
+                        size += Size::newline();
+                    }
+                    if i == header {
+                        size += bracket + child_size + bracket;
+                    } else {
+                        size += child_size;
+                    }
+                }
+                return size;
+            }
+            TOML::InlineTable(_) => {
+                if child_sizes.is_empty() {
+                    return Size::line(2);
+                }
+                let (brace, comma) = if is_spaced { (2, 2) } else { (1, 1) };
+                let mut size = Size::line(brace);
+                for (i, &child_size) in child_sizes.iter().enumerate() {
+                    if i > 0 {
+                        size += Size::line(comma);
+                    }
+                    size += child_size;
+                }
+                return size + Size::line(brace);
+            }
+            TOML::Array(items) => items,
+        };
+
+        if items.is_empty() {
+            return Size::line(2);
+        }
+        let is_broken = self.is_broken(format_style);
+        let trailing_commas = is_broken && format_style.has_trailing_commas();
+        // How far each element is indented when they're on lines of their own, counting tabs as
+        // a single column (like `Size::from`)
+        let indent_columns = format_style.indent().columns(1);
+        let mut size = Size::line(1);
+        for (i, &child_size) in child_sizes.iter().enumerate() {
+            if is_broken {
+                size += Size::newline() + child_size.indented(indent_columns);
+            } else {
+                if i > 0 {
+                    if items[i - 1].is_comment() {
+                        size += Size::newline();
+                    } else if is_spaced {
+                        size += Size::line(1);
+                    }
+                }
+                size += child_size;
+            }
+            if Self::has_comma(items, i, trailing_commas) {
+                size += Size::line(1);
+            }
+        }
+        if is_broken || items.last().is_some_and(|item| item.is_comment()) {
+            size += Size::newline();
+        }
+        size + Size::line(1)
+    }
+
+    /* DEBUG VIEW FUNCTIONS */
+
+    fn children<'s>(&'s self) -> &'s [&'arena TOML<'arena>] {
+        match self {
+            TOML::Document(children)
+            | TOML::Table(children)
+            | TOML::ArrayTable(children)
+            | TOML::Array(children)
+            | TOML::InlineTable(children) => children,
+            TOML::Pair(key_value) => &key_value[..],
+            TOML::Key(_)
+            | TOML::Str(_)
+            | TOML::Integer(_)
+            | TOML::Float(_)
+            | TOML::Boolean(_)
+            | TOML::Comment(_) => &[],
+        }
+    }
+
+    fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena TOML<'arena>] {
+        match self {
+            TOML::Document(children)
+            | TOML::Table(children)
+            | TOML::ArrayTable(children)
+            | TOML::Array(children)
+            | TOML::InlineTable(children) => children,
+            TOML::Pair(key_value) => &mut key_value[..],
+            TOML::Key(_)
+            | TOML::Str(_)
+            | TOML::Integer(_)
+            | TOML::Float(_)
+            | TOML::Boolean(_)
+            | TOML::Comment(_) => &mut [],
+        }
+    }
+
+    fn insert_child(
+        &mut self,
+        new_node: &'arena Self,
+        arena: &'arena Arena<Self>,
+        index: usize,
+    ) -> Result<(), InsertError> {
+        let len = self.children().len();
+        if index > len {
+            return Err(InsertError::IndexOutOfRange { len, index });
+        }
+        let invalid_child = InsertError::InvalidChild {
+            parent_kind: self.node_kind(),
+            child_kind: new_node.node_kind(),
+        };
+        let invalid_position = InsertError::InvalidPosition {
+            parent_kind: self.node_kind(),
+            child_kind: new_node.node_kind(),
+            index,
+        };
+        // Values can't go straight into tables, so (like the fields of a JSON object) they're
+        // given an empty key
+        let contains_pairs = matches!(
+            self,
+            TOML::Document(_) | TOML::Table(_) | TOML::ArrayTable(_) | TOML::InlineTable(_)
+        );
+        let new_node = if new_node.is_value() && contains_pairs {
+            let key = arena.alloc(TOML::Key(String::new()));
+            arena.alloc(TOML::Pair([key, new_node]))
+        } else {
+            new_node
+        };
+        match self {
+            TOML::Key(_)
+            | TOML::Str(_)
+            | TOML::Integer(_)
+            | TOML::Float(_)
+            | TOML::Boolean(_)
+            | TOML::Comment(_) => Err(InsertError::TooManyChildren {
+                kind: self.node_kind(),
+                max_children: 0,
+            }),
+            TOML::Pair(_) => Err(InsertError::TooManyChildren {
+                kind: self.node_kind(),
+                max_children: 2,
+            }),
+            TOML::Document(children) => {
+                // A new table is given an empty header
+                let new_node = match new_node {
+                    TOML::Table(c) if c.is_empty() => {
+                        let key = arena.alloc(TOML::Key(String::new()));
+                        arena.alloc(TOML::Table(vec![key]))
+                    }
+                    TOML::ArrayTable(c) if c.is_empty() => {
+                        let key = arena.alloc(TOML::Key(String::new()));
+                        arena.alloc(TOML::ArrayTable(vec![key]))
+                    }
+                    _ => new_node,
+                };
+                // Pairs and comments have to come before every table, and tables after every
+                // pair and comment
+                let is_valid_position = match new_node {
+                    TOML::Pair(_) | TOML::Comment(_) => !children[..index.min(children.len())]
+                        .iter()
+                        .any(|c| c.is_table()),
+                    _ if new_node.has_header() => children[index.min(children.len())..]
+                        .iter()
+                        .all(|c| c.is_table()),
+                    _ => return Err(invalid_child),
+                };
+                if !is_valid_position {
+                    return Err(invalid_position);
+                }
+                children.insert(index, new_node);
+                Ok(())
+            }
+            TOML::Table(children) | TOML::ArrayTable(children) => {
+                if !matches!(new_node, TOML::Pair(_) | TOML::Comment(_)) {
+                    return Err(invalid_child);
+                }
+                // Only comments can go before the header
+                if !new_node.is_comment() && index <= header_index(children) {
+                    return Err(invalid_position);
+                }
+                children.insert(index, new_node);
+                Ok(())
+            }
+            TOML::InlineTable(pairs) => {
+                // Inline tables are always on one line, so can't contain comments
+                if !matches!(new_node, TOML::Pair(_)) {
+                    return Err(invalid_child);
+                }
+                pairs.insert(index, new_node);
+                Ok(())
+            }
+            TOML::Array(items) => {
+                if !new_node.is_value() && !new_node.is_comment() {
+                    return Err(invalid_child);
+                }
+                items.insert(index, new_node);
+                Ok(())
+            }
+        }
+    }
+
+    fn has_reorderable_children(&self) -> bool {
+        // Swapping the key and value of a pair would make the key something other than a key
+        !matches!(self, TOML::Pair(_))
+    }
+
+    fn move_child(&mut self, index: usize, new_index: isize) -> Result<(), MoveError> {
+        if !self.has_reorderable_children() {
+            return Err(MoveError::FixedPositions {
+                kind: self.node_kind(),
+            });
+        }
+        // Check the order of a moved copy, so that nothing is moved if the order isn't valid
+        let mut moved = self.clone();
+        moved.rotate_children(index, new_index)?;
+        if !moved.is_well_ordered() {
+            return Err(MoveError::InvalidPosition {
+                parent_kind: self.node_kind(),
+                child_kind: self.children()[index].node_kind(),
+                new_index: new_index as usize,
+            });
+        }
+        *self = moved;
+        Ok(())
+    }
+
+    fn delete_children(&mut self, range: std::ops::Range<usize>) -> Result<(), DeleteError> {
+        match self {
+            TOML::Key(_)
+            | TOML::Str(_)
+            | TOML::Integer(_)
+            | TOML::Float(_)
+            | TOML::Boolean(_)
+            | TOML::Comment(_) => {
+                // Leaves have no children, so there's nothing that could be selected for deletion
+                unreachable!()
+            }
+            TOML::Pair(_) => Err(DeleteError::TooFewChildren {
+                kind: self.node_kind(),
+                min_children: 2,
+            }),
+            TOML::Table(children) | TOML::ArrayTable(children)
+                if range.contains(&header_index(children)) =>
+            {
+                Err(DeleteError::RequiredChild {
+                    kind: self.node_kind(),
+                    child_kind: "header",
+                })
+            }
+            // Removing children can never put the rest out of order, so we only need to check
+            // that the children exist
+            TOML::Document(children)
+            | TOML::Table(children)
+            | TOML::ArrayTable(children)
+            | TOML::Array(children)
+            | TOML::InlineTable(children) => {
+                if range.end <= children.len() {
+                    children.drain(range);
+                    Ok(())
+                } else {
+                    Err(DeleteError::IndexOutOfRange {
+                        len: children.len(),
+                        index: range.end - 1,
+                    })
+                }
+            }
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match self {
+            TOML::ArrayTable(_) => "array of tables".to_string(),
+            TOML::Pair(_) => "key-value pair".to_string(),
+            TOML::Key(key) => rendered_key(key).to_string(),
+            TOML::Str(string) => format!("\"{}\"", escape_string(string)),
+            TOML::Integer(number) | TOML::Float(number) => number.clone(),
+            TOML::Boolean(value) => value.to_string(),
+            TOML::InlineTable(_) => "inline table".to_string(),
+            _ => self.node_kind().to_string(),
+        }
+    }
+
+    fn node_kind(&self) -> &'static str {
+        match self {
+            TOML::Document(_) => "document",
+            TOML::Table(_) => "table",
+            TOML::ArrayTable(_) => "array-table",
+            TOML::Pair(_) => "pair",
+            TOML::Key(_) => "key",
+            TOML::Str(_) => "string",
+            TOML::Integer(_) => "integer",
+            TOML::Float(_) => "float",
+            TOML::Boolean(_) => "boolean",
+            TOML::Array(_) => "array",
+            TOML::InlineTable(_) => "inline-table",
+            TOML::Comment(_) => "comment",
+        }
+    }
+
+    fn fold_summary(&self) -> String {
+        // Pluralise a count of some noun
+        let count = |n: usize, noun: &str| match n {
+            1 => format!("1 {}", noun),
+            _ => format!("{} {}s", n, noun),
+        };
+        // Comments aren't counted as pairs or items
+        let values = |children: &[&TOML]| children.iter().filter(|c| !c.is_comment()).count();
+        match self {
+            TOML::Table(children) | TOML::ArrayTable(children) if self.has_header() => {
+                let (open, close) = match self {
+                    TOML::Table(_) => ("[", "]"),
+                    _ => ("[[", "]]"),
+                };
+                let header = header_index(children);
+                format!(
+                    "{}{}{} ({})",
+                    open,
+                    children[header].display_name(),
+                    close,
+                    count(values(&children[header + 1..]), "pair")
+                )
+            }
+            TOML::InlineTable(pairs) => format!("{{…}} ({})", count(pairs.len(), "pair")),
+            TOML::Array(items) => format!("[…] ({})", count(values(items), "item")),
+            TOML::Pair([key, _]) => format!("{} = …", key.display_name()),
+            _ => self.display_name(),
+        }
+    }
+
+    /* AST EDITING FUNCTIONS */
+
+    fn replace_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            // Tables can be swapped between the two kinds, keeping their contents
+            TOML::Table(_) | TOML::ArrayTable(_) => Self::table_chars(),
+            TOML::Comment(_) => Box::new(std::iter::once(CHAR_COMMENT)),
+            // Keys are changed by typing into them, and neither the document nor a pair can be
+            // replaced by anything that could take its place
+            TOML::Document(_) | TOML::Pair(_) | TOML::Key(_) => Box::new(std::iter::empty()),
+            _ => Self::value_chars(),
+        }
+    }
+
+    fn from_char(&self, c: char) -> Option<Self> {
+        // Swapping between the kinds of table keeps the header and pairs
+        let table_children = match self {
+            TOML::Table(children) | TOML::ArrayTable(children) => children.clone(),
+            _ => vec![],
+        };
+        match c {
+            CHAR_STRING => Some(TOML::Str(String::new())),
+            CHAR_TRUE => Some(TOML::Boolean(true)),
+            CHAR_FALSE => Some(TOML::Boolean(false)),
+            CHAR_ARRAY => Some(TOML::Array(vec![])),
+            CHAR_INLINE_TABLE => Some(TOML::InlineTable(vec![])),
+            '0'..='9' => Some(TOML::Integer(c.to_string())),
+            CHAR_TABLE => Some(TOML::Table(table_children)),
+            CHAR_ARRAY_TABLE => Some(TOML::ArrayTable(table_children)),
+            CHAR_COMMENT => Some(TOML::Comment(String::new())),
+            _ => None,
+        }
+    }
+
+    fn is_valid_child(&self, index: usize, child: &Self) -> bool {
+        match self {
+            TOML::Document(children) => match child {
+                TOML::Pair(_) | TOML::Comment(_) => !children[..index.min(children.len())]
+                    .iter()
+                    .any(|c| c.is_table()),
+                _ if child.has_header() => children
+                    .get(index + 1..)
+                    .is_none_or(|after| after.iter().all(|c| c.is_table())),
+                _ => false,
+            },
+            TOML::Table(children) | TOML::ArrayTable(children) => {
+                match index.cmp(&header_index(children)) {
+                    std::cmp::Ordering::Less => child.is_comment(),
+                    std::cmp::Ordering::Equal => matches!(child, TOML::Key(_)),
+                    std::cmp::Ordering::Greater => {
+                        matches!(child, TOML::Pair(_) | TOML::Comment(_))
+                    }
+                }
+            }
+            TOML::Pair(_) => match index {
+                0 => matches!(child, TOML::Key(_)),
+                _ => index == 1 && child.is_value(),
+            },
+            TOML::Array(_) => child.is_value() || child.is_comment(),
+            TOML::InlineTable(_) => matches!(child, TOML::Pair(_)),
+            TOML::Key(_)
+            | TOML::Str(_)
+            | TOML::Integer(_)
+            | TOML::Float(_)
+            | TOML::Boolean(_)
+            | TOML::Comment(_) => false,
+        }
+    }
+
+    fn unwrap_index(&self) -> Option<usize> {
+        match self {
+            // Unwrapping a pair gives its value
+            TOML::Pair(_) => Some(1),
+            TOML::Array(items) | TOML::InlineTable(items) if items.len() == 1 => Some(0),
+            _ => None,
+        }
+    }
+
+    fn wrap_in(&'arena self, c: char, arena: &'arena Arena<Self>) -> Option<&'arena Self> {
+        // Only values can be wrapped, since only a value could take the place of an array or
+        // inline table
+        if !self.is_value() {
+            return None;
+        }
+        match c {
+            CHAR_ARRAY => Some(arena.alloc(TOML::Array(vec![self]))),
+            CHAR_INLINE_TABLE => {
+                let key = arena.alloc(TOML::Key(String::new()));
+                let pair = arena.alloc(TOML::Pair([key, self]));
+                Some(arena.alloc(TOML::InlineTable(vec![pair])))
+            }
+            _ => None,
+        }
+    }
+
+    fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            TOML::Document(_) => Box::new(
+                Self::value_chars()
+                    .chain(std::iter::once(CHAR_COMMENT))
+                    .chain(Self::table_chars()),
+            ),
+            TOML::Table(_) | TOML::ArrayTable(_) | TOML::Array(_) => {
+                Box::new(Self::value_chars().chain(std::iter::once(CHAR_COMMENT)))
+            }
+            TOML::InlineTable(_) => Self::value_chars(),
+            TOML::Pair(_)
+            | TOML::Key(_)
+            | TOML::Str(_)
+            | TOML::Integer(_)
+            | TOML::Float(_)
+            | TOML::Boolean(_)
+            | TOML::Comment(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    fn push_char(&self, c: char) -> Option<Self> {
+        let number = match self {
+            TOML::Integer(number) | TOML::Float(number) => number,
+            _ => return None,
+        };
+        let new_number = match (c, number.chars().last()) {
+            // Typing '-' directly after an exponent sets the sign of the exponent, and otherwise
+            // toggles the sign of the whole number
+            ('-', Some('e')) | ('-', Some('E')) => format!("{}-", number),
+            ('-', _) => match number.strip_prefix('-') {
+                Some(positive) => positive.to_string(),
+                None => format!("-{}", number),
+            },
+            _ => format!("{}{}", number, c),
+        };
+        // Typing can turn an integer into a float (e.g. typing `.`) and back again
+        Self::number(new_number)
+    }
+
+    fn pop_char(&self) -> Option<Self> {
+        match self {
+            TOML::Integer(number) | TOML::Float(number) if number.chars().count() > 1 => {
+                let mut new_number = number.clone();
+                new_number.pop();
+                // Numbers that were already invalid (e.g. ones that were parsed) stay the same kind
+                Some(Self::number(new_number.clone()).unwrap_or(match self {
+                    TOML::Integer(_) => TOML::Integer(new_number),
+                    _ => TOML::Float(new_number),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    fn replace_text(&self, pattern: &str, replacement: &str) -> Option<Self> {
+        let text = self.text().filter(|_| !self.is_comment())?;
+        if pattern.is_empty() || !text.contains(pattern) {
+            return None;
+        }
+        self.with_text(&text.replace(pattern, replacement))
+    }
+
+    fn is_key_child(&self, index: usize) -> bool {
+        match self {
+            TOML::Pair(_) => index == 0,
+            TOML::Table(children) | TOML::ArrayTable(children) => index == header_index(children),
+            _ => false,
+        }
+    }
+
+    /// Comments always end their line
+    fn is_comment(&self) -> bool {
+        matches!(self, TOML::Comment(_))
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            TOML::Key(text) | TOML::Str(text) | TOML::Comment(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn with_text(&self, text: &str) -> Option<Self> {
+        match self {
+            TOML::Str(_) => Some(TOML::Str(text.to_string())),
+            // Neither keys nor comments can contain the newline that would end their line
+            TOML::Key(_) => Some(TOML::Key(text.replace('\n', " "))),
+            TOML::Comment(_) => Some(TOML::Comment(text.replace('\n', " "))),
+            _ => None,
+        }
+    }
+
+    fn text_offset(&self, index: usize) -> usize {
+        match self {
+            // Skip the opening quote, and the extra `\` of every escaped char
+            TOML::Str(string) => 1 + escape_string(&string[..index]).len(),
+            // An empty key is shown as `""`, so the text cursor goes between the quotes
+            TOML::Key(key) if key.is_empty() => 1,
+            TOML::Key(_) => index,
+            TOML::Comment(_) => 1 + index,
+            _ => 0,
+        }
+    }
+
+    fn validate(&self) -> Vec<ValidationError> {
+        let (children, first_pair) = match self {
+            TOML::Document(children) => (children, 0),
+            TOML::Table(children) | TOML::ArrayTable(children) => {
+                (children, header_index(children) + 1)
+            }
+            TOML::InlineTable(children) => (children, 0),
+            _ => return Vec::new(),
+        };
+        // The index of the first pair or table with each key.  Tables can't have the same header
+        // as each other, but can have the same header as a pair (e.g. a pair of the last table
+        // before them).  Arrays of tables are meant to have the same header many times.
+        let mut first_indices: HashMap<(bool, Vec<String>), usize> = HashMap::new();
+        let mut errors = Vec::new();
+        for (index, child) in children.iter().enumerate().skip(first_pair) {
+            let (key, is_table) = match child {
+                TOML::Pair([key, _]) => (key, false),
+                TOML::Table(c) if child.has_header() => (&c[header_index(c)], true),
+                _ => continue,
+            };
+            let id = (is_table, key.key_segments());
+            match first_indices.get(&id) {
+                Some(&first_index) => errors.push(ValidationError::DuplicateKey {
+                    key: key.display_name(),
+                    index,
+                    first_index,
+                }),
+                None => {
+                    first_indices.insert(id, index);
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// Returns the index of the header of a table with these children, which comes after the
+/// comments on the lines before it
+fn header_index(children: &[&TOML]) -> usize {
+    children
+        .iter()
+        .take_while(|child| child.is_comment())
+        .count()
+}
+
+/* ===== PARSING ===== */
+
+/// The maximum depth of nested arrays and inline tables that [`TOML::parse`] will accept, so that
+/// a pathological input like `a = [[[[[...` can't overflow the stack
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// The different ways that parsing TOML can fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// We expected to find some specific thing, but found either a different char or the end of
+    /// the input (if `found` is `None`)
+    Expected {
+        expected: &'static str,
+        found: Option<char>,
+    },
+    /// A backslash in a string was followed by a char that isn't a valid escape
+    InvalidEscape(char),
+    /// A `\u` or `\U` escape was malformed, or described an invalid codepoint
+    InvalidUnicodeEscape,
+    /// A value (like `1__0` or `yes`) which isn't any kind of TOML value
+    InvalidValue(String),
+    /// A value that's valid TOML, but that Sapling can't edit yet
+    Unsupported(&'static str),
+    /// The input contains arrays or inline tables nested more than [`MAX_NESTING_DEPTH`] levels
+    /// deep
+    TooDeeplyNested,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Expected {
+                expected,
+                found: Some(c),
+            } => write!(f, "expected {}, found {:?}", expected, c),
+            ParseErrorKind::Expected {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of input", expected),
+            ParseErrorKind::InvalidEscape(c) => write!(f, "invalid escape sequence '\\{}'", c),
+            ParseErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape sequence"),
+            ParseErrorKind::InvalidValue(value) => write!(f, "invalid value '{}'", value),
+            ParseErrorKind::Unsupported(what) => write!(f, "{} aren't supported yet", what),
+            ParseErrorKind::TooDeeplyNested => write!(
+                f,
+                "arrays and inline tables are nested more than {} levels deep",
+                MAX_NESTING_DEPTH
+            ),
+        }
+    }
+}
+
+/// An error generated when parsing TOML, along with the location of the error.  Both `line` and
+/// `column` start from 1, and `column` is measured in [`char`]s.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A recursive-descent TOML parser.  Unlike the other parsers, this doesn't keep the arena that
+/// nodes are allocated into, so that it can also be used to read keys (see [`key_segments`]).
+struct Parser<'t> {
+    text: &'t str,
+    /// The byte offset of the next char to be consumed
+    offset: usize,
+    /// The line of the next char to be consumed
+    line: usize,
+    /// The column of the next char to be consumed
+    column: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn new(text: &'t str) -> Self {
+        Parser {
+            text,
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Creates an error of a given kind at the current position
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    /// Creates an error saying that we expected something other than the next char
+    fn expected(&self, expected: &'static str) -> ParseError {
+        let found = self.peek();
+        self.error(ParseErrorKind::Expected { expected, found })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    /// Returns `true` if the unconsumed text starts with `prefix`
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.text[self.offset..].starts_with(prefix)
+    }
+
+    /// Consumes the next char, keeping track of the position in the text
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Consumes `prefix` if the unconsumed text starts with it, returning whether or not it did
+    fn eat(&mut self, prefix: &str) -> bool {
+        if !self.starts_with(prefix) {
+            return false;
+        }
+        for _ in prefix.chars() {
+            self.next();
+        }
+        true
+    }
+
+    /// Consumes `prefix`, or returns an error if the unconsumed text doesn't start with it
+    fn expect(&mut self, prefix: &str, expected: &'static str) -> Result<(), ParseError> {
+        if self.eat(prefix) {
+            Ok(())
+        } else {
+            Err(self.expected(expected))
+        }
+    }
+
+    /// Skips spaces and tabs (but not newlines, which end lines of the file)
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.next();
+        }
+    }
+
+    /// Consumes a `\n` or `\r\n`, returning whether or not there was one
+    fn eat_newline(&mut self) -> bool {
+        self.eat("\n") || self.eat("\r\n")
+    }
+
+    /// Parses the rest of a line after some content: any whitespace and an optional comment
+    /// (which is added to `items`), followed by a newline or the end of the input
+    fn parse_line_end<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+        items: &mut Vec<&'arena TOML<'arena>>,
+    ) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some('#') {
+            items.push(arena.alloc(self.parse_comment()));
+        }
+        if self.peek().is_none() || self.eat_newline() {
+            Ok(())
+        } else {
+            Err(self.expected("the end of the line"))
+        }
+    }
+
+    /// Skips whitespace and newlines, adding any comments to `items`
+    fn skip_trivia<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+        items: &mut Vec<&'arena TOML<'arena>>,
+    ) {
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('#') {
+                items.push(arena.alloc(self.parse_comment()));
+            } else if !self.eat_newline() {
+                return;
+            }
+        }
+    }
+
+    /// Parses a `#` comment, which ends at the end of its line
+    fn parse_comment<'arena>(&mut self) -> TOML<'arena> {
+        self.next();
+        let mut comment = String::new();
+        while let Some(c) = self.peek().filter(|&c| c != '\n') {
+            comment.push(c);
+            self.next();
+        }
+        // Don't keep the '\r' of a '\r\n' line ending
+        if comment.ends_with('\r') {
+            comment.pop();
+        }
+        TOML::Comment(comment)
+    }
+
+    /// Parses an entire file into a [`Document`](TOML::Document)
+    fn parse_document<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> Result<&'arena TOML<'arena>, ParseError> {
+        let mut document = Vec::new();
+        // The pairs and comments of the table that's being parsed (or of the document itself,
+        // before the first table header), along with the table's kind and header
+        let mut items = Vec::new();
+        let mut table: Option<(bool, Vec<&'arena TOML<'arena>>)> = None;
+        // How many of the last items are comments on lines of their own, with no blank line
+        // after them.  If a table header comes next, then these are about that table.
+        let mut comments_before = 0;
+        // Adds the table that has just been parsed to the document
+        let finish_table =
+            |document: &mut Vec<_>, items: Vec<_>, table: Option<(bool, Vec<_>)>| match table {
+                None => document.extend(items),
+                Some((is_array, mut children)) => {
+                    children.extend(items);
+                    document.push(arena.alloc(if is_array {
+                        TOML::ArrayTable(children)
+                    } else {
+                        TOML::Table(children)
+                    }));
+                }
+            };
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some('#') => {
+                    let comment = self.parse_comment();
+                    items.push(arena.alloc(comment));
+                    comments_before += 1;
+                    self.parse_line_end(arena, &mut items)?;
+                }
+                Some('[') => {
+                    let is_array = self.eat("[[");
+                    if !is_array {
+                        self.next();
+                    }
+                    self.skip_whitespace();
+                    let header = arena.alloc(TOML::Key(key_text(&self.parse_key()?)));
+                    self.skip_whitespace();
+                    if is_array {
+                        self.expect("]]", "']]'")?;
+                    } else {
+                        self.expect("]", "']'")?;
+                    }
+                    let mut children = items.split_off(items.len() - comments_before);
+                    children.push(header);
+                    comments_before = 0;
+                    finish_table(&mut document, std::mem::take(&mut items), table);
+                    table = Some((is_array, children));
+                    self.parse_line_end(arena, &mut items)?;
+                }
+                Some(_) => {
+                    // Blank lines aren't kept
+                    comments_before = 0;
+                    if self.eat_newline() {
+                        continue;
+                    }
+                    let pair = self.parse_pair(arena, 0)?;
+                    items.push(arena.alloc(pair));
+                    self.parse_line_end(arena, &mut items)?;
+                }
+            }
+        }
+        finish_table(&mut document, items, table);
+        Ok(arena.alloc(TOML::Document(document)))
+    }
+
+    /// Parses a `key = value` pair
+    fn parse_pair<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+        depth: usize,
+    ) -> Result<TOML<'arena>, ParseError> {
+        let key = arena.alloc(TOML::Key(key_text(&self.parse_key()?)));
+        self.skip_whitespace();
+        self.expect("=", "'='")?;
+        self.skip_whitespace();
+        let value = self.parse_value(arena, depth)?;
+        Ok(TOML::Pair([key, arena.alloc(value)]))
+    }
+
+    /// Parses a (possibly dotted) key, returning its segments
+    fn parse_key(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut segments = Vec::new();
+        loop {
+            let segment = match self.peek() {
+                Some('"') => self.parse_basic_string()?,
+                Some('\'') => self.parse_literal_string()?,
+                Some(c) if is_bare_key_char(c) => {
+                    let mut segment = String::new();
+                    while let Some(c) = self.peek().filter(|&c| is_bare_key_char(c)) {
+                        segment.push(c);
+                        self.next();
+                    }
+                    segment
+                }
+                _ => return Err(self.expected("a key")),
+            };
+            segments.push(segment);
+            // Dots can have whitespace around them, but the whitespace after the key isn't part
+            // of it
+            let offset = (self.offset, self.line, self.column);
+            self.skip_whitespace();
+            if !self.eat(".") {
+                self.offset = offset.0;
+                self.line = offset.1;
+                self.column = offset.2;
+                return Ok(segments);
+            }
+            self.skip_whitespace();
+        }
+    }
+
+    /// Parses a single value, which must start at the next char
+    fn parse_value<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+        depth: usize,
+    ) -> Result<TOML<'arena>, ParseError> {
+        match self.peek() {
+            Some('"') if self.starts_with(r#"""""#) => Ok(TOML::Str(self.parse_multiline('"')?)),
+            Some('"') => Ok(TOML::Str(self.parse_basic_string()?)),
+            Some('\'') if self.starts_with("'''") => Ok(TOML::Str(self.parse_multiline('\'')?)),
+            Some('\'') => Ok(TOML::Str(self.parse_literal_string()?)),
+            Some('[') | Some('{') if depth >= MAX_NESTING_DEPTH => {
+                Err(self.error(ParseErrorKind::TooDeeplyNested))
+            }
+            Some('[') => self.parse_array(arena, depth + 1),
+            Some('{') => self.parse_inline_table(arena, depth + 1),
+            _ => {
+                let error = self.error(ParseErrorKind::InvalidValue(String::new()));
+                let mut token = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|&c| c.is_ascii_alphanumeric() || "_+-.:".contains(c))
+                {
+                    token.push(c);
+                    self.next();
+                }
+                let kind = match token.as_str() {
+                    "" => return Err(self.expected("a value")),
+                    "true" => return Ok(TOML::Boolean(true)),
+                    "false" => return Ok(TOML::Boolean(false)),
+                    _ => match number_kind(&token) {
+                        Some(NumberKind::Integer) => return Ok(TOML::Integer(token)),
+                        Some(NumberKind::Float) => return Ok(TOML::Float(token)),
+                        // Dates look like `1979-05-27`, and times like `07:32:00`
+                        None if token.contains(':')
+                            || (token.get(4..5) == Some("-")
+                                && token[..4].chars().all(|c| c.is_ascii_digit())) =>
+                        {
+                            ParseErrorKind::Unsupported("dates and times")
+                        }
+                        None => ParseErrorKind::InvalidValue(token),
+                    },
+                };
+                Err(ParseError { kind, ..error })
+            }
+        }
+    }
+
+    /// Parses an array, which can be spread over many lines and contain comments
+    fn parse_array<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+        depth: usize,
+    ) -> Result<TOML<'arena>, ParseError> {
+        self.next();
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia(arena, &mut items);
+            if self.eat("]") {
+                return Ok(TOML::Array(items));
+            }
+            let value = self.parse_value(arena, depth)?;
+            items.push(arena.alloc(value));
+            self.skip_trivia(arena, &mut items);
+            if self.eat("]") {
+                return Ok(TOML::Array(items));
+            }
+            self.expect(",", "',' or ']'")?;
+        }
+    }
+
+    /// Parses an inline table, which has to be on one line (apart from inside its values)
+    fn parse_inline_table<'arena>(
+        &mut self,
+        arena: &'arena Arena<TOML<'arena>>,
+        depth: usize,
+    ) -> Result<TOML<'arena>, ParseError> {
+        self.next();
+        let mut pairs = Vec::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(TOML::InlineTable(pairs));
+        }
+        loop {
+            self.skip_whitespace();
+            let pair = self.parse_pair(arena, depth)?;
+            pairs.push(arena.alloc(pair));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(TOML::InlineTable(pairs));
+            }
+            self.expect(",", "',' or '}'")?;
+        }
+    }
+
+    /// Parses a single-line `"`-quoted string, including the quotes
+    fn parse_basic_string(&mut self) -> Result<String, ParseError> {
+        self.next();
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.next();
+                    return Ok(string);
+                }
+                Some('\\') => {
+                    self.next();
+                    string.push(self.parse_escape()?);
+                }
+                Some('\n') | None => return Err(self.expected("'\"'")),
+                Some(c) => {
+                    string.push(c);
+                    self.next();
+                }
+            }
+        }
+    }
+
+    /// Parses a single-line `'`-quoted string, which has no escape sequences
+    fn parse_literal_string(&mut self) -> Result<String, ParseError> {
+        self.next();
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                Some('\'') => {
+                    self.next();
+                    return Ok(string);
+                }
+                Some('\n') | None => return Err(self.expected("\"'\"")),
+                Some(c) => {
+                    string.push(c);
+                    self.next();
+                }
+            }
+        }
+    }
+
+    /// Parses a multi-line string between three `quote`s (which is `"` for basic strings and `'`
+    /// for literal strings)
+    fn parse_multiline(&mut self, quote: char) -> Result<String, ParseError> {
+        let delimiter: String = std::iter::repeat_n(quote, 3).collect();
+        self.eat(&delimiter);
+        // A newline straight after the opening quotes isn't part of the string
+        self.eat_newline();
+        let mut string = String::new();
+        loop {
+            if self.starts_with(&delimiter) {
+                // Up to two quotes can go right before the closing quotes
+                let mut quotes = 0;
+                while quotes < 5 && self.peek() == Some(quote) {
+                    self.next();
+                    quotes += 1;
+                }
+                string.extend(std::iter::repeat_n(quote, quotes - 3));
+                return Ok(string);
+            }
+            match self.next() {
+                Some('\\') if quote == '"' => {
+                    // A backslash at the end of a line removes all the whitespace after it
+                    let rest = &self.text[self.offset..];
+                    let line_rest = rest.trim_start_matches(&[' ', '\t'][..]);
+                    if line_rest.starts_with('\n') || line_rest.starts_with("\r\n") {
+                        while self.peek().is_some_and(char::is_whitespace) {
+                            self.next();
+                        }
+                    } else {
+                        string.push(self.parse_escape()?);
+                    }
+                }
+                Some('\r') if self.peek() == Some('\n') => {}
+                Some(c) => string.push(c),
+                None => {
+                    return Err(self.expected(if quote == '"' { r#"'"""'"# } else { r#""'''""# }))
+                }
+            }
+        }
+    }
+
+    /// Parses the rest of an escape sequence, after the `\`
+    fn parse_escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('u') => {
+                self.next();
+                return self.parse_unicode_escape(4);
+            }
+            Some('U') => {
+                self.next();
+                return self.parse_unicode_escape(8);
+            }
+            Some(c) => return Err(self.error(ParseErrorKind::InvalidEscape(c))),
+            None => return Err(self.expected("an escape sequence")),
+        };
+        self.next();
+        Ok(c)
+    }
+
+    /// Parses the `digits` hex digits of a `\uXXXX` or `\UXXXXXXXX` escape sequence
+    fn parse_unicode_escape(&mut self, digits: usize) -> Result<char, ParseError> {
+        let mut code = 0;
+        for _ in 0..digits {
+            let digit = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error(ParseErrorKind::InvalidUnicodeEscape))?;
+            code = code * 16 + digit;
+            self.next();
+        }
+        std::char::from_u32(code).ok_or_else(|| self.error(ParseErrorKind::InvalidUnicodeEscape))
+    }
+}
+
+impl<'arena> TOML<'arena> {
+    /// Parses the text of a TOML file into a [`Document`](TOML::Document), allocating all the
+    /// nodes into a given [`Arena`] and returning a reference to the root node.
+    pub fn parse(
+        text: &str,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> Result<&'arena TOML<'arena>, ParseError> {
+        Parser::new(text).parse_document(arena)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::display_token::{
+        DisplayToken, FormatKind, IndentKind, RenderStyle, SyntaxCategory,
+    };
+    use super::super::size::Size;
+    use super::super::test_json::Rng;
+    use super::{
+        key_text, number_kind, NumberKind, ParseError, ParseErrorKind, TOMLFormat,
+        MAX_NESTING_DEPTH, TOML,
+    };
+    use crate::arena::Arena;
+    use crate::ast::{Ast, DeleteError, InsertError, MoveError, ValidationError};
+    use crate::config::options::Options;
+    use crate::language::Language;
+
+    const PRETTY: TOMLFormat = TOMLFormat::Pretty {
+        indent: IndentKind::Spaces(2),
+        trailing_commas: false,
+    };
+
+    /// Parses some text, and returns the value of its only pair
+    fn parse_value<'arena>(text: &str, arena: &'arena Arena<TOML<'arena>>) -> &'arena TOML<'arena> {
+        let document = TOML::parse(&format!("a = {}", text), arena).unwrap();
+        assert_eq!(document.children().len(), 1);
+        document.children()[0].children()[1]
+    }
+
+    #[test]
+    fn to_text() {
+        let arena = Arena::new();
+        let root = TOML::parse(
+            "# top\ntitle = \"x\"\n[package]\nname = \"sapling\"\n[[bin]]\nname = \"a\"\n[[bin]]",
+            &arena,
+        )
+        .unwrap();
+        assert_eq!(
+            root.to_text(&TOMLFormat::Compact),
+            "# top\ntitle=\"x\"\n[package]\nname=\"sapling\"\n[[bin]]\nname=\"a\"\n[[bin]]"
+        );
+        assert_eq!(
+            root.to_text(&PRETTY),
+            "# top\ntitle = \"x\"\n\n[package]\nname = \"sapling\"\n\n[[bin]]\nname = \"a\"\n\n[[bin]]"
+        );
+        // Arrays are only broken if they contain comments, arrays or inline tables
+        let flat = parse_value("[1, 2]", &arena);
+        assert_eq!(flat.to_text(&PRETTY), "[1, 2]");
+        assert_eq!(flat.to_text(&TOMLFormat::Compact), "[1,2]");
+        let nested = parse_value("[[1], { b = true }]", &arena);
+        assert_eq!(nested.to_text(&PRETTY), "[\n  [1],\n  { b = true }\n]");
+        assert_eq!(nested.to_text(&TOMLFormat::Compact), "[[1],{b=true}]");
+        let trailing = TOMLFormat::Pretty {
+            indent: IndentKind::Tabs,
+            trailing_commas: true,
+        };
+        assert_eq!(nested.to_text(&trailing), "[\n\t[1],\n\t{ b = true },\n]");
+        // Comments always end their line, even when the array isn't broken
+        let comment = parse_value("[1, # one\n 2]", &arena);
+        assert_eq!(comment.to_text(&TOMLFormat::Compact), "[1,# one\n2]");
+        assert_eq!(comment.to_text(&PRETTY), "[\n  1,\n  # one\n  2\n]");
+        // A comment at the end of a line goes on a line of its own
+        let root = TOML::parse("a = 1 # one\n[t] # header", &arena).unwrap();
+        assert_eq!(root.to_text(&PRETTY), "a = 1\n# one\n\n[t]\n# header");
+        assert_eq!(parse_value("{}", &arena).to_text(&PRETTY), "{}");
+        assert_eq!(parse_value("[]", &arena).to_text(&PRETTY), "[]");
+        assert_eq!(TOML::default().to_text(&PRETTY), "");
+    }
+
+    #[test]
+    fn number_kinds() {
+        for (text, kind) in &[
+            ("0", Some(NumberKind::Integer)),
+            ("+99", Some(NumberKind::Integer)),
+            ("-17", Some(NumberKind::Integer)),
+            ("1_000", Some(NumberKind::Integer)),
+            ("0xDEAD_beef", Some(NumberKind::Integer)),
+            ("0o755", Some(NumberKind::Integer)),
+            ("0b1101", Some(NumberKind::Integer)),
+            ("3.1415", Some(NumberKind::Float)),
+            ("-0.01", Some(NumberKind::Float)),
+            ("5e+22", Some(NumberKind::Float)),
+            ("6.626e-34", Some(NumberKind::Float)),
+            ("1e06", Some(NumberKind::Float)),
+            ("224_617.445_991", Some(NumberKind::Float)),
+            ("-inf", Some(NumberKind::Float)),
+            ("nan", Some(NumberKind::Float)),
+            ("", None),
+            ("01", None),
+            ("1__0", None),
+            ("_1", None),
+            ("1_", None),
+            ("+0x1", None),
+            ("0o8", None),
+            (".5", None),
+            ("5.", None),
+            ("1e", None),
+            ("1.e5", None),
+            ("inf.0", None),
+        ] {
+            assert_eq!(number_kind(text), *kind, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn syntax_categories() {
+        let arena = Arena::new();
+        let items = vec![
+            arena.alloc(TOML::Str("x\n".to_string())),
+            arena.alloc(TOML::Integer("1".to_string())),
+            arena.alloc(TOML::Float("1.5".to_string())),
+            // Numbers are errors whilst they're incomplete, or if they're the wrong kind
+            arena.alloc(TOML::Integer("0x".to_string())),
+            arena.alloc(TOML::Integer("1.5".to_string())),
+            arena.alloc(TOML::Boolean(true)),
+        ];
+        let key = arena.alloc(TOML::Key(r#""a b""#.to_string()));
+        let bad_key = arena.alloc(TOML::Key("a b".to_string()));
+        let pair = arena.alloc(TOML::Pair([key, arena.alloc(TOML::Array(items))]));
+        let bad_pair = arena.alloc(TOML::Pair([bad_key, arena.alloc(TOML::Boolean(false))]));
+        let comment = arena.alloc(TOML::Comment(" c".to_string()));
+        let header = arena.alloc(TOML::Key("t".to_string()));
+        let root = arena.alloc(TOML::Table(vec![header, pair, bad_pair, comment]));
+        let categories: Vec<(String, SyntaxCategory)> = root
+            .display_tokens(&TOMLFormat::Compact)
+            .filter_map(|(_, tok)| match tok {
+                DisplayToken::Text(text, category) => Some((text.into_owned(), category)),
+                _ => None,
+            })
+            .collect();
+        let expected = [
+            ("[", SyntaxCategory::Default),
+            ("t", SyntaxCategory::Custom("key")),
+            ("]", SyntaxCategory::Default),
+            (r#""a b""#, SyntaxCategory::Custom("key")),
+            ("=", SyntaxCategory::Default),
+            ("[", SyntaxCategory::Default),
+            ("\"x", SyntaxCategory::Literal),
+            (r"\n", SyntaxCategory::Special),
+            ("\"", SyntaxCategory::Literal),
+            (",", SyntaxCategory::Default),
+            ("1", SyntaxCategory::Literal),
+            (",", SyntaxCategory::Default),
+            ("1.5", SyntaxCategory::Literal),
+            (",", SyntaxCategory::Default),
+            ("0x", SyntaxCategory::Error),
+            (",", SyntaxCategory::Default),
+            ("1.5", SyntaxCategory::Error),
+            (",", SyntaxCategory::Default),
+            ("true", SyntaxCategory::Const),
+            ("]", SyntaxCategory::Default),
+            ("a b", SyntaxCategory::Error),
+            ("=", SyntaxCategory::Default),
+            ("false", SyntaxCategory::Const),
+            ("# c", SyntaxCategory::Comment),
+        ];
+        assert_eq!(
+            categories,
+            expected
+                .iter()
+                .map(|&(text, category)| (text.to_string(), category))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        for text in &[
+            "",
+            "a=1",
+            "# only a comment",
+            "a.b.\"c d\"=\"e\"\n\"\"=true",
+            "[package]\nname=\"sapling\"\nversion=\"0.1.0\"\nedition=\"2018\"",
+            "[dependencies]\ntuikit=\"0.4.5\"\n[dependencies.serde]\nfeatures=[\"derive\"]",
+            "[[bin]]\nname=\"a\"\n[[bin]]\nname=\"b\"",
+            "x=[1,-2.5,+inf,0xff,1_000,5e+22,false]",
+            "x=[[],{},{a=1,b={c=[2]}}]",
+            "x=[# start\n1,# one\n2]",
+            "s=\"tab\\tquote\\\"\\u0001日本語🎉\"",
+            "[t]\n# comment\nk=\"v\"",
+            "# about a\n[a]\n# end of a\n\n# about b\n# still about b\n[b]",
+            "# top\n\n[a]",
+        ] {
+            let arena = Arena::new();
+            let root = TOML::parse(text, &arena).unwrap();
+            // Compact formatting should exactly reproduce our (compactly formatted) inputs
+            assert_eq!(root.to_text(&TOMLFormat::Compact), *text);
+            // Pretty formatting should reparse to the same tree
+            let pretty_text = root.to_text(&PRETTY);
+            assert_eq!(TOML::parse(&pretty_text, &arena), Ok(root));
+        }
+    }
+
+    #[test]
+    fn parse_structure() {
+        let arena = Arena::new();
+        let root = TOML::parse(
+            "\r\n a = 1 \r\n\n[ t . 'u v' ]  # c\r\nb = { x = 'y' }\n",
+            &arena,
+        )
+        .unwrap();
+        let a = TOML::Key("a".to_string());
+        let one = TOML::Integer("1".to_string());
+        let pair_a = TOML::Pair([&a, &one]);
+        let header = TOML::Key(r#"t."u v""#.to_string());
+        let comment = TOML::Comment(" c".to_string());
+        let b = TOML::Key("b".to_string());
+        let x = TOML::Key("x".to_string());
+        let y = TOML::Str("y".to_string());
+        let pair_x = TOML::Pair([&x, &y]);
+        let inline = TOML::InlineTable(vec![&pair_x]);
+        let pair_b = TOML::Pair([&b, &inline]);
+        let table = TOML::Table(vec![&header, &comment, &pair_b]);
+        assert_eq!(*root, TOML::Document(vec![&pair_a, &table]));
+        assert_eq!(
+            key_text(&["a.b".to_string(), "c".to_string()]),
+            r#""a.b".c"#
+        );
+    }
+
+    #[test]
+    fn comments_before_headers() {
+        // Comments on the lines just before a header are about that table, but ones followed by
+        // a blank line aren't
+        let arena = Arena::new();
+        let text =
+            "# Top\n\n[dependencies.sapling]\npath = \"..\"\n\n# Keep out of any workspace\n\
+                    [workspace]\nmembers = [\".\"]\n# Last\n";
+        let root = TOML::parse(text, &arena).unwrap();
+        let top = TOML::Comment(" Top".to_string());
+        let dependencies = root.children()[1];
+        let workspace = root.children()[2];
+        assert_eq!(root.children()[0], &top);
+        assert_eq!(dependencies.children().len(), 2);
+        assert_eq!(
+            workspace.children()[..2],
+            [
+                &TOML::Comment(" Keep out of any workspace".to_string()),
+                &TOML::Key("workspace".to_string())
+            ]
+        );
+        assert_eq!(workspace.children()[3], &TOML::Comment(" Last".to_string()));
+        assert_eq!(workspace.fold_summary(), "[workspace] (1 pair)");
+        // Reprinting keeps every comment where it was
+        assert_eq!(root.to_text(&PRETTY), text.trim_end());
+        let compact = root.to_text(&TOMLFormat::Compact);
+        assert_eq!(
+            compact,
+            "# Top\n\n[dependencies.sapling]\npath=\"..\"\n# Keep out of any workspace\n\
+             [workspace]\nmembers=[\".\"]\n# Last"
+        );
+        assert_eq!(TOML::parse(&compact, &arena), Ok(root));
+    }
+
+    #[test]
+    fn parse_strings() {
+        let arena = Arena::new();
+        for (text, string) in &[
+            (r#""a\"b\\c\n\b\f\r""#, "a\"b\\c\n\u{8}\u{c}\r"),
+            (r#""\u00e9\U0001F389""#, "é🎉"),
+            (r"'C:\Users'", r"C:\Users"),
+            ("\"\"\"\nline one\nline two\"\"\"", "line one\nline two"),
+            ("\"\"\"a \\\n    b \\\r\n\n  c\"\"\"", "a b c"),
+            ("\"\"\"say \"hi\"\"\"\"\"", "say \"hi\"\""),
+            ("'''\n\\n is ''literal'''''", "\\n is ''literal''"),
+        ] {
+            assert_eq!(
+                *parse_value(text, &arena),
+                TOML::Str(string.to_string()),
+                "parsing {}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line, column, kind) in &[
+            (
+                "a",
+                1,
+                2,
+                ParseErrorKind::Expected {
+                    expected: "'='",
+                    found: None,
+                },
+            ),
+            (
+                "a = 1 2",
+                1,
+                7,
+                ParseErrorKind::Expected {
+                    expected: "the end of the line",
+                    found: Some('2'),
+                },
+            ),
+            (
+                "[a\n",
+                1,
+                3,
+                ParseErrorKind::Expected {
+                    expected: "']'",
+                    found: Some('\n'),
+                },
+            ),
+            (
+                "= 1",
+                1,
+                1,
+                ParseErrorKind::Expected {
+                    expected: "a key",
+                    found: Some('='),
+                },
+            ),
+            (
+                "a = [1 2]",
+                1,
+                8,
+                ParseErrorKind::Expected {
+                    expected: "',' or ']'",
+                    found: Some('2'),
+                },
+            ),
+            (
+                "a = {b = 1,}",
+                1,
+                12,
+                ParseErrorKind::Expected {
+                    expected: "a key",
+                    found: Some('}'),
+                },
+            ),
+            (
+                "a = {\n}",
+                1,
+                6,
+                ParseErrorKind::Expected {
+                    expected: "a key",
+                    found: Some('\n'),
+                },
+            ),
+            (
+                "a = \"b\nc\"",
+                1,
+                7,
+                ParseErrorKind::Expected {
+                    expected: "'\"'",
+                    found: Some('\n'),
+                },
+            ),
+            ("a = \"\\q\"", 1, 7, ParseErrorKind::InvalidEscape('q')),
+            ("a = \"\\u12\"", 1, 10, ParseErrorKind::InvalidUnicodeEscape),
+            (
+                "a = \"\\ud800\"",
+                1,
+                12,
+                ParseErrorKind::InvalidUnicodeEscape,
+            ),
+            (
+                "a = yes",
+                1,
+                5,
+                ParseErrorKind::InvalidValue("yes".to_string()),
+            ),
+            (
+                "a = 01",
+                1,
+                5,
+                ParseErrorKind::InvalidValue("01".to_string()),
+            ),
+            (
+                "\na = 1979-05-27",
+                2,
+                5,
+                ParseErrorKind::Unsupported("dates and times"),
+            ),
+        ] {
+            let arena = Arena::new();
+            assert_eq!(
+                TOML::parse(text, &arena),
+                Err(ParseError {
+                    line: *line,
+                    column: *column,
+                    kind: kind.clone()
+                }),
+                "parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let arena = Arena::new();
+        let text =
+            "a = ".to_string() + &"[".repeat(MAX_NESTING_DEPTH) + &"]".repeat(MAX_NESTING_DEPTH);
+        assert!(TOML::parse(&text, &arena).is_ok());
+        let text = "a = ".to_string()
+            + &"[{b=".repeat(MAX_NESTING_DEPTH / 2)
+            + "[1]"
+            + &"}]".repeat(MAX_NESTING_DEPTH / 2);
+        assert_eq!(
+            TOML::parse(&text, &arena).unwrap_err().kind,
+            ParseErrorKind::TooDeeplyNested
+        );
+    }
+
+    #[test]
+    fn fit_to_width() {
+        let arena = Arena::new();
+        let root = TOML::parse("k = [[1, 2], { a = [] }]", &arena).unwrap();
+        let mut format = PRETTY;
+        for (max_width, expected_text) in &[
+            (20, "k = [[1, 2], { a = [] }]"),
+            (19, "k = [\n  [1, 2],\n  { a = [] }\n]"),
+            (6, "k = [\n  [1, 2],\n  { a = [] }\n]"),
+            (5, "k = [\n  [\n    1,\n    2\n  ],\n  { a = [] }\n]"),
+        ] {
+            format.set_kind(FormatKind::Fit {
+                max_width: *max_width,
+            });
+            assert_eq!(root.to_text(&format), *expected_text);
+            assert_eq!(root.size(&format), Size::from(*expected_text));
+        }
+        // Changing the kind of style keeps the indentation and trailing commas
+        let options = Options {
+            trailing_commas: true,
+            ..Options::default()
+        };
+        format.apply_options(&options, Language::TOML);
+        format.set_kind(FormatKind::Pretty);
+        assert_eq!(
+            format,
+            TOMLFormat::Pretty {
+                indent: IndentKind::Spaces(2),
+                trailing_commas: true,
+            }
+        );
+        format.set_kind(FormatKind::Compact);
+        assert_eq!(format.indent(), IndentKind::Spaces(0));
+    }
+
+    /// Generates a pseudo-random value which is at most `max_depth` levels deep
+    fn random_value<'arena>(
+        rng: &mut Rng,
+        max_depth: usize,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> &'arena TOML<'arena> {
+        const NUMBERS: &[&str] = &["0", "-17", "1_000", "0xff", "1.5", "-1e-9", "+inf", "nan"];
+        let kinds = if max_depth == 0 { 4 } else { 6 };
+        let node = match rng.below(kinds) {
+            0 => TOML::Str(random_string(rng)),
+            1 => TOML::Boolean(rng.below(2) == 0),
+            2 | 3 => TOML::number(NUMBERS[rng.below(NUMBERS.len())].to_string()).unwrap(),
+            4 => TOML::Array(
+                (0..rng.below(4))
+                    .map(|_| match rng.below(4) {
+                        0 => random_comment(rng, arena),
+                        _ => random_value(rng, max_depth - 1, arena),
+                    })
+                    .collect(),
+            ),
+            _ => TOML::InlineTable(
+                (0..rng.below(3))
+                    .map(|_| random_pair(rng, max_depth - 1, arena))
+                    .collect(),
+            ),
+        };
+        arena.alloc(node)
+    }
+
+    /// Generates a short pseudo-random string, which is full of chars that need escaping or
+    /// quoting
+    fn random_string(rng: &mut Rng) -> String {
+        const CHARS: &[char] = &[
+            'a', ' ', '0', '.', '#', '=', '"', '\'', '\\', '\n', '\t', '\u{1}', '日', '\u{301}',
+            '😀',
+        ];
+        (0..rng.below(5))
+            .map(|_| CHARS[rng.below(CHARS.len())])
+            .collect()
+    }
+
+    fn random_comment<'arena>(
+        rng: &mut Rng,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> &'arena TOML<'arena> {
+        let text = random_string(rng).replace(&['\n', '\u{1}'][..], " ");
+        arena.alloc(TOML::Comment(text))
+    }
+
+    fn random_key<'arena>(
+        rng: &mut Rng,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> &'arena TOML<'arena> {
+        let segments: Vec<String> = (0..1 + rng.below(2)).map(|_| random_string(rng)).collect();
+        arena.alloc(TOML::Key(key_text(&segments)))
+    }
+
+    fn random_pair<'arena>(
+        rng: &mut Rng,
+        max_depth: usize,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> &'arena TOML<'arena> {
+        let key = random_key(rng, arena);
+        arena.alloc(TOML::Pair([key, random_value(rng, max_depth, arena)]))
+    }
+
+    /// Generates the pairs and comments of a pseudo-random table
+    fn random_items<'arena>(
+        rng: &mut Rng,
+        arena: &'arena Arena<TOML<'arena>>,
+    ) -> Vec<&'arena TOML<'arena>> {
+        (0..rng.below(4))
+            .map(|_| match rng.below(4) {
+                0 => random_comment(rng, arena),
+                _ => random_pair(rng, 3, arena),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn size_matches_text() {
+        // The size of a node should always be the size of the text that it's rendered as, and
+        // the text should parse back into the same tree
+        let formats = [
+            TOMLFormat::Compact,
+            PRETTY,
+            TOMLFormat::Pretty {
+                indent: IndentKind::Tabs,
+                trailing_commas: true,
+            },
+            TOMLFormat::Fit {
+                indent: IndentKind::default(),
+                max_width: 0,
+                trailing_commas: false,
+            },
+            TOMLFormat::Fit {
+                indent: IndentKind::Spaces(2),
+                max_width: 30,
+                trailing_commas: true,
+            },
+        ];
+        let mut rng = Rng::new(0);
+        for _ in 0..500 {
+            let arena = Arena::new();
+            let mut children = random_items(&mut rng, &arena);
+            for _ in 0..rng.below(3) {
+                let mut table = vec![random_key(&mut rng, &arena)];
+                table.extend(random_items(&mut rng, &arena));
+                children.push(arena.alloc(match rng.below(2) {
+                    0 => TOML::Table(table),
+                    _ => TOML::ArrayTable(table),
+                }));
+            }
+            let root = arena.alloc(TOML::Document(children));
+            for format in &formats {
+                let text = root.to_text(format);
+                assert_eq!(root.size(format), Size::from(text.as_str()), "{}", text);
+                assert_eq!(TOML::parse(&text, &arena), Ok(root), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn editing_atoms() {
+        let number = TOML::Integer("1".to_string());
+        // Typing can turn an integer into a float and back
+        let float = number.push_char('.').unwrap();
+        assert_eq!(float, TOML::Float("1.".to_string()));
+        assert_eq!(float.pop_char(), Some(number.clone()));
+        let number = number.push_char('_').unwrap().push_char('0').unwrap();
+        assert_eq!(number, TOML::Integer("1_0".to_string()));
+        assert_eq!(
+            number.push_char('-'),
+            Some(TOML::Integer("-1_0".to_string()))
+        );
+        assert_eq!(number.push_char('x'), None);
+        assert_eq!(
+            TOML::Integer("0".to_string()).push_char('x'),
+            Some(TOML::Integer("0x".to_string()))
+        );
+        assert_eq!(TOML::Integer("1".to_string()).pop_char(), None);
+        assert_eq!(number.text(), None);
+
+        // Keys, strings and comments all have text that can be typed into
+        let key = TOML::Key(String::new());
+        assert_eq!(key.to_text(&PRETTY), r#""""#);
+        assert_eq!(key.text_offset(0), 1);
+        assert_eq!(TOML::Key("ab".to_string()).text_offset(1), 1);
+        assert_eq!(TOML::Str("\"x".to_string()).text_offset(1), 3);
+        assert_eq!(TOML::Comment(String::new()).text_offset(0), 1);
+        assert_eq!(key.with_text("a\nb"), Some(TOML::Key("a b".to_string())));
+        assert_eq!(
+            TOML::Key("foo-bar".to_string()).replace_text("-", "_"),
+            Some(TOML::Key("foo_bar".to_string()))
+        );
+        assert_eq!(TOML::Comment("-".to_string()).replace_text("-", "_"), None);
+    }
+
+    #[test]
+    fn structure() {
+        let arena = Arena::new();
+        let root = TOML::parse("a = 1\n[t]\nb = 2", &arena).unwrap();
+        let table = root.children()[1];
+        let pair = root.children()[0];
+        let value = pair.children()[1];
+        let comment = arena.alloc(TOML::Comment(String::new()));
+
+        // Values are given an empty key when they're inserted into a table
+        let mut document = root.clone();
+        document.insert_child(value, &arena, 1).unwrap();
+        assert_eq!(document.to_text(&PRETTY), "a = 1\n\"\" = 1\n\n[t]\nb = 2");
+        // Pairs and comments can't go after a table, and tables can't go before a pair
+        assert_eq!(
+            document.insert_child(comment, &arena, 3),
+            Err(InsertError::InvalidPosition {
+                parent_kind: "document",
+                child_kind: "comment",
+                index: 3,
+            })
+        );
+        assert_eq!(
+            document.insert_child(table, &arena, 0),
+            Err(InsertError::InvalidPosition {
+                parent_kind: "document",
+                child_kind: "table",
+                index: 0,
+            })
+        );
+        // Replacing a child can't put the children out of order either
+        assert!(document.is_valid_child(2, comment));
+        assert!(document.is_valid_child(1, table));
+        assert!(!document.is_valid_child(0, table));
+        // New tables are given an empty header
+        let new_table = arena.alloc(root.from_char('A').unwrap());
+        document.insert_child(new_table, &arena, 3).unwrap();
+        assert_eq!(document.children()[3].to_text(&PRETTY), "[[\"\"]]");
+        assert!(!document.is_valid_child(3, comment));
+        assert_eq!(
+            document.move_child(0, 3),
+            Err(MoveError::InvalidPosition {
+                parent_kind: "document",
+                child_kind: "pair",
+                new_index: 3,
+            })
+        );
+        document.move_child(3, 2).unwrap();
+        assert!(document.children()[2].has_header());
+        assert_eq!(
+            document.insert_child(arena.alloc(TOML::Key("k".to_string())), &arena, 0),
+            Err(InsertError::InvalidChild {
+                parent_kind: "document",
+                child_kind: "key",
+            })
+        );
+
+        // The header of a table comes before everything but the comments about it, and can't be
+        // deleted
+        let mut table = table.clone();
+        assert_eq!(
+            table.insert_child(pair, &arena, 0),
+            Err(InsertError::InvalidPosition {
+                parent_kind: "table",
+                child_kind: "pair",
+                index: 0,
+            })
+        );
+        let mut commented = table.clone();
+        commented.insert_child(comment, &arena, 0).unwrap();
+        assert_eq!(commented.to_text(&PRETTY), "#\n[t]\nb = 2");
+        assert_eq!(commented.fold_summary(), "[t] (1 pair)");
+        assert!(commented.is_key_child(1));
+        assert!(!commented.is_valid_child(1, comment));
+        assert!(!commented.is_valid_child(0, pair));
+        assert_eq!(
+            commented.delete_children(0..2),
+            Err(DeleteError::RequiredChild {
+                kind: "table",
+                child_kind: "header",
+            })
+        );
+        commented.delete_child(0).unwrap();
+        assert_eq!(commented, table);
+        table.insert_child(comment, &arena, 2).unwrap();
+        assert_eq!(table.fold_summary(), "[t] (1 pair)");
+        assert_eq!(
+            table.delete_children(0..2),
+            Err(DeleteError::RequiredChild {
+                kind: "table",
+                child_kind: "header",
+            })
+        );
+        assert!(matches!(
+            table.move_child(1, 0),
+            Err(MoveError::InvalidPosition { .. })
+        ));
+        table.move_child(1, 2).unwrap();
+        assert!(table.children()[2].is_valid_child(1, value));
+        table.delete_children(1..3).unwrap();
+        assert_eq!(table.unwrap_index(), None);
+        // Swapping the kind of a table keeps its contents
+        assert_eq!(
+            table.from_char('A'),
+            Some(TOML::ArrayTable(table.children().to_vec()))
+        );
+
+        // The key and value of a pair are fixed
+        let mut pair = pair.clone();
+        assert_eq!(
+            pair.move_child(0, 1),
+            Err(MoveError::FixedPositions { kind: "pair" })
+        );
+        assert!(!pair.is_valid_child(1, comment));
+        assert!(!pair.is_valid_child(0, value));
+        assert_eq!(
+            pair.delete_child(1),
+            Err(DeleteError::TooFewChildren {
+                kind: "pair",
+                min_children: 2,
+            })
+        );
+        assert_eq!(pair.unwrap_index(), Some(1));
+
+        // Inline tables only contain pairs, and arrays only contain values and comments
+        let mut inline = TOML::InlineTable(vec![]);
+        assert!(inline.insert_child(comment, &arena, 0).is_err());
+        inline.insert_child(value, &arena, 0).unwrap();
+        assert_eq!(inline.to_text(&PRETTY), "{ \"\" = 1 }");
+        let mut array = TOML::Array(vec![]);
+        array.insert_child(comment, &arena, 0).unwrap();
+        assert!(array.insert_child(pair_node(&arena), &arena, 0).is_err());
+        assert_eq!(
+            value.clone().insert_child(comment, &arena, 0),
+            Err(InsertError::TooManyChildren {
+                kind: "integer",
+                max_children: 0,
+            })
+        );
+
+        // Only values can be wrapped
+        assert_eq!(value.wrap_in('a', &arena), Some(&TOML::Array(vec![value])));
+        assert_eq!(
+            value
+                .wrap_in('o', &arena)
+                .unwrap()
+                .to_text(&TOMLFormat::Compact),
+            "{\"\"=1}"
+        );
+        assert_eq!(comment.wrap_in('a', &arena), None);
+        assert_eq!(root.replace_chars().count(), 0);
+        for c in value.replace_chars() {
+            assert!(pair.is_valid_child(1, &value.from_char(c).unwrap()));
+        }
+        assert!(root.is_insert_char('T'));
+        assert!(!root.children()[1].is_insert_char('T'));
+    }
+
+    /// Allocates the pair `a = true`
+    fn pair_node<'arena>(arena: &'arena Arena<TOML<'arena>>) -> &'arena TOML<'arena> {
+        let key = arena.alloc(TOML::Key("a".to_string()));
+        arena.alloc(TOML::Pair([key, arena.alloc(TOML::Boolean(true))]))
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let arena = Arena::new();
+        let root = TOML::parse(
+            "a = 1\n\"a\" = 2\n[t]\nx = 1\n'x' = { y = 1, \"y\" = 2 }\n[t]\n[[u]]\n[[u]]",
+            &arena,
+        )
+        .unwrap();
+        let duplicate = |key: &str, index, first_index| ValidationError::DuplicateKey {
+            key: key.to_string(),
+            index,
+            first_index,
+        };
+        // Keys are compared without their quotes, and only tables (not arrays of tables) can't
+        // be repeated
+        assert_eq!(
+            root.validate(),
+            vec![duplicate("a", 1, 0), duplicate("t", 3, 2)]
+        );
+        let table = root.children()[2];
+        assert_eq!(table.validate(), vec![duplicate("x", 2, 1)]);
+        let inline = table.children()[2].children()[1];
+        assert_eq!(inline.validate(), vec![duplicate("y", 1, 0)]);
+        assert!(root.children()[4].validate().is_empty());
+    }
+}
@@ -0,0 +1,2066 @@
+//! XML documents (along with HTML documents that are also well-formed XML, like XHTML).
+//!
+//! An [`Element`](XML::Element) stores its name itself, rather than in a child, because the name
+//! is rendered twice (in the opening and the closing tag).  This way, typing into the name of an
+//! element changes both of its tags at once.  The attributes of an element are its first
+//! children, followed by its content.
+//!
+//! Whitespace between the lines of text, comments and CDATA sections is treated as indentation:
+//! the leading whitespace of every line after the first is left out when they're parsed, and
+//! replaced by the indentation of the node when they're rendered.
+
+use super::display_token::{
+    DisplayToken, FormatKind, IndentKind, RecTok, RenderStyle, SyntaxCategory,
+};
+use super::size::{self, Size};
+use super::{Ast, DeleteError, InsertError, MoveError, ValidationError};
+use crate::arena::{Arena, HeapSize};
+use crate::config::options::Options;
+use crate::language::Language;
+use std::collections::HashMap;
+
+/// The ways that XML documents can be formatted.  Every style has `self_closing`, which chooses
+/// whether elements with no children are written as a single tag (`<br/>`) or as an opening tag
+/// followed by a closing tag (`<br></br>`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum XMLFormat {
+    /// Every element is on one line, with no whitespace between tags.  E.g.
+    /// `<note><to>Sapling</to><from/></note>`
+    Compact { self_closing: bool },
+    /// Elements that only contain other elements (or comments and CDATA sections) have every
+    /// child on its own line, indented by `indent`.  Elements containing any text are always kept
+    /// on one line, since adding whitespace between their children would change the text.
+    Pretty {
+        indent: IndentKind,
+        self_closing: bool,
+    },
+    /// Like `Pretty`, except that elements are kept on one line if they fit within `max_width`
+    /// columns (and spread over many lines if they don't).  Like JSON's
+    /// [`Fit`](super::json::JSONFormat::Fit), whether an element fits doesn't depend on how far
+    /// it's indented.
+    Fit {
+        indent: IndentKind,
+        max_width: usize,
+        self_closing: bool,
+    },
+}
+
+impl XMLFormat {
+    /// Returns `true` if elements with no children should be written as self-closing tags
+    fn is_self_closing(&self) -> bool {
+        match self {
+            XMLFormat::Compact { self_closing }
+            | XMLFormat::Pretty { self_closing, .. }
+            | XMLFormat::Fit { self_closing, .. } => *self_closing,
+        }
+    }
+}
+
+impl RenderStyle for XMLFormat {
+    fn indent(&self) -> IndentKind {
+        match self {
+            XMLFormat::Compact { .. } => IndentKind::Spaces(0),
+            XMLFormat::Pretty { indent, .. } | XMLFormat::Fit { indent, .. } => *indent,
+        }
+    }
+
+    fn set_indent(&mut self, new_indent: IndentKind) {
+        if let XMLFormat::Pretty { indent, .. } | XMLFormat::Fit { indent, .. } = self {
+            *indent = new_indent;
+        }
+    }
+
+    fn set_kind(&mut self, kind: FormatKind) {
+        let indent = match *self {
+            XMLFormat::Pretty { indent, .. } | XMLFormat::Fit { indent, .. } => indent,
+            XMLFormat::Compact { .. } => IndentKind::default(),
+        };
+        let self_closing = self.is_self_closing();
+        *self = match kind {
+            FormatKind::Compact => XMLFormat::Compact { self_closing },
+            FormatKind::Pretty => XMLFormat::Pretty {
+                indent,
+                self_closing,
+            },
+            FormatKind::Fit { max_width } => XMLFormat::Fit {
+                indent,
+                max_width,
+                self_closing,
+            },
+        };
+    }
+
+    fn apply_options(&mut self, options: &Options, _language: Language) {
+        match self {
+            XMLFormat::Compact { self_closing }
+            | XMLFormat::Pretty { self_closing, .. }
+            | XMLFormat::Fit { self_closing, .. } => *self_closing = options.self_closing,
+        }
+    }
+}
+
+const CHAR_ELEMENT: char = 'e';
+const CHAR_ATTRIBUTE: char = 'a';
+const CHAR_TEXT: char = 't';
+const CHAR_COMMENT: char = '!';
+const CHAR_CDATA: char = '[';
+const CHAR_INSTRUCTION: char = '?';
+
+/// The category used for the names of elements
+const CATEGORY_KEYWORD: SyntaxCategory = SyntaxCategory::Keyword;
+/// The category used for the names of attributes
+const CATEGORY_IDENT: SyntaxCategory = SyntaxCategory::Ident;
+/// The category used for attribute values and CDATA sections
+const CATEGORY_LITERAL: SyntaxCategory = SyntaxCategory::Literal;
+/// The category used for text and punctuation
+const CATEGORY_DEFAULT: SyntaxCategory = SyntaxCategory::Default;
+/// The category used for character and entity references (like `&amp;`)
+const CATEGORY_SPECIAL: SyntaxCategory = SyntaxCategory::Special;
+/// The category used for comments
+const CATEGORY_COMMENT: SyntaxCategory = SyntaxCategory::Comment;
+/// The category used for processing instructions and doctypes
+const CATEGORY_PREPROC: SyntaxCategory = SyntaxCategory::Preproc;
+/// The category used for names that aren't valid XML names (e.g. new elements, whose names
+/// haven't been typed yet)
+const CATEGORY_ERROR: SyntaxCategory = SyntaxCategory::Error;
+
+/// Returns `true` if `c` can be the first char of an element or attribute name.  This is a
+/// simplification of the XML spec, which allows letters, `_`, `:` and most non-ASCII chars.
+fn is_name_start_char(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == ':' || (!c.is_ascii() && is_name_char(c))
+}
+
+/// Returns `true` if `c` can be in an element or attribute name, after the first char
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || matches!(c, '_' | ':' | '-' | '.')
+        || (!c.is_ascii() && !c.is_whitespace() && !c.is_control())
+}
+
+/// Returns `true` if `name` is a valid element or attribute name
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_name_start_char) && chars.all(is_name_char)
+}
+
+/// Removes the chars that could never be part of a name, so that typing into a name can't break
+/// the tags around it
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect()
+}
+
+/// Removes the leading spaces and tabs from every line of `text` after the first, which is
+/// treated as indentation (see the [module docs](self))
+fn strip_indentation(text: &str) -> String {
+    let lines: Vec<&str> = text
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => line,
+            _ => line.trim_start_matches([' ', '\t']),
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Returns the reference that a char has to be written as in text (or in an attribute value, if
+/// `in_attribute` is `true`), or `None` if it can be written as it is.  Attribute values are
+/// always written between `"`s, and newlines and tabs are escaped in them because a parser would
+/// otherwise turn them into spaces.  Control chars are escaped so that they can't garble the
+/// screen.
+fn escape_char(c: char, in_attribute: bool) -> Option<String> {
+    match c {
+        '&' => Some("&amp;".to_string()),
+        '<' => Some("&lt;".to_string()),
+        '>' if !in_attribute => Some("&gt;".to_string()),
+        '"' if in_attribute => Some("&quot;".to_string()),
+        '\n' | '\t' if !in_attribute => None,
+        '\n' | '\t' | '\r' => Some(format!("&#{};", c as u32)),
+        _ if c.is_control() => Some(format!("&#x{:X};", c as u32)),
+        _ => None,
+    }
+}
+
+/// Escapes some text so that it can be written as text (or as an attribute value, if
+/// `in_attribute` is `true`)
+fn escape(text: &str, in_attribute: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match escape_char(c, in_attribute) {
+            Some(reference) => escaped.push_str(&reference),
+            None => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns how many bytes of a node's rendered text come before the char at byte `index` of
+/// `text`, if the text is written as it is after a `prefix_len` byte prefix.  Newlines aren't
+/// part of the rendered text, since they become [`Newline`](DisplayToken::Newline)s.
+fn rendered_offset(text: &str, index: usize, prefix_len: usize) -> usize {
+    let before = &text[..index];
+    prefix_len + before.len() - before.matches('\n').count()
+}
+
+/// Generates the tokens of some text between `open` and `close`, where references are separate
+/// tokens (so that they can be highlighted differently to the rest of the text) and every
+/// newline is a [`Newline`](DisplayToken::Newline), so that every line is indented
+fn escaped_tokens<'arena>(
+    text: &str,
+    in_attribute: bool,
+    category: SyntaxCategory,
+    (open, close): (&str, &str),
+) -> Vec<RecTok<'arena, XML<'arena>>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::from(open);
+    let flush = |literal: &mut String, tokens: &mut Vec<RecTok<'arena, XML<'arena>>>| {
+        if !literal.is_empty() {
+            let text = std::mem::take(literal);
+            tokens.push(RecTok::Tok(DisplayToken::Text(text.into(), category)));
+        }
+    };
+    for c in text.chars() {
+        if c == '\n' && !in_attribute {
+            flush(&mut literal, &mut tokens);
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+            continue;
+        }
+        match escape_char(c, in_attribute) {
+            Some(reference) => {
+                flush(&mut literal, &mut tokens);
+                tokens.push(RecTok::Tok(DisplayToken::Text(
+                    reference.into(),
+                    CATEGORY_SPECIAL,
+                )));
+            }
+            None => literal.push(c),
+        }
+    }
+    literal.push_str(close);
+    flush(&mut literal, &mut tokens);
+    tokens
+}
+
+/// Generates the tokens of some text which is written as it is, with a
+/// [`Newline`](DisplayToken::Newline) between every line so that they're all indented
+fn line_tokens<'arena>(text: &str, category: SyntaxCategory) -> Vec<RecTok<'arena, XML<'arena>>> {
+    let mut tokens = Vec::new();
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        if !line.is_empty() {
+            tokens.push(RecTok::Tok(DisplayToken::Text(
+                line.to_string().into(),
+                category,
+            )));
+        }
+    }
+    tokens
+}
+
+/// The sapling representation of an XML document
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum XML<'arena> {
+    /// The top level of a file, which holds its elements along with any comments, processing
+    /// instructions and doctypes around them.  This is always the root of the tree, and can't be
+    /// inside anything else.
+    Document(Vec<&'arena XML<'arena>>),
+    /// An element with a name, whose children are its [`Attribute`](XML::Attribute)s followed by
+    /// its content.  Corresponds to a string `<name a="1">...</name>`, or `<name a="1"/>` if it
+    /// has no content (and the format style is self-closing).
+    Element(String, Vec<&'arena XML<'arena>>),
+    /// An attribute of an element, which is always a [`Name`](XML::Name) and a
+    /// [`Value`](XML::Value).  Corresponds to a string `name="value"`.
+    Attribute([&'arena XML<'arena>; 2]),
+    /// The name of an attribute
+    Name(String),
+    /// The value of an attribute, which is always written between `"`s
+    Value(String),
+    /// Some text inside an element, with every reference (like `&lt;`) already replaced by the
+    /// char that it refers to
+    Text(String),
+    /// A `<!-- comment -->`.  The text can't contain `-->`, which would end the comment.
+    Comment(String),
+    /// A `<![CDATA[ section ]]>` of text which is written without escaping anything.  The text
+    /// can't contain `]]>`, which would end the section.
+    CData(String),
+    /// A processing instruction, like `<?xml version="1.0"?>`, which holds everything between
+    /// the `<?` and the `?>`
+    Instruction(String),
+    /// A document type declaration, like `<!DOCTYPE html>`, which holds everything after the
+    /// `DOCTYPE` keyword
+    Doctype(String),
+}
+
+impl XML<'_> {
+    /// Return an iterator over all the chars that could represent the content of an element
+    fn content_chars() -> Box<dyn Iterator<Item = char>> {
+        Box::new(
+            [
+                CHAR_ELEMENT,
+                CHAR_TEXT,
+                CHAR_COMMENT,
+                CHAR_CDATA,
+                CHAR_INSTRUCTION,
+            ]
+            .iter()
+            .copied(),
+        )
+    }
+
+    /// Returns `true` if this is an attribute, which can only go at the start of an element
+    fn is_attribute(&self) -> bool {
+        matches!(self, XML::Attribute(_))
+    }
+
+    /// Returns `true` if this can be part of the content of an element
+    fn is_content(&self) -> bool {
+        matches!(
+            self,
+            XML::Element(..) | XML::Text(_) | XML::Comment(_) | XML::CData(_) | XML::Instruction(_)
+        )
+    }
+
+    /// Returns `true` if this can be at the top level of a document
+    fn is_top_level(&self) -> bool {
+        matches!(
+            self,
+            XML::Element(..) | XML::Comment(_) | XML::Instruction(_) | XML::Doctype(_)
+        )
+    }
+
+    /// Returns `true` if this is text.  CDATA sections don't count: like comments, they're kept
+    /// whole, so whitespace around them is only indentation.
+    fn is_text(&self) -> bool {
+        matches!(self, XML::Text(_))
+    }
+
+    /// Returns how many of the children of an element are attributes
+    fn attribute_count(&self) -> usize {
+        self.children()
+            .iter()
+            .take_while(|child| child.is_attribute())
+            .count()
+    }
+
+    /// Returns `true` if every attribute of an element comes before all of its content
+    fn is_well_ordered(&self) -> bool {
+        let attribute_count = self.attribute_count();
+        !self.children()[attribute_count..]
+            .iter()
+            .any(|child| child.is_attribute())
+    }
+
+    /// Returns `true` if this is an element which will be spread over many lines when rendered
+    /// in `format_style`
+    fn is_broken(&self, format_style: &XMLFormat) -> bool {
+        let content = match self {
+            XML::Element(_, children) => &children[self.attribute_count()..],
+            _ => return false,
+        };
+        // Breaking an element which contains text would add whitespace to the text
+        if content.is_empty() || content.iter().any(|child| child.is_text()) {
+            return false;
+        }
+        match format_style {
+            XMLFormat::Compact { .. } => false,
+            XMLFormat::Pretty { .. } => true,
+            XMLFormat::Fit {
+                max_width,
+                self_closing,
+                ..
+            } => self.inline_width(*max_width, *self_closing).is_none(),
+        }
+    }
+
+    /// Returns how many columns this node would occupy if it was written on one line, or `None`
+    /// if it can't be written on one line within `limit` columns (which is always true of nodes
+    /// whose text contains newlines).  This stops measuring as soon as the limit is exceeded, so
+    /// that the whole tree isn't measured for every element.
+    fn inline_width(&self, limit: usize, self_closing: bool) -> Option<usize> {
+        let mut width = match self {
+            XML::Element(name, children) => {
+                let name_width = Size::from(name.as_str()).max_width();
+                // Every attribute has a space before it
+                let attribute_count = self.attribute_count();
+                let tags = if attribute_count == children.len() && self_closing {
+                    // `<name/>`
+                    name_width + 3
+                } else {
+                    // `<name></name>`
+                    2 * name_width + 5
+                };
+                tags + attribute_count
+            }
+            // `=`
+            XML::Attribute(_) => 1,
+            XML::Document(_) => return None,
+            leaf => {
+                let size = leaf.size_from_children(&XMLFormat::Compact { self_closing }, &[]);
+                let width = size.max_width();
+                return Some(width).filter(|&width| size.lines() == 0 && width <= limit);
+            }
+        };
+        for child in self.children() {
+            width += child.inline_width(limit.checked_sub(width)?, self_closing)?;
+        }
+        Some(width).filter(|&width| width <= limit)
+    }
+
+    /// Moves the child at `index` to `new_index` (like [`Ast::move_child`]), without checking
+    /// that the new order is valid
+    fn rotate_children(&mut self, index: usize, new_index: isize) -> Result<(), MoveError> {
+        let children = self.children_mut();
+        let len = children.len();
+        if index >= len || new_index < 0 || new_index as usize >= len {
+            return Err(MoveError::OutOfRange { len, new_index });
+        }
+        let new_index = new_index as usize;
+        if index < new_index {
+            children[index..=new_index].rotate_left(1);
+        } else {
+            children[new_index..=index].rotate_right(1);
+        }
+        Ok(())
+    }
+}
+
+impl Default for XML<'_> {
+    fn default() -> XML<'static> {
+        XML::Document(vec![])
+    }
+}
+
+impl HeapSize for XML<'_> {
+    fn heap_size(&self) -> usize {
+        match self {
+            XML::Document(children) => children.heap_size(),
+            XML::Element(name, children) => name.heap_size() + children.heap_size(),
+            XML::Attribute(_) => 0,
+            XML::Name(text)
+            | XML::Value(text)
+            | XML::Text(text)
+            | XML::Comment(text)
+            | XML::CData(text)
+            | XML::Instruction(text)
+            | XML::Doctype(text) => text.heap_size(),
+        }
+    }
+}
+
+impl<'arena> Ast<'arena> for XML<'arena> {
+    type FormatStyle = XMLFormat;
+
+    const TOKEN_CATEGORIES: &'static [SyntaxCategory] = &[
+        CATEGORY_KEYWORD,
+        CATEGORY_IDENT,
+        CATEGORY_LITERAL,
+        CATEGORY_DEFAULT,
+        CATEGORY_SPECIAL,
+        CATEGORY_COMMENT,
+        CATEGORY_PREPROC,
+        CATEGORY_ERROR,
+    ];
+
+    /* FORMATTING FUNCTIONS */
+
+    fn display_tokens_rec(
+        &'arena self,
+        format_style: &Self::FormatStyle,
+    ) -> Vec<RecTok<'arena, Self>> {
+        let text = |text: &str, category| {
+            RecTok::Tok(DisplayToken::Text(text.to_string().into(), category))
+        };
+        let (name, children) = match self {
+            XML::Document(children) => {
+                // Top-level nodes go on lines of their own, unless everything is compact
+                let mut tokens = Vec::with_capacity(2 * children.len());
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 && !matches!(format_style, XMLFormat::Compact { .. }) {
+                        tokens.push(RecTok::Tok(DisplayToken::Newline));
+                    }
+                    tokens.push(RecTok::Child(*child));
+                }
+                return tokens;
+            }
+            XML::Attribute([name, value]) => {
+                return vec![
+                    RecTok::Child(*name),
+                    text("=", CATEGORY_DEFAULT),
+                    RecTok::Child(*value),
+                ]
+            }
+            XML::Name(name) => {
+                let category = if is_name(name) {
+                    CATEGORY_IDENT
+                } else {
+                    CATEGORY_ERROR
+                };
+                return vec![text(name, category)];
+            }
+            XML::Value(value) => {
+                return escaped_tokens(value, true, CATEGORY_LITERAL, ("\"", "\""));
+            }
+            XML::Text(contents) => {
+                return escaped_tokens(contents, false, CATEGORY_DEFAULT, ("", ""))
+            }
+            XML::Comment(comment) => {
+                return line_tokens(&format!("<!--{}-->", comment), CATEGORY_COMMENT)
+            }
+            XML::CData(contents) => {
+                return line_tokens(&format!("<![CDATA[{}]]>", contents), CATEGORY_LITERAL)
+            }
+            XML::Instruction(instruction) => {
+                return line_tokens(&format!("<?{}?>", instruction), CATEGORY_PREPROC)
+            }
+            XML::Doctype(doctype) => {
+                return line_tokens(&format!("<!DOCTYPE {}>", doctype), CATEGORY_PREPROC)
+            }
+            XML::Element(name, children) => (name, children),
+        };
+
+        // The same name is rendered in both tags
+        let name_category = if is_name(name) {
+            CATEGORY_KEYWORD
+        } else {
+            CATEGORY_ERROR
+        };
+        let attribute_count = self.attribute_count();
+        let content = &children[attribute_count..];
+        let is_broken = self.is_broken(format_style);
+        let mut tokens: Vec<RecTok<'_, Self>> = Vec::with_capacity(8 + 2 * children.len());
+        tokens.push(text("<", CATEGORY_DEFAULT));
+        tokens.push(text(name, name_category));
+        for attribute in &children[..attribute_count] {
+            tokens.push(RecTok::Tok(DisplayToken::Whitespace(1)));
+            tokens.push(RecTok::Child(*attribute));
+        }
+        if content.is_empty() && format_style.is_self_closing() {
+            tokens.push(text("/>", CATEGORY_DEFAULT));
+            return tokens;
+        }
+        tokens.push(text(">", CATEGORY_DEFAULT));
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Indent));
+        }
+        for child in content {
+            if is_broken {
+                tokens.push(RecTok::Tok(DisplayToken::Newline));
+            }
+            tokens.push(RecTok::Child(*child));
+        }
+        if is_broken {
+            tokens.push(RecTok::Tok(DisplayToken::Dedent));
+            tokens.push(RecTok::Tok(DisplayToken::Newline));
+        }
+        tokens.push(text("</", CATEGORY_DEFAULT));
+        tokens.push(text(name, name_category));
+        tokens.push(text(">", CATEGORY_DEFAULT));
+        tokens
+    }
+
+    fn size_from_children(&self, format_style: &Self::FormatStyle, child_sizes: &[Size]) -> Size {
+        let name = match self {
+            XML::Document(_) => {
+                let is_compact = matches!(format_style, XMLFormat::Compact { .. });
+                let mut size = size::ZERO;
+                for (i, &child_size) in child_sizes.iter().enumerate() {
+                    if i > 0 && !is_compact {
+                        size += Size::newline();
+                    }
+                    size += child_size;
+                }
+                return size;
+            }
+            XML::Attribute(_) => return child_sizes[0] + Size::line(1) + child_sizes[1],
+            XML::Name(name) => return Size::from(name.as_str()),
+            // Every reference is printable ASCII, so escaped values are always one line
+            XML::Value(value) => {
+                return Size::line(1) + Size::from(escape(value, true).as_str()) + Size::line(1)
+            }
+            XML::Text(contents) => return Size::from(escape(contents, false).as_str()),
+            XML::Comment(comment) => return Size::from(format!("<!--{}-->", comment).as_str()),
+            XML::CData(contents) => {
+                return Size::from(format!("<![CDATA[{}]]>", contents).as_str())
+            }
+            XML::Instruction(instruction) => {
+                return Size::from(format!("<?{}?>", instruction).as_str())
+            }
+            XML::Doctype(doctype) => return Size::from(format!("<!DOCTYPE {}>", doctype).as_str()),
+            XML::Element(name, _) => name,
+        };
+
+        let name_size = Size::from(name.as_str());
+        let attribute_count = self.attribute_count();
+        let is_broken = self.is_broken(format_style);
+        // How far each child is indented when it's on a line of its own, counting tabs as a
+        // single column (like `Size::from`)
+        let indent_columns = if is_broken {
+            format_style.indent().columns(1)
+        } else {
+            0
+        };
+        let mut size = Size::line(1) + name_size;
+        for &attribute_size in &child_sizes[..attribute_count] {
+            size += Size::line(1) + attribute_size;
+        }
+        let content_sizes = &child_sizes[attribute_count..];
+        if content_sizes.is_empty() && format_style.is_self_closing() {
+            return size + Size::line(2);
+        }
+        size += Size::line(1);
+        for &child_size in content_sizes {
+            if is_broken {
+                size += Size::newline() + child_size.indented(indent_columns);
+            } else {
+                size += child_size;
+            }
+        }
+        if is_broken {
+            size += Size::newline();
+        }
+        size + Size::line(2) + name_size + Size::line(1)
+    }
+
+    /* DEBUG VIEW FUNCTIONS */
+
+    fn children<'s>(&'s self) -> &'s [&'arena XML<'arena>] {
+        match self {
+            XML::Document(children) | XML::Element(_, children) => children,
+            XML::Attribute(pair) => pair,
+            XML::Name(_)
+            | XML::Value(_)
+            | XML::Text(_)
+            | XML::Comment(_)
+            | XML::CData(_)
+            | XML::Instruction(_)
+            | XML::Doctype(_) => &[],
+        }
+    }
+
+    fn children_mut<'s>(&'s mut self) -> &'s mut [&'arena XML<'arena>] {
+        match self {
+            XML::Document(children) | XML::Element(_, children) => children,
+            XML::Attribute(pair) => pair,
+            XML::Name(_)
+            | XML::Value(_)
+            | XML::Text(_)
+            | XML::Comment(_)
+            | XML::CData(_)
+            | XML::Instruction(_)
+            | XML::Doctype(_) => &mut [],
+        }
+    }
+
+    fn insert_child(
+        &mut self,
+        new_node: &'arena Self,
+        arena: &'arena Arena<Self>,
+        index: usize,
+    ) -> Result<(), InsertError> {
+        let len = self.children().len();
+        if index > len {
+            return Err(InsertError::IndexOutOfRange { len, index });
+        }
+        // Values can't go straight into elements, so (like the values of a TOML table) they're
+        // given an empty name
+        let new_node = match (&*self, new_node) {
+            (XML::Element(..), XML::Value(_)) => {
+                let name = arena.alloc(XML::Name(String::new()));
+                arena.alloc(XML::Attribute([name, new_node]))
+            }
+            _ => new_node,
+        };
+        let invalid_child = InsertError::InvalidChild {
+            parent_kind: self.node_kind(),
+            child_kind: new_node.node_kind(),
+        };
+        let invalid_position = InsertError::InvalidPosition {
+            parent_kind: self.node_kind(),
+            child_kind: new_node.node_kind(),
+            index,
+        };
+        let attribute_count = self.attribute_count();
+        match self {
+            XML::Document(children) => {
+                if !new_node.is_top_level() {
+                    return Err(invalid_child);
+                }
+                children.insert(index, new_node);
+                Ok(())
+            }
+            XML::Element(_, children) => {
+                // Attributes come before all of the content of an element
+                let is_valid_position = if new_node.is_attribute() {
+                    index <= attribute_count
+                } else if new_node.is_content() {
+                    index >= attribute_count
+                } else {
+                    return Err(invalid_child);
+                };
+                if !is_valid_position {
+                    return Err(invalid_position);
+                }
+                children.insert(index, new_node);
+                Ok(())
+            }
+            XML::Attribute(_) => Err(InsertError::TooManyChildren {
+                kind: self.node_kind(),
+                max_children: 2,
+            }),
+            XML::Name(_)
+            | XML::Value(_)
+            | XML::Text(_)
+            | XML::Comment(_)
+            | XML::CData(_)
+            | XML::Instruction(_)
+            | XML::Doctype(_) => Err(InsertError::TooManyChildren {
+                kind: self.node_kind(),
+                max_children: 0,
+            }),
+        }
+    }
+
+    fn has_reorderable_children(&self) -> bool {
+        // Swapping the name and value of an attribute would make the name something else
+        !self.is_attribute()
+    }
+
+    fn move_child(&mut self, index: usize, new_index: isize) -> Result<(), MoveError> {
+        if !self.has_reorderable_children() {
+            return Err(MoveError::FixedPositions {
+                kind: self.node_kind(),
+            });
+        }
+        // Check the order of a moved copy, so that nothing is moved if the order isn't valid
+        let mut moved = self.clone();
+        moved.rotate_children(index, new_index)?;
+        if !moved.is_well_ordered() {
+            return Err(MoveError::InvalidPosition {
+                parent_kind: self.node_kind(),
+                child_kind: self.children()[index].node_kind(),
+                new_index: new_index as usize,
+            });
+        }
+        *self = moved;
+        Ok(())
+    }
+
+    fn delete_children(&mut self, range: std::ops::Range<usize>) -> Result<(), DeleteError> {
+        match self {
+            XML::Attribute([name, value]) => Err(DeleteError::RequiredChild {
+                kind: "attribute",
+                child_kind: match range.start {
+                    0 => name.node_kind(),
+                    _ => value.node_kind(),
+                },
+            }),
+            // Removing children can never put the rest out of order, and an element whose last
+            // child is removed just becomes empty (so is rendered as a self-closing tag)
+            XML::Document(children) | XML::Element(_, children) => {
+                if range.end <= children.len() {
+                    children.drain(range);
+                    Ok(())
+                } else {
+                    Err(DeleteError::IndexOutOfRange {
+                        len: children.len(),
+                        index: range.end - 1,
+                    })
+                }
+            }
+            // Leaves have no children, so there's nothing that could be selected for deletion
+            XML::Name(_)
+            | XML::Value(_)
+            | XML::Text(_)
+            | XML::Comment(_)
+            | XML::CData(_)
+            | XML::Instruction(_)
+            | XML::Doctype(_) => unreachable!(),
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match self {
+            XML::Element(name, _) => format!("<{}>", name),
+            XML::Name(name) => name.clone(),
+            XML::Value(value) => format!("\"{}\"", escape(value, true)),
+            XML::CData(_) => "CDATA section".to_string(),
+            XML::Instruction(_) => "processing instruction".to_string(),
+            _ => self.node_kind().to_string(),
+        }
+    }
+
+    fn node_kind(&self) -> &'static str {
+        match self {
+            XML::Document(_) => "document",
+            XML::Element(..) => "element",
+            XML::Attribute(_) => "attribute",
+            XML::Name(_) => "name",
+            XML::Value(_) => "value",
+            XML::Text(_) => "text",
+            XML::Comment(_) => "comment",
+            XML::CData(_) => "cdata",
+            XML::Instruction(_) => "instruction",
+            XML::Doctype(_) => "doctype",
+        }
+    }
+
+    fn fold_summary(&self) -> String {
+        match self {
+            XML::Element(name, children) => {
+                let count = children.len() - self.attribute_count();
+                match count {
+                    1 => format!("<{}>…</{}> (1 child)", name, name),
+                    _ => format!("<{}>…</{}> ({} children)", name, name, count),
+                }
+            }
+            XML::Attribute([name, _]) => format!("{}=…", name.display_name()),
+            _ => self.display_name(),
+        }
+    }
+
+    /* AST EDITING FUNCTIONS */
+
+    fn replace_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            _ if self.is_content() => Self::content_chars(),
+            // Names and values are changed by typing into them, and neither the document nor an
+            // attribute can be replaced by anything that could take its place
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+
+    fn from_char(&self, c: char) -> Option<Self> {
+        match c {
+            CHAR_ELEMENT => Some(XML::Element(String::new(), vec![])),
+            // This is given a name when it's inserted into an element
+            CHAR_ATTRIBUTE => Some(XML::Value(String::new())),
+            CHAR_TEXT => Some(XML::Text(String::new())),
+            CHAR_COMMENT => Some(XML::Comment(String::new())),
+            CHAR_CDATA => Some(XML::CData(String::new())),
+            CHAR_INSTRUCTION => Some(XML::Instruction(String::new())),
+            _ => None,
+        }
+    }
+
+    fn is_valid_child(&self, index: usize, child: &Self) -> bool {
+        match self {
+            XML::Document(_) => child.is_top_level(),
+            XML::Element(_, children) => {
+                if child.is_attribute() {
+                    children[..index.min(children.len())]
+                        .iter()
+                        .all(|c| c.is_attribute())
+                } else {
+                    child.is_content()
+                        && children
+                            .get(index + 1..)
+                            .is_none_or(|after| !after.iter().any(|c| c.is_attribute()))
+                }
+            }
+            XML::Attribute(_) => match index {
+                0 => matches!(child, XML::Name(_)),
+                _ => index == 1 && matches!(child, XML::Value(_)),
+            },
+            XML::Name(_)
+            | XML::Value(_)
+            | XML::Text(_)
+            | XML::Comment(_)
+            | XML::CData(_)
+            | XML::Instruction(_)
+            | XML::Doctype(_) => false,
+        }
+    }
+
+    fn unwrap_index(&self) -> Option<usize> {
+        match self {
+            // Unwrapping an element with attributes would throw them away
+            XML::Element(_, children) if children.len() == 1 && !children[0].is_attribute() => {
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+
+    fn wrap_in(&'arena self, c: char, arena: &'arena Arena<Self>) -> Option<&'arena Self> {
+        match c {
+            CHAR_ELEMENT if self.is_content() => {
+                Some(arena.alloc(XML::Element(String::new(), vec![self])))
+            }
+            _ => None,
+        }
+    }
+
+    fn insert_chars(&self) -> Box<dyn Iterator<Item = char>> {
+        match self {
+            XML::Document(_) => Box::new(
+                [CHAR_ELEMENT, CHAR_COMMENT, CHAR_INSTRUCTION]
+                    .iter()
+                    .copied(),
+            ),
+            XML::Element(..) => {
+                Box::new(std::iter::once(CHAR_ATTRIBUTE).chain(Self::content_chars()))
+            }
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+
+    fn replace_text(&self, pattern: &str, replacement: &str) -> Option<Self> {
+        let text = self
+            .text()
+            .filter(|_| !matches!(self, XML::Comment(_) | XML::Instruction(_)))?;
+        if pattern.is_empty() || !text.contains(pattern) {
+            return None;
+        }
+        self.with_text(&text.replace(pattern, replacement))
+    }
+
+    fn is_key_child(&self, index: usize) -> bool {
+        index == 0 && self.is_attribute()
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            XML::Element(text, _)
+            | XML::Name(text)
+            | XML::Value(text)
+            | XML::Text(text)
+            | XML::Comment(text)
+            | XML::CData(text)
+            | XML::Instruction(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn with_text(&self, text: &str) -> Option<Self> {
+        // Text can't contain whatever would end it, so those are split up by a space
+        match self {
+            XML::Element(_, children) => Some(XML::Element(sanitize_name(text), children.clone())),
+            XML::Name(_) => Some(XML::Name(sanitize_name(text))),
+            XML::Value(_) => Some(XML::Value(text.to_string())),
+            XML::Text(_) => Some(XML::Text(strip_indentation(text))),
+            XML::Comment(_) => Some(XML::Comment(strip_indentation(text).replace("-->", "-- >"))),
+            XML::CData(_) => Some(XML::CData(strip_indentation(text).replace("]]>", "]] >"))),
+            XML::Instruction(_) => Some(XML::Instruction(
+                strip_indentation(text).replace("?>", "? >"),
+            )),
+            _ => None,
+        }
+    }
+
+    fn text_offset(&self, index: usize) -> usize {
+        match self {
+            // Skip the `<` of the opening tag
+            XML::Element(..) => 1 + index,
+            XML::Name(_) => index,
+            // Skip the opening quote of a value, and the extra chars of every reference
+            XML::Value(value) => 1 + escape(&value[..index], true).len(),
+            XML::Text(contents) => {
+                let escaped = escape(&contents[..index], false);
+                rendered_offset(&escaped, escaped.len(), 0)
+            }
+            // Nothing is escaped in these, so we only need to skip the `<!--` (or whatever they
+            // start with)
+            XML::Comment(text) => rendered_offset(text, index, 4),
+            XML::CData(text) => rendered_offset(text, index, 9),
+            XML::Instruction(text) => rendered_offset(text, index, 2),
+            _ => 0,
+        }
+    }
+
+    fn validate(&self) -> Vec<ValidationError> {
+        let attributes = match self {
+            XML::Element(_, children) => &children[..self.attribute_count()],
+            _ => return Vec::new(),
+        };
+        // The index of the first attribute with each name
+        let mut first_indices: HashMap<&str, usize> = HashMap::new();
+        let mut errors = Vec::new();
+        for (index, attribute) in attributes.iter().enumerate() {
+            let name = attribute.children()[0].text().unwrap_or_default();
+            match first_indices.get(name) {
+                Some(&first_index) => errors.push(ValidationError::DuplicateKey {
+                    key: name.to_string(),
+                    index,
+                    first_index,
+                }),
+                None => {
+                    first_indices.insert(name, index);
+                }
+            }
+        }
+        errors
+    }
+}
+
+/* ===== PARSING ===== */
+
+/// The maximum depth of nested elements that [`XML::parse`] will accept, so that a pathological
+/// input like `<a><a><a><a>...` can't overflow the stack
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// The different ways that parsing XML can fail
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// We expected to find some specific thing, but found either a different char or the end of
+    /// the input (if `found` is `None`)
+    Expected {
+        expected: &'static str,
+        found: Option<char>,
+    },
+    /// A char which has to be written as a reference (like `<` in an attribute value) was
+    /// written as it is
+    Unescaped(char),
+    /// A reference (like `&nbsp;` or `&#0;`) which doesn't refer to any char.  This holds the
+    /// text between the `&` and the `;`.
+    InvalidReference(String),
+    /// An element was closed with a different name to the one it was opened with
+    MismatchedTag { open: String, close: String },
+    /// The input contains elements nested more than [`MAX_NESTING_DEPTH`] levels deep
+    TooDeeplyNested,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Expected {
+                expected,
+                found: Some(c),
+            } => write!(f, "expected {}, found {:?}", expected, c),
+            ParseErrorKind::Expected {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of input", expected),
+            ParseErrorKind::Unescaped(c) => {
+                write!(
+                    f,
+                    "{:?} has to be written as '{}'",
+                    c,
+                    escape(&c.to_string(), true)
+                )
+            }
+            ParseErrorKind::InvalidReference(name) => write!(f, "invalid reference '&{};'", name),
+            ParseErrorKind::MismatchedTag { open, close } => write!(
+                f,
+                "closing tag '</{}>' doesn't match opening tag '<{}>'",
+                close, open
+            ),
+            ParseErrorKind::TooDeeplyNested => write!(
+                f,
+                "elements are nested more than {} levels deep",
+                MAX_NESTING_DEPTH
+            ),
+        }
+    }
+}
+
+/// An error generated when parsing XML, along with the location of the error.  Both `line` and
+/// `column` start from 1, and `column` is measured in [`char`]s.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A recursive-descent XML parser, which allocates the nodes it parses into an [`Arena`]
+struct Parser<'t, 'arena> {
+    arena: &'arena Arena<XML<'arena>>,
+    text: &'t str,
+    /// The byte offset of the next char to be consumed
+    offset: usize,
+    /// The line of the next char to be consumed
+    line: usize,
+    /// The column of the next char to be consumed
+    column: usize,
+}
+
+impl<'t, 'arena> Parser<'t, 'arena> {
+    fn new(text: &'t str, arena: &'arena Arena<XML<'arena>>) -> Self {
+        Parser {
+            arena,
+            text,
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// Creates an error of a given kind at the current position
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    /// Creates an error saying that we expected something other than the next char
+    fn expected(&self, expected: &'static str) -> ParseError {
+        let found = self.peek();
+        self.error(ParseErrorKind::Expected { expected, found })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    /// Returns `true` if the unconsumed text starts with `prefix`
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.text[self.offset..].starts_with(prefix)
+    }
+
+    /// Consumes the next char, keeping track of the position in the text
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Consumes `prefix` if the unconsumed text starts with it, returning whether or not it did
+    fn eat(&mut self, prefix: &str) -> bool {
+        if !self.starts_with(prefix) {
+            return false;
+        }
+        for _ in prefix.chars() {
+            self.next();
+        }
+        true
+    }
+
+    /// Consumes `prefix`, or returns an error if the unconsumed text doesn't start with it
+    fn expect(&mut self, prefix: &str, expected: &'static str) -> Result<(), ParseError> {
+        if self.eat(prefix) {
+            Ok(())
+        } else {
+            Err(self.expected(expected))
+        }
+    }
+
+    /// Skips whitespace, returning whether or not there was any
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.offset;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+        self.offset > start
+    }
+
+    /// Consumes everything up to (and including) `end`, returning the text before it
+    fn take_until(&mut self, end: &str, expected: &'static str) -> Result<String, ParseError> {
+        let mut text = String::new();
+        while !self.eat(end) {
+            match self.next() {
+                Some(c) => text.push(c),
+                None => return Err(self.expected(expected)),
+            }
+        }
+        Ok(text)
+    }
+
+    /// Parses an entire file into a [`Document`](XML::Document)
+    fn parse_document(&mut self) -> Result<&'arena XML<'arena>, ParseError> {
+        let mut children = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                return Ok(self.arena.alloc(XML::Document(children)));
+            }
+            // Text, CDATA sections and closing tags can only be inside elements
+            if self.starts_with("<![CDATA[") || self.starts_with("</") || !self.starts_with("<") {
+                return Err(self.expected("an element"));
+            }
+            let child = self.parse_markup(0)?;
+            children.push(self.arena.alloc(child));
+        }
+    }
+
+    /// Parses a node which starts with `<` (an element, comment, CDATA section, processing
+    /// instruction or doctype)
+    fn parse_markup(&mut self, depth: usize) -> Result<XML<'arena>, ParseError> {
+        if self.eat("<!--") {
+            let comment = self.take_until("-->", "'-->'")?;
+            Ok(XML::Comment(strip_indentation(&comment)))
+        } else if self.eat("<![CDATA[") {
+            let contents = self.take_until("]]>", "']]>'")?;
+            Ok(XML::CData(strip_indentation(&contents)))
+        } else if self.eat("<?") {
+            let instruction = self.take_until("?>", "'?>'")?;
+            Ok(XML::Instruction(strip_indentation(&instruction)))
+        } else if self.eat("<!DOCTYPE") || self.eat("<!doctype") {
+            if !self.skip_whitespace() {
+                return Err(self.expected("whitespace"));
+            }
+            self.parse_doctype()
+        } else if self.starts_with("<!") {
+            self.next();
+            Err(self.expected("a comment, CDATA section or doctype"))
+        } else {
+            self.parse_element(depth)
+        }
+    }
+
+    /// Parses the rest of a doctype after the `DOCTYPE` keyword, which ends at the first `>`
+    /// that isn't inside the `[...]` of an internal subset
+    fn parse_doctype(&mut self) -> Result<XML<'arena>, ParseError> {
+        let mut doctype = String::new();
+        let mut depth = 0usize;
+        loop {
+            match self.next() {
+                Some('>') if depth == 0 => return Ok(XML::Doctype(strip_indentation(&doctype))),
+                Some(c) => {
+                    match c {
+                        '[' => depth += 1,
+                        ']' => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    doctype.push(c);
+                }
+                None => return Err(self.expected("'>'")),
+            }
+        }
+    }
+
+    /// Parses an element or attribute name
+    fn parse_name(&mut self, expected: &'static str) -> Result<String, ParseError> {
+        if !self.peek().is_some_and(is_name_start_char) {
+            return Err(self.expected(expected));
+        }
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|&c| is_name_char(c)) {
+            name.push(c);
+            self.next();
+        }
+        Ok(name)
+    }
+
+    /// Parses an element, from its opening `<` to the end of its closing tag
+    fn parse_element(&mut self, depth: usize) -> Result<XML<'arena>, ParseError> {
+        if depth >= MAX_NESTING_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeeplyNested));
+        }
+        self.expect("<", "'<'")?;
+        let name = self.parse_name("an element name")?;
+        let mut children = Vec::new();
+        // Parse the attributes, until the end of the opening tag
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            if self.eat("/>") {
+                return Ok(XML::Element(name, children));
+            }
+            if self.eat(">") {
+                break;
+            }
+            // Attributes have to be separated from the name and each other
+            if !had_whitespace {
+                return Err(self.expected("whitespace, '>' or '/>'"));
+            }
+            let attribute_name = self.parse_name("an attribute name, '>' or '/>'")?;
+            self.skip_whitespace();
+            self.expect("=", "'='")?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            let attribute_name = self.arena.alloc(XML::Name(attribute_name));
+            let value = self.arena.alloc(XML::Value(value));
+            children.push(self.arena.alloc(XML::Attribute([attribute_name, value])));
+        }
+
+        // Parse the content, until the closing tag
+        let attribute_count = children.len();
+        loop {
+            if self.starts_with("</") {
+                break;
+            }
+            let child = match self.peek() {
+                Some('<') => self.parse_markup(depth + 1)?,
+                Some(_) => XML::Text(strip_indentation(&self.parse_text()?)),
+                None => return Err(self.expected("a closing tag")),
+            };
+            children.push(self.arena.alloc(child));
+        }
+        self.eat("</");
+        let (line, column) = (self.line, self.column);
+        let close = self.parse_name("an element name")?;
+        if close != name {
+            return Err(ParseError {
+                line,
+                column,
+                kind: ParseErrorKind::MismatchedTag { open: name, close },
+            });
+        }
+        self.skip_whitespace();
+        self.expect(">", "'>'")?;
+
+        // Text that's only whitespace is how the content of an element is indented, unless
+        // there's other text around it or it's all that the element contains
+        let is_whitespace = |child: &XML| match child {
+            XML::Text(text) => text.chars().all(char::is_whitespace),
+            _ => false,
+        };
+        let content = &children[attribute_count..];
+        let has_text = content.iter().all(|child| is_whitespace(child))
+            || content
+                .iter()
+                .any(|child| child.is_text() && !is_whitespace(child));
+        if !has_text {
+            children.retain(|child| !is_whitespace(child));
+        }
+        Ok(XML::Element(name, children))
+    }
+
+    /// Parses an attribute value, including the quotes around it (which are either `"` or `'`)
+    fn parse_value(&mut self) -> Result<String, ParseError> {
+        let quote = match self.peek() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => return Err(self.expected("'\"' or \"'\"")),
+        };
+        self.next();
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('&') => value.push(self.parse_reference()?),
+                Some('<') => return Err(self.error(ParseErrorKind::Unescaped('<'))),
+                Some(c) => {
+                    self.next();
+                    if c == quote {
+                        return Ok(value);
+                    }
+                    value.push(c);
+                }
+                None => {
+                    return Err(self.expected(if quote == '"' { "'\"'" } else { "\"'\"" }));
+                }
+            }
+        }
+    }
+
+    /// Parses some text, up to the next `<`
+    fn parse_text(&mut self) -> Result<String, ParseError> {
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|&c| c != '<') {
+            if c == '&' {
+                text.push(self.parse_reference()?);
+            } else {
+                text.push(c);
+                self.next();
+            }
+        }
+        Ok(text)
+    }
+
+    /// Parses a reference to a char, like `&lt;` or `&#x3C;`, returning the char that it refers
+    /// to
+    fn parse_reference(&mut self) -> Result<char, ParseError> {
+        let (line, column) = (self.line, self.column);
+        self.next();
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|&c| c.is_alphanumeric() || c == '#') {
+            name.push(c);
+            self.next();
+        }
+        self.expect(";", "';'")?;
+        let c = match name.as_str() {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match name.strip_prefix('#') {
+                Some(code) => match code.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => code.parse().ok(),
+                }
+                .and_then(std::char::from_u32)
+                .filter(|&c| c != '\0'),
+                None => None,
+            },
+        };
+        c.ok_or(ParseError {
+            line,
+            column,
+            kind: ParseErrorKind::InvalidReference(name),
+        })
+    }
+}
+
+impl<'arena> XML<'arena> {
+    /// Parses the text of an XML file into a [`Document`](XML::Document), allocating all the
+    /// nodes into a given [`Arena`] and returning a reference to the root node.  Line endings
+    /// are normalised to `\n`, like the XML spec asks for.
+    pub fn parse(
+        text: &str,
+        arena: &'arena Arena<XML<'arena>>,
+    ) -> Result<&'arena XML<'arena>, ParseError> {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        Parser::new(&text, arena).parse_document()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::display_token::{
+        DisplayToken, FormatKind, IndentKind, RenderStyle, SyntaxCategory,
+    };
+    use super::super::size::Size;
+    use super::super::test_json::Rng;
+    use super::{ParseError, ParseErrorKind, XMLFormat, MAX_NESTING_DEPTH, XML};
+    use crate::arena::Arena;
+    use crate::ast::{Ast, DeleteError, InsertError, MoveError, ValidationError};
+    use crate::config::options::Options;
+    use crate::language::Language;
+
+    const COMPACT: XMLFormat = XMLFormat::Compact { self_closing: true };
+    const PRETTY: XMLFormat = XMLFormat::Pretty {
+        indent: IndentKind::Spaces(2),
+        self_closing: true,
+    };
+
+    /// Parses some text, and returns its only top-level node
+    fn parse_one<'arena>(text: &str, arena: &'arena Arena<XML<'arena>>) -> &'arena XML<'arena> {
+        let document = XML::parse(text, arena).unwrap();
+        assert_eq!(document.children().len(), 1);
+        document.children()[0]
+    }
+
+    #[test]
+    fn to_text() {
+        let arena = Arena::new();
+        let root = XML::parse(
+            "<?xml version=\"1.0\"?>\n<note id=\"1\" lang='en'>\n  <to>Sapling</to>\n  \
+             <!-- sent -->\n  <empty></empty>\n</note>",
+            &arena,
+        )
+        .unwrap();
+        assert_eq!(
+            root.to_text(&COMPACT),
+            "<?xml version=\"1.0\"?><note id=\"1\" lang=\"en\"><to>Sapling</to>\
+             <!-- sent --><empty/></note>"
+        );
+        assert_eq!(
+            root.to_text(&PRETTY),
+            "<?xml version=\"1.0\"?>\n<note id=\"1\" lang=\"en\">\n  <to>Sapling</to>\n  \
+             <!-- sent -->\n  <empty/>\n</note>"
+        );
+        // Elements that contain text are never broken, since that would change the text
+        let mixed = parse_one("<p>Some <b>bold</b> text</p>", &arena);
+        assert_eq!(mixed.to_text(&PRETTY), "<p>Some <b>bold</b> text</p>");
+        // The lines of text are indented with the element they're in
+        let lines = parse_one("<a>\n  <p>one\n     two</p>\n</a>", &arena);
+        assert_eq!(lines.to_text(&PRETTY), "<a>\n  <p>one\n  two</p>\n</a>");
+        let tabs = XMLFormat::Pretty {
+            indent: IndentKind::Tabs,
+            self_closing: true,
+        };
+        assert_eq!(
+            parse_one("<a><b/><c/></a>", &arena).to_text(&tabs),
+            "<a>\n\t<b/>\n\t<c/>\n</a>"
+        );
+        assert_eq!(XML::default().to_text(&PRETTY), "");
+    }
+
+    #[test]
+    fn self_closing() {
+        let arena = Arena::new();
+        let root = parse_one("<a x=\"1\"><b></b><c/></a>", &arena);
+        let mut format = COMPACT;
+        assert_eq!(root.to_text(&format), "<a x=\"1\"><b/><c/></a>");
+        let options = Options {
+            self_closing: false,
+            ..Options::default()
+        };
+        format.apply_options(&options, Language::XML);
+        assert_eq!(root.to_text(&format), "<a x=\"1\"><b></b><c></c></a>");
+        assert_eq!(
+            root.size(&format),
+            Size::from(root.to_text(&format).as_str())
+        );
+        // Changing the kind of style keeps the choice
+        format.set_kind(FormatKind::Pretty);
+        assert_eq!(
+            format,
+            XMLFormat::Pretty {
+                indent: IndentKind::default(),
+                self_closing: false
+            }
+        );
+        assert_eq!(
+            root.to_text(&format),
+            "<a x=\"1\">\n    <b></b>\n    <c></c>\n</a>"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let arena = Arena::new();
+        let root = parse_one(
+            "<a q='say \"hi\" &amp; &lt;go&gt;' t=\"&#9;&#x41;&apos;\">1 &lt; 2 &amp;&amp; \
+             \"x\" &gt; 'y'</a>",
+            &arena,
+        );
+        let attribute_values: Vec<&str> = root.children()[..2]
+            .iter()
+            .map(|attribute| attribute.children()[1].text().unwrap())
+            .collect();
+        assert_eq!(attribute_values, vec!["say \"hi\" & <go>", "\tA'"]);
+        assert_eq!(root.children()[2].text(), Some("1 < 2 && \"x\" > 'y'"));
+        // Quotes only have to be escaped in attribute values, and `<` and `&` everywhere
+        assert_eq!(
+            root.to_text(&COMPACT),
+            "<a q=\"say &quot;hi&quot; &amp; &lt;go>\" t=\"&#9;A'\">1 &lt; 2 &amp;&amp; \"x\" \
+             &gt; 'y'</a>"
+        );
+        // Control chars are escaped too
+        let text = XML::Text("\r\u{1}".to_string());
+        assert_eq!(text.to_text(&COMPACT), "&#13;&#x1;");
+        let value = XML::Value("a\nb".to_string());
+        assert_eq!(value.to_text(&COMPACT), "\"a&#10;b\"");
+        assert_eq!(value.display_name(), "\"a&#10;b\"");
+        // Nothing is escaped in CDATA sections and comments
+        let cdata = parse_one("<a><![CDATA[<&>]]><!--<&>--></a>", &arena);
+        assert_eq!(cdata.children()[0], &XML::CData("<&>".to_string()));
+        assert_eq!(cdata.to_text(&COMPACT), "<a><![CDATA[<&>]]><!--<&>--></a>");
+    }
+
+    #[test]
+    fn syntax_categories() {
+        let arena = Arena::new();
+        let root = parse_one(
+            "<a x=\"&amp;\"><!--c--><?pi?>t&lt;<![CDATA[d]]><b/></a>",
+            &arena,
+        );
+        let unnamed = arena.alloc(XML::Element(String::new(), vec![]));
+        let root = arena.alloc(XML::Document(vec![root, unnamed]));
+        let categories: Vec<(String, SyntaxCategory)> = root
+            .display_tokens(&COMPACT)
+            .filter_map(|(_, tok)| match tok {
+                DisplayToken::Text(text, category) => Some((text.into_owned(), category)),
+                _ => None,
+            })
+            .collect();
+        let expected = [
+            ("<", SyntaxCategory::Default),
+            ("a", SyntaxCategory::Keyword),
+            ("x", SyntaxCategory::Ident),
+            ("=", SyntaxCategory::Default),
+            ("\"", SyntaxCategory::Literal),
+            ("&amp;", SyntaxCategory::Special),
+            ("\"", SyntaxCategory::Literal),
+            (">", SyntaxCategory::Default),
+            ("<!--c-->", SyntaxCategory::Comment),
+            ("<?pi?>", SyntaxCategory::Preproc),
+            ("t", SyntaxCategory::Default),
+            ("&lt;", SyntaxCategory::Special),
+            ("<![CDATA[d]]>", SyntaxCategory::Literal),
+            ("<", SyntaxCategory::Default),
+            ("b", SyntaxCategory::Keyword),
+            ("/>", SyntaxCategory::Default),
+            ("</", SyntaxCategory::Default),
+            ("a", SyntaxCategory::Keyword),
+            (">", SyntaxCategory::Default),
+            // Elements without a name are errors
+            ("<", SyntaxCategory::Default),
+            ("", SyntaxCategory::Error),
+            ("/>", SyntaxCategory::Default),
+        ];
+        assert_eq!(
+            categories,
+            expected
+                .iter()
+                .map(|&(text, category)| (text.to_string(), category))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        for text in &[
+            "",
+            "<a/>",
+            "<a> </a>",
+            "<a b=\"1\" c=\"\"/>",
+            "<!DOCTYPE html><html><body/></html>",
+            "<!DOCTYPE note [<!ELEMENT note (#PCDATA)>]><note/>",
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><a/><!-- after -->",
+            "<p>Text with <b>bold</b>, <![CDATA[raw <data>]]> and &amp; &lt;</p>",
+            "<a>  spaces  <b/>  </a>",
+            "<ns:a xml:lang=\"en\" data-x=\"&quot;&#10;\"><日本 語=\"🎉\"/></ns:a>",
+            "<a><!----><!-- - - --></a>",
+        ] {
+            let arena = Arena::new();
+            let root = XML::parse(text, &arena).unwrap();
+            // Compact formatting should exactly reproduce our (compactly formatted) inputs
+            assert_eq!(root.to_text(&COMPACT), *text);
+            // Pretty formatting should reparse to the same tree
+            let pretty_text = root.to_text(&PRETTY);
+            assert_eq!(
+                XML::parse(&pretty_text, &arena),
+                Ok(root),
+                "{}",
+                pretty_text
+            );
+        }
+    }
+
+    #[test]
+    fn indented_cdata_and_comments() {
+        // Like comments, CDATA sections don't make the whitespace around them into text, so it's
+        // only the indentation that pretty formatting puts back
+        let text = concat!(
+            "<root>\n",
+            "  <![CDATA[x]]>\n",
+            "  <a>1</a>\n",
+            "  <!-- c -->\n",
+            "  <b>\n",
+            "    <![CDATA[y]]>\n",
+            "    <!-- d -->\n",
+            "    <c/>\n",
+            "  </b>\n",
+            "</root>"
+        );
+        let arena = Arena::new();
+        let root = XML::parse(text, &arena).unwrap();
+        assert_eq!(root.to_text(&PRETTY), text);
+        assert_eq!(
+            root.to_text(&COMPACT),
+            "<root><![CDATA[x]]><a>1</a><!-- c --><b><![CDATA[y]]><!-- d --><c/></b></root>"
+        );
+        // Text next to a CDATA section is still kept as it is
+        let root = XML::parse("<a>x <![CDATA[y]]> </a>", &arena).unwrap();
+        assert_eq!(root.to_text(&PRETTY), "<a>x <![CDATA[y]]> </a>");
+    }
+
+    #[test]
+    fn parse_structure() {
+        let arena = Arena::new();
+        let root = XML::parse(
+            "\r\n<a x = 'y'>\r\n  <b>hi\r\n    there</b>\r\n  <c> </c>\n</a >\n",
+            &arena,
+        )
+        .unwrap();
+        let name = XML::Name("x".to_string());
+        let value = XML::Value("y".to_string());
+        let attribute = XML::Attribute([&name, &value]);
+        // Whitespace is kept in text, apart from the indentation of every line after the
+        // first (and whitespace between elements, which is only indentation)
+        let text = XML::Text("hi\nthere".to_string());
+        let b = XML::Element("b".to_string(), vec![&text]);
+        let space = XML::Text(" ".to_string());
+        let c = XML::Element("c".to_string(), vec![&space]);
+        let a = XML::Element("a".to_string(), vec![&attribute, &b, &c]);
+        assert_eq!(*root, XML::Document(vec![&a]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line, column, kind) in &[
+            (
+                "<a>",
+                1,
+                4,
+                ParseErrorKind::Expected {
+                    expected: "a closing tag",
+                    found: None,
+                },
+            ),
+            (
+                "<a>\n</b>",
+                2,
+                3,
+                ParseErrorKind::MismatchedTag {
+                    open: "a".to_string(),
+                    close: "b".to_string(),
+                },
+            ),
+            (
+                "text",
+                1,
+                1,
+                ParseErrorKind::Expected {
+                    expected: "an element",
+                    found: Some('t'),
+                },
+            ),
+            (
+                "<1/>",
+                1,
+                2,
+                ParseErrorKind::Expected {
+                    expected: "an element name",
+                    found: Some('1'),
+                },
+            ),
+            (
+                "<a b=\"1\"c=\"2\"/>",
+                1,
+                9,
+                ParseErrorKind::Expected {
+                    expected: "whitespace, '>' or '/>'",
+                    found: Some('c'),
+                },
+            ),
+            (
+                "<a b=1/>",
+                1,
+                6,
+                ParseErrorKind::Expected {
+                    expected: "'\"' or \"'\"",
+                    found: Some('1'),
+                },
+            ),
+            ("<a b=\"<\"/>", 1, 7, ParseErrorKind::Unescaped('<')),
+            (
+                "<a>&nbsp;</a>",
+                1,
+                4,
+                ParseErrorKind::InvalidReference("nbsp".to_string()),
+            ),
+            (
+                "<a>&#0;</a>",
+                1,
+                4,
+                ParseErrorKind::InvalidReference("#0".to_string()),
+            ),
+            (
+                "<a>a & b</a>",
+                1,
+                7,
+                ParseErrorKind::Expected {
+                    expected: "';'",
+                    found: Some(' '),
+                },
+            ),
+            (
+                "<!-- a",
+                1,
+                7,
+                ParseErrorKind::Expected {
+                    expected: "'-->'",
+                    found: None,
+                },
+            ),
+            (
+                "<a><![CDATA[x</a>",
+                1,
+                18,
+                ParseErrorKind::Expected {
+                    expected: "']]>'",
+                    found: None,
+                },
+            ),
+        ] {
+            let arena = Arena::new();
+            assert_eq!(
+                XML::parse(text, &arena),
+                Err(ParseError {
+                    line: *line,
+                    column: *column,
+                    kind: kind.clone()
+                }),
+                "parsing {:?}",
+                text
+            );
+        }
+        assert_eq!(
+            ParseErrorKind::Unescaped('<').to_string(),
+            "'<' has to be written as '&lt;'"
+        );
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let arena = Arena::new();
+        let text = "<a>".repeat(MAX_NESTING_DEPTH) + &"</a>".repeat(MAX_NESTING_DEPTH);
+        assert!(XML::parse(&text, &arena).is_ok());
+        let text = "<a>".repeat(MAX_NESTING_DEPTH + 1) + &"</a>".repeat(MAX_NESTING_DEPTH + 1);
+        assert_eq!(
+            XML::parse(&text, &arena).unwrap_err().kind,
+            ParseErrorKind::TooDeeplyNested
+        );
+    }
+
+    #[test]
+    fn fit_to_width() {
+        let arena = Arena::new();
+        let root = parse_one("<a x=\"1\"><b><c/></b><d>text</d></a>", &arena);
+        let mut format = PRETTY;
+        for (max_width, expected_text) in &[
+            (35, "<a x=\"1\"><b><c/></b><d>text</d></a>"),
+            (34, "<a x=\"1\">\n  <b><c/></b>\n  <d>text</d>\n</a>"),
+            (11, "<a x=\"1\">\n  <b><c/></b>\n  <d>text</d>\n</a>"),
+            (
+                10,
+                "<a x=\"1\">\n  <b>\n    <c/>\n  </b>\n  <d>text</d>\n</a>",
+            ),
+        ] {
+            format.set_kind(FormatKind::Fit {
+                max_width: *max_width,
+            });
+            assert_eq!(root.to_text(&format), *expected_text);
+            assert_eq!(root.size(&format), Size::from(*expected_text));
+        }
+        // Elements containing text are never broken, however wide they are
+        format.set_kind(FormatKind::Fit { max_width: 0 });
+        let mixed = parse_one("<p>Some <b>bold</b> text</p>", &arena);
+        assert_eq!(mixed.to_text(&format), "<p>Some <b>bold</b> text</p>");
+        format.set_kind(FormatKind::Compact);
+        assert_eq!(format.indent(), IndentKind::Spaces(0));
+    }
+
+    /// Generates a pseudo-random node which could be part of the content of an element, and is
+    /// at most `max_depth` levels deep
+    fn random<'arena>(
+        rng: &mut Rng,
+        max_depth: usize,
+        arena: &'arena Arena<XML<'arena>>,
+    ) -> &'arena XML<'arena> {
+        const NAMES: &[&str] = &["a", "b-c", "_x.1", "ns:tag", "日本"];
+        const CHARS: &[char] = &[
+            'a', ' ', '<', '&', '>', '"', '\'', '-', ']', '?', '\n', '\t', '\u{1}', '日', '😀',
+        ];
+        let random_string = |rng: &mut Rng| -> String {
+            (0..rng.below(6))
+                .map(|_| CHARS[rng.below(CHARS.len())])
+                .collect()
+        };
+        let kinds = if max_depth == 0 { 4 } else { 5 };
+        let node = match rng.below(kinds) {
+            // Text always has something other than whitespace in it, since whitespace between
+            // elements is only indentation
+            0 => XML::Text("x".to_string())
+                .with_text(&(random_string(rng) + "x" + &random_string(rng)))
+                .unwrap(),
+            1 => XML::Comment(String::new())
+                .with_text(&random_string(rng))
+                .unwrap(),
+            2 => XML::CData(String::new())
+                .with_text(&random_string(rng))
+                .unwrap(),
+            3 => XML::Instruction(String::new())
+                .with_text(&format!("pi {}", random_string(rng)))
+                .unwrap(),
+            _ => {
+                let mut children: Vec<&XML> = Vec::new();
+                for _ in 0..rng.below(3) {
+                    let name = arena.alloc(XML::Name(NAMES[rng.below(NAMES.len())].to_string()));
+                    let value = arena.alloc(XML::Value(random_string(rng) + "\r"));
+                    children.push(arena.alloc(XML::Attribute([name, value])));
+                }
+                for _ in 0..rng.below(5) {
+                    let child = random(rng, max_depth - 1, arena);
+                    // Neighbouring text would be read back as one text node
+                    let is_text = |node: &XML| matches!(node, XML::Text(_));
+                    if !(is_text(child) && children.last().is_some_and(|c| is_text(c))) {
+                        children.push(child);
+                    }
+                }
+                XML::Element(NAMES[rng.below(NAMES.len())].to_string(), children)
+            }
+        };
+        arena.alloc(node)
+    }
+
+    #[test]
+    fn size_matches_text() {
+        // The size of a node should always be the size of the text that it's rendered as, and
+        // the text should parse back into the same tree
+        let formats = [
+            COMPACT,
+            XMLFormat::Compact {
+                self_closing: false,
+            },
+            PRETTY,
+            XMLFormat::Pretty {
+                indent: IndentKind::Tabs,
+                self_closing: false,
+            },
+            XMLFormat::Fit {
+                indent: IndentKind::default(),
+                max_width: 0,
+                self_closing: true,
+            },
+            XMLFormat::Fit {
+                indent: IndentKind::Spaces(2),
+                max_width: 30,
+                self_closing: true,
+            },
+        ];
+        let mut rng = Rng::new(0);
+        for _ in 0..500 {
+            let arena = Arena::new();
+            let mut children: Vec<&XML> = Vec::new();
+            if rng.below(4) == 0 {
+                children.push(arena.alloc(XML::Doctype("html".to_string())));
+            }
+            for _ in 0..rng.below(3) {
+                let child = random(&mut rng, 4, &arena);
+                if child.is_top_level() {
+                    children.push(child);
+                }
+            }
+            let root = arena.alloc(XML::Document(children));
+            for format in &formats {
+                let text = root.to_text(format);
+                assert_eq!(root.size(format), Size::from(text.as_str()), "{}", text);
+                assert_eq!(XML::parse(&text, &arena), Ok(root), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn editing_text() {
+        // Typing into an element changes the name in both of its tags
+        let arena = Arena::new();
+        let element = parse_one("<a x=\"1\">text</a>", &arena);
+        let renamed = arena.alloc(element.with_text("b c\n").unwrap());
+        assert_eq!(renamed.to_text(&COMPACT), "<bc x=\"1\">text</bc>");
+        assert_eq!(renamed.text_offset(1), 2);
+        assert_eq!(
+            XML::Name("a".to_string()).with_text("x y"),
+            Some(XML::Name("xy".to_string()))
+        );
+        // Text can't contain whatever would end it
+        assert_eq!(
+            XML::Comment(String::new()).with_text("a-->b\n   c"),
+            Some(XML::Comment("a-- >b\nc".to_string()))
+        );
+        assert_eq!(
+            XML::CData(String::new()).with_text("]]>"),
+            Some(XML::CData("]] >".to_string()))
+        );
+        assert_eq!(
+            XML::Instruction(String::new()).with_text("a?>"),
+            Some(XML::Instruction("a? >".to_string()))
+        );
+        // The text cursor skips references and newlines
+        assert_eq!(XML::Value("&x".to_string()).text_offset(1), 6);
+        assert_eq!(XML::Text("a<\nb".to_string()).text_offset(4), 6);
+        assert_eq!(XML::Comment("a\nb".to_string()).text_offset(3), 6);
+        assert_eq!(XML::Doctype("html".to_string()).text(), None);
+        // Comments are left alone by replacements
+        assert_eq!(
+            XML::Text("a-b".to_string()).replace_text("-", "_"),
+            Some(XML::Text("a_b".to_string()))
+        );
+        assert_eq!(XML::Comment("-".to_string()).replace_text("-", "_"), None);
+    }
+
+    #[test]
+    fn structure() {
+        let arena = Arena::new();
+        let mut element = parse_one("<a x=\"1\"><b/></a>", &arena).clone();
+        let e = XML::default().from_char('e').unwrap();
+        let text = arena.alloc(XML::Text("t".to_string()));
+        // Attributes are inserted with an empty name, before all of the content
+        let value = arena.alloc(XML::default().from_char('a').unwrap());
+        assert_eq!(
+            element.insert_child(value, &arena, 2),
+            Err(InsertError::InvalidPosition {
+                parent_kind: "element",
+                child_kind: "attribute",
+                index: 2,
+            })
+        );
+        element.insert_child(value, &arena, 1).unwrap();
+        assert_eq!(element.to_text(&COMPACT), "<a x=\"1\" =\"\"><b/></a>");
+        assert_eq!(
+            element.insert_child(text, &arena, 1),
+            Err(InsertError::InvalidPosition {
+                parent_kind: "element",
+                child_kind: "text",
+                index: 1,
+            })
+        );
+        element.insert_child(text, &arena, 3).unwrap();
+        assert!(element.is_valid_child(1, &XML::Attribute([text, text])));
+        assert!(!element.is_valid_child(0, text));
+        assert!(element.is_valid_child(1, text));
+        assert!(!element.is_valid_child(3, &XML::Attribute([text, text])));
+        assert_eq!(
+            element.move_child(2, 0),
+            Err(MoveError::InvalidPosition {
+                parent_kind: "element",
+                child_kind: "element",
+                new_index: 0,
+            })
+        );
+        element.move_child(3, 2).unwrap();
+        assert_eq!(element.to_text(&COMPACT), "<a x=\"1\" =\"\">t<b/></a>");
+        assert_eq!(element.fold_summary(), "<a>…</a> (2 children)");
+
+        // An attribute always has a name and a value
+        let attribute = element.children()[0];
+        assert_eq!(attribute.fold_summary(), "x=…");
+        assert!(attribute.is_key_child(0));
+        assert_eq!(
+            attribute.clone().delete_child(1),
+            Err(DeleteError::RequiredChild {
+                kind: "attribute",
+                child_kind: "value",
+            })
+        );
+        assert_eq!(
+            attribute.clone().move_child(0, 1),
+            Err(MoveError::FixedPositions { kind: "attribute" })
+        );
+
+        // Deleting the last child of an element leaves it self-closing
+        element.delete_children(2..4).unwrap();
+        assert_eq!(element.to_text(&COMPACT), "<a x=\"1\" =\"\"/>");
+        assert_eq!(element.unwrap_index(), None);
+
+        // Only content can be wrapped (in an element with no name) or unwrapped
+        let wrapped = text.wrap_in('e', &arena).unwrap();
+        assert_eq!(wrapped, &XML::Element(String::new(), vec![text]));
+        assert_eq!(wrapped.unwrap_index(), Some(0));
+        assert_eq!(attribute.wrap_in('e', &arena), None);
+        let document = arena.alloc(XML::Document(vec![wrapped]));
+        assert_eq!(document.wrap_in('e', &arena), None);
+        assert_eq!(document.replace_chars().count(), 0);
+        assert_eq!(attribute.replace_chars().count(), 0);
+        assert!(!document.is_valid_child(0, text));
+        assert!(document.is_valid_child(0, &e));
+        assert_eq!(
+            document.clone().insert_child(text, &arena, 0),
+            Err(InsertError::InvalidChild {
+                parent_kind: "document",
+                child_kind: "text",
+            })
+        );
+        assert!(document.is_insert_char('!'));
+        assert!(!document.is_insert_char('t'));
+        assert!(wrapped.is_insert_char('a'));
+        assert!(!text.is_insert_char('e'));
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        let arena = Arena::new();
+        let element = parse_one("<a x=\"1\" y=\"2\" x=\"3\"><b x=\"4\"/></a>", &arena);
+        assert_eq!(
+            element.validate(),
+            vec![ValidationError::DuplicateKey {
+                key: "x".to_string(),
+                index: 2,
+                first_index: 0,
+            }]
+        );
+        assert_eq!(element.children()[3].validate(), vec![]);
+    }
+}
@@ -0,0 +1,652 @@
+//! The command line arguments of the `sapling` binary, which are parsed into [`Arguments`].
+//! Everything that the arguments change about the editor itself is applied to its [`Config`]
+//! (see [`Arguments::apply_to`]), so that nothing deeper down has to look at the arguments.
+
+use crate::ast::display_token::IndentKind;
+use crate::config::Config;
+use crate::editor::script::{Script, ScriptError, ScriptOutput};
+use crate::language::{Language, LanguageRegistry};
+use std::path::PathBuf;
+
+/// The code that Sapling exits with if its arguments are invalid (which is what most command
+/// line parsers use, to tell usage errors apart from everything else going wrong)
+pub const USAGE_EXIT_CODE: i32 = 2;
+
+/// What `--version` prints
+pub const VERSION: &str = concat!("sapling ", env!("CARGO_PKG_VERSION"));
+
+/// The flags that Sapling understands, along with their short names (if they have one) and
+/// whether they take a value.  Values can be given after an `=` or as the next argument.
+/// `--diff` is followed by two files, so it's parsed separately.
+const FLAGS: [(&str, Option<&str>, bool); 20] = [
+    ("--help", Some("-h"), false),
+    ("--version", Some("-V"), false),
+    ("--language", None, true),
+    ("--jsonc", None, false),
+    ("--config", None, true),
+    ("--read-only", Some("-R"), false),
+    ("--indent-width", None, true),
+    ("--tabs", None, false),
+    ("--tab-width", None, true),
+    ("--scrolloff", None, true),
+    ("--diff", None, false),
+    ("--print", None, false),
+    ("--color", None, true),
+    ("--dump-dot", None, false),
+    ("--script", None, true),
+    ("--input", None, true),
+    ("--output", None, true),
+    ("--emit-patch", None, false),
+    ("--log-file", None, true),
+    ("--", None, false),
+];
+
+/// Returns the text printed by `--help`, which lists the languages in `languages`
+pub fn help(languages: &LanguageRegistry) -> String {
+    let names: Vec<_> = languages.names().collect();
+    format!(
+        "\
+{version}
+A highly experimental code editor where you edit code, not text.
+
+Usage: sapling [options] [<file>...]
+       sapling [options] --diff <old> <new>
+       sapling [options] (--print | --dump-dot) [<file> | -]
+       sapling [options] --script <keys> [--emit-patch] [--output <path>] [<file> | -]
+
+Every <file> is opened in its own buffer, and the first one is edited.  Without a file, an
+example tree is edited.  `-` (or piping something into Sapling without giving it a file) reads
+the tree from standard input.
+
+Options:
+  -h, --help              Print this help and exit
+  -V, --version           Print Sapling's version and exit
+      --language <name>   The language of the files, instead of the one their extension
+                          belongs to ({languages})
+      --jsonc             The same as --language jsonc
+      --config <path>     Read the config from <path>, instead of
+                          ~/.config/sapling/config.toml
+  -R, --read-only         Open every buffer read-only, so that nothing can be edited
+      --indent-width <n>  Indent each level by <n> spaces
+      --tabs              Indent with tabs
+      --tab-width <n>     How many columns a tab takes up on screen
+      --scrolloff <n>     How many lines are kept visible above and below the cursor
+      --diff <old> <new>  Compare two files side by side
+      --print             Print the file with syntax highlighting, instead of editing it
+      --color <when>      Whether --print uses colours: auto (the default), always or never
+      --dump-dot          Print a Graphviz graph of the file's tree, instead of editing it
+      --script <keys>     Type <keys> into the editor without a terminal (written like
+                          `jlx:w<CR>`), and then write the resulting text
+      --input <path>      The file to read (the same as giving <path> on its own)
+      --output <path>     Where --script writes its result, instead of standard output
+      --emit-patch        Make --script write a JSON Patch of its edits, instead of the text
+      --log-file <path>   Append the log to <path> (which `:messages` also shows).  If
+                          $RUST_LOG is set, the log goes to ~/.local/state/sapling/sapling.log
+
+Options which take a value can also be written like `--tab-width=4`, and any arguments after
+`--` are files.",
+        version = VERSION,
+        languages = names.join(", "),
+    )
+}
+
+/// What Sapling reads
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Input {
+    /// Nothing, so an example tree is edited
+    Example,
+    /// Standard input (given as `-`, or piped into Sapling without a file)
+    Stdin,
+    /// Some files, which are each opened in their own buffer
+    Files(Vec<PathBuf>),
+    /// Two files, which are compared side by side (with `--diff`)
+    Diff { old: PathBuf, new: PathBuf },
+}
+
+/// What Sapling does with its [`Input`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Mode {
+    /// Edit it in the terminal
+    Edit,
+    /// Print it with syntax highlighting (`--print`).  `color` is whether the output is
+    /// coloured, or `None` to decide by where the output goes.
+    Print { color: Option<bool> },
+    /// Print a Graphviz graph of its tree (`--dump-dot`)
+    DumpDot,
+    /// Run a script on it without a terminal (`--script`), and then write `output` to
+    /// `output_path` (or standard output)
+    Script {
+        script: Script,
+        output: ScriptOutput,
+        output_path: Option<PathBuf>,
+    },
+    /// Print the help and exit (`--help`)
+    Help,
+    /// Print the version and exit (`--version`)
+    Version,
+}
+
+impl Mode {
+    /// Returns the flag that chose this mode
+    fn flag(&self) -> &'static str {
+        match self {
+            Mode::Edit => "editing",
+            Mode::Print { .. } => "--print",
+            Mode::DumpDot => "--dump-dot",
+            Mode::Script { .. } => "--script",
+            Mode::Help => "--help",
+            Mode::Version => "--version",
+        }
+    }
+}
+
+/// The command line arguments, once they've been parsed and checked
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Arguments {
+    pub mode: Mode,
+    pub input: Input,
+    /// The language that every file is read as (`--language` or `--jsonc`), instead of the
+    /// language of their extensions
+    pub language: Option<Language>,
+    /// The config file to read instead of the default one (`--config`)
+    pub config_path: Option<PathBuf>,
+    /// Whether every buffer is opened read-only (`--read-only`)
+    pub read_only: bool,
+    /// `--indent-width` or `--tabs`
+    pub indent: Option<IndentKind>,
+    /// `--tab-width`
+    pub tab_width: Option<usize>,
+    /// `--scrolloff`
+    pub scroll_off: Option<usize>,
+    /// The file that the log is appended to (`--log-file`)
+    pub log_file: Option<PathBuf>,
+}
+
+impl Arguments {
+    /// Parses the command line arguments (not including the name of the binary), checking that
+    /// they make sense together.  If no file is given but standard input isn't a terminal, then
+    /// the tree is read from standard input.
+    pub fn parse(
+        args: impl IntoIterator<Item = String>,
+        languages: &LanguageRegistry,
+        stdin_is_terminal: bool,
+    ) -> Result<Arguments, ArgumentError> {
+        let mut parsed = Arguments {
+            mode: Mode::Edit,
+            input: Input::Example,
+            language: None,
+            config_path: None,
+            read_only: false,
+            indent: None,
+            tab_width: None,
+            scroll_off: None,
+            log_file: None,
+        };
+        let mut files = Vec::new();
+        let mut stdin = false;
+        let mut diff = None;
+        // Every flag that was given, so that flags which can't be used together can be found
+        let mut given = Vec::new();
+        let mut color = None;
+        let mut script = None;
+        let mut output_path = None;
+        let mut only_files = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if only_files || !arg.starts_with('-') {
+                files.push(PathBuf::from(arg));
+                continue;
+            }
+            if arg == "-" {
+                stdin = true;
+                continue;
+            }
+            let (name, inline_value) = match arg.find('=') {
+                Some(i) => (&arg[..i], Some(arg[i + 1..].to_string())),
+                None => (arg.as_str(), None),
+            };
+            let (flag, takes_value) = match FLAGS
+                .iter()
+                .find(|(long, short, _)| *long == name || *short == Some(name))
+            {
+                Some(&(flag, _, takes_value)) => (flag, takes_value),
+                None => return Err(ArgumentError::UnknownFlag(arg)),
+            };
+            let value = match (takes_value, inline_value) {
+                (true, Some(value)) => value,
+                (true, None) => args.next().ok_or(ArgumentError::MissingValue(flag))?,
+                (false, Some(_)) => return Err(ArgumentError::UnexpectedValue(flag)),
+                (false, None) => String::new(),
+            };
+            let number = || {
+                value.parse().map_err(|_| ArgumentError::InvalidNumber {
+                    flag,
+                    value: value.clone(),
+                })
+            };
+            match flag {
+                "--help" => parsed.mode = Mode::Help,
+                "--version" => parsed.mode = Mode::Version,
+                "--language" | "--jsonc" => {
+                    let name = if flag == "--jsonc" { "jsonc" } else { &value };
+                    let language = languages.from_name(name).ok_or_else(|| {
+                        ArgumentError::UnknownLanguage {
+                            name: name.to_string(),
+                            names: languages.names().collect(),
+                        }
+                    })?;
+                    parsed.language = Some(language);
+                }
+                "--config" => parsed.config_path = Some(PathBuf::from(value)),
+                "--log-file" => parsed.log_file = Some(PathBuf::from(value)),
+                "--read-only" => parsed.read_only = true,
+                "--indent-width" => parsed.indent = Some(IndentKind::Spaces(number()?)),
+                "--tabs" => parsed.indent = Some(IndentKind::Tabs),
+                "--tab-width" => parsed.tab_width = Some(number()?),
+                "--scrolloff" => parsed.scroll_off = Some(number()?),
+                "--diff" => match (args.next(), args.next()) {
+                    (Some(old), Some(new)) => diff = Some((PathBuf::from(old), PathBuf::from(new))),
+                    _ => return Err(ArgumentError::DiffNeedsTwoFiles),
+                },
+                "--color" => {
+                    color = Some(match value.as_str() {
+                        "auto" => None,
+                        "always" => Some(true),
+                        "never" => Some(false),
+                        _ => return Err(ArgumentError::InvalidColor(value)),
+                    })
+                }
+                "--script" => {
+                    script = Some(Script::parse(&value).map_err(ArgumentError::InvalidScript)?)
+                }
+                "--input" => files.push(PathBuf::from(value)),
+                "--output" => output_path = Some(PathBuf::from(value)),
+                "--" => only_files = true,
+                _ => {}
+            }
+            given.push(flag);
+        }
+        // `--help` and `--version` ignore everything else
+        if matches!(parsed.mode, Mode::Help | Mode::Version) {
+            return Ok(parsed);
+        }
+
+        let is_given = |flag| given.contains(&flag);
+        for &(first, second) in &[
+            ("--print", "--dump-dot"),
+            ("--script", "--print"),
+            ("--script", "--dump-dot"),
+            ("--indent-width", "--tabs"),
+            ("--jsonc", "--language"),
+        ] {
+            if is_given(first) && is_given(second) {
+                return Err(ArgumentError::Conflict(first, second));
+            }
+        }
+        for &(flag, needs) in &[
+            ("--output", "--script"),
+            ("--emit-patch", "--script"),
+            ("--color", "--print"),
+        ] {
+            if is_given(flag) && !is_given(needs) {
+                return Err(ArgumentError::NeedsFlag { flag, needs });
+            }
+        }
+
+        if let Some(script) = script {
+            parsed.mode = Mode::Script {
+                script,
+                output: match is_given("--emit-patch") {
+                    true => ScriptOutput::Patch,
+                    false => ScriptOutput::Text,
+                },
+                output_path,
+            };
+        } else if let Some(color) = color {
+            parsed.mode = Mode::Print { color };
+        } else if is_given("--print") {
+            parsed.mode = Mode::Print { color: None };
+        } else if is_given("--dump-dot") {
+            parsed.mode = Mode::DumpDot;
+        }
+        let editing = parsed.mode == Mode::Edit;
+
+        parsed.input = match diff {
+            Some(_) if !editing => {
+                return Err(ArgumentError::Conflict("--diff", parsed.mode.flag()))
+            }
+            Some(_) if stdin => return Err(ArgumentError::DiffWith("standard input")),
+            Some(_) if !files.is_empty() => return Err(ArgumentError::DiffWith("another file")),
+            Some((old, new)) => Input::Diff { old, new },
+            None if stdin && !files.is_empty() => return Err(ArgumentError::StdinWithFiles),
+            None if stdin => Input::Stdin,
+            None if files.is_empty() && !stdin_is_terminal => Input::Stdin,
+            None if files.is_empty() => Input::Example,
+            None => Input::Files(files),
+        };
+        match &parsed.input {
+            Input::Example if !editing => Err(ArgumentError::NeedsFile(parsed.mode.flag())),
+            Input::Files(files) if files.len() > 1 && !editing => {
+                Err(ArgumentError::TooManyFiles(parsed.mode.flag()))
+            }
+            _ => Ok(parsed),
+        }
+    }
+
+    /// Returns the file whose extension decides the language of the input (if there's no
+    /// `--language`): the first file, or the old file of a diff
+    pub fn first_path(&self) -> Option<&PathBuf> {
+        match &self.input {
+            Input::Files(files) => files.first(),
+            Input::Diff { old, .. } => Some(old),
+            Input::Example | Input::Stdin => None,
+        }
+    }
+
+    /// Applies the arguments which override the config file to `config`
+    pub fn apply_to(&self, config: &mut Config) {
+        match self.indent {
+            Some(IndentKind::Spaces(width)) => {
+                config.options.tab_expand = true;
+                config.options.indent_width = width;
+            }
+            Some(IndentKind::Tabs) => config.options.tab_expand = false,
+            None => {}
+        }
+        if let Some(width) = self.tab_width {
+            config.options.tab_width = width;
+        }
+        if let Some(lines) = self.scroll_off {
+            config.options.scrolloff = lines;
+        }
+        config.read_only |= self.read_only;
+    }
+}
+
+/// The reason that the command line arguments are invalid
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ArgumentError {
+    /// An argument starting with `-` isn't one of Sapling's flags
+    UnknownFlag(String),
+    /// A flag that takes a value was the last argument
+    MissingValue(&'static str),
+    /// A flag that doesn't take a value was given one (after an `=`)
+    UnexpectedValue(&'static str),
+    /// A flag that takes a number was given something else
+    InvalidNumber { flag: &'static str, value: String },
+    /// `--language` was given a name that doesn't belong to any language
+    UnknownLanguage {
+        name: String,
+        names: Vec<&'static str>,
+    },
+    /// `--color` was given something other than `auto`, `always` or `never`
+    InvalidColor(String),
+    /// The keys given to `--script` couldn't be parsed
+    InvalidScript(ScriptError),
+    /// Two flags were given which can't be used together
+    Conflict(&'static str, &'static str),
+    /// A flag was given without the flag that it changes
+    NeedsFlag {
+        flag: &'static str,
+        needs: &'static str,
+    },
+    /// `--diff` wasn't followed by two files
+    DiffNeedsTwoFiles,
+    /// `--diff` was given something else to edit (either `another file` or `standard input`)
+    DiffWith(&'static str),
+    /// `-` was given along with some files
+    StdinWithFiles,
+    /// A flag that reads one file was given several
+    TooManyFiles(&'static str),
+    /// A flag that reads a file was given neither a file nor standard input
+    NeedsFile(&'static str),
+}
+
+impl std::fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgumentError::UnknownFlag(arg) => write!(f, "Unknown option '{}'", arg),
+            ArgumentError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ArgumentError::UnexpectedValue(flag) => write!(f, "{} doesn't take a value", flag),
+            ArgumentError::InvalidNumber { flag, value } => {
+                write!(f, "Invalid number '{}' for {}", value, flag)
+            }
+            ArgumentError::UnknownLanguage { name, names } => write!(
+                f,
+                "Unknown language '{}' (expected one of {})",
+                name,
+                names.join(", ")
+            ),
+            ArgumentError::InvalidColor(choice) => write!(
+                f,
+                "Invalid colour choice '{}' (expected auto, always or never)",
+                choice
+            ),
+            ArgumentError::InvalidScript(error) => write!(f, "{}", error),
+            ArgumentError::Conflict(first, second) => {
+                write!(f, "{} and {} can't be used together", first, second)
+            }
+            ArgumentError::NeedsFlag { flag, needs } => {
+                write!(f, "{} can only be used with {}", flag, needs)
+            }
+            ArgumentError::DiffNeedsTwoFiles => write!(f, "--diff needs two files to compare"),
+            ArgumentError::DiffWith(what) => write!(f, "--diff can't be given {} to edit", what),
+            ArgumentError::StdinWithFiles => {
+                write!(
+                    f,
+                    "Standard input (-) can't be edited along with other files"
+                )
+            }
+            ArgumentError::TooManyFiles(flag) => write!(f, "{} can only read one file", flag),
+            ArgumentError::NeedsFile(flag) => write!(
+                f,
+                "{} needs a file to read (either a path, or something piped into standard input)",
+                flag
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{help, ArgumentError, Arguments, Input, Mode};
+    use crate::ast::display_token::IndentKind;
+    use crate::ast::json::Dialect;
+    use crate::config::Config;
+    use crate::editor::script::{Script, ScriptOutput};
+    use crate::language::{Language, LanguageRegistry};
+    use std::path::PathBuf;
+
+    /// Parses some space-separated arguments, as if standard input is a terminal
+    fn parse(args: &str) -> Result<Arguments, ArgumentError> {
+        let args = args.split_whitespace().map(str::to_string);
+        Arguments::parse(args, &LanguageRegistry::default(), true)
+    }
+
+    /// Parses arguments which should be valid, and returns what they read
+    fn input(args: &str) -> Input {
+        parse(args).unwrap().input
+    }
+
+    fn files(paths: &[&str]) -> Input {
+        Input::Files(paths.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn inputs() {
+        assert_eq!(input(""), Input::Example);
+        assert_eq!(input("a.json"), files(&["a.json"]));
+        assert_eq!(input("a.json b.json"), files(&["a.json", "b.json"]));
+        assert_eq!(input("--input a.json b.json"), files(&["a.json", "b.json"]));
+        assert_eq!(input("-- --help -"), files(&["--help", "-"]));
+        assert_eq!(input("-"), Input::Stdin);
+        assert_eq!(
+            input("--diff old.json new.json"),
+            Input::Diff {
+                old: PathBuf::from("old.json"),
+                new: PathBuf::from("new.json")
+            }
+        );
+        // Something piped into Sapling is only read if there's no file
+        let piped = |args: &str| {
+            let args = args.split_whitespace().map(str::to_string);
+            Arguments::parse(args, &LanguageRegistry::default(), false).map(|a| a.input)
+        };
+        assert_eq!(piped(""), Ok(Input::Stdin));
+        assert_eq!(piped("a.json"), Ok(files(&["a.json"])));
+        assert_eq!(
+            parse("a.json").unwrap().first_path(),
+            Some(&PathBuf::from("a.json"))
+        );
+    }
+
+    #[test]
+    fn options() {
+        let arguments =
+            parse("--language=sexpr --config c.toml -R --tabs --tab-width 4 --scrolloff=0 a.el")
+                .unwrap();
+        assert_eq!(arguments.mode, Mode::Edit);
+        assert_eq!(arguments.language, Some(Language::SExpr));
+        assert_eq!(arguments.config_path, Some(PathBuf::from("c.toml")));
+        assert_eq!(arguments.log_file, None);
+        assert!(arguments.read_only);
+        assert_eq!(arguments.indent, Some(IndentKind::Tabs));
+        assert_eq!(
+            parse("--jsonc").unwrap().language,
+            Some(Language::JSON(Dialect::JSONC))
+        );
+        assert_eq!(
+            parse("--indent-width=2").unwrap().indent,
+            Some(IndentKind::Spaces(2))
+        );
+        assert_eq!(
+            parse("--log-file /tmp/sapling.log").unwrap().log_file,
+            Some(PathBuf::from("/tmp/sapling.log"))
+        );
+
+        // The options which override the config file are applied to it
+        let mut config = Config::default();
+        arguments.apply_to(&mut config);
+        assert!(!config.options.tab_expand);
+        assert_eq!(config.options.tab_width, 4);
+        assert_eq!(config.options.scrolloff, 0);
+        assert!(config.read_only);
+    }
+
+    #[test]
+    fn modes() {
+        let mode = |args: &str| parse(args).unwrap().mode;
+        assert_eq!(mode("--help a.json --tabs"), Mode::Help);
+        assert_eq!(mode("-V"), Mode::Version);
+        // `--help` is shown even if the other arguments don't make sense together
+        assert_eq!(mode("--print --dump-dot -h"), Mode::Help);
+        assert_eq!(mode("--print a.json"), Mode::Print { color: None });
+        assert_eq!(
+            mode("--print --color never a.json"),
+            Mode::Print { color: Some(false) }
+        );
+        assert_eq!(mode("--dump-dot a.json"), Mode::DumpDot);
+        assert_eq!(
+            mode("--script=jx --emit-patch --output out.json --input a.json"),
+            Mode::Script {
+                script: Script::parse("jx").unwrap(),
+                output: ScriptOutput::Patch,
+                output_path: Some(PathBuf::from("out.json")),
+            }
+        );
+        assert_eq!(
+            mode("--script <CR> -"),
+            Mode::Script {
+                script: Script::parse("<CR>").unwrap(),
+                output: ScriptOutput::Text,
+                output_path: None,
+            }
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for (args, expected_error) in &[
+            ("--frobnicate", "Unknown option '--frobnicate'"),
+            ("-x a.json", "Unknown option '-x'"),
+            ("a.json --config", "--config needs a value"),
+            ("--tabs=yes", "--tabs doesn't take a value"),
+            ("--scrolloff=lots", "Invalid number 'lots' for --scrolloff"),
+            (
+                "--language klingon",
+                "Unknown language 'klingon' (expected one of expr, json, jsonc, sexpr, toml, xml)",
+            ),
+            (
+                "--print --color=sometimes a.json",
+                "Invalid colour choice 'sometimes' (expected auto, always or never)",
+            ),
+            (
+                "--script a<Nope> a.json",
+                "Script failed at position 1 ('<Nope>'): unknown special key",
+            ),
+            (
+                "--print --dump-dot a.json",
+                "--print and --dump-dot can't be used together",
+            ),
+            (
+                "--dump-dot --script x a.json",
+                "--script and --dump-dot can't be used together",
+            ),
+            (
+                "--tabs --indent-width=2",
+                "--indent-width and --tabs can't be used together",
+            ),
+            (
+                "--language json --jsonc",
+                "--jsonc and --language can't be used together",
+            ),
+            (
+                "--output out.json a.json",
+                "--output can only be used with --script",
+            ),
+            (
+                "--emit-patch a.json",
+                "--emit-patch can only be used with --script",
+            ),
+            (
+                "--color always a.json",
+                "--color can only be used with --print",
+            ),
+            ("--diff old.json", "--diff needs two files to compare"),
+            ("--diff a b c", "--diff can't be given another file to edit"),
+            (
+                "- --diff a b",
+                "--diff can't be given standard input to edit",
+            ),
+            (
+                "--print --diff a b",
+                "--diff and --print can't be used together",
+            ),
+            (
+                "- a.json",
+                "Standard input (-) can't be edited along with other files",
+            ),
+            ("--print a.json b.json", "--print can only read one file"),
+            ("--script jx", "--script needs a file to read"),
+        ] {
+            let error = parse(args).unwrap_err().to_string();
+            assert!(error.starts_with(expected_error), "{}: {}", args, error);
+        }
+    }
+
+    #[test]
+    fn help_text() {
+        let text = help(&LanguageRegistry::default());
+        assert!(text.contains("(expr, json, jsonc, sexpr, toml, xml)"));
+        // Every flag is explained
+        for (long, short, _) in &super::FLAGS[..super::FLAGS.len() - 1] {
+            assert!(text.contains(long), "{}", long);
+            if let Some(short) = short {
+                assert!(text.contains(&format!("{}, {}", short, long)), "{}", short);
+            }
+        }
+        assert!(text.lines().all(|line| line.chars().count() <= 100));
+    }
+}
@@ -0,0 +1,644 @@
+//! Parsing of colours and styles, and conversion of colours to whatever the terminal can display.
+
+use tuikit::prelude::{Attr, Color, Effect};
+
+/// How text of one [`SyntaxCategory`](crate::ast::display_token::SyntaxCategory) is drawn: its
+/// colours along with the effects (like bold) that it's drawn with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Style {
+    /// The colour of the text, which is [`Color::Default`] for the terminal's default colour
+    pub fg: Color,
+    /// The colour behind the text, or `None` to leave whatever is already there
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    /// Italic text can only be shown when a tree is printed or exported, since the terminal
+    /// library doesn't support it
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Creates a `Style` that only gives text a colour
+    pub const fn new(fg: Color) -> Self {
+        Style {
+            fg,
+            bg: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+
+    /// Returns the [`Effect`]s that this style draws text with in the editor
+    pub fn effect(&self) -> Effect {
+        let mut effect = Effect::empty();
+        for (on, flag) in &[
+            (self.bold, Effect::BOLD),
+            (self.dim, Effect::DIM),
+            (self.underline, Effect::UNDERLINE),
+            (self.reverse, Effect::REVERSE),
+        ] {
+            if *on {
+                effect |= *flag;
+            }
+        }
+        effect
+    }
+
+    /// Returns the [`Attr`] that text of this style is drawn with in the editor
+    pub fn attr(&self) -> Attr {
+        Attr::default()
+            .fg(self.fg)
+            .bg(self.bg.unwrap_or(Color::Default))
+            .effect(self.effect())
+    }
+
+    /// Returns `true` if the style only sets the colour of the text, so it can be drawn without
+    /// resetting whatever the previous text was drawn with
+    pub fn is_plain(&self) -> bool {
+        *self == Style::new(self.fg)
+    }
+
+    /// Returns a copy of this style where both colours have been converted with
+    /// [`quantize_in`], using the fallbacks of the colours in `palette`
+    pub fn quantized(&self, support: ColorSupport, palette: &[PaletteColor]) -> Style {
+        Style {
+            fg: quantize_in(self.fg, support, palette),
+            bg: self.bg.map(|bg| quantize_in(bg, support, palette)),
+            ..*self
+        }
+    }
+}
+
+impl From<Color> for Style {
+    fn from(fg: Color) -> Self {
+        Style::new(fg)
+    }
+}
+
+/// The colours that a terminal is able to display
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ColorSupport {
+    /// The terminal can display any 24-bit RGB colour
+    TrueColor,
+    /// The terminal can display the 256 colours of the xterm palette
+    Ansi256,
+    /// The terminal can only display the 16 standard colours
+    Ansi16,
+}
+
+impl ColorSupport {
+    /// Determines which colours the current terminal supports, using the `$COLORTERM` and
+    /// `$TERM` environment variables.
+    pub fn detect() -> ColorSupport {
+        Self::from_env_vars(
+            std::env::var("COLORTERM").ok().as_deref(),
+            std::env::var("TERM").ok().as_deref(),
+        )
+    }
+
+    /// Determines which colours a terminal supports, given the values of its `$COLORTERM` and
+    /// `$TERM` environment variables.  `$COLORTERM` is the de-facto standard way for terminals to
+    /// advertise true colour support, whereas the terminfo name in `$TERM` tells us about 256
+    /// colour support.
+    pub fn from_env_vars(colorterm: Option<&str>, term: Option<&str>) -> ColorSupport {
+        if let Some("truecolor") | Some("24bit") = colorterm {
+            return ColorSupport::TrueColor;
+        }
+        match term {
+            Some(t) if t.ends_with("-direct") => ColorSupport::TrueColor,
+            Some(t) if t.contains("256color") => ColorSupport::Ansi256,
+            _ => ColorSupport::Ansi16,
+        }
+    }
+}
+
+/// The RGB values of the 16 standard colours, using xterm's default palette.  Terminals let users
+/// change these, so this can only ever approximate what the user will see.
+const ANSI_16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The values that each RGB component can take in the 6x6x6 colour cube (indices `16..232`) of
+/// the 256 colour palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Returns the RGB values of a colour in the 256 colour palette
+fn ansi_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16_RGB[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        // The last 24 colours are a greyscale ramp
+        232..=255 => {
+            let v = 8 + 10 * (index - 232);
+            (v, v, v)
+        }
+    }
+}
+
+/// Returns the square of the (Euclidean) distance between two colours
+fn distance_squared(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// Returns the closest colour in the 256 colour palette to a given RGB colour.  This only ever
+/// returns colours from the cube or the greyscale ramp, because the first 16 colours are often
+/// changed by the user's terminal theme.
+fn nearest_ansi_256(rgb: (u8, u8, u8)) -> u8 {
+    // Find the nearest cube colour.  The cube is a grid, so we can find the nearest level of each
+    // component independently
+    let nearest_level = |v: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+            .unwrap() as u8
+    };
+    let cube_index =
+        16 + 36 * nearest_level(rgb.0) + 6 * nearest_level(rgb.1) + nearest_level(rgb.2);
+    // Find the nearest grey
+    let grey_index = (232..=255)
+        .min_by_key(|&i| distance_squared(rgb, ansi_to_rgb(i)))
+        .unwrap();
+    // Use whichever is closer, preferring the cube if they're equally close
+    if distance_squared(rgb, ansi_to_rgb(grey_index))
+        < distance_squared(rgb, ansi_to_rgb(cube_index))
+    {
+        grey_index
+    } else {
+        cube_index
+    }
+}
+
+/// Returns the closest of the 16 standard colours to a given RGB colour
+fn nearest_ansi_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&i| distance_squared(rgb, ANSI_16_RGB[i as usize]))
+        .unwrap()
+}
+
+/// Converts a [`Color`] into the closest colour that a terminal with a given level of
+/// [`ColorSupport`] can display.  Colours that the terminal supports are returned unchanged.
+pub fn quantize(color: Color, support: ColorSupport) -> Color {
+    match (color, support) {
+        (_, ColorSupport::TrueColor) => color,
+        (Color::Rgb(r, g, b), ColorSupport::Ansi256) => {
+            Color::AnsiValue(nearest_ansi_256((r, g, b)))
+        }
+        (Color::Rgb(r, g, b), ColorSupport::Ansi16) => Color::AnsiValue(nearest_ansi_16((r, g, b))),
+        (Color::AnsiValue(i), ColorSupport::Ansi16) if i >= 16 => {
+            Color::AnsiValue(nearest_ansi_16(ansi_to_rgb(i)))
+        }
+        _ => color,
+    }
+}
+
+/// One of the colours that a theme is made of, along with the colours that stand in for it on
+/// terminals that can't display it.  The stand-ins are chosen by hand, since the closest colour
+/// (which is what [`quantize`] picks) often loses the differences between a theme's colours.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PaletteColor {
+    pub rgb: (u8, u8, u8),
+    /// The colour of the 256 colour palette used instead on terminals without true colour
+    pub ansi_256: u8,
+    /// The colour of the 16 standard colours used instead on terminals with only those
+    pub ansi_16: u8,
+}
+
+impl PaletteColor {
+    /// Creates a `PaletteColor` from the hex code of its RGB value (e.g. `0xfabd2f`)
+    pub const fn new(hex: u32, ansi_256: u8, ansi_16: u8) -> Self {
+        PaletteColor {
+            rgb: ((hex >> 16) as u8, (hex >> 8) as u8, hex as u8),
+            ansi_256,
+            ansi_16,
+        }
+    }
+
+    /// Returns the RGB colour
+    pub fn color(&self) -> Color {
+        let (r, g, b) = self.rgb;
+        Color::Rgb(r, g, b)
+    }
+
+    /// Returns the colour that a terminal with the given [`ColorSupport`] displays instead
+    pub fn quantized(&self, support: ColorSupport) -> Color {
+        match support {
+            ColorSupport::TrueColor => self.color(),
+            ColorSupport::Ansi256 => Color::AnsiValue(self.ansi_256),
+            ColorSupport::Ansi16 => Color::AnsiValue(self.ansi_16),
+        }
+    }
+}
+
+/// Converts a [`Color`] like [`quantize`] does, except that the RGB colours of `palette` are
+/// converted to their own stand-ins
+pub fn quantize_in(color: Color, support: ColorSupport, palette: &[PaletteColor]) -> Color {
+    let palette_color = match color {
+        Color::Rgb(r, g, b) => palette.iter().find(|p| p.rgb == (r, g, b)),
+        _ => None,
+    };
+    match palette_color {
+        Some(palette_color) => palette_color.quantized(support),
+        None => quantize(color, support),
+    }
+}
+
+/// The colours used to render nodes when debug highlighting is enabled.  This doesn't contain
+/// black, since that's the colour of the text of the selected node.
+const DEBUG_COLORS: [Color; 14] = [
+    Color::MAGENTA,
+    Color::RED,
+    Color::YELLOW,
+    Color::GREEN,
+    Color::CYAN,
+    Color::BLUE,
+    Color::WHITE,
+    Color::LIGHT_RED,
+    Color::LIGHT_BLUE,
+    Color::LIGHT_CYAN,
+    Color::LIGHT_GREEN,
+    Color::LIGHT_YELLOW,
+    Color::LIGHT_MAGENTA,
+    Color::LIGHT_WHITE,
+];
+
+/// Returns the colour that a node with a given hash should be rendered with when debug
+/// highlighting is enabled.  Nodes that are equal have equal hashes, and so will be rendered in
+/// the same colour.
+pub fn debug_color(node_hash: u64) -> Color {
+    DEBUG_COLORS[(node_hash % DEBUG_COLORS.len() as u64) as usize]
+}
+
+/// Returns the hex code (e.g. `"#ff8800"`) of a colour, for use outside the terminal.  Colours
+/// from the 256 colour palette are given the RGB values of xterm's default palette.  Returns
+/// `None` for the terminal's default colour, which has no fixed value.
+pub fn to_hex(color: Color) -> Option<String> {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::AnsiValue(index) => ansi_to_rgb(index),
+        _ => return None,
+    };
+    Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+/// Returns the ANSI escape sequence that makes a terminal write text in a given colour.  The 16
+/// standard colours use the short escapes that every terminal understands.
+pub fn ansi_escape(color: Color) -> String {
+    color_escape(color, 0)
+}
+
+/// Returns the ANSI escape sequence that makes a terminal fill the background of text with a
+/// given colour (like [`ansi_escape`] does for the text itself)
+pub fn ansi_background_escape(color: Color) -> String {
+    // Every background escape is 10 more than the foreground escape of the same colour
+    color_escape(color, 10)
+}
+
+/// Returns the ANSI escape sequence of a colour, whose codes are `offset` more than the codes
+/// of the foreground colours
+fn color_escape(color: Color, offset: u8) -> String {
+    match color {
+        Color::AnsiValue(index @ 0..=7) => format!("\x1b[{}m", 30 + offset + index),
+        Color::AnsiValue(index @ 8..=15) => format!("\x1b[{}m", 90 + offset + index - 8),
+        Color::AnsiValue(index) => format!("\x1b[{};5;{}m", 38 + offset, index),
+        Color::Rgb(r, g, b) => format!("\x1b[{};2;{};{};{}m", 38 + offset, r, g, b),
+        _ => format!("\x1b[{}m", 39 + offset),
+    }
+}
+
+/// Decides whether text written to some output should be coloured (unless the user has said
+/// otherwise), given the value of the `$NO_COLOR` environment variable and whether the output is
+/// a terminal.  Following <https://no-color.org>, setting `$NO_COLOR` to anything but the empty
+/// string turns colours off.  Colours are also left out of anything that isn't a terminal, since
+/// that's usually a file or another program that doesn't understand escape sequences.
+pub fn use_color(no_color: Option<&std::ffi::OsStr>, is_terminal: bool) -> bool {
+    is_terminal && no_color.is_none_or(|value| value.is_empty())
+}
+
+/// Converts a colour name (e.g. `"red"` or `"light blue"`), index into the 256 colour palette
+/// (e.g. `"196"`), or hex code (e.g. `"#ff8800"`) into a [`Color`].  Returns `None` if the
+/// string isn't a valid colour.
+pub fn parse_color(string: &str) -> Option<Color> {
+    if let Some(hex) = string.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        return Some(Color::Rgb(component(0), component(2), component(4)));
+    }
+    if !string.is_empty() && string.chars().all(|c| c.is_ascii_digit()) {
+        // Parsing will fail for any numbers that don't fit in a `u8`
+        return string.parse().ok().map(Color::AnsiValue);
+    }
+    // Allow the user to write names in any case, with spaces, dashes or underscores
+    let name = string.trim().to_lowercase().replace([' ', '-'], "_");
+    Some(match name.as_str() {
+        "black" => Color::BLACK,
+        "red" => Color::RED,
+        "green" => Color::GREEN,
+        "yellow" => Color::YELLOW,
+        "blue" => Color::BLUE,
+        "magenta" => Color::MAGENTA,
+        "cyan" => Color::CYAN,
+        "white" => Color::WHITE,
+        "light_black" => Color::LIGHT_BLACK,
+        "light_red" => Color::LIGHT_RED,
+        "light_green" => Color::LIGHT_GREEN,
+        "light_yellow" => Color::LIGHT_YELLOW,
+        "light_blue" => Color::LIGHT_BLUE,
+        "light_magenta" => Color::LIGHT_MAGENTA,
+        "light_cyan" => Color::LIGHT_CYAN,
+        "light_white" => Color::LIGHT_WHITE,
+        _ => return None,
+    })
+}
+
+/// Parses the shorthand for the style of a category in the `[colors]` table of the config file,
+/// which is a colour that can start with the effects that the text is drawn with (e.g. `bold`,
+/// `italic light blue` or `bold underline #303030`).  If only effects are given, the colour is
+/// the terminal's default colour.
+pub fn parse_style(string: &str) -> Option<Style> {
+    let mut style = Style::new(Color::Default);
+    let mut rest = string.trim();
+    loop {
+        let (word, after) = rest.split_once(' ').unwrap_or((rest, ""));
+        let effect = match word.to_lowercase().as_str() {
+            "bold" => &mut style.bold,
+            "dim" => &mut style.dim,
+            "italic" => &mut style.italic,
+            "underline" => &mut style.underline,
+            "reverse" => &mut style.reverse,
+            _ => break,
+        };
+        *effect = true;
+        rest = after.trim_start();
+    }
+    if rest.is_empty() {
+        return (!style.is_plain()).then_some(style);
+    }
+    parse_color(rest).map(|fg| Style { fg, ..style })
+}
+
+/// Returns the ANSI escape sequences that make a terminal write text in a given [`Style`], on
+/// top of whatever it was writing before (so turning effects off needs a reset first)
+pub fn ansi_style_escape(style: Style) -> String {
+    let mut escape = ansi_escape(style.fg);
+    if let Some(bg) = style.bg {
+        escape.push_str(&ansi_background_escape(bg));
+    }
+    for (on, code) in &[
+        (style.bold, 1),
+        (style.dim, 2),
+        (style.italic, 3),
+        (style.underline, 4),
+        (style.reverse, 7),
+    ] {
+        if *on {
+            escape.push_str(&format!("\x1b[{}m", code));
+        }
+    }
+    escape
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ansi_background_escape, ansi_escape, ansi_style_escape, debug_color, parse_color,
+        parse_style, quantize, to_hex, use_color, ColorSupport, Style, DEBUG_COLORS,
+    };
+    use tuikit::prelude::{Color, Effect};
+
+    #[test]
+    fn parse_colors() {
+        for (string, expected_color) in &[
+            ("red", Some(Color::RED)),
+            ("Light Blue", Some(Color::LIGHT_BLUE)),
+            ("light-green", Some(Color::LIGHT_GREEN)),
+            ("light_black", Some(Color::LIGHT_BLACK)),
+            ("#ff8800", Some(Color::Rgb(255, 136, 0))),
+            ("#00AAff", Some(Color::Rgb(0, 170, 255))),
+            ("0", Some(Color::AnsiValue(0))),
+            ("196", Some(Color::AnsiValue(196))),
+            ("255", Some(Color::AnsiValue(255))),
+            ("256", None),
+            ("-1", None),
+            ("#ff880", None),
+            ("#ff88000", None),
+            ("#gg8800", None),
+            ("ff8800", None),
+            ("reddish", None),
+            ("", None),
+        ] {
+            assert_eq!(parse_color(string), *expected_color, "{}", string);
+        }
+    }
+
+    #[test]
+    fn parse_styles() {
+        for (string, expected_style) in &[
+            ("red", Some((Color::RED, Effect::empty(), false))),
+            ("bold", Some((Color::Default, Effect::BOLD, false))),
+            (
+                "Bold underline light blue",
+                Some((Color::LIGHT_BLUE, Effect::BOLD | Effect::UNDERLINE, false)),
+            ),
+            (
+                "reverse #303030",
+                Some((Color::Rgb(48, 48, 48), Effect::REVERSE, false)),
+            ),
+            ("italic green", Some((Color::GREEN, Effect::empty(), true))),
+            ("bold reddish", None),
+            ("", None),
+        ] {
+            let style = parse_style(string).map(|s| (s.fg, s.effect(), s.italic));
+            assert_eq!(style, *expected_style, "{}", string);
+        }
+
+        let style = Style {
+            bg: Some(Color::BLUE),
+            italic: true,
+            underline: true,
+            ..Style::new(Color::RED)
+        };
+        assert!(!style.is_plain());
+        assert!(Style::new(Color::RED).is_plain());
+        assert_eq!(ansi_style_escape(style), "\x1b[31m\x1b[44m\x1b[3m\x1b[4m");
+        // Italic text can't be drawn in the editor
+        assert_eq!(style.attr().effect, Effect::UNDERLINE);
+        assert_eq!(style.attr().bg, Color::BLUE);
+    }
+
+    #[test]
+    fn hex_codes() {
+        for (color, expected_hex) in &[
+            (Color::Rgb(255, 136, 0), Some("#ff8800")),
+            (Color::Rgb(0, 0, 0), Some("#000000")),
+            (Color::RED, Some("#cd0000")),
+            (Color::LIGHT_BLUE, Some("#5c5cff")),
+            (Color::AnsiValue(196), Some("#ff0000")),
+            (Color::AnsiValue(244), Some("#808080")),
+            (Color::Default, None),
+        ] {
+            assert_eq!(to_hex(*color).as_deref(), *expected_hex, "{:?}", color);
+        }
+        // Hex codes can be parsed back into the colours they came from
+        assert_eq!(
+            to_hex(Color::Rgb(1, 2, 3)).and_then(|hex| parse_color(&hex)),
+            Some(Color::Rgb(1, 2, 3))
+        );
+    }
+
+    #[test]
+    fn ansi_escapes() {
+        for (color, expected_escape) in &[
+            (Color::BLACK, "\x1b[30m"),
+            (Color::WHITE, "\x1b[37m"),
+            (Color::LIGHT_BLACK, "\x1b[90m"),
+            (Color::LIGHT_WHITE, "\x1b[97m"),
+            (Color::AnsiValue(16), "\x1b[38;5;16m"),
+            (Color::AnsiValue(255), "\x1b[38;5;255m"),
+            (Color::Rgb(255, 136, 0), "\x1b[38;2;255;136;0m"),
+            (Color::Default, "\x1b[39m"),
+        ] {
+            assert_eq!(ansi_escape(*color), *expected_escape, "{:?}", color);
+        }
+        for (color, expected_escape) in &[
+            (Color::BLACK, "\x1b[40m"),
+            (Color::LIGHT_WHITE, "\x1b[107m"),
+            (Color::AnsiValue(196), "\x1b[48;5;196m"),
+            (Color::Rgb(1, 2, 3), "\x1b[48;2;1;2;3m"),
+            (Color::Default, "\x1b[49m"),
+        ] {
+            assert_eq!(
+                ansi_background_escape(*color),
+                *expected_escape,
+                "{:?}",
+                color
+            );
+        }
+    }
+
+    #[test]
+    fn color_choice() {
+        use std::ffi::OsStr;
+        for (no_color, is_terminal, expected) in &[
+            (None, true, true),
+            (None, false, false),
+            (Some(""), true, true),
+            (Some(""), false, false),
+            (Some("1"), true, false),
+            (Some("1"), false, false),
+        ] {
+            assert_eq!(
+                use_color(no_color.map(OsStr::new), *is_terminal),
+                *expected,
+                "{:?} {}",
+                no_color,
+                is_terminal
+            );
+        }
+    }
+
+    #[test]
+    fn debug_colors() {
+        // The mapping must be deterministic, so that shared nodes always get the same colour
+        assert_eq!(debug_color(12345), debug_color(12345));
+        // Consecutive hashes get different colours, and every colour gets used
+        for i in 0..DEBUG_COLORS.len() as u64 {
+            assert_ne!(debug_color(i), debug_color(i + 1));
+        }
+        let mut used_colors: Vec<_> = (0..100).map(debug_color).collect();
+        used_colors.sort_by_key(|c| format!("{:?}", c));
+        used_colors.dedup();
+        assert_eq!(used_colors.len(), DEBUG_COLORS.len());
+        // Large hashes (which don't fit in a 32-bit `usize`) don't cause problems
+        assert_eq!(debug_color(u64::MAX), DEBUG_COLORS[1]);
+        // Black text is used for the cursor, so nodes should never be black
+        assert!((0..100).all(|h| debug_color(h) != Color::BLACK));
+    }
+
+    #[test]
+    fn quantization() {
+        use ColorSupport::*;
+        for (color, support, expected_color) in &[
+            // True colour terminals can display everything
+            (Color::Rgb(1, 2, 3), TrueColor, Color::Rgb(1, 2, 3)),
+            (Color::AnsiValue(196), TrueColor, Color::AnsiValue(196)),
+            // RGB -> 256 colours
+            (Color::Rgb(255, 0, 0), Ansi256, Color::AnsiValue(196)),
+            (Color::Rgb(0, 0, 0), Ansi256, Color::AnsiValue(16)),
+            (Color::Rgb(255, 255, 255), Ansi256, Color::AnsiValue(231)),
+            (Color::Rgb(255, 136, 0), Ansi256, Color::AnsiValue(208)),
+            (Color::Rgb(95, 135, 175), Ansi256, Color::AnsiValue(67)),
+            (Color::Rgb(128, 128, 128), Ansi256, Color::AnsiValue(244)),
+            (Color::Rgb(10, 10, 12), Ansi256, Color::AnsiValue(232)),
+            (Color::AnsiValue(196), Ansi256, Color::AnsiValue(196)),
+            (Color::LIGHT_RED, Ansi256, Color::LIGHT_RED),
+            // RGB -> 16 colours
+            (Color::Rgb(255, 0, 0), Ansi16, Color::LIGHT_RED),
+            (Color::Rgb(200, 10, 10), Ansi16, Color::RED),
+            (Color::Rgb(0, 0, 0), Ansi16, Color::BLACK),
+            (Color::Rgb(128, 128, 128), Ansi16, Color::LIGHT_BLACK),
+            (Color::Rgb(250, 250, 250), Ansi16, Color::LIGHT_WHITE),
+            // 256 colours -> 16 colours
+            (Color::AnsiValue(196), Ansi16, Color::LIGHT_RED),
+            (Color::AnsiValue(231), Ansi16, Color::LIGHT_WHITE),
+            (Color::AnsiValue(232), Ansi16, Color::BLACK),
+            (Color::CYAN, Ansi16, Color::CYAN),
+        ] {
+            assert_eq!(
+                quantize(*color, *support),
+                *expected_color,
+                "{:?} with {:?}",
+                color,
+                support
+            );
+        }
+    }
+
+    #[test]
+    fn detection() {
+        use ColorSupport::*;
+        for (colorterm, term, expected_support) in &[
+            (Some("truecolor"), Some("xterm-256color"), TrueColor),
+            (Some("24bit"), None, TrueColor),
+            (None, Some("xterm-direct"), TrueColor),
+            (None, Some("xterm-256color"), Ansi256),
+            (Some("yes"), Some("screen-256color"), Ansi256),
+            (None, Some("xterm"), Ansi16),
+            (None, Some("linux"), Ansi16),
+            (None, None, Ansi16),
+        ] {
+            assert_eq!(
+                ColorSupport::from_env_vars(*colorterm, *term),
+                *expected_support
+            );
+        }
+    }
+}
@@ -0,0 +1,1567 @@
+//! Configuration for Sapling.  Most of this is loaded from a TOML file (see [`config_path`]),
+//! falling back on default values for anything that the file doesn't specify.
+
+pub mod color;
+pub mod options;
+
+use crate::ast::display_token::SyntaxCategory;
+use crate::editor::normal_mode::{conflicting_keys, default_keymap, parse_keys, KeyMap, KeyStroke};
+use crate::editor::status_bar::StatusFormat;
+use crate::language::Language;
+use color::{parse_color, parse_style, ColorSupport, PaletteColor, Style};
+use options::{OptionError, Options};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tuikit::prelude::Color;
+
+/// Every [`SyntaxCategory`] that can be given a style in a [`ColorScheme`]
+pub const CATEGORIES: [SyntaxCategory; 17] = [
+    SyntaxCategory::Default,
+    SyntaxCategory::Literal,
+    SyntaxCategory::Const,
+    SyntaxCategory::Comment,
+    SyntaxCategory::Ident,
+    SyntaxCategory::Keyword,
+    SyntaxCategory::Preproc,
+    SyntaxCategory::Type,
+    SyntaxCategory::Special,
+    SyntaxCategory::Error,
+    SyntaxCategory::Search,
+    SyntaxCategory::DiffAdded,
+    SyntaxCategory::DiffRemoved,
+    SyntaxCategory::DiffChanged,
+    SyntaxCategory::Selection,
+    SyntaxCategory::Gutter,
+    SyntaxCategory::Ancestor,
+];
+
+/// The names of the built-in colour schemes (or themes), which can be chosen with the `theme`
+/// option
+pub const THEME_NAMES: [&str; 6] = [
+    "default",
+    "mono",
+    "dark",
+    "light",
+    "solarized-dark",
+    "gruvbox",
+];
+
+/// A mapping from [`SyntaxCategory`]s to the [`Style`] (colours and effects) that text of that
+/// category should be rendered with.  Each language can also give its own styles to categories,
+/// which are used instead of the shared ones once the scheme is [made for that
+/// language](ColorScheme::for_language).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorScheme {
+    styles: HashMap<SyntaxCategory, Style>,
+    /// The styles that only apply to one language (from the `[colors.<language>]` tables), by
+    /// the [name](Language::name) of the language
+    languages: HashMap<&'static str, HashMap<SyntaxCategory, Style>>,
+    /// The language whose styles are used, if the scheme has been made for one
+    language: Option<Language>,
+    /// The colours that the scheme is made of, which say what each RGB colour is replaced with
+    /// when the scheme is [`quantized`](ColorScheme::quantized)
+    palette: Vec<PaletteColor>,
+    /// The categories that text has been [resolved](ColorScheme::resolve) in which the scheme
+    /// has no style for, in the order they were first resolved
+    unknown: RefCell<Vec<SyntaxCategory>>,
+}
+
+impl ColorScheme {
+    /// Returns the [`Style`] that text of a given [`SyntaxCategory`] should be rendered with.
+    /// The style is looked for in the scheme's language first, and then in the shared styles.
+    /// Categories that only the language uses fall back on the shared category they're paired
+    /// with (see [`Ast::CATEGORIES`](crate::ast::Ast::CATEGORIES)), and anything else that the
+    /// scheme doesn't know about falls back on the style of [`Default`](SyntaxCategory::Default).
+    pub fn style(&self, category: SyntaxCategory) -> Style {
+        self.find_style(category)
+            .or_else(|| self.find_style(SyntaxCategory::Default))
+            .unwrap_or_else(|| Style::new(Color::WHITE))
+    }
+
+    /// Same as [`style`](ColorScheme::style), except that if the scheme has no style for
+    /// `category` (so it falls back on [`Default`](SyntaxCategory::Default)), the category is
+    /// recorded, and a warning is logged the first time.  The recorded categories are returned
+    /// by [`unknown_categories`](ColorScheme::unknown_categories).
+    pub fn resolve(&self, category: SyntaxCategory) -> Style {
+        if self.find_style(category).is_none() {
+            let mut unknown = self.unknown.borrow_mut();
+            if !unknown.contains(&category) {
+                log::warn!(
+                    "The colour scheme has no style for '{}', so it's drawn like 'default'",
+                    category
+                );
+                unknown.push(category);
+            }
+        }
+        self.style(category)
+    }
+
+    /// Returns the categories that have been [resolved](ColorScheme::resolve) which the scheme
+    /// has no style for, in the order they were first resolved
+    pub fn unknown_categories(&self) -> Vec<SyntaxCategory> {
+        self.unknown.borrow().clone()
+    }
+
+    /// Checks that the scheme has a style for every shared category and every category that the
+    /// text of its language is drawn in (see [`Language::token_categories`]), by
+    /// [resolving](ColorScheme::resolve) them all.  Returns the ones that it has no style for.
+    pub fn validate(&self) -> Vec<SyntaxCategory> {
+        let language_categories = self.language.map_or(&[][..], Language::token_categories);
+        for category in CATEGORIES.iter().chain(language_categories) {
+            self.resolve(*category);
+        }
+        self.unknown_categories()
+    }
+
+    /// Returns the style given to `category` (by the scheme's language, the shared styles or the
+    /// category that the language pairs it with), or `None` if the scheme doesn't know about it
+    fn find_style(&self, category: SyntaxCategory) -> Option<Style> {
+        let language_styles = self
+            .language
+            .and_then(|language| self.languages.get(language.name()));
+        let lookup = |category: SyntaxCategory| {
+            language_styles
+                .and_then(|styles| styles.get(&category))
+                .or_else(|| self.styles.get(&category))
+        };
+        let parent = || {
+            let categories = self.language?.categories();
+            let (_, parent) = categories.iter().find(|(c, _)| *c == category)?;
+            lookup(*parent)
+        };
+        lookup(category).or_else(parent).copied()
+    }
+
+    /// Returns a copy of this `ColorScheme` which styles text in `language`, using the styles
+    /// that the scheme gives to that language's categories
+    pub fn for_language(&self, language: Language) -> ColorScheme {
+        ColorScheme {
+            language: Some(language),
+            ..self.clone()
+        }
+    }
+
+    /// Returns every category that text can be styled with: the shared [`CATEGORIES`], followed
+    /// by the ones that only the scheme's language uses
+    pub fn categories(&self) -> impl Iterator<Item = SyntaxCategory> + '_ {
+        let language_categories = self.language.map_or(&[][..], Language::categories);
+        CATEGORIES
+            .iter()
+            .copied()
+            .chain(language_categories.iter().map(|(category, _)| *category))
+    }
+
+    /// Returns the [`Color`] of the text of a given [`SyntaxCategory`] (see
+    /// [`style`](ColorScheme::style))
+    pub fn color(&self, category: SyntaxCategory) -> Color {
+        self.style(category).fg
+    }
+
+    /// Returns the colour that a [`SyntaxCategory`] which is drawn behind other text (like
+    /// [`Selection`](SyntaxCategory::Selection)) fills the background with.  This is its
+    /// background colour if it has one, so that the shorthand `selection = "blue"` still works.
+    pub fn background(&self, category: SyntaxCategory) -> Color {
+        let style = self.style(category);
+        style.bg.unwrap_or(style.fg)
+    }
+
+    /// Returns the built-in colour scheme called `name` (one of [`THEME_NAMES`]), or `None` if
+    /// there isn't one
+    pub fn named(name: &str) -> Option<ColorScheme> {
+        match name {
+            "default" => Some(default_color_scheme()),
+            "mono" => Some(mono_color_scheme()),
+            "dark" => Some(dark_color_scheme()),
+            "light" => Some(light_color_scheme()),
+            "solarized-dark" => Some(solarized_dark_color_scheme()),
+            "gruvbox" => Some(gruvbox_color_scheme()),
+            _ => None,
+        }
+    }
+
+    /// Returns a copy of this `ColorScheme` where the style of every category in `overrides`
+    /// (in every language) has been replaced with the one from `overrides`
+    pub fn merged(&self, overrides: &ColorScheme) -> ColorScheme {
+        let mut merged = self.clone();
+        merged.styles.extend(&overrides.styles);
+        for (language, styles) in &overrides.languages {
+            merged.languages.entry(language).or_default().extend(styles);
+        }
+        merged
+    }
+
+    /// Returns a copy of this `ColorScheme` where every colour has been converted to a colour
+    /// that a terminal with the given [`ColorSupport`] can display.  The colours of the scheme's
+    /// palette are replaced with the fallbacks that the palette gives them, and any other colour
+    /// is replaced with the closest one.
+    pub fn quantized(&self, support: ColorSupport) -> ColorScheme {
+        let quantize = |styles: &HashMap<SyntaxCategory, Style>| {
+            styles
+                .iter()
+                .map(|(category, style)| (*category, style.quantized(support, &self.palette)))
+                .collect()
+        };
+        ColorScheme {
+            styles: quantize(&self.styles),
+            languages: self
+                .languages
+                .iter()
+                .map(|(language, styles)| (*language, quantize(styles)))
+                .collect(),
+            language: self.language,
+            palette: self.palette.clone(),
+            unknown: RefCell::default(),
+        }
+    }
+
+    /// Creates a `ColorScheme` with the given shared styles, which doesn't give any language its
+    /// own styles
+    fn new(styles: HashMap<SyntaxCategory, Style>) -> ColorScheme {
+        ColorScheme {
+            styles,
+            languages: HashMap::new(),
+            language: None,
+            palette: Vec::new(),
+            unknown: RefCell::default(),
+        }
+    }
+
+    /// Creates a `ColorScheme` that gives no category a style, for
+    /// [merging](ColorScheme::merged) on top of another scheme
+    fn empty() -> ColorScheme {
+        ColorScheme::new(HashMap::new())
+    }
+
+    /// Reads a `ColorScheme` from the `[colors]` table of the TOML config file whose text is
+    /// `text`.  The styles in the file are applied on top of the [default
+    /// scheme](default_color_scheme), so the file only needs to specify the categories it wants
+    /// to change.  Problems which don't stop the scheme from being loaded (like unknown category
+    /// names) are returned as a list of [`ConfigWarning`]s.
+    pub fn from_toml(text: &str) -> Result<(ColorScheme, Vec<ConfigWarning>), ConfigError> {
+        Config::from_toml(text).map(|(config, warnings)| (config.color_scheme, warnings))
+    }
+
+    /// Builds a `ColorScheme` from the `[colors]` table of the config file whose text is `text`.
+    /// Only the categories in the table are given styles, so that the result can be
+    /// [`merged`](ColorScheme::merged) on top of any other scheme.  Tables inside `[colors]`
+    /// which are named after languages (like `[colors.json]`) give styles to the categories of
+    /// that language, and problems inside them are reported on the line where they start.
+    fn from_table(
+        colors: HashMap<String, toml::Spanned<ColorsEntry>>,
+        text: &str,
+    ) -> Result<(ColorScheme, Vec<ConfigWarning>), ConfigError> {
+        // Flatten the tables of the languages into `(position, language, name, style)`, and sort
+        // the entries so that errors and warnings are reported in the order they appear in the
+        // file
+        let mut entries = Vec::new();
+        for (name, entry) in colors {
+            let start = entry.start();
+            let language = Language::all().iter().copied().find(|l| l.name() == name);
+            match (language, entry.into_inner()) {
+                (Some(language), ColorsEntry::Table(table)) => {
+                    for (name, value) in table {
+                        entries.push((value.start(), Some(language), name, value.into_inner()));
+                    }
+                }
+                (_, entry) => {
+                    let start = entry.start().unwrap_or(start);
+                    entries.push((start, None, name, entry.into_value()));
+                }
+            }
+        }
+        entries.sort_by_key(|(start, ..)| *start);
+
+        let mut scheme = ColorScheme::empty();
+        let mut warnings = Vec::new();
+        // The shared table can also style the categories that only some languages use
+        let every_language_category: Vec<_> = Language::all()
+            .iter()
+            .flat_map(|language| language.categories())
+            .map(|(category, _)| *category)
+            .collect();
+        for (start, language, name, value) in entries {
+            let line = line_number(text, start);
+            let extra_categories = match language {
+                Some(language) => language.categories().iter().map(|(c, _)| *c).collect(),
+                None => every_language_category.clone(),
+            };
+            let category = match category_named(name, &extra_categories, line) {
+                Ok(category) => category,
+                Err(warning) => {
+                    warnings.push(warning);
+                    continue;
+                }
+            };
+            let style = parse_style_value(category, value, line)?;
+            let styles = match language {
+                Some(language) => scheme.languages.entry(language.name()).or_default(),
+                None => &mut scheme.styles,
+            };
+            styles.insert(category, style);
+        }
+        Ok((scheme, warnings))
+    }
+}
+
+/// Finds the category called `name` (on `line` of the config file), which is either one of the
+/// shared [`CATEGORIES`] or one of `extra_categories`.  This converts the name into a `&'static
+/// str`, or returns the warning to give if there's no such category.
+fn category_named(
+    name: String,
+    extra_categories: &[SyntaxCategory],
+    line: usize,
+) -> Result<SyntaxCategory, ConfigWarning> {
+    match CATEGORIES
+        .iter()
+        .chain(extra_categories)
+        .find(|c| c.name() == name)
+    {
+        Some(c) => Ok(*c),
+        None if name == "underlined" => Err(ConfigWarning::RemovedCategory { name, line }),
+        None => Err(ConfigWarning::UnknownCategory { name, line }),
+    }
+}
+
+/// Reads the style given to `category` on `line` of the config file
+fn parse_style_value(
+    category: SyntaxCategory,
+    value: toml::Value,
+    line: usize,
+) -> Result<Style, ConfigError> {
+    let invalid_style = || ConfigError::InvalidStyle {
+        category: category.to_string(),
+        line,
+    };
+    let style_value: StyleValue = value.try_into().map_err(|_| invalid_style())?;
+    style_value
+        .style()
+        .map_err(|value| ConfigError::InvalidColor {
+            category: category.to_string(),
+            value,
+            line,
+        })
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        default_color_scheme()
+    }
+}
+
+/// Everything that can be configured in Sapling's config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The styles used for syntax highlighting, from the `[colors]` table
+    pub color_scheme: ColorScheme,
+    /// Whether clicking and scrolling with the mouse moves around the tree (`mouse = true` in the
+    /// `[editor]` table).  This is off by default, since capturing the mouse stops the terminal
+    /// from selecting text.
+    pub mouse: bool,
+    /// What the status bar at the bottom of the screen shows (`status_line = "<format>"` in the
+    /// `[editor]` table)
+    pub status_format: StatusFormat,
+    /// The options that can also be changed with `:set`, from the `[editor]` table
+    pub options: Options,
+    /// The styles given in the `[colors]` table, which are applied on top of whichever colour
+    /// scheme is chosen with the `theme` option
+    pub color_overrides: ColorScheme,
+    /// The keys that each keystroke is bound to, which are the [defaults](default_keymap) with
+    /// the changes made by the `[keys]` table
+    pub keymap: KeyMap,
+    /// If `true`, every buffer is opened read-only, so that it can be looked at but not edited.
+    /// This is only set by the `--read-only` command line argument.
+    pub read_only: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            color_scheme: ColorScheme::default(),
+            mouse: false,
+            status_format: StatusFormat::default(),
+            options: Options::default(),
+            color_overrides: ColorScheme::empty(),
+            keymap: default_keymap(),
+            read_only: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the `Config` from the TOML file at `path`.  Anything that the file doesn't specify
+    /// keeps its default value, and problems which don't stop the file from being loaded are
+    /// returned as a list of [`ConfigWarning`]s.
+    pub fn load(path: &Path) -> Result<(Config, Vec<ConfigWarning>), ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        Self::from_toml(&text)
+    }
+
+    /// Same as [`load`](Config::load), but reads the TOML from a string rather than a file.
+    pub fn from_toml(text: &str) -> Result<(Config, Vec<ConfigWarning>), ConfigError> {
+        let file: ConfigFile = toml::from_str(text).map_err(ConfigError::Toml)?;
+        let (color_overrides, mut warnings) = ColorScheme::from_table(file.colors, text)?;
+        let (keymap, key_warnings) = keymap_from_table(file.keys, text)?;
+        warnings.extend(key_warnings);
+        let options = file.editor.options(text)?;
+        // The option has already been checked, so the scheme always exists
+        let color_scheme = ColorScheme::named(&options.theme)
+            .unwrap_or_default()
+            .merged(&color_overrides);
+        let status_format = match file.editor.status_line {
+            Some(format) => {
+                format
+                    .get_ref()
+                    .parse()
+                    .map_err(|reason| ConfigError::InvalidStatusFormat {
+                        format: format.get_ref().clone(),
+                        reason,
+                        line: line_number(text, format.start()),
+                    })?
+            }
+            None => StatusFormat::default(),
+        };
+        let config = Config {
+            color_scheme,
+            mouse: file.editor.mouse,
+            status_format,
+            options,
+            color_overrides,
+            keymap,
+            read_only: false,
+        };
+        Ok((config, warnings))
+    }
+}
+
+/// Generates the [`ColorScheme`] that Sapling uses if no other scheme is specified.
+pub fn default_color_scheme() -> ColorScheme {
+    let mut styles: HashMap<_, _> = hmap::hmap! {
+        SyntaxCategory::Default => Color::WHITE,
+        SyntaxCategory::Literal => Color::LIGHT_YELLOW,
+        SyntaxCategory::Const => Color::LIGHT_RED,
+        SyntaxCategory::Ident => Color::LIGHT_CYAN,
+        SyntaxCategory::Keyword => Color::YELLOW,
+        SyntaxCategory::Preproc => Color::MAGENTA,
+        SyntaxCategory::Type => Color::LIGHT_GREEN,
+        SyntaxCategory::Special => Color::RED,
+        SyntaxCategory::Search => Color::GREEN,
+        SyntaxCategory::DiffAdded => Color::LIGHT_GREEN,
+        SyntaxCategory::DiffRemoved => Color::LIGHT_RED,
+        SyntaxCategory::DiffChanged => Color::YELLOW,
+        SyntaxCategory::Selection => Color::BLUE,
+        SyntaxCategory::Gutter => Color::LIGHT_BLACK
+    }
+    .into_iter()
+    .map(|(category, color)| (category, Style::new(color)))
+    .collect();
+    styles.insert(
+        SyntaxCategory::Comment,
+        Style {
+            italic: true,
+            ..Style::new(Color::LIGHT_BLUE)
+        },
+    );
+    styles.insert(
+        SyntaxCategory::Error,
+        Style {
+            bold: true,
+            underline: true,
+            ..Style::new(Color::LIGHT_MAGENTA)
+        },
+    );
+    // The ancestors' brackets keep their own colours, and are only made bold
+    styles.insert(
+        SyntaxCategory::Ancestor,
+        Style {
+            bold: true,
+            ..Style::new(Color::Default)
+        },
+    );
+    ColorScheme::new(styles)
+}
+
+/// Generates the `mono` [`ColorScheme`], where only search matches, errors and changes stand out
+pub fn mono_color_scheme() -> ColorScheme {
+    let mut styles: HashMap<_, _> = CATEGORIES
+        .iter()
+        .map(|c| (*c, Style::new(Color::WHITE)))
+        .collect();
+    for (category, color) in &[
+        (SyntaxCategory::Comment, Color::LIGHT_BLACK),
+        (SyntaxCategory::Error, Color::LIGHT_RED),
+        (SyntaxCategory::Search, Color::YELLOW),
+        (SyntaxCategory::DiffAdded, Color::GREEN),
+        (SyntaxCategory::DiffRemoved, Color::RED),
+        (SyntaxCategory::DiffChanged, Color::LIGHT_BLUE),
+        (SyntaxCategory::Selection, Color::LIGHT_BLACK),
+        (SyntaxCategory::Gutter, Color::LIGHT_BLACK),
+    ] {
+        styles.insert(*category, Style::new(*color));
+    }
+    styles.insert(
+        SyntaxCategory::Ancestor,
+        Style {
+            bold: true,
+            ..Style::new(Color::Default)
+        },
+    );
+    ColorScheme::new(styles)
+}
+
+/// Builds the [`ColorScheme`] of a theme from the colour of each category.  Like the default
+/// scheme, themes draw comments in italics, errors in bold and underlined, and the brackets of
+/// the cursor's ancestors in bold.
+fn theme_color_scheme(colors: &[(SyntaxCategory, PaletteColor)]) -> ColorScheme {
+    let mut styles: HashMap<_, _> = colors
+        .iter()
+        .map(|(category, color)| (*category, Style::new(color.color())))
+        .collect();
+    for (category, style) in styles.iter_mut() {
+        match *category {
+            SyntaxCategory::Comment => style.italic = true,
+            SyntaxCategory::Error => {
+                style.bold = true;
+                style.underline = true;
+            }
+            _ => {}
+        }
+    }
+    styles.insert(
+        SyntaxCategory::Ancestor,
+        Style {
+            bold: true,
+            ..Style::new(Color::Default)
+        },
+    );
+    ColorScheme {
+        palette: colors.iter().map(|(_, color)| *color).collect(),
+        ..ColorScheme::new(styles)
+    }
+}
+
+/// Generates the `dark` [`ColorScheme`], which uses the colours of Atom's One Dark theme
+pub fn dark_color_scheme() -> ColorScheme {
+    let fg = PaletteColor::new(0xabb2bf, 145, 7);
+    let green = PaletteColor::new(0x98c379, 114, 2);
+    let orange = PaletteColor::new(0xd19a66, 173, 3);
+    let red = PaletteColor::new(0xe06c75, 168, 1);
+    let yellow = PaletteColor::new(0xe5c07b, 180, 11);
+    theme_color_scheme(&[
+        (SyntaxCategory::Default, fg),
+        (SyntaxCategory::Literal, green),
+        (SyntaxCategory::Const, orange),
+        (SyntaxCategory::Comment, PaletteColor::new(0x5c6370, 59, 8)),
+        (SyntaxCategory::Ident, red),
+        (SyntaxCategory::Keyword, PaletteColor::new(0xc678dd, 176, 5)),
+        (SyntaxCategory::Preproc, PaletteColor::new(0x61afef, 75, 4)),
+        (SyntaxCategory::Type, yellow),
+        (SyntaxCategory::Special, PaletteColor::new(0x56b6c2, 73, 6)),
+        (SyntaxCategory::Error, PaletteColor::new(0xbe5046, 131, 9)),
+        (SyntaxCategory::Search, yellow),
+        (SyntaxCategory::DiffAdded, green),
+        (SyntaxCategory::DiffRemoved, red),
+        (SyntaxCategory::DiffChanged, orange),
+        (
+            SyntaxCategory::Selection,
+            PaletteColor::new(0x3e4451, 238, 8),
+        ),
+        (SyntaxCategory::Gutter, PaletteColor::new(0x4b5263, 239, 8)),
+    ])
+}
+
+/// Generates the `light` [`ColorScheme`], which uses the colours of Atom's One Light theme
+pub fn light_color_scheme() -> ColorScheme {
+    let green = PaletteColor::new(0x50a14f, 71, 2);
+    let red = PaletteColor::new(0xe45649, 167, 1);
+    let yellow = PaletteColor::new(0xc18401, 136, 3);
+    theme_color_scheme(&[
+        (SyntaxCategory::Default, PaletteColor::new(0x383a42, 237, 0)),
+        (SyntaxCategory::Literal, green),
+        (SyntaxCategory::Const, PaletteColor::new(0x986801, 94, 3)),
+        (SyntaxCategory::Comment, PaletteColor::new(0xa0a1a7, 247, 8)),
+        (SyntaxCategory::Ident, red),
+        (SyntaxCategory::Keyword, PaletteColor::new(0xa626a4, 127, 5)),
+        (SyntaxCategory::Preproc, PaletteColor::new(0x4078f2, 33, 4)),
+        (SyntaxCategory::Type, yellow),
+        (SyntaxCategory::Special, PaletteColor::new(0x0184bc, 31, 6)),
+        (SyntaxCategory::Error, PaletteColor::new(0xca1243, 161, 1)),
+        (SyntaxCategory::Search, yellow),
+        (SyntaxCategory::DiffAdded, green),
+        (SyntaxCategory::DiffRemoved, red),
+        (SyntaxCategory::DiffChanged, yellow),
+        (
+            SyntaxCategory::Selection,
+            PaletteColor::new(0xe5e5e6, 254, 7),
+        ),
+        (SyntaxCategory::Gutter, PaletteColor::new(0x9d9d9f, 247, 8)),
+    ])
+}
+
+/// Generates the `solarized-dark` [`ColorScheme`], from Ethan Schoonover's Solarized.  The
+/// fallbacks are the ones that Solarized itself gives for 256 and 16 colour terminals.
+pub fn solarized_dark_color_scheme() -> ColorScheme {
+    let base01 = PaletteColor::new(0x586e75, 240, 10);
+    let yellow = PaletteColor::new(0xb58900, 136, 3);
+    let red = PaletteColor::new(0xdc322f, 160, 1);
+    let green = PaletteColor::new(0x859900, 64, 2);
+    theme_color_scheme(&[
+        (
+            SyntaxCategory::Default,
+            PaletteColor::new(0x839496, 244, 12),
+        ),
+        (SyntaxCategory::Literal, PaletteColor::new(0x2aa198, 37, 6)),
+        (SyntaxCategory::Const, PaletteColor::new(0x6c71c4, 61, 13)),
+        (SyntaxCategory::Comment, base01),
+        (SyntaxCategory::Ident, PaletteColor::new(0x268bd2, 33, 4)),
+        (SyntaxCategory::Keyword, green),
+        (SyntaxCategory::Preproc, PaletteColor::new(0xcb4b16, 166, 9)),
+        (SyntaxCategory::Type, yellow),
+        (SyntaxCategory::Special, red),
+        (SyntaxCategory::Error, red),
+        (SyntaxCategory::Search, yellow),
+        (SyntaxCategory::DiffAdded, green),
+        (SyntaxCategory::DiffRemoved, red),
+        (SyntaxCategory::DiffChanged, yellow),
+        (
+            SyntaxCategory::Selection,
+            PaletteColor::new(0x073642, 235, 0),
+        ),
+        (SyntaxCategory::Gutter, base01),
+    ])
+}
+
+/// Generates the `gruvbox` [`ColorScheme`], from the dark version of Pavel Pertsev's gruvbox
+pub fn gruvbox_color_scheme() -> ColorScheme {
+    let red = PaletteColor::new(0xfb4934, 167, 9);
+    let green = PaletteColor::new(0xb8bb26, 142, 10);
+    let yellow = PaletteColor::new(0xfabd2f, 214, 11);
+    let aqua = PaletteColor::new(0x8ec07c, 108, 14);
+    theme_color_scheme(&[
+        (
+            SyntaxCategory::Default,
+            PaletteColor::new(0xebdbb2, 223, 15),
+        ),
+        (SyntaxCategory::Literal, green),
+        (SyntaxCategory::Const, PaletteColor::new(0xd3869b, 175, 13)),
+        (SyntaxCategory::Comment, PaletteColor::new(0x928374, 245, 8)),
+        (SyntaxCategory::Ident, PaletteColor::new(0x83a598, 109, 12)),
+        (SyntaxCategory::Keyword, red),
+        (SyntaxCategory::Preproc, aqua),
+        (SyntaxCategory::Type, yellow),
+        (SyntaxCategory::Special, PaletteColor::new(0xfe8019, 208, 9)),
+        (SyntaxCategory::Error, red),
+        (SyntaxCategory::Search, yellow),
+        (SyntaxCategory::DiffAdded, green),
+        (SyntaxCategory::DiffRemoved, red),
+        (SyntaxCategory::DiffChanged, aqua),
+        (
+            SyntaxCategory::Selection,
+            PaletteColor::new(0x504945, 239, 8),
+        ),
+        (SyntaxCategory::Gutter, PaletteColor::new(0x7c6f64, 243, 8)),
+    ])
+}
+
+/// Returns the path that Sapling's config file should be loaded from, which is
+/// `$XDG_CONFIG_HOME/sapling/config.toml` (or `~/.config/sapling/config.toml` if
+/// `$XDG_CONFIG_HOME` isn't set).  Returns `None` if neither variable is set.
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("sapling").join("config.toml"))
+}
+
+/// The layout of Sapling's config file.  Any sections that Sapling doesn't recognise are ignored.
+#[derive(Debug, serde::Deserialize)]
+struct ConfigFile {
+    /// A mapping from category names to styles, and from language names to tables of the styles
+    /// that only apply to that language
+    #[serde(default)]
+    colors: HashMap<String, toml::Spanned<ColorsEntry>>,
+    /// Options for how the editor behaves
+    #[serde(default)]
+    editor: EditorTable,
+    /// A mapping from the names of keystrokes to the keys they're bound to
+    #[serde(default)]
+    keys: HashMap<String, toml::Spanned<KeyBindings>>,
+}
+
+/// An entry of the `[colors]` table, which is either the style of a category or (if it's named
+/// after a language) a table of the styles that only apply to that language.  Tables are kept as
+/// their entries, so that each entry knows where it is in the file even if the table is a
+/// `[colors.<language>]` section (whose own position `toml` doesn't record).
+#[derive(Debug)]
+enum ColorsEntry {
+    Value(toml::Value),
+    Table(Vec<(String, toml::Spanned<toml::Value>)>),
+}
+
+impl ColorsEntry {
+    /// Returns where the first entry of the table is in the file, or `None` if this isn't a
+    /// table (or is empty)
+    fn start(&self) -> Option<usize> {
+        match self {
+            ColorsEntry::Table(table) => table.iter().map(|(_, value)| value.start()).min(),
+            ColorsEntry::Value(_) => None,
+        }
+    }
+
+    /// Converts the entry back into the value that it was read from
+    fn into_value(self) -> toml::Value {
+        match self {
+            ColorsEntry::Value(value) => value,
+            ColorsEntry::Table(table) => toml::Value::Table(
+                table
+                    .into_iter()
+                    .map(|(name, value)| (name, value.into_inner()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ColorsEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EntryVisitor {
+            type Value = ColorsEntry;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "a colour or a table")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<ColorsEntry, E> {
+                Ok(ColorsEntry::Value(toml::Value::String(value.to_string())))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<ColorsEntry, A::Error> {
+                let mut table = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    table.push(entry);
+                }
+                Ok(ColorsEntry::Table(table))
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+/// The style of a category in the `[colors]` table, which is either a colour that can start with
+/// effects (`const = "bold red"`, see [`parse_style`]) or a table of the colours and effects
+/// (`comment = { fg = "green", italic = true }`)
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum StyleValue {
+    Shorthand(String),
+    Table(StyleTable),
+}
+
+/// A style written out as a table in the `[colors]` table.  Any colours which aren't given are
+/// the terminal's default colours, and any effects which aren't given are off.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleTable {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    bold: bool,
+    #[serde(default)]
+    dim: bool,
+    #[serde(default)]
+    italic: bool,
+    #[serde(default)]
+    underline: bool,
+    #[serde(default)]
+    reverse: bool,
+}
+
+impl StyleValue {
+    /// Converts the value into a [`Style`], or returns the colour that couldn't be parsed
+    fn style(self) -> Result<Style, String> {
+        match self {
+            StyleValue::Shorthand(string) => parse_style(&string).ok_or(string),
+            StyleValue::Table(table) => {
+                let color = |string: Option<String>| match string {
+                    Some(string) => parse_color(&string).map(Some).ok_or(string),
+                    None => Ok(None),
+                };
+                Ok(Style {
+                    fg: color(table.fg)?.unwrap_or(Color::Default),
+                    bg: color(table.bg)?,
+                    bold: table.bold,
+                    dim: table.dim,
+                    italic: table.italic,
+                    underline: table.underline,
+                    reverse: table.reverse,
+                })
+            }
+        }
+    }
+}
+
+/// The keys that a keystroke is bound to in the `[keys]` table, which are either a single
+/// sequence of keys (`"move-parent" = "ctrl-k"`) or a list of them (`"redo" = ["R", "ctrl-r"]`)
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum KeyBindings {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Builds the [`KeyMap`] given by the `[keys]` table of the config file whose text is `text`.
+/// Every keystroke in the table loses its default keys, and any default keys which conflict with
+/// the keys in the table are unbound.  Keystrokes that don't exist and keys which conflict with
+/// other keys in the table are ignored, and returned as [`ConfigWarning`]s.
+fn keymap_from_table(
+    keys: HashMap<String, toml::Spanned<KeyBindings>>,
+    text: &str,
+) -> Result<(KeyMap, Vec<ConfigWarning>), ConfigError> {
+    let mut warnings = Vec::new();
+    // Sort the entries so that errors and warnings are reported in the order they appear in the
+    // file
+    let mut entries: Vec<_> = keys.into_iter().collect();
+    entries.sort_by_key(|(_, value)| value.start());
+    let mut bindings = Vec::new();
+    for (name, value) in entries {
+        let line = line_number(text, value.start());
+        let stroke = match KeyStroke::from_name(&name) {
+            Some(stroke) => stroke,
+            None => {
+                warnings.push(ConfigWarning::UnknownKeyStroke { name, line });
+                continue;
+            }
+        };
+        let written = match value.into_inner() {
+            KeyBindings::One(keys) => vec![keys],
+            KeyBindings::Many(keys) => keys,
+        };
+        for written_keys in written {
+            let keys = parse_keys(&written_keys).map_err(|reason| ConfigError::InvalidKeys {
+                keys: written_keys.clone(),
+                reason,
+                line,
+            })?;
+            bindings.push((written_keys, keys, stroke, line));
+        }
+    }
+
+    let mut keymap = default_keymap();
+    keymap.retain(|_, stroke| !bindings.iter().any(|(_, _, s, _)| s == stroke));
+    // The keys bound by the table so far, along with how they were written and on which line
+    let mut bound: HashMap<String, (String, usize)> = HashMap::new();
+    for (written_keys, keys, stroke, line) in bindings {
+        if let Some(other) = conflicting_keys(&keys, bound.keys()).first() {
+            let (other_keys, other_line) = bound[*other].clone();
+            warnings.push(ConfigWarning::ConflictingKeys {
+                keys: written_keys,
+                line,
+                other_keys,
+                other_line,
+            });
+            continue;
+        }
+        let defaults: Vec<String> = conflicting_keys(&keys, keymap.keys())
+            .into_iter()
+            .cloned()
+            .collect();
+        for default in defaults {
+            keymap.remove(&default);
+        }
+        keymap.insert(keys.clone(), stroke);
+        bound.insert(keys, (written_keys, line));
+    }
+    Ok((keymap, warnings))
+}
+
+/// The `[editor]` table of Sapling's config file
+#[derive(Debug, Default, serde::Deserialize)]
+struct EditorTable {
+    #[serde(default)]
+    mouse: bool,
+    status_line: Option<toml::Spanned<String>>,
+    format: Option<toml::Spanned<String>>,
+    max_width: Option<usize>,
+    trailing_commas: Option<bool>,
+    self_closing: Option<bool>,
+    indent_width: Option<usize>,
+    tab_expand: Option<bool>,
+    tab_width: Option<usize>,
+    scrolloff: Option<usize>,
+    number: Option<bool>,
+    relativenumber: Option<bool>,
+    debug_highlighting: Option<bool>,
+    /// The theme, which can also be given by its old name of `color_scheme`
+    #[serde(alias = "color_scheme")]
+    theme: Option<toml::Spanned<String>>,
+    clipboard: Option<toml::Spanned<String>>,
+    which_key: Option<bool>,
+    which_key_delay: Option<usize>,
+    autosave: Option<usize>,
+    autosave_to: Option<toml::Spanned<String>>,
+    schema: Option<String>,
+}
+
+impl EditorTable {
+    /// Returns the [`Options`] given in this table, where `text` is the text of the config file.
+    /// Any options that aren't given keep their default values.
+    fn options(&self, text: &str) -> Result<Options, ConfigError> {
+        let defaults = Options::default();
+        // The options whose values are checked when they're set, which have to be set one by one
+        let mut options = Options::default();
+        for (name, value) in [
+            ("format", &self.format),
+            ("theme", &self.theme),
+            ("clipboard", &self.clipboard),
+            ("autosave_to", &self.autosave_to),
+        ] {
+            if let Some(value) = value {
+                options
+                    .set(name, value.get_ref())
+                    .map_err(|error| ConfigError::InvalidOption {
+                        error,
+                        line: line_number(text, value.start()),
+                    })?;
+            }
+        }
+        Ok(Options {
+            format: options.format,
+            max_width: self.max_width.unwrap_or(defaults.max_width),
+            trailing_commas: self.trailing_commas.unwrap_or(defaults.trailing_commas),
+            self_closing: self.self_closing.unwrap_or(defaults.self_closing),
+            indent_width: self.indent_width.unwrap_or(defaults.indent_width),
+            tab_expand: self.tab_expand.unwrap_or(defaults.tab_expand),
+            tab_width: self.tab_width.unwrap_or(defaults.tab_width),
+            scrolloff: self.scrolloff.unwrap_or(defaults.scrolloff),
+            number: self.number.unwrap_or(defaults.number),
+            relativenumber: self.relativenumber.unwrap_or(defaults.relativenumber),
+            debug_highlighting: self
+                .debug_highlighting
+                .unwrap_or(defaults.debug_highlighting),
+            theme: options.theme,
+            clipboard: options.clipboard,
+            which_key: self.which_key.unwrap_or(defaults.which_key),
+            which_key_delay: self.which_key_delay.unwrap_or(defaults.which_key_delay),
+            autosave: self.autosave.unwrap_or(defaults.autosave),
+            autosave_to: options.autosave_to,
+            schema: self.schema.clone().unwrap_or_default(),
+        })
+    }
+}
+
+/// Returns the (1-indexed) line number of a given byte index into `text`
+fn line_number(text: &str, byte_index: usize) -> usize {
+    text[..byte_index].matches('\n').count() + 1
+}
+
+/// A problem with the config file that doesn't stop it from being loaded
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ConfigWarning {
+    /// The config file gave a style to a category that doesn't exist
+    UnknownCategory { name: String, line: usize },
+    /// The config file gave a style to a category that used to exist, but whose job can now be
+    /// done by giving other categories effects (like `underlined`, which is now `underline`)
+    RemovedCategory { name: String, line: usize },
+    /// The `[keys]` table bound keys to a keystroke that doesn't exist
+    UnknownKeyStroke { name: String, line: usize },
+    /// The `[keys]` table bound `keys`, which are the start of `other_keys` (or vice versa), so
+    /// `keys` were ignored
+    ConflictingKeys {
+        keys: String,
+        line: usize,
+        other_keys: String,
+        other_line: usize,
+    },
+}
+
+impl std::fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigWarning::UnknownCategory { name, line } => {
+                write!(f, "line {}: unknown syntax category '{}'", line, name)
+            }
+            ConfigWarning::RemovedCategory { name, line } => write!(
+                f,
+                "line {}: the '{}' category has been removed (give a style `underline = true` instead)",
+                line, name
+            ),
+            ConfigWarning::UnknownKeyStroke { name, line } => {
+                write!(f, "line {}: unknown keystroke '{}'", line, name)
+            }
+            ConfigWarning::ConflictingKeys {
+                keys,
+                line,
+                other_keys,
+                other_line,
+            } => write!(
+                f,
+                "line {}: '{}' conflicts with '{}' (line {})",
+                line, keys, other_keys, other_line
+            ),
+        }
+    }
+}
+
+/// An error that stops the config file from being loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The config file isn't valid TOML (or has the wrong structure)
+    Toml(toml::de::Error),
+    /// A category was given a value that isn't a colour
+    InvalidColor {
+        category: String,
+        value: String,
+        line: usize,
+    },
+    /// A category's style was neither a colour nor a table of colours and effects
+    InvalidStyle { category: String, line: usize },
+    /// The status line's format string couldn't be parsed
+    InvalidStatusFormat {
+        format: String,
+        reason: String,
+        line: usize,
+    },
+    /// One of the [`Options`] was given a value that it can't be set to
+    InvalidOption { error: OptionError, line: usize },
+    /// A keystroke in the `[keys]` table was bound to keys that can't be parsed
+    InvalidKeys {
+        keys: String,
+        reason: String,
+        line: usize,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => {
+                write!(f, "couldn't read {}: {}", path.to_string_lossy(), error)
+            }
+            ConfigError::Toml(e) => write!(f, "{}", e),
+            ConfigError::InvalidColor {
+                category,
+                value,
+                line,
+            } => write!(
+                f,
+                "line {}: '{}' is not a valid colour for '{}'",
+                line, value, category
+            ),
+            ConfigError::InvalidStyle { category, line } => write!(
+                f,
+                "line {}: the style of '{}' should be a colour or a table of `fg`, `bg` and effects",
+                line, category
+            ),
+            ConfigError::InvalidStatusFormat {
+                format,
+                reason,
+                line,
+            } => write!(
+                f,
+                "line {}: invalid status line '{}': {}",
+                line, format, reason
+            ),
+            ConfigError::InvalidOption { error, line } => write!(f, "line {}: {}", line, error),
+            ConfigError::InvalidKeys { keys, reason, line } => {
+                write!(f, "line {}: can't bind '{}': {}", line, keys, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::color::{ColorSupport, Style};
+    use super::options::Options;
+    use super::{
+        default_color_scheme, gruvbox_color_scheme, mono_color_scheme, solarized_dark_color_scheme,
+        ColorScheme, Config, ConfigError, ConfigWarning, CATEGORIES, THEME_NAMES,
+    };
+    use crate::ast::display_token::{FormatKind, SyntaxCategory};
+    use crate::ast::json::Dialect;
+    use crate::editor::status_bar::StatusFormat;
+    use crate::language::Language;
+    use std::collections::HashMap;
+    use tuikit::prelude::Color;
+
+    #[test]
+    fn merge_over_defaults() {
+        let (scheme, warnings) = ColorScheme::from_toml(
+            r##"
+[colors]
+const = "#ff0000"
+literal = "green"
+"##,
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(scheme.color(SyntaxCategory::Const), Color::Rgb(255, 0, 0));
+        assert_eq!(scheme.color(SyntaxCategory::Literal), Color::GREEN);
+        // Unspecified categories keep their default colours
+        assert_eq!(scheme.color(SyntaxCategory::Comment), Color::LIGHT_BLUE);
+        // Effects can be given before a colour or on their own, or the style can be a table
+        let (scheme, warnings) = ColorScheme::from_toml(
+            r##"
+[colors]
+ancestor = "underline #303030"
+const = "bold"
+comment = { fg = "green", italic = true }
+selection = { bg = "red", bold = true }
+underlined = "cyan"
+"##,
+        )
+        .unwrap();
+        let style = |fg, bg| Style {
+            fg,
+            bg,
+            ..Style::new(fg)
+        };
+        assert_eq!(
+            scheme.style(SyntaxCategory::Ancestor),
+            Style {
+                underline: true,
+                ..style(Color::Rgb(48, 48, 48), None)
+            }
+        );
+        assert_eq!(
+            scheme.style(SyntaxCategory::Const),
+            Style {
+                bold: true,
+                ..style(Color::Default, None)
+            }
+        );
+        assert_eq!(
+            scheme.style(SyntaxCategory::Comment),
+            Style {
+                italic: true,
+                ..style(Color::GREEN, None)
+            }
+        );
+        assert_eq!(scheme.background(SyntaxCategory::Selection), Color::RED);
+        assert_eq!(scheme.color(SyntaxCategory::Selection), Color::Default);
+        // `underlined` has been replaced by giving any category `underline`
+        assert_eq!(
+            warnings,
+            vec![ConfigWarning::RemovedCategory {
+                name: "underlined".to_string(),
+                line: 7
+            }]
+        );
+        assert!(default_color_scheme().style(SyntaxCategory::Ancestor).bold);
+        assert!(default_color_scheme().style(SyntaxCategory::Comment).italic);
+        assert_eq!(
+            default_color_scheme().background(SyntaxCategory::Selection),
+            Color::BLUE
+        );
+        // Style tables can't contain anything else, and their colours are checked
+        assert!(matches!(
+            ColorScheme::from_toml("[colors]\nconst = { fg = \"red\", blink = true }"),
+            Err(ConfigError::InvalidStyle { line: 2, .. })
+        ));
+        assert!(matches!(
+            ColorScheme::from_toml("[colors]\nconst = { bg = \"reddish\" }"),
+            Err(ConfigError::InvalidColor { value, line: 2, .. }) if value == "reddish"
+        ));
+        // An empty file gives the default scheme
+        let (scheme, _) = ColorScheme::from_toml("").unwrap();
+        assert_eq!(scheme, default_color_scheme());
+    }
+
+    #[test]
+    fn language_styles() {
+        let (config, warnings) = Config::from_toml(
+            r##"
+[colors]
+literal = "green"
+ident = "red"
+
+[colors.json]
+literal = "blue"
+default = "cyan"
+key = "yellow"
+
+[colors.toml]
+comment = "italic"
+"##,
+        )
+        .unwrap();
+        // TOML's `key` isn't a category of JSON
+        assert_eq!(
+            warnings,
+            vec![ConfigWarning::UnknownCategory {
+                name: "key".to_string(),
+                line: 9
+            }]
+        );
+        let json = config
+            .color_scheme
+            .for_language(Language::JSON(Dialect::JSON));
+        let toml = config.color_scheme.for_language(Language::TOML);
+        // A language's own style comes first, then the shared style, and then `default`
+        assert_eq!(json.color(SyntaxCategory::Literal), Color::BLUE);
+        assert_eq!(toml.color(SyntaxCategory::Literal), Color::GREEN);
+        assert_eq!(
+            config.color_scheme.color(SyntaxCategory::Literal),
+            Color::GREEN
+        );
+        assert_eq!(json.color(SyntaxCategory::Const), Color::LIGHT_RED);
+        assert_eq!(json.color(SyntaxCategory::Custom("made-up")), Color::CYAN);
+        assert_eq!(toml.color(SyntaxCategory::Custom("made-up")), Color::WHITE);
+        assert_eq!(toml.color(SyntaxCategory::Comment), Color::Default);
+        assert_eq!(json.color(SyntaxCategory::Comment), Color::LIGHT_BLUE);
+        // A category that only one language has falls back on the shared category it's paired
+        // with, before `default`
+        assert_eq!(toml.color(SyntaxCategory::Custom("key")), Color::RED);
+        assert_eq!(
+            toml.categories().last(),
+            Some(SyntaxCategory::Custom("key")),
+            "the language's categories are listed after the shared ones"
+        );
+        let (config, _) = Config::from_toml("[colors]\nkey = \"blue\"").unwrap();
+        assert_eq!(
+            config
+                .color_scheme
+                .for_language(Language::TOML)
+                .color(SyntaxCategory::Custom("key")),
+            Color::BLUE
+        );
+
+        // Switching to another colour scheme keeps the languages' styles
+        let mono = mono_color_scheme()
+            .merged(&config.color_overrides)
+            .for_language(Language::TOML);
+        assert_eq!(mono.color(SyntaxCategory::Custom("key")), Color::BLUE);
+        assert_eq!(mono.color(SyntaxCategory::Literal), Color::WHITE);
+    }
+
+    #[test]
+    fn warnings_and_errors() {
+        let (scheme, warnings) = ColorScheme::from_toml(
+            "[colors]\nconst = \"red\"\nidnet = \"blue\"\nliteral = \"cyan\"\nfoo = \"blue\"",
+        )
+        .unwrap();
+        assert_eq!(scheme.color(SyntaxCategory::Const), Color::RED);
+        assert_eq!(scheme.color(SyntaxCategory::Literal), Color::CYAN);
+        assert_eq!(
+            warnings,
+            vec![
+                ConfigWarning::UnknownCategory {
+                    name: "idnet".to_string(),
+                    line: 3
+                },
+                ConfigWarning::UnknownCategory {
+                    name: "foo".to_string(),
+                    line: 5
+                }
+            ]
+        );
+
+        match ColorScheme::from_toml("[colors]\nconst = \"red\"\n\nliteral = \"#12345z\"\n") {
+            Err(ConfigError::InvalidColor {
+                category,
+                value,
+                line,
+            }) => {
+                assert_eq!(category, "literal");
+                assert_eq!(value, "#12345z");
+                assert_eq!(line, 4);
+            }
+            r => panic!("Expected an invalid colour error, got {:?}", r),
+        }
+        assert!(matches!(
+            ColorScheme::from_toml("[colors\n"),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn quantized_scheme() {
+        let (scheme, _) =
+            ColorScheme::from_toml("[colors]\nconst = \"#ff0000\"\nliteral = \"196\"").unwrap();
+        let scheme_16 = scheme.quantized(ColorSupport::Ansi16);
+        assert_eq!(scheme_16.color(SyntaxCategory::Const), Color::LIGHT_RED);
+        assert_eq!(scheme_16.color(SyntaxCategory::Literal), Color::LIGHT_RED);
+        assert_eq!(scheme_16.color(SyntaxCategory::Comment), Color::LIGHT_BLUE);
+        let scheme_256 = scheme.quantized(ColorSupport::Ansi256);
+        assert_eq!(
+            scheme_256.color(SyntaxCategory::Const),
+            Color::AnsiValue(196)
+        );
+        assert_eq!(scheme.quantized(ColorSupport::TrueColor), scheme);
+    }
+
+    #[test]
+    fn json_categories() {
+        use crate::arena::Arena;
+        use crate::ast::display_token::DisplayToken;
+        use crate::ast::json::{JSONFormat, JSON};
+        use crate::ast::Ast;
+        use std::collections::HashSet;
+
+        let arena = Arena::new();
+        let root = JSON::parse_dialect(
+            "// c\n{\"a\": [true, null, 1.5, \"x\\ny\", /* b */], \"b\": {}}",
+            &arena,
+            Dialect::JSONC,
+        )
+        .unwrap();
+        // The parser never makes an invalid number, but the tree can hold one
+        let invalid = arena.alloc(JSON::Number("1e".to_string()));
+        let mut categories = HashSet::new();
+        for node in &[root, invalid] {
+            for (_, token) in node.display_tokens(&JSONFormat::Compact) {
+                if let DisplayToken::Text(_, category) = token {
+                    categories.insert(category);
+                }
+            }
+        }
+        for category in &[SyntaxCategory::Comment, SyntaxCategory::Error] {
+            assert!(categories.contains(category), "{}", category);
+        }
+        // Every category that JSON is drawn in has a style of its own in the default scheme,
+        // rather than falling back on `default`
+        let scheme = default_color_scheme();
+        for category in &categories {
+            assert!(scheme.styles.contains_key(category), "{}", category);
+        }
+    }
+
+    #[test]
+    fn unknown_categories() {
+        let scheme = default_color_scheme().for_language(Language::TOML);
+        assert_eq!(
+            scheme.resolve(SyntaxCategory::Custom("made-up")),
+            scheme.style(SyntaxCategory::Default)
+        );
+        // Categories that fall back on the one their language pairs them with are known
+        scheme.resolve(SyntaxCategory::Custom("key"));
+        scheme.resolve(SyntaxCategory::Custom("made-up"));
+        assert_eq!(
+            scheme.unknown_categories(),
+            vec![SyntaxCategory::Custom("made-up")]
+        );
+        // `style` doesn't record anything
+        let scheme = default_color_scheme();
+        scheme.style(SyntaxCategory::Custom("made-up"));
+        assert_eq!(scheme.unknown_categories(), vec![]);
+
+        // Every built-in theme has a style for everything that every language is drawn in
+        for name in &THEME_NAMES {
+            for language in Language::all() {
+                let scheme = ColorScheme::named(name).unwrap().for_language(language);
+                assert_eq!(scheme.validate(), vec![], "{} in {}", name, language.name());
+            }
+        }
+        let mut styles = HashMap::new();
+        styles.insert(SyntaxCategory::Default, Style::new(Color::WHITE));
+        styles.insert(SyntaxCategory::Literal, Style::new(Color::RED));
+        let missing = ColorScheme::new(styles)
+            .for_language(Language::SExpr)
+            .validate();
+        assert!(missing.contains(&SyntaxCategory::Comment));
+        assert!(!missing.contains(&SyntaxCategory::Literal));
+    }
+
+    #[test]
+    fn themes() {
+        for name in &THEME_NAMES {
+            let scheme = ColorScheme::named(name).unwrap();
+            // Every theme styles every category itself, rather than falling back on `default`
+            for category in &CATEGORIES {
+                assert!(scheme.styles.contains_key(category), "{}", category);
+            }
+        }
+        assert_eq!(ColorScheme::named("neon"), None);
+
+        // The colours of a theme are replaced with its own fallbacks, rather than the closest
+        // colours (which would turn most of gruvbox's colours into the same shade)
+        let gruvbox = gruvbox_color_scheme();
+        assert_eq!(
+            gruvbox.color(SyntaxCategory::Keyword),
+            Color::Rgb(0xfb, 0x49, 0x34)
+        );
+        assert!(gruvbox.style(SyntaxCategory::Comment).italic);
+        let gruvbox_256 = gruvbox.quantized(ColorSupport::Ansi256);
+        assert_eq!(
+            gruvbox_256.color(SyntaxCategory::Keyword),
+            Color::AnsiValue(167)
+        );
+        assert_eq!(
+            gruvbox_256.background(SyntaxCategory::Selection),
+            Color::AnsiValue(239)
+        );
+        let gruvbox_16 = gruvbox.quantized(ColorSupport::Ansi16);
+        assert_eq!(gruvbox_16.color(SyntaxCategory::Type), Color::AnsiValue(11));
+        assert_eq!(gruvbox_16.color(SyntaxCategory::Ancestor), Color::Default);
+        // Colours given on top of a theme are still replaced with the closest ones
+        let (config, _) = Config::from_toml("[colors]\nkeyword = \"#ff0000\"").unwrap();
+        let merged = gruvbox
+            .merged(&config.color_overrides)
+            .quantized(ColorSupport::Ansi256);
+        assert_eq!(merged.color(SyntaxCategory::Keyword), Color::AnsiValue(196));
+        assert_eq!(
+            solarized_dark_color_scheme()
+                .quantized(ColorSupport::Ansi16)
+                .color(SyntaxCategory::Default),
+            Color::AnsiValue(12)
+        );
+    }
+
+    #[test]
+    fn editor_options() {
+        let (config, _) = Config::from_toml("").unwrap();
+        assert_eq!(config, Config::default());
+        assert!(!config.mouse);
+        let (config, _) =
+            Config::from_toml("[editor]\nmouse = true\n\n[colors]\nconst = \"red\"").unwrap();
+        assert!(config.mouse);
+        assert_eq!(config.color_scheme.color(SyntaxCategory::Const), Color::RED);
+        assert!(matches!(
+            Config::from_toml("[editor]\nmouse = \"yes\""),
+            Err(ConfigError::Toml(_))
+        ));
+
+        assert_eq!(config.status_format, StatusFormat::default());
+        let (config, _) = Config::from_toml("[editor]\nstatus_line = \"%f %p\"").unwrap();
+        assert_eq!(config.status_format, "%f %p".parse().unwrap());
+        let error = Config::from_toml("[editor]\n\nstatus_line = \"%q\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3: invalid status line '%q': unknown item '%q'"
+        );
+    }
+
+    #[test]
+    fn options() {
+        let (config, _) = Config::from_toml("").unwrap();
+        assert_eq!(config.options, Options::default());
+        let (config, _) = Config::from_toml(
+            "[editor]\nindent_width = 2\nscrolloff = 0\ntab_expand = false\n\
+             color_scheme = \"mono\"\nformat = \"compact\"\ntrailing_commas = true\n\
+             self_closing = false\n[colors]\nconst = \"red\"",
+        )
+        .unwrap();
+        assert_eq!(config.options.indent_width, 2);
+        assert_eq!(config.options.scrolloff, 0);
+        assert!(!config.options.tab_expand);
+        assert!(config.options.trailing_commas);
+        assert!(!config.options.self_closing);
+        assert_eq!(config.options.format_kind(), FormatKind::Compact);
+        assert_eq!(config.options.tab_width, Options::default().tab_width);
+        // The `[colors]` are applied on top of the chosen scheme
+        assert_eq!(config.color_scheme.color(SyntaxCategory::Const), Color::RED);
+        assert_eq!(
+            config.color_scheme.color(SyntaxCategory::Comment),
+            Color::LIGHT_BLACK
+        );
+        assert_eq!(
+            config.color_overrides.color(SyntaxCategory::Const),
+            Color::RED
+        );
+        assert_eq!(
+            ColorScheme::named("mono")
+                .unwrap()
+                .merged(&config.color_overrides),
+            config.color_scheme
+        );
+
+        let (config, _) = Config::from_toml("[editor]\ntheme = \"gruvbox\"").unwrap();
+        assert_eq!(config.options.theme, "gruvbox");
+        assert_eq!(config.color_scheme, gruvbox_color_scheme());
+        let error = Config::from_toml("[editor]\n\ntheme = \"neon\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3: Can't set 'theme' to 'neon': expected one of the themes (default, mono, \
+             dark, light, solarized-dark, gruvbox)."
+        );
+        assert!(matches!(
+            Config::from_toml("[editor]\nindent_width = -2"),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn keys() {
+        use crate::editable_tree::{Direction, Side};
+        use crate::editor::normal_mode::KeyStroke;
+
+        let (config, warnings) = Config::from_toml(
+            "[keys]\n\"move-parent\" = \"ctrl-k\"\n\"move-root\" = [\"g g\", \"<Home>\"]\n\
+             \"move-sideways\" = \"z\"\n\"redo\" = \"U\"\n\"help\" = \"g g x\"",
+        )
+        .unwrap();
+        let keymap = &config.keymap;
+        assert_eq!(
+            keymap.get("<C-k>"),
+            Some(&KeyStroke::MoveCursor(Direction::Up))
+        );
+        assert_eq!(
+            keymap.get("gg"),
+            Some(&KeyStroke::MoveCursor(Direction::Root))
+        );
+        assert_eq!(
+            keymap.get("<Home>"),
+            Some(&KeyStroke::MoveCursor(Direction::Root))
+        );
+        // Rebound keystrokes lose their default keys, and default keys which conflict with the
+        // table (like `g` with `gg`) are unbound
+        assert_eq!(keymap.get("k"), None);
+        assert_eq!(keymap.get("g"), None);
+        assert_eq!(keymap.get("R"), None);
+        assert_eq!(keymap.get("<C-r>"), None);
+        assert_eq!(keymap.get("U"), Some(&KeyStroke::Redo));
+        // Everything else keeps its default keys
+        assert_eq!(keymap.get("x"), Some(&KeyStroke::Delete));
+        assert_eq!(
+            keymap.get("<C-d>"),
+            Some(&KeyStroke::ScrollHalfPage(Side::Next))
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                ConfigWarning::UnknownKeyStroke {
+                    name: "move-sideways".to_string(),
+                    line: 4
+                },
+                ConfigWarning::ConflictingKeys {
+                    keys: "g g x".to_string(),
+                    line: 6,
+                    other_keys: "g g".to_string(),
+                    other_line: 3
+                }
+            ]
+        );
+        assert_eq!(
+            warnings[1].to_string(),
+            "line 6: 'g g x' conflicts with 'g g' (line 3)"
+        );
+
+        let error = Config::from_toml("[keys]\n\n\"undo\" = \"<Nope>\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3: can't bind '<Nope>': unknown special key (a '<' on its own is written '<lt>')"
+        );
+        let error = Config::from_toml("[keys]\n\"undo\" = \"2u\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 2: can't bind '2u': keys can't start with '2', which starts a count"
+        );
+    }
+}
@@ -0,0 +1,413 @@
+//! The options that can be changed whilst Sapling is running (with `:set`), as well as in the
+//! `[editor]` table of the config file.
+
+use super::THEME_NAMES;
+use crate::ast::display_token::{
+    FormatKind, IndentKind, RenderStyle, DEFAULT_INDENT_WIDTH, DEFAULT_TAB_WIDTH,
+};
+use crate::editor::autosave::AUTOSAVE_TARGETS;
+use crate::editor::clipboard::CLIPBOARD_NAMES;
+use crate::editor::viewport::DEFAULT_SCROLL_OFF;
+use crate::language::Language;
+
+/// The name of every option, in the order that they're listed
+pub const OPTION_NAMES: [&str; 18] = [
+    "format",
+    "max_width",
+    "trailing_commas",
+    "self_closing",
+    "indent_width",
+    "tab_expand",
+    "tab_width",
+    "scrolloff",
+    "number",
+    "relativenumber",
+    "debug_highlighting",
+    "theme",
+    "clipboard",
+    "which_key",
+    "which_key_delay",
+    "autosave",
+    "autosave_to",
+    "schema",
+];
+
+/// The names of the ways that the tree can be formatted: `pretty` spreads it over indented lines,
+/// `compact` leaves out all the whitespace that isn't needed, and `fit` is like `pretty` but
+/// keeps the nodes that fit within `max_width` columns on one line
+pub const FORMAT_NAMES: [&str; 3] = ["pretty", "compact", "fit"];
+
+/// How many columns wide a node can be and still be kept on one line by the `fit` format, if no
+/// other width is specified
+pub const DEFAULT_MAX_WIDTH: usize = 80;
+
+/// How many milliseconds Sapling waits during an unfinished keystroke before showing which keys
+/// can finish it, if no other delay is specified
+pub const DEFAULT_WHICH_KEY_DELAY: usize = 500;
+
+/// The options that change how Sapling behaves, which can be set with `:set <option>=<value>`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Options {
+    /// How the tree is laid out, both on the screen and when it's saved (one of
+    /// [`FORMAT_NAMES`])
+    pub format: String,
+    /// How many columns wide a node can be for the `fit` format to keep it on one line
+    pub max_width: usize,
+    /// If `true`, the last element of every array or object that's spread over many lines is
+    /// followed by a comma
+    pub trailing_commas: bool,
+    /// If `true`, XML elements with no children are written as self-closing tags (like `<br/>`)
+    pub self_closing: bool,
+    /// How many spaces make up one level of indentation (if `tab_expand` is `true`)
+    pub indent_width: usize,
+    /// If `true`, the tree is indented with spaces.  Otherwise, it's indented with tabs.
+    pub tab_expand: bool,
+    /// How many columns of the screen a tab character occupies
+    pub tab_width: usize,
+    /// How many lines are kept visible above and below the cursor when scrolling
+    pub scrolloff: usize,
+    /// If `true`, every line of the tree is numbered in a gutter to the left of it
+    pub number: bool,
+    /// If `true`, the lines in the gutter are numbered by how far they are from the cursor's
+    /// line.  If `number` is also `true`, then the cursor's own line shows its line number.
+    pub relativenumber: bool,
+    /// If `true`, every node is rendered in a colour derived from its hash
+    pub debug_highlighting: bool,
+    /// The name of the built-in colour scheme that the `[colors]` of the config file are applied
+    /// on top of (one of [`THEME_NAMES`])
+    pub theme: String,
+    /// How the `+` register reaches the system clipboard (one of [`CLIPBOARD_NAMES`])
+    pub clipboard: String,
+    /// If `true`, the keys which can finish an unfinished keystroke are shown once the user has
+    /// waited `which_key_delay` milliseconds without typing them
+    pub which_key: bool,
+    /// How many milliseconds to wait before showing the keys which can finish a keystroke
+    pub which_key_delay: usize,
+    /// How many milliseconds after the last change to a buffer it's auto-saved, or 0 if buffers
+    /// aren't auto-saved
+    pub autosave: usize,
+    /// Where buffers are auto-saved to (one of [`AUTOSAVE_TARGETS`])
+    pub autosave_to: String,
+    /// The path of the JSON Schema that JSON buffers are checked against, or empty if they
+    /// aren't checked against one
+    pub schema: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            format: "pretty".to_string(),
+            max_width: DEFAULT_MAX_WIDTH,
+            trailing_commas: false,
+            self_closing: true,
+            indent_width: DEFAULT_INDENT_WIDTH,
+            tab_expand: true,
+            tab_width: DEFAULT_TAB_WIDTH,
+            scrolloff: DEFAULT_SCROLL_OFF,
+            number: false,
+            relativenumber: false,
+            debug_highlighting: false,
+            theme: "default".to_string(),
+            clipboard: "auto".to_string(),
+            which_key: true,
+            which_key_delay: DEFAULT_WHICH_KEY_DELAY,
+            autosave: 0,
+            autosave_to: "file".to_string(),
+            schema: String::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Returns how the tree should be spread over lines
+    pub fn format_kind(&self) -> FormatKind {
+        match self.format.as_str() {
+            "compact" => FormatKind::Compact,
+            "fit" => FormatKind::Fit {
+                max_width: self.max_width,
+            },
+            _ => FormatKind::Pretty,
+        }
+    }
+
+    /// Returns how the tree should be indented
+    pub fn indent(&self) -> IndentKind {
+        if self.tab_expand {
+            IndentKind::Spaces(self.indent_width)
+        } else {
+            IndentKind::Tabs
+        }
+    }
+
+    /// Makes `format_style` format trees of `language` as these options say
+    pub fn apply_to(&self, format_style: &mut impl RenderStyle, language: Language) {
+        format_style.set_kind(self.format_kind());
+        format_style.set_indent(self.indent());
+        format_style.apply_options(self, language);
+    }
+
+    /// Returns the value of the option called `name`, in the same form that it would be set
+    pub fn get(&self, name: &str) -> Result<String, OptionError> {
+        Ok(match canonical_name(name) {
+            "format" => self.format.clone(),
+            "max_width" => self.max_width.to_string(),
+            "trailing_commas" => self.trailing_commas.to_string(),
+            "self_closing" => self.self_closing.to_string(),
+            "indent_width" => self.indent_width.to_string(),
+            "tab_expand" => self.tab_expand.to_string(),
+            "tab_width" => self.tab_width.to_string(),
+            "scrolloff" => self.scrolloff.to_string(),
+            "number" => self.number.to_string(),
+            "relativenumber" => self.relativenumber.to_string(),
+            "debug_highlighting" => self.debug_highlighting.to_string(),
+            "theme" => self.theme.clone(),
+            "clipboard" => self.clipboard.clone(),
+            "which_key" => self.which_key.to_string(),
+            "which_key_delay" => self.which_key_delay.to_string(),
+            "autosave" => self.autosave.to_string(),
+            "autosave_to" => self.autosave_to.clone(),
+            "schema" => self.schema.clone(),
+            _ => return Err(OptionError::Unknown(name.to_string())),
+        })
+    }
+
+    /// Sets the option called `name` to a value parsed from `value`.  If the value can't be
+    /// parsed, the option is left unchanged.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), OptionError> {
+        let name = canonical_name(name);
+        match name {
+            "max_width" => self.max_width = parse_value(name, value)?,
+            "trailing_commas" => self.trailing_commas = parse_value(name, value)?,
+            "self_closing" => self.self_closing = parse_value(name, value)?,
+            "indent_width" => self.indent_width = parse_value(name, value)?,
+            "tab_expand" => self.tab_expand = parse_value(name, value)?,
+            "tab_width" => self.tab_width = parse_value(name, value)?,
+            "scrolloff" => self.scrolloff = parse_value(name, value)?,
+            "number" => self.number = parse_value(name, value)?,
+            "relativenumber" => self.relativenumber = parse_value(name, value)?,
+            "debug_highlighting" => self.debug_highlighting = parse_value(name, value)?,
+            "which_key" => self.which_key = parse_value(name, value)?,
+            "which_key_delay" => self.which_key_delay = parse_value(name, value)?,
+            "autosave" => self.autosave = parse_value(name, value)?,
+            "format" => {
+                if !FORMAT_NAMES.contains(&value) {
+                    return Err(invalid_value(name, value));
+                }
+                self.format = value.to_string();
+            }
+            "theme" => {
+                if !THEME_NAMES.contains(&value) {
+                    return Err(invalid_value(name, value));
+                }
+                self.theme = value.to_string();
+            }
+            "clipboard" => {
+                if !CLIPBOARD_NAMES.contains(&value) {
+                    return Err(invalid_value(name, value));
+                }
+                self.clipboard = value.to_string();
+            }
+            "autosave_to" => {
+                if !AUTOSAVE_TARGETS.contains(&value) {
+                    return Err(invalid_value(name, value));
+                }
+                self.autosave_to = value.to_string();
+            }
+            "schema" => self.schema = value.to_string(),
+            _ => return Err(OptionError::Unknown(name.to_string())),
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name that the option called `name` goes by, which is only different for options
+/// that have been renamed (`color_scheme` is now `theme`)
+pub fn canonical_name(name: &str) -> &str {
+    match name {
+        "color_scheme" => "theme",
+        _ => name,
+    }
+}
+
+/// Returns a description of the values that the option called `name` can be set to
+fn expected_type(name: &str) -> String {
+    match name {
+        "tab_expand" | "debug_highlighting" | "trailing_commas" | "self_closing" | "which_key"
+        | "number" | "relativenumber" => "a boolean ('true' or 'false')".to_string(),
+        "format" => format!("one of the formats ({})", FORMAT_NAMES.join(", ")),
+        "theme" => format!("one of the themes ({})", THEME_NAMES.join(", ")),
+        "clipboard" => format!("one of the clipboards ({})", CLIPBOARD_NAMES.join(", ")),
+        "autosave_to" => format!("one of {}", AUTOSAVE_TARGETS.join(", ")),
+        _ => "a non-negative integer".to_string(),
+    }
+}
+
+/// Parses the value of the option called `name`
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, OptionError> {
+    value.parse().map_err(|_| invalid_value(name, value))
+}
+
+/// Creates the error for when the option called `name` can't be set to `value`
+fn invalid_value(name: &str, value: &str) -> OptionError {
+    OptionError::InvalidValue {
+        option: name.to_string(),
+        value: value.to_string(),
+        expected: expected_type(name),
+    }
+}
+
+/// The reason why an option couldn't be read or set
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum OptionError {
+    /// There's no option with the given name
+    Unknown(String),
+    /// The option can't be set to `value`, which should have been `expected`
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: String,
+    },
+}
+
+impl std::fmt::Display for OptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionError::Unknown(name) => write!(f, "There's no option called '{}'.", name),
+            OptionError::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Can't set '{}' to '{}': expected {}.",
+                option, value, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{OptionError, Options, OPTION_NAMES};
+    use crate::ast::display_token::{FormatKind, IndentKind};
+
+    #[test]
+    fn get_and_set() {
+        let mut options = Options::default();
+        // Every option can be read, and set back to the value that was read
+        for name in &OPTION_NAMES {
+            let value = options.get(name).unwrap();
+            options.set(name, &value).unwrap();
+        }
+        assert_eq!(options, Options::default());
+
+        options.set("indent_width", "2").unwrap();
+        options.set("scrolloff", "0").unwrap();
+        options.set("theme", "gruvbox").unwrap();
+        assert_eq!(options.format_kind(), FormatKind::Pretty);
+        options.set("format", "compact").unwrap();
+        assert_eq!(options.format_kind(), FormatKind::Compact);
+        options.set("format", "fit").unwrap();
+        options.set("max_width", "40").unwrap();
+        assert_eq!(options.format_kind(), FormatKind::Fit { max_width: 40 });
+        options.set("trailing_commas", "true").unwrap();
+        assert!(options.trailing_commas);
+        options.set("self_closing", "false").unwrap();
+        assert!(!options.self_closing);
+        assert_eq!(options.indent_width, 2);
+        assert_eq!(options.scrolloff, 0);
+        assert_eq!(options.get("theme").unwrap(), "gruvbox");
+        // `color_scheme` is the old name of `theme`
+        options.set("color_scheme", "mono").unwrap();
+        assert_eq!(options.theme, "mono");
+        assert_eq!(options.get("color_scheme").unwrap(), "mono");
+        assert_eq!(options.indent(), IndentKind::Spaces(2));
+        options.set("tab_expand", "false").unwrap();
+        assert_eq!(options.indent(), IndentKind::Tabs);
+        options.set("which_key", "false").unwrap();
+        options.set("which_key_delay", "0").unwrap();
+        assert!(!options.which_key);
+        assert_eq!(options.get("which_key_delay").unwrap(), "0");
+        options.set("number", "true").unwrap();
+        options.set("relativenumber", "true").unwrap();
+        assert!(options.number && options.relativenumber);
+        options.set("autosave", "2000").unwrap();
+        options.set("autosave_to", "shadow").unwrap();
+        assert_eq!(options.autosave, 2000);
+        assert_eq!(options.get("autosave_to").unwrap(), "shadow");
+    }
+
+    #[test]
+    fn apply_to_format_style() {
+        use crate::ast::json::{Dialect, JSONFormat};
+        use crate::language::Language;
+        let mut options = Options::default();
+        let mut format_style = JSONFormat::Compact;
+        options.apply_to(&mut format_style, Language::JSON(Dialect::JSONC));
+        // `JSONFormat` isn't `Debug`, so it can't be compared with `assert_eq!`
+        assert!(
+            format_style
+                == JSONFormat::Pretty {
+                    indent: IndentKind::Spaces(4),
+                    trailing_commas: false,
+                }
+        );
+        options.set("tab_expand", "false").unwrap();
+        options.set("trailing_commas", "true").unwrap();
+        options.apply_to(&mut format_style, Language::JSON(Dialect::JSONC));
+        assert!(
+            format_style
+                == JSONFormat::Pretty {
+                    indent: IndentKind::Tabs,
+                    trailing_commas: true,
+                }
+        );
+        // Strict JSON doesn't allow trailing commas, so they're left out
+        options.apply_to(&mut format_style, Language::JSON(Dialect::JSON));
+        assert!(
+            format_style
+                == JSONFormat::Pretty {
+                    indent: IndentKind::Tabs,
+                    trailing_commas: false,
+                }
+        );
+        options.set("format", "compact").unwrap();
+        options.apply_to(&mut format_style, Language::JSON(Dialect::JSONC));
+        assert!(format_style == JSONFormat::Compact);
+    }
+
+    #[test]
+    fn errors() {
+        let mut options = Options::default();
+        assert_eq!(
+            options.get("tabstop"),
+            Err(OptionError::Unknown("tabstop".to_string()))
+        );
+        assert_eq!(
+            options.set("indent_width", "-1").unwrap_err().to_string(),
+            "Can't set 'indent_width' to '-1': expected a non-negative integer."
+        );
+        assert_eq!(
+            options.set("tab_expand", "yes").unwrap_err().to_string(),
+            "Can't set 'tab_expand' to 'yes': expected a boolean ('true' or 'false')."
+        );
+        assert_eq!(
+            options.set("color_scheme", "neon").unwrap_err().to_string(),
+            "Can't set 'theme' to 'neon': expected one of the themes (default, mono, dark, \
+             light, solarized-dark, gruvbox)."
+        );
+        assert_eq!(
+            options.set("format", "minified").unwrap_err().to_string(),
+            "Can't set 'format' to 'minified': expected one of the formats (pretty, compact, fit)."
+        );
+        assert_eq!(
+            options.set("autosave_to", "cloud").unwrap_err().to_string(),
+            "Can't set 'autosave_to' to 'cloud': expected one of file, shadow."
+        );
+        assert!(options.set("nope", "1").is_err());
+        // Invalid values leave the options unchanged
+        assert_eq!(options, Options::default());
+    }
+}
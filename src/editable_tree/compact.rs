@@ -0,0 +1,182 @@
+//! Compaction of trees, which copies only the nodes that are still reachable into a fresh
+//! [`Arena`].
+//!
+//! Editing never frees nodes: every edit allocates new copies of the nodes between the root and
+//! the edited node, and the nodes that they replace stay in the arena.  Compacting the trees that
+//! are still wanted (the current tree and the snapshots in its undo history) leaves a new arena
+//! containing only their nodes.  Nodes are shared between the trees exactly as they were before,
+//! so every node is only copied once.
+//!
+//! Compaction doesn't free the old arena: every arena lives as long as the
+//! [`BufferLoader`](crate::editor::buffer::BufferLoader) that made it, so that nodes can be
+//! shared between buffers.  Reclaiming the memory needs arenas that can be dropped once they're
+//! no longer used, which is still to come.
+
+use crate::arena::Arena;
+use crate::ast::Ast;
+use std::collections::HashMap;
+
+/// Copies trees into a new [`Arena`], keeping track of the nodes that have already been copied
+/// so that nodes which are shared between trees stay shared
+pub struct Compactor<'arena, Node: Ast<'arena>> {
+    arena: &'arena Arena<Node>,
+    /// The copy of every node that has been copied, indexed by the address of the original
+    copies: HashMap<*const Node, &'arena Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Compactor<'arena, Node> {
+    /// Creates a `Compactor` which copies nodes into `arena`
+    pub fn new(arena: &'arena Arena<Node>) -> Self {
+        Compactor {
+            arena,
+            copies: HashMap::new(),
+        }
+    }
+
+    /// Returns the arena that nodes are copied into
+    pub fn arena(&self) -> &'arena Arena<Node> {
+        self.arena
+    }
+
+    /// Returns the copy of the tree under `node`, copying any of its nodes that haven't already
+    /// been copied.  The tree is walked with an explicit stack, so this works on trees of any
+    /// depth.
+    pub fn copy(&mut self, node: &'arena Node) -> &'arena Node {
+        // Every node is visited twice: once to push its children, and then once all of its
+        // children have been copied, to copy the node itself
+        let mut stack = vec![(node, false)];
+        while let Some((node, children_copied)) = stack.pop() {
+            if self.copies.contains_key(&(node as *const Node)) {
+                // A shared node which was copied since it was put on the stack
+                continue;
+            }
+            if !children_copied {
+                stack.push((node, true));
+                for &child in node.children().iter().rev() {
+                    if !self.copies.contains_key(&(child as *const Node)) {
+                        stack.push((child, false));
+                    }
+                }
+                continue;
+            }
+            let mut copy = node.clone();
+            for child in copy.children_mut() {
+                *child = self.copies[&(*child as *const Node)];
+            }
+            self.copies
+                .insert(node as *const Node, self.arena.alloc(copy));
+        }
+        self.copies[&(node as *const Node)]
+    }
+
+    /// Returns the number of nodes that have been copied
+    pub fn copied_count(&self) -> usize {
+        self.copies.len()
+    }
+}
+
+/// How much memory a compaction used before and after (see [`Arena::len`] and
+/// [`Arena::bytes_allocated`])
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Compaction {
+    pub nodes_before: usize,
+    pub bytes_before: usize,
+    pub nodes_after: usize,
+    pub bytes_after: usize,
+}
+
+impl Compaction {
+    /// Returns how many fewer bytes the nodes take up after the compaction
+    pub fn bytes_saved(&self) -> usize {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compactor;
+    use crate::arena::Arena;
+    use crate::ast::json::JSONFormat;
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::DAG;
+    use crate::editor::normal_mode::Action;
+
+    #[test]
+    fn sharing_is_kept() {
+        let arena = Arena::new();
+        let shared = J::Array(vec![J::True, J::Null]).add_to_arena(&arena);
+        let a = arena.alloc(JSON::Array(vec![shared, shared]));
+        let b = arena.alloc(JSON::Array(vec![shared]));
+        // Garbage that isn't reachable from either root
+        J::Array(vec![J::False; 10]).add_to_arena(&arena);
+
+        let new_arena = Arena::new();
+        let mut compactor = Compactor::new(&new_arena);
+        let (new_a, new_b) = (compactor.copy(a), compactor.copy(b));
+        assert_eq!((new_a, new_b), (a, b));
+        // `shared` and its two children were only copied once
+        assert_eq!(compactor.copied_count(), 5);
+        assert_eq!(new_arena.len(), 5);
+        assert!(std::ptr::eq(new_a.children()[0], new_b.children()[0]));
+        assert!(std::ptr::eq(new_a.children()[0], new_a.children()[1]));
+        assert!(!std::ptr::eq(new_a.children()[0], shared));
+        // Copying a tree again gives the same copy
+        assert!(std::ptr::eq(compactor.copy(a), new_a));
+    }
+
+    #[test]
+    fn copies_are_independent() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::Object(vec![("a".to_string(), J::Null)])])
+            .add_to_arena(&arena);
+        let target = Arena::new();
+        let copy = root.clone_subtree_into(&target);
+        assert_eq!(copy, root);
+        assert!(copy
+            .iter_pre_order()
+            .all(|c| root.iter_pre_order().all(|n| !std::ptr::eq(c, n))));
+
+        // Editing the copy leaves the original alone
+        let mut tree = DAG::new(&target, copy, CursorPath::from_vec(vec![0]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        assert_eq!(tree.to_text(&JSONFormat::Compact), r#"[{"a":null}]"#);
+        assert_eq!(root.to_text(&JSONFormat::Compact), r#"[true,{"a":null}]"#);
+    }
+
+    #[test]
+    fn dag_shaped_trees() {
+        let arena = Arena::new();
+        // A tree where every level contains its level below twice, so it has 2^21 - 1 nodes
+        // but only 21 unique ones
+        let mut node = arena.alloc(JSON::Null);
+        for _ in 0..20 {
+            node = arena.alloc(JSON::Array(vec![node, node]));
+        }
+        let target = Arena::new();
+        let copy = node.clone_subtree_into(&target);
+        assert_eq!(target.len(), 21);
+        assert_eq!(copy.node_count(), node.node_count());
+        assert_eq!(copy.node_count(), (1 << 21) - 1);
+        assert!(std::ptr::eq(copy.children()[0], copy.children()[1]));
+    }
+
+    #[test]
+    fn deep_trees() {
+        // Deep enough to overflow the stack if the copy was recursive
+        let arena = Arena::new();
+        let mut node = arena.alloc(JSON::True);
+        for _ in 0..200_000 {
+            node = arena.alloc(JSON::Array(vec![node]));
+        }
+        let target = Arena::new();
+        let copy = node.clone_subtree_into(&target);
+        assert_eq!(target.len(), 200_001);
+        assert_eq!(copy.depth(), 200_000);
+        let leaf = copy.iter_pre_order().last().unwrap();
+        assert_eq!(leaf, &JSON::True);
+        assert!(!std::ptr::eq(leaf, node.iter_pre_order().last().unwrap()));
+    }
+}
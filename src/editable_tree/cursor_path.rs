@@ -1,7 +1,12 @@
 use crate::ast::Ast;
+use std::error::Error;
+use std::str::FromStr;
 
 /// A tree-independent struct for representing the locations of nodes within trees.
-#[derive(Debug, Clone, Eq, PartialEq)]
+///
+/// Paths are written as the child indices separated by `.`s (so `0.3.1` is the 2nd child of the
+/// 4th child of the 1st child of the root), and the root is written as `root`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct CursorPath {
     child_indices: Vec<usize>,
 }
@@ -33,6 +38,55 @@ impl CursorPath {
         self.node_iter(root).last().unwrap()
     }
 
+    /// Walks this path down from the given root, and returns the node that it points to.  Unlike
+    /// [`cursor`](CursorPath::cursor), this returns `None` rather than panicking if the path
+    /// doesn't exist in the tree.
+    pub fn resolve<'arena, Node: Ast<'arena>>(&self, root: &'arena Node) -> Option<&'arena Node> {
+        match self.resolve_longest(root) {
+            (node, steps) if steps == self.depth() => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Walks as far down this path from the given root as possible, returning the deepest node
+    /// reached along with the number of steps that were taken from the root.  If the whole path
+    /// exists, then this node is the one that the path points to.
+    pub fn resolve_longest<'arena, Node: Ast<'arena>>(
+        &self,
+        root: &'arena Node,
+    ) -> (&'arena Node, usize) {
+        let mut node = root;
+        for (steps, &index) in self.child_indices.iter().enumerate() {
+            match node.children().get(index) {
+                Some(child) => node = child,
+                None => return (node, steps),
+            }
+        }
+        (node, self.depth())
+    }
+
+    /// Returns the path from `root` to `node`, or `None` if `node` isn't in the tree under
+    /// `root`.  Nodes are compared by reference rather than by value, and if `node` occurs in
+    /// several places in the tree then the first one (in pre-order) is returned.
+    pub fn path_of<'arena, Node: Ast<'arena>>(
+        root: &'arena Node,
+        node: &'arena Node,
+    ) -> Option<CursorPath> {
+        // Walk the tree with an explicit stack, so that deep trees can't overflow the call stack
+        let mut stack = vec![(root, CursorPath::root())];
+        while let Some((n, path)) = stack.pop() {
+            if std::ptr::eq(n, node) {
+                return Some(path);
+            }
+            for (i, child) in n.children().iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                stack.push((child, child_path));
+            }
+        }
+        None
+    }
+
     /// Walks this path down from the given root, and returns the node that lies underneath the
     /// cursor, along with the direct parent of that node (if it exists).
     pub fn cursor_and_parent<'arena, Node: Ast<'arena>>(
@@ -70,6 +124,12 @@ impl CursorPath {
         self.child_indices.pop()
     }
 
+    /// Returns `true` if `prefix` is an ancestor of this path, or is this path itself
+    #[inline]
+    pub fn starts_with(&self, prefix: &CursorPath) -> bool {
+        self.child_indices.starts_with(&prefix.child_indices)
+    }
+
     /// Returns `true` if this path refers to the root of any tree (i.e. the path has no segments).
     #[inline]
     pub fn is_root(&self) -> bool {
@@ -95,7 +155,57 @@ impl CursorPath {
     where
         Node: Ast<'arena>,
     {
-        NodeIter::new(root, &self)
+        NodeIter::new(root, self)
+    }
+}
+
+impl std::fmt::Display for CursorPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return write!(f, "root");
+        }
+        for (i, index) in self.child_indices.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when a string can't be parsed into a [`CursorPath`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ParsePathError {
+    /// The part of the string that isn't a valid child index
+    pub segment: String,
+}
+
+impl std::fmt::Display for ParsePathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' isn't a valid child index", self.segment)
+    }
+}
+
+impl Error for ParsePathError {}
+
+impl FromStr for CursorPath {
+    type Err = ParsePathError;
+
+    /// Parses a path in the format produced by [`Display`](std::fmt::Display), e.g. `0.3.1`.  An
+    /// empty string is also treated as the root.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "root" {
+            return Ok(CursorPath::root());
+        }
+        s.split('.')
+            .map(|segment| {
+                segment.parse().map_err(|_| ParsePathError {
+                    segment: segment.to_string(),
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(CursorPath::from_vec)
     }
 }
 
@@ -166,7 +276,7 @@ impl<'arena, Node: Ast<'arena>> std::iter::FusedIterator for NodeIter<'arena, '_
 
 #[cfg(test)]
 mod tests {
-    use super::CursorPath;
+    use super::{CursorPath, ParsePathError};
     use crate::arena::Arena;
     use crate::ast::{json::JSON, test_json::TestJSON, Ast};
 
@@ -321,4 +431,62 @@ mod tests {
         assert_eq!(c.display_name(), "true");
         assert_eq!(p.unwrap().display_name(), "field");
     }
+
+    #[test]
+    fn resolve() {
+        let arena = Arena::new();
+        let root = TestJSON::Array(vec![
+            TestJSON::True,
+            TestJSON::Object(vec![("value".to_string(), TestJSON::False)]),
+        ])
+        .add_to_arena(&arena);
+        let path = CursorPath::from_vec;
+
+        assert!(std::ptr::eq(path(vec![]).resolve(root).unwrap(), root));
+        assert_eq!(path(vec![1, 0, 1]).resolve(root), Some(&JSON::False));
+        assert_eq!(path(vec![2]).resolve(root), None);
+        assert_eq!(path(vec![0, 0]).resolve(root), None);
+        // `resolve_longest` stops at the deepest node that exists
+        let (node, steps) = path(vec![1, 0, 5, 2]).resolve_longest(root);
+        assert_eq!((node.display_name().as_str(), steps), ("field", 2));
+
+        // `path_of` is the inverse of `resolve`
+        for p in &[
+            path(vec![]),
+            path(vec![0]),
+            path(vec![1, 0]),
+            path(vec![1, 0, 1]),
+        ] {
+            let node = p.resolve(root).unwrap();
+            assert_eq!(CursorPath::path_of(root, node).as_ref(), Some(p));
+        }
+        // Nodes are compared by reference, so an equal node from another tree isn't found
+        let other = TestJSON::True.add_to_arena(&arena);
+        assert_eq!(CursorPath::path_of(root, other), None);
+    }
+
+    #[test]
+    fn display_and_parse() {
+        for (string, indices) in &[
+            ("root", vec![]),
+            ("0", vec![0]),
+            ("0.3.1", vec![0, 3, 1]),
+            ("12.0", vec![12, 0]),
+        ] {
+            let path = CursorPath::from_vec(indices.clone());
+            assert_eq!(path.to_string(), *string);
+            assert_eq!(string.parse(), Ok(path.clone()));
+            // Round-trip through both conversions
+            assert_eq!(path.to_string().parse::<CursorPath>(), Ok(path));
+        }
+        assert_eq!("".parse(), Ok(CursorPath::root()));
+        for (string, segment) in &[("0.x", "x"), ("1..2", ""), ("-1", "-1"), ("0.3.", "")] {
+            assert_eq!(
+                string.parse::<CursorPath>(),
+                Err(ParsePathError {
+                    segment: segment.to_string()
+                })
+            );
+        }
+    }
 }
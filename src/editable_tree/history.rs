@@ -0,0 +1,376 @@
+//! The undo history of a tree, stored as a tree of snapshots.
+
+use super::compact::Compactor;
+use super::cursor_path::CursorPath;
+use crate::ast::owned::{OwnedReader, OwnedTreeError, OwnedWriter};
+use crate::ast::Ast;
+use serde::{Deserialize, Serialize};
+
+/// A single state of the tree in the undo history
+#[derive(Debug, Clone)]
+struct Snapshot<'arena, Node: Ast<'arena>> {
+    /// The root of the tree in this state
+    root: &'arena Node,
+    /// The location of the cursor in this state.  This must always be a valid path in the tree
+    /// under `root`.
+    cursor_path: CursorPath,
+    /// The index of the snapshot that this snapshot was created from, or `None` if this is the
+    /// first snapshot
+    parent: Option<usize>,
+    /// The index of the child snapshot that [`EditHistory::redo`] should move to.  This is the
+    /// child that was most recently created or moved away from.
+    redo_child: Option<usize>,
+}
+
+/// A [`Snapshot`] that can be serialized, whose tree is stored as the index of its root in a
+/// list of [`OwnedNode`](crate::ast::owned::OwnedNode)s
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SavedSnapshot {
+    pub root: usize,
+    pub cursor_path: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redo_child: Option<usize>,
+}
+
+/// A point in an [`EditHistory`] that can be gone back to (see [`EditHistory::checkpoint`])
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Checkpoint {
+    /// The number of snapshots
+    len: usize,
+    current: usize,
+    /// The redo child of the current snapshot, which adding a new snapshot replaces
+    redo_child: Option<usize>,
+}
+
+/// An [`EditHistory`] that can be serialized (see [`EditHistory::save`])
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SavedHistory {
+    pub current: usize,
+    pub snapshots: Vec<SavedSnapshot>,
+}
+
+/// The undo history of a tree.  Every state of the tree is stored as a snapshot, which is cheap
+/// because the nodes in the states share all of their unchanged nodes in the
+/// [`Arena`](crate::arena::Arena).
+///
+/// The snapshots form a tree rather than a linear stack: undoing some changes then making a new
+/// edit starts a new branch of the history rather than destroying the changes that were undone.
+/// [`undo`](EditHistory::undo) and [`redo`](EditHistory::redo) move up and down the current
+/// branch, whereas [`older`](EditHistory::older) and [`newer`](EditHistory::newer) step through
+/// every state in the order they were created (like Vim's `g-` and `g+`), which allows every
+/// branch to be reached.
+#[derive(Debug, Clone)]
+pub struct EditHistory<'arena, Node: Ast<'arena>> {
+    /// Every snapshot ever created, in the order they were created.  This always contains at
+    /// least one snapshot.
+    snapshots: Vec<Snapshot<'arena, Node>>,
+    /// The index of the snapshot that is currently checked out
+    current: usize,
+}
+
+impl<'arena, Node: Ast<'arena>> EditHistory<'arena, Node> {
+    /// Creates a new `EditHistory` containing only one state
+    pub fn new(root: &'arena Node, cursor_path: CursorPath) -> Self {
+        EditHistory {
+            snapshots: vec![Snapshot {
+                root,
+                cursor_path,
+                parent: None,
+                redo_child: None,
+            }],
+            current: 0,
+        }
+    }
+
+    /// Returns the root of the tree in the current state
+    pub fn root(&self) -> &'arena Node {
+        self.snapshots[self.current].root
+    }
+
+    /// Returns the location of the cursor in the current state
+    pub fn cursor_path(&self) -> &CursorPath {
+        &self.snapshots[self.current].cursor_path
+    }
+
+    /// Changes where the cursor is in the current state, so that undoing back to this state (or
+    /// redoing to it) puts the cursor there
+    pub fn set_cursor_path(&mut self, cursor_path: CursorPath) {
+        self.snapshots[self.current].cursor_path = cursor_path;
+    }
+
+    /// Adds a new state as a child of the current state, and moves to it
+    pub fn push(&mut self, root: &'arena Node, cursor_path: CursorPath) {
+        let new_index = self.snapshots.len();
+        self.snapshots.push(Snapshot {
+            root,
+            cursor_path,
+            parent: Some(self.current),
+            redo_child: None,
+        });
+        self.snapshots[self.current].redo_child = Some(new_index);
+        self.current = new_index;
+    }
+
+    /// Replaces the tree of the current state without adding a new state.  This is only for
+    /// changes which leave the tree's text the same, so that there's nothing to undo.
+    pub fn replace_root(&mut self, root: &'arena Node) {
+        self.snapshots[self.current].root = root;
+    }
+
+    /// Returns a [`Checkpoint`] of the history as it is now, which can be gone back to (with
+    /// [`rollback`](EditHistory::rollback)) as long as only new states are added in the meantime
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            len: self.snapshots.len(),
+            current: self.current,
+            redo_child: self.snapshots[self.current].redo_child,
+        }
+    }
+
+    /// Removes every state added since `checkpoint`, returning to the state that was current
+    /// when it was taken
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.snapshots.truncate(checkpoint.len);
+        self.current = checkpoint.current;
+        self.snapshots[self.current].redo_child = checkpoint.redo_child;
+    }
+
+    /// Replaces every state added since `checkpoint` with only the current state, so that they
+    /// are undone as one change.  If the current tree is the one that was current at
+    /// `checkpoint`, then no state is added at all.
+    pub fn squash(&mut self, checkpoint: Checkpoint) {
+        let latest = self.snapshots[self.current].clone();
+        self.rollback(checkpoint);
+        if !std::ptr::eq(latest.root, self.root()) {
+            self.push(latest.root, latest.cursor_path);
+        }
+    }
+
+    /// Moves to the state before the current one, returning the root of that state.  Returns
+    /// `None` (without changing anything) if there are no changes to undo.
+    pub fn undo(&mut self) -> Option<&'arena Node> {
+        let parent = self.snapshots[self.current].parent?;
+        // Make sure that redoing takes us back to where we were
+        self.snapshots[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(self.root())
+    }
+
+    /// Moves to the state that was most recently undone from the current state, returning the
+    /// root of that state.  Returns `None` (without changing anything) if there are no changes to
+    /// redo.
+    pub fn redo(&mut self) -> Option<&'arena Node> {
+        self.current = self.snapshots[self.current].redo_child?;
+        Some(self.root())
+    }
+
+    /// Moves to the state that was created before the current state, even if it is on a different
+    /// branch.  Returns `None` if the current state is the oldest state.
+    pub fn older(&mut self) -> Option<&'arena Node> {
+        let index = self.current.checked_sub(1)?;
+        self.jump_to(index);
+        Some(self.root())
+    }
+
+    /// Moves to the state that was created after the current state, even if it is on a different
+    /// branch.  Returns `None` if the current state is the newest state.
+    pub fn newer(&mut self) -> Option<&'arena Node> {
+        let index = self.current + 1;
+        if index >= self.snapshots.len() {
+            return None;
+        }
+        self.jump_to(index);
+        Some(self.root())
+    }
+
+    /// Moves to an arbitrary snapshot, updating the redo children of all its ancestors so that
+    /// undoing then redoing will return to the new snapshot.
+    fn jump_to(&mut self, index: usize) {
+        let mut child = index;
+        while let Some(parent) = self.snapshots[child].parent {
+            self.snapshots[parent].redo_child = Some(child);
+            child = parent;
+        }
+        self.current = index;
+    }
+
+    /// Writes the tree of every state with `writer` (so the nodes that the states share are only
+    /// written once), returning the rest of the history in a form that can be serialized
+    pub fn save(
+        &self,
+        writer: &mut OwnedWriter<'arena, Node>,
+    ) -> Result<SavedHistory, OwnedTreeError> {
+        let snapshots = self
+            .snapshots
+            .iter()
+            .map(|snapshot| {
+                Ok(SavedSnapshot {
+                    root: writer.add(snapshot.root)?,
+                    cursor_path: snapshot.cursor_path.iter().copied().collect(),
+                    parent: snapshot.parent,
+                    redo_child: snapshot.redo_child,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(SavedHistory {
+            current: self.current,
+            snapshots,
+        })
+    }
+
+    /// Rebuilds a history that was [`save`](EditHistory::save)d, taking the trees from `reader`.
+    /// Returns `None` if the history isn't valid (e.g. a snapshot's parent comes after it, or a
+    /// cursor isn't in its tree).
+    pub fn restore(saved: &SavedHistory, reader: &OwnedReader<'arena, Node>) -> Option<Self> {
+        let len = saved.snapshots.len();
+        let mut snapshots = Vec::with_capacity(len);
+        for (index, snapshot) in saved.snapshots.iter().enumerate() {
+            let root = reader.get(snapshot.root).ok()?;
+            let cursor_path = CursorPath::from_vec(snapshot.cursor_path.clone());
+            // Only the first snapshot has no parent, and snapshots come after their parents
+            let valid_parent = match snapshot.parent {
+                None => index == 0,
+                Some(parent) => parent < index,
+            };
+            let valid_redo_child = snapshot
+                .redo_child
+                .is_none_or(|child| child < len && saved.snapshots[child].parent == Some(index));
+            let valid_cursor = cursor_path.resolve_longest(root).1 == cursor_path.depth();
+            if !(valid_parent && valid_redo_child && valid_cursor) {
+                return None;
+            }
+            snapshots.push(Snapshot {
+                root,
+                cursor_path,
+                parent: snapshot.parent,
+                redo_child: snapshot.redo_child,
+            });
+        }
+        if saved.current >= len {
+            return None;
+        }
+        Some(EditHistory {
+            snapshots,
+            current: saved.current,
+        })
+    }
+
+    /// Copies the tree of every state into the arena of a [`Compactor`].  The cursor paths stay
+    /// the same, since the copied trees have exactly the same shape.
+    pub fn compact(&mut self, compactor: &mut Compactor<'arena, Node>) {
+        for snapshot in &mut self.snapshots {
+            snapshot.root = compactor.copy(snapshot.root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditHistory, SavedHistory};
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use crate::ast::owned::{OwnedReader, OwnedWriter};
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    #[test]
+    fn linear() {
+        let arena = Arena::new();
+        let roots: Vec<&JSON> = (0..3)
+            .map(|i| arena.alloc(JSON::Number(i.to_string())))
+            .collect();
+        let mut history = EditHistory::new(roots[0], CursorPath::root());
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), None);
+        history.push(roots[1], CursorPath::from_vec(vec![1]));
+        history.push(roots[2], CursorPath::from_vec(vec![2]));
+        // Undo all the way back, checking that the cursor is restored
+        assert!(std::ptr::eq(history.undo().unwrap(), roots[1]));
+        assert_eq!(history.cursor_path(), &CursorPath::from_vec(vec![1]));
+        assert!(std::ptr::eq(history.undo().unwrap(), roots[0]));
+        assert_eq!(history.cursor_path(), &CursorPath::root());
+        assert_eq!(history.undo(), None);
+        // Redo all the way forward again
+        assert!(std::ptr::eq(history.redo().unwrap(), roots[1]));
+        assert!(std::ptr::eq(history.redo().unwrap(), roots[2]));
+        assert_eq!(history.redo(), None);
+        assert!(std::ptr::eq(history.root(), roots[2]));
+    }
+
+    #[test]
+    fn branching() {
+        let arena = Arena::new();
+        let roots: Vec<&JSON> = (0..4)
+            .map(|i| arena.alloc(JSON::Number(i.to_string())))
+            .collect();
+        // Make the history:
+        // 0 -> 1 -> 2
+        //   \
+        //    -> 3
+        let mut history = EditHistory::new(roots[0], CursorPath::root());
+        history.push(roots[1], CursorPath::root());
+        history.push(roots[2], CursorPath::root());
+        history.undo();
+        history.undo();
+        history.push(roots[3], CursorPath::root());
+        // Undoing and redoing follow the newest branch
+        assert!(std::ptr::eq(history.undo().unwrap(), roots[0]));
+        assert!(std::ptr::eq(history.redo().unwrap(), roots[3]));
+        assert_eq!(history.redo(), None);
+        // But the old branch is still reachable by stepping back in time
+        assert!(std::ptr::eq(history.older().unwrap(), roots[2]));
+        assert!(std::ptr::eq(history.older().unwrap(), roots[1]));
+        assert!(std::ptr::eq(history.older().unwrap(), roots[0]));
+        assert_eq!(history.older(), None);
+        assert!(std::ptr::eq(history.newer().unwrap(), roots[1]));
+        assert!(std::ptr::eq(history.newer().unwrap(), roots[2]));
+        // Now that we're on the old branch, undo/redo follow that branch
+        assert!(std::ptr::eq(history.undo().unwrap(), roots[1]));
+        assert!(std::ptr::eq(history.undo().unwrap(), roots[0]));
+        assert!(std::ptr::eq(history.redo().unwrap(), roots[1]));
+        assert!(std::ptr::eq(history.redo().unwrap(), roots[2]));
+        assert!(std::ptr::eq(history.newer().unwrap(), roots[3]));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let arena = Arena::new();
+        let roots: Vec<&JSON> = (0..3)
+            .map(|i| arena.alloc(JSON::Array(vec![arena.alloc(JSON::Number(i.to_string()))])))
+            .collect();
+        let mut history = EditHistory::new(roots[0], CursorPath::root());
+        history.push(roots[1], CursorPath::from_vec(vec![0]));
+        history.undo();
+        history.push(roots[2], CursorPath::root());
+        history.undo();
+
+        let mut writer = OwnedWriter::new();
+        let saved = history.save(&mut writer).unwrap();
+        let nodes = writer.finish();
+        let new_arena = Arena::new();
+        let reader = OwnedReader::new(&nodes, &new_arena).unwrap();
+        let mut restored = EditHistory::restore(&saved, &reader).unwrap();
+        assert_eq!(restored.save(&mut OwnedWriter::new()), Ok(saved.clone()));
+        // The restored history behaves in the same way as the original
+        assert_eq!(restored.root(), roots[0]);
+        assert_eq!(restored.redo(), Some(roots[2]));
+        assert_eq!(restored.older(), Some(roots[1]));
+        assert_eq!(restored.cursor_path(), &CursorPath::from_vec(vec![0]));
+
+        // Histories that don't make sense aren't restored
+        let broken = |f: fn(&mut SavedHistory)| {
+            let mut saved = saved.clone();
+            f(&mut saved);
+            EditHistory::restore(&saved, &reader).is_none()
+        };
+        assert!(broken(|h| h.current = 3));
+        assert!(broken(|h| h.snapshots.clear()));
+        assert!(broken(|h| h.snapshots[1].parent = Some(2)));
+        assert!(broken(|h| h.snapshots[2].parent = None));
+        assert!(broken(|h| h.snapshots[0].redo_child = Some(0)));
+        assert!(broken(|h| h.snapshots[1].cursor_path = vec![1]));
+        assert!(broken(|h| h.snapshots[1].root = 100));
+    }
+}
@@ -1,11 +1,28 @@
 //! Code for an editable, undoable forest of syntax trees.
 
+pub mod compact;
 pub mod cursor_path;
+pub mod history;
+pub mod replace;
+pub mod search;
+pub mod transaction;
+pub mod validate;
 
 use crate::ast::Ast;
+use crate::config::options::OptionError;
 use crate::editor::normal_mode::Action;
+use crate::editor::window::Split;
 use crate::{arena::Arena, ast};
+use compact::Compactor;
 use cursor_path::CursorPath;
+use history::EditHistory;
+
+/// The deepest that any node can be below the root after an edit (counting the steps down from
+/// the root).  Many of the walks over the tree are recursive, so without this a tree could be
+/// nested deeply enough (e.g. by `10000sa`) to overflow the stack.  This allows every tree that
+/// the parsers accept: they stop arrays and objects being nested more than 128 levels deep, and
+/// the values of an object are one step further down, under its fields.
+pub const MAX_DEPTH: usize = 256;
 
 /// The possible ways you can move the cursor
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -14,6 +31,11 @@ pub enum Direction {
     Down,
     Prev,
     Next,
+    /// Jump straight to the root of the tree
+    Root,
+    /// Jump to the last leaf of the tree (i.e. the node reached by repeatedly moving to the last
+    /// child, starting from the root)
+    LastLeaf,
 }
 
 /// An enum to represent the two sides of a node
@@ -39,11 +61,236 @@ pub enum EditSuccess {
     Quit,
     Undo,
     Redo,
+    Older,
+    Newer,
     Move(Direction),
-    Replace { c: char, name: String },
-    InsertChild { c: char, name: String },
-    InsertNextToCursor { side: Side, c: char, name: String },
-    Delete { name: String },
+    Goto {
+        path: String,
+        name: String,
+    },
+    SetMark {
+        c: char,
+        path: String,
+    },
+    /// Jumped to a mark.  If `exact` is `false`, then the marked position no longer exists, and
+    /// the cursor was moved to its closest surviving ancestor
+    JumpToMark {
+        c: char,
+        path: String,
+        exact: bool,
+    },
+    ListMarks {
+        /// The register, path and display name of each mark's current target
+        marks: Vec<(char, String, String)>,
+    },
+    ListRegisters {
+        /// The name of each register that isn't empty, along with a preview of its text
+        registers: Vec<(char, String)>,
+    },
+    ListErrors {
+        /// The path of each node with a problem, and a description of the problem
+        errors: Vec<(String, String)>,
+    },
+    Replace {
+        c: char,
+        name: String,
+    },
+    Wrap {
+        c: char,
+        name: String,
+    },
+    Unwrap {
+        name: String,
+        child_name: String,
+    },
+    InsertChild {
+        c: char,
+        name: String,
+    },
+    InsertNextToCursor {
+        side: Side,
+        c: char,
+        name: String,
+    },
+    Delete {
+        name: String,
+        count: usize,
+    },
+    PushChar {
+        c: char,
+        name: String,
+    },
+    PopChar {
+        name: String,
+    },
+    /// The text of the cursor was set by typing it in insert mode, giving the node `name`
+    SetText {
+        name: String,
+    },
+    StartInsert,
+    /// The text of `count` nodes had `pattern` replaced.  The contents of `unparsed` nodes
+    /// couldn't be looked inside.
+    ReplaceText {
+        pattern: String,
+        replacement: String,
+        count: usize,
+        unparsed: usize,
+    },
+    Save {
+        path: String,
+    },
+    /// The tree was read again from its file at `path`, which another program had changed
+    Reload {
+        path: String,
+    },
+    /// The user chose to keep their version of `name` rather than the one that another program
+    /// wrote to its file, so the next save overwrites the file without asking
+    KeepOwnVersion {
+        name: String,
+    },
+    /// The version of `name` that another program wrote to its file was opened in a new
+    /// (read-only) buffer, next to the user's version
+    OpenDiskVersion {
+        name: String,
+    },
+    /// The tree was written to `path` in an export format (e.g. `"dot"`)
+    Export {
+        format: String,
+        path: String,
+    },
+    /// The buffer at `index` (of `count` buffers) became the one being edited
+    SwitchBuffer {
+        index: usize,
+        count: usize,
+        name: String,
+    },
+    /// The window at `index` (of `count` windows, in the order they're cycled through) became
+    /// the focused window
+    FocusWindow {
+        index: usize,
+        count: usize,
+    },
+    ListBuffers {
+        /// The name of each buffer, whether it has unsaved changes and whether it's the one
+        /// being edited
+        buffers: Vec<(String, bool, bool)>,
+    },
+    /// `:messages` opened the log, which has `count` messages
+    Messages {
+        count: usize,
+    },
+    /// The names of the built-in themes, along with the one being used
+    ListThemes {
+        themes: Vec<&'static str>,
+        current: String,
+    },
+    /// The memory used by the current buffer's nodes (see [`Arena::bytes_allocated`]), along
+    /// with how many nodes have been allocated and how many of those are in the current tree
+    Stats {
+        nodes: usize,
+        bytes: usize,
+        live_nodes: usize,
+    },
+    /// `keys` were bound to the keystroke called `keystroke`
+    Map {
+        keys: String,
+        keystroke: &'static str,
+    },
+    /// `keys` were bound to the keystroke called `keystroke`, but aren't any more
+    Unmap {
+        keys: String,
+        keystroke: &'static str,
+    },
+    /// The structure of the current tree, along with how many lines its text has on screen
+    TreeStats {
+        stats: ast::stats::TreeStats,
+        lines: usize,
+    },
+    /// The differences between the tree when it was last saved and the current tree (see
+    /// [`TreeEdit`](crate::ast::diff::TreeEdit))
+    ListChanges {
+        changes: Vec<String>,
+    },
+    DebugHighlighting(bool),
+    /// The full-screen keystroke log was shown (if `true`) or hidden
+    LogView(bool),
+    /// The help box was shown for the cursor
+    ShowHelp,
+    /// Sapling was suspended, and has now been carried on
+    Suspend,
+    /// The cursor was piped through a command by `:pipe`, and replaced by its output (`name`)
+    Pipe {
+        command: String,
+        name: String,
+    },
+    /// A shell command was run by `:!`, and exited with `status` (or was killed by a signal, if
+    /// that's `None`)
+    Shell {
+        command: String,
+        status: Option<i32>,
+    },
+    /// The cursor was folded (if `folded` is `true`) or unfolded
+    Fold {
+        name: String,
+        folded: bool,
+    },
+    /// The screen was scrolled so that its top line is the `line`th line of the tree
+    Scroll {
+        line: usize,
+    },
+    /// `name` (and the `count - 1` siblings after it) were copied into the register
+    Yank {
+        name: String,
+        count: usize,
+    },
+    StartRecording(char),
+    StopRecording(char),
+    StartSearch,
+    StartCommand,
+    /// An option was set with `:set` (or its value was asked for), and it now has `value`
+    ShowOption {
+        option: String,
+        value: String,
+    },
+    /// A search for `query` found `matches` nodes, without looking inside `unparsed` nodes
+    Search {
+        query: String,
+        matches: usize,
+        unparsed: usize,
+    },
+    SearchMatch {
+        index: usize,
+        matches: usize,
+    },
+    /// The cursors of a diff moved to the hunk at `index` (of `count` hunks)
+    MoveToChange {
+        index: usize,
+        count: usize,
+    },
+    /// The cursor moved to the problem at `index` (of `count` problems), which is described by
+    /// `message`
+    MoveToError {
+        index: usize,
+        count: usize,
+        message: String,
+    },
+    Replay {
+        register: char,
+        count: usize,
+    },
+    MoveNode {
+        side: Side,
+        count: usize,
+        name: String,
+    },
+    /// `count` nodes were pasted from the register, the first of which is `name`
+    Paste {
+        side: Side,
+        name: String,
+        count: usize,
+    },
+    StartVisual,
+    StopVisual,
 }
 
 impl EditSuccess {
@@ -53,11 +300,34 @@ impl EditSuccess {
             EditSuccess::Quit => log::info!("Quitting Sapling"),
             EditSuccess::Undo => log::info!("Undoing one change"),
             EditSuccess::Redo => log::info!("Redoing one change"),
+            EditSuccess::Older => log::info!("Moving to the previous tree in time"),
+            EditSuccess::Newer => log::info!("Moving to the next tree in time"),
             EditSuccess::Move(Direction::Up) => log::info!("Moving up the tree"),
             EditSuccess::Move(Direction::Down) => log::info!("Moving down the tree"),
             EditSuccess::Move(Direction::Prev) => log::info!("Moving to previous child"),
             EditSuccess::Move(Direction::Next) => log::info!("Moving to next child"),
+            EditSuccess::Move(Direction::Root) => log::info!("Moving to the root"),
+            EditSuccess::Move(Direction::LastLeaf) => log::info!("Moving to the last leaf"),
+            EditSuccess::Goto { path, name } => log::info!("Moving to {} at {}", name, path),
+            EditSuccess::SetMark { c, path } => log::info!("Marking {} as '{}'", path, c),
+            EditSuccess::JumpToMark { c, path, exact } => log::info!(
+                "Jumping to mark '{}' at {}{}",
+                c,
+                path,
+                if exact { "" } else { " (closest ancestor)" }
+            ),
+            EditSuccess::ListMarks { marks } => log::info!("Listing {} mark(s)", marks.len()),
+            EditSuccess::ListRegisters { registers } => {
+                log::info!("Listing {} register(s)", registers.len())
+            }
+            EditSuccess::ListErrors { errors } => {
+                log::info!("Listing {} error(s)", errors.len())
+            }
             EditSuccess::Replace { c, name } => log::info!("Replacing with '{}'/{}", c, name),
+            EditSuccess::Wrap { c, name } => log::info!("Wrapping in '{}'/{}", c, name),
+            EditSuccess::Unwrap { name, child_name } => {
+                log::info!("Replacing {} with its child {}", name, child_name)
+            }
             EditSuccess::InsertChild { c, name } => {
                 log::info!("Inserting '{}'/{} as new child", c, name)
             }
@@ -67,7 +337,151 @@ impl EditSuccess {
                 name,
                 side.relational_word()
             ),
-            EditSuccess::Delete { name } => log::info!("Deleting {}", name),
+            EditSuccess::Delete { name, count: 1 } => log::info!("Deleting {}", name),
+            EditSuccess::Delete { name, count } => {
+                log::info!("Deleting {} and {} sibling(s)", name, count - 1)
+            }
+            EditSuccess::PushChar { c, name } => log::info!("Appending '{}' to get {}", c, name),
+            EditSuccess::PopChar { name } => log::info!("Removing a char to get {}", name),
+            EditSuccess::SetText { name } => log::info!("Setting text to get {}", name),
+            EditSuccess::StartInsert => log::info!("Starting to insert text"),
+            EditSuccess::ReplaceText {
+                pattern,
+                replacement,
+                count,
+                unparsed,
+            } => log::info!(
+                "Replaced '{}' with '{}' in {} node(s){}",
+                pattern,
+                replacement,
+                count,
+                search::unparsed_note(unparsed)
+            ),
+            EditSuccess::Save { path } => log::info!("Saved to {}", path),
+            EditSuccess::Reload { path } => log::info!("Reloaded {}", path),
+            EditSuccess::KeepOwnVersion { name } => {
+                log::info!("Keeping the edited version of {}", name)
+            }
+            EditSuccess::OpenDiskVersion { name } => {
+                log::info!("Opening the version of {} on disk", name)
+            }
+            EditSuccess::Export { format, path } => {
+                log::info!("Exported {} to {}", format, path)
+            }
+            EditSuccess::SwitchBuffer { index, count, name } => {
+                log::info!("Switching to buffer {}/{} ({})", index + 1, count, name)
+            }
+            EditSuccess::FocusWindow { index, count } => {
+                log::info!("Focusing window {}/{}", index + 1, count)
+            }
+            EditSuccess::ListBuffers { buffers } => {
+                log::info!("Listing {} buffer(s)", buffers.len())
+            }
+            EditSuccess::Messages { count } => log::info!("Showing {} message(s)", count),
+            EditSuccess::ListThemes { themes, current } => {
+                log::info!("Listing {} theme(s), using '{}'", themes.len(), current)
+            }
+            EditSuccess::Stats {
+                nodes,
+                bytes,
+                live_nodes,
+            } => log::info!(
+                "{} node(s) allocated ({} bytes), {} in the current tree",
+                nodes,
+                bytes,
+                live_nodes
+            ),
+            EditSuccess::Map { keys, keystroke } => {
+                log::info!("Mapping '{}' to {}", keys, keystroke)
+            }
+            EditSuccess::Unmap { keys, keystroke } => {
+                log::info!("Unmapping '{}' from {}", keys, keystroke)
+            }
+            EditSuccess::TreeStats { stats, lines } => {
+                log::info!(
+                    "The tree has {} node(s) and {} line(s), and is {} level(s) deep",
+                    stats.nodes,
+                    lines,
+                    stats.depth
+                );
+                for (kind, count) in stats.kinds_by_count() {
+                    log::info!("  {}: {}", kind, count);
+                }
+            }
+            EditSuccess::ListChanges { changes } => {
+                log::info!("Listing {} change(s) since the last save", changes.len())
+            }
+            EditSuccess::DebugHighlighting(true) => log::info!("Turning debug highlighting on"),
+            EditSuccess::DebugHighlighting(false) => log::info!("Turning debug highlighting off"),
+            EditSuccess::LogView(true) => log::info!("Showing the keystroke log"),
+            EditSuccess::LogView(false) => log::info!("Hiding the keystroke log"),
+            EditSuccess::ShowHelp => log::info!("Showing the help for the cursor"),
+            EditSuccess::Suspend => log::info!("Carrying on after being suspended"),
+            EditSuccess::Pipe { command, name } => {
+                log::info!("Replacing the cursor with {} from '{}'", name, command)
+            }
+            EditSuccess::Shell { command, status } => {
+                log::info!("Ran '{}', which exited with {:?}", command, status)
+            }
+            EditSuccess::Fold { name, folded: true } => log::info!("Folding {}", name),
+            EditSuccess::Fold {
+                name,
+                folded: false,
+            } => log::info!("Unfolding {}", name),
+            EditSuccess::Scroll { line } => log::info!("Scrolling to line {}", line),
+            EditSuccess::Yank { name, count: 1 } => log::info!("Yanking {}", name),
+            EditSuccess::Yank { name, count } => {
+                log::info!("Yanking {} and {} sibling(s)", name, count - 1)
+            }
+            EditSuccess::StartRecording(c) => log::info!("Recording macro '{}'", c),
+            EditSuccess::StartSearch => log::info!("Starting search"),
+            EditSuccess::StartCommand => log::info!("Starting command"),
+            EditSuccess::ShowOption { option, value } => log::info!("{} is {}", option, value),
+            EditSuccess::Search {
+                query,
+                matches,
+                unparsed,
+            } => log::info!(
+                "Found {} match(es) of '{}'{}",
+                matches,
+                query,
+                search::unparsed_note(unparsed)
+            ),
+            EditSuccess::SearchMatch { index, matches } => {
+                log::info!("Moving to match {} of {}", index + 1, matches)
+            }
+            EditSuccess::MoveToChange { index, count } => {
+                log::info!("Moving to change {} of {}", index + 1, count)
+            }
+            EditSuccess::MoveToError { index, count, .. } => {
+                log::info!("Moving to error {} of {}", index + 1, count)
+            }
+            EditSuccess::StopRecording(c) => log::info!("Finished recording macro '{}'", c),
+            EditSuccess::Replay { register, count } => {
+                log::info!("Replayed macro '{}' {} time(s)", register, count)
+            }
+            EditSuccess::MoveNode { side, count, name } => log::info!(
+                "Moving {} {} place(s) {}",
+                name,
+                count,
+                match side {
+                    Side::Prev => "back",
+                    Side::Next => "forward",
+                }
+            ),
+            EditSuccess::Paste {
+                side,
+                name,
+                count: 1,
+            } => log::info!("Pasting {} {} the cursor", name, side.relational_word()),
+            EditSuccess::Paste { side, name, count } => log::info!(
+                "Pasting {} and {} other node(s) {} the cursor",
+                name,
+                count - 1,
+                side.relational_word()
+            ),
+            EditSuccess::StartVisual => log::info!("Starting to select siblings"),
+            EditSuccess::StopVisual => log::info!("Stopping selecting siblings"),
         }
     }
 }
@@ -78,6 +492,18 @@ pub enum EditErr {
     /* MISC ERRORS */
     /// The keystrokes that were attempted to be executed where invalid
     Invalid(String),
+    /// The command typed after `:` couldn't be parsed
+    InvalidCommand { command: String, reason: String },
+    /// Trying to quit (without `!`) whilst the buffers with these names have unsaved changes
+    UnsavedChanges { buffers: Vec<String> },
+    /// Trying to compare the tree with its saved version, when the tree has never been saved
+    NoSavedTree,
+    /// `:set` was given an option that doesn't exist, or a value that the option can't be set to
+    InvalidOption(OptionError),
+    /// `:map` couldn't bind `keys` to a keystroke
+    InvalidMapping { keys: String, reason: String },
+    /// `:unmap` was given keys which aren't bound to anything
+    NotMapped(String),
 
     /* MOVEMENT ERRORS */
     /// Trying to move to the child of a node with no children
@@ -88,6 +514,15 @@ pub enum EditErr {
     MoveToNonexistentSibling,
     /// Trying to move to a sibling of the root
     MoveToSiblingOfRoot,
+    /// Trying to move to a path that doesn't exist in the tree, because the node at
+    /// `parent_path` doesn't have a child at `index`
+    NoNodeAtPath {
+        path: String,
+        parent_path: String,
+        parent_name: String,
+        index: usize,
+        len: usize,
+    },
 
     /* EDITING ERRORS */
     /// Trying to undo the earliest change
@@ -97,51 +532,388 @@ pub enum EditErr {
     /// The user typed a char that doesn't correspond to any node
     CharNotANode(char),
     /// Trying to insert a node that cannot be a child of the cursor
-    CannotBeChild { c: char, parent_name: String },
+    CannotBeChild { c: char, parent_kind: &'static str },
     /// An error was generated by the Ast code when trying to insert a node
     InsertError(ast::InsertError),
-    /// An error was generated by the Ast code when trying to insert a node
+    /// An error was generated by the Ast code when trying to delete a node
     DeleteError(ast::DeleteError),
+    /// An error was generated by the Ast code when trying to move a node between its siblings
+    MoveError(ast::MoveError),
     /// Trying to add a sibling to the root
     AddSiblingToRoot,
     /// Trying to delete the root
     DeletingRoot,
+    /// Trying to append a char to a node that can't accept it
+    CannotPushChar { c: char, name: String },
+    /// Trying to remove a char from a node that has no chars to remove
+    CannotPopChar { name: String },
+    /// Trying to type the text of a node which has no text
+    NoText { kind: &'static str },
+    /// Trying to wrap a node in a node that can't contain it
+    CannotWrap { c: char, kind: &'static str },
+    /// An edit would put a node more than [`MAX_DEPTH`] steps below the root
+    TooDeep,
+    /// Trying to unwrap the root, which has no parent to put its child into
+    UnwrapRoot,
+    /// Trying to unwrap a node that doesn't have a single child to replace it with
+    CannotUnwrap { kind: &'static str },
+    /// Trying to unwrap a node whose child couldn't take its place in its parent
+    InvalidUnwrap {
+        child_kind: &'static str,
+        parent_kind: &'static str,
+    },
+    /// Trying to paste before anything has been yanked or cut
+    EmptyRegister,
+    /// Trying to paste from a named register that nothing has been yanked into
+    EmptyNamedRegister(char),
+    /// Trying to paste a register that was yanked whilst editing a different language
+    RegisterLanguage { register: char, language: String },
+    /// The system clipboard couldn't be copied to or read
+    Clipboard(String),
+    /// The text on the clipboard isn't valid in the language being edited
+    ClipboardParse { language: String, message: String },
+    /// Trying to change a tree which is read-only (e.g. one side of a diff, or any tree opened
+    /// with `--read-only`)
+    ReadOnly,
+    /// Trying to write a read-only buffer to its file without `:w!`
+    WriteReadOnly { name: String },
+    /// Trying to make a buffer writable (with `:set noreadonly`) when it can't be edited
+    CantMakeWritable { name: String, reason: String },
+
+    /// Trying to repeat the last edit before any edits have been made
+    NothingToRepeat,
+    /// Trying to undo or redo partway through a [transaction](transaction::Transaction)
+    HistoryInTransaction,
+    /// Trying to start selecting siblings at the root, which has no siblings
+    SelectRoot,
+    /// Trying to move the cursor of a selection away from the siblings it started among
+    SelectionAcrossParents,
+    /// Trying to do something whilst siblings are selected which can't act on a selection
+    InvalidInVisualMode { action: String },
+
+    /* SEARCH ERRORS */
+    /// Trying to close the only window
+    CloseLastWindow,
+    /// Trying to fold a node that has no children to hide
+    CannotFold { kind: &'static str },
+    /// Part of a large file couldn't be parsed when it was unfolded
+    Unparsable(String),
+    /// Trying to jump to a mark that hasn't been set
+    NoSuchMark(char),
+
+    /// Trying to move to a search match without having searched for anything
+    NoSearch,
+    /// The search query didn't match any nodes, without looking inside `unparsed` nodes
+    NoMatches { query: String, unparsed: usize },
+    /// Trying to move to a change without comparing the buffer with another one
+    NoDiff,
+    /// Trying to move past the first or last change of a diff
+    NoMoreChanges,
+    /// Trying to move to a problem with the tree when it has none
+    NoErrors,
+    /// Trying to move past the first or last problem with the tree
+    NoMoreErrors,
+
+    /* MACRO ERRORS */
+    /// Trying to replay a macro register that nothing has been recorded into
+    EmptyMacro(char),
+    /// Too many macros were replayed inside one another (usually because a macro replays itself)
+    MacroRecursionLimit { register: char },
+    /// One of the keystrokes of a macro failed, so replaying stopped after `iterations` of the
+    /// requested `count` replays were completed
+    MacroFailed {
+        register: char,
+        iterations: usize,
+        count: usize,
+        error: Box<EditErr>,
+    },
+
+    /* RENDERING ERRORS */
+    /// The tree's display tokens don't indent and dedent in matching pairs, which is a bug in
+    /// the [`Ast`] implementation.  This is only checked in debug builds.
+    InvalidTokens(ast::display_token::TokenStreamError),
+
+    /* FILE ERRORS */
+    /// Trying to save a tree that wasn't loaded from a file, without giving a new path
+    NoFilePath,
+    /// Writing the tree to disk failed (e.g. because of permissions or a full disk)
+    SaveFailed { path: String, message: String },
+    /// Trying to save a buffer whose file another program has changed since it was loaded or
+    /// saved (the user is then asked what to do about it)
+    ChangedOnDisk { name: String },
+    /// The tree can't be written because of the problem at `path` (see
+    /// [`ValidationError::prevents_writing`](ast::ValidationError::prevents_writing))
+    Unwritable { path: String, message: String },
+    /// Writing an export of the tree (with `:export`) failed
+    ExportFailed { path: String, message: String },
+    /// The buffers' language can't be exported in a format (e.g. only JSON has JSON Patches)
+    UnsupportedExport { format: String, language: String },
+    /// `:query` can't be run on trees of a language (see
+    /// [`Language::supports_queries`](crate::language::Language::supports_queries))
+    UnsupportedQuery { language: String },
+    /// Reading or parsing the JSON Schema at `path` (set with `:set schema`) failed
+    SchemaFailed { path: String, message: String },
+    /// Trees of a language can't be checked against a JSON Schema (see
+    /// [`Language::supports_schemas`](crate::language::Language::supports_schemas))
+    UnsupportedSchema { language: String },
+    /// Reading or parsing a file into a new buffer failed
+    LoadFailed { path: String, message: String },
+    /// The file at `path` is written in a different language to the open buffers, so it can't
+    /// be opened alongside them
+    WrongLanguage {
+        path: String,
+        language: String,
+        current: String,
+    },
+    /// The text of the current buffer couldn't be parsed in the language that it was being
+    /// switched to
+    CannotSwitchLanguage { language: String, message: String },
+    /// Sapling can only be suspended whilst it's running in a terminal
+    CannotSuspend { reason: String },
+    /// A shell command given to `:!` couldn't be run
+    ShellFailed { command: String, message: String },
+    /// The command given to `:pipe` failed, or its output couldn't be parsed
+    PipeFailed { command: String, message: String },
+    /// A node of a given kind can't take the cursor's place in its parent
+    InvalidReplacement {
+        kind: &'static str,
+        parent_kind: &'static str,
+    },
 }
 
-impl EditErr {
-    /// Writes an warning message of the encountered error using either `warn!` or `error!`,
-    /// depending on the severity of the error
-    fn log_message(self) {
+impl std::fmt::Display for EditErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            EditErr::Invalid(keys) => log::warn!("Invalid command '{}'", keys),
+            EditErr::Invalid(keys) => write!(f, "Invalid command '{}'", keys),
+            EditErr::InvalidCommand { command, reason } => {
+                write!(f, "Invalid command ':{}': {}", command, reason)
+            }
+            // The first of the buffers is the one being edited, so it only needs naming if
+            // there are others
+            EditErr::UnsavedChanges { buffers } if buffers.len() <= 1 => {
+                write!(f, "No write since last change (add ! to override).")
+            }
+            EditErr::UnsavedChanges { buffers } => write!(
+                f,
+                "No write since last change to {} (add ! to override).",
+                buffers.join(", ")
+            ),
+            EditErr::NoSavedTree => write!(f, "The tree has never been saved."),
+            EditErr::InvalidOption(e) => write!(f, "{}", e),
+            EditErr::InvalidMapping { keys, reason } => {
+                write!(f, "Can't map '{}': {}.", keys, reason)
+            }
+            EditErr::NotMapped(keys) => write!(f, "'{}' isn't mapped to anything.", keys),
             EditErr::MoveToNonexistentChild => {
-                log::warn!("Can't move down if the cursor has no children.")
-            }
-            EditErr::MoveToNonexistentSibling => {
-                log::warn!("Can't move to a non-existent sibling.")
-            }
-            EditErr::MoveToParentOfRoot => log::warn!("Can't move to the parent of the root."),
-            EditErr::MoveToSiblingOfRoot => log::warn!("Can't move to a sibling of the root."),
-            EditErr::NoChangesToUndo => log::warn!("No changes to undo."),
-            EditErr::NoChangesToRedo => log::warn!("No changes to redo."),
-            EditErr::InsertError(e) => log::warn!("{}", e),
-            EditErr::DeleteError(e) => log::warn!("{}", e),
-            EditErr::CharNotANode(c) => log::warn!("'{}' doesn't correspond to any node type.", c),
-            EditErr::CannotBeChild { c, parent_name } => {
-                log::warn!("'{}' cannot be a child of {}", c, parent_name)
-            }
-            EditErr::AddSiblingToRoot => log::warn!("Can't add siblings to the root."),
-            EditErr::DeletingRoot => log::warn!("Can't delete the root."),
+                write!(f, "Can't move down if the cursor has no children.")
+            }
+            EditErr::MoveToNonexistentSibling => write!(f, "Can't move to a non-existent sibling."),
+            EditErr::MoveToParentOfRoot => write!(f, "Can't move to the parent of the root."),
+            EditErr::MoveToSiblingOfRoot => write!(f, "Can't move to a sibling of the root."),
+            EditErr::NoNodeAtPath {
+                path,
+                parent_path,
+                parent_name,
+                index,
+                len,
+            } => write!(
+                f,
+                "There's no node at {}: {} at {} has no child {} (it has {} child(ren)).",
+                path, parent_name, parent_path, index, len
+            ),
+            EditErr::NoChangesToUndo => write!(f, "No changes to undo."),
+            EditErr::NoChangesToRedo => write!(f, "No changes to redo."),
+            EditErr::InsertError(e) => write!(f, "{}", e),
+            EditErr::DeleteError(e) => write!(f, "{}", e),
+            EditErr::MoveError(e) => write!(f, "{}", e),
+            EditErr::CharNotANode(c) => write!(f, "'{}' doesn't correspond to any node type.", c),
+            EditErr::CannotBeChild { c, parent_kind } => {
+                write!(f, "'{}' cannot be a child of {}", c, parent_kind)
+            }
+            EditErr::AddSiblingToRoot => write!(f, "Can't add siblings to the root."),
+            EditErr::DeletingRoot => write!(f, "Can't delete the root."),
+            EditErr::CannotPushChar { c, name } => write!(f, "Can't append '{}' to {}", c, name),
+            EditErr::CannotPopChar { name } => write!(f, "Can't remove a char from {}", name),
+            EditErr::NoText { kind } => write!(f, "{} has no text to type into", kind),
+            EditErr::CannotWrap { c, kind } => write!(f, "Can't wrap {} in '{}'", kind, c),
+            EditErr::TooDeep => write!(
+                f,
+                "Can't nest nodes more than {} levels below the root.",
+                MAX_DEPTH
+            ),
+            EditErr::UnwrapRoot => write!(f, "Can't unwrap the root."),
+            EditErr::NothingToRepeat => write!(f, "There's no edit to repeat yet."),
+            EditErr::SelectRoot => write!(f, "The root has no siblings to select."),
+            EditErr::SelectionAcrossParents => write!(
+                f,
+                "Can't select nodes with different parents (press Esc to stop selecting)."
+            ),
+            EditErr::InvalidInVisualMode { action } => {
+                write!(f, "Can't {} whilst nodes are selected.", action)
+            }
+            EditErr::HistoryInTransaction => {
+                write!(
+                    f,
+                    "Can't move through the undo history in the middle of a change."
+                )
+            }
+            EditErr::CloseLastWindow => {
+                write!(f, "Can't close the last window (use ':q' to quit).")
+            }
+            EditErr::NoSearch => write!(f, "There's no search to find matches of."),
+            EditErr::NoSuchMark(c) => write!(f, "Mark '{}' hasn't been set.", c),
+            EditErr::CannotFold { kind } => {
+                write!(f, "Can't fold {}, since it has no children.", kind)
+            }
+            EditErr::Unparsable(message) => write!(f, "Couldn't parse this node: {}.", message),
+            EditErr::NoMatches { query, unparsed } => write!(
+                f,
+                "Nothing matches '{}'{}.",
+                query,
+                search::unparsed_note(*unparsed)
+            ),
+            EditErr::NoDiff => write!(f, "This buffer isn't being compared with another."),
+            EditErr::NoMoreChanges => write!(f, "There are no more changes."),
+            EditErr::NoErrors => write!(f, "There are no errors."),
+            EditErr::NoMoreErrors => write!(f, "There are no more errors."),
+            EditErr::ReadOnly => write!(f, "The buffer is read-only."),
+            EditErr::WriteReadOnly { name } => {
+                write!(f, "{} is read-only (add ! to write it anyway).", name)
+            }
+            EditErr::CantMakeWritable { name, reason } => {
+                write!(f, "Can't make {} writable: {}.", name, reason)
+            }
+            EditErr::EmptyMacro(c) => write!(f, "Nothing has been recorded into macro '{}'.", c),
+            EditErr::MacroRecursionLimit { register } => write!(
+                f,
+                "Macro '{}' replayed too many macros inside each other.",
+                register
+            ),
+            EditErr::MacroFailed {
+                register,
+                iterations,
+                count,
+                error,
+            } => write!(
+                f,
+                "Macro '{}' stopped after {} of {} replays: {}",
+                register, iterations, count, error
+            ),
+            EditErr::CannotUnwrap { kind } => {
+                write!(f, "Can't unwrap {}, since it doesn't have one child.", kind)
+            }
+            EditErr::InvalidUnwrap {
+                child_kind,
+                parent_kind,
+            } => write!(
+                f,
+                "Can't unwrap, since {} can't be a child of {}",
+                child_kind, parent_kind
+            ),
+            EditErr::EmptyRegister => write!(f, "Nothing has been yanked or cut yet."),
+            EditErr::EmptyNamedRegister(c) => {
+                write!(f, "Nothing has been yanked into register '{}' yet.", c)
+            }
+            EditErr::RegisterLanguage { register, language } => write!(
+                f,
+                "Register '{}' was yanked whilst editing {}, so it can't be pasted here.",
+                register, language
+            ),
+            EditErr::Clipboard(message) => write!(f, "Couldn't use the clipboard: {}.", message),
+            EditErr::ClipboardParse { language, message } => {
+                write!(
+                    f,
+                    "Couldn't parse the clipboard as {}: {}",
+                    language, message
+                )
+            }
+            EditErr::InvalidTokens(e) => write!(f, "Bug in the rendering code: {}", e),
+            EditErr::NoFilePath => write!(
+                f,
+                "No file name to save to (use ':w <path>' to give it one)."
+            ),
+            EditErr::SaveFailed { path, message } => {
+                write!(f, "Couldn't save to {}: {}", path, message)
+            }
+            EditErr::ChangedOnDisk { name } => {
+                write!(f, "{} has changed on disk since it was last read.", name)
+            }
+            EditErr::ExportFailed { path, message } => {
+                write!(f, "Couldn't export to {}: {}", path, message)
+            }
+            EditErr::Unwritable { path, message } => {
+                write!(f, "Can't write the tree, since {} (at {}).", message, path)
+            }
+            EditErr::UnsupportedExport { format, language } => {
+                write!(f, "Can't export {} as '{}'.", language, format)
+            }
+            EditErr::UnsupportedQuery { language } => {
+                write!(f, "Queries can't be run on {} yet.", language)
+            }
+            EditErr::SchemaFailed { path, message } => {
+                write!(f, "Couldn't load the schema {}: {}", path, message)
+            }
+            EditErr::UnsupportedSchema { language } => {
+                write!(f, "{} can't be checked against a JSON Schema.", language)
+            }
+            EditErr::LoadFailed { path, message } => {
+                write!(f, "Couldn't open {}: {}", path, message)
+            }
+            EditErr::WrongLanguage {
+                path,
+                language,
+                current,
+            } => write!(
+                f,
+                "Can't open {}, since it's written in {} but the open buffers are {}.",
+                path, language, current
+            ),
+            EditErr::CannotSwitchLanguage { language, message } => {
+                write!(f, "Can't read the buffer as {}: {}", language, message)
+            }
+            EditErr::CannotSuspend { reason } => write!(f, "Can't suspend Sapling: {}.", reason),
+            EditErr::ShellFailed { command, message } => {
+                write!(f, "Couldn't run '{}': {}.", command, message)
+            }
+            EditErr::PipeFailed { command, message } => {
+                write!(f, "Couldn't pipe through '{}': {}", command, message)
+            }
+            EditErr::InvalidReplacement { kind, parent_kind } => {
+                write!(
+                    f,
+                    "{} can't take the cursor's place in {}.",
+                    kind, parent_kind
+                )
+            }
         }
     }
 }
 
+impl EditErr {
+    /// Writes an warning message of the encountered error using `warn!`
+    fn log_message(self) {
+        log::warn!("{}", self);
+    }
+}
+
 impl From<ast::InsertError> for EditErr {
     fn from(e: ast::InsertError) -> EditErr {
         EditErr::InsertError(e)
     }
 }
 
+impl From<ast::MoveError> for EditErr {
+    fn from(e: ast::MoveError) -> EditErr {
+        EditErr::MoveError(e)
+    }
+}
+
+impl From<OptionError> for EditErr {
+    fn from(e: OptionError) -> EditErr {
+        EditErr::InvalidOption(e)
+    }
+}
+
 impl From<ast::DeleteError> for EditErr {
     fn from(e: ast::DeleteError) -> EditErr {
         EditErr::DeleteError(e)
@@ -177,17 +949,15 @@ impl LogMessage for EditResult {
 /// and every edit is analogous to a Git rebase.
 ///
 /// Therefore, moving back through the history is as simple as reading a different root node from
-/// the `roots` vector, and following its descendants through the DAG of nodes.
+/// the [`EditHistory`], and following its descendants through the DAG of nodes.
 pub struct DAG<'arena, Node: Ast<'arena>> {
     /// The arena in which all the [`Node`]s will be stored
     arena: &'arena Arena<Node>,
-    /// A [`Vec`] containing a reference to the root node at every edit in the undo history.  This
-    /// is required to always have length at least one.
-    root_history: Vec<(&'arena Node, CursorPath)>,
-    /// An index into [`root_history`](DAG::root_history) of the current edit.  This is required to
-    /// be in `0..root_history.len()`.
-    history_index: usize,
+    /// The root node (and cursor location) of every tree in the undo history
+    history: EditHistory<'arena, Node>,
     current_cursor_path: CursorPath,
+    /// If `true`, the cursor can move around the tree but the tree can't be changed
+    read_only: bool,
 }
 
 impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
@@ -195,19 +965,114 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
     pub fn new(arena: &'arena Arena<Node>, root: &'arena Node, cursor_path: CursorPath) -> Self {
         DAG {
             arena,
-            root_history: vec![(root, cursor_path.clone())],
-            history_index: 0,
+            history: EditHistory::new(root, cursor_path.clone()),
+            current_cursor_path: cursor_path,
+            read_only: false,
+        }
+    }
+
+    /// Builds a `DAG` with an existing undo history (e.g. one that was
+    /// [`restore`](EditHistory::restore)d from a saved session), with the cursor at `cursor_path`
+    /// (which must be a valid path in the current tree of the history)
+    pub fn from_history(
+        arena: &'arena Arena<Node>,
+        history: EditHistory<'arena, Node>,
+        cursor_path: CursorPath,
+    ) -> Self {
+        DAG {
+            arena,
+            history,
             current_cursor_path: cursor_path,
+            read_only: false,
         }
     }
 
+    /// Returns the undo history, which contains the root of every version of the tree
+    pub fn history(&self) -> &EditHistory<'arena, Node> {
+        &self.history
+    }
+
+    /// Returns `true` if the tree can't be changed
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Stops the tree from being changed (or allows it again), including by undoing and redoing.
+    /// Moving the cursor and expanding [unparsed](Ast::is_unparsed) nodes still work.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns [`EditErr::ReadOnly`] if the tree is read-only.  Every public method that changes
+    /// the tree checks this before doing anything else, so that edits to a read-only tree are
+    /// all turned away in one place and leave the tree (and cursor) exactly as they were.
+    fn check_writable(&self) -> Result<(), EditErr> {
+        match self.read_only {
+            true => Err(EditErr::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks that the tree can be edited (see [`check_writable`](DAG::check_writable)), and
+    /// remembers where the cursor is in the current state of the history.  Every public method
+    /// that makes an edit calls this first, so that undoing the edit puts the cursor back where
+    /// it was just before the edit (rather than where it was when the state was created).
+    fn start_edit(&mut self) -> Result<(), EditErr> {
+        self.check_writable()?;
+        self.history
+            .set_cursor_path(self.current_cursor_path.clone());
+        Ok(())
+    }
+
     /* NAVIGATION METHODS */
 
     /// Returns a reference to the node that is currently the root of the AST.
     pub fn root(&self) -> &'arena Node {
-        // This indexing shouldn't panic because we require that `self.history_index` is a valid index
-        // into `self.root_history`, and `self.root_history` has at least one element
-        self.root_history[self.history_index].0
+        self.history.root()
+    }
+
+    /// Returns the arena that the nodes of every tree in the undo history are stored in
+    pub fn arena(&self) -> &'arena Arena<Node> {
+        self.arena
+    }
+
+    /// Moves every tree in the undo history into the arena of a [`Compactor`], which is where
+    /// new nodes will be allocated from now on
+    pub fn compact(&mut self, compactor: &mut Compactor<'arena, Node>) {
+        self.history.compact(compactor);
+        self.arena = compactor.arena();
+    }
+
+    /// Returns the path from the root to the cursor
+    pub fn cursor_path(&self) -> &CursorPath {
+        &self.current_cursor_path
+    }
+
+    /// Moves the cursor to a given path, which must be a valid path in the current tree
+    pub fn set_cursor_path(&mut self, path: CursorPath) {
+        self.current_cursor_path = path;
+    }
+
+    /// Moves the cursor to a given path, explaining which index is out of range if the path
+    /// doesn't exist in the current tree
+    pub fn goto(&mut self, path: CursorPath) -> EditResult {
+        let (node, steps) = path.resolve_longest(self.root());
+        if steps < path.depth() {
+            let parent_path = CursorPath::from_vec(path.iter().copied().take(steps).collect());
+            return Err(EditErr::NoNodeAtPath {
+                path: path.to_string(),
+                parent_path: parent_path.to_string(),
+                parent_name: node.display_name(),
+                index: path.iter().copied().nth(steps).unwrap(),
+                len: node.children().len(),
+            });
+        }
+        let success = EditSuccess::Goto {
+            path: path.to_string(),
+            name: node.display_name(),
+        };
+        self.current_cursor_path = path;
+        Ok(success)
     }
 
     /// Returns the cursor node and its direct parent (if such a parent exists)
@@ -260,63 +1125,109 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
                 }
                 *last_index += 1;
             }
+            Direction::Root => {
+                self.current_cursor_path = CursorPath::root();
+            }
+            Direction::LastLeaf => {
+                let mut path = CursorPath::root();
+                let mut node = self.root();
+                while let Some(&last_child) = node.children().last() {
+                    path.push(node.children().len() - 1);
+                    node = last_child;
+                }
+                self.current_cursor_path = path;
+            }
         }
         Ok(EditSuccess::Move(direction))
     }
 
     /* HISTORY METHODS */
 
+    /// Follow the behaviour of other text editors and update the location of the cursor with its
+    /// location in the snapshot we have just moved to.  The snapshot's cursor path always points
+    /// to a node in that snapshot's tree, so the cursor can't be left dangling.
+    fn restore_cursor_path(&mut self) {
+        self.current_cursor_path
+            .clone_from(self.history.cursor_path());
+    }
+
     /// Move one step back in the tree history
     pub fn undo(&mut self) -> EditResult {
-        // Early return if there are no changes to undo
-        if self.history_index == 0 {
-            return Err(EditErr::NoChangesToUndo);
-        }
-        // Move the history index back by one to perform the undo
-        self.history_index -= 1;
-        // Follow the behaviour of other text editors and update the location of the cursor
-        // with its location in the snapshot we are going back to
-        self.current_cursor_path
-            .clone_from(&self.root_history[self.history_index].1);
+        self.check_writable()?;
+        self.history.undo().ok_or(EditErr::NoChangesToUndo)?;
+        self.restore_cursor_path();
         Ok(EditSuccess::Undo)
     }
 
-    /// Move one step forward in the tree history
+    /// Move one step forward in the tree history, following the branch that was most recently
+    /// undone
     pub fn redo(&mut self) -> EditResult {
-        // Early return if there are no changes to redo
-        if self.history_index >= self.root_history.len() - 1 {
-            return Err(EditErr::NoChangesToRedo);
-        }
-        // Move the history index forward by one to perform the redo
-        self.history_index += 1;
-        // Follow the behaviour of other text editors and update the location of the cursor
-        // with its location in the snapshot we are going back to
-        self.current_cursor_path
-            .clone_from(&self.root_history[self.history_index].1);
+        self.check_writable()?;
+        self.history.redo().ok_or(EditErr::NoChangesToRedo)?;
+        self.restore_cursor_path();
         Ok(EditSuccess::Redo)
     }
 
+    /// Move to the tree that was created before the current one, even if it's on a different
+    /// branch of the history
+    pub fn older(&mut self) -> EditResult {
+        self.check_writable()?;
+        self.history.older().ok_or(EditErr::NoChangesToUndo)?;
+        self.restore_cursor_path();
+        Ok(EditSuccess::Older)
+    }
+
+    /// Move to the tree that was created after the current one, even if it's on a different
+    /// branch of the history
+    pub fn newer(&mut self) -> EditResult {
+        self.check_writable()?;
+        self.history.newer().ok_or(EditErr::NoChangesToRedo)?;
+        self.restore_cursor_path();
+        Ok(EditSuccess::Newer)
+    }
+
     /* EDITING METHODS */
 
-    /// Utility function to finish an edit.  This handles removing any redo history, and cloning
-    /// the nodes that are parents of the node that changed.
+    /// Replaces the whole tree with the one under `root` as a single change, which can be undone
+    /// (e.g. when the tree is read again from its file).  The cursor goes as far down its old
+    /// path as the new tree allows.
+    pub fn replace_root(&mut self, root: &'arena Node) -> Result<(), EditErr> {
+        self.start_edit()?;
+        let (_, steps) = self.current_cursor_path.resolve_longest(root);
+        while self.current_cursor_path.depth() > steps {
+            self.current_cursor_path.pop();
+        }
+        self.push_history(root);
+        Ok(())
+    }
+
+    /// Utility function to build the tree produced by an edit, by cloning the nodes that are
+    /// parents of the node that changed.  Returns the root of the new tree, which should be
+    /// passed to [`push_history`](DAG::push_history) once the cursor has been moved to its final
+    /// location.
     fn finish_edit(
         &mut self,
         nodes_to_clone: &[&'arena Node],
         steps_above_cursor: usize,
         new_node: Node,
-    ) {
-        // Remove future trees from the history vector so that the currently 'checked-out' tree is
-        // the most recent tree in the history.
-        while self.history_index < self.root_history.len() - 1 {
-            // TODO: Deallocate the tree so that we don't get a 'memory leak'
-            self.root_history.pop();
-        }
+    ) -> &'arena Node {
+        let new_node = self.arena.alloc(new_node);
+        self.finish_edit_with_ref(nodes_to_clone, steps_above_cursor, new_node)
+    }
+
+    /// The same as [`finish_edit`](DAG::finish_edit), but for a node that is already in the
+    /// arena (e.g. a node taken from elsewhere in the tree).
+    fn finish_edit_with_ref(
+        &mut self,
+        nodes_to_clone: &[&'arena Node],
+        steps_above_cursor: usize,
+        new_node: &'arena Node,
+    ) -> &'arena Node {
         // Because AST nodes are immutable, we make changes to nodes by entirely cloning the path
         // down to the node under the cursor.  We do this starting at the node under the cursor and
         // work our way up parent by parent until we reach the root of the tree.  At that point,
         // this node becomes the root of the new tree.
-        let mut node = self.arena.alloc(new_node);
+        let mut node = new_node;
         // SANITY CHECK: Assert that items_to_clone and self.cursor_path have the same length once
         // steps_above_cursor have been taken off - i.e. that we aren't losing any information by
         // zipping the two things together
@@ -339,12 +1250,19 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
             cloned_node.children_mut()[*child_index] = node;
             node = self.arena.alloc(cloned_node);
         }
-        // At this point, `node` contains a reference to the root of the new tree, so we just add
-        // this to the history, along with the cursor path.
-        self.root_history
-            .push((node, self.current_cursor_path.clone()));
-        // Move the history index on by one so that we are pointing at the latest change
-        self.history_index = self.root_history.len() - 1;
+        // At this point, `node` contains a reference to the root of the new tree
+        node
+    }
+
+    /// Adds a new tree to the history as a child of the current tree, along with the current
+    /// cursor path.  Any changes that have been undone are kept on their own branch of the
+    /// history, rather than being thrown away.
+    fn push_history(&mut self, root: &'arena Node) {
+        log::debug!(
+            "Committed an edit, leaving the cursor at {}",
+            self.current_cursor_path
+        );
+        self.history.push(root, self.current_cursor_path.clone());
     }
 
     /// Replaces the current cursor with a node represented by `c`
@@ -358,14 +1276,43 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         if !cursor.is_replace_char(c) {
             return Err(EditErr::CannotBeChild {
                 c,
-                // TODO: Get the parent name
-                parent_name: "<unkown>".to_string(),
+                parent_kind: self
+                    .cursor_and_parent()
+                    .1
+                    .map_or("<unknown>", |parent| parent.node_kind()),
             });
         }
 
         /* PERFORM THE ACTION */
 
         let new_node = cursor.from_char(c).ok_or(EditErr::CharNotANode(c))?;
+        // The new node also has to be able to take the cursor's place (e.g. the key of a JSON
+        // field can't be replaced with a number)
+        if let (Some(parent), Some(&index)) =
+            (self.cursor_and_parent().1, self.cursor_path().iter().last())
+        {
+            if !parent.is_valid_child(index, &new_node) {
+                return Err(EditErr::CannotBeChild {
+                    c,
+                    parent_kind: parent.node_kind(),
+                });
+            }
+        }
+        let name = self.replace_cursor_with(new_node)?;
+        Ok(EditSuccess::Replace { c, name })
+    }
+
+    /// Utility function to replace the node under the cursor with a given node, and finish the
+    /// edit.  Returns the display name of the new node.
+    fn replace_cursor_with(&mut self, new_node: Node) -> Result<String, EditErr> {
+        let new_node = self.arena.alloc(new_node);
+        self.replace_cursor_with_ref(new_node)
+    }
+
+    /// The same as [`replace_cursor_with`](DAG::replace_cursor_with), but for a node that is
+    /// already in the arena.
+    fn replace_cursor_with_ref(&mut self, new_node: &'arena Node) -> Result<String, EditErr> {
+        check_depth(new_node, self.current_cursor_path.depth())?;
         // Generate a vec of pointers to the nodes that we will have to clone.  We have to store
         // this as a vec because the iterator that produces them (cursor_path::NodeIter) can only
         // yield values from the root downwards, whereas we need the nodes in the opposite order.
@@ -373,15 +1320,165 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         // The last value of nodes_to_clone is the node under the cursor, which we do not need to
         // clone, so we pop that reference.
         assert!(nodes_to_clone.pop().is_some());
-
-        /* FINISH THE EDIT AND RETURN SUCCESS */
-
         // Store the new_node's display name before it's consumed by `finish_edit`
         let new_node_name = new_node.display_name();
-        self.finish_edit(&nodes_to_clone, 0, new_node);
-        Ok(EditSuccess::Replace {
+        let new_root = self.finish_edit_with_ref(&nodes_to_clone, 0, new_node);
+        self.push_history(new_root);
+        Ok(new_node_name)
+    }
+
+    /// Replaces the node under the cursor with the nodes parsed from its text, if it's a node
+    /// that hasn't been [parsed](Ast::parse_unparsed) yet.  This doesn't change the text of the
+    /// tree, so the current state of the tree is replaced rather than a new one being added to
+    /// the undo history.  Returns the new root, or `None` if the cursor isn't unparsed.
+    pub fn parse_cursor(&mut self) -> Option<Result<&'arena Node, EditErr>> {
+        let parsed = match self.cursor().parse_unparsed(self.arena)? {
+            Ok(node) => node,
+            Err(message) => return Some(Err(EditErr::Unparsable(message))),
+        };
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        assert!(nodes_to_clone.pop().is_some());
+        let new_root = self.finish_edit_with_ref(&nodes_to_clone, 0, parsed);
+        self.history.replace_root(new_root);
+        Some(Ok(new_root))
+    }
+
+    /// Replaces the node under the cursor with a new node (represented by `c`) which contains the
+    /// old cursor as its only child.  The cursor stays at the same path, so ends up on the new
+    /// parent.
+    fn wrap_cursor(&mut self, c: char) -> EditResult {
+        let cursor = self.cursor();
+        let wrapper = cursor
+            .wrap_in(c, self.arena)
+            .ok_or_else(|| EditErr::CannotWrap {
+                c,
+                kind: cursor.node_kind(),
+            })?;
+        let name = self.replace_cursor_with_ref(wrapper)?;
+        Ok(EditSuccess::Wrap { c, name })
+    }
+
+    /// Replaces the node under the cursor with its child at `index`, reusing that child rather
+    /// than allocating a copy of it.  The cursor stays at the same path, so ends up on the child.
+    pub fn unwrap_child(&mut self, index: usize) -> EditResult {
+        self.start_edit()?;
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        let cursor = nodes_to_clone.pop().unwrap();
+        let parent = nodes_to_clone.last().ok_or(EditErr::UnwrapRoot)?;
+        let child = *cursor
+            .children()
+            .get(index)
+            .ok_or_else(|| EditErr::CannotUnwrap {
+                kind: cursor.node_kind(),
+            })?;
+        // Check that the child can take the place of the cursor
+        let cursor_index = *self.current_cursor_path.last_mut().unwrap();
+        if !parent.is_valid_child(cursor_index, child) {
+            return Err(EditErr::InvalidUnwrap {
+                child_kind: child.node_kind(),
+                parent_kind: parent.node_kind(),
+            });
+        }
+        let name = cursor.display_name();
+        let new_root = self.finish_edit_with_ref(&nodes_to_clone, 0, child);
+        self.push_history(new_root);
+        Ok(EditSuccess::Unwrap {
+            name,
+            child_name: child.display_name(),
+        })
+    }
+
+    /// Replaces the node under the cursor with its child given by
+    /// [`Ast::unwrap_index`](crate::ast::Ast::unwrap_index)
+    fn unwrap_cursor(&mut self) -> EditResult {
+        let cursor = self.cursor();
+        let index = cursor.unwrap_index().ok_or_else(|| EditErr::CannotUnwrap {
+            kind: cursor.node_kind(),
+        })?;
+        self.unwrap_child(index)
+    }
+
+    /// Appends a [`char`] to the textual contents of the cursor (e.g. adding a digit to a number)
+    fn push_char(&mut self, c: char) -> EditResult {
+        let cursor = self.cursor();
+        let new_node = cursor.push_char(c).ok_or_else(|| EditErr::CannotPushChar {
             c,
-            name: new_node_name,
+            name: cursor.display_name(),
+        })?;
+        let name = self.replace_cursor_with(new_node)?;
+        Ok(EditSuccess::PushChar { c, name })
+    }
+
+    /// Builds the tree that would be made by replacing the textual contents of the cursor with
+    /// `text`, without adding it to the history (e.g. to show a string whilst it's being typed).
+    /// Returns `None` if the cursor has no textual contents.
+    pub fn preview_cursor_text(&mut self, text: &str) -> Option<&'arena Node> {
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        let new_node = nodes_to_clone.pop().unwrap().with_text(text)?;
+        Some(self.finish_edit(&nodes_to_clone, 0, new_node))
+    }
+
+    /// Replaces the textual contents of the cursor with `text`, as a single change.  If the text
+    /// hasn't changed, then the history is left untouched.
+    pub fn set_cursor_text(&mut self, text: &str) -> EditResult {
+        self.start_edit()?;
+        let cursor = self.cursor();
+        if cursor.text() == Some(text) {
+            return Ok(EditSuccess::SetText {
+                name: cursor.display_name(),
+            });
+        }
+        let new_node = cursor.with_text(text).ok_or_else(|| EditErr::NoText {
+            kind: cursor.node_kind(),
+        })?;
+        let name = self.replace_cursor_with(new_node)?;
+        Ok(EditSuccess::SetText { name })
+    }
+
+    /// Removes the last [`char`] from the textual contents of the cursor
+    fn pop_char(&mut self) -> EditResult {
+        let cursor = self.cursor();
+        let new_node = cursor.pop_char().ok_or_else(|| EditErr::CannotPopChar {
+            name: cursor.display_name(),
+        })?;
+        let name = self.replace_cursor_with(new_node)?;
+        Ok(EditSuccess::PopChar { name })
+    }
+
+    /// Appends `c` to (or if it's `None`, removes the last char from) the textual contents of
+    /// the node at `path` instead of the cursor, leaving the cursor where it is.  This is how the
+    /// editor carries on typing into a number that's just been inserted next to the cursor.
+    pub fn type_at(&mut self, path: CursorPath, c: Option<char>) -> EditResult {
+        self.start_edit()?;
+        let cursor_path = std::mem::replace(&mut self.current_cursor_path, path);
+        let result = match c {
+            Some(c) => self.push_char(c),
+            None => self.pop_char(),
+        };
+        self.current_cursor_path = cursor_path;
+        result
+    }
+
+    /// Replaces every occurrence of `pattern` with `replacement` in the text of every node in
+    /// `paths` (usually found with [`replace::candidates`]), as a single change.  If no nodes are
+    /// changed, then the history is left untouched.
+    pub fn replace_text(
+        &mut self,
+        paths: &[CursorPath],
+        pattern: &str,
+        replacement: &str,
+    ) -> EditResult {
+        self.start_edit()?;
+        let (new_root, count) =
+            replace::replace_at(self.root(), self.arena, paths, pattern, replacement);
+        if count > 0 {
+            self.push_history(new_root);
+        }
+        Ok(EditSuccess::ReplaceText {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            count,
+            unparsed: search::unparsed_count(self.root()),
         })
     }
 
@@ -397,7 +1494,7 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         if !cursor.is_insert_char(c) {
             return Err(EditErr::CannotBeChild {
                 c,
-                parent_name: cursor.display_name(),
+                parent_kind: cursor.node_kind(),
             });
         }
 
@@ -406,6 +1503,7 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         let new_node = self
             .arena
             .alloc(cursor.from_char(c).ok_or(EditErr::CharNotANode(c))?);
+        check_depth(new_node, self.current_cursor_path.depth() + 1)?;
         // Generate a vec of pointers to the nodes that we will have to clone.  We have to store
         // this as a vec because the iterator that produces them (cursor_path::NodeIter) can only
         // yield values from the root downwards, whereas we need the nodes in the opposite order.
@@ -421,7 +1519,8 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
 
         /* FINISH THE EDIT AND RETURN SUCCESS */
 
-        self.finish_edit(&nodes_to_clone, 0, cloned_cursor);
+        let new_root = self.finish_edit(&nodes_to_clone, 0, cloned_cursor);
+        self.push_history(new_root);
         Ok(EditSuccess::InsertChild {
             c,
             name: new_node_name,
@@ -443,12 +1542,60 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         if !parent.is_insert_char(c) {
             return Err(EditErr::CannotBeChild {
                 c,
-                parent_name: parent.display_name(),
+                parent_kind: parent.node_kind(),
             });
         }
 
         /* PERFORM THE INSERTION */
 
+        // Create the new child node according to the given char.
+        let new_child_node = self
+            .arena
+            .alloc(parent.from_char(c).ok_or(EditErr::CharNotANode(c))?);
+        let name = self.insert_node_next_to_cursor(new_child_node, side)?;
+        Ok(EditSuccess::InsertNextToCursor { side, c, name })
+    }
+
+    /// Replaces the node under the cursor with `node`, which is already in the arena (e.g. the
+    /// tree parsed from the output of `:pipe`), as a single change.  `node` has to be able to
+    /// take the cursor's place.
+    pub fn replace_cursor_with_node(&mut self, node: &'arena Node) -> Result<String, EditErr> {
+        self.start_edit()?;
+        if let (Some(parent), Some(&index)) =
+            (self.cursor_and_parent().1, self.cursor_path().iter().last())
+        {
+            if !parent.is_valid_child(index, node) {
+                return Err(EditErr::InvalidReplacement {
+                    kind: node.node_kind(),
+                    parent_kind: parent.node_kind(),
+                });
+            }
+        }
+        self.replace_cursor_with_ref(node)
+    }
+
+    /// Inserts a deep copy of `node` next to the cursor (e.g. when pasting a subtree that has
+    /// been yanked).  The copy means that the pasted nodes are never shared with the original
+    /// nodes.
+    pub fn paste_next_to_cursor(&mut self, node: &'arena Node, side: Side) -> EditResult {
+        self.start_edit()?;
+        let new_node = node.clone_into_arena(self.arena);
+        let name = self.insert_node_next_to_cursor(new_node, side)?;
+        Ok(EditSuccess::Paste {
+            side,
+            name,
+            count: 1,
+        })
+    }
+
+    /// Utility function to insert an already allocated node as a sibling of the cursor, and
+    /// finish the edit.  Returns the display name of the new node.
+    fn insert_node_next_to_cursor(
+        &mut self,
+        new_child_node: &'arena Node,
+        side: Side,
+    ) -> Result<String, EditErr> {
+        check_depth(new_child_node, self.current_cursor_path.depth())?;
         // Generate a vec of pointers to the nodes that we will have to clone.  We have to store
         // this as a vec because the iterator that produces them (cursor_path::NodeIter) can only
         // yield values from the root downwards, whereas we need the nodes in the opposite order.
@@ -456,6 +1603,10 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         // Pop the cursor, because it will be unchanged.  The only part of this that we need is
         // the cursor's index.
         assert!(nodes_to_clone.pop().is_some());
+        // The cursor can't have siblings if it's the root
+        if nodes_to_clone.is_empty() {
+            return Err(EditErr::AddSiblingToRoot);
+        }
         // Find the index of the cursor, so that we know where to insert.  We can unwrap, because
         // if we were at the root, then we'd early return from the if statement above
         let cursor_sibling_index = *self.current_cursor_path.last_mut().unwrap();
@@ -464,32 +1615,25 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
                 Side::Prev => 0,
                 Side::Next => 1,
             };
-        // Clone the node that currently is the cursor, and add the new child to the end of its
-        // children.  Unwrapping here is fine, because `cursor_path::NodeIter` will always
-        // return one value.
+        // Clone the parent of the cursor, and add the new child next to the cursor.  Unwrapping
+        // here is fine, because we've just checked that `nodes_to_clone` isn't empty.
         let mut cloned_parent = nodes_to_clone.pop().unwrap().clone();
-        // Create the new child node according to the given char.
-        let new_child_node = self
-            .arena
-            .alloc(cloned_parent.from_char(c).ok_or(EditErr::CharNotANode(c))?);
         // Store the new_node's display name before it's consumed by `insert_child`
         let new_node_name = new_child_node.display_name();
-        // Add the new child to the children of the cloned cursor
+        // Add the new child to the children of the cloned parent
         cloned_parent.insert_child(new_child_node, self.arena, insert_index)?;
 
-        /* FINISH THE EDIT AND RETURN SUCCESS */
+        /* FINISH THE EDIT */
 
-        // Finish the edit and update the history
-        self.finish_edit(&nodes_to_clone, 1, cloned_parent);
-        // Return the success
-        Ok(EditSuccess::InsertNextToCursor {
-            side,
-            c,
-            name: new_node_name,
-        })
+        let new_root = self.finish_edit(&nodes_to_clone, 1, cloned_parent);
+        self.push_history(new_root);
+        Ok(new_node_name)
     }
 
-    fn delete_cursor(&mut self) -> EditResult {
+    /// Deletes `count` consecutive siblings, starting at the cursor (or as many siblings as there
+    /// are after the cursor, if there are fewer).  This is a single edit, so either all of the
+    /// siblings are deleted or none of them are.
+    fn delete_cursor(&mut self, count: usize) -> EditResult {
         /* CHECK VALIDITY OF ARGUMENTS */
 
         // Generate a vec of pointers to the nodes that we will have to clone.  We have to store
@@ -511,38 +1655,155 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
         // if we were at the root, then we'd early return from the if statement above.
         let cursor_sibling_index = *self.current_cursor_path.last_mut().unwrap();
         let mut cloned_parent = nodes_to_clone.pop().unwrap().clone();
-        cloned_parent.delete_child(cursor_sibling_index)?;
+        let count = count.min(cloned_parent.children().len() - cursor_sibling_index);
+        // If this fails then `cloned_parent` is thrown away, so the tree is left unchanged
+        cloned_parent.delete_children(cursor_sibling_index..cursor_sibling_index + count)?;
         // Cache this so that we can
         let new_parents_child_count = cloned_parent.children().len();
 
         /* FINISH THE EDIT AND RETURN SUCCESS */
 
-        self.finish_edit(&nodes_to_clone, 1, cloned_parent);
+        let new_root = self.finish_edit(&nodes_to_clone, 1, cloned_parent);
 
         /* MOVE THE CURSOR TO THE NEAREST VALID NODE */
 
         // IMPORTANTLY, we move the cursor **AFTER** calling `self.finish_edit`, because
-        // `self.finish_edit` reads the cursor path
+        // `self.finish_edit` reads the cursor path.  However, we must add the new tree to the
+        // history **AFTER** moving the cursor, so that the cursor path stored in the history
+        // doesn't point to the deleted node.
 
         // If we remove the only child of a node then we move the cursor up
         if new_parents_child_count == 0 {
             self.current_cursor_path.pop();
         } else {
-            // If we deleted the last child of a node (and this isn't the last child), we move
-            // the cursor back by one
-            if cursor_sibling_index == new_parents_child_count {
+            // If we deleted the last children of a node (and these aren't the only children), we
+            // move the cursor to the new last child
+            if cursor_sibling_index >= new_parents_child_count {
                 // We can unwrap here because we know we aren't removing the root
-                *self.current_cursor_path.last_mut().unwrap() -= 1;
+                *self.current_cursor_path.last_mut().unwrap() = new_parents_child_count - 1;
             }
         }
+        self.push_history(new_root);
         Ok(EditSuccess::Delete {
             name: deleted_node_name,
+            count,
         })
     }
 
+    /// Moves the node under the cursor `count` places through its siblings (towards the first
+    /// sibling if `side` is [`Side::Prev`]), keeping the cursor on the moved node.  The node
+    /// stops at its first or last sibling, so moving it past either end of its siblings only
+    /// moves it as far as that end (and a node that's already there isn't moved at all).
+    fn move_cursor_node(&mut self, side: Side, count: usize) -> EditResult {
+        let mut nodes_to_clone: Vec<_> = self.current_cursor_path.node_iter(self.root()).collect();
+        // Pop the cursor, because it will be unchanged
+        let name = nodes_to_clone.pop().unwrap().display_name();
+        if nodes_to_clone.is_empty() {
+            return Err(EditErr::MoveToSiblingOfRoot);
+        }
+        let index = *self.current_cursor_path.last_mut().unwrap();
+        let mut cloned_parent = nodes_to_clone.pop().unwrap().clone();
+        let last_index = cloned_parent.children().len() - 1;
+        let new_index = match side {
+            Side::Prev => index.saturating_sub(count),
+            Side::Next => index.saturating_add(count).min(last_index),
+        };
+        // This still checks that the parent's children can be moved, even if the node is already
+        // at the end of its siblings
+        cloned_parent.move_child(index, new_index as isize)?;
+        let count = index.abs_diff(new_index);
+        if count == 0 {
+            return Ok(EditSuccess::MoveNode { side, count, name });
+        }
+        let new_root = self.finish_edit(&nodes_to_clone, 1, cloned_parent);
+        // Move the cursor so that it follows the moved node
+        *self.current_cursor_path.last_mut().unwrap() = new_index;
+        self.push_history(new_root);
+        Ok(EditSuccess::MoveNode { side, count, name })
+    }
+
+    /// Execute an [`Action`] that was preceded by a count (e.g. the `3` in `3x`).  Deletions remove
+    /// `count` siblings at once and moves of the cursor's node go `count` times as far, whereas
+    /// most other actions are simply repeated `count` times (stopping at the first error).  Other
+    /// edits are repeated as one [transaction](DAG::transact), so that either all of the repeats
+    /// happen (and are undone together) or none of them do.  Actions that would have no further
+    /// effect when repeated are only executed once.
+    pub fn execute_action_with_count(
+        &mut self,
+        action: Action,
+        count: usize,
+    ) -> (bool, EditResult) {
+        if action.is_edit() {
+            if let Err(e) = self.start_edit() {
+                return (false, Err(e));
+            }
+        }
+        match action {
+            Action::Delete => (false, self.delete_cursor(count)),
+            Action::MoveNode {
+                side,
+                count: places,
+            } => self.execute_action(Action::MoveNode {
+                side,
+                count: places * count,
+            }),
+            Action::Undefined(_)
+            | Action::Quit
+            | Action::Replace(_)
+            | Action::Save
+            | Action::SaveAndQuit
+            | Action::ToggleDebugHighlighting
+            | Action::NextTheme
+            | Action::Suspend
+            | Action::Yank
+            | Action::StartRecording(_)
+            | Action::StopRecording
+            | Action::Repeat
+            | Action::StartSearch
+            | Action::StartVisual
+            | Action::StartCommand
+            | Action::StartInsert
+            | Action::SetMark(_)
+            | Action::JumpToMark(_)
+            | Action::ToggleFold
+            | Action::ScrollHalfPage(_)
+            | Action::CentreCursor
+            | Action::ToggleLogView
+            | Action::ShowHelp
+            | Action::SplitWindow(_)
+            | Action::CycleWindow
+            | Action::CloseWindow => self.execute_action(action),
+            _ if action.is_edit() => (
+                false,
+                self.transact(|tx| {
+                    let mut result = tx.execute_action(action.clone())?;
+                    for _ in 1..count {
+                        result = tx.execute_action(action.clone())?;
+                    }
+                    Ok(result)
+                }),
+            ),
+            _ => {
+                let mut result = self.execute_action(action.clone());
+                for _ in 1..count {
+                    if result.1.is_err() {
+                        break;
+                    }
+                    result = self.execute_action(action.clone());
+                }
+                result
+            }
+        }
+    }
+
     /// Execute an [`Action`] generated by user's keystrokes.  Returns `true` if the user executed
     /// [`Action::Quit`], false otherwise
     pub fn execute_action(&mut self, action: Action) -> (bool, EditResult) {
+        if action.is_edit() {
+            if let Err(e) = self.start_edit() {
+                return (false, Err(e));
+            }
+        }
         let mut should_quit = false;
         // Respond to the action
         let result = match action {
@@ -551,6 +1812,8 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
             // History keystrokes
             Action::Undo => self.undo(),
             Action::Redo => self.redo(),
+            Action::Older => self.older(),
+            Action::Newer => self.newer(),
             // Move keystroke
             Action::MoveCursor(direction) => self.move_cursor(direction),
             // Edit keystrokes
@@ -558,7 +1821,54 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
             Action::InsertChild(c) => self.insert_child(c),
             Action::InsertBefore(c) => self.insert_next_to_cursor(c, Side::Prev),
             Action::InsertAfter(c) => self.insert_next_to_cursor(c, Side::Next),
-            Action::Delete => self.delete_cursor(),
+            Action::Delete => self.delete_cursor(1),
+            Action::Wrap(c) => self.wrap_cursor(c),
+            Action::Unwrap => self.unwrap_cursor(),
+            Action::MoveNode { side, count } => self.move_cursor_node(side, count),
+            Action::PushChar(c) => self.push_char(c),
+            Action::PopChar => self.pop_char(),
+            // Saving requires knowing where the tree came from, so is handled by the `Editor`
+            // before the action reaches the `DAG`.  If we do get sent a save action, then there's
+            // nowhere for the tree to be saved to.
+            Action::Save | Action::SaveAndQuit => Err(EditErr::NoFilePath),
+            // Likewise, the `DAG` doesn't render anything so can't change how it's rendered
+            Action::ToggleDebugHighlighting => Err(EditErr::Invalid("<C-t>".to_string())),
+            Action::NextTheme => Err(EditErr::Invalid("T".to_string())),
+            Action::Suspend => Err(EditErr::Invalid("<C-z>".to_string())),
+            Action::ToggleFold => Err(EditErr::Invalid("za".to_string())),
+            Action::ScrollHalfPage(Side::Next) => Err(EditErr::Invalid("<C-d>".to_string())),
+            Action::ScrollHalfPage(Side::Prev) => Err(EditErr::Invalid("<C-u>".to_string())),
+            Action::CentreCursor => Err(EditErr::Invalid("zz".to_string())),
+            Action::ToggleLogView => Err(EditErr::Invalid("L".to_string())),
+            Action::ShowHelp => Err(EditErr::Invalid("?".to_string())),
+            Action::SplitWindow(Split::Horizontal) => Err(EditErr::Invalid("<C-w>s".to_string())),
+            Action::SplitWindow(Split::Vertical) => Err(EditErr::Invalid("<C-w>v".to_string())),
+            Action::CycleWindow => Err(EditErr::Invalid("<C-w>w".to_string())),
+            Action::CloseWindow => Err(EditErr::Invalid("<C-w>q".to_string())),
+            // The register is owned by the `Editor`, so the `DAG` has nowhere to yank to and
+            // nothing to paste
+            Action::Yank => Err(EditErr::Invalid("y".to_string())),
+            Action::PasteBefore | Action::PasteAfter => Err(EditErr::EmptyRegister),
+            // Macros are made of keystrokes, which only the `Editor` knows about
+            Action::StartRecording(c) | Action::Replay(c) => Err(EditErr::EmptyMacro(c)),
+            Action::StopRecording => Err(EditErr::Invalid("q".to_string())),
+            // The last edit is remembered by the `Editor`
+            Action::Repeat => Err(EditErr::NothingToRepeat),
+            // Searching needs a prompt, which is part of the `Editor`
+            Action::StartSearch => Err(EditErr::Invalid("/".to_string())),
+            Action::StartVisual => Err(EditErr::Invalid("v".to_string())),
+            Action::SearchMatch(_) => Err(EditErr::NoSearch),
+            // Diffs compare buffers, which belong to the `Editor`
+            Action::MoveToChange(_) => Err(EditErr::NoDiff),
+            // Problems are found by the `Validation` of the buffer, which the tree doesn't have
+            Action::MoveToError(_) => Err(EditErr::NoErrors),
+            // Commands are also typed into a prompt
+            Action::StartCommand => Err(EditErr::Invalid(":".to_string())),
+            // Likewise, text is typed into the `Editor` and only reaches the tree once it's done
+            Action::StartInsert => Err(EditErr::Invalid("i".to_string())),
+            // Marks are stored by the `Editor`
+            Action::SetMark(c) => Err(EditErr::Invalid(format!("m{}", c))),
+            Action::JumpToMark(c) => Err(EditErr::NoSuchMark(c)),
             // Quit Sapling
             Action::Quit => {
                 should_quit = true;
@@ -583,11 +1893,26 @@ impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
     }
 }
 
+/// Returns [`EditErr::TooDeep`] if putting `node` `depth` steps below the root would leave any of
+/// its descendants more than [`MAX_DEPTH`] steps below the root.  This walks the tree with a
+/// stack rather than recursing, so that it can't overflow the stack itself.
+fn check_depth<'arena, Node: Ast<'arena>>(node: &'arena Node, depth: usize) -> Result<(), EditErr> {
+    let mut stack = vec![(node, depth)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth > MAX_DEPTH {
+            return Err(EditErr::TooDeep);
+        }
+        stack.extend(node.children().iter().map(|child| (*child, depth + 1)));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod integration {
-    use super::{cursor_path::CursorPath, EditSuccess, DAG};
+    use super::{cursor_path::CursorPath, Direction, EditErr, EditSuccess, Side, DAG};
     use crate::arena::Arena;
     use crate::ast::test_json::TestJSON as J;
+    use crate::ast::{Ast, DeleteError, InsertError};
     use crate::editor::normal_mode::Action;
 
     #[test]
@@ -625,4 +1950,838 @@ mod integration {
         assert_eq!(expected_tree, editable_tree.root());
         assert_eq!(expected_cursor_location, editable_tree.current_cursor_path);
     }
+
+    /// Runs a single action on a given tree, and checks that the resulting tree and cursor are
+    /// what we expect.
+    fn check_action(
+        start_tree: J,
+        start_cursor_location: Vec<usize>,
+        action: Action,
+        expected_tree: J,
+        expected_cursor_location: Vec<usize>,
+    ) {
+        let arena = Arena::new();
+        let root = start_tree.add_to_arena(&arena);
+        let mut editable_tree = DAG::new(&arena, root, CursorPath::from_vec(start_cursor_location));
+        let (should_quit, result) = editable_tree.execute_action(action);
+        assert!(!should_quit);
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(expected_tree, editable_tree.root());
+        assert_eq!(
+            CursorPath::from_vec(expected_cursor_location),
+            editable_tree.current_cursor_path
+        );
+    }
+
+    #[test]
+    fn number_editing() {
+        // Digits create numbers
+        check_action(
+            J::Array(vec![J::True]),
+            vec![0],
+            Action::Replace('5'),
+            J::Array(vec![J::Number("5".to_string())]),
+            vec![0],
+        );
+        check_action(
+            J::Array(vec![]),
+            vec![],
+            Action::InsertChild('0'),
+            J::Array(vec![J::Number("0".to_string())]),
+            vec![],
+        );
+        // Chars can be appended and removed from numbers
+        check_action(
+            J::Array(vec![J::Number("5".to_string())]),
+            vec![0],
+            Action::PushChar('.'),
+            J::Array(vec![J::Number("5.".to_string())]),
+            vec![0],
+        );
+        check_action(
+            J::Array(vec![J::Number("5.2".to_string())]),
+            vec![0],
+            Action::PopChar,
+            J::Array(vec![J::Number("5.".to_string())]),
+            vec![0],
+        );
+
+        // Invalid chars are rejected
+        let arena = Arena::new();
+        let root = J::Array(vec![J::Number("0".to_string())]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        assert_eq!(
+            tree.execute_action(Action::PushChar('0')).1,
+            Err(EditErr::CannotPushChar {
+                c: '0',
+                name: "0".to_string()
+            })
+        );
+        assert_eq!(
+            tree.execute_action(Action::PopChar).1,
+            Err(EditErr::CannotPopChar {
+                name: "0".to_string()
+            })
+        );
+        // ... and don't create any history
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+
+        // The keys of fields can't be replaced with numbers
+        let root = J::Object(vec![("k".to_string(), J::True)]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0, 0]));
+        assert_eq!(
+            tree.execute_action(Action::Replace('5')).1,
+            Err(EditErr::CannotBeChild {
+                c: '5',
+                parent_kind: "field"
+            })
+        );
+        assert_eq!(J::Object(vec![("k".to_string(), J::True)]), tree.root());
+    }
+
+    #[test]
+    fn null_editing() {
+        // Replace values with `null`, both in arrays and in objects
+        check_action(
+            J::Array(vec![J::True, J::False]),
+            vec![1],
+            Action::Replace('n'),
+            J::Array(vec![J::True, J::Null]),
+            vec![1],
+        );
+        check_action(
+            J::Object(vec![("k".to_string(), J::True)]),
+            vec![0, 1],
+            Action::Replace('n'),
+            J::Object(vec![("k".to_string(), J::Null)]),
+            vec![0, 1],
+        );
+        // Replace `null` with other values
+        check_action(
+            J::Array(vec![J::Null]),
+            vec![0],
+            Action::Replace('f'),
+            J::Array(vec![J::False]),
+            vec![0],
+        );
+        // Insert `null` next to other nodes
+        check_action(
+            J::Array(vec![J::True]),
+            vec![0],
+            Action::InsertAfter('n'),
+            J::Array(vec![J::True, J::Null]),
+            vec![0],
+        );
+        // Delete `null` from arrays and objects
+        check_action(
+            J::Array(vec![J::True, J::Null]),
+            vec![1],
+            Action::Delete,
+            J::Array(vec![J::True]),
+            vec![0],
+        );
+        check_action(
+            J::Object(vec![("a".to_string(), J::Null), ("b".to_string(), J::True)]),
+            vec![0],
+            Action::Delete,
+            J::Object(vec![("b".to_string(), J::True)]),
+            vec![0],
+        );
+    }
+
+    #[test]
+    fn undo_restores_valid_cursor() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::False]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![1]));
+        // Deleting the last child moves the cursor back onto the remaining child
+        tree.execute_action(Action::Delete).1.unwrap();
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![0]));
+        // Undoing puts the cursor back on the node that was deleted
+        tree.execute_action(Action::Undo).1.unwrap();
+        assert_eq!(J::Array(vec![J::True, J::False]), tree.root());
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![1]));
+        // Redoing must not leave the cursor pointing at the deleted node
+        tree.execute_action(Action::Redo).1.unwrap();
+        assert_eq!(J::Array(vec![J::True]), tree.root());
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![0]));
+        tree.cursor();
+    }
+
+    #[test]
+    fn undo_restores_cursor_from_before_edit() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::False, J::Null]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+        // The cursor moves after the tree's state was created, and then an edit is made
+        tree.execute_action(Action::MoveCursor(Direction::Down))
+            .1
+            .unwrap();
+        tree.execute_action(Action::MoveCursor(Direction::Next))
+            .1
+            .unwrap();
+        tree.execute_action(Action::Delete).1.unwrap();
+        tree.execute_action(Action::Undo).1.unwrap();
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![1]));
+        tree.cursor();
+    }
+
+    #[test]
+    fn undo_branches() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        tree.execute_action(Action::Replace('f')).1.unwrap();
+        tree.execute_action(Action::Undo).1.unwrap();
+        // Making a new edit after undoing starts a new branch
+        tree.execute_action(Action::Replace('n')).1.unwrap();
+        assert_eq!(tree.redo(), Err(EditErr::NoChangesToRedo));
+        assert_eq!(J::Array(vec![J::Null]), tree.root());
+        // ... but the old branch can still be reached
+        assert_eq!(tree.older(), Ok(EditSuccess::Older));
+        assert_eq!(J::Array(vec![J::False]), tree.root());
+        assert_eq!(tree.undo(), Ok(EditSuccess::Undo));
+        assert_eq!(J::Array(vec![J::True]), tree.root());
+        assert_eq!(tree.redo(), Ok(EditSuccess::Redo));
+        assert_eq!(J::Array(vec![J::False]), tree.root());
+        assert_eq!(tree.newer(), Ok(EditSuccess::Newer));
+        assert_eq!(J::Array(vec![J::Null]), tree.root());
+        assert_eq!(tree.newer(), Err(EditErr::NoChangesToRedo));
+    }
+
+    #[test]
+    fn navigation() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::Array(vec![J::Null, J::False])]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+        let mut check_move = |direction, expected: Result<Vec<usize>, EditErr>| {
+            let old_path = tree.current_cursor_path.clone();
+            let result = tree.move_cursor(direction);
+            match expected {
+                Ok(path) => {
+                    assert_eq!(result, Ok(EditSuccess::Move(direction)));
+                    assert_eq!(tree.current_cursor_path, CursorPath::from_vec(path));
+                }
+                // Failed moves shouldn't move the cursor
+                Err(e) => {
+                    assert_eq!(result, Err(e));
+                    assert_eq!(tree.current_cursor_path, old_path);
+                }
+            }
+        };
+        check_move(Direction::Up, Err(EditErr::MoveToParentOfRoot));
+        check_move(Direction::Next, Err(EditErr::MoveToSiblingOfRoot));
+        check_move(Direction::Down, Ok(vec![0]));
+        check_move(Direction::Down, Err(EditErr::MoveToNonexistentChild));
+        check_move(Direction::Prev, Err(EditErr::MoveToNonexistentSibling));
+        check_move(Direction::Next, Ok(vec![1]));
+        check_move(Direction::Next, Err(EditErr::MoveToNonexistentSibling));
+        check_move(Direction::Down, Ok(vec![1, 0]));
+        check_move(Direction::Root, Ok(vec![]));
+        check_move(Direction::LastLeaf, Ok(vec![1, 1]));
+        check_move(Direction::Up, Ok(vec![1]));
+        check_move(Direction::Prev, Ok(vec![0]));
+    }
+
+    #[test]
+    fn goto() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::Array(vec![J::Null, J::False])]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+
+        assert_eq!(
+            tree.goto("1.1".parse().unwrap()),
+            Ok(EditSuccess::Goto {
+                path: "1.1".to_string(),
+                name: "false".to_string()
+            })
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        // Out of range indices are explained, and don't move the cursor
+        assert_eq!(
+            tree.goto("1.2.0".parse().unwrap()),
+            Err(EditErr::NoNodeAtPath {
+                path: "1.2.0".to_string(),
+                parent_path: "1".to_string(),
+                parent_name: "array".to_string(),
+                index: 2,
+                len: 2
+            })
+        );
+        assert_eq!(
+            tree.goto("0.0".parse().unwrap()).unwrap_err().to_string(),
+            "There's no node at 0.0: true at 0 has no child 0 (it has 0 child(ren))."
+        );
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![1, 1]));
+        tree.goto(CursorPath::root()).unwrap();
+        assert!(tree.cursor_path().is_root());
+    }
+
+    #[test]
+    fn paste() {
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::True,
+            J::Object(vec![("a".to_string(), J::Array(vec![J::Null]))]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        // Paste a copy of the object after `true`
+        let object = root.children()[1];
+        assert_eq!(
+            tree.paste_next_to_cursor(object, Side::Next),
+            Ok(EditSuccess::Paste {
+                side: Side::Next,
+                name: "object".to_string(),
+                count: 1,
+            })
+        );
+        assert_eq!(
+            J::Array(vec![
+                J::True,
+                J::Object(vec![("a".to_string(), J::Array(vec![J::Null]))]),
+                J::Object(vec![("a".to_string(), J::Array(vec![J::Null]))]),
+            ]),
+            tree.root()
+        );
+        // The pasted subtree should be a deep copy, sharing no nodes with the original
+        let pasted_object = tree.root().children()[1];
+        assert!(!std::ptr::eq(pasted_object, object));
+        assert!(!std::ptr::eq(
+            pasted_object.children()[0],
+            object.children()[0]
+        ));
+        // Fields can be pasted straight into other objects, keeping their keys
+        let field = object.children()[0];
+        tree.current_cursor_path = CursorPath::from_vec(vec![1, 0]);
+        tree.paste_next_to_cursor(field, Side::Prev).unwrap();
+        assert_eq!(
+            J::Array(vec![
+                J::True,
+                J::Object(vec![
+                    ("a".to_string(), J::Array(vec![J::Null])),
+                    ("a".to_string(), J::Array(vec![J::Null]))
+                ]),
+                J::Object(vec![("a".to_string(), J::Array(vec![J::Null]))]),
+            ]),
+            tree.root()
+        );
+        // ... but not into arrays
+        tree.current_cursor_path = CursorPath::from_vec(vec![0]);
+        assert_eq!(
+            tree.paste_next_to_cursor(field, Side::Next),
+            Err(EditErr::InsertError(InsertError::InvalidChild {
+                parent_kind: "array",
+                child_kind: "field"
+            }))
+        );
+        // Fields can't gain a third child
+        tree.current_cursor_path = CursorPath::from_vec(vec![2, 0, 0]);
+        assert_eq!(
+            tree.paste_next_to_cursor(object, Side::Next),
+            Err(EditErr::InsertError(InsertError::TooManyChildren {
+                kind: "field",
+                max_children: 2
+            }))
+        );
+        // The root can't have siblings
+        tree.current_cursor_path = CursorPath::root();
+        assert_eq!(
+            tree.paste_next_to_cursor(object, Side::Prev),
+            Err(EditErr::AddSiblingToRoot)
+        );
+        // None of the failed pastes should have changed the tree
+        assert_eq!(tree.undo(), Ok(EditSuccess::Undo));
+        assert_eq!(tree.undo(), Ok(EditSuccess::Undo));
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+    }
+
+    #[test]
+    fn wrap() {
+        // Wrapping in an array
+        check_action(
+            J::Array(vec![J::True, J::Null]),
+            vec![1],
+            Action::Wrap('a'),
+            J::Array(vec![J::True, J::Array(vec![J::Null])]),
+            vec![1],
+        );
+        // Wrapping in an object creates a field with an empty key
+        check_action(
+            J::Object(vec![("x".to_string(), J::False)]),
+            vec![0, 1],
+            Action::Wrap('o'),
+            J::Object(vec![(
+                "x".to_string(),
+                J::Object(vec![("".to_string(), J::False)]),
+            )]),
+            vec![0, 1],
+        );
+        // The root can be wrapped too
+        check_action(
+            J::True,
+            vec![],
+            Action::Wrap('a'),
+            J::Array(vec![J::True]),
+            vec![],
+        );
+        // Wrapping twice nests the wrappers, with the cursor on the outermost one
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        assert_eq!(
+            tree.execute_action(Action::Wrap('a')).1,
+            Ok(EditSuccess::Wrap {
+                c: 'a',
+                name: "array".to_string()
+            })
+        );
+        tree.execute_action(Action::Wrap('o')).1.unwrap();
+        assert_eq!(
+            J::Array(vec![J::Object(vec![(
+                "".to_string(),
+                J::Array(vec![J::True])
+            )])]),
+            tree.root()
+        );
+        assert_eq!(tree.cursor().display_name(), "object");
+        // Nodes can only be wrapped in nodes that can contain them
+        assert_eq!(
+            tree.execute_action(Action::Wrap('t')).1,
+            Err(EditErr::CannotWrap {
+                c: 't',
+                kind: "object"
+            })
+        );
+        tree.current_cursor_path = CursorPath::from_vec(vec![0, 0]);
+        assert_eq!(
+            tree.execute_action(Action::Wrap('a')).1,
+            Err(EditErr::CannotWrap {
+                c: 'a',
+                kind: "field"
+            })
+        );
+    }
+
+    #[test]
+    fn unwrap() {
+        // Unwrapping an array with one element
+        check_action(
+            J::Array(vec![J::True, J::Array(vec![J::Str("x".to_string())])]),
+            vec![1],
+            Action::Unwrap,
+            J::Array(vec![J::True, J::Str("x".to_string())]),
+            vec![1],
+        );
+        // Unwrapping an array inside a field
+        check_action(
+            J::Object(vec![("a".to_string(), J::Array(vec![J::Null]))]),
+            vec![0, 1],
+            Action::Unwrap,
+            J::Object(vec![("a".to_string(), J::Null)]),
+            vec![0, 1],
+        );
+
+        let arena = Arena::new();
+        let root = J::Array(vec![J::Array(vec![J::Array(vec![J::Object(vec![(
+            "a".to_string(),
+            J::Array(vec![J::True, J::False]),
+        )])])])])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+        // The root has no parent to put its child into
+        assert_eq!(tree.unwrap_child(0), Err(EditErr::UnwrapRoot));
+        // Unwrapping nested arrays one at a time should reuse the same object node each time
+        let object = root.children()[0].children()[0].children()[0];
+        tree.current_cursor_path = CursorPath::from_vec(vec![0]);
+        assert_eq!(
+            tree.execute_action(Action::Unwrap).1,
+            Ok(EditSuccess::Unwrap {
+                name: "array".to_string(),
+                child_name: "array".to_string()
+            })
+        );
+        tree.execute_action(Action::Unwrap).1.unwrap();
+        assert_eq!(
+            J::Array(vec![J::Object(vec![(
+                "a".to_string(),
+                J::Array(vec![J::True, J::False]),
+            )])]),
+            tree.root()
+        );
+        assert!(std::ptr::eq(tree.cursor(), object));
+        assert!(std::ptr::eq(
+            tree.cursor().children()[0],
+            object.children()[0]
+        ));
+        // An object's only child is a field, which can't be put into an array
+        assert_eq!(
+            tree.execute_action(Action::Unwrap).1,
+            Err(EditErr::InvalidUnwrap {
+                child_kind: "field",
+                parent_kind: "array"
+            })
+        );
+        // Similarly, a field's value can't take its place in the object
+        tree.current_cursor_path = CursorPath::from_vec(vec![0, 0]);
+        assert_eq!(
+            tree.execute_action(Action::Unwrap).1,
+            Err(EditErr::InvalidUnwrap {
+                child_kind: "array",
+                parent_kind: "object"
+            })
+        );
+        // Arrays with more than one element can't be unwrapped
+        tree.current_cursor_path = CursorPath::from_vec(vec![0, 0, 1]);
+        assert_eq!(
+            tree.execute_action(Action::Unwrap).1,
+            Err(EditErr::CannotUnwrap { kind: "array" })
+        );
+        // But the element to unwrap can be chosen explicitly
+        tree.unwrap_child(1).unwrap();
+        assert_eq!(
+            J::Array(vec![J::Object(vec![("a".to_string(), J::False)])]),
+            tree.root()
+        );
+        assert!(std::ptr::eq(
+            tree.cursor(),
+            object.children()[0].children()[1].children()[1]
+        ));
+        // Failed unwraps don't change the tree, so undoing goes straight back through the
+        // successful ones
+        tree.undo().unwrap();
+        tree.undo().unwrap();
+        tree.undo().unwrap();
+        assert!(std::ptr::eq(tree.root(), root));
+    }
+
+    #[test]
+    fn counts() {
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::True,
+            J::False,
+            J::Null,
+            J::Array(vec![]),
+            J::Object(vec![("a".to_string(), J::True)]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        // Motions are repeated, stopping at the first one that fails
+        assert_eq!(
+            tree.execute_action_with_count(Action::MoveCursor(Direction::Next), 3),
+            (false, Ok(EditSuccess::Move(Direction::Next)))
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![3]));
+        assert_eq!(
+            tree.execute_action_with_count(Action::MoveCursor(Direction::Next), 5),
+            (false, Err(EditErr::MoveToNonexistentSibling))
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![4]));
+        // Deleting a field's key and value at once must fail without deleting the key
+        tree.current_cursor_path = CursorPath::from_vec(vec![4, 0, 0]);
+        assert_eq!(
+            tree.execute_action_with_count(Action::Delete, 2).1,
+            Err(EditErr::DeleteError(DeleteError::TooFewChildren {
+                kind: "field",
+                min_children: 2
+            }))
+        );
+        assert!(std::ptr::eq(tree.root(), root));
+        // Deleting many siblings at once is a single edit
+        tree.current_cursor_path = CursorPath::from_vec(vec![1]);
+        assert_eq!(
+            tree.execute_action_with_count(Action::Delete, 2).1,
+            Ok(EditSuccess::Delete {
+                name: "false".to_string(),
+                count: 2
+            })
+        );
+        assert_eq!(
+            J::Array(vec![
+                J::True,
+                J::Array(vec![]),
+                J::Object(vec![("a".to_string(), J::True)]),
+            ]),
+            tree.root()
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![1]));
+        // Counts that go past the last sibling delete up to the last sibling
+        assert_eq!(
+            tree.execute_action_with_count(Action::Delete, 10).1,
+            Ok(EditSuccess::Delete {
+                name: "array".to_string(),
+                count: 2
+            })
+        );
+        assert_eq!(J::Array(vec![J::True]), tree.root());
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![0]));
+        // Undoing twice gets back to the start, and counts apply to undo too
+        tree.execute_action_with_count(Action::Undo, 2).1.unwrap();
+        assert!(std::ptr::eq(tree.root(), root));
+        // Inserts are repeated
+        tree.current_cursor_path = CursorPath::from_vec(vec![3]);
+        tree.execute_action_with_count(Action::InsertChild('n'), 3)
+            .1
+            .unwrap();
+        assert_eq!(
+            J::Array(vec![J::Null, J::Null, J::Null]),
+            tree.root().children()[3]
+        );
+    }
+
+    #[test]
+    fn replace_text() {
+        use super::replace::{candidates, ReplaceTarget};
+
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::Str("staging".to_string()),
+            J::Object(vec![("env".to_string(), J::Str("staging".to_string()))]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![1]));
+
+        let paths = candidates(tree.root(), "staging", ReplaceTarget::default());
+        assert_eq!(
+            tree.replace_text(&paths, "staging", "production"),
+            Ok(EditSuccess::ReplaceText {
+                pattern: "staging".to_string(),
+                replacement: "production".to_string(),
+                count: 2,
+                unparsed: 0,
+            })
+        );
+        assert_eq!(
+            J::Array(vec![
+                J::Str("production".to_string()),
+                J::Object(vec![("env".to_string(), J::Str("production".to_string()))]),
+            ]),
+            tree.root()
+        );
+        // The cursor doesn't move, and every replacement is undone in one go
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![1]));
+        tree.undo().unwrap();
+        assert!(std::ptr::eq(tree.root(), root));
+        // Replacing nothing doesn't add to the history
+        tree.replace_text(&[], "staging", "production").unwrap();
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+    }
+
+    #[test]
+    fn set_text() {
+        let arena = Arena::new();
+        let root =
+            J::Object(vec![("key".to_string(), J::Str("old".to_string()))]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0, 1]));
+
+        // Previewing text doesn't change the tree or its history
+        let preview = tree.preview_cursor_text("ne").unwrap();
+        assert_eq!(
+            J::Object(vec![("key".to_string(), J::Str("ne".to_string()))]),
+            preview
+        );
+        assert!(std::ptr::eq(tree.root(), root));
+        // Setting the text is a single change
+        assert_eq!(
+            tree.set_cursor_text("new \"text\""),
+            Ok(EditSuccess::SetText {
+                name: r#""new \"text\"""#.to_string()
+            })
+        );
+        assert_eq!(
+            J::Object(vec![(
+                "key".to_string(),
+                J::Str("new \"text\"".to_string())
+            )]),
+            tree.root()
+        );
+        tree.undo().unwrap();
+        assert!(std::ptr::eq(tree.root(), root));
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+        // Setting the text it already has doesn't add to the history
+        tree.set_cursor_text("old").unwrap();
+        assert!(std::ptr::eq(tree.root(), root));
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+        // Only nodes with text can have their text set
+        tree.set_cursor_path(CursorPath::from_vec(vec![0]));
+        assert_eq!(tree.preview_cursor_text("x"), None);
+        assert_eq!(
+            tree.set_cursor_text("x"),
+            Err(EditErr::NoText { kind: "field" })
+        );
+    }
+
+    #[test]
+    fn repeat_edits() {
+        use crate::ast::json::JSONFormat;
+
+        // `.` repeats edits by executing the last edit's `Action` again at the new cursor
+        // location, so check that edits don't depend on where they were first made
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::Array(vec![]),
+            J::Array(vec![J::Null]),
+            J::Object(vec![]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        let insert = Action::InsertChild('t');
+        tree.execute_action(insert.clone()).1.unwrap();
+        tree.execute_action(Action::MoveCursor(Direction::Next))
+            .1
+            .unwrap();
+        tree.execute_action(insert.clone()).1.unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[[true],[null,true],{}]"
+        );
+        // Repeating an insertion into an object creates a field for the new node
+        tree.current_cursor_path = CursorPath::from_vec(vec![2]);
+        tree.execute_action(insert).1.unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[[true],[null,true],{\"\":true}]"
+        );
+        // Counts are repeated along with the edit
+        let wrap = Action::Wrap('a');
+        tree.current_cursor_path = CursorPath::from_vec(vec![0, 0]);
+        tree.execute_action_with_count(wrap.clone(), 2).1.unwrap();
+        tree.current_cursor_path = CursorPath::from_vec(vec![1, 0]);
+        tree.execute_action_with_count(wrap, 2).1.unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[[[[true]]],[[[null]],true],{\"\":true}]"
+        );
+    }
+
+    #[test]
+    fn move_node() {
+        use crate::ast::json::JSONFormat;
+        use crate::ast::json::JSON;
+        use crate::ast::MoveError;
+
+        fn move_node<'arena>(
+            tree: &mut DAG<'arena, JSON<'arena>>,
+            side: Side,
+            count: usize,
+        ) -> super::EditResult {
+            tree.execute_action(Action::MoveNode { side, count }).1
+        }
+
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::True,
+            J::False,
+            J::Null,
+            J::Object(vec![
+                ("a".to_string(), J::True),
+                ("b".to_string(), J::False),
+            ]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        // Moving past the first sibling does nothing, and isn't added to the undo history
+        assert_eq!(
+            move_node(&mut tree, Side::Prev, 1),
+            Ok(EditSuccess::MoveNode {
+                side: Side::Prev,
+                count: 0,
+                name: "true".to_string()
+            })
+        );
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[true,false,null,{\"a\":true,\"b\":false}]"
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![0]));
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+        // Move `true` forward one place, then two places.  The cursor should follow it
+        move_node(&mut tree, Side::Next, 1).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[false,true,null,{\"a\":true,\"b\":false}]"
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![1]));
+        move_node(&mut tree, Side::Next, 2).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[false,null,{\"a\":true,\"b\":false},true]"
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![3]));
+        // Moving past the last sibling does nothing either
+        move_node(&mut tree, Side::Next, 1).unwrap();
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![3]));
+        // Moving too far in one go stops at the first sibling
+        let result = move_node(&mut tree, Side::Prev, 4).unwrap();
+        assert!(matches!(result, EditSuccess::MoveNode { count: 3, .. }));
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![0]));
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[true,false,null,{\"a\":true,\"b\":false}]"
+        );
+        // Fields can be reordered within their object
+        tree.current_cursor_path = CursorPath::from_vec(vec![3, 0]);
+        move_node(&mut tree, Side::Next, 1).unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[true,false,null,{\"b\":false,\"a\":true}]"
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![3, 1]));
+        move_node(&mut tree, Side::Next, 1).unwrap();
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![3, 1]));
+        // But the key and value of a field can't be swapped
+        tree.current_cursor_path = CursorPath::from_vec(vec![3, 1, 0]);
+        assert_eq!(
+            move_node(&mut tree, Side::Next, 1),
+            Err(EditErr::MoveError(MoveError::FixedPositions {
+                kind: "field"
+            }))
+        );
+        // Moving the root isn't possible
+        tree.current_cursor_path = CursorPath::root();
+        assert_eq!(
+            move_node(&mut tree, Side::Next, 1),
+            Err(EditErr::MoveToSiblingOfRoot)
+        );
+        // Every move is a single step in the undo history
+        tree.undo().unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[true,false,null,{\"a\":true,\"b\":false}]"
+        );
+        assert_eq!(tree.current_cursor_path, CursorPath::from_vec(vec![3, 0]));
+        tree.undo().unwrap();
+        assert_eq!(
+            tree.to_text(&JSONFormat::Compact),
+            "[false,null,{\"a\":true,\"b\":false},true]"
+        );
+    }
+
+    #[test]
+    fn read_only() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::True, J::False]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        tree.set_read_only(true);
+        // Every edit is turned away, including undo and redo, and leaves the tree alone
+        assert_eq!(
+            tree.execute_action(Action::Delete).1,
+            Err(EditErr::ReadOnly)
+        );
+        assert_eq!(tree.undo(), Err(EditErr::ReadOnly));
+        assert_eq!(tree.transact(|_| Ok(())), Err(EditErr::ReadOnly));
+        assert_eq!(J::Array(vec![J::False]), tree.root());
+        // But the cursor can still move around the tree
+        tree.current_cursor_path = CursorPath::from_vec(vec![0]);
+        assert!(tree
+            .execute_action(Action::MoveCursor(Direction::Up))
+            .1
+            .is_ok());
+        assert_eq!(tree.current_cursor_path, CursorPath::root());
+        tree.set_read_only(false);
+        tree.undo().unwrap();
+        assert_eq!(J::Array(vec![J::True, J::False]), tree.root());
+    }
 }
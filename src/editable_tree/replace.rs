@@ -0,0 +1,179 @@
+//! Replacing text throughout a tree (like Vim's `:%s/old/new/`).
+
+use super::cursor_path::CursorPath;
+use crate::arena::Arena;
+use crate::ast::Ast;
+
+/// Which nodes a find-and-replace is allowed to change
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ReplaceTarget {
+    /// Replace text in keys (e.g. the keys of JSON fields)
+    pub keys: bool,
+    /// Replace text in every node that isn't a key
+    pub values: bool,
+}
+
+impl Default for ReplaceTarget {
+    /// By default, only values are replaced
+    fn default() -> Self {
+        ReplaceTarget {
+            keys: false,
+            values: true,
+        }
+    }
+}
+
+/// Returns the paths of every node under `root` whose text contains `pattern` and which is
+/// allowed to be changed by `target`, in pre-order.  These are the nodes that
+/// [`replace_at`] would change.
+pub fn candidates<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    pattern: &str,
+    target: ReplaceTarget,
+) -> Vec<CursorPath> {
+    let mut paths = Vec::new();
+    // Pushing children in reverse means that they get popped in order.  As well as the path of
+    // each node, we store whether or not it's a key of its parent.
+    let mut stack = vec![(root, CursorPath::root(), false)];
+    while let Some((node, path, is_key)) = stack.pop() {
+        for (i, child) in node.children().iter().enumerate().rev() {
+            let mut child_path = path.clone();
+            child_path.push(i);
+            stack.push((child, child_path, node.is_key_child(i)));
+        }
+        let allowed = if is_key { target.keys } else { target.values };
+        if allowed && node.replace_text(pattern, "").is_some() {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Builds a new tree where the text of every node in `paths` has had `pattern` replaced with
+/// `replacement`.  All the unchanged nodes are shared with the tree under `root`, and each
+/// ancestor of the changed nodes is only cloned once, however many of its descendants change.
+/// `paths` must be sorted in pre-order (as returned by [`candidates`]).
+///
+/// Returns the root of the new tree, along with how many nodes were changed.
+pub fn replace_at<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    arena: &'arena Arena<Node>,
+    paths: &[CursorPath],
+    pattern: &str,
+    replacement: &str,
+) -> (&'arena Node, usize) {
+    let paths: Vec<Vec<usize>> = paths.iter().map(|p| p.iter().copied().collect()).collect();
+    let mut count = 0;
+    let new_root = replace_rec(root, arena, &paths, 0, pattern, replacement, &mut count);
+    (new_root, count)
+}
+
+/// Recursive helper for [`replace_at`].  Every path in `paths` passes through `node`, which is
+/// `depth` steps below the root.
+fn replace_rec<'arena, Node: Ast<'arena>>(
+    node: &'arena Node,
+    arena: &'arena Arena<Node>,
+    paths: &[Vec<usize>],
+    depth: usize,
+    pattern: &str,
+    replacement: &str,
+    count: &mut usize,
+) -> &'arena Node {
+    if paths.is_empty() {
+        return node;
+    }
+    // In pre-order, a path that ends at `node` comes before the paths of its descendants
+    let (mut new_node, descendant_paths) = if paths[0].len() == depth {
+        match node.replace_text(pattern, replacement) {
+            Some(replaced) => {
+                *count += 1;
+                (replaced, &paths[1..])
+            }
+            None => (node.clone(), &paths[1..]),
+        }
+    } else {
+        (node.clone(), paths)
+    };
+    if descendant_paths.is_empty() {
+        return arena.alloc(new_node);
+    }
+    // The paths are sorted, so the paths through each child are next to each other
+    let mut remaining = descendant_paths;
+    while let Some(first) = remaining.first() {
+        let index = first[depth];
+        let group_len = remaining
+            .iter()
+            .take_while(|path| path[depth] == index)
+            .count();
+        let (group, rest) = remaining.split_at(group_len);
+        let child = new_node.children()[index];
+        new_node.children_mut()[index] =
+            replace_rec(child, arena, group, depth + 1, pattern, replacement, count);
+        remaining = rest;
+    }
+    arena.alloc(new_node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{candidates, replace_at, ReplaceTarget};
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    #[test]
+    fn replace() {
+        let arena = Arena::new();
+        let root = J::Object(vec![
+            ("staging".to_string(), J::Str("staging-db".to_string())),
+            (
+                "hosts".to_string(),
+                J::Array(vec![J::Str("staging".to_string()), J::True]),
+            ),
+            ("name".to_string(), J::Str("prod".to_string())),
+        ])
+        .add_to_arena(&arena);
+        let path = CursorPath::from_vec;
+        let all = ReplaceTarget {
+            keys: true,
+            values: true,
+        };
+        let keys = ReplaceTarget {
+            keys: true,
+            values: false,
+        };
+
+        // Only values are replaced by default
+        let values = candidates(root, "staging", ReplaceTarget::default());
+        assert_eq!(values, vec![path(vec![0, 1]), path(vec![1, 1, 0])]);
+        assert_eq!(candidates(root, "staging", keys), vec![path(vec![0, 0])]);
+        assert_eq!(candidates(root, "staging", all).len(), 3);
+        assert!(candidates(root, "", all).is_empty());
+
+        let (new_root, count) = replace_at(root, &arena, &values, "staging", "production");
+        assert_eq!(count, 2);
+        let expected = J::Object(vec![
+            ("staging".to_string(), J::Str("production-db".to_string())),
+            (
+                "hosts".to_string(),
+                J::Array(vec![J::Str("production".to_string()), J::True]),
+            ),
+            ("name".to_string(), J::Str("prod".to_string())),
+        ]);
+        assert_eq!(expected, new_root);
+        // Unchanged subtrees are shared with the old tree
+        assert!(std::ptr::eq(new_root.children()[2], root.children()[2]));
+        // The original tree isn't modified
+        assert_eq!(
+            root.children()[0].children()[1],
+            &JSON::Str("staging-db".to_string())
+        );
+
+        // Replacing nothing just returns the old tree
+        let (same_root, count) = replace_at(root, &arena, &[], "staging", "production");
+        assert_eq!(count, 0);
+        assert!(std::ptr::eq(same_root, root));
+    }
+}
@@ -0,0 +1,315 @@
+//! Searching a tree for nodes whose text contains a query.
+
+use super::{cursor_path::CursorPath, Side};
+use crate::ast::json::query::Query;
+use crate::ast::Ast;
+use std::collections::HashSet;
+
+/// The results of searching a tree for a query.  A node matches the query if its
+/// [`display_name`](Ast::display_name) contains the query, or if it has no children and its text
+/// contains the query.
+///
+/// Matching is case-insensitive, unless the query contains an upper-case letter (the 'smart case'
+/// rule from Vim).
+///
+/// A search can also be [made from a `Query`](Search::from_query) (for `:query`), in which case
+/// the matches are the nodes that the query finds.
+#[derive(Debug, Clone)]
+pub struct Search<'arena, Node: Ast<'arena>> {
+    /// The query, exactly as the user typed it
+    query: String,
+    /// The query that finds the matches, if this search was made from one rather than from text
+    path_query: Option<Query>,
+    /// The root of the tree that was searched
+    root: &'arena Node,
+    /// The paths to every matching node, in pre-order
+    matches: Vec<CursorPath>,
+    /// The addresses of every matching node, so that the renderer can quickly check whether or
+    /// not a node should be highlighted
+    match_nodes: HashSet<*const Node>,
+    /// The number of [unparsed](Ast::is_unparsed) nodes in the tree, whose contents weren't
+    /// searched
+    unparsed: usize,
+}
+
+impl<'arena, Node: Ast<'arena>> Search<'arena, Node> {
+    /// Searches the tree under `root` for nodes that match `query`
+    pub fn new(root: &'arena Node, query: &str, format_style: &Node::FormatStyle) -> Self {
+        let mut search = Search {
+            query: query.to_string(),
+            path_query: None,
+            root,
+            matches: Vec::new(),
+            match_nodes: HashSet::new(),
+            unparsed: 0,
+        };
+        search.search_tree(format_style);
+        search
+    }
+
+    /// Creates a search whose matches are the nodes of the tree under `root` that `query` finds
+    pub fn from_query(root: &'arena Node, query: Query, format_style: &Node::FormatStyle) -> Self {
+        let mut search = Search {
+            query: query.to_string(),
+            path_query: Some(query),
+            root,
+            matches: Vec::new(),
+            match_nodes: HashSet::new(),
+            unparsed: 0,
+        };
+        search.search_tree(format_style);
+        search
+    }
+
+    /// Changes the query of this search.  If the new query extends the old one (which is what
+    /// happens whilst the user types the query), then only the nodes that matched the old query
+    /// need to be checked again, rather than the whole tree.  A search made from a [`Query`] becomes
+    /// a search for text.
+    pub fn set_query(&mut self, query: &str, format_style: &Node::FormatStyle) {
+        let was_path_query = self.path_query.take().is_some();
+        let can_refine = query.starts_with(&self.query) && !was_path_query;
+        self.query = query.to_string();
+        if !can_refine {
+            self.search_tree(format_style);
+            return;
+        }
+        // A node that matches the new query must also have matched the old query, even if the
+        // new query switched to being case-sensitive
+        let (query, case_sensitive) = self.normalised_query();
+        let matches = std::mem::take(&mut self.matches);
+        self.match_nodes.clear();
+        for path in matches {
+            let node = path.cursor(self.root);
+            if is_match(node, &query, case_sensitive, format_style) {
+                self.match_nodes.insert(node);
+                self.matches.push(path);
+            }
+        }
+    }
+
+    /// Runs the search again if the tree has changed since it was searched
+    pub fn update_root(&mut self, root: &'arena Node, format_style: &Node::FormatStyle) {
+        if !std::ptr::eq(root, self.root) {
+            self.root = root;
+            self.search_tree(format_style);
+        }
+    }
+
+    /// Returns the query that this `Search` is looking for
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Returns the paths of every matching node, in pre-order
+    pub fn matches(&self) -> &[CursorPath] {
+        &self.matches
+    }
+
+    /// Returns the number of [unparsed](Ast::is_unparsed) nodes in the tree, whose contents
+    /// weren't searched
+    pub fn unparsed(&self) -> usize {
+        self.unparsed
+    }
+
+    /// Returns `true` if a given node matched the search
+    pub fn contains(&self, node: &'arena Node) -> bool {
+        self.match_nodes.contains(&(node as *const Node))
+    }
+
+    /// Returns the index of the first match after `cursor` in pre-order (or before `cursor` if
+    /// `side` is [`Side::Prev`]), wrapping around the ends of the tree.  Returns `None` if there
+    /// are no matches.
+    pub fn next_match(&self, cursor: &CursorPath, side: Side) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        // The matches are sorted, so we can binary search for where the cursor would go
+        Some(match side {
+            Side::Next => {
+                let index = self.matches.partition_point(|path| path <= cursor);
+                index % self.matches.len()
+            }
+            Side::Prev => match self.matches.partition_point(|path| path < cursor) {
+                0 => self.matches.len() - 1,
+                index => index - 1,
+            },
+        })
+    }
+
+    /// Walks the whole tree in pre-order, recording every match
+    fn search_tree(&mut self, format_style: &Node::FormatStyle) {
+        self.matches.clear();
+        self.match_nodes.clear();
+        if let Some(query) = &self.path_query {
+            let root = self.root;
+            self.unparsed = unparsed_count(root);
+            self.matches = query.evaluate(root);
+            self.match_nodes = self
+                .matches
+                .iter()
+                .map(|p| p.cursor(root) as *const _)
+                .collect();
+            return;
+        }
+        let (query, case_sensitive) = self.normalised_query();
+        self.unparsed = 0;
+        // Pushing children in reverse means that they get popped in order
+        let mut stack = vec![(self.root, CursorPath::root())];
+        while let Some((node, path)) = stack.pop() {
+            if node.is_unparsed() {
+                self.unparsed += 1;
+            }
+            for (i, child) in node.children().iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                stack.push((child, child_path));
+            }
+            if is_match(node, &query, case_sensitive, format_style) {
+                self.match_nodes.insert(node);
+                self.matches.push(path);
+            }
+        }
+    }
+
+    /// Returns the query in the form that [`is_match`] expects, along with whether or not the
+    /// match should be case-sensitive.  Using smart case, matches are only case-sensitive if the
+    /// query contains upper-case letters.
+    fn normalised_query(&self) -> (String, bool) {
+        if self.query.chars().any(char::is_uppercase) {
+            (self.query.clone(), true)
+        } else {
+            (self.query.to_lowercase(), false)
+        }
+    }
+}
+
+/// Returns the number of [unparsed](Ast::is_unparsed) nodes in the tree under `root`
+pub fn unparsed_count<'arena, Node: Ast<'arena>>(root: &'arena Node) -> usize {
+    let mut count = 0;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.is_unparsed() {
+            count += 1;
+        }
+        stack.extend(node.children().iter().copied());
+    }
+    count
+}
+
+/// Returns what's added to the message of a search or replacement to say that `unparsed` parts
+/// of the tree weren't looked inside (or nothing, if `unparsed` is 0)
+pub fn unparsed_note(unparsed: usize) -> String {
+    match unparsed {
+        0 => String::new(),
+        1 => " (without looking inside 1 unparsed node, which za unfolds)".to_string(),
+        n => format!(
+            " (without looking inside {} unparsed nodes, which za unfolds)",
+            n
+        ),
+    }
+}
+
+/// Returns `true` if a given node matches a query.  If the match isn't `case_sensitive`, then
+/// `query` must already be lower-case.
+fn is_match<'arena, Node: Ast<'arena>>(
+    node: &'arena Node,
+    query: &str,
+    case_sensitive: bool,
+    format_style: &Node::FormatStyle,
+) -> bool {
+    let contains_query = |text: String| {
+        if case_sensitive {
+            text.contains(query)
+        } else {
+            text.to_lowercase().contains(query)
+        }
+    };
+    contains_query(node.display_name())
+        || (node.children().is_empty() && contains_query(node.to_text(format_style)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Search;
+    use crate::arena::Arena;
+    use crate::ast::json::query::Query;
+    use crate::ast::json::JSONFormat;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+    use crate::editable_tree::{cursor_path::CursorPath, Side};
+
+    #[test]
+    fn search() {
+        let arena = Arena::new();
+        let root = J::Object(vec![
+            ("timeout".to_string(), J::Array(vec![J::True])),
+            ("Retries".to_string(), J::Str("Timeout".to_string())),
+        ])
+        .add_to_arena(&arena);
+        let path = CursorPath::from_vec;
+
+        // Lower-case queries match either case
+        let mut search = Search::new(root, "time", &JSONFormat::Compact);
+        assert_eq!(search.matches(), &[path(vec![0, 0]), path(vec![1, 1])]);
+        assert!(search.contains(root.children()[0].children()[0]));
+        assert!(!search.contains(root.children()[0]));
+        // Upper-case queries are case-sensitive
+        search.set_query("Time", &JSONFormat::Compact);
+        assert_eq!(search.matches(), &[path(vec![1, 1])]);
+        // Display names are matched too
+        search.set_query("arr", &JSONFormat::Compact);
+        assert_eq!(search.matches(), &[path(vec![0, 1])]);
+        search.set_query("e", &JSONFormat::Compact);
+        assert_eq!(search.matches().len(), 7);
+        // Extending the query refines the existing matches
+        search.set_query("et", &JSONFormat::Compact);
+        assert_eq!(search.matches(), &[path(vec![1, 0])]);
+
+        // Finding the next match wraps around the ends of the tree
+        search.set_query("t", &JSONFormat::Compact);
+        assert_eq!(
+            search.matches(),
+            &[
+                path(vec![]),
+                path(vec![0, 0]),
+                path(vec![0, 1, 0]),
+                path(vec![1, 0]),
+                path(vec![1, 1]),
+            ]
+        );
+        assert_eq!(search.next_match(&path(vec![0]), Side::Next), Some(1));
+        assert_eq!(search.next_match(&path(vec![0, 1]), Side::Next), Some(2));
+        assert_eq!(search.next_match(&path(vec![1, 1]), Side::Next), Some(0));
+        assert_eq!(search.next_match(&path(vec![0, 1]), Side::Prev), Some(1));
+        assert_eq!(search.next_match(&path(vec![]), Side::Prev), Some(4));
+        search.set_query("xyz", &JSONFormat::Compact);
+        assert_eq!(search.next_match(&path(vec![]), Side::Next), None);
+
+        // Searches can be made from queries, and then changed back into text searches
+        let query = Query::parse("$.*[0]").unwrap();
+        let mut search = Search::from_query(root, query, &JSONFormat::Compact);
+        assert_eq!(search.query(), "$.*[0]");
+        assert_eq!(search.matches(), &[path(vec![0, 1, 0])]);
+        assert!(search.contains(root.children()[0].children()[1].children()[0]));
+        search.set_query("$", &JSONFormat::Compact);
+        assert!(search.matches().is_empty());
+    }
+
+    #[test]
+    fn large_tree() {
+        // Searching tens of thousands of nodes should be fast enough to happen on every keypress
+        let arena = Arena::new();
+        let root = J::Array(
+            (0..20_000)
+                .map(|i| J::Object(vec![(format!("key{}", i), J::Str(i.to_string()))]))
+                .collect(),
+        )
+        .add_to_arena(&arena);
+        let start = std::time::Instant::now();
+        let mut search = Search::new(root, "key1", &JSONFormat::Compact);
+        search.set_query("key19", &JSONFormat::Compact);
+        search.set_query("key199", &JSONFormat::Compact);
+        assert_eq!(search.matches().len(), 111);
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+}
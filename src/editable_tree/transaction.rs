@@ -0,0 +1,160 @@
+//! Transactions, which make several edits to a [`DAG`] as one change.  Either every edit of a
+//! transaction is kept (and they're undone together), or none of them are.
+//!
+//! Because nodes are never changed once they're in the [`Arena`](crate::arena::Arena), rolling
+//! back a transaction only has to go back to the tree that was current before it started: the
+//! nodes that the transaction made are simply never used again.
+
+use super::cursor_path::CursorPath;
+use super::{Direction, EditErr, EditResult, Side, DAG};
+use crate::ast::Ast;
+use crate::editor::normal_mode::Action;
+
+/// The edits that can be made to a tree as part of a transaction (see [`DAG::transact`]).  These
+/// are the same as the edits of the [`DAG`], but moving through the undo history isn't allowed.
+pub struct Transaction<'t, 'arena, Node: Ast<'arena>> {
+    tree: &'t mut DAG<'arena, Node>,
+}
+
+impl<'t, 'arena, Node: Ast<'arena>> Transaction<'t, 'arena, Node> {
+    /// Returns the root of the tree, including the edits made so far
+    pub fn root(&self) -> &'arena Node {
+        self.tree.root()
+    }
+
+    /// Returns the node under the cursor
+    pub fn cursor(&self) -> &'arena Node {
+        self.tree.cursor()
+    }
+
+    /// Returns the path to the node under the cursor
+    pub fn cursor_path(&self) -> &CursorPath {
+        self.tree.cursor_path()
+    }
+
+    /// Moves the cursor to `path` (see [`DAG::goto`])
+    pub fn goto(&mut self, path: CursorPath) -> EditResult {
+        self.tree.goto(path)
+    }
+
+    /// Moves the cursor in `direction` (see [`DAG::move_cursor`])
+    pub fn move_cursor(&mut self, direction: Direction) -> EditResult {
+        self.tree.move_cursor(direction)
+    }
+
+    /// Inserts the node represented by `c` as the first child of the cursor
+    pub fn insert_child(&mut self, c: char) -> EditResult {
+        self.tree.insert_child(c)
+    }
+
+    /// Inserts the node represented by `c` next to the cursor, on `side` of it
+    pub fn insert(&mut self, c: char, side: Side) -> EditResult {
+        self.tree.insert_next_to_cursor(c, side)
+    }
+
+    /// Deletes `count` siblings, starting at the cursor
+    pub fn delete(&mut self, count: usize) -> EditResult {
+        self.tree.delete_cursor(count)
+    }
+
+    /// Replaces the cursor with the node represented by `c`
+    pub fn replace(&mut self, c: char) -> EditResult {
+        self.tree.replace_cursor(c)
+    }
+
+    /// Pastes `node` next to the cursor, on `side` of it
+    pub fn paste(&mut self, node: &'arena Node, side: Side) -> EditResult {
+        self.tree.paste_next_to_cursor(node, side)
+    }
+
+    /// Executes an [`Action`] (see [`DAG::execute_action`]).  Actions that move through the undo
+    /// history fail, since the transaction's edits aren't in the history until it's committed.
+    pub fn execute_action(&mut self, action: Action) -> EditResult {
+        match action {
+            Action::Undo | Action::Redo | Action::Older | Action::Newer => {
+                Err(EditErr::HistoryInTransaction)
+            }
+            _ => self.tree.execute_action(action).1,
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> DAG<'arena, Node> {
+    /// Runs `edits` as a transaction.  If `edits` returns `Ok`, then all of its edits are kept
+    /// as one change to the tree, which is undone in one go.  If it returns `Err`, then all of its
+    /// edits are thrown away, and the tree and cursor are left exactly as they were.
+    pub fn transact<T>(
+        &mut self,
+        edits: impl FnOnce(&mut Transaction<'_, 'arena, Node>) -> Result<T, EditErr>,
+    ) -> Result<T, EditErr> {
+        self.start_edit()?;
+        let checkpoint = self.history.checkpoint();
+        let cursor_path = self.current_cursor_path.clone();
+        let result = edits(&mut Transaction { tree: self });
+        match result {
+            Ok(_) => self.history.squash(checkpoint),
+            Err(_) => {
+                self.history.rollback(checkpoint);
+                self.current_cursor_path = cursor_path;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arena::Arena;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::{EditErr, Side, DAG};
+    use crate::editor::normal_mode::Action;
+
+    #[test]
+    fn commit_and_rollback() {
+        let arena = Arena::new();
+        let root = JSON::parse("[1, {}]", &arena).unwrap();
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0]));
+        fn text<'a>(tree: &DAG<'a, JSON<'a>>) -> String {
+            tree.to_text(&JSONFormat::Compact)
+        }
+
+        // A transaction that succeeds is kept as one change
+        tree.transact(|tx| {
+            tx.replace('t')?;
+            tx.insert('n', Side::Next)?;
+            tx.delete(1)
+        })
+        .unwrap();
+        assert_eq!(text(&tree), "[null,{}]");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        tree.undo().unwrap();
+        assert_eq!(text(&tree), "[1,{}]");
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+        tree.redo().unwrap();
+        assert_eq!(text(&tree), "[null,{}]");
+
+        // A transaction that fails partway through leaves no trace, not even in the history
+        let root = tree.root();
+        let error = tree.transact(|tx| {
+            tx.insert('f', Side::Next)?;
+            tx.delete(1)?;
+            tx.execute_action(Action::Undo)
+        });
+        assert_eq!(error, Err(EditErr::HistoryInTransaction));
+        assert!(std::ptr::eq(tree.root(), root));
+        assert_eq!(tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert_eq!(tree.redo(), Err(EditErr::NoChangesToRedo));
+        tree.undo().unwrap();
+        assert_eq!(text(&tree), "[1,{}]");
+        assert_eq!(tree.undo(), Err(EditErr::NoChangesToUndo));
+        tree.redo().unwrap();
+
+        // A transaction that doesn't change the tree doesn't add a change
+        tree.transact(|tx| tx.goto(CursorPath::from_vec(vec![0])))
+            .unwrap();
+        tree.undo().unwrap();
+        assert_eq!(text(&tree), "[1,{}]");
+    }
+}
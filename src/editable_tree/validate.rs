@@ -0,0 +1,349 @@
+//! Finding the problems which make a tree invalid (see [`Ast::validate`]) or which don't match a
+//! [`Schema`], and keeping them up to date as the tree is edited.
+
+use super::cursor_path::CursorPath;
+use crate::ast::json::schema::Schema;
+use crate::ast::{Ast, ValidationError};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// A problem with one node of a tree
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Problem {
+    /// The path of the node which has the problem
+    pub path: CursorPath,
+    pub error: ValidationError,
+}
+
+impl Problem {
+    /// Returns the path of the node that the cursor should be moved to in order to fix this
+    /// problem: the last child that causes it (e.g. the second of two keys that clash), or the
+    /// node itself if no child does
+    pub fn target(&self) -> CursorPath {
+        let mut path = self.path.clone();
+        if let Some(index) = self.error.children().last() {
+            path.push(*index);
+        }
+        path
+    }
+}
+
+/// Every [`Problem`] in a tree.
+///
+/// Like a [`SizeCache`](crate::ast::size::SizeCache), the problems in every subtree are cached by
+/// the address of the subtree's root.  Nodes are never modified once they've been created, so
+/// after an edit only the new nodes on the path from the root to the edited node are checked
+/// again (the problems of every other subtree are already in the cache).  The nodes must all be
+/// stored in the same arena, which must outlive the `Validation`, so that no address is ever
+/// reused.
+///
+/// If there's a [`Schema`], then the problems that each subtree has with the part of the schema
+/// that it has to match are also cached, by the addresses of both the subtree and that part of
+/// the schema (since the same subtree could be moved to somewhere with a different schema).
+#[derive(Debug, Clone)]
+pub struct Validation<'arena, Node: Ast<'arena>> {
+    /// The root of the tree that was checked
+    root: &'arena Node,
+    /// The problems inside every subtree that has been checked, with paths relative to the root
+    /// of that subtree
+    subtrees: HashMap<*const Node, Rc<Vec<Problem>>>,
+    /// The schema that the tree has to match, if there is one
+    schema: Option<Rc<Schema>>,
+    /// The ways that every subtree which has been checked doesn't match its part of the schema,
+    /// with paths relative to the root of that subtree
+    schema_subtrees: HashMap<(*const Node, *const Schema), Rc<Vec<Problem>>>,
+    /// The problems in the whole tree, in pre-order
+    problems: Rc<Vec<Problem>>,
+    /// The addresses of the nodes which cause the problems (e.g. both of the keys that clash),
+    /// so that the renderer can quickly check whether or not a node should be highlighted
+    problem_nodes: HashSet<*const Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Validation<'arena, Node> {
+    /// Finds every problem in the tree under `root`
+    pub fn new(root: &'arena Node) -> Self {
+        Self::with_schema(root, None)
+    }
+
+    /// Finds every problem in the tree under `root`, including the ways it doesn't match
+    /// `schema` (if it's given)
+    pub fn with_schema(root: &'arena Node, schema: Option<Rc<Schema>>) -> Self {
+        let mut validation = Validation {
+            root,
+            subtrees: HashMap::new(),
+            schema,
+            schema_subtrees: HashMap::new(),
+            problems: Rc::new(Vec::new()),
+            problem_nodes: HashSet::new(),
+        };
+        validation.validate_tree();
+        validation
+    }
+
+    /// Finds the problems again if the tree has changed since it was checked.  Only the nodes
+    /// that weren't in the old tree are checked.
+    pub fn update_root(&mut self, root: &'arena Node) {
+        if !std::ptr::eq(root, self.root) {
+            self.root = root;
+            self.validate_tree();
+        }
+    }
+
+    /// Returns the schema that the tree has to match, if there is one
+    pub fn schema(&self) -> Option<&Rc<Schema>> {
+        self.schema.as_ref()
+    }
+
+    /// Changes the schema that the tree has to match (or stops checking the tree against a schema
+    /// if `schema` is `None`), and checks the whole tree against it
+    pub fn set_schema(&mut self, schema: Option<Rc<Schema>>) {
+        self.schema = schema;
+        self.schema_subtrees.clear();
+        self.validate_tree();
+    }
+
+    /// Returns every problem in the tree, in pre-order
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns the first problem which stops the tree from being written (see
+    /// [`ValidationError::prevents_writing`]), if there is one
+    pub fn unwritable(&self) -> Option<&Problem> {
+        self.problems.iter().find(|p| p.error.prevents_writing())
+    }
+
+    /// Returns `true` if `node` causes one of the problems, and so should be highlighted
+    pub fn is_problem(&self, node: &'arena Node) -> bool {
+        self.problem_nodes.contains(&(node as *const Node))
+    }
+
+    /// Returns the number of subtrees whose problems are cached
+    #[cfg(test)]
+    pub fn checked_count(&self) -> usize {
+        self.subtrees.len()
+    }
+
+    /// Returns the number of subtrees whose problems with the schema are cached
+    #[cfg(test)]
+    pub fn schema_checked_count(&self) -> usize {
+        self.schema_subtrees.len()
+    }
+
+    fn validate_tree(&mut self) {
+        self.problems = self.subtree_problems(self.root);
+        if let Some(schema) = self.schema.clone() {
+            let schema_problems = self.schema_problems(self.root, &schema);
+            if !schema_problems.is_empty() {
+                let mut problems = self.problems.to_vec();
+                problems.extend(schema_problems.iter().cloned());
+                // Sorting by path puts the problems in pre-order, and the sort is stable so each
+                // node's own problems stay in the order they were found
+                problems.sort_by(|a, b| a.path.cmp(&b.path));
+                self.problems = Rc::new(problems);
+            }
+        }
+        self.problem_nodes.clear();
+        for problem in self.problems.iter() {
+            let node = problem.path.cursor(self.root);
+            let children = problem.error.children();
+            if children.is_empty() {
+                self.problem_nodes.insert(node);
+            }
+            for index in children {
+                // Highlight the key of each child that causes the problem, if it has one
+                let child = node.children()[index];
+                let key = (0..child.children().len()).find(|&i| child.is_key_child(i));
+                let culprit = key.map_or(child, |i| child.children()[i]);
+                self.problem_nodes.insert(culprit);
+            }
+        }
+    }
+
+    /// Returns the problems in the subtree under `node`, checking (and caching) any of its
+    /// descendants that haven't been checked yet
+    fn subtree_problems(&mut self, node: &'arena Node) -> Rc<Vec<Problem>> {
+        if let Some(problems) = self.subtrees.get(&(node as *const Node)) {
+            return problems.clone();
+        }
+        let mut problems: Vec<Problem> = node
+            .validate()
+            .into_iter()
+            .map(|error| Problem {
+                path: CursorPath::root(),
+                error,
+            })
+            .collect();
+        for (index, child) in node.children().iter().enumerate() {
+            for problem in self.subtree_problems(child).iter() {
+                let mut path = vec![index];
+                path.extend(problem.path.iter());
+                problems.push(Problem {
+                    path: CursorPath::from_vec(path),
+                    error: problem.error.clone(),
+                });
+            }
+        }
+        let problems = Rc::new(problems);
+        self.subtrees.insert(node, problems.clone());
+        problems
+    }
+
+    /// Returns the ways that the subtree under `node` doesn't match `schema`, checking (and
+    /// caching) any of its descendants that haven't been checked against their part of
+    /// `schema` yet.  `schema` must be part of `self.schema`, so that it lives as long as the
+    /// cache does.
+    fn schema_problems(&mut self, node: &'arena Node, schema: &Schema) -> Rc<Vec<Problem>> {
+        let key = (node as *const Node, schema as *const Schema);
+        if let Some(problems) = self.schema_subtrees.get(&key) {
+            return problems.clone();
+        }
+        let mut problems: Vec<Problem> = schema
+            .check(node)
+            .into_iter()
+            .map(|error| Problem {
+                path: CursorPath::root(),
+                error: ValidationError::Schema(error),
+            })
+            .collect();
+        for (child_path, child, child_schema) in schema.children(node) {
+            for problem in self.schema_problems(child, child_schema).iter() {
+                let mut path = child_path.clone();
+                for &index in problem.path.iter() {
+                    path.push(index);
+                }
+                problems.push(Problem {
+                    path,
+                    error: problem.error.clone(),
+                });
+            }
+        }
+        let problems = Rc::new(problems);
+        self.schema_subtrees.insert(key, problems.clone());
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Validation;
+    use crate::arena::Arena;
+    use crate::ast::json::schema::{Schema, SchemaError};
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::{Ast, ValidationError};
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::DAG;
+    use crate::editor::normal_mode::Action;
+
+    #[test]
+    fn incremental() {
+        let arena = Arena::new();
+        let field = |key: &str| (key.to_string(), J::Null);
+        let root = J::Array(vec![
+            J::Object(vec![field("a"), field("b")]),
+            J::Array(vec![J::Object(vec![field("a"), field("a")])]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![0, 1, 0]));
+        let mut validation = Validation::new(tree.root());
+        let count = tree.root().node_count();
+        assert_eq!(validation.checked_count(), count);
+        let duplicate = ValidationError::DuplicateKey {
+            key: r#""a""#.to_string(),
+            index: 1,
+            first_index: 0,
+        };
+        assert_eq!(validation.problems().len(), 1);
+        assert_eq!(
+            validation.problems()[0].path,
+            CursorPath::from_vec(vec![1, 0])
+        );
+        assert_eq!(validation.problems()[0].error, duplicate);
+        // Both of the keys that clash are highlighted, but not their values
+        let object = CursorPath::from_vec(vec![1, 0]).cursor(tree.root());
+        assert!(validation.is_problem(object.children()[0].children()[0]));
+        assert!(validation.is_problem(object.children()[1].children()[0]));
+        assert!(!validation.is_problem(object.children()[1].children()[1]));
+        assert!(!validation.is_problem(object));
+
+        // Renaming a key only checks the nodes on the path to it
+        tree.set_cursor_text("a").unwrap();
+        validation.update_root(tree.root());
+        assert_eq!(validation.checked_count(), count + 4);
+        assert_eq!(validation.problems().len(), 2);
+        assert_eq!(validation.problems()[0].path, CursorPath::from_vec(vec![0]));
+        assert_eq!(validation.problems()[0].error, duplicate);
+        // Deleting a duplicate fixes its problem
+        tree.set_cursor_path(CursorPath::from_vec(vec![1, 0, 1]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        validation.update_root(tree.root());
+        assert_eq!(validation.problems().len(), 1);
+        assert!(validation.is_problem(tree.root().children()[0].children()[1].children()[0]));
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Schema::parse(
+            r#"{
+                "properties": {
+                    "a": {"items": {"type": "number"}},
+                    "b": {"properties": {"c": {"type": "string"}}}
+                },
+                "additionalProperties": false
+            }"#,
+        )
+        .unwrap();
+        let arena = Arena::new();
+        let root = JSON::parse(r#"{"a": [1, "x"], "b": {"c": "y"}, "d": null}"#, &arena).unwrap();
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![1, 1, 0, 1]));
+        let mut validation = Validation::with_schema(tree.root(), Some(schema.into()));
+        let problems: Vec<_> = validation
+            .problems()
+            .iter()
+            .map(|p| (p.path.to_string(), p.target().to_string(), p.error.clone()))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    "root".to_string(),
+                    "2".to_string(),
+                    ValidationError::Schema(SchemaError::UnexpectedProperty {
+                        key: "d".to_string(),
+                        index: 2
+                    })
+                ),
+                (
+                    "0.1.1".to_string(),
+                    "0.1.1".to_string(),
+                    ValidationError::Schema(SchemaError::WrongType {
+                        expected: vec!["number"],
+                        found: "string"
+                    })
+                ),
+            ]
+        );
+        // The key of a field that isn't allowed is highlighted, as is a value of the wrong type
+        let root = tree.root();
+        assert!(validation.is_problem(CursorPath::from_vec(vec![2, 0]).cursor(root)));
+        assert!(validation.is_problem(CursorPath::from_vec(vec![0, 1, 1]).cursor(root)));
+        assert!(!validation.is_problem(root));
+        // The value of "d" isn't checked, since "d" isn't allowed at all
+        assert_eq!(validation.schema_checked_count(), 6);
+
+        // Editing a value only checks the nodes on the path to it against the schema
+        tree.set_cursor_text("z").unwrap();
+        validation.update_root(tree.root());
+        assert_eq!(validation.schema_checked_count(), 9);
+        assert_eq!(validation.problems().len(), 2);
+        tree.set_cursor_path(CursorPath::from_vec(vec![2]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        validation.update_root(tree.root());
+        assert_eq!(validation.problems().len(), 1);
+        // Without the schema, nothing is wrong with the tree
+        validation.set_schema(None);
+        assert!(validation.problems().is_empty());
+        assert!(validation.schema().is_none());
+    }
+}
@@ -0,0 +1,132 @@
+//! Auto-saving, which writes a buffer's tree to disk once it hasn't been changed for `autosave`
+//! milliseconds.  The tree is written either to its own file (as `:w` would write it) or, if
+//! `autosave_to` is `shadow`, to `<file>.autosave` next to it, leaving the file itself alone.
+//! If a buffer can't be auto-saved, it isn't tried again until `autosave` is set again, so that
+//! a read-only disk doesn't fill the bottom bar with the same error every few seconds.
+
+use crate::ast::Ast;
+use crate::editable_tree::compact::Compactor;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The names of the places that buffers can be auto-saved to: `file` is the buffer's own file,
+/// and `shadow` is a file next to it (see [`shadow_path`])
+pub const AUTOSAVE_TARGETS: [&str; 2] = ["file", "shadow"];
+
+/// Returns the path of the shadow file that the buffer of the file at `path` is auto-saved to
+/// when `autosave_to` is `shadow`
+pub fn shadow_path(path: &Path) -> PathBuf {
+    let mut shadow_path = path.as_os_str().to_owned();
+    shadow_path.push(".autosave");
+    PathBuf::from(shadow_path)
+}
+
+/// Keeps track of when one buffer's tree was last changed, so that it's auto-saved once the
+/// changes have stopped for long enough
+#[derive(Debug, Clone)]
+pub struct AutoSave<'arena, Node: Ast<'arena>> {
+    /// The root of the tree when it was last looked at
+    root: &'arena Node,
+    /// When the tree was last changed, if it has been changed since it was last auto-saved
+    changed_at: Option<Instant>,
+    /// If `true`, auto-saving failed, so it isn't tried again until [`retry`](AutoSave::retry)
+    failed: bool,
+}
+
+impl<'arena, Node: Ast<'arena>> AutoSave<'arena, Node> {
+    /// Starts keeping track of a tree whose root is `root`, which has no changes to save
+    pub fn new(root: &'arena Node) -> Self {
+        AutoSave {
+            root,
+            changed_at: None,
+            failed: false,
+        }
+    }
+
+    /// Looks at the tree's current `root`, recording that it was changed at `now` if it isn't
+    /// the root that was last looked at
+    pub fn update(&mut self, root: &'arena Node, now: Instant) {
+        if !std::ptr::eq(root, self.root) {
+            self.root = root;
+            self.changed_at = Some(now);
+        }
+    }
+
+    /// Returns how long after `now` the tree should be auto-saved, if auto-saving waits for
+    /// `delay` after the last change.  This is zero if it should be saved straight away, and
+    /// `None` if there's nothing to save (or auto-saving has failed).
+    pub fn remaining(&self, now: Instant, delay: Duration) -> Option<Duration> {
+        if self.failed {
+            return None;
+        }
+        Some((self.changed_at? + delay).saturating_duration_since(now))
+    }
+
+    /// Records that the tree has been saved (by auto-saving or otherwise), so its changes don't
+    /// need auto-saving any more
+    pub fn saved(&mut self) {
+        self.changed_at = None;
+    }
+
+    /// Records that auto-saving the tree failed, so that it isn't tried again
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// Lets a tree that failed to auto-save be tried again (e.g. after `autosave` is set)
+    pub fn retry(&mut self) {
+        self.failed = false;
+    }
+
+    /// Copies the root that was last looked at into the compactor's arena (see
+    /// [`Buffer::compact`](super::buffer::Buffer::compact)), so that compacting the tree doesn't
+    /// look like a change to it
+    pub fn compact(&mut self, compactor: &mut Compactor<'arena, Node>) {
+        self.root = compactor.copy(self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shadow_path, AutoSave};
+    use crate::arena::Arena;
+    use crate::ast::json::JSON;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn debounce() {
+        assert_eq!(
+            shadow_path(Path::new("dir/data.json")),
+            Path::new("dir/data.json.autosave")
+        );
+
+        let arena = Arena::new();
+        let (first, second) = (arena.alloc(JSON::True), arena.alloc(JSON::False));
+        let delay = Duration::from_secs(2);
+        let start = Instant::now();
+        let mut autosave = AutoSave::new(first);
+        // Looking at the same tree again isn't a change
+        autosave.update(first, start);
+        assert_eq!(autosave.remaining(start, delay), None);
+        // The wait starts again after every change
+        autosave.update(second, start);
+        autosave.update(first, start + Duration::from_secs(1));
+        assert_eq!(
+            autosave.remaining(start + Duration::from_millis(1500), delay),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            autosave.remaining(start + Duration::from_secs(5), delay),
+            Some(Duration::ZERO)
+        );
+        autosave.saved();
+        assert_eq!(autosave.remaining(start, delay), None);
+        // Failing stops any more attempts until they're allowed again
+        autosave.update(second, start);
+        autosave.fail();
+        assert_eq!(autosave.remaining(start, delay), None);
+        autosave.retry();
+        assert_eq!(autosave.remaining(start, delay), Some(delay));
+    }
+}
@@ -0,0 +1,563 @@
+//! Buffers, each of which holds a tree (usually loaded from a file) that is being edited.
+
+use super::autosave::AutoSave;
+use super::file::FileStamp;
+use super::marks::MarkStore;
+use super::session::file_hash;
+use super::swap::{self, Journal};
+use crate::arena::Arena;
+use crate::ast::Ast;
+use crate::editable_tree::compact::{Compaction, Compactor};
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::validate::Validation;
+use crate::editable_tree::{EditErr, DAG};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A function that parses the text of a file into a tree, allocating the tree's nodes into the
+/// given [`Arena`].  If the text can't be parsed, the reason why is returned.
+pub type ParseFn<'arena, Node> = fn(&str, &'arena Arena<Node>) -> Result<&'arena Node, String>;
+
+/// One tree that is open in the editor, along with its undo history and everything else that
+/// belongs to it (like the file that it's saved to).  Every buffer's nodes are stored in an
+/// [`Arena`] of its own, which is created by a [`BufferLoader`].
+pub struct Buffer<'arena, Node: Ast<'arena>> {
+    /// The tree being edited, which also keeps the cursor and the undo history
+    pub tree: DAG<'arena, Node>,
+    /// The path of the file that the tree will be saved to, if there is one
+    pub file_path: Option<PathBuf>,
+    /// The root of the tree as it was when it was last loaded or saved.  If this isn't the root
+    /// of the current tree, then there are unsaved changes.  This is `None` if the tree has
+    /// unsaved changes from before it was loaded (see [`mark_unsaved`](Buffer::mark_unsaved)).
+    saved_root: Option<&'arena Node>,
+    /// The [`file_hash`] of the text that `saved_root` was loaded from or saved as, if it's known
+    saved_hash: Option<String>,
+    /// The stamp of the file as it was when the tree was last read from or written to it (or
+    /// when the user chose to keep their version over one written by another program)
+    disk_stamp: Option<FileStamp>,
+    /// The stamp of a version of the file written by another program, which the user has
+    /// already been asked about (so isn't asked about again until they save)
+    ignored_stamp: Option<FileStamp>,
+    /// The swap file that the changes to the tree are written to (see [`swap`])
+    swap: SwapState<'arena, Node>,
+    /// The root of the tree as it was first loaded, which JSON Patches are made against
+    loaded_root: &'arena Node,
+    /// The positions that the user has marked, which follow their nodes as the tree is edited
+    pub marks: MarkStore<'arena, Node>,
+    /// The problems that make the tree invalid (e.g. duplicate keys)
+    pub validation: Validation<'arena, Node>,
+    /// When the tree was last changed, so that it can be auto-saved (see [`autosave`])
+    ///
+    /// [`autosave`]: super::autosave
+    pub autosave: AutoSave<'arena, Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Buffer<'arena, Node> {
+    /// Creates a `Buffer` containing the tree under `root`, whose nodes are in `arena`
+    pub fn new(arena: &'arena Arena<Node>, root: &'arena Node, file_path: Option<PathBuf>) -> Self {
+        Buffer {
+            tree: DAG::new(arena, root, CursorPath::root()),
+            file_path,
+            saved_root: Some(root),
+            saved_hash: None,
+            disk_stamp: None,
+            ignored_stamp: None,
+            swap: SwapState::Idle,
+            loaded_root: root,
+            marks: MarkStore::new(),
+            validation: Validation::new(root),
+            autosave: AutoSave::new(root),
+        }
+    }
+
+    /// Returns `true` if the tree has been changed since it was last loaded or saved
+    pub fn is_dirty(&self) -> bool {
+        !self
+            .saved_root
+            .is_some_and(|root| std::ptr::eq(self.tree.root(), root))
+    }
+
+    /// Returns an error if the tree can't be written in its current state (e.g. because a number
+    /// is only half-typed, like `1e`), since the file couldn't be read back in
+    pub fn check_writable(&self) -> Result<(), EditErr> {
+        match self.validation.unwritable() {
+            Some(problem) => Err(EditErr::Unwritable {
+                path: problem.path.to_string(),
+                message: problem.error.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the root of the tree as it was when it was last loaded or saved, or `None` if the
+    /// tree had unsaved changes from before it was loaded
+    pub fn saved_root(&self) -> Option<&'arena Node> {
+        self.saved_root
+    }
+
+    /// Returns the [`file_hash`] of the text that the tree returned by
+    /// [`saved_root`](Buffer::saved_root) was loaded from or saved as, if it's known
+    pub fn saved_hash(&self) -> Option<&str> {
+        self.saved_hash.as_deref()
+    }
+
+    /// Returns the root of the tree as it was when the buffer was created
+    pub fn loaded_root(&self) -> &'arena Node {
+        self.loaded_root
+    }
+
+    /// Replaces the tree (and its undo history) with one from a saved session, along with the
+    /// roots that [`saved_root`](Buffer::saved_root) and [`loaded_root`](Buffer::loaded_root)
+    /// return.  Any marks are removed, and the buffer stays read-only if it was.
+    pub fn restore(
+        &mut self,
+        mut tree: DAG<'arena, Node>,
+        saved_root: Option<&'arena Node>,
+        loaded_root: &'arena Node,
+    ) {
+        tree.set_read_only(self.tree.is_read_only());
+        let schema = self.validation.schema().cloned();
+        self.validation = Validation::with_schema(tree.root(), schema);
+        self.tree = tree;
+        self.saved_root = saved_root;
+        self.loaded_root = loaded_root;
+        self.marks = MarkStore::new();
+    }
+
+    /// Parses the node under the cursor if it hasn't been parsed yet (see
+    /// [`DAG::parse_cursor`]), returning `None` if it has.  This doesn't change the text of the
+    /// tree, so it doesn't count as an unsaved change.
+    pub fn parse_cursor(&mut self) -> Option<Result<(), EditErr>> {
+        let old_root = self.tree.root();
+        let new_root = match self.tree.parse_cursor()? {
+            Ok(root) => root,
+            Err(e) => return Some(Err(e)),
+        };
+        if self
+            .saved_root
+            .is_some_and(|root| std::ptr::eq(root, old_root))
+        {
+            self.saved_root = Some(new_root);
+        }
+        if std::ptr::eq(self.loaded_root, old_root) {
+            self.loaded_root = new_root;
+        }
+        Some(Ok(()))
+    }
+
+    /// Records that the current tree has been saved as `text`.  Its changes no longer need to be
+    /// recovered, so the swap file is deleted.
+    pub fn mark_saved(&mut self, text: &str) {
+        self.mark_saved_as(file_hash(text));
+    }
+
+    /// Records that the current tree has been saved as text whose [`file_hash`] is `hash`, in the
+    /// same way as [`mark_saved`](Buffer::mark_saved)
+    pub fn mark_saved_as(&mut self, hash: String) {
+        self.saved_root = Some(self.tree.root());
+        self.saved_hash = Some(hash);
+        self.disk_stamp = self.file_path.as_deref().and_then(FileStamp::of);
+        self.autosave.saved();
+        self.remove_swap();
+    }
+
+    /// Returns the text of the buffer's file if another program has changed it since the tree
+    /// was last read from or written to it.  Files whose stamp has changed but whose text
+    /// hasn't (e.g. because they were only touched) don't count, and neither do files that
+    /// have been deleted (since saving simply writes them again).
+    pub fn disk_version(&mut self) -> Option<DiskVersion> {
+        let recorded = self.disk_stamp.as_ref()?;
+        // The stamp says nothing about a file that the tree is about to be saved to instead
+        if self.file_path.as_deref() != Some(recorded.path()) {
+            return None;
+        }
+        let stamp = FileStamp::of(recorded.path())?;
+        if stamp == *recorded {
+            return None;
+        }
+        let text = std::fs::read_to_string(stamp.path()).ok()?;
+        let hash = file_hash(&text);
+        if self.saved_hash.as_ref() == Some(&hash) {
+            self.disk_stamp = Some(stamp);
+            return None;
+        }
+        Some(DiskVersion { text, hash, stamp })
+    }
+
+    /// Returns `true` if the user has already been asked about `version` of the buffer's file
+    pub fn is_ignored(&self, version: &DiskVersion) -> bool {
+        self.ignored_stamp.as_ref() == Some(&version.stamp)
+    }
+
+    /// Records that the user has been asked about `version` of the buffer's file, and decided
+    /// not to do anything about it yet
+    pub fn ignore(&mut self, version: &DiskVersion) {
+        self.ignored_stamp = Some(version.stamp.clone());
+    }
+
+    /// Records that the user would rather keep their version of the tree than `version` of the
+    /// buffer's file, so saving overwrites the file without asking
+    pub fn keep_own_version(&mut self, version: &DiskVersion) {
+        self.disk_stamp = Some(version.stamp.clone());
+    }
+
+    /// Replaces the tree with `root`, which was parsed from the text of `version` of the
+    /// buffer's file.  In a tree that can be edited this is one more change, so the user's
+    /// edits can be got back by undoing it.  A read-only tree's history can't be moved through,
+    /// so it's started again from `root`.
+    pub fn reload(&mut self, root: &'arena Node, version: DiskVersion) {
+        if self.tree.is_read_only() {
+            // Keep the cursor as close to where it was as the new tree allows
+            let old_path = self.tree.cursor_path();
+            let (_, steps) = old_path.resolve_longest(root);
+            let cursor_path = CursorPath::from_vec(old_path.iter().take(steps).copied().collect());
+            self.tree = DAG::new(self.tree.arena(), root, cursor_path);
+            self.tree.set_read_only(true);
+        } else {
+            self.tree
+                .replace_root(root)
+                .expect("the tree is writable, so its root can be replaced");
+        }
+        self.saved_root = Some(root);
+        self.saved_hash = Some(version.hash);
+        self.disk_stamp = Some(version.stamp);
+        self.ignored_stamp = None;
+        let schema = self.validation.schema().cloned();
+        self.validation = Validation::with_schema(root, schema);
+        self.remove_swap();
+    }
+
+    /// Records that the tree has changes that aren't saved, even though it hasn't been edited
+    /// (e.g. because it was parsed from the unsaved text of a buffer in another language)
+    pub fn mark_unsaved(&mut self) {
+        self.saved_root = None;
+        self.saved_hash = None;
+    }
+
+    /// Writes any change to the tree to the buffer's swap file, starting the swap file once the
+    /// tree has unsaved changes.  Nothing is written for buffers which are read-only, don't have
+    /// a file, or whose nodes can't be serialized.  If the swap file can't be written (or
+    /// another swap file is in the way), then nothing more is written until the tree is saved.
+    pub fn write_swap(&mut self, now: Instant) {
+        let path = match &self.file_path {
+            Some(path) if !self.tree.is_read_only() => swap::swap_path(path),
+            _ => return,
+        };
+        let root = self.tree.root();
+        if let SwapState::Idle = self.swap {
+            if !self.is_dirty() || root.to_owned_node().is_none() {
+                return;
+            }
+            let base = self.saved_root.zip(self.saved_hash.as_deref());
+            match Journal::create(path.clone(), base) {
+                Ok(journal) => self.swap = SwapState::Writing(journal),
+                Err(e) => {
+                    log::warn!("Couldn't start the swap file {}: {}", path.display(), e);
+                    self.swap = SwapState::Failed;
+                }
+            }
+        }
+        if let SwapState::Writing(journal) = &mut self.swap {
+            if let Err(e) = journal.record(root, self.tree.cursor_path(), now) {
+                log::warn!("Couldn't write to the swap file {}: {}", path.display(), e);
+                self.swap = SwapState::Failed;
+            }
+        }
+    }
+
+    /// Carries on writing the changes to the tree to a swap file that has been recovered
+    pub fn resume_swap(&mut self, journal: Journal<'arena, Node>) {
+        self.swap = SwapState::Writing(journal);
+    }
+
+    /// Deletes the buffer's swap file (if it has one), because its changes have been saved or
+    /// thrown away
+    pub fn remove_swap(&mut self) {
+        if let SwapState::Writing(journal) = std::mem::replace(&mut self.swap, SwapState::Idle) {
+            let path = journal.path().to_owned();
+            if let Err(e) = journal.remove() {
+                log::warn!("Couldn't delete the swap file {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Copies every node that the buffer still uses (in its tree, undo history and marks) into
+    /// `arena`, which the buffer then uses for all its new nodes.  The text, cursor, undo history
+    /// and marks all stay exactly as they were.
+    pub fn compact(&mut self, arena: &'arena Arena<Node>) -> Compaction {
+        let old_arena = self.tree.arena();
+        let (nodes_before, bytes_before) = (old_arena.len(), old_arena.bytes_allocated());
+        let mut compactor = Compactor::new(arena);
+        self.tree.compact(&mut compactor);
+        self.saved_root = self.saved_root.map(|root| compactor.copy(root));
+        self.loaded_root = compactor.copy(self.loaded_root);
+        if let SwapState::Writing(journal) = &mut self.swap {
+            journal.compact(&mut compactor);
+        }
+        self.marks.compact(&mut compactor);
+        self.autosave.compact(&mut compactor);
+        let schema = self.validation.schema().cloned();
+        self.validation = Validation::with_schema(self.tree.root(), schema);
+        Compaction {
+            nodes_before,
+            bytes_before,
+            nodes_after: arena.len(),
+            bytes_after: arena.bytes_allocated(),
+        }
+    }
+
+    /// Returns the name that the buffer is shown with, which is the path of its file
+    pub fn name(&self) -> String {
+        match &self.file_path {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => "[No Name]".to_string(),
+        }
+    }
+}
+
+/// The text of a buffer's file, as written by another program since the buffer's tree was last
+/// read from or written to it (see [`Buffer::disk_version`])
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiskVersion {
+    pub text: String,
+    /// The [`file_hash`] of `text`
+    hash: String,
+    stamp: FileStamp,
+}
+
+/// Whether the changes to a [`Buffer`] are being written to a swap file
+#[derive(Debug)]
+enum SwapState<'arena, Node: Ast<'arena>> {
+    /// Nothing has been written, because the tree hasn't been changed since it was saved
+    Idle,
+    Writing(Journal<'arena, Node>),
+    /// The swap file couldn't be written, so no more is written until the tree is saved
+    Failed,
+}
+
+/// Creates [`Buffer`]s, giving each one a new [`Arena`] for its nodes.  The arenas are all kept
+/// in `arenas`, so they (and every node in them) live until Sapling exits.  This means that nodes
+/// can be copied between buffers (e.g. by yanking in one and pasting in another).
+pub struct BufferLoader<'arena, Node: Ast<'arena>> {
+    arenas: &'arena typed_arena::Arena<Arena<Node>>,
+    parse: ParseFn<'arena, Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> BufferLoader<'arena, Node> {
+    /// Creates a `BufferLoader` which keeps its arenas in `arenas`, and uses `parse` to read
+    /// files
+    pub fn new(
+        arenas: &'arena typed_arena::Arena<Arena<Node>>,
+        parse: ParseFn<'arena, Node>,
+    ) -> Self {
+        BufferLoader { arenas, parse }
+    }
+
+    /// Creates a new, empty [`Arena`] for a buffer's nodes
+    pub fn new_arena(&self) -> &'arena Arena<Node> {
+        self.arenas.alloc(Arena::new())
+    }
+
+    /// Parses some text into a new [`Buffer`], which will be saved to `file_path`
+    pub fn parse(
+        &self,
+        text: &str,
+        file_path: Option<PathBuf>,
+    ) -> Result<Buffer<'arena, Node>, String> {
+        let arena = self.new_arena();
+        let start = Instant::now();
+        let root = (self.parse)(text, arena)?;
+        let buffer = Buffer::new(arena, root, file_path);
+        log::debug!(
+            "Parsed {} in {} ms",
+            buffer.name(),
+            start.elapsed().as_millis()
+        );
+        Ok(buffer)
+    }
+
+    /// Parses some text into a tree in a new [`Arena`], without making a buffer for it (e.g. to
+    /// paste the text on the clipboard)
+    pub fn parse_node(&self, text: &str) -> Result<&'arena Node, String> {
+        (self.parse)(text, self.new_arena())
+    }
+
+    /// Parses some text into a tree in an existing `arena` (e.g. that of the buffer whose file
+    /// the text was read from)
+    pub fn parse_in(&self, text: &str, arena: &'arena Arena<Node>) -> Result<&'arena Node, String> {
+        (self.parse)(text, arena)
+    }
+
+    /// Reads and parses the file at `path` into a new [`Buffer`]
+    pub fn load(&self, path: &Path) -> Result<Buffer<'arena, Node>, EditErr> {
+        let load_failed = |message: String| EditErr::LoadFailed {
+            path: path.to_string_lossy().into_owned(),
+            message,
+        };
+        // The stamp is read first, so that a write that happens whilst the file is being read
+        // is noticed later rather than missed
+        let stamp = FileStamp::of(path);
+        let text = std::fs::read_to_string(path).map_err(|e| load_failed(e.to_string()))?;
+        let mut buffer = self
+            .parse(&text, Some(path.to_owned()))
+            .map_err(load_failed)?;
+        buffer.saved_hash = Some(file_hash(&text));
+        buffer.disk_stamp = stamp;
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Buffer, BufferLoader};
+    use crate::arena::Arena;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::{Direction, EditErr, Side};
+    use crate::editor::marks::Mark;
+    use crate::editor::normal_mode::Action;
+    use crate::editor::session::file_hash;
+    use crate::testing::{random_json, Bounds, Edit, Rng};
+    use std::path::Path;
+
+    #[test]
+    fn separate_arenas() {
+        let arenas = typed_arena::Arena::new();
+        let loader = BufferLoader::new(&arenas, |text, arena| {
+            JSON::parse(text, arena).map_err(|e| e.to_string())
+        });
+        let mut a = loader.parse("[true]", Some("a.json".into())).unwrap();
+        let mut b = loader.parse("[{}]", None).unwrap();
+        assert_eq!(
+            (a.name(), b.name()),
+            ("a.json".to_string(), "[No Name]".to_string())
+        );
+
+        // Edits to one buffer don't affect the other
+        a.tree.move_cursor(Direction::Down).unwrap();
+        let node = a.tree.cursor();
+        a.tree.execute_action(Action::Delete).1.unwrap();
+        assert!(a.is_dirty());
+        assert!(!b.is_dirty());
+        assert_eq!(a.tree.to_text(&JSONFormat::Compact), "[]");
+        a.mark_saved("[]");
+        assert_eq!(a.saved_hash(), Some(file_hash("[]").as_str()));
+        assert!(!a.is_dirty());
+        // Nodes from one buffer can be pasted into another
+        b.tree.move_cursor(Direction::Down).unwrap();
+        b.tree.paste_next_to_cursor(node, Side::Next).unwrap();
+        assert!(b.is_dirty());
+        assert_eq!(b.tree.to_text(&JSONFormat::Compact), "[{},true]");
+
+        b.mark_saved("[{},true]");
+        b.mark_unsaved();
+        assert!(b.is_dirty());
+
+        assert!(loader.parse("[", None).is_err());
+        let error = loader.load(Path::new("/nonexistent/file.json"));
+        assert!(matches!(error, Err(EditErr::LoadFailed { .. })));
+    }
+
+    /// Returns the text of every state from the current one back to the first, leaving the tree
+    /// as it was
+    fn undo_texts<'arena>(buffer: &mut Buffer<'arena, JSON<'arena>>) -> Vec<String> {
+        let format = JSONFormat::Compact;
+        let mut texts = vec![buffer.tree.to_text(&format)];
+        while buffer.tree.undo().is_ok() {
+            texts.push(buffer.tree.to_text(&format));
+        }
+        for _ in 1..texts.len() {
+            buffer.tree.redo().unwrap();
+        }
+        texts
+    }
+
+    #[test]
+    fn compaction() {
+        let mut rng = Rng::new(61);
+        let bounds = Bounds {
+            max_depth: 4,
+            max_nodes: 40,
+        };
+        for _ in 0..50 {
+            let arena = Arena::new();
+            let root = random_json(&mut rng, &arena, bounds);
+            let mut buffer = Buffer::new(&arena, root, None);
+            buffer.marks.set('a', Mark::new(CursorPath::root(), root));
+            for i in 0..20 {
+                let _ = Edit::random(&mut rng, buffer.tree.root()).apply(&mut buffer.tree);
+                if i == 10 {
+                    let text = buffer.tree.to_text(&JSONFormat::Compact);
+                    buffer.mark_saved(&text);
+                }
+            }
+            let _ = buffer.tree.undo();
+            let cursor_path = buffer.tree.cursor_path().clone();
+            let texts = undo_texts(&mut buffer);
+            let is_dirty = buffer.is_dirty();
+
+            let new_arena = Arena::new();
+            let compaction = buffer.compact(&new_arena);
+            assert!(std::ptr::eq(buffer.tree.arena(), &new_arena));
+            assert_eq!(compaction.nodes_before, arena.len());
+            assert_eq!(compaction.nodes_after, new_arena.len());
+            assert!(compaction.nodes_after <= compaction.nodes_before);
+            assert_eq!(buffer.tree.cursor_path(), &cursor_path);
+            assert_eq!(buffer.is_dirty(), is_dirty);
+            assert_eq!(undo_texts(&mut buffer), texts);
+            // The mark was set on a node in the old arena, but still finds its copy
+            while buffer.tree.undo().is_ok() {}
+            assert!(!std::ptr::eq(buffer.tree.root(), root));
+            assert_eq!(
+                buffer.marks.get('a').unwrap().follow(buffer.tree.root()),
+                Some(CursorPath::root())
+            );
+            // New edits go into the new arena
+            let nodes = new_arena.len();
+            let go_to_root = Action::MoveCursor(Direction::Root);
+            buffer.tree.execute_action(go_to_root).1.ok();
+            buffer.tree.execute_action(Action::InsertChild('t')).1.ok();
+            buffer.tree.execute_action(Action::Replace('t')).1.unwrap();
+            assert!(new_arena.len() > nodes);
+        }
+    }
+
+    #[test]
+    fn disk_versions() {
+        let dir = std::env::temp_dir().join(format!("sapling-test-disk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        std::fs::write(&path, "[1]").unwrap();
+        let arenas = typed_arena::Arena::new();
+        let loader = BufferLoader::new(&arenas, |text, arena| {
+            JSON::parse(text, arena).map_err(|e| e.to_string())
+        });
+        let mut buffer = loader.load(&path).unwrap();
+        assert_eq!(buffer.disk_version(), None);
+        // Writing the same text again doesn't count as a change
+        std::fs::write(&path, "[1]\n").unwrap();
+        std::fs::write(&path, "[1]").unwrap();
+        assert_eq!(buffer.disk_version(), None);
+
+        std::fs::write(&path, "[1, 2]").unwrap();
+        let version = buffer.disk_version().unwrap();
+        assert_eq!(version.text, "[1, 2]");
+        assert!(!buffer.is_ignored(&version));
+        buffer.ignore(&version);
+        assert!(buffer.is_ignored(&version));
+        // Read-only trees start their history again when they're reloaded
+        buffer.tree.set_read_only(true);
+        buffer.tree.move_cursor(Direction::Down).unwrap();
+        let root = loader.parse_in(&version.text, buffer.tree.arena()).unwrap();
+        buffer.reload(root, version);
+        assert_eq!(buffer.tree.to_text(&JSONFormat::Compact), "[1,2]");
+        assert_eq!(buffer.tree.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert!(!buffer.is_dirty());
+        buffer.tree.set_read_only(false);
+        assert_eq!(buffer.tree.undo(), Err(EditErr::NoChangesToUndo));
+        assert_eq!(buffer.disk_version(), None);
+        // Deleted files are simply written again
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(buffer.disk_version(), None);
+    }
+}
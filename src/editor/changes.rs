@@ -0,0 +1,122 @@
+//! Highlighting of the nodes that were changed by an undo or a redo, so that the user can see
+//! what moved under them.
+
+use crate::ast::diff::{diff_nodes, TreeEdit};
+use crate::ast::display_token::SyntaxCategory;
+use crate::ast::Ast;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// How long the changes are highlighted for
+pub const HIGHLIGHT_DURATION: Duration = Duration::from_secs(1);
+
+/// The nodes of a tree which differ from an older version of the tree, which are highlighted
+/// until [`HIGHLIGHT_DURATION`] has passed
+#[derive(Debug, Clone)]
+pub struct ChangeHighlight<'arena, Node: Ast<'arena>> {
+    /// The tree containing the changed nodes.  Nothing is highlighted in any other tree.
+    root: &'arena Node,
+    /// The addresses of every inserted or replaced node, and all of their descendants
+    added: HashSet<*const Node>,
+    /// The addresses of the nodes that had children removed
+    removed: HashSet<*const Node>,
+    /// When the highlight disappears
+    until: Instant,
+}
+
+impl<'arena, Node: Ast<'arena>> ChangeHighlight<'arena, Node> {
+    /// Finds the nodes that changed between the tree under `old` and the tree under `new`, which
+    /// will be highlighted from `now` on
+    pub fn new(old: &'arena Node, new: &'arena Node, now: Instant) -> Self {
+        let mut added = HashSet::new();
+        let mut removed = HashSet::new();
+        for (edit, node) in diff_nodes(old, new) {
+            match edit {
+                TreeEdit::Inserted(_) | TreeEdit::Replaced(_) => {
+                    added.extend(node.iter_pre_order().map(|n| n as *const Node));
+                }
+                TreeEdit::Deleted(_) => {
+                    removed.insert(node as *const Node);
+                }
+            }
+        }
+        ChangeHighlight {
+            root: new,
+            added,
+            removed,
+            until: now + HIGHLIGHT_DURATION,
+        }
+    }
+
+    /// Returns how much longer the highlight will be shown for after `now`, or `None` if it has
+    /// already disappeared
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.until
+            .checked_duration_since(now)
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Returns the category that `node` should be highlighted with at time `now`, if it's one of
+    /// the changed nodes of the tree under `root`
+    pub fn category(
+        &self,
+        root: &'arena Node,
+        node: &'arena Node,
+        now: Instant,
+    ) -> Option<SyntaxCategory> {
+        if !std::ptr::eq(root, self.root) || self.remaining(now).is_none() {
+            return None;
+        }
+        let address = node as *const Node;
+        if self.added.contains(&address) {
+            Some(SyntaxCategory::DiffAdded)
+        } else if self.removed.contains(&address) {
+            Some(SyntaxCategory::DiffRemoved)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangeHighlight, HIGHLIGHT_DURATION};
+    use crate::arena::Arena;
+    use crate::ast::display_token::SyntaxCategory;
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+    use std::time::Instant;
+
+    #[test]
+    fn highlights() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::True, J::Array(vec![J::Null])]).add_to_arena(&arena);
+        let [t, inner] = [old.children()[0], old.children()[1]];
+        let added = J::Object(vec![("a".to_string(), J::Null)]).add_to_arena(&arena);
+        let new_inner = arena.alloc(JSON::Array(vec![]));
+        let new = arena.alloc(JSON::Array(vec![t, new_inner, added]));
+
+        let now = Instant::now();
+        let highlight = ChangeHighlight::new(old, new, now);
+        // Every node of an inserted subtree is highlighted
+        for node in added.iter_pre_order() {
+            assert_eq!(
+                highlight.category(new, node, now),
+                Some(SyntaxCategory::DiffAdded)
+            );
+        }
+        assert_eq!(
+            highlight.category(new, new_inner, now),
+            Some(SyntaxCategory::DiffRemoved)
+        );
+        assert_eq!(highlight.category(new, t, now), None);
+        assert_eq!(highlight.category(new, new, now), None);
+        // Nothing is highlighted in other trees, or once the highlight has timed out
+        assert_eq!(highlight.category(old, inner, now), None);
+        let later = now + HIGHLIGHT_DURATION;
+        assert_eq!(highlight.remaining(now), Some(HIGHLIGHT_DURATION));
+        assert_eq!(highlight.remaining(later), None);
+        assert_eq!(highlight.category(new, added, later), None);
+    }
+}
@@ -0,0 +1,256 @@
+//! The system clipboard, which is the register `+`.  `"+y` copies the text of the cursor to the
+//! clipboard, and `"+p` parses the text on the clipboard and pastes the resulting tree.
+//!
+//! The clipboard is reached in one of two ways, chosen with the `clipboard` option:
+//! - `system` runs the clipboard program of the OS (e.g. `pbcopy` on macOS, or `wl-copy` or
+//!   `xclip` on Linux)
+//! - `osc52` asks the terminal to copy the text with an OSC 52 escape sequence.  This works over
+//!   SSH, since the terminal is on the user's own machine.  Very few terminals let programs read
+//!   their clipboard, so in this mode `"+p` pastes whatever Sapling last copied (and text copied
+//!   elsewhere can be pasted with the terminal's own paste instead).
+//!
+//! The default (`auto`) picks `osc52` inside SSH sessions and `system` everywhere else.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The names of the values that the `clipboard` option can take
+pub const CLIPBOARD_NAMES: [&str; 3] = ["auto", "system", "osc52"];
+
+/// The programs which are tried, in order, to copy text to the system clipboard
+const COPY_PROGRAMS: [&[&str]; 5] = [
+    &["pbcopy"],
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+    &["clip.exe"],
+];
+
+/// The programs which are tried, in order, to read the text on the system clipboard
+const PASTE_PROGRAMS: [&[&str]; 5] = [
+    &["pbpaste"],
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+    &["powershell.exe", "-NoProfile", "-Command", "Get-Clipboard"],
+];
+
+/// The ways of reaching the clipboard
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Backend {
+    /// Run the OS's clipboard programs
+    System,
+    /// Send OSC 52 escape sequences to the terminal
+    Osc52,
+}
+
+impl Backend {
+    /// Returns the `Backend` that the `clipboard` option value `name` stands for, or `None` if
+    /// `name` isn't one of [`CLIPBOARD_NAMES`]
+    pub fn named(name: &str) -> Option<Backend> {
+        match name {
+            "auto" if is_ssh_session() => Some(Backend::Osc52),
+            "auto" | "system" => Some(Backend::System),
+            "osc52" => Some(Backend::Osc52),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `true` if Sapling is being run over SSH
+fn is_ssh_session() -> bool {
+    std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some()
+}
+
+/// The clipboard, along with the text that Sapling last copied to it
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Clipboard {
+    last_copied: Option<String>,
+}
+
+impl Clipboard {
+    /// Creates a `Clipboard` which hasn't been copied to yet
+    pub fn new() -> Self {
+        Clipboard { last_copied: None }
+    }
+
+    /// Copies `text` to the clipboard.  If `terminal` is `false` then there's no terminal to send
+    /// OSC 52 sequences to, so the text is only remembered.
+    pub fn copy(
+        &mut self,
+        backend: Backend,
+        text: &str,
+        terminal: bool,
+    ) -> Result<(), ClipboardError> {
+        match backend {
+            Backend::System => run_copy_program(text)?,
+            Backend::Osc52 if terminal => {
+                let mut tty = std::fs::OpenOptions::new()
+                    .write(true)
+                    .open("/dev/tty")
+                    .map_err(|e| ClipboardError::Terminal(e.to_string()))?;
+                tty.write_all(osc52_sequence(text, std::env::var_os("TMUX").is_some()).as_bytes())
+                    .and_then(|()| tty.flush())
+                    .map_err(|e| ClipboardError::Terminal(e.to_string()))?;
+            }
+            Backend::Osc52 => {}
+        }
+        self.last_copied = Some(text.to_string());
+        Ok(())
+    }
+
+    /// Returns the text on the clipboard
+    pub fn paste(&self, backend: Backend) -> Result<String, ClipboardError> {
+        match backend {
+            Backend::System => run_paste_program(),
+            Backend::Osc52 => self.last_copied.clone().ok_or(ClipboardError::Unreadable),
+        }
+    }
+}
+
+/// Pipes `text` into the first of the [`COPY_PROGRAMS`] that runs successfully
+fn run_copy_program(text: &str) -> Result<(), ClipboardError> {
+    for program in COPY_PROGRAMS.iter() {
+        let child = Command::new(program[0])
+            .args(&program[1..])
+            .stdin(Stdio::piped())
+            // Some programs (like `xclip`) stay running to serve the clipboard, so they mustn't
+            // keep hold of Sapling's output
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => continue,
+        };
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        if child.wait().is_ok_and(|status| status.success()) && written {
+            return Ok(());
+        }
+    }
+    Err(ClipboardError::NoProgram(program_names(&COPY_PROGRAMS)))
+}
+
+/// Returns the output of the first of the [`PASTE_PROGRAMS`] that runs successfully
+fn run_paste_program() -> Result<String, ClipboardError> {
+    for program in PASTE_PROGRAMS.iter() {
+        let output = Command::new(program[0])
+            .args(&program[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            _ => continue,
+        }
+    }
+    Err(ClipboardError::NoProgram(program_names(&PASTE_PROGRAMS)))
+}
+
+/// Returns the names of some clipboard programs, separated by commas
+fn program_names(programs: &[&[&str]]) -> String {
+    programs
+        .iter()
+        .map(|program| program[0])
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the OSC 52 escape sequence which makes the terminal copy `text` to its clipboard.
+/// tmux only passes escape sequences through to the terminal if they are wrapped in a DCS
+/// sequence, so they are wrapped if `tmux` is `true`.
+pub fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+/// Encodes `bytes` as (padded) base64, which is how OSC 52 sequences carry their text
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The reason that the clipboard couldn't be copied to or read
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ClipboardError {
+    /// None of the clipboard programs (whose names are given) could be run
+    NoProgram(String),
+    /// The escape sequence couldn't be sent to the terminal
+    Terminal(String),
+    /// The terminal's clipboard can't be read, and Sapling hasn't copied anything to it
+    Unreadable,
+}
+
+impl std::fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClipboardError::NoProgram(names) => write!(
+                f,
+                "none of the clipboard programs ({}) worked (try `:set clipboard=osc52`)",
+                names
+            ),
+            ClipboardError::Terminal(message) => {
+                write!(f, "couldn't write to the terminal: {}", message)
+            }
+            ClipboardError::Unreadable => write!(
+                f,
+                "the terminal's clipboard can't be read, and nothing has been copied to it yet"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, osc52_sequence, Backend, Clipboard, ClipboardError};
+
+    #[test]
+    fn osc52() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"[true, null]"), "W3RydWUsIG51bGxd");
+        assert_eq!(osc52_sequence("fo", false), "\x1b]52;c;Zm8=\x07");
+        assert_eq!(
+            osc52_sequence("fo", true),
+            "\x1bPtmux;\x1b\x1b]52;c;Zm8=\x07\x1b\\"
+        );
+        // Without a terminal, copying only remembers the text
+        let mut clipboard = Clipboard::new();
+        assert_eq!(
+            clipboard.paste(Backend::Osc52),
+            Err(ClipboardError::Unreadable)
+        );
+        clipboard.copy(Backend::Osc52, "[1]", false).unwrap();
+        assert_eq!(clipboard.paste(Backend::Osc52), Ok("[1]".to_string()));
+        assert_eq!(Backend::named("osc52"), Some(Backend::Osc52));
+        assert_eq!(Backend::named("system"), Some(Backend::System));
+        assert_eq!(Backend::named("x11"), None);
+    }
+}
@@ -0,0 +1,851 @@
+//! Parsing of the commands typed after `:` (like Vim's command-line mode).
+
+use crate::ast::json::query::Query;
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::replace::ReplaceTarget;
+use crate::editable_tree::{EditErr, Side};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The options which `set <option>` turns on and `set no<option>` turns off, rather than
+/// showing their values
+const FLAG_NAMES: [&str; 3] = ["readonly", "number", "relativenumber"];
+
+/// A command that can be typed after `:`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Command {
+    /// `%s/<pattern>/<replacement>/<flags>`: replace every occurrence of `pattern` with
+    /// `replacement` throughout the tree.  The flags are any combination of:
+    /// - `k`: replace inside keys (e.g. the keys of JSON fields)
+    /// - `v`: replace inside values.  If neither `k` nor `v` is given, only values are replaced
+    /// - `c`: ask for confirmation before replacing each match
+    Substitute {
+        pattern: String,
+        replacement: String,
+        target: ReplaceTarget,
+        confirm: bool,
+    },
+    /// `goto <path>`: move the cursor to the node at a given path (e.g. `goto 0.3.1`)
+    Goto(CursorPath),
+    /// `marks`: list every mark that has been set
+    Marks,
+    /// `registers`: list every register that isn't empty, with a preview of its contents
+    Registers,
+    /// `errors`: list every problem that makes the tree invalid (e.g. duplicate keys)
+    Errors,
+    /// `w [path]`: write the tree to `path`, or to the file it was loaded from if no path is
+    /// given.  `wq [path]` does the same, then quits (if the write succeeded).  `w!` (where
+    /// `force` is `true`) writes read-only buffers too.
+    Write {
+        path: Option<PathBuf>,
+        quit: bool,
+        force: bool,
+    },
+    /// `q` (or `qa`): quit, unless any buffer has unsaved changes.  `q!` (where `force` is
+    /// `true`) quits anyway.
+    Quit { force: bool },
+    /// `set <option>=<value>` (or `set <option> <value>`): set an option to `value`.  `set
+    /// <option>?` (or just `set <option>`) shows the option's current value, and has a `value`
+    /// of `None`.
+    Set {
+        option: String,
+        value: Option<String>,
+    },
+    /// `pipe <cmd>`: feed the text of the cursor to a shell command, and replace the cursor with
+    /// the tree parsed from what the command prints
+    Pipe(String),
+    /// `query <query>`: search for the nodes found by a [`Query`] (e.g. `query $.store.book[2]`),
+    /// and move the cursor to the first one.  `query!` (where `list` is `true`) also lists every
+    /// match in a box over the tree.
+    Query { query: Query, list: bool },
+    /// `!<cmd>`: run a shell command with the terminal (putting the buffer's file in place of
+    /// `%`), and come back once Enter is pressed
+    Shell(String),
+    /// `e <path>`: open the file at `path` in a new buffer (or switch to it, if it's already
+    /// open)
+    Edit(PathBuf),
+    /// `bn` and `bp`: switch to the next or previous buffer, wrapping around at the ends
+    SwitchBuffer(Side),
+    /// `ls`: list every open buffer, and whether it has unsaved changes
+    ListBuffers,
+    /// `themes`: list the built-in themes, which can be chosen with `set theme=<name>`
+    ListThemes,
+    /// `messages`: show the log (including any warnings about the colour scheme) in a box over
+    /// the tree
+    Messages,
+    /// `crash`: panic, to test what happens when Sapling crashes.  This only exists with the
+    /// `testing` feature.
+    #[cfg(feature = "testing")]
+    Crash,
+    /// `stats`: show how many nodes the current buffer has allocated and how much memory they
+    /// use, along with how many of them are in the current tree
+    Stats,
+    /// `stats tree`: show how many nodes of each kind the current tree has, how deep it is, which
+    /// node has the most children and how many lines the tree's text has
+    TreeStats,
+    /// `changes`: list the differences between the current tree and the tree as it was when it
+    /// was last saved
+    Changes,
+    /// `export <format> <path>`: write the current tree to `path` in a format that Sapling can't
+    /// read back (e.g. `export dot tree.dot`), or write how it changed since it was loaded
+    Export { format: ExportFormat, path: PathBuf },
+    /// `map <keys> <keystroke>`: bind `keys` (written like in the `[keys]` table of the config
+    /// file) to the keystroke with the given name, e.g. `map gg move-root`
+    Map { keys: String, keystroke: String },
+    /// `unmap <keys>`: stop `keys` from being bound to anything
+    Unmap(String),
+}
+
+/// The formats that `:export` can write trees in
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ExportFormat {
+    /// A Graphviz DOT graph of the tree's structure (see [`Ast::write_dot`](crate::ast::Ast::write_dot))
+    Dot,
+    /// The text of the tree as syntax highlighted HTML, coloured by the current colour scheme
+    Html,
+    /// A JSON Patch which turns the tree as it was loaded into the current tree (see
+    /// [`diff_patch`](crate::ast::json_patch::diff_patch))
+    Patch,
+}
+
+impl ExportFormat {
+    /// Every export format, in the order they're listed to the user
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Dot, ExportFormat::Html, ExportFormat::Patch];
+
+    /// Returns the name used to choose this format in `:export`
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Dot => "dot",
+            ExportFormat::Html => "html",
+            ExportFormat::Patch => "patch",
+        }
+    }
+
+    /// Returns the format called `name`, if there is one
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name() == name)
+    }
+}
+
+/// The arguments given to a command, which are split at whitespace (see [`split_args`])
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Args {
+    /// `true` if the command's name was followed by a `!` (e.g. `q!`)
+    pub bang: bool,
+    pub words: Vec<String>,
+}
+
+impl Args {
+    /// Returns an error unless at most `max` arguments were given
+    fn at_most(&self, max: usize, name: &str) -> Result<(), String> {
+        match (max, self.words.len() > max) {
+            (_, false) => Ok(()),
+            (0, true) => Err(format!("'{}' doesn't take any arguments", name)),
+            (1, true) => Err(format!("'{}' takes at most one argument", name)),
+            (_, true) => Err(format!("'{}' takes at most {} arguments", name, max)),
+        }
+    }
+}
+
+/// A function that turns the arguments of a command into the [`Command`] to run, returning the
+/// reason why if the arguments are invalid
+pub type CommandParser = fn(&Args) -> Result<Command, String>;
+
+/// The commands that can be typed after `:`, indexed by name.  Commands are added by
+/// [`register`](CommandRegistry::register)ing a parser for their arguments, and the
+/// [`Default`] registry contains every built-in command.
+#[derive(Debug, Clone)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, CommandParser>,
+}
+
+impl CommandRegistry {
+    /// Creates a `CommandRegistry` which doesn't contain any commands
+    pub fn empty() -> Self {
+        CommandRegistry {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a command called `name`, replacing any command that already has that name
+    pub fn register(&mut self, name: &'static str, parser: CommandParser) {
+        self.commands.insert(name, parser);
+    }
+
+    /// Returns the name of every command, in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.commands.keys().copied()
+    }
+
+    /// Parses the text of a command (without the leading `:`)
+    pub fn parse(&self, command: &str) -> Result<Command, EditErr> {
+        let invalid = |reason: &str| EditErr::InvalidCommand {
+            command: command.to_string(),
+            reason: reason.to_string(),
+        };
+
+        // Substitutions don't need a space between the command and its arguments
+        if let Some(args) = command.strip_prefix("%s") {
+            return parse_substitute(args).map_err(|reason| invalid(&reason));
+        }
+        // Everything after `pipe` is given to the shell as it is, without being split up
+        if let Some(shell_command) = command.trim_start().strip_prefix("pipe") {
+            if shell_command.is_empty() || shell_command.starts_with(char::is_whitespace) {
+                return match shell_command.trim() {
+                    "" => Err(invalid("no shell command to pipe through")),
+                    shell_command => Ok(Command::Pipe(shell_command.to_string())),
+                };
+            }
+        }
+        // Queries can contain spaces and quotes, so everything after `query` is one query
+        if let Some(rest) = command.trim_start().strip_prefix("query") {
+            let (list, rest) = match rest.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, rest),
+            };
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                return match rest.trim() {
+                    "" => Err(invalid("no query to run")),
+                    text => Query::parse(text)
+                        .map(|query| Command::Query { query, list })
+                        .map_err(|e| invalid(&e.to_string())),
+                };
+            }
+        }
+        // Everything after `!` is given to the shell as it is
+        if let Some(shell_command) = command.trim_start().strip_prefix('!') {
+            return match shell_command.trim() {
+                "" => Err(invalid("no shell command to run")),
+                shell_command => Ok(Command::Shell(shell_command.to_string())),
+            };
+        }
+        let command = command.trim_start();
+        let name_end = command
+            .find(|c: char| c.is_whitespace() || c == '!')
+            .unwrap_or(command.len());
+        let (name, rest) = command.split_at(name_end);
+        let (bang, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        // Arguments have to be separated from the name (so `goto0` isn't `goto 0`)
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return Err(invalid("unknown command"));
+        }
+        let parser = self
+            .commands
+            .get(name)
+            .ok_or_else(|| invalid("unknown command"))?;
+        let words = split_args(rest).map_err(|reason| invalid(&reason))?;
+        parser(&Args { bang, words }).map_err(|reason| invalid(&reason))
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = CommandRegistry::empty();
+        registry.register("goto", |args| {
+            args.at_most(1, "goto")?;
+            match args.words.first() {
+                Some(path) => path.parse().map(Command::Goto).map_err(|e| e.to_string()),
+                None => Ok(Command::Goto(CursorPath::root())),
+            }
+        });
+        registry.register("marks", |args| {
+            args.at_most(0, "marks")?;
+            Ok(Command::Marks)
+        });
+        registry.register("registers", |args| {
+            args.at_most(0, "registers")?;
+            Ok(Command::Registers)
+        });
+        registry.register("errors", |args| {
+            args.at_most(0, "errors")?;
+            Ok(Command::Errors)
+        });
+        registry.register("w", |args| {
+            args.at_most(1, "w")?;
+            let path = args.words.first().map(PathBuf::from);
+            Ok(Command::Write {
+                path,
+                quit: false,
+                force: args.bang,
+            })
+        });
+        registry.register("wq", |args| {
+            args.at_most(1, "wq")?;
+            let path = args.words.first().map(PathBuf::from);
+            Ok(Command::Write {
+                path,
+                quit: true,
+                force: args.bang,
+            })
+        });
+        registry.register("q", |args| {
+            args.at_most(0, "q")?;
+            Ok(Command::Quit { force: args.bang })
+        });
+        // Quitting always closes every buffer, so `qa` is the same as `q`
+        registry.register("qa", |args| {
+            args.at_most(0, "qa")?;
+            Ok(Command::Quit { force: args.bang })
+        });
+        registry.register("set", |args| {
+            args.at_most(2, "set")?;
+            let (option, value) = match args.words.as_slice() {
+                [] => return Err("expected an option".to_string()),
+                [option, value] => (option.as_str(), Some(value.clone())),
+                // Like in Vim, `set number` and `set nonumber` turn the flag on and off
+                [option] if FLAG_NAMES.contains(&option.as_str()) => {
+                    (option.as_str(), Some("true".to_string()))
+                }
+                [option]
+                    if (option.strip_prefix("no")).is_some_and(|f| FLAG_NAMES.contains(&f)) =>
+                {
+                    (&option[2..], Some("false".to_string()))
+                }
+                [option] => match option.split_once('=') {
+                    Some((option, value)) => (option, Some(value.to_string())),
+                    None => (option.strip_suffix('?').unwrap_or(option), None),
+                },
+                _ => unreachable!(),
+            };
+            if option.is_empty() {
+                return Err("expected an option".to_string());
+            }
+            Ok(Command::Set {
+                option: option.to_string(),
+                value,
+            })
+        });
+        registry.register("e", |args| {
+            args.at_most(1, "e")?;
+            match args.words.first() {
+                Some(path) => Ok(Command::Edit(PathBuf::from(path))),
+                None => Err("expected a path".to_string()),
+            }
+        });
+        registry.register("bn", |args| {
+            args.at_most(0, "bn")?;
+            Ok(Command::SwitchBuffer(Side::Next))
+        });
+        registry.register("bp", |args| {
+            args.at_most(0, "bp")?;
+            Ok(Command::SwitchBuffer(Side::Prev))
+        });
+        registry.register("ls", |args| {
+            args.at_most(0, "ls")?;
+            Ok(Command::ListBuffers)
+        });
+        registry.register("themes", |args| {
+            args.at_most(0, "themes")?;
+            Ok(Command::ListThemes)
+        });
+        registry.register("messages", |args| {
+            args.at_most(0, "messages")?;
+            Ok(Command::Messages)
+        });
+        #[cfg(feature = "testing")]
+        registry.register("crash", |args| {
+            args.at_most(0, "crash")?;
+            Ok(Command::Crash)
+        });
+        registry.register("stats", |args| {
+            args.at_most(1, "stats")?;
+            match args.words.first().map(String::as_str) {
+                None => Ok(Command::Stats),
+                Some("tree") => Ok(Command::TreeStats),
+                Some(other) => Err(format!("unknown statistics '{}' (expected 'tree')", other)),
+            }
+        });
+        registry.register("map", |args| {
+            args.at_most(2, "map")?;
+            match args.words.as_slice() {
+                [] => Err("expected some keys".to_string()),
+                [_] => Err("expected the name of a keystroke".to_string()),
+                [keys, keystroke] => Ok(Command::Map {
+                    keys: keys.clone(),
+                    keystroke: keystroke.clone(),
+                }),
+                _ => unreachable!(),
+            }
+        });
+        registry.register("unmap", |args| {
+            args.at_most(1, "unmap")?;
+            match args.words.first() {
+                Some(keys) => Ok(Command::Unmap(keys.clone())),
+                None => Err("expected some keys".to_string()),
+            }
+        });
+        registry.register("changes", |args| {
+            args.at_most(0, "changes")?;
+            Ok(Command::Changes)
+        });
+        registry.register("export", |args| {
+            args.at_most(2, "export")?;
+            let names: Vec<_> = ExportFormat::ALL.iter().map(|f| f.name()).collect();
+            match args.words.as_slice() {
+                [] => Err(format!("expected a format ({})", names.join(", "))),
+                [_] => Err("expected a path".to_string()),
+                [name, path] => match ExportFormat::from_name(name) {
+                    Some(format) => Ok(Command::Export {
+                        format,
+                        path: PathBuf::from(path),
+                    }),
+                    None => Err(format!(
+                        "unknown format '{}' (expected one of {})",
+                        name,
+                        names.join(", ")
+                    )),
+                },
+                _ => unreachable!(),
+            }
+        });
+        registry
+    }
+}
+
+/// Parses the text of a command (without the leading `:`), using the built-in commands
+pub fn parse_command(command: &str) -> Result<Command, EditErr> {
+    CommandRegistry::default().parse(command)
+}
+
+/// Splits the arguments of a command at whitespace, like a shell does.  Text inside `"` or `'`
+/// quotes is kept in one argument (so `"my file.json"` is one argument).  Outside `'` quotes, a
+/// `\` escapes a quote, a space or another `\` after it (any other `\` is left untouched, so
+/// Windows paths can be typed as they are).  If a quote isn't closed, the reason is returned.
+pub fn split_args(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    // The argument being read, which is `None` between arguments
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let is_escapable = |c: &char| matches!(c, '"' | '\'' | '\\') || c.is_whitespace();
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') if chars.peek().is_some_and(is_escapable) => {
+                let escaped = chars.next().unwrap();
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("missing a closing {}", q));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Parses the arguments of a `%s` command (i.e. everything after the `%s`).  If the arguments
+/// are invalid, the reason why is returned.
+fn parse_substitute(args: &str) -> Result<Command, String> {
+    // Like in Vim, the delimiter is whatever char comes straight after the `s`
+    let mut chars = args.chars();
+    let delimiter = chars.next().ok_or("expected a pattern")?;
+    if delimiter.is_alphanumeric() || delimiter == '\\' || delimiter.is_whitespace() {
+        return Err("the delimiter can't be a letter, digit, '\\' or space".to_string());
+    }
+    let mut parts = split_unescaped(chars.as_str(), delimiter).into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    if pattern.is_empty() {
+        return Err("the pattern can't be empty".to_string());
+    }
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+    if parts.next().is_some() {
+        return Err("too many delimiters".to_string());
+    }
+
+    let mut target = ReplaceTarget {
+        keys: false,
+        values: false,
+    };
+    let mut confirm = false;
+    for flag in flags.chars() {
+        match flag {
+            'k' => target.keys = true,
+            'v' => target.values = true,
+            'c' => confirm = true,
+            _ => return Err(format!("unknown flag '{}'", flag)),
+        }
+    }
+    if !target.keys && !target.values {
+        target = ReplaceTarget::default();
+    }
+    Ok(Command::Substitute {
+        pattern,
+        replacement,
+        target,
+        confirm,
+    })
+}
+
+/// Splits a string at every `delimiter` that isn't escaped by a `\`, removing the escapes from
+/// the delimiters (a `\` that doesn't escape a delimiter is left untouched).
+fn split_unescaped(string: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = string.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&delimiter) {
+            parts.last_mut().unwrap().push(delimiter);
+            chars.next();
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_command, split_args, Command, CommandRegistry, ExportFormat};
+    use crate::ast::json::query::Query;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::replace::ReplaceTarget;
+    use crate::editable_tree::Side;
+
+    #[test]
+    fn goto() {
+        let path = CursorPath::from_vec;
+        assert_eq!(
+            parse_command("goto 0.3.1"),
+            Ok(Command::Goto(path(vec![0, 3, 1])))
+        );
+        assert_eq!(parse_command("goto  2 "), Ok(Command::Goto(path(vec![2]))));
+        assert_eq!(parse_command("goto"), Ok(Command::Goto(path(vec![]))));
+        assert_eq!(parse_command("goto root"), Ok(Command::Goto(path(vec![]))));
+        assert_eq!(
+            parse_command("goto 0.x").unwrap_err().to_string(),
+            "Invalid command ':goto 0.x': 'x' isn't a valid child index"
+        );
+        assert!(parse_command("got 0").is_err());
+        assert!(parse_command("goto0").is_err());
+    }
+
+    #[test]
+    fn argument_splitting() {
+        let split = |text| split_args(text).unwrap();
+        assert_eq!(split(""), Vec::<String>::new());
+        assert_eq!(split("  a  bc d "), vec!["a", "bc", "d"]);
+        // Quotes keep whitespace in one argument, and can appear part-way through a word
+        assert_eq!(split(r#""my file.json" x"#), vec!["my file.json", "x"]);
+        assert_eq!(split("'a \"b\" c'"), vec![r#"a "b" c"#]);
+        assert_eq!(split(r#"a"b c"d"#), vec!["ab cd"]);
+        assert_eq!(split(r#""" ''"#), vec!["", ""]);
+        // Backslashes escape quotes, spaces and backslashes, but nothing inside single quotes
+        assert_eq!(
+            split(r#"my\ file \"x\" \\"#),
+            vec!["my file", r#""x""#, r"\"]
+        );
+        assert_eq!(split(r#""a \" b""#), vec![r#"a " b"#]);
+        assert_eq!(split(r"'a\ b'"), vec![r"a\ b"]);
+        assert_eq!(split(r"C:\data\x.json"), vec![r"C:\data\x.json"]);
+
+        assert_eq!(
+            split_args(r#""abc"#),
+            Err("missing a closing \"".to_string())
+        );
+        assert_eq!(split_args("a 'b"), Err("missing a closing '".to_string()));
+    }
+
+    #[test]
+    fn builtin_commands() {
+        for (command, expected) in &[
+            (
+                "w",
+                Command::Write {
+                    path: None,
+                    quit: false,
+                    force: false,
+                },
+            ),
+            (
+                "w 'new file.json'",
+                Command::Write {
+                    path: Some("new file.json".into()),
+                    quit: false,
+                    force: false,
+                },
+            ),
+            (
+                "wq",
+                Command::Write {
+                    path: None,
+                    quit: true,
+                    force: false,
+                },
+            ),
+            (
+                "w! copy.json",
+                Command::Write {
+                    path: Some("copy.json".into()),
+                    quit: false,
+                    force: true,
+                },
+            ),
+            (
+                "set noreadonly",
+                Command::Set {
+                    option: "readonly".to_string(),
+                    value: Some("false".to_string()),
+                },
+            ),
+            (
+                "set nonumber",
+                Command::Set {
+                    option: "number".to_string(),
+                    value: Some("false".to_string()),
+                },
+            ),
+            (
+                "set relativenumber",
+                Command::Set {
+                    option: "relativenumber".to_string(),
+                    value: Some("true".to_string()),
+                },
+            ),
+            (
+                "set readonly?",
+                Command::Set {
+                    option: "readonly".to_string(),
+                    value: None,
+                },
+            ),
+            ("q", Command::Quit { force: false }),
+            ("q!", Command::Quit { force: true }),
+            ("qa", Command::Quit { force: false }),
+            ("qa!", Command::Quit { force: true }),
+            (
+                "set scrolloff 5",
+                Command::Set {
+                    option: "scrolloff".to_string(),
+                    value: Some("5".to_string()),
+                },
+            ),
+            (
+                "set tab_width",
+                Command::Set {
+                    option: "tab_width".to_string(),
+                    value: None,
+                },
+            ),
+            (
+                "set indent_width=2",
+                Command::Set {
+                    option: "indent_width".to_string(),
+                    value: Some("2".to_string()),
+                },
+            ),
+            (
+                "set indent_width?",
+                Command::Set {
+                    option: "indent_width".to_string(),
+                    value: None,
+                },
+            ),
+            (
+                "set color_scheme=",
+                Command::Set {
+                    option: "color_scheme".to_string(),
+                    value: Some(String::new()),
+                },
+            ),
+            ("e data.json", Command::Edit("data.json".into())),
+            ("!git diff %", Command::Shell("git diff %".to_string())),
+            (" ! ls -l ", Command::Shell("ls -l".to_string())),
+            ("pipe jq '.foo'", Command::Pipe("jq '.foo'".to_string())),
+            (
+                "query $['first name']",
+                Command::Query {
+                    query: Query::parse("$['first name']").unwrap(),
+                    list: false,
+                },
+            ),
+            (
+                "query! ..price",
+                Command::Query {
+                    query: Query::parse("$..price").unwrap(),
+                    list: true,
+                },
+            ),
+            ("bn", Command::SwitchBuffer(Side::Next)),
+            ("bp", Command::SwitchBuffer(Side::Prev)),
+            ("ls", Command::ListBuffers),
+            ("themes", Command::ListThemes),
+            ("messages", Command::Messages),
+            ("stats", Command::Stats),
+            ("stats tree", Command::TreeStats),
+            ("changes", Command::Changes),
+            (
+                "map 'g g' move-root",
+                Command::Map {
+                    keys: "g g".to_string(),
+                    keystroke: "move-root".to_string(),
+                },
+            ),
+            ("unmap <C-k>", Command::Unmap("<C-k>".to_string())),
+            (
+                "export html tree.html",
+                Command::Export {
+                    format: ExportFormat::Html,
+                    path: "tree.html".into(),
+                },
+            ),
+            (
+                "export dot 'my tree.dot'",
+                Command::Export {
+                    format: ExportFormat::Dot,
+                    path: "my tree.dot".into(),
+                },
+            ),
+            (
+                "export patch edits.json",
+                Command::Export {
+                    format: ExportFormat::Patch,
+                    path: "edits.json".into(),
+                },
+            ),
+        ] {
+            assert_eq!(parse_command(command).as_ref(), Ok(expected), "{}", command);
+        }
+        for command in &[
+            "w a b",
+            "q x",
+            "q !",
+            "set",
+            "set a b c",
+            "set =2",
+            "set ?",
+            "nope",
+            "w 'a",
+            "e",
+            "e a b",
+            "bn 2",
+            "ls -a",
+            "stats 1",
+            "stats tree 1",
+            "changes 1",
+            "map",
+            "map x",
+            "map x y z",
+            "unmap",
+            "export",
+            "export dot",
+            "export dot a b",
+        ] {
+            assert!(parse_command(command).is_err(), "{}", command);
+        }
+        assert_eq!(
+            parse_command("export png tree.png")
+                .unwrap_err()
+                .to_string(),
+            "Invalid command ':export png tree.png': unknown format 'png' (expected one of dot, html, patch)"
+        );
+        assert_eq!(
+            parse_command("frobnicate now").unwrap_err().to_string(),
+            "Invalid command ':frobnicate now': unknown command"
+        );
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = CommandRegistry::empty();
+        assert!(registry.parse("marks").is_err());
+        // Handlers are given the split arguments
+        registry.register("jump", |args| {
+            let path = args.words.join(".");
+            path.parse().map(Command::Goto).map_err(|e| e.to_string())
+        });
+        assert_eq!(
+            registry.parse("jump 0 '2'"),
+            Ok(Command::Goto(CursorPath::from_vec(vec![0, 2])))
+        );
+        assert_eq!(registry.names().collect::<Vec<_>>(), vec!["jump"]);
+        assert!(CommandRegistry::default().names().any(|name| name == "wq"));
+    }
+
+    #[test]
+    fn marks() {
+        assert_eq!(parse_command("marks"), Ok(Command::Marks));
+        assert_eq!(parse_command("marks "), Ok(Command::Marks));
+        assert_eq!(parse_command("registers"), Ok(Command::Registers));
+        assert!(parse_command("marks a").is_err());
+        assert_eq!(parse_command("errors"), Ok(Command::Errors));
+        assert!(parse_command("errors 1").is_err());
+    }
+
+    #[test]
+    fn substitute() {
+        fn sub(
+            pattern: &str,
+            replacement: &str,
+            keys: bool,
+            values: bool,
+            confirm: bool,
+        ) -> Command {
+            Command::Substitute {
+                pattern: pattern.to_string(),
+                replacement: replacement.to_string(),
+                target: ReplaceTarget { keys, values },
+                confirm,
+            }
+        }
+
+        for (command, expected) in &[
+            (
+                "%s/staging/production/",
+                sub("staging", "production", false, true, false),
+            ),
+            (
+                "%s/staging/production",
+                sub("staging", "production", false, true, false),
+            ),
+            ("%s/x/", sub("x", "", false, true, false)),
+            ("%s/x", sub("x", "", false, true, false)),
+            ("%s/a/b/k", sub("a", "b", true, false, false)),
+            ("%s/a/b/kv", sub("a", "b", true, true, false)),
+            ("%s/a/b/vc", sub("a", "b", false, true, true)),
+            ("%s/a/b/c", sub("a", "b", false, true, true)),
+            // Other delimiters can be used, and delimiters can be escaped
+            ("%s#a/b#c#", sub("a/b", "c", false, true, false)),
+            (r"%s/a\/b/c\d/", sub("a/b", r"c\d", false, true, false)),
+        ] {
+            assert_eq!(parse_command(command).as_ref(), Ok(expected), "{}", command);
+        }
+
+        for command in &[
+            "",
+            "s/a/b/",
+            "s",
+            "%s",
+            "%s//b/",
+            "%saba",
+            "%s a b ",
+            "%s/a/b/x",
+            "%s/a/b/c/d",
+            "!",
+            "pipe",
+            "pipejq",
+            "query",
+            "query! ",
+            "query $.",
+            "queryx",
+        ] {
+            assert!(parse_command(command).is_err(), "{}", command);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! What happens when Sapling panics.  Whilst the editor is using the terminal, the terminal is
+//! in raw mode and showing its alternate screen, so a panic message printed straight away would
+//! be drawn over (and then wiped away with) the editor.  Instead, the panic hook writes the
+//! message and a backtrace to the crash log, and holds on to the message until the terminal has
+//! been given back (see [`TerminalGuard`]).  Before the panic carries on, the editor writes the
+//! text of every buffer with unsaved changes to a [`recovery_path`], so that no work is lost.
+
+use std::io::Write;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Whether a [`TerminalGuard`] exists, so that panic messages have to wait to be printed
+static IN_TERMINAL: AtomicBool = AtomicBool::new(false);
+/// The message of the last panic that happened whilst the terminal was in use, which hasn't been
+/// printed yet
+static DEFERRED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Returns the file that crash reports are appended to: `crash.log` in the
+/// [`state_dir`](crate::logging::state_dir)
+pub fn crash_log_path() -> Option<PathBuf> {
+    Some(crate::logging::state_dir()?.join("crash.log"))
+}
+
+/// Returns the file that the unsaved changes to the file at `path` are written to if Sapling
+/// crashes
+pub fn recovery_path(path: &Path) -> PathBuf {
+    let mut recovery_path = path.as_os_str().to_owned();
+    recovery_path.push(".crash-recovery");
+    PathBuf::from(recovery_path)
+}
+
+/// Installs the panic hook, which appends a report of every panic (with a backtrace) to
+/// `crash_log` (if it's given).  Whilst a [`TerminalGuard`] exists, the panic's message is kept
+/// until the guard is dropped.  Otherwise it's printed by the hook that was installed before.
+pub fn install_hook(crash_log: Option<PathBuf>) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = report(info);
+        if let Some(path) = &crash_log {
+            // A panic inside the hook would abort, so errors are only logged
+            if let Err(e) = append(path, &report) {
+                log::error!("Couldn't write the crash log {}: {}", path.display(), e);
+            }
+        }
+        log::error!("{}", panic_message(info));
+        if IN_TERMINAL.load(Ordering::SeqCst) {
+            let message = match &crash_log {
+                Some(path) => format!(
+                    "{}\nThe crash log was written to {}",
+                    panic_message(info),
+                    path.display()
+                ),
+                None => panic_message(info),
+            };
+            if let Ok(mut deferred) = DEFERRED.lock() {
+                *deferred = Some(message);
+            }
+        } else {
+            previous(info);
+        }
+    }));
+}
+
+/// Returns the message of a panic that's been held back whilst the terminal was in use, if
+/// there was one
+pub fn take_deferred() -> Option<String> {
+    DEFERRED.lock().ok()?.take()
+}
+
+/// Exists whilst the editor is using the terminal, and stops panic messages from being printed
+/// until the terminal has been given back.  Create the guard before the terminal, so that it's
+/// dropped after the terminal is.  When it's dropped, it prints any message that was held back.
+#[derive(Debug)]
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    /// Starts holding back panic messages
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        IN_TERMINAL.store(true, Ordering::SeqCst);
+        TerminalGuard { _private: () }
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        IN_TERMINAL.store(false, Ordering::SeqCst);
+        if let Some(message) = take_deferred() {
+            let _ = writeln!(std::io::stderr(), "{}", message);
+        }
+    }
+}
+
+/// Returns the message of a panic, along with where it happened
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(no message)");
+    match info.location() {
+        Some(location) => format!("Sapling crashed at {}: {}", location, message),
+        None => format!("Sapling crashed: {}", message),
+    }
+}
+
+/// Returns the report of a panic that's written to the crash log, which has the time, the
+/// message and a backtrace
+fn report(info: &PanicHookInfo) -> String {
+    format!(
+        "{} {}\n{}\n",
+        crate::logging::timestamp(crate::logging::now()),
+        panic_message(info),
+        std::backtrace::Backtrace::force_capture()
+    )
+}
+
+/// Appends `text` to the file at `path`, creating it (and its directory) if it doesn't exist
+fn append(path: &Path, text: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recovery_path;
+    use std::path::Path;
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            recovery_path(Path::new("dir/data.json")),
+            Path::new("dir/data.json.crash-recovery")
+        );
+    }
+}
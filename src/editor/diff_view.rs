@@ -0,0 +1,217 @@
+//! Comparing two trees side by side (with `sapling --diff <old> <new>`).  Each tree is shown in a
+//! window of its own, with the nodes that differ from the other tree highlighted, and `]c`/`[c`
+//! move both windows through the differences together.
+
+use crate::ast::diff::{differences, TreeEdit};
+use crate::ast::display_token::SyntaxCategory;
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::Side;
+use std::collections::HashMap;
+
+/// A run of differences next to each other (e.g. a few consecutive elements of an array that were
+/// replaced), which is where `]c` and `[c` move the cursors to
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Hunk {
+    /// Where the first difference of the hunk is in the old tree and in the new tree
+    pub paths: [CursorPath; 2],
+    /// How many differences the hunk contains
+    pub len: usize,
+}
+
+/// The differences between the trees of two buffers, which are shown side by side.  Everything
+/// that comes in pairs is given as `[old, new]`.
+#[derive(Debug, Clone)]
+pub struct DiffView<'arena, Node: Ast<'arena>> {
+    /// The indices of the two buffers being compared
+    buffers: [usize; 2],
+    /// The trees that were compared.  Nothing is highlighted in any other tree.
+    roots: [&'arena Node; 2],
+    hunks: Vec<Hunk>,
+    /// The category that every highlighted node of each tree is shown with, indexed by address
+    categories: [HashMap<*const Node, SyntaxCategory>; 2],
+    /// The hunk that the cursors were last moved to, if they haven't moved since
+    current: Option<usize>,
+}
+
+impl<'arena, Node: Ast<'arena>> DiffView<'arena, Node> {
+    /// Compares the tree under `old` (from the buffer at index `buffers[0]`) with the tree under
+    /// `new` (from the buffer at index `buffers[1]`)
+    pub fn new(buffers: [usize; 2], old: &'arena Node, new: &'arena Node) -> Self {
+        let mut categories = [HashMap::new(), HashMap::new()];
+        let mut highlight = |tree: usize, node: &'arena Node, category: SyntaxCategory| {
+            let nodes = node.iter_pre_order().map(|n| (n as *const Node, category));
+            categories[tree].extend(nodes);
+        };
+        let mut hunks: Vec<Hunk> = Vec::new();
+        let mut last_path: Option<CursorPath> = None;
+        for difference in differences(old, new) {
+            match difference.edit {
+                TreeEdit::Deleted(_) => highlight(0, difference.old, SyntaxCategory::DiffRemoved),
+                TreeEdit::Inserted(_) => highlight(1, difference.new, SyntaxCategory::DiffAdded),
+                TreeEdit::Replaced(_) => {
+                    highlight(0, difference.old, SyntaxCategory::DiffChanged);
+                    highlight(1, difference.new, SyntaxCategory::DiffChanged);
+                }
+            }
+            let path = difference.edit.path().clone();
+            match (hunks.last_mut(), &last_path) {
+                (Some(hunk), Some(last)) if follows_on(last, &path) => hunk.len += 1,
+                _ => hunks.push(Hunk {
+                    paths: [difference.old_path, difference.new_path],
+                    len: 1,
+                }),
+            }
+            last_path = Some(path);
+        }
+        DiffView {
+            buffers,
+            roots: [old, new],
+            hunks,
+            categories,
+            current: None,
+        }
+    }
+
+    /// Returns the indices of the buffers being compared
+    pub fn buffers(&self) -> [usize; 2] {
+        self.buffers
+    }
+
+    /// Returns every hunk, in the order of the trees
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    /// Returns how many differences there are between the trees
+    pub fn difference_count(&self) -> usize {
+        self.hunks.iter().map(|hunk| hunk.len).sum()
+    }
+
+    /// Returns the category that `node` should be highlighted with, if it's one of the nodes of
+    /// the tree under `root` which differ from the other tree
+    pub fn category(&self, root: &'arena Node, node: &'arena Node) -> Option<SyntaxCategory> {
+        let tree = self.roots.iter().position(|&r| std::ptr::eq(r, root))?;
+        self.categories[tree].get(&(node as *const Node)).copied()
+    }
+
+    /// Returns the index of the hunk next to the cursor of one of the trees (`0` for the old tree
+    /// and `1` for the new one) on a given side, if there is one.  If the cursor is still where
+    /// it was put by [`move_to`](DiffView::move_to), then this is the hunk next to that one.
+    pub fn next_hunk(&self, tree: usize, cursor: &CursorPath, side: Side) -> Option<usize> {
+        let current = self
+            .current
+            .filter(|&i| &self.hunks[i].paths[tree] == cursor);
+        match (side, current) {
+            (Side::Next, Some(i)) => Some(i + 1).filter(|&i| i < self.hunks.len()),
+            (Side::Prev, Some(i)) => i.checked_sub(1),
+            (Side::Next, None) => self.hunks.iter().position(|h| &h.paths[tree] > cursor),
+            (Side::Prev, None) => self.hunks.iter().rposition(|h| &h.paths[tree] < cursor),
+        }
+    }
+
+    /// Records that the cursors were moved to the hunk at `index`, returning that hunk
+    pub fn move_to(&mut self, index: usize) -> &Hunk {
+        self.current = Some(index);
+        &self.hunks[index]
+    }
+}
+
+/// Returns `true` if a difference at `path` is part of the same hunk as a difference at `last`,
+/// because they're siblings with nothing unchanged between them
+fn follows_on(last: &CursorPath, path: &CursorPath) -> bool {
+    let (mut last_parent, mut parent) = (last.clone(), path.clone());
+    match (last_parent.pop(), parent.pop()) {
+        (Some(last_index), Some(index)) => {
+            last_parent == parent && (last_index..=last_index + 1).contains(&index)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffView;
+    use crate::arena::Arena;
+    use crate::ast::display_token::SyntaxCategory;
+    use crate::ast::json::JSON;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::Side;
+
+    fn path(indices: &[usize]) -> CursorPath {
+        CursorPath::from_vec(indices.to_vec())
+    }
+
+    fn number(n: usize) -> J {
+        J::Number(n.to_string())
+    }
+
+    #[test]
+    fn categories() {
+        let arena = Arena::new();
+        let old = J::Array(vec![J::True, number(1), J::Array(vec![J::Null])]).add_to_arena(&arena);
+        let new = J::Array(vec![number(2), J::Array(vec![J::Null, J::False])]).add_to_arena(&arena);
+        let view = DiffView::new([0, 1], old, new);
+        // `true` and `2` are paired up as a replacement, so `1` was deleted
+        let category = |root, indices: &[usize]| view.category(root, path(indices).cursor(root));
+        assert_eq!(category(old, &[0]), Some(SyntaxCategory::DiffChanged));
+        assert_eq!(category(old, &[1]), Some(SyntaxCategory::DiffRemoved));
+        assert_eq!(category(new, &[0]), Some(SyntaxCategory::DiffChanged));
+        assert_eq!(category(new, &[1, 1]), Some(SyntaxCategory::DiffAdded));
+        assert_eq!(category(old, &[2, 0]), None);
+        assert_eq!(category(new, &[1]), None);
+        assert_eq!(view.difference_count(), 3);
+        // Nothing is highlighted in any other tree
+        let other = arena.alloc(JSON::Array(vec![old.children()[0]]));
+        assert_eq!(view.category(other, old.children()[0]), None);
+    }
+
+    #[test]
+    fn hunks() {
+        let arena = Arena::new();
+        let old = J::Array((0..10).map(number).collect()).add_to_arena(&arena);
+        // Replace 2 and 3, and delete 7
+        let new = J::Array(
+            (0..10)
+                .filter(|&i| i != 7)
+                .map(|i| number(if i == 2 || i == 3 { i * 100 } else { i }))
+                .collect(),
+        )
+        .add_to_arena(&arena);
+        let mut view = DiffView::new([0, 1], old, new);
+        let starts: Vec<_> = view
+            .hunks()
+            .iter()
+            .map(|h| (h.paths.clone(), h.len))
+            .collect();
+        assert_eq!(
+            starts,
+            vec![([path(&[2]), path(&[2])], 2), ([path(&[7]), path(&[])], 1)]
+        );
+
+        // Moving from the root of either tree finds the first hunk after it
+        assert_eq!(view.next_hunk(0, &path(&[]), Side::Next), Some(0));
+        assert_eq!(view.next_hunk(0, &path(&[8]), Side::Prev), Some(1));
+        assert_eq!(view.next_hunk(0, &path(&[8]), Side::Next), None);
+        // Once the cursors are at a hunk, moving goes to the hunks either side of it, even though
+        // the second hunk's path in the new tree is before the first's
+        view.move_to(0);
+        assert_eq!(view.next_hunk(1, &path(&[2]), Side::Next), Some(1));
+        view.move_to(1);
+        assert_eq!(view.next_hunk(1, &path(&[]), Side::Next), None);
+        assert_eq!(view.next_hunk(1, &path(&[]), Side::Prev), Some(0));
+    }
+
+    #[test]
+    fn many_differences() {
+        // Two large trees which differ everywhere are still compared quickly
+        let arena = Arena::new();
+        let old = J::Array((0..20_000).map(number).collect()).add_to_arena(&arena);
+        let new = J::Array((0..20_000).map(|i| number(i * 3 % 7)).collect()).add_to_arena(&arena);
+        let view = DiffView::new([0, 1], old, new);
+        assert!(view.difference_count() > 10_000);
+        assert!(view.hunks().len() < view.difference_count());
+    }
+}
@@ -0,0 +1,365 @@
+//! Utilities for reading and writing the files that Sapling edits.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The FNV-1a hash of no bytes at all, which every hash starts from
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Returns the path of the temporary file used when atomically writing to `path`.  This is in the
+/// same directory as `path`, so that renaming it over `path` doesn't cross filesystems.
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.sapling-tmp", file_name))
+}
+
+/// Creates the temporary file at `temp_path`, which will replace a file with the given
+/// `metadata` (if there is one).  The temporary file is created with the permissions of the file
+/// it replaces, so that its contents are never readable by anyone who can't read that file.  It
+/// must be a new file, rather than a file (or link) that was already there.
+fn create_temp_file(
+    temp_path: &Path,
+    metadata: Option<&std::fs::Metadata>,
+) -> io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if let Some(metadata) = metadata {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(metadata.permissions().mode() & 0o777);
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    match options.open(temp_path) {
+        // A temporary file left behind by a Sapling that died whilst writing
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            std::fs::remove_file(temp_path)?;
+            options.open(temp_path)
+        }
+        result => result,
+    }
+}
+
+/// Returns `true` if the file at `path` exists but its permissions don't allow it to be written.
+/// Files are written by renaming a new file over them, which would otherwise replace read-only
+/// files without complaint.
+pub fn is_read_only(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|m| m.permissions().readonly())
+}
+
+/// The modification time and length of a file, which (almost always) change whenever the file
+/// is written.  These are cheap to read, so they're used to notice when another program has
+/// written to a file without reading the whole file every time.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FileStamp {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    /// Returns the stamp of the file at `path` as it is now, or `None` if there's no file there
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(FileStamp {
+            path: path.to_owned(),
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+
+    /// Returns the path of the file that this is the stamp of
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Writes `contents` to the file at `path`, without ever leaving `path` half-written.  This is
+/// done by first writing the contents to a temporary file, and then renaming that temporary file
+/// over the top of `path`.  If anything goes wrong, the original file is left untouched and the
+/// error is returned.  If `path` is a symlink, then the file it links to is the one that's
+/// replaced (leaving the link in place), and the new file gets the permissions of the old one.
+pub fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    write_atomically_with(path, |file| file.write_all(contents.as_bytes()))
+}
+
+/// Writes a file in the same way as [`write_atomically`], except that its contents are written by
+/// `write` (so they don't all have to be in memory at once)
+pub fn write_atomically_with(
+    path: &Path,
+    write: impl FnOnce(&mut std::fs::File) -> io::Result<()>,
+) -> io::Result<()> {
+    // Renaming over a symlink would replace the link rather than the file it links to.  A new
+    // file (or a broken link) can't be canonicalized, and is written as it is.
+    let path = &std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let temp_path = temp_path(path);
+    let metadata = std::fs::metadata(path).ok();
+    let result = (|| {
+        let mut file = create_temp_file(&temp_path, metadata.as_ref())?;
+        write(&mut file)?;
+        // The umask can take bits away from the mode that the file was created with, so the
+        // permissions are copied exactly once everything has been written
+        if let Some(metadata) = &metadata {
+            file.set_permissions(metadata.permissions())?;
+        }
+        // Make sure that the contents have actually reached the disk before we replace the
+        // original file
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+    // If any of the steps failed, then don't leave the temporary file lying around
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Returns the 64-bit FNV-1a hash of `bytes`, which (unlike the hashes of the standard library)
+/// is the same every time Sapling runs
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_extend(FNV_OFFSET_BASIS, bytes)
+}
+
+/// Returns the FNV-1a hash of some bytes followed by `bytes`, given the `hash` of the bytes
+/// before
+fn fnv1a_extend(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Writes text to an [`io::Write`] as soon as it's given (e.g. by [`Ast::write_text_to`]),
+/// keeping the [`fnv1a`] hash of everything written so far.  A [`fmt::Error`] can't say what
+/// went wrong, so the first I/O error is kept to be returned by
+/// [`check`](TextWriter::check).
+///
+/// [`Ast::write_text_to`]: crate::ast::Ast::write_text_to
+#[derive(Debug)]
+pub struct TextWriter<W: Write> {
+    inner: W,
+    hash: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// Creates a `TextWriter` which writes to `inner`
+    pub fn new(inner: W) -> Self {
+        TextWriter {
+            inner,
+            hash: FNV_OFFSET_BASIS,
+            error: None,
+        }
+    }
+
+    /// Turns the `result` of writing some text into the I/O error that made it fail (if any did)
+    pub fn check(&mut self, result: fmt::Result) -> io::Result<()> {
+        match (result, self.error.take()) {
+            (_, Some(error)) => Err(error),
+            (Err(fmt::Error), None) => Err(io::Error::other("the text couldn't be formatted")),
+            (Ok(()), None) => Ok(()),
+        }
+    }
+
+    /// Returns the [`fnv1a`] hash of the text written so far
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Returns the writer that the text was written to
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> fmt::Write for TextWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.error.is_some() {
+            return Err(fmt::Error);
+        }
+        self.hash = fnv1a_extend(self.hash, s.as_bytes());
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fnv1a, temp_path, write_atomically, write_atomically_with, TextWriter};
+    use crate::arena::Arena;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::Ast;
+    use std::io::{self, BufWriter, Write};
+
+    /// Creates a new empty directory for a test to write files into
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sapling-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_and_overwrite() {
+        let dir = test_dir("write_and_overwrite");
+        let path = dir.join("file.json");
+        // Write a new file
+        write_atomically(&path, "[true]").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[true]");
+        // Overwrite an existing file
+        write_atomically(&path, "{}").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+        // The temporary file shouldn't be left behind
+        assert!(!temp_path(&path).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("write_keeps_permissions");
+        let path = dir.join("secret.json");
+        std::fs::write(&path, "[]").unwrap();
+        let mode =
+            |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        for permissions in [0o600, 0o640, 0o755] {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(permissions)).unwrap();
+            write_atomically_with(&path, |file| {
+                // Whilst the contents are written, the temporary file can't be read by anyone who
+                // can't read the original
+                assert_eq!(mode(&temp_path(&path)) & !permissions, 0);
+                file.write_all(b"{}")
+            })
+            .unwrap();
+            assert_eq!(mode(&path), permissions);
+        }
+        // A temporary file left behind by an earlier write is replaced, rather than written into
+        std::fs::write(temp_path(&path), "stale").unwrap();
+        std::fs::set_permissions(temp_path(&path), std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        write_atomically(&path, "[]").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        assert_eq!(mode(&path), 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_through_symlink() {
+        let dir = test_dir("write_through_symlink");
+        std::fs::create_dir(dir.join("real")).unwrap();
+        let target = dir.join("real").join("file.json");
+        let link = dir.join("link.json");
+        std::fs::write(&target, "[]").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_atomically(&link, "[true]").unwrap();
+        // The link is left alone, and the file that it links to is written
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "[true]");
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "[true]");
+        // The temporary file was next to the target, and isn't left behind
+        assert!(!temp_path(&target).exists());
+        assert!(!temp_path(&link).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_to_missing_directory() {
+        let dir = test_dir("write_to_missing_directory");
+        let path = dir.join("not-a-dir").join("file.json");
+        // Writing should fail with an error, not a panic
+        assert!(write_atomically(&path, "[]").is_err());
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A writer which only counts the bytes written to it, and fails once it's been given `limit`
+    /// bytes
+    #[derive(Debug)]
+    struct Counter {
+        bytes: usize,
+        largest_write: usize,
+        limit: usize,
+    }
+
+    impl Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.bytes + buf.len() > self.limit {
+                return Err(io::Error::other("the disk is full"));
+            }
+            self.bytes += buf.len();
+            self.largest_write = self.largest_write.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes the text of `root` through a [`TextWriter`] into a [`Counter`]
+    fn stream<'arena>(root: &'arena JSON<'arena>, limit: usize) -> io::Result<(Counter, u64)> {
+        let counter = Counter {
+            bytes: 0,
+            largest_write: 0,
+            limit,
+        };
+        let mut writer = TextWriter::new(BufWriter::new(counter));
+        let result = root.write_text_to(&mut writer, &JSONFormat::Compact);
+        writer.check(result)?;
+        let hash = writer.hash();
+        let counter = writer
+            .into_inner()
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        Ok((counter, hash))
+    }
+
+    #[test]
+    fn stream_text() {
+        let arena = Arena::new();
+        let root = JSON::parse("[true, {\"a\": null}]", &arena).unwrap();
+        let (counter, hash) = stream(root, usize::MAX).unwrap();
+        assert_eq!(counter.bytes, 17);
+        assert_eq!(hash, fnv1a(br#"[true,{"a":null}]"#));
+        // The first error is the one that's returned
+        let error = stream(root, 0).unwrap_err();
+        assert_eq!(error.to_string(), "the disk is full");
+        // The text is written a token at a time, so nothing longer than one string is ever written
+        // at once
+        assert_streams(16);
+    }
+
+    /// Writes an array of `count` copies of a 64 KiB string, checking that its text is never all
+    /// in memory at once
+    fn assert_streams(count: usize) {
+        let arena = Arena::new();
+        let string = arena.alloc(JSON::Str("s".repeat(64 << 10)));
+        let root = arena.alloc(JSON::Array(vec![string; count]));
+        let (counter, _) = stream(root, usize::MAX).unwrap();
+        assert_eq!(counter.bytes, 2 + count * ((64 << 10) + 2) + count - 1);
+        assert!(counter.largest_write <= (64 << 10) + 2);
+    }
+
+    /// Streams 256 MiB of text.  This takes a few seconds without optimisations, so it only runs
+    /// with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn stream_huge_text() {
+        assert_streams(4096);
+    }
+}
@@ -0,0 +1,295 @@
+//! Folding (collapsing) subtrees, so that only a one-line summary of them is rendered.
+
+use super::marks::Mark;
+use crate::ast::display_token::{DisplayToken, RecTok, SyntaxCategory};
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// The [`SyntaxCategory`] used to render the summary
+/// of a folded node
+const FOLD_CATEGORY: SyntaxCategory = SyntaxCategory::Comment;
+
+/// The set of nodes that are folded in the editor.  Each fold follows its node as the tree is
+/// edited (in the same way as a [`Mark`]), so folds survive edits elsewhere in the tree.  A fold
+/// is removed if its node stops being in the tree.
+#[derive(Debug, Clone, Default)]
+pub struct FoldSet<'arena, Node: Ast<'arena>> {
+    folds: Vec<Mark<'arena, Node>>,
+}
+
+impl<'arena, Node: Ast<'arena>> FoldSet<'arena, Node> {
+    /// Creates a new `FoldSet` where nothing is folded
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the node at a given path is folded
+    pub fn is_folded(&self, path: &CursorPath) -> bool {
+        self.folds.iter().any(|fold| fold.path() == path)
+    }
+
+    /// Folds the node at `path` if it isn't folded, and unfolds it if it is.  Returns `true` if
+    /// the node is now folded.
+    pub fn toggle(&mut self, path: &CursorPath, root: &'arena Node) -> bool {
+        if self.unfold(path) {
+            return false;
+        }
+        self.folds.push(Mark::new(path.clone(), root));
+        true
+    }
+
+    /// Unfolds the node at `path`, returning `true` if it was folded
+    pub fn unfold(&mut self, path: &CursorPath) -> bool {
+        let len = self.folds.len();
+        self.folds.retain(|fold| fold.path() != path);
+        self.folds.len() != len
+    }
+
+    /// Returns the paths of every folded node, in the order they were folded
+    pub fn paths(&self) -> Vec<&CursorPath> {
+        self.folds.iter().map(Mark::path).collect()
+    }
+
+    /// Returns `true` if the node at `path`, or any of its descendants, is folded
+    pub fn has_folds_within(&self, path: &CursorPath) -> bool {
+        self.folds.iter().any(|fold| fold.path().starts_with(path))
+    }
+
+    /// Unfolds every strict ancestor of the node at `path`, so that it can be seen
+    pub fn unfold_ancestors_of(&mut self, path: &CursorPath) {
+        self.folds.retain(|fold| {
+            let fold_path = fold.path();
+            !(fold_path.depth() < path.depth() && path.starts_with(fold_path))
+        });
+    }
+
+    /// Updates every fold to follow its node into a new version of the tree (e.g. after an edit),
+    /// removing the folds whose nodes are no longer in the tree
+    pub fn update(&mut self, root: &'arena Node) {
+        self.folds = self
+            .folds
+            .iter()
+            .filter_map(|fold| fold.follow(root))
+            .map(|path| Mark::new(path, root))
+            .collect();
+    }
+
+    /// Returns an iterator over the [`DisplayToken`]s that make up the tree under `root`, with
+    /// every folded node replaced by its [`fold_summary`](Ast::fold_summary).  Because the summary
+    /// is a text token like any other, the on-screen position of everything after a folded node
+    /// stays correct.  The tokens are generated lazily, so only the part of the tree that is
+    /// actually iterated over is visited.
+    pub fn display_tokens<'s>(
+        &'s self,
+        root: &'arena Node,
+        format_style: &'s Node::FormatStyle,
+    ) -> FoldedTokens<'s, 'arena, Node> {
+        let mut tokens = FoldedTokens {
+            folds: self,
+            format_style,
+            stack: Vec::new(),
+            path: CursorPath::root(),
+        };
+        tokens.enter(root);
+        tokens
+    }
+}
+
+/// A lazy iterator over the [`DisplayToken`]s of a tree, where folded nodes are replaced by their
+/// summaries.  Created by [`FoldSet::display_tokens`].
+pub struct FoldedTokens<'s, 'arena, Node: Ast<'arena>> {
+    folds: &'s FoldSet<'arena, Node>,
+    format_style: &'s Node::FormatStyle,
+    /// The nodes between the root and the node whose tokens are being generated, along with each
+    /// node's remaining tokens and how many of its children have been visited
+    stack: Vec<(
+        &'arena Node,
+        std::vec::IntoIter<RecTok<'arena, Node>>,
+        usize,
+    )>,
+    /// The path to the node at the top of `stack`
+    path: CursorPath,
+}
+
+/// One step of [`FoldedTokens::next_or_skip`]
+#[derive(Debug, Clone)]
+pub enum Step<'arena, Node: Ast<'arena>> {
+    /// A token, along with the node that generated it
+    Token(&'arena Node, DisplayToken),
+    /// A node whose tokens were all skipped
+    Skipped(&'arena Node),
+}
+
+impl<'s, 'arena, Node: Ast<'arena>> FoldedTokens<'s, 'arena, Node> {
+    /// Returns the path of the node that generated the token most recently returned by
+    /// [`next`](Iterator::next)
+    pub fn path(&self) -> &CursorPath {
+        &self.path
+    }
+
+    /// Returns `true` if the token most recently returned by [`next`](Iterator::next) came before
+    /// the first child or after the last child of its node (like the brackets around a JSON
+    /// array), rather than between two of its children (like the commas).  Nodes without
+    /// children have no delimiters, since there's nothing for them to go around.
+    pub fn is_delimiter(&self) -> bool {
+        match self.stack.last() {
+            Some((node, _, child_index)) => {
+                let child_count = node.children().len();
+                child_count > 0 && (*child_index == 0 || *child_index == child_count)
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the format style that the tokens are being generated in
+    pub(super) fn format_style(&self) -> &'s Node::FormatStyle {
+        self.format_style
+    }
+
+    /// Returns the next token, like [`next`](Iterator::next), but first calls `skip` with every
+    /// child (and its path) whose tokens are about to be generated.  If `skip` returns `true`,
+    /// then none of that child's tokens are generated, and the child is returned as
+    /// [`Step::Skipped`] instead.  Nodes that contain folds are never skipped, since their tokens
+    /// aren't the same as the tokens of the unfolded node.
+    pub fn next_or_skip(
+        &mut self,
+        mut skip: impl FnMut(&'arena Node, &CursorPath) -> bool,
+    ) -> Option<Step<'arena, Node>> {
+        loop {
+            let (node, tokens, child_index) = self.stack.last_mut()?;
+            match tokens.next() {
+                Some(RecTok::Tok(tok)) => return Some(Step::Token(*node, tok)),
+                Some(RecTok::Child(child)) => {
+                    // Children are always rendered in order, so we can count them to find their
+                    // indices
+                    debug_assert!(std::ptr::eq(child, node.children()[*child_index]));
+                    self.path.push(*child_index);
+                    *child_index += 1;
+                    if !self.folds.has_folds_within(&self.path) && skip(child, &self.path) {
+                        self.path.pop();
+                        return Some(Step::Skipped(child));
+                    }
+                    self.enter(child);
+                }
+                None => {
+                    self.stack.pop();
+                    if !self.stack.is_empty() {
+                        self.path.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Starts generating the tokens of `node`, which is at `self.path`
+    fn enter(&mut self, node: &'arena Node) {
+        let tokens = if self.folds.is_folded(&self.path) {
+            vec![RecTok::Tok(DisplayToken::Text(
+                node.fold_summary().into(),
+                FOLD_CATEGORY,
+            ))]
+        } else {
+            node.display_tokens_rec(self.format_style)
+        };
+        self.stack.push((node, tokens.into_iter(), 0));
+    }
+}
+
+impl<'s, 'arena, Node: Ast<'arena>> Iterator for FoldedTokens<'s, 'arena, Node> {
+    type Item = (&'arena Node, DisplayToken);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_or_skip(|_, _| false)? {
+            Step::Token(node, tok) => Some((node, tok)),
+            Step::Skipped(_) => unreachable!("No nodes should be skipped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FoldSet;
+    use crate::arena::Arena;
+    use crate::ast::display_token::DisplayToken;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::test_json::TestJSON as J;
+    use crate::editable_tree::{cursor_path::CursorPath, DAG};
+    use crate::editor::normal_mode::Action;
+
+    /// Concatenates the text of some tokens, which must all be on one line
+    fn text<'a>(tokens: impl Iterator<Item = (&'a JSON<'a>, DisplayToken)>) -> String {
+        let mut string = String::new();
+        for (_, tok) in tokens {
+            match tok {
+                DisplayToken::Text(s, _) => string.push_str(&s),
+                DisplayToken::Whitespace(n) => string.push_str(&" ".repeat(n)),
+                _ => panic!("Unexpected token {:?}", tok),
+            }
+        }
+        string
+    }
+
+    #[test]
+    fn folding() {
+        let arena = Arena::new();
+        let root = J::Object(vec![
+            ("a".to_string(), J::Array(vec![J::True, J::False])),
+            ("b".to_string(), J::Object(vec![("c".to_string(), J::Null)])),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+        let path = CursorPath::from_vec;
+        let format = JSONFormat::Compact;
+        let mut folds = FoldSet::new();
+
+        assert_eq!(
+            text(folds.display_tokens(tree.root(), &format)),
+            tree.to_text(&format)
+        );
+        assert!(folds.toggle(&path(vec![0, 1]), tree.root()));
+        assert!(folds.toggle(&path(vec![1, 1]), tree.root()));
+        assert_eq!(
+            text(folds.display_tokens(tree.root(), &format)),
+            r#"{"a":[…] (2 items),"b":{…} (1 field)}"#
+        );
+        // Folding a field shows its key
+        assert!(!folds.toggle(&path(vec![1, 1]), tree.root()));
+        assert!(folds.toggle(&path(vec![1]), tree.root()));
+        assert_eq!(
+            text(folds.display_tokens(tree.root(), &format)),
+            r#"{"a":[…] (2 items),"b": …}"#
+        );
+
+        // Folds survive edits elsewhere in the tree, even if the folded node moves
+        tree.set_cursor_path(path(vec![0]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        folds.update(tree.root());
+        assert!(folds.is_folded(&path(vec![0])));
+        assert_eq!(
+            text(folds.display_tokens(tree.root(), &format)),
+            r#"{"b": …}"#
+        );
+        // The fold of the deleted node is gone, even once it's been brought back by undoing
+        tree.execute_action(Action::Undo).1.unwrap();
+        folds.update(tree.root());
+        assert!(!folds.is_folded(&path(vec![0, 1])));
+        assert!(folds.is_folded(&path(vec![1])));
+        folds.toggle(&path(vec![0, 1]), tree.root());
+
+        // Moving into a folded node unfolds it
+        folds.unfold_ancestors_of(&path(vec![0, 1, 0]));
+        assert!(!folds.is_folded(&path(vec![0, 1])));
+        assert!(folds.is_folded(&path(vec![1])));
+        folds.unfold_ancestors_of(&path(vec![1]));
+        assert!(folds.is_folded(&path(vec![1])));
+        // Editing a folded node's descendants removes the fold, since the node has changed
+        tree.set_cursor_path(path(vec![1, 1, 0, 1]));
+        tree.execute_action(Action::Replace('t')).1.unwrap();
+        folds.update(tree.root());
+        assert!(!folds.is_folded(&path(vec![1])));
+        assert_eq!(
+            text(folds.display_tokens(tree.root(), &format)),
+            tree.to_text(&format)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Conversion of a drawn frame of the editor into text, so that what the editor shows can be
+//! checked without a terminal (see [`Editor::render_to_string`](super::Editor::render_to_string)).
+
+use crate::config::color::{ansi_background_escape, ansi_escape};
+use tuikit::prelude::{Attr, Color, Effect};
+use tuikit::screen::Screen;
+use unicode_width::UnicodeWidthChar;
+
+/// The escape sequence that puts the terminal's colours and effects back to normal
+const RESET: &str = "\x1b[0m";
+
+/// The effects that can be marked in text, along with their ANSI escape codes
+const EFFECT_CODES: [(Effect, u8); 5] = [
+    (Effect::BOLD, 1),
+    (Effect::DIM, 2),
+    (Effect::UNDERLINE, 4),
+    (Effect::BLINK, 5),
+    (Effect::REVERSE, 7),
+];
+
+/// Returns the escape sequences that make a terminal write text with a given [`Attr`], starting
+/// from the terminal's normal colours
+fn attr_escape(attr: Attr) -> String {
+    let mut escape = RESET.to_string();
+    if attr.fg != Color::Default {
+        escape.push_str(&ansi_escape(attr.fg));
+    }
+    if attr.bg != Color::Default {
+        escape.push_str(&ansi_background_escape(attr.bg));
+    }
+    for (effect, code) in &EFFECT_CODES {
+        if attr.effect.contains(*effect) {
+            escape.push_str(&format!("\x1b[{}m", code));
+        }
+    }
+    escape
+}
+
+/// Returns the text on a [`Screen`], with one line (ending in a newline) for every row.  Cells
+/// that haven't been drawn to are spaces, and spaces at the end of each line are left out.  If
+/// `colored` is `true`, then the text is marked with an ANSI escape sequence wherever its
+/// colours or effects change, and every line that doesn't end in the normal colours is reset at
+/// its end.
+pub fn screen_to_string(screen: &Screen, colored: bool) -> String {
+    let mut rows = vec![Vec::new(); screen.height()];
+    // The second cell of a wide char is a placeholder, which isn't part of the text
+    let mut skip_next = false;
+    for (row, _col, cell) in screen.iter_cell() {
+        if std::mem::take(&mut skip_next) {
+            continue;
+        }
+        let ch = if cell.ch == '\0' { ' ' } else { cell.ch };
+        skip_next = ch.width().unwrap_or(2) > 1;
+        rows[row].push((ch, cell.attr));
+    }
+
+    let mut text = String::new();
+    for mut cells in rows {
+        while let Some(&(' ', attr)) = cells.last() {
+            // Spaces with a background or effect can be seen, so they aren't trimmed
+            if colored && attr != Attr::default() {
+                break;
+            }
+            cells.pop();
+        }
+        let mut current_attr = Attr::default();
+        for (ch, attr) in cells {
+            if colored && attr != current_attr {
+                text.push_str(&attr_escape(attr));
+                current_attr = attr;
+            }
+            text.push(ch);
+        }
+        if current_attr != Attr::default() {
+            text.push_str(RESET);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::screen_to_string;
+    use tuikit::prelude::{Attr, Canvas, Color, Effect};
+    use tuikit::screen::Screen;
+
+    #[test]
+    fn plain() {
+        let mut screen = Screen::new(7, 4);
+        screen.print(0, 1, "ab").unwrap();
+        screen
+            .print_with_attr(1, 0, "  ", Attr::default().bg(Color::RED))
+            .unwrap();
+        screen.print(2, 0, "wide 字").unwrap();
+        // Text that doesn't fit on the screen is cut off, including wide chars that would only
+        // half fit
+        screen.print(3, 0, "cut 字字").unwrap();
+        assert_eq!(screen_to_string(&screen, false), " ab\n\nwide 字\ncut 字\n");
+    }
+
+    #[test]
+    fn colored() {
+        let mut screen = Screen::new(8, 2);
+        screen
+            .print_with_attr(0, 0, "ab", Attr::default().fg(Color::RED))
+            .unwrap();
+        screen.print(0, 2, "c").unwrap();
+        let cursor = Attr::default().fg(Color::BLACK).bg(Color::YELLOW);
+        screen.print_with_attr(1, 1, "d", cursor).unwrap();
+        screen
+            .print_with_attr(1, 3, " ", Attr::default().effect(Effect::REVERSE))
+            .unwrap();
+        assert_eq!(
+            screen_to_string(&screen, true),
+            "\x1b[0m\x1b[31mab\x1b[0mc\n \x1b[0m\x1b[30m\x1b[43md\x1b[0m \x1b[0m\x1b[7m \x1b[0m\n"
+        );
+    }
+}
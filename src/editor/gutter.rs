@@ -0,0 +1,112 @@
+//! The gutter of line numbers drawn to the left of each window's tree when `number` or
+//! `relativenumber` is set.  Line numbers count the lines of the tree as it would be saved, so a
+//! folded node (which is drawn on one line) is numbered by its first line, and the line after it
+//! carries on from the last line that the fold hides.
+
+/// The fewest digits that the gutter has room for (like Vim's `numberwidth`, not counting the
+/// space between the numbers and the tree)
+pub const MIN_GUTTER_DIGITS: usize = 3;
+
+/// Returns how many columns the gutter of a tree with `line_count` lines takes up, including the
+/// space between the numbers and the tree
+pub fn gutter_width(line_count: usize) -> usize {
+    line_count.to_string().len().max(MIN_GUTTER_DIGITS) + 1
+}
+
+/// Which numbers the gutter shows
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NumberStyle {
+    /// If `true`, lines are numbered from the top of the tree (`number`)
+    pub absolute: bool,
+    /// If `true`, lines are numbered by how far they are from the cursor (`relativenumber`)
+    pub relative: bool,
+}
+
+/// The line numbers of the lines of a window's tree, which can be folded
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct LineNumbers {
+    /// The line that each visible fold is drawn on, along with how many lines it hides, in order
+    /// from the top of the tree
+    folds: Vec<(usize, usize)>,
+    /// How many lines the tree is drawn over
+    drawn_lines: usize,
+}
+
+impl LineNumbers {
+    /// Creates the line numbers of a tree that has `line_count` lines when nothing is folded,
+    /// given the line that each visible fold is drawn on and how many lines its node hides
+    pub fn new(mut folds: Vec<(usize, usize)>, line_count: usize) -> Self {
+        folds.sort_unstable();
+        let hidden: usize = folds.iter().map(|&(_, hidden)| hidden).sum();
+        LineNumbers {
+            folds,
+            drawn_lines: line_count.saturating_sub(hidden),
+        }
+    }
+
+    /// Returns the line of the unfolded tree (counting from 0) that drawn line `line` starts on,
+    /// or `None` if the tree doesn't reach that line
+    pub fn source_line(&self, line: usize) -> Option<usize> {
+        if line >= self.drawn_lines {
+            return None;
+        }
+        let hidden: usize = self
+            .folds
+            .iter()
+            .take_while(|&&(fold_line, _)| fold_line < line)
+            .map(|&(_, hidden)| hidden)
+            .sum();
+        Some(line + hidden)
+    }
+
+    /// Returns the number shown in the gutter next to drawn line `line`, when the cursor is on
+    /// drawn line `cursor_line`.  Relative numbers count drawn lines, so a fold counts as one.
+    pub fn label(&self, line: usize, cursor_line: usize, style: NumberStyle) -> Option<usize> {
+        let absolute = self.source_line(line)? + 1;
+        let distance = line.abs_diff(cursor_line);
+        Some(if !style.relative || (style.absolute && distance == 0) {
+            absolute
+        } else {
+            distance
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gutter_width, LineNumbers, NumberStyle};
+
+    #[test]
+    fn line_numbers() {
+        assert_eq!(gutter_width(7), 4);
+        assert_eq!(gutter_width(12345), 6);
+
+        // A tree of 10 lines, where lines 2-4 are folded onto line 2 and lines 6-7 are folded
+        // onto what is then drawn as line 4
+        let numbers = LineNumbers::new(vec![(4, 1), (2, 2)], 10);
+        let lines: Vec<_> = (0..8).map(|line| numbers.source_line(line)).collect();
+        assert_eq!(
+            lines,
+            vec![
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(5),
+                Some(6),
+                Some(8),
+                Some(9),
+                None
+            ]
+        );
+
+        let style = |absolute, relative| NumberStyle { absolute, relative };
+        assert_eq!(numbers.label(3, 1, style(true, false)), Some(6));
+        assert_eq!(numbers.label(3, 1, style(false, true)), Some(2));
+        assert_eq!(numbers.label(0, 1, style(false, true)), Some(1));
+        // The cursor's line shows its own number if both are turned on
+        assert_eq!(numbers.label(1, 1, style(false, true)), Some(0));
+        assert_eq!(numbers.label(1, 1, style(true, true)), Some(2));
+        assert_eq!(numbers.label(3, 1, style(true, true)), Some(2));
+        assert_eq!(numbers.label(7, 1, style(true, true)), None);
+    }
+}
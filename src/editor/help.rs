@@ -0,0 +1,400 @@
+//! The help box shown by `?`, which lists the chars that can be typed after `r`, `o`, `i` and
+//! `a` on the node under the cursor, followed by every other key.  The box floats over the tree
+//! until the next key is pressed.  If it doesn't fit on the screen then `j` and `k` scroll it,
+//! and any other key closes it.  The same box shows the log for `:messages` and the matches of
+//! `:query!`.
+
+use super::normal_mode::{KeyMap, KeyStroke};
+use super::window::Rect;
+use crate::ast::json::query::path_string;
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::logging::Message;
+use tuikit::prelude::Canvas;
+
+/// The most chars of a match's text that `:query!` shows
+pub const MAX_PREVIEW_CHARS: usize = 60;
+
+/// The help box (or any other box of read-only text, like `:messages`), whilst it's being shown
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Help {
+    /// What the box shows, e.g. `help`
+    name: &'static str,
+    lines: Vec<String>,
+    /// How many lines are scrolled off the top of the box
+    scroll: usize,
+}
+
+impl Help {
+    /// Creates the help for `cursor`, whose parent (if it has one) is `parent`
+    pub fn new<'arena, Node: Ast<'arena>>(
+        cursor: &Node,
+        parent: Option<&Node>,
+        keymap: &KeyMap,
+    ) -> Self {
+        // The keys which are bound to each keystroke, in a stable order (with the shortest first)
+        let mut keys: Vec<(&str, &KeyStroke)> =
+            keymap.iter().map(|(k, s)| (k.as_str(), s)).collect();
+        keys.sort_unstable_by_key(|(k, _)| (k.chars().count(), *k));
+        let key_for = |stroke: &KeyStroke| {
+            keys.iter()
+                .find(|(_, s)| *s == stroke)
+                .map_or(String::new(), |(k, _)| k.to_string())
+        };
+
+        let mut lines = vec![format!("Help for {}", cursor.display_name()), String::new()];
+        let mut section = |title: String, node: &Node, chars: Vec<char>| {
+            let entries = char_entries(node, chars);
+            if !entries.is_empty() {
+                lines.push(title);
+                lines.extend(
+                    entries
+                        .into_iter()
+                        .map(|(keys, description)| format!("  {}  {}", keys, description)),
+                );
+                lines.push(String::new());
+            }
+        };
+        section(
+            format!(
+                "{}<c>: replace the cursor with",
+                key_for(&KeyStroke::Replace)
+            ),
+            cursor,
+            cursor.replace_chars().collect(),
+        );
+        section(
+            format!("{}<c>: insert a child", key_for(&KeyStroke::InsertChild)),
+            cursor,
+            cursor.insert_chars().collect(),
+        );
+        if let Some(parent) = parent {
+            section(
+                format!(
+                    "{}<c>/{}<c>: insert a sibling before/after the cursor",
+                    key_for(&KeyStroke::InsertBefore),
+                    key_for(&KeyStroke::InsertAfter),
+                ),
+                parent,
+                parent.insert_chars().collect(),
+            );
+        }
+        lines.push("Keys:".to_string());
+        let keys_width = keys.iter().map(|(k, _)| k.chars().count()).max();
+        for (k, stroke) in &keys {
+            lines.push(format!(
+                "  {:width$}  {}",
+                k,
+                stroke.summary_string(),
+                width = keys_width.unwrap_or(0)
+            ));
+        }
+        Help::from_lines("help", lines)
+    }
+
+    /// Creates a box called `name` which shows some `lines` of text
+    pub fn from_lines(name: &'static str, lines: Vec<String>) -> Self {
+        Help {
+            name,
+            lines,
+            scroll: 0,
+        }
+    }
+
+    /// Creates the box shown by `:messages`, which lists the messages that have been logged
+    /// (given oldest first).  The newest messages are shown at the top, so that they're in view
+    /// when the box is opened.
+    pub fn messages(messages: &[Message]) -> Self {
+        let mut lines = vec![format!("Messages ({})", messages.len()), String::new()];
+        if messages.is_empty() {
+            lines.push("Nothing has been logged yet.".to_string());
+        }
+        lines.extend(messages.iter().rev().map(Message::to_short_line));
+        Help::from_lines("messages", lines)
+    }
+
+    /// Creates the box shown by `:query!`, which lists the path and a preview of the text of each
+    /// of the `matches` of `query` (in the tree under `root`)
+    pub fn query_matches<'arena, Node: Ast<'arena>>(
+        query: &str,
+        root: &'arena Node,
+        matches: &[CursorPath],
+        format_style: &Node::FormatStyle,
+    ) -> Self {
+        let mut lines = vec![
+            format!("Matches of {} ({})", query, matches.len()),
+            String::new(),
+        ];
+        let paths: Vec<String> = matches.iter().map(|p| path_string(root, p)).collect();
+        let number_width = matches.len().to_string().len();
+        let path_width = paths.iter().map(|p| p.chars().count()).max().unwrap_or(0);
+        for (i, (path, cursor)) in paths.iter().zip(matches).enumerate() {
+            let text = cursor.cursor(root).to_text(format_style);
+            lines.push(format!(
+                "  {:>number_width$}  {:path_width$}  {}",
+                i + 1,
+                path,
+                preview(&text),
+                number_width = number_width,
+                path_width = path_width
+            ));
+        }
+        lines.push(String::new());
+        lines.push("n/N: move to the next/previous match".to_string());
+        Help::from_lines("matches", lines)
+    }
+
+    /// Creates the box that `:errors` shows, which lists every problem with the tree (given as
+    /// the path of the node with the problem and a description of it)
+    pub fn errors(errors: &[(String, String)]) -> Self {
+        let mut lines = vec![format!("Errors ({})", errors.len()), String::new()];
+        let path_width = errors.iter().map(|(p, _)| p.chars().count()).max();
+        for (path, message) in errors {
+            lines.push(format!(
+                "  {:path_width$}  {}",
+                path,
+                message,
+                path_width = path_width.unwrap_or(0)
+            ));
+        }
+        lines.push(String::new());
+        lines.push("]e/[e: move to the next/previous error".to_string());
+        Help::from_lines("errors", lines)
+    }
+
+    /// Returns what the box shows, e.g. `help`
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns every line of the help, whether or not it's scrolled into view
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Returns the lines that are scrolled into a box of the given `height` (including its
+    /// border)
+    pub fn visible_lines(&self, height: usize) -> &[String] {
+        let rows = height.saturating_sub(2);
+        let end = (self.scroll + rows).min(self.lines.len());
+        &self.lines[self.scroll..end]
+    }
+
+    /// Returns the rectangle that the help box (including its border) covers on a screen of the
+    /// given size.  The box is centred over everything except the bottom bar, and is shrunk to
+    /// fit on the screen.
+    pub fn rect(&self, screen_width: usize, screen_height: usize) -> Rect {
+        let space = screen_height.saturating_sub(1);
+        let text_width = self.lines.iter().map(|l| l.chars().count()).max();
+        let width = (text_width.unwrap_or(0) + 4).min(screen_width);
+        let height = (self.lines.len() + 2).min(space);
+        Rect {
+            row: (space - height) / 2,
+            col: (screen_width - width) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// Returns `true` if every line fits in a box of the given `height`
+    pub fn fits(&self, height: usize) -> bool {
+        self.lines.len() + 2 <= height
+    }
+
+    /// Scrolls the box (of the given `height`) down by `lines`, or up if `lines` is negative
+    pub fn scroll(&mut self, lines: isize, height: usize) {
+        let max_scroll = (self.lines.len() + 2).saturating_sub(height);
+        let scroll = self.scroll as isize + lines;
+        self.scroll = (scroll.max(0) as usize).min(max_scroll);
+    }
+
+    /// Returns how many lines are scrolled off the top of the box
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll
+    }
+
+    /// Draws the help box, with a border, over whatever has already been drawn to a screen of
+    /// the given size
+    pub fn render(&self, canvas: &mut dyn Canvas, screen_width: usize, screen_height: usize) {
+        let rect = self.rect(screen_width, screen_height);
+        if rect.width < 2 || rect.height < 2 {
+            return;
+        }
+        let inner = rect.width - 2;
+        // Show how far down the help is scrolled if it doesn't all fit
+        let position = if self.fits(rect.height) {
+            String::new()
+        } else {
+            format!(" {}/{} ", self.scroll + rect.height - 2, self.lines.len())
+        };
+        let bottom = if position.len() < inner {
+            format!("{}{}", position, "─".repeat(inner - position.len()))
+        } else {
+            "─".repeat(inner)
+        };
+        canvas
+            .print(rect.row, rect.col, &format!("┌{}┐", "─".repeat(inner)))
+            .unwrap();
+        for row in 1..rect.height - 1 {
+            let line = self
+                .lines
+                .get(self.scroll + row - 1)
+                .map_or("", String::as_str);
+            let text: String = format!(" {}", line).chars().take(inner).collect();
+            let padding = inner - text.chars().count();
+            canvas
+                .print(
+                    rect.row + row,
+                    rect.col,
+                    &format!("│{}{}│", text, " ".repeat(padding)),
+                )
+                .unwrap();
+        }
+        canvas
+            .print(
+                rect.row + rect.height - 1,
+                rect.col,
+                &format!("└{}┘", bottom),
+            )
+            .unwrap();
+    }
+}
+
+/// Returns one entry for each different description of `chars` (as given by
+/// [`Ast::char_description`] on `node`), pairing the description with the chars which have it.
+/// Runs of three or more consecutive chars are shortened (e.g. to `0-9`).
+pub fn char_entries<'arena, Node: Ast<'arena>>(
+    node: &Node,
+    chars: Vec<char>,
+) -> Vec<(String, String)> {
+    let mut groups: Vec<(String, Vec<char>)> = Vec::new();
+    for c in chars {
+        let description = match node.char_description(c) {
+            Some(description) => description,
+            None => continue,
+        };
+        match groups.iter_mut().find(|(d, _)| *d == description) {
+            Some((_, group)) => group.push(c),
+            None => groups.push((description, vec![c])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(description, chars)| (char_ranges(&chars), description))
+        .collect()
+}
+
+/// Returns the text of a node on one line, cut short to [`MAX_PREVIEW_CHARS`]
+fn preview(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if line.chars().count() <= MAX_PREVIEW_CHARS {
+        return line;
+    }
+    let mut short: String = line.chars().take(MAX_PREVIEW_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Writes some chars one after another, shortening runs of three or more consecutive chars to
+/// their first and last chars separated by `-`
+fn char_ranges(chars: &[char]) -> String {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let mut end = i;
+        while end + 1 < chars.len() && chars[end + 1] as u32 == chars[end] as u32 + 1 {
+            end += 1;
+        }
+        if end >= i + 2 {
+            text.push(chars[i]);
+            text.push('-');
+            text.push(chars[end]);
+        } else {
+            text.extend(&chars[i..=end]);
+        }
+        i = end + 1;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{char_ranges, preview, Help, MAX_PREVIEW_CHARS};
+    use crate::arena::Arena;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editor::normal_mode::default_keymap;
+    use crate::logging::Message;
+    use log::Level;
+
+    #[test]
+    fn help() {
+        assert_eq!(char_ranges(&['t', 'f', '0', '1', '2', '3']), "tf0-3");
+        assert_eq!(char_ranges(&['a', 'b', 'd']), "abd");
+
+        let arena = Arena::new();
+        let root = JSON::parse("[true]", &arena).unwrap();
+        let help = Help::new(root.children()[0], Some(root), &default_keymap());
+        let lines = help.lines().to_vec();
+        assert_eq!(lines[0], "Help for true");
+        assert_eq!(lines[2], "r<c>: replace the cursor with");
+        assert!(lines.contains(&"  0-9  number".to_string()));
+        assert!(lines.contains(&"  -  negative number".to_string()));
+        // `true` has no children, so nothing can be inserted into it
+        assert!(!lines.iter().any(|l| l.starts_with("o<c>")));
+        assert!(lines.contains(&"i<c>/a<c>: insert a sibling before/after the cursor".to_string()));
+        assert!(lines.contains(&"  x      delete".to_string()));
+        assert!(lines.contains(&"  ?      help".to_string()));
+        assert!(lines.contains(&"  <C-r>  redo".to_string()));
+
+        // The box is shrunk to fit small screens, and can then be scrolled
+        let rect = help.rect(20, 11);
+        assert_eq!(
+            (rect.row, rect.col, rect.width, rect.height),
+            (0, 0, 20, 10)
+        );
+        assert!(!help.fits(rect.height));
+        let mut help = help;
+        help.scroll(-1, rect.height);
+        assert_eq!(help.scroll_offset(), 0);
+        help.scroll(1000, rect.height);
+        assert_eq!(help.scroll_offset(), lines.len() + 2 - 10);
+        assert_eq!(help.visible_lines(rect.height).len(), 8);
+
+        let messages = Help::messages(&[Message {
+            level: Level::Info,
+            target: "sapling".to_string(),
+            text: "Starting".to_string(),
+            time: 0,
+        }]);
+        assert_eq!(messages.name(), "messages");
+        assert_eq!(messages.lines()[2], "00:00:00 INFO  Starting");
+
+        let root = JSON::parse(r#"{"a": [1, {"b": 2}], "long key": 3}"#, &arena).unwrap();
+        let paths = [
+            CursorPath::from_vec(vec![0, 1]),
+            CursorPath::from_vec(vec![1, 1]),
+        ];
+        let matches = Help::query_matches("$.*", root, &paths, &JSONFormat::Compact);
+        assert_eq!(matches.name(), "matches");
+        assert_eq!(
+            matches.lines()[..4],
+            [
+                "Matches of $.* (2)",
+                "",
+                "  1  $.a            [1,{\"b\":2}]",
+                "  2  $['long key']  3",
+            ]
+        );
+        assert_eq!(preview("{\n  \"a\": 1\n}\n"), "{ \"a\": 1 }");
+        let long = preview(&"x".repeat(100));
+        assert_eq!(long.chars().count(), MAX_PREVIEW_CHARS);
+        assert!(long.ends_with('…'));
+    }
+}
@@ -0,0 +1,104 @@
+//! The panel of hints that appears above the bottom bar when the user stops halfway through a
+//! keystroke, listing the keys that can finish it.  The panel is only drawn, and never handles
+//! keys itself, so the next key finishes the keystroke exactly as if the panel wasn't there.
+
+use tuikit::prelude::Canvas;
+
+/// The hints for an unfinished keystroke
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Hints {
+    /// The unfinished keystroke, which is shown in the panel's border
+    keystroke: String,
+    /// The keys that can come next, each along with what it does
+    entries: Vec<(String, String)>,
+}
+
+impl Hints {
+    /// Creates the hints for `keystroke`, which can be followed by any of `entries`
+    pub fn new(keystroke: String, entries: Vec<(String, String)>) -> Self {
+        Hints { keystroke, entries }
+    }
+
+    /// Returns each entry as it's written in the panel
+    fn entry_strings(&self) -> Vec<String> {
+        let keys_width = self.entries.iter().map(|(k, _)| k.chars().count()).max();
+        self.entries
+            .iter()
+            .map(|(keys, description)| {
+                format!(
+                    "{:width$}  {}",
+                    keys,
+                    description,
+                    width = keys_width.unwrap_or(0)
+                )
+            })
+            .collect()
+    }
+
+    /// Lays the entries out in columns across a screen of the given `width` and returns the
+    /// lines of the panel (not counting its border).  The entries go down each column in turn,
+    /// and only the lines that fit above the bottom bar of a screen `height` rows tall are kept.
+    pub fn lines(&self, width: usize, height: usize) -> Vec<String> {
+        let entries = self.entry_strings();
+        let column_width = entries.iter().map(|e| e.chars().count()).max().unwrap_or(0) + 2;
+        let columns = (width / column_width).max(1);
+        let rows = entries.len().div_ceil(columns);
+        (0..rows.min(height.saturating_sub(2)))
+            .map(|row| {
+                let mut line = String::new();
+                for entry in entries.iter().skip(row).step_by(rows) {
+                    line.push_str(&format!(" {:width$}", entry, width = column_width - 1));
+                }
+                line.trim_end().chars().take(width).collect()
+            })
+            .collect()
+    }
+
+    /// Draws the panel, with a border along its top, over the rows just above the bottom bar of
+    /// a screen of the given size
+    pub fn render(&self, canvas: &mut dyn Canvas, screen_width: usize, screen_height: usize) {
+        let lines = self.lines(screen_width, screen_height);
+        if lines.is_empty() {
+            return;
+        }
+        let top = screen_height - 2 - lines.len();
+        let title = format!("── {} ", self.keystroke);
+        let border_length = screen_width.saturating_sub(title.chars().count());
+        let border: String = format!("{}{}", title, "─".repeat(border_length))
+            .chars()
+            .take(screen_width)
+            .collect();
+        canvas.print(top, 0, &border).unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            let padding = screen_width - line.chars().count();
+            canvas
+                .print(top + 1 + i, 0, &format!("{}{}", line, " ".repeat(padding)))
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hints;
+
+    #[test]
+    fn lines() {
+        let entries = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|k| (k.to_string(), format!("key {}", k)))
+            .collect();
+        let hints = Hints::new("z".to_string(), entries);
+        // Each column is 10 wide (including the gaps around the entry), so 2 fit across 20
+        assert_eq!(
+            hints.lines(20, 10),
+            vec![" a  key a  d  key d", " b  key b  e  key e", " c  key c"]
+        );
+        assert_eq!(
+            hints.lines(80, 10),
+            vec![" a  key a  b  key b  c  key c  d  key d  e  key e"]
+        );
+        // Only the rows that fit above the bottom bar are kept
+        assert_eq!(hints.lines(20, 4).len(), 2);
+    }
+}
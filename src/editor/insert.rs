@@ -0,0 +1,132 @@
+//! The text of a string being typed in insert mode.
+
+use crate::ast::size;
+
+/// The text being typed into a node (e.g. the contents of a string), with a cursor that can be
+/// moved through it.  The cursor moves over (and deletes) whole graphemes, so an accented letter
+/// or an emoji is never split into its separate chars.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StringEditor {
+    text: String,
+    /// The byte index of the grapheme that the cursor is on (or `text.len()` if the cursor is
+    /// past the end)
+    cursor: usize,
+}
+
+impl StringEditor {
+    /// Creates a `StringEditor` containing `text`, with the cursor past the end of it
+    pub fn new(text: String) -> Self {
+        let cursor = text.len();
+        StringEditor { text, cursor }
+    }
+
+    /// Returns the text that has been typed
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the byte index of the cursor in the text
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns the byte index of the start of the grapheme before the cursor, if there is one
+    fn prev_boundary(&self) -> Option<usize> {
+        size::grapheme_boundaries(&self.text)
+            .into_iter()
+            .rev()
+            .find(|&i| i < self.cursor)
+    }
+
+    /// Returns the byte index of the end of the grapheme under the cursor, if there is one
+    fn next_boundary(&self) -> Option<usize> {
+        size::grapheme_boundaries(&self.text)
+            .into_iter()
+            .find(|&i| i > self.cursor)
+    }
+
+    /// Types a char at the cursor
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Deletes the grapheme before the cursor, if there is one
+    pub fn backspace(&mut self) {
+        if let Some(start) = self.prev_boundary() {
+            self.text.replace_range(start..self.cursor, "");
+            self.cursor = start;
+        }
+    }
+
+    /// Deletes the grapheme under the cursor, if the cursor isn't past the end of the text
+    pub fn delete(&mut self) {
+        if let Some(end) = self.next_boundary() {
+            self.text.replace_range(self.cursor..end, "");
+        }
+    }
+
+    /// Moves the cursor one grapheme to the left, if it isn't at the start of the text
+    pub fn move_left(&mut self) {
+        if let Some(start) = self.prev_boundary() {
+            self.cursor = start;
+        }
+    }
+
+    /// Moves the cursor one grapheme to the right, if it isn't past the end of the text
+    pub fn move_right(&mut self) {
+        if let Some(end) = self.next_boundary() {
+            self.cursor = end;
+        }
+    }
+
+    /// Moves the cursor to the start of the text
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Moves the cursor past the end of the text
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StringEditor;
+
+    #[test]
+    fn editing() {
+        let mut text = StringEditor::new("cafe\u{301}".to_string());
+        assert_eq!(text.cursor(), 6);
+        // Moving and deleting skip over the whole of `e\u{301}`
+        text.move_left();
+        assert_eq!(text.cursor(), 3);
+        text.insert('X');
+        assert_eq!(text.text(), "cafXe\u{301}");
+        text.delete();
+        assert_eq!(text.text(), "cafX");
+        assert_eq!(text.cursor(), 4);
+        // Deleting past the end does nothing
+        text.delete();
+        assert_eq!(text.text(), "cafX");
+        text.move_to_start();
+        text.backspace();
+        text.move_left();
+        assert_eq!((text.text(), text.cursor()), ("cafX", 0));
+        text.move_right();
+        text.delete();
+        assert_eq!((text.text(), text.cursor()), ("cfX", 1));
+        text.move_to_end();
+        text.move_right();
+        assert_eq!(text.cursor(), 3);
+
+        // Emoji made of many chars are removed in one go
+        let mut text = StringEditor::new("a👨\u{200D}👩\u{200D}👧".to_string());
+        text.backspace();
+        assert_eq!((text.text(), text.cursor()), ("a", 1));
+        text.backspace();
+        text.backspace();
+        assert_eq!((text.text(), text.cursor()), ("", 0));
+    }
+}
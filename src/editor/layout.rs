@@ -0,0 +1,253 @@
+//! Laying out the visible part of the tree as lines of text, which is what gets drawn to the
+//! screen.
+
+use super::viewport::Layout;
+use crate::ast::display_token::{DisplayToken, SyntaxCategory};
+use crate::ast::size;
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// A piece of text on a single line, all of which was generated by one node
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Span<'arena, Node: Ast<'arena>> {
+    /// The column (before scrolling) that the text starts on
+    pub x: usize,
+    /// How many columns the text occupies
+    pub width: usize,
+    /// The node that generated the text
+    pub node: &'arena Node,
+    /// The path of the node that generated the text
+    pub path: CursorPath,
+    /// How the text should be highlighted
+    pub category: SyntaxCategory,
+    /// If `true`, the text came before the first child or after the last child of its node
+    /// (like the brackets around a JSON array)
+    pub delimiter: bool,
+    pub text: String,
+}
+
+impl<'arena, Node: Ast<'arena>> Span<'arena, Node> {
+    /// Returns `true` if this span occupies the given column
+    pub fn contains_col(&self, col: usize) -> bool {
+        self.x <= col && col < self.x + self.width
+    }
+
+    /// Returns the column (before scrolling) that the char at byte `index` of this span's text
+    /// starts on, measuring tabs as `tab_width` columns like [`Lines::new`] does
+    pub fn col_of(&self, index: usize, tab_width: usize) -> usize {
+        let before: usize = size::char_widths(&self.text, tab_width)
+            .take_while(|&(i, _)| i < index)
+            .map(|(_, w)| w)
+            .sum();
+        self.x + before
+    }
+}
+
+/// A single line of the tree, made up of [`Span`]s in order from left to right
+pub type Line<'arena, Node> = Vec<Span<'arena, Node>>;
+
+/// Some consecutive lines of the tree, starting at line `first_line`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Lines<'arena, Node: Ast<'arena>> {
+    first_line: usize,
+    lines: Vec<Line<'arena, Node>>,
+}
+
+impl<'arena, Node: Ast<'arena>> Lines<'arena, Node> {
+    /// Lays out the `height` lines of the tree that start at line `first_line`.  Tokens are only
+    /// generated until the last of those lines has been filled.
+    pub fn new(mut layout: Layout<'_, 'arena, Node>, first_line: usize, height: usize) -> Self {
+        let mut lines = vec![Vec::new(); height];
+        let tab_width = layout.tab_width();
+        while let Some(((line, col), node, tok)) = layout.next() {
+            if line >= first_line + height {
+                break;
+            }
+            // Every other token is already accounted for by the positions from the layout
+            let (text, category) = match tok {
+                DisplayToken::Text(text, category) => (text, category),
+                _ => continue,
+            };
+            // Every line of the text after the first starts at the left edge of the tree
+            for (i, text_line) in text.split('\n').enumerate() {
+                let x = if i == 0 { col } else { 0 };
+                let index = match (line + i).checked_sub(first_line) {
+                    Some(index) if index < height => index,
+                    _ => continue,
+                };
+                let width = size::char_widths(text_line, tab_width)
+                    .map(|(_, w)| w)
+                    .sum();
+                lines[index].push(Span {
+                    x,
+                    width,
+                    node,
+                    path: layout.path().clone(),
+                    category,
+                    delimiter: layout.is_delimiter(),
+                    text: text_line.to_string(),
+                });
+            }
+        }
+        Lines { first_line, lines }
+    }
+
+    /// Returns the line of the tree that these `Lines` start at
+    pub fn first_line(&self) -> usize {
+        self.first_line
+    }
+
+    /// Returns an iterator over every line, along with its index in the whole tree
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Line<'arena, Node>)> {
+        let first_line = self.first_line;
+        self.lines
+            .iter()
+            .enumerate()
+            .map(move |(i, line)| (first_line + i, line))
+    }
+
+    /// Returns the [`Span`] which covers column `col` of line `line` of the tree, or `None` if
+    /// that position is whitespace (or isn't in these lines).  This is the text that was under
+    /// the given position of the screen.
+    pub fn span_at(&self, line: usize, col: usize) -> Option<&Span<'arena, Node>> {
+        let line = self.lines.get(line.checked_sub(self.first_line)?)?;
+        line.iter().find(|span| span.contains_col(col))
+    }
+
+    /// Returns the [`Span`] on line `line` of the tree which is closest to column `col` (so a
+    /// position in the indentation or past the end of a line finds the text nearest to it).
+    /// Returns `None` if that line is empty (or isn't in these lines).
+    pub fn nearest_span(&self, line: usize, col: usize) -> Option<&Span<'arena, Node>> {
+        let line = self.lines.get(line.checked_sub(self.first_line)?)?;
+        let distance = |span: &&Span<'arena, Node>| {
+            if col < span.x {
+                span.x - col
+            } else {
+                (col + 1).saturating_sub(span.x + span.width)
+            }
+        };
+        // `min_by_key` returns the first of any equally close spans
+        line.iter().min_by_key(distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lines;
+    use crate::arena::Arena;
+    use crate::ast::display_token::{IndentKind, SyntaxCategory};
+    use crate::ast::json::JSONFormat;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editor::folds::FoldSet;
+    use crate::editor::viewport::Layout;
+
+    #[test]
+    fn spans() {
+        let arena = Arena::new();
+        // Pretty printed, this is:
+        // 0 {
+        // 1   "a": [
+        // 2     true,
+        // 3     "日本"
+        // 4   ]
+        // 5 }
+        let root = J::Object(vec![(
+            "a".to_string(),
+            J::Array(vec![J::True, J::Str("日本".to_string())]),
+        )])
+        .add_to_arena(&arena);
+        let format = JSONFormat::Pretty {
+            indent: IndentKind::Spaces(2),
+            trailing_commas: false,
+        };
+        let folds = FoldSet::new();
+        let lines = |first_line, height| {
+            let layout = Layout::new(folds.display_tokens(root, &format), 2, 8);
+            Lines::new(layout, first_line, height)
+        };
+        // Returns the `(x, width, path, category, text)` of every span on each line
+        let spans = |lines: &Lines<_>| {
+            lines
+                .iter()
+                .map(|(_, line)| {
+                    line.iter()
+                        .map(|s| (s.x, s.width, s.path.to_string(), s.category, s.text.clone()))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let span = |x, width, path: &str, category, text: &str| {
+            (x, width, path.to_string(), category, text.to_string())
+        };
+
+        let all = lines(0, 6);
+        assert_eq!(
+            spans(&all),
+            vec![
+                vec![span(0, 1, "root", SyntaxCategory::Default, "{")],
+                vec![
+                    span(2, 3, "0.0", SyntaxCategory::Literal, r#""a""#),
+                    span(5, 2, "0", SyntaxCategory::Default, ": "),
+                    span(7, 1, "0.1", SyntaxCategory::Default, "["),
+                ],
+                vec![
+                    span(4, 4, "0.1.0", SyntaxCategory::Const, "true"),
+                    span(8, 1, "0.1", SyntaxCategory::Default, ","),
+                ],
+                vec![span(4, 6, "0.1.1", SyntaxCategory::Literal, r#""日本""#)],
+                vec![span(2, 1, "0.1", SyntaxCategory::Default, "]")],
+                vec![span(0, 1, "root", SyntaxCategory::Default, "}")],
+            ]
+        );
+        // The brackets around each node's children are delimiters, but separators aren't
+        let delimiters: Vec<Vec<bool>> = all
+            .iter()
+            .map(|(_, line)| line.iter().map(|s| s.delimiter).collect())
+            .collect();
+        assert_eq!(
+            delimiters,
+            vec![
+                vec![true],
+                vec![false, false, true],
+                vec![false, false],
+                vec![false],
+                vec![true],
+                vec![true],
+            ]
+        );
+        // Only the requested lines are laid out, and lines past the end of the tree are empty
+        let some = lines(3, 4);
+        assert_eq!(some.first_line(), 3);
+        assert_eq!(spans(&some)[..3], spans(&all)[3..]);
+        assert!(spans(&some)[3].is_empty());
+
+        // Positions on the screen map back to the spans under them
+        let path_at = |line, col| some.span_at(line, col).map(|s| s.path.clone());
+        let path = CursorPath::from_vec;
+        assert_eq!(path_at(3, 4), Some(path(vec![0, 1, 1])));
+        assert_eq!(path_at(3, 9), Some(path(vec![0, 1, 1])));
+        assert_eq!(path_at(3, 10), None);
+        assert_eq!(path_at(3, 3), None);
+        assert_eq!(path_at(4, 2), Some(path(vec![0, 1])));
+        assert_eq!(path_at(2, 4), None);
+        assert_eq!(path_at(100, 0), None);
+        // Clicks in whitespace find the closest text on the same line
+        let nearest = |line, col| some.nearest_span(line, col).map(|s| s.path.clone());
+        assert_eq!(nearest(3, 0), Some(path(vec![0, 1, 1])));
+        assert_eq!(nearest(3, 50), Some(path(vec![0, 1, 1])));
+        assert_eq!(nearest(4, 1), Some(path(vec![0, 1])));
+        assert_eq!(nearest(6, 0), None);
+        let nearest = |line, col| all.nearest_span(line, col).map(|s| s.path.clone());
+        assert_eq!(nearest(1, 0), Some(path(vec![0, 0])));
+        assert_eq!(nearest(1, 6), Some(path(vec![0])));
+        assert_eq!(nearest(2, 100), Some(path(vec![0, 1])));
+
+        // Columns inside a span are measured in screen columns, not bytes
+        let wide = some.span_at(3, 4).unwrap();
+        assert_eq!(wide.col_of(0, 8), 4);
+        assert_eq!(wide.col_of(1, 8), 5);
+        assert_eq!(wide.col_of(4, 8), 7);
+        assert_eq!(wide.col_of(7, 8), 9);
+    }
+}
@@ -0,0 +1,100 @@
+//! Storage for keystroke macros, which are recorded with `q<register>` and replayed with
+//! `@<register>` (exactly like in Vim).
+
+use std::collections::HashMap;
+use tuikit::prelude::Key;
+
+/// The maximum number of macros that can be replayed inside one another.  Without this limit, a
+/// macro that replays itself would never stop.
+pub const MAX_MACRO_DEPTH: usize = 100;
+
+/// A store of recorded macros, along with the macro that is currently being recorded (if any).
+/// Macros are stored as the raw [`Key`]s that the user pressed, so that replaying a macro goes
+/// through exactly the same code path as typing it.
+#[derive(Debug, Clone, Default)]
+pub struct MacroStore {
+    /// The keys of every macro that has finished being recorded, indexed by register
+    macros: HashMap<char, Vec<Key>>,
+    /// The register and keys of the macro currently being recorded
+    recording: Option<(char, Vec<Key>)>,
+}
+
+impl MacroStore {
+    /// Creates a new `MacroStore` with no macros in it
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the register of the macro being recorded, or `None` if no macro is being recorded
+    pub fn recording_register(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    /// Starts recording a new macro into a given register.  Any macro that was already being
+    /// recorded is stored before the new one starts.
+    pub fn start_recording(&mut self, register: char) {
+        self.stop_recording();
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Adds a key to the macro being recorded.  This does nothing if no macro is being recorded.
+    pub fn record(&mut self, key: Key) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Removes the most recently recorded key from the macro being recorded (e.g. to remove the
+    /// key that stopped the recording).
+    pub fn unrecord_last(&mut self) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.pop();
+        }
+    }
+
+    /// Stops recording, storing the recorded macro in its register (replacing whatever was there
+    /// before).  Returns the register that the macro was stored in, or `None` if no macro was
+    /// being recorded.
+    pub fn stop_recording(&mut self) -> Option<char> {
+        let (register, keys) = self.recording.take()?;
+        self.macros.insert(register, keys);
+        Some(register)
+    }
+
+    /// Returns the keys of the macro stored in a given register, if one has been recorded
+    pub fn get(&self, register: char) -> Option<&[Key]> {
+        self.macros.get(&register).map(Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MacroStore;
+    use tuikit::prelude::Key;
+
+    #[test]
+    fn record() {
+        let mut store = MacroStore::new();
+        // Keys are only recorded whilst recording
+        store.record(Key::Char('x'));
+        assert_eq!(store.recording_register(), None);
+        store.start_recording('a');
+        assert_eq!(store.recording_register(), Some('a'));
+        store.record(Key::Char('j'));
+        store.record(Key::Alt('j'));
+        store.record(Key::Char('q'));
+        store.unrecord_last();
+        assert_eq!(store.get('a'), None);
+        assert_eq!(store.stop_recording(), Some('a'));
+        assert_eq!(store.stop_recording(), None);
+        assert_eq!(store.get('a'), Some(&[Key::Char('j'), Key::Alt('j')][..]));
+        // Starting a new recording whilst recording stores the first macro
+        store.start_recording('b');
+        store.record(Key::Char('x'));
+        store.start_recording('a');
+        assert_eq!(store.get('b'), Some(&[Key::Char('x')][..]));
+        // Re-recording a register replaces its old contents
+        store.stop_recording();
+        assert_eq!(store.get('a'), Some(&[][..]));
+    }
+}
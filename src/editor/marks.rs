@@ -0,0 +1,200 @@
+//! Storage for marks, which remember positions in the tree.  Marks are set with `m<register>` and
+//! jumped to with `'<register>` (exactly like in Vim).
+
+use crate::ast::Ast;
+use crate::editable_tree::compact::Compactor;
+use crate::editable_tree::cursor_path::CursorPath;
+use std::collections::BTreeMap;
+
+/// A position in the tree that the user has marked
+#[derive(Debug, Clone)]
+pub struct Mark<'arena, Node: Ast<'arena>> {
+    /// The path of the marked node, as of the last time it was found in the tree
+    path: CursorPath,
+    /// The node that was marked.  Nodes are immutable, so this can be used to find the node again
+    /// even if its path changes (e.g. because one of its siblings was deleted).
+    node: &'arena Node,
+}
+
+/// Where a [`Mark`] points to in a given tree
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum MarkTarget {
+    /// The marked node (or a node at its path) is still in the tree, at the given path
+    Exact(CursorPath),
+    /// The marked position no longer exists in the tree, so this is the path of its deepest
+    /// ancestor that does
+    Ancestor(CursorPath),
+}
+
+impl MarkTarget {
+    /// Returns the path that this `MarkTarget` points to
+    pub fn path(&self) -> &CursorPath {
+        match self {
+            MarkTarget::Exact(path) | MarkTarget::Ancestor(path) => path,
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Mark<'arena, Node> {
+    /// Creates a mark for the node at `path` in the tree under `root`
+    pub fn new(path: CursorPath, root: &'arena Node) -> Self {
+        let node = path.cursor(root);
+        Mark { path, node }
+    }
+
+    /// Returns the path of the marked node, as of the last time it was found in the tree
+    pub fn path(&self) -> &CursorPath {
+        &self.path
+    }
+
+    /// Returns the path of the marked node in the tree under `root`, or `None` if that node isn't
+    /// in the tree (e.g. because it, or one of its descendants, was edited).
+    pub fn follow(&self, root: &'arena Node) -> Option<CursorPath> {
+        // The common case is that nothing has moved under the mark
+        if self
+            .path
+            .resolve(root)
+            .is_some_and(|node| std::ptr::eq(node, self.node))
+        {
+            return Some(self.path.clone());
+        }
+        CursorPath::path_of(root, self.node)
+    }
+
+    /// Finds the position of this mark in the tree under `root`, which could be a different
+    /// tree to the one that the mark was made in.  The mark follows the node that was marked if
+    /// it's still in the tree, and otherwise goes to whatever is at the marked path.  If nothing
+    /// is at that path any more, the mark falls back to the path's deepest ancestor.
+    pub fn locate(&self, root: &'arena Node) -> MarkTarget {
+        if let Some(path) = self.follow(root) {
+            return MarkTarget::Exact(path);
+        }
+        let (_, steps) = self.path.resolve_longest(root);
+        let path = CursorPath::from_vec(self.path.iter().copied().take(steps).collect());
+        if steps == self.path.depth() {
+            MarkTarget::Exact(path)
+        } else {
+            MarkTarget::Ancestor(path)
+        }
+    }
+}
+
+/// The marks that the user has set, indexed by register
+#[derive(Debug, Clone, Default)]
+pub struct MarkStore<'arena, Node: Ast<'arena>> {
+    marks: BTreeMap<char, Mark<'arena, Node>>,
+}
+
+impl<'arena, Node: Ast<'arena>> MarkStore<'arena, Node> {
+    /// Creates a new `MarkStore` with no marks in it
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks a position in a given register, replacing whatever was there before
+    pub fn set(&mut self, register: char, mark: Mark<'arena, Node>) {
+        self.marks.insert(register, mark);
+    }
+
+    /// Returns the mark in a given register, if one has been set
+    pub fn get(&self, register: char) -> Option<&Mark<'arena, Node>> {
+        self.marks.get(&register)
+    }
+
+    /// Updates every mark to follow its node into a new version of the tree (e.g. after an
+    /// edit).  Marks whose position no longer exists are left unchanged, so that they can be
+    /// found again if the edit is undone.
+    pub fn update(&mut self, root: &'arena Node) {
+        for mark in self.marks.values_mut() {
+            if let MarkTarget::Exact(path) = mark.locate(root) {
+                *mark = Mark::new(path, root);
+            }
+        }
+    }
+
+    /// Makes every mark point to the copy of its node made by a [`Compactor`].  This copies the
+    /// marked nodes if they haven't been copied already, so that marks of nodes which are no
+    /// longer in the tree can still be found if the change that removed them is undone.
+    pub fn compact(&mut self, compactor: &mut Compactor<'arena, Node>) {
+        for mark in self.marks.values_mut() {
+            mark.node = compactor.copy(mark.node);
+        }
+    }
+
+    /// Returns an iterator over every mark, in order of their registers
+    pub fn iter(&self) -> impl Iterator<Item = (char, &Mark<'arena, Node>)> {
+        self.marks.iter().map(|(register, mark)| (*register, mark))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mark, MarkStore, MarkTarget};
+    use crate::arena::Arena;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::editable_tree::{cursor_path::CursorPath, DAG};
+    use crate::editor::normal_mode::Action;
+
+    #[test]
+    fn invalidation() {
+        let arena = Arena::new();
+        let root = J::Array(vec![
+            J::Null,
+            J::Array(vec![J::True, J::Array(vec![J::False])]),
+            J::Array(vec![J::Null]),
+        ])
+        .add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+        let path = CursorPath::from_vec;
+
+        let mut marks = MarkStore::new();
+        marks.set('a', Mark::new(path(vec![1, 1, 0]), tree.root()));
+        marks.set('b', Mark::new(path(vec![2, 0]), tree.root()));
+        assert_eq!(
+            marks.iter().map(|(c, _)| c).collect::<Vec<_>>(),
+            vec!['a', 'b']
+        );
+        let locate = |marks: &MarkStore<_>, c, root| marks.get(c).unwrap().locate(root);
+        assert_eq!(
+            locate(&marks, 'a', tree.root()),
+            MarkTarget::Exact(path(vec![1, 1, 0]))
+        );
+
+        // Deleting a sibling of an ancestor moves the marked nodes, and the marks follow them
+        tree.set_cursor_path(path(vec![0]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        marks.update(tree.root());
+        assert_eq!(
+            locate(&marks, 'a', tree.root()),
+            MarkTarget::Exact(path(vec![0, 1, 0]))
+        );
+        assert_eq!(
+            locate(&marks, 'b', tree.root()),
+            MarkTarget::Exact(path(vec![1, 0]))
+        );
+
+        // Deleting an ancestor of a marked node sends the mark to the deepest ancestor that
+        // still exists
+        tree.set_cursor_path(path(vec![0, 1]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        marks.update(tree.root());
+        assert_eq!(
+            locate(&marks, 'a', tree.root()),
+            MarkTarget::Ancestor(path(vec![0]))
+        );
+        // Undoing brings the marked node back
+        tree.execute_action(Action::Undo).1.unwrap();
+        marks.update(tree.root());
+        assert_eq!(
+            locate(&marks, 'a', tree.root()),
+            MarkTarget::Exact(path(vec![0, 1, 0]))
+        );
+        // If the marked node is replaced, the mark goes to whatever replaced it
+        tree.set_cursor_path(path(vec![0, 1, 0]));
+        tree.execute_action(Action::Replace('n')).1.unwrap();
+        assert_eq!(
+            locate(&marks, 'a', tree.root()),
+            MarkTarget::Exact(path(vec![0, 1, 0]))
+        );
+    }
+}
@@ -1,230 +1,3590 @@
 //! The top-level functionality of Sapling
 
+pub mod autosave;
+pub mod buffer;
+pub mod changes;
+pub mod clipboard;
+pub mod command;
+pub mod crash;
+pub mod diff_view;
+pub mod file;
+pub mod folds;
+pub mod frame;
+pub mod gutter;
+pub mod help;
+pub mod hints;
+pub mod insert;
+pub mod layout;
+pub mod macros;
+pub mod marks;
 pub mod normal_mode;
+pub mod prompt;
+pub mod registers;
+pub mod script;
+pub mod session;
+pub mod shell;
+pub mod signals;
+pub mod status_bar;
+pub mod swap;
+pub mod viewport;
+pub mod visual;
+pub mod window;
 
-use crate::ast::display_token::DisplayToken;
-use crate::ast::{size, Ast};
-use crate::editable_tree::{EditResult, LogMessage, DAG};
-use normal_mode::{keystroke_log, parse_keystroke, KeyMap};
+use crate::ast::diff::diff;
+use crate::ast::display_token::{validate_token_stream, FormatKind, RenderStyle, SyntaxCategory};
+use crate::ast::html;
+use crate::ast::json::query::{self, Query};
+use crate::ast::json::schema::Schema;
+use crate::ast::json::Dialect;
+use crate::ast::json_patch;
+use crate::ast::owned::{OwnedReader, OwnedWriter};
+use crate::ast::size::{self, Size, SizeCache};
+use crate::ast::stats::TreeStats;
+use crate::ast::Ast;
+use crate::config::color::{debug_color, ColorSupport, Style};
+use crate::config::options::{self, OptionError, Options};
+use crate::config::{ColorScheme, Config, THEME_NAMES};
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::history::EditHistory;
+use crate::editable_tree::replace;
+use crate::editable_tree::search::{self, Search};
+use crate::editable_tree::transaction::Transaction;
+use crate::editable_tree::{EditErr, EditResult, EditSuccess, LogMessage, Side, DAG};
+use crate::language::{Exit, Language, LanguageRegistry};
+use crate::logging;
+use buffer::{Buffer, BufferLoader, DiskVersion};
+use changes::ChangeHighlight;
+use clipboard::{Backend, Clipboard};
+use command::{Command, CommandRegistry, ExportFormat};
+use diff_view::DiffView;
+use file::TextWriter;
+use gutter::{LineNumbers, NumberStyle};
+use help::Help;
+use hints::Hints;
+use insert::StringEditor;
+use layout::Lines;
+use macros::MacroStore;
+use marks::{Mark, MarkTarget};
+use normal_mode::{
+    conflicting_keys, keystroke_binding, keystroke_log, parse_keys, parse_keystroke, pending_keys,
+    split_prefix, Action, KeyMap, KeyStroke, Pending,
+};
+use prompt::{History, LineEditor};
+use registers::{Registers, SavedRegister};
+use script::{Script, ScriptError, ScriptOutput};
+use session::{SavedSession, SessionError, SessionFile};
+use signals::Signal;
+use status_bar::{StatusFormat, StatusInfo};
+use std::cell::Cell;
 use std::collections::hash_map::DefaultHasher;
+use std::fmt;
 use std::hash::Hasher;
+use std::io::{BufWriter, Write as _};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use swap::{Journal, SwapError, SwapFile};
 use tuikit::prelude::*;
+use tuikit::screen::Screen;
+use viewport::{Layout, Skip};
+use visual::Selection;
+use window::{Partition, Rect, Split, SplitTree, Window};
+
+/// How many lines the tree scrolls for every step of the mouse wheel
+const MOUSE_SCROLL_LINES: usize = 3;
+
+/// How often the file of the buffer being edited is checked for changes made by other programs.
+/// Terminals don't say when they regain focus, so this is the only way of noticing.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The width and height of the screen that the editor pretends to have when it isn't running in
+/// a terminal (e.g. whilst running a script), until it's rendered at a different size with
+/// [`Editor::render_to_string`]
+const HEADLESS_SIZE: (usize, usize) = (80, 24);
+
+/// The smallest screen (in columns and lines) that the editor is drawn on.  Anything smaller only
+/// shows a message saying that the terminal is too small.
+pub const MIN_SCREEN_SIZE: (usize, usize) = (20, 5);
+
+/// Returns `true` if a [`Key`] was generated by the mouse rather than the keyboard
+fn is_mouse_event(key: Key) -> bool {
+    matches!(
+        key,
+        Key::MousePress(..)
+            | Key::MouseRelease(..)
+            | Key::MouseHold(..)
+            | Key::SingleClick(..)
+            | Key::DoubleClick(..)
+            | Key::WheelUp(..)
+            | Key::WheelDown(..)
+    )
+}
+
+/// Returns the kind of format that the tree is shown on the screen with, if the `format` option
+/// asks for `kind` and the terminal is `screen_width` columns wide.  Nodes are only kept on one
+/// line by the `fit` format if they fit on the screen.
+fn screen_format_kind(kind: FormatKind, screen_width: usize) -> FormatKind {
+    match kind {
+        FormatKind::Fit { max_width } => FormatKind::Fit {
+            max_width: max_width.min(screen_width),
+        },
+        _ => kind,
+    }
+}
+
+/// Returns a number of bytes as a human-readable size, like `512 B` or `3.4 MiB`
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The state of the editor whilst the user is typing a search query
+struct SearchPrompt {
+    /// The query typed so far
+    query: String,
+    /// Where the cursor was when the search started.  Matches are searched for from here, and
+    /// the cursor returns here if the search is cancelled.
+    origin: CursorPath,
+}
+
+/// The state of a find-and-replace which is asking the user whether to replace each match
+struct ReplaceConfirm {
+    /// The text being replaced
+    pattern: String,
+    /// The text to replace `pattern` with
+    replacement: String,
+    /// The paths of every node that could be changed, in pre-order
+    candidates: Vec<CursorPath>,
+    /// The index of the candidate that the user is being asked about
+    next: usize,
+    /// The candidates that the user has agreed to replace
+    accepted: Vec<CursorPath>,
+}
+
+/// The state of the editor whilst the user is being asked what to do about the file of the
+/// buffer being edited, which another program has changed
+struct DiskChange {
+    /// The text that the other program wrote
+    version: DiskVersion,
+    /// If the change was noticed whilst saving, whether Sapling should quit once the file has
+    /// been saved (as with `:wq`).  This is `None` if the change was noticed in the background.
+    saving: Option<bool>,
+}
+
+impl DiskChange {
+    /// Returns the prompt shown in the bottom bar, for the buffer called `name`
+    fn prompt(&self, name: &str) -> String {
+        let (keep, cancel) = match self.saving {
+            Some(_) => ("overwrite it", "don't save"),
+            None => ("keep mine", "decide later"),
+        };
+        format!(
+            "{} changed on disk.  r: reload  o: {}  b: compare  Esc: {}",
+            name, keep, cancel
+        )
+    }
+}
+
+/// The state of the editor whilst the user is typing the text of the cursor (e.g. the contents
+/// of a string).  The tree isn't changed until the text is finished.
+struct TextInsert<'arena, Node: Ast<'arena>> {
+    /// The text typed so far, along with where the text cursor is in it
+    text: StringEditor,
+    /// The tree with the text typed so far in place of the cursor's text, which is what the
+    /// focused window shows until the text is finished
+    preview: &'arena Node,
+}
+
+/// The state of the full-screen keystroke log, whilst it's being shown
+struct LogView {
+    /// If `true`, only the keystrokes that failed are shown
+    errors_only: bool,
+    /// How many entries are scrolled off the top of the screen
+    scroll: usize,
+}
 
 /// A struct to hold the top-level components of the editor.
 pub struct Editor<'arena, Node: Ast<'arena>> {
-    /// The [`EditableTree`] that the `Editor` is editing
-    tree: &'arena mut DAG<'arena, Node>,
+    /// Every tree that is open, in the order that they were opened
+    buffers: Vec<Buffer<'arena, Node>>,
+    /// Every window, each of which shows one of the buffers
+    windows: Vec<Window<'arena, Node>>,
+    /// How the screen is split between the windows
+    split_tree: SplitTree,
+    /// The index of the window that keys are sent to.  Its buffer is the one being edited.
+    focused: usize,
+    /// Opens files into new buffers
+    loader: BufferLoader<'arena, Node>,
     /// The style that the tree is being printed to the screen
     format_style: Node::FormatStyle,
-    /// The `tuikit` terminal that the `Editor` is rendering to
-    term: Term,
+    /// The kind of `format_style`, which can be narrower than the `format` option says (see
+    /// [`Editor::update_format_style`])
+    format_kind: FormatKind,
+    /// The options that can be changed with `:set`
+    options: Options,
+    /// The `tuikit` terminal that the `Editor` is rendering to, which is only opened once the
+    /// editor starts [`run`](Editor::run)ning (so scripts can be run without a terminal)
+    term: Option<Term>,
+    /// If `true`, the terminal reports mouse events
+    mouse: bool,
+    /// The width and height of the screen whilst there's no terminal
+    headless_size: (usize, usize),
     /// The current contents of the keystroke buffer
     keystroke: String,
     /// The configured key map
     keymap: KeyMap,
+    /// The colours used to syntax highlight the tree
+    color_scheme: ColorScheme,
+    /// The colours from the config file, which are applied on top of whichever colour scheme is
+    /// chosen
+    color_overrides: ColorScheme,
     /// A list of the keystrokes that have been executed, along with a summary of what they mean
     keystroke_log: keystroke_log::KeyStrokeLog,
+    /// The full-screen view of the keystroke log, if it's being shown
+    log_view: Option<LogView>,
+    /// The help box, if it's being shown
+    help: Option<Help>,
+    /// A message (usually an error) to show the user in the bottom bar
+    status_message: Option<String>,
+    /// What the status bar shows
+    status_format: StatusFormat,
+    /// The tree whose nodes were most recently counted for the status bar, along with how many
+    /// nodes it has
+    node_count: Cell<Option<(&'arena Node, usize)>>,
+    /// The subtrees that have been yanked or cut, which are inserted by pasting.  These are shared
+    /// by every buffer, so nodes can be pasted from one buffer into another.
+    registers: Registers<'arena, Node>,
+    /// The system clipboard, which is the register `+`
+    clipboard: Clipboard,
+    /// The keystroke macros that have been recorded, and the one being recorded (if any)
+    macros: MacroStore,
+    /// How many macros are currently being replayed inside one another
+    macro_depth: usize,
+    /// The sizes of the nodes that have been laid out, which let layouts skip over subtrees that
+    /// aren't on the screen
+    sizes: SizeCache<'arena, Node>,
+    /// The most recent [`Action`] that edited the tree, along with its count.  This is what `.`
+    /// repeats.
+    last_edit: Option<(Action, usize)>,
+    /// The node that the most recent keystroke typed a char into (or removed one from, or made
+    /// out of a digit or `-`), along with its path, if it did.  Whilst it's still in the tree, chars
+    /// carry on being typed into this node, even if it isn't the cursor (e.g. after `a0`).  Chars
+    /// other than digits which are also keystrokes (like `.`, which repeats the last edit) are
+    /// only typed into this node.
+    typed_into: Option<(CursorPath, &'arena Node)>,
+    /// The most recent search, whose matches are highlighted until it's dismissed with `<ESC>`
+    search: Option<Search<'arena, Node>>,
+    /// The nodes that were changed by the most recent undo or redo, which are highlighted for a
+    /// moment afterwards
+    change_highlight: Option<ChangeHighlight<'arena, Node>>,
+    /// When the unfinished keystroke in the keystroke buffer was last typed into, which is when
+    /// the wait before showing its [`Hints`] starts
+    pending_since: Option<Instant>,
+    /// The two buffers being compared side by side, if there are any (see
+    /// [`show_diff`](Editor::show_diff))
+    diff: Option<DiffView<'arena, Node>>,
+    /// The search query being typed, if the user is typing one
+    search_prompt: Option<SearchPrompt>,
+    /// The command being typed after `:`, if the user is typing one
+    command_prompt: Option<LineEditor>,
+    /// The commands that have been run, which can be recalled with the arrow keys
+    command_history: History,
+    /// The commands that can be typed after `:`
+    commands: CommandRegistry,
+    /// The find-and-replace that is waiting for the user to confirm each match, if there is one
+    replace_confirm: Option<ReplaceConfirm>,
+    /// The change to the buffer's file that the user is being asked about, if there is one
+    disk_change: Option<DiskChange>,
+    /// When the file of the buffer being edited was last checked for changes made by other
+    /// programs
+    last_disk_check: Instant,
+    /// The text being typed into the cursor, if the user is in insert mode
+    insert: Option<TextInsert<'arena, Node>>,
+    /// The siblings being selected, if the user is in visual mode
+    visual: Option<Selection>,
+    /// The language that every buffer is written in
+    language: Language,
+    /// The languages that the buffers could be switched to, and the extensions of their files
+    languages: LanguageRegistry,
+    /// The JSON Schema that every buffer is checked against (see the `schema` option), if there
+    /// is one
+    schema: Option<Rc<Schema>>,
+    /// Why the editor is about to stop running, if it's for any reason other than quitting
+    exit: Option<Exit>,
+    /// If `true`, the changes to every buffer are written to [`swap`] files.  This is only done
+    /// whilst the editor is running in a terminal, so that scripts don't leave files behind.
+    swap_files: bool,
+    /// If `true`, every buffer is opened read-only (with `--read-only`)
+    read_only: bool,
 }
 
-impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
-    /// Create a new [`Editor`] with a given tree
-    pub fn new(
-        tree: &'arena mut DAG<'arena, Node>,
-        format_style: Node::FormatStyle,
-        keymap: KeyMap,
-    ) -> Editor<'arena, Node> {
-        let term = Term::new().unwrap();
-        Editor {
-            tree,
-            term,
-            format_style,
-            keystroke: String::new(),
-            keymap,
-            keystroke_log: keystroke_log::KeyStrokeLog::new(10),
+impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
+    /// Create a new [`Editor`] which starts by editing a given buffer, which is written in
+    /// `language`.  Any other files that the user opens are loaded with `loader`.
+    pub fn new(
+        mut buffer: Buffer<'arena, Node>,
+        loader: BufferLoader<'arena, Node>,
+        mut format_style: Node::FormatStyle,
+        config: Config,
+        language: Language,
+        languages: LanguageRegistry,
+    ) -> Editor<'arena, Node> {
+        let format_kind = config.options.format_kind();
+        config.options.apply_to(&mut format_style, language);
+        let window = Window::new(0, buffer.tree.cursor_path().clone(), buffer.tree.root());
+        if config.read_only {
+            buffer.tree.set_read_only(true);
+        }
+        // Tell the user straight away if the colour scheme is missing any styles, rather than
+        // leaving them to wonder why some text is drawn in the default colour
+        let color_scheme = config.color_scheme.for_language(language);
+        let missing = color_scheme.validate();
+        let status_message = if missing.is_empty() {
+            None
+        } else {
+            let names: Vec<_> = missing.iter().map(|c| format!("'{}'", c)).collect();
+            Some(format!(
+                "The colour scheme has no style for {} (see :messages)",
+                names.join(", ")
+            ))
+        };
+        let mut editor = Editor {
+            buffers: vec![buffer],
+            windows: vec![window],
+            split_tree: SplitTree::Window(0),
+            focused: 0,
+            loader,
+            status_message,
+            status_format: config.status_format,
+            node_count: Cell::new(None),
+            registers: Registers::new(),
+            clipboard: Clipboard::new(),
+            macros: MacroStore::new(),
+            macro_depth: 0,
+            sizes: SizeCache::new(),
+            last_edit: None,
+            typed_into: None,
+            search: None,
+            change_highlight: None,
+            pending_since: None,
+            diff: None,
+            search_prompt: None,
+            command_prompt: None,
+            command_history: History::new(),
+            commands: CommandRegistry::default(),
+            replace_confirm: None,
+            disk_change: None,
+            last_disk_check: Instant::now(),
+            insert: None,
+            visual: None,
+            term: None,
+            mouse: config.mouse,
+            headless_size: HEADLESS_SIZE,
+            format_style,
+            format_kind,
+            options: config.options,
+            keystroke: String::new(),
+            keymap: config.keymap,
+            color_scheme,
+            color_overrides: config.color_overrides,
+            keystroke_log: keystroke_log::KeyStrokeLog::new(10),
+            log_view: None,
+            help: None,
+            language,
+            languages,
+            schema: None,
+            exit: None,
+            swap_files: false,
+            read_only: config.read_only,
+        };
+        if !editor.options.schema.is_empty() {
+            if let Err(e) = editor.load_schema() {
+                log::warn!("{}", e);
+                editor.status_message = Some(e.to_string());
+            }
+        }
+        editor
+    }
+
+    /// Returns the terminal that the editor is rendering to.  This must only be called whilst the
+    /// editor is running in a terminal.
+    fn term(&self) -> &Term {
+        self.term
+            .as_ref()
+            .expect("the editor is only rendered whilst it's running in a terminal")
+    }
+
+    /// Returns the width and height of the screen.  Without a terminal (e.g. whilst running a
+    /// script), the screen is the size that it was last rendered at (or [`HEADLESS_SIZE`]).
+    fn screen_size(&self) -> (usize, usize) {
+        match &self.term {
+            Some(term) => term.term_size().unwrap_or(HEADLESS_SIZE),
+            None => self.headless_size,
+        }
+    }
+
+    /// Returns the index of the buffer being edited
+    fn active(&self) -> usize {
+        self.windows[self.focused].buffer
+    }
+
+    /// Returns the buffer being edited
+    fn buffer(&self) -> &Buffer<'arena, Node> {
+        &self.buffers[self.active()]
+    }
+
+    /// Returns the buffer being edited, mutably
+    fn buffer_mut(&mut self) -> &mut Buffer<'arena, Node> {
+        let active = self.active();
+        &mut self.buffers[active]
+    }
+
+    /// Returns the tree being edited
+    fn tree(&self) -> &DAG<'arena, Node> {
+        &self.buffer().tree
+    }
+
+    /// Returns the tree being edited, mutably
+    fn tree_mut(&mut self) -> &mut DAG<'arena, Node> {
+        &mut self.buffer_mut().tree
+    }
+
+    /// Returns the focused window
+    fn window(&self) -> &Window<'arena, Node> {
+        &self.windows[self.focused]
+    }
+
+    /// Returns the focused window, mutably
+    fn window_mut(&mut self) -> &mut Window<'arena, Node> {
+        &mut self.windows[self.focused]
+    }
+
+    /// Returns the buffer being edited and the focused window (which shows that buffer) at the
+    /// same time, so that one can be changed using the other
+    fn buffer_and_window_mut(&mut self) -> (&mut Buffer<'arena, Node>, &mut Window<'arena, Node>) {
+        let window = &mut self.windows[self.focused];
+        (&mut self.buffers[window.buffer], window)
+    }
+
+    /// Returns the root of the tree that a window shows.  Whilst text is being typed, the focused
+    /// window shows the tree with the text typed so far.
+    fn shown_root(&self, window: &Window<'arena, Node>) -> &'arena Node {
+        match &self.insert {
+            Some(insert) if std::ptr::eq(window, self.window()) => insert.preview,
+            _ => self.buffers[window.buffer].tree.root(),
+        }
+    }
+
+    /// Returns the [`Layout`] of the tokens of a window's tree, as they would be rendered to the
+    /// screen
+    fn layout<'s>(&'s self, window: &'s Window<'arena, Node>) -> Layout<'s, 'arena, Node> {
+        let root = self.shown_root(window);
+        Layout::new(
+            window.folds.display_tokens(root, &self.format_style),
+            self.format_style.indent().columns(self.options.tab_width),
+            self.options.tab_width,
+        )
+    }
+
+    /// Lays out the `height` lines of a window's tree that are scrolled into view.  Subtrees that
+    /// end above the window are skipped, and tokens are only generated until the bottom of the
+    /// window is reached.
+    fn visible_lines(&self, window: &Window<'arena, Node>, height: usize) -> Lines<'arena, Node> {
+        let layout = self
+            .layout(window)
+            .skipping(&self.sizes, Skip::BeforeLine(window.scroll_offset));
+        Lines::new(layout, window.scroll_offset, height)
+    }
+
+    /// Returns how many lines a window's tree has when nothing is folded
+    fn line_count(&self, window: &Window<'arena, Node>) -> usize {
+        let root = self.shown_root(window);
+        self.sizes.size(root, &self.format_style).lines() + 1
+    }
+
+    /// Returns how many columns the gutter of line numbers on the left of a window takes up,
+    /// which is 0 unless `number` or `relativenumber` is set
+    fn gutter_width(&self, window: &Window<'arena, Node>) -> usize {
+        if !self.options.number && !self.options.relativenumber {
+            return 0;
+        }
+        gutter::gutter_width(self.line_count(window))
+    }
+
+    /// Returns the part of a window's rectangle that its tree is drawn in, to the right of its
+    /// gutter.  Windows which are too narrow for the gutter don't have one.
+    fn tree_rect(&self, window: &Window<'arena, Node>, rect: Rect) -> Rect {
+        let gutter = self.gutter_width(window);
+        if gutter >= rect.width {
+            return rect;
+        }
+        Rect {
+            col: rect.col + gutter,
+            width: rect.width - gutter,
+            ..rect
+        }
+    }
+
+    /// Returns the line numbers of a window's tree, which take the window's folds into account
+    fn line_numbers(&self, window: &Window<'arena, Node>) -> LineNumbers {
+        let root = self.shown_root(window);
+        // Folds inside other folds aren't drawn, so they don't hide any more lines
+        let folds = window
+            .folds
+            .paths()
+            .into_iter()
+            .filter_map(|path| {
+                let layout = self
+                    .layout(window)
+                    .skipping(&self.sizes, Skip::Outside(path.clone()));
+                let (line, _) = viewport::position_of(layout, path)?;
+                let node = path.resolve(root)?;
+                Some((line, self.sizes.size(node, &self.format_style).lines()))
+            })
+            .collect();
+        LineNumbers::new(folds, self.line_count(window))
+    }
+
+    /// Draws the line numbers of the lines of a window's tree that are scrolled into view, in
+    /// the gutter between `rect` (the window's rectangle) and `tree_rect` (where its tree is)
+    fn render_gutter(&self, canvas: &mut dyn Canvas, index: usize, rect: Rect, tree_rect: Rect) {
+        let window = &self.windows[index];
+        let numbers = self.line_numbers(window);
+        let cursor_line = self.cursor_position_in(index).0;
+        let style = NumberStyle {
+            absolute: self.options.number,
+            relative: self.options.relativenumber,
+        };
+        let attr = self.color_scheme.style(SyntaxCategory::Gutter).attr();
+        // One column is left between the numbers and the tree
+        let digits = tree_rect.col - rect.col - 1;
+        for row in 0..rect.height {
+            if let Some(label) = numbers.label(window.scroll_offset + row, cursor_line, style) {
+                let text = format!("{:>width$}", label, width = digits);
+                canvas
+                    .print_with_attr(rect.row + row, rect.col, &text, attr)
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Render the part of a window's tree that is scrolled into view to the window's rectangle of
+    /// the screen.  Only the focused window's cursor is filled in, and the cursors of the other
+    /// windows are underlined.
+    fn render_window(&self, canvas: &mut dyn Canvas, index: usize, rect: Rect) {
+        let window = &self.windows[index];
+        let tree_rect = self.tree_rect(window, rect);
+        if tree_rect.col > rect.col {
+            self.render_gutter(canvas, index, rect, tree_rect);
+        }
+        let rect = tree_rect;
+        let root = self.shown_root(window);
+        let validation = &self.buffers[window.buffer].validation;
+        let cursor_path = if index == self.focused {
+            self.tree().cursor_path()
+        } else {
+            window.cursor_path()
+        };
+        let cursor = cursor_path.cursor(root);
+        // The brackets of every node that the cursor is inside are picked out, so that it's easy
+        // to see which arrays and objects contain it
+        let encloses_cursor = |span: &layout::Span<'arena, Node>| {
+            span.delimiter
+                && span.path.depth() < cursor_path.depth()
+                && cursor_path.starts_with(&span.path)
+        };
+        // Where the text cursor is in the rendered text of the cursor (counting from the start of
+        // the current span once the cursor's first span has been reached), if text is being typed
+        let mut text_offset = match &self.insert {
+            Some(insert) if index == self.focused => Some(cursor.text_offset(insert.text.cursor())),
+            _ => None,
+        };
+        let now = Instant::now();
+        let change_category = |node| {
+            self.change_highlight
+                .as_ref()
+                .and_then(|h| h.category(root, node, now))
+                .or_else(|| self.diff.as_ref().and_then(|d| d.category(root, node)))
+        };
+        // The selected siblings are only highlighted in the window that's selecting them
+        let selection = self.visual.as_ref().filter(|_| index == self.focused);
+        let selected = |path: &CursorPath| {
+            selection.is_some_and(|s| s.contains(self.tree().cursor_path(), path))
+        };
+        for (line, spans) in self.visible_lines(window, rect.height).iter() {
+            for span in spans {
+                let style = if self.options.debug_highlighting {
+                    // Hash the ref to decide on the colour
+                    let mut hasher = DefaultHasher::new();
+                    span.node.hash(&mut hasher);
+                    Style::new(debug_color(hasher.finish()))
+                } else if let Some(category) = change_category(span.node) {
+                    self.color_scheme.style(category)
+                } else if self.search.as_ref().is_some_and(|s| s.contains(span.node)) {
+                    self.color_scheme.style(SyntaxCategory::Search)
+                } else if validation.is_problem(span.node) {
+                    self.color_scheme.style(SyntaxCategory::Error)
+                } else {
+                    self.color_scheme.resolve(span.category)
+                };
+                // Generate the display attributes depending on if the node is selected
+                let mut attr = match std::ptr::eq(span.node, cursor) {
+                    true if index == self.focused => Attr::default().fg(Color::BLACK).bg(style.fg),
+                    true => Attr::default().fg(style.fg).effect(Effect::UNDERLINE),
+                    false if selected(&span.path) => style
+                        .attr()
+                        .bg(self.color_scheme.background(SyntaxCategory::Selection)),
+                    false => style.attr(),
+                };
+                if !std::ptr::eq(span.node, cursor) && encloses_cursor(span) {
+                    let ancestor = self.color_scheme.style(SyntaxCategory::Ancestor);
+                    attr.effect |= ancestor.effect();
+                    if ancestor.fg != Color::Default {
+                        attr.fg = ancestor.fg;
+                    }
+                    if let (Some(background), Color::Default) = (ancestor.bg, attr.bg) {
+                        attr.bg = background;
+                    }
+                }
+                let visible = viewport::clip(
+                    &span.text,
+                    span.x,
+                    window.col_offset,
+                    rect.width,
+                    self.options.tab_width,
+                );
+                let row = rect.row + line - window.scroll_offset;
+                if let Some((screen_col, text)) = visible {
+                    canvas
+                        .print_with_attr(row, rect.col + screen_col, text, attr)
+                        .unwrap();
+                }
+                // Draw the text cursor over the grapheme that the next typed char will go before.
+                // The cursor's text can be split into many spans (e.g. around escape sequences).
+                let offset = match text_offset {
+                    Some(offset) if std::ptr::eq(span.node, cursor) => offset,
+                    _ => continue,
+                };
+                if offset >= span.text.len() {
+                    text_offset = Some(offset - span.text.len());
+                    continue;
+                }
+                text_offset = None;
+                if let Some(rest) = span.text.get(offset..) {
+                    let grapheme = &rest[..size::grapheme_boundaries(rest)[1]];
+                    let col = span.col_of(offset, self.options.tab_width);
+                    let visible = viewport::clip(
+                        grapheme,
+                        col,
+                        window.col_offset,
+                        rect.width,
+                        self.options.tab_width,
+                    );
+                    if let Some((screen_col, text)) = visible {
+                        let attr = attr.effect(Effect::REVERSE);
+                        canvas
+                            .print_with_attr(row, rect.col + screen_col, text, attr)
+                            .unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws the lines between windows
+    fn render_dividers(&self, canvas: &mut dyn Canvas, partition: &Partition) {
+        for (split, rect) in &partition.dividers {
+            match split {
+                Split::Horizontal => {
+                    let line = "─".repeat(rect.width);
+                    canvas.print(rect.row, rect.col, &line).unwrap();
+                }
+                Split::Vertical => {
+                    for row in rect.row..rect.row + rect.height {
+                        canvas.print(row, rect.col, "│").unwrap();
+                    }
+                }
+            };
+        }
+    }
+
+    /// Returns how the screen is shared between the windows.  The windows cover everything
+    /// except the bottom bar.
+    fn partition(&self) -> Partition {
+        let (width, height) = self.screen_size();
+        self.split_tree.partition(Rect {
+            row: 0,
+            col: 0,
+            width,
+            height: height.saturating_sub(1),
+        })
+    }
+
+    /// Returns the rectangle of the screen that the focused window is rendered to
+    fn focused_rect(&self) -> Rect {
+        self.partition().rect_of(self.focused).unwrap()
+    }
+
+    /// Returns the line and column of the tree that the cursor starts on
+    fn cursor_position(&self) -> (usize, usize) {
+        self.cursor_position_in(self.focused)
+    }
+
+    /// Returns the line and column of the tree that the cursor of the window at `index` starts on
+    fn cursor_position_in(&self, index: usize) -> (usize, usize) {
+        // The cursor is never inside a fold, so it's always rendered
+        let window = &self.windows[index];
+        let cursor_path = if index == self.focused {
+            self.tree().cursor_path()
+        } else {
+            window.cursor_path()
+        };
+        let layout = self
+            .layout(window)
+            .skipping(&self.sizes, Skip::Outside(cursor_path.clone()));
+        viewport::position_of(layout, cursor_path).unwrap_or((0, 0))
+    }
+
+    /// Scrolls the focused window as little as possible to keep the start of the cursor (and the
+    /// lines and columns around it) in the window
+    fn scroll_to_cursor(&mut self) {
+        let rect = self.tree_rect(self.window(), self.focused_rect());
+        let (line, col) = self.cursor_position();
+        let scroll_off = self.options.scrolloff;
+        let window = self.window_mut();
+        window.scroll_offset =
+            viewport::scroll_to_show(window.scroll_offset, line, rect.height, scroll_off);
+        window.col_offset = viewport::scroll_to_show(
+            window.col_offset,
+            col,
+            rect.width,
+            viewport::SIDE_SCROLL_OFF,
+        );
+    }
+
+    /// Scrolls half a window in a given direction, moving the cursor to the first node which
+    /// starts at least as far from the top of the window as the cursor did
+    fn scroll_half_page(&mut self, side: Side) -> EditResult {
+        self.scroll_by(side, (self.focused_rect().height / 2).max(1))
+    }
+
+    /// Scrolls `amount` lines in a given direction, moving the cursor to the first node which
+    /// starts at least as far from the top of the window as the cursor did
+    fn scroll_by(&mut self, side: Side, amount: usize) -> EditResult {
+        let cursor_line = self.cursor_position().0;
+        let scroll_offset = self.window().scroll_offset;
+        let (offset, target_line) = match side {
+            Side::Next => (scroll_offset + amount, cursor_line + amount),
+            Side::Prev => (
+                scroll_offset.saturating_sub(amount),
+                cursor_line.saturating_sub(amount),
+            ),
+        };
+        let (path, line) = viewport::first_node_from(self.layout(self.window()), target_line);
+        // Don't scroll off the end of the tree
+        self.window_mut().scroll_offset = offset.min(line);
+        self.tree_mut().set_cursor_path(path);
+        Ok(EditSuccess::Scroll {
+            line: self.window().scroll_offset,
+        })
+    }
+
+    /* ===== MOUSE FUNCTIONS ===== */
+
+    /// Responds to a mouse event (which is only sent if mouse support is turned on).  Returns
+    /// `None` if the event doesn't do anything.
+    fn handle_mouse(&mut self, key: Key) -> Option<EditResult> {
+        // The tree isn't visible behind the log, and the cursor can't move away from the text
+        // that's being typed into it
+        if self.log_view.is_some() || self.insert.is_some() {
+            return None;
+        }
+        let result = match key {
+            // Clicking in a window focuses it
+            Key::SingleClick(MouseButton::Left, row, col) => {
+                let (window, path) = self.path_on_screen(row as usize, col as usize)?;
+                self.focus_window(window);
+                self.tree_mut().goto(path)
+            }
+            Key::DoubleClick(MouseButton::Left, row, col) => {
+                let (window, mut path) = self.path_on_screen(row as usize, col as usize)?;
+                path.pop();
+                self.focus_window(window);
+                self.tree_mut().goto(path)
+            }
+            Key::WheelUp(_, _, count) => {
+                self.scroll_by(Side::Prev, MOUSE_SCROLL_LINES * count as usize)
+            }
+            Key::WheelDown(_, _, count) => {
+                self.scroll_by(Side::Next, MOUSE_SCROLL_LINES * count as usize)
+            }
+            _ => return None,
+        };
+        // Using the mouse cancels any keystroke that was being typed
+        self.keystroke.clear();
+        self.follow_tree_changes();
+        Some(result)
+    }
+
+    /// Returns the window under a given row and column of the screen, along with the path of the
+    /// node in that window that is rendered closest to that position (on the same line).  Returns
+    /// `None` if the position isn't in a window, or that line of the window is empty.
+    fn path_on_screen(&self, row: usize, col: usize) -> Option<(usize, CursorPath)> {
+        let (index, rect) = self.partition().window_at(row, col)?;
+        let window = &self.windows[index];
+        let rect = self.tree_rect(window, rect);
+        let lines = self.visible_lines(window, rect.height);
+        // Clicking in the gutter finds the start of the line
+        let span = lines.nearest_span(
+            window.scroll_offset + row - rect.row,
+            window.col_offset + col.saturating_sub(rect.col),
+        )?;
+        Some((index, span.path.clone()))
+    }
+
+    /* ===== FILE FUNCTIONS ===== */
+
+    /// Returns the text of the status bar, filled in from the [`StatusFormat`]
+    fn status_text(&self) -> String {
+        let file_name = self.buffer().name();
+        let mut flags = Vec::new();
+        if self.buffer().is_dirty() {
+            flags.push("[+]".to_string());
+        }
+        if self.tree().is_read_only() {
+            flags.push("[RO]".to_string());
+        }
+        if self.buffers.len() > 1 {
+            flags.push(format!(
+                "[buffer {}/{}]",
+                self.active() + 1,
+                self.buffers.len()
+            ));
+        }
+        if self.windows.len() > 1 {
+            flags.push(format!(
+                "[window {}/{}]",
+                self.window_position() + 1,
+                self.windows.len()
+            ));
+        }
+        // Make it obvious that the strange colours are intentional
+        if self.options.debug_highlighting {
+            flags.push("[debug colours]".to_string());
+        }
+        if let Some(register) = self.macros.recording_register() {
+            flags.push(format!("[recording @{}]", register));
+        }
+        if let Some(Ok((_, range))) = self
+            .visual
+            .as_ref()
+            .map(|v| v.range(self.tree().cursor_path()))
+        {
+            flags.push(format!("[visual: {} selected]", range.len()));
+        }
+        // Counting the nodes takes a while in big trees, so don't do it unless it's shown
+        let node_count = if self.status_format.shows_node_count() {
+            self.node_count()
+        } else {
+            0
+        };
+        self.status_format.render(&StatusInfo {
+            file_name: &file_name,
+            flags: &flags,
+            path: self.tree().cursor_path(),
+            node_name: &self.tree().cursor().display_name(),
+            node_count,
+        })
+    }
+
+    /// Returns the number of nodes in the tree, which is only counted again if the tree has changed
+    fn node_count(&self) -> usize {
+        let root = self.tree().root();
+        match self.node_count.get() {
+            Some((counted_root, count)) if std::ptr::eq(counted_root, root) => count,
+            _ => {
+                let count = root.node_count();
+                self.node_count.set(Some((root, count)));
+                count
+            }
+        }
+    }
+
+    /// Writes the current tree to the file that it was loaded from.  The text is streamed to the
+    /// file rather than being built up in memory first, which matters for huge trees.  If
+    /// another program has changed the file since it was last read or written, then nothing is
+    /// written and the user is asked what to do instead.
+    pub fn save(&mut self) -> EditResult {
+        if self.tree().is_read_only() {
+            let name = self.buffer().name();
+            return Err(EditErr::WriteReadOnly { name });
+        }
+        if let Some(version) = self.buffer_mut().disk_version() {
+            let name = self.buffer().name();
+            self.disk_change = Some(DiskChange {
+                version,
+                saving: Some(false),
+            });
+            return Err(EditErr::ChangedOnDisk { name });
+        }
+        self.write_to_file()
+    }
+
+    /// Writes the current tree to the file it was loaded from, even if the buffer is read-only
+    /// (with `:w!`).  Files which are read-only themselves are never overwritten.
+    fn write_to_file(&mut self) -> EditResult {
+        let path = self.buffer().file_path.clone().ok_or(EditErr::NoFilePath)?;
+        self.buffer().check_writable()?;
+        if file::is_read_only(&path) {
+            return Err(EditErr::SaveFailed {
+                path: path.to_string_lossy().into_owned(),
+                message: "the file is read-only".to_string(),
+            });
+        }
+        let hash = self
+            .write_tree(self.tree().root(), &path)
+            .map_err(|e| EditErr::SaveFailed {
+                path: path.to_string_lossy().into_owned(),
+                message: e.to_string(),
+            })?;
+        self.buffer_mut().mark_saved_as(session::hash_to_hex(hash));
+        // The file now has everything that the shadow file had
+        let shadow_path = autosave::shadow_path(&path);
+        if shadow_path.exists() {
+            if let Err(e) = std::fs::remove_file(&shadow_path) {
+                log::warn!("Couldn't delete {}: {}", shadow_path.display(), e);
+            }
+        }
+        Ok(EditSuccess::Save {
+            path: path.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// Atomically writes the text of the tree under `root` to `path`, formatted as files are
+    /// saved, and returns the [`fnv1a`](file::fnv1a) hash of the text
+    fn write_tree(&self, root: &'arena Node, path: &std::path::Path) -> std::io::Result<u64> {
+        let format_style = self.file_format_style();
+        let mut hash = 0;
+        file::write_atomically_with(path, |file| {
+            let mut writer = TextWriter::new(BufWriter::new(file));
+            let result = root
+                .write_text_to(&mut writer, &format_style)
+                // Text files should end with a newline
+                .and_then(|()| fmt::Write::write_char(&mut writer, '\n'));
+            writer.check(result)?;
+            hash = writer.hash();
+            writer.into_inner().flush()
+        })?;
+        Ok(hash)
+    }
+
+    /// Returns how long auto-saving waits after the last change to a buffer, or `None` if
+    /// buffers aren't auto-saved
+    fn autosave_delay(&self) -> Option<Duration> {
+        match self.options.autosave {
+            0 => None,
+            delay => Some(Duration::from_millis(delay as u64)),
+        }
+    }
+
+    /// Returns how long after `now` the next buffer should be auto-saved, or `None` if no buffer
+    /// has changes that are waiting to be auto-saved.  Nothing is auto-saved whilst text is
+    /// being typed, since the text isn't in the tree until it's finished.
+    fn autosave_remaining(&self, now: Instant) -> Option<Duration> {
+        let delay = self.autosave_delay()?;
+        if self.insert.is_some() {
+            return None;
+        }
+        self.buffers
+            .iter()
+            .filter_map(|buffer| buffer.autosave.remaining(now, delay))
+            .min()
+    }
+
+    /// Auto-saves every buffer whose tree hasn't changed for the `autosave` delay before `now`,
+    /// to either its file or its shadow file (depending on `autosave_to`).  This only happens
+    /// between keys, so never in the middle of a transaction.  Buffers which are read-only or
+    /// have no file aren't auto-saved, and neither are files which another program has changed
+    /// (which the user is asked about instead of having them overwritten).  If a buffer can't be
+    /// written, then a warning is shown and that buffer isn't auto-saved again until `autosave`
+    /// is set again.
+    pub fn autosave(&mut self, now: Instant) {
+        if self.autosave_remaining(now) != Some(Duration::ZERO) {
+            return;
+        }
+        let delay = self.autosave_delay().unwrap();
+        let shadow = self.options.autosave_to == "shadow";
+        for index in 0..self.buffers.len() {
+            let buffer = &mut self.buffers[index];
+            if buffer.autosave.remaining(now, delay) != Some(Duration::ZERO) {
+                continue;
+            }
+            // Whatever happens, the changes so far don't need looking at again
+            buffer.autosave.saved();
+            let path = match &buffer.file_path {
+                Some(path) if buffer.is_dirty() && !buffer.tree.is_read_only() => path.clone(),
+                _ => continue,
+            };
+            if !shadow && buffer.disk_version().is_some() {
+                continue;
+            }
+            let target = match shadow {
+                true => autosave::shadow_path(&path),
+                false => path,
+            };
+            // The buffer is auto-saved again once it's been changed, so the number that's being
+            // typed gets the chance to be finished
+            if let Err(e) = buffer.check_writable() {
+                log::info!("Not auto-saving {}: {}", buffer.name(), e);
+                continue;
+            }
+            let root = buffer.tree.root();
+            match self.write_tree(root, &target) {
+                Ok(hash) => {
+                    log::info!("Auto-saved to {}", target.display());
+                    if !shadow {
+                        self.buffers[index].mark_saved_as(session::hash_to_hex(hash));
+                    }
+                }
+                Err(e) => {
+                    let buffer = &mut self.buffers[index];
+                    buffer.autosave.fail();
+                    let message = format!(
+                        "Stopped auto-saving {}, since {} couldn't be written: {}",
+                        buffer.name(),
+                        target.display(),
+                        e
+                    );
+                    log::warn!("{}", message);
+                    self.status_message = Some(message);
+                }
+            }
+        }
+    }
+
+    /// Writes the current tree to a new file, and makes that the file that future saves will be
+    /// written to.  If the save fails, then future saves still go to the old path.
+    pub fn save_as(&mut self, path: PathBuf) -> EditResult {
+        self.write_as(path, false)
+    }
+
+    /// Writes the current tree to a new file like [`save_as`](Editor::save_as), but writes
+    /// read-only buffers as well if `force` is `true`
+    fn write_as(&mut self, path: PathBuf, force: bool) -> EditResult {
+        let old_path = self.buffer_mut().file_path.replace(path);
+        let result = match force {
+            true => self.write_to_file(),
+            false => self.save(),
+        };
+        if result.is_err() {
+            self.buffer_mut().file_path = old_path;
+        }
+        result
+    }
+
+    /// Writes the current tree to `path` in an export `format`.  This doesn't change where the
+    /// buffer is saved to, or whether it has unsaved changes.
+    fn export(&self, format: ExportFormat, path: PathBuf) -> EditResult {
+        self.buffer().check_writable()?;
+        let root = self.tree().root();
+        let mut contents = Vec::new();
+        match format {
+            ExportFormat::Dot => root.write_dot(&mut contents, self.options.debug_highlighting),
+            ExportFormat::Html => {
+                // Like saving, the text is formatted as the options say
+                let mut format_style = self.format_style.clone();
+                format_style.set_kind(self.options.format_kind());
+                html::write_html(root, &format_style, &self.color_scheme, &mut contents)
+            }
+            ExportFormat::Patch => {
+                contents = self.patch()?.into_bytes();
+                Ok(())
+            }
+        }
+        .and_then(|()| file::write_atomically(&path, &String::from_utf8_lossy(&contents)))
+        .map_err(|e| EditErr::ExportFailed {
+            path: path.to_string_lossy().into_owned(),
+            message: e.to_string(),
+        })?;
+        Ok(EditSuccess::Export {
+            format: format.name().to_string(),
+            path: path.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// Returns the JSON Patch which turns the current buffer's tree as it was loaded into the
+    /// current tree, with one operation on each line
+    pub fn patch(&self) -> std::result::Result<String, EditErr> {
+        if self.language != Language::JSON(Dialect::JSON) {
+            return Err(EditErr::UnsupportedExport {
+                format: ExportFormat::Patch.name().to_string(),
+                language: self.language.name().to_string(),
+            });
+        }
+        self.buffer().check_writable()?;
+        let mut format_style = self.format_style.clone();
+        format_style.set_kind(FormatKind::Compact);
+        let buffer = self.buffer();
+        let patch = json_patch::diff_patch(buffer.loaded_root(), buffer.tree.root(), &format_style);
+        Ok(json_patch::write_patch(&patch))
+    }
+
+    /// Makes several edits to the buffer being edited as one [transaction](DAG::transact): they
+    /// are all undone together if `edits` returns `Ok`, or all thrown away if it returns `Err`.
+    /// The marks, folds and search matches are only updated once the edits have been kept.
+    pub fn transact<T>(
+        &mut self,
+        edits: impl FnOnce(&mut Transaction<'_, 'arena, Node>) -> std::result::Result<T, EditErr>,
+    ) -> std::result::Result<T, EditErr> {
+        let result = self.tree_mut().transact(edits);
+        if result.is_ok() {
+            self.follow_tree_changes();
+        }
+        result
+    }
+
+    /// Returns the session of the buffer being edited, so that it can be restored the next time
+    /// that its file is opened (see [`session`]).  Returns `None` if there's nothing that could
+    /// be restored: the buffer has no file (or is read-only, e.g. one side of a diff), or its
+    /// language can't be serialized.
+    pub fn save_session(&self) -> Option<SavedSession> {
+        let buffer = self.buffer();
+        if buffer.tree.is_read_only() {
+            return None;
+        }
+        let path = buffer.file_path.as_ref()?;
+        let text = std::fs::read_to_string(path).ok()?;
+        let mut writer = OwnedWriter::new();
+        let history = buffer.tree.history().save(&mut writer).ok()?;
+        let saved_root = match buffer.saved_root() {
+            Some(root) => Some(writer.add(root).ok()?),
+            None => None,
+        };
+        let loaded_root = writer.add(buffer.loaded_root()).ok()?;
+        let to_vec = |path: &CursorPath| path.iter().copied().collect();
+        Some(SavedSession {
+            language: self.language.name().to_string(),
+            file_path: path.canonicalize().ok()?,
+            file_hash: session::file_hash(&text),
+            cursor_path: to_vec(buffer.tree.cursor_path()),
+            folds: self
+                .window()
+                .folds
+                .paths()
+                .into_iter()
+                .map(to_vec)
+                .collect(),
+            saved_root,
+            loaded_root,
+            history,
+            nodes: writer.finish(),
+        })
+    }
+
+    /// Fills the registers with the registers of an editor for a different language, so that they
+    /// can still be listed after switching language
+    pub fn restore_registers(&mut self, saved: Vec<SavedRegister>) {
+        self.registers.restore(saved);
+    }
+
+    /// Restores a session of the buffer being edited, replacing its tree and undo history and
+    /// the cursor and folds of the focused window
+    pub fn restore_session(
+        &mut self,
+        saved: &SavedSession,
+    ) -> std::result::Result<(), SessionError> {
+        let matches_buffer = self.buffer().file_path.as_ref().is_some_and(|path| {
+            path.canonicalize()
+                .is_ok_and(|path| path == saved.file_path)
+        });
+        if saved.language != self.language.name() || !matches_buffer {
+            return Err(SessionError::WrongBuffer);
+        }
+        let arena = self.tree().arena();
+        let reader = OwnedReader::new(&saved.nodes, arena)?;
+        let history =
+            EditHistory::restore(&saved.history, &reader).ok_or(SessionError::InvalidHistory)?;
+        let root = history.root();
+        let cursor_path = CursorPath::from_vec(saved.cursor_path.clone());
+        if cursor_path.resolve_longest(root).1 != cursor_path.depth() {
+            return Err(SessionError::InvalidHistory);
+        }
+        let saved_root = saved.saved_root.map(|i| reader.get(i)).transpose()?;
+        let loaded_root = reader.get(saved.loaded_root)?;
+        let tree = DAG::from_history(arena, history, cursor_path.clone());
+        self.buffer_mut().restore(tree, saved_root, loaded_root);
+        let mut window = Window::new(self.active(), cursor_path, root);
+        for fold in &saved.folds {
+            let path = CursorPath::from_vec(fold.clone());
+            if path.resolve_longest(root).1 == path.depth() {
+                window.folds.toggle(&path, root);
+            }
+        }
+        *self.window_mut() = window;
+        Ok(())
+    }
+
+    /// Saves the session of the buffer being edited to [`session::session_path`], if it has one
+    /// that could be restored.  The sessions of other files are kept, unless the session file
+    /// can't be read.
+    fn write_session(&self) {
+        let (path, session) = match (session::session_path(), self.save_session()) {
+            (Some(path), Some(session)) => (path, session),
+            _ => return,
+        };
+        let mut sessions = SessionFile::read(&path).unwrap_or_default();
+        sessions.insert(session);
+        match sessions.write(&path) {
+            Ok(()) => log::info!("Saved the session to {}", path.to_string_lossy()),
+            Err(e) => log::warn!("Couldn't save the session: {}", e),
+        }
+    }
+
+    /// Starts writing the changes to every buffer to [`swap`] files, so that they can be
+    /// recovered if Sapling dies before they're saved
+    pub fn enable_swap_files(&mut self) {
+        self.swap_files = true;
+        self.write_swap_files();
+    }
+
+    /// Writes any changes to the buffers to their swap files (if swap files are enabled)
+    fn write_swap_files(&mut self) {
+        if self.swap_files {
+            let now = Instant::now();
+            for buffer in &mut self.buffers {
+                buffer.write_swap(now);
+            }
+        }
+    }
+
+    /// Recovers the changes in the swap file of the buffer being edited, adding them to the undo
+    /// history as one state per change, and returns how many changes were recovered.  The swap
+    /// file carries on being written from the last change that could be read.
+    pub fn recover_swap(&mut self, swap: &SwapFile) -> std::result::Result<usize, SwapError> {
+        let buffer = self.buffer();
+        let matches_buffer =
+            (buffer.file_path.as_ref()).is_some_and(|path| swap::swap_path(path) == swap.path());
+        if !matches_buffer {
+            return Err(SwapError::WrongBuffer);
+        }
+        let base = buffer.saved_root().zip(buffer.saved_hash());
+        let history = swap.replay(base, self.tree().arena())?;
+        let root = history.root();
+        let cursor_path = history.cursor_path().clone();
+        let journal = Journal::resume(swap, root).map_err(|e| SwapError::Read(e.to_string()))?;
+        let (saved_root, loaded_root) = (buffer.saved_root(), buffer.loaded_root());
+        let tree = DAG::from_history(self.tree().arena(), history, cursor_path.clone());
+        self.buffer_mut().restore(tree, saved_root, loaded_root);
+        self.buffer_mut().resume_swap(journal);
+        *self.window_mut() = Window::new(self.active(), cursor_path, root);
+        Ok(swap.change_count())
+    }
+
+    /* ===== MAIN FUNCTIONS ===== */
+
+    /// Update the terminal UI display
+    fn update_display(&mut self) {
+        self.prepare_frame();
+        self.term().draw(self).unwrap();
+        self.term().present().unwrap();
+    }
+
+    /// Gets everything ready to draw a frame of the screen: the tree is formatted to fit the
+    /// screen, and the focused window is scrolled so that the cursor can be seen
+    fn prepare_frame(&mut self) {
+        let (width, height) = self.screen_size();
+        // Nothing but a message is drawn on a screen that's too small, so the tree's format and
+        // scroll are left as they were until the screen is big enough again
+        if !fits_on_screen(width, height) {
+            return;
+        }
+        // The gutter is measured with the format of the last frame, which is only different if
+        // the tree has just gained or lost enough lines to need another digit
+        let gutter = self.gutter_width(self.window());
+        self.update_format_style(width.saturating_sub(gutter));
+        self.scroll_to_cursor();
+        // Make sure that the logger isn't taller than the screen
+        self.keystroke_log.set_max_entries(height.min(10));
+    }
+
+    /// Draws everything on the screen to a canvas which is the size of the screen (see
+    /// [`prepare_frame`](Editor::prepare_frame))
+    fn render(&self, canvas: &mut dyn Canvas) {
+        // Put the screen size into some convenient variables
+        let (width, height) = self.screen_size();
+        canvas.clear().unwrap();
+        if !fits_on_screen(width, height) {
+            render_too_small(canvas, width, height);
+            return;
+        }
+
+        /* RENDER MAIN TEXT VIEW */
+
+        if let Some(view) = &self.log_view {
+            // The full-screen log covers the tree
+            self.keystroke_log.render_full(
+                canvas,
+                height.saturating_sub(1),
+                view.scroll,
+                view.errors_only,
+                &self.color_scheme,
+            );
+        } else {
+            let partition = self.partition();
+            for &(window, rect) in &partition.windows {
+                self.render_window(canvas, window, rect);
+            }
+            self.render_dividers(canvas, &partition);
+
+            /* RENDER LOG SECTION */
+
+            self.keystroke_log
+                .render(canvas, 0, width / 2, &self.color_scheme);
+        }
+        if let Some(help) = &self.help {
+            help.render(canvas, width, height);
+        } else if let Some(hints) = self.hints() {
+            hints.render(canvas, width, height);
+        }
+
+        /* RENDER BOTTOM BAR */
+
+        let status = self.status_text();
+        // Add either a prompt, the status message or the `Press 'Q' to exit.` message
+        let prompt = if let Some(change) = &self.disk_change {
+            Some(change.prompt(&self.buffer().name()))
+        } else if self.log_view.is_some() {
+            Some("e: toggle errors only  j/k: scroll  q: close".to_string())
+        } else if let Some(help) = &self.help {
+            Some(if help.fits(help.rect(width, height).height) {
+                format!("Press any key to close the {}.", help.name())
+            } else {
+                "j/k: scroll  any other key: close".to_string()
+            })
+        } else if let Some(p) = &self.search_prompt {
+            Some(format!("/{}", p.query))
+        } else if let Some(command) = &self.command_prompt {
+            Some(format!(":{}", command.text()))
+        } else if self.insert.is_some() {
+            Some("-- INSERT --".to_string())
+        } else {
+            self.replace_confirm.as_ref().map(|r| {
+                format!(
+                    "Replace with '{}' ({}/{})? [y/n/a/q]",
+                    r.replacement,
+                    r.next + 1,
+                    r.candidates.len()
+                )
+            })
+        };
+        let message = prompt
+            .as_deref()
+            .or(self.status_message.as_deref())
+            .unwrap_or("Press 'Q' to exit.");
+        // Leave space for the keystroke buffer on the right
+        let keystroke_width = Size::from_str(&self.keystroke).last_line_length();
+        let bar_width = width.saturating_sub(keystroke_width + 6);
+        let (status, message_col, message) = status_bar::fit(&status, message, bar_width);
+        canvas.print(height - 1, 0, &status).unwrap();
+        canvas.print(height - 1, message_col, &message).unwrap();
+        // Show where the cursor is in the command being typed (unless the command has been
+        // shortened to fit)
+        if let Some(command) = &self.command_prompt {
+            if prompt.as_deref() == Some(message.as_str()) {
+                let c = command.char_at_cursor().unwrap_or(' ');
+                let col = message_col + 1 + command.cursor_col();
+                canvas
+                    .print_with_attr(
+                        height - 1,
+                        col,
+                        &c.to_string(),
+                        Attr::default().effect(Effect::REVERSE),
+                    )
+                    .unwrap();
+            }
+        }
+        // Draw the current keystroke buffer
+        canvas
+            .print(
+                height - 1,
+                width.saturating_sub(5 + keystroke_width),
+                &self.keystroke,
+            )
+            .unwrap();
+    }
+
+    /// Consumes a [`char`] and adds it to the keystroke buffer.  If the keystroke buffer contains a
+    /// valid keystroke, then execute that keystroke.
+    ///
+    /// This returns a tuple of:
+    /// 1. A [`bool`] value that determines whether or not Sapling should quit
+    /// 2. The [`EditResult`] of the edit, or `None` if the keystroke is incomplete
+    fn consume_keystroke(&mut self, c: char) -> (bool, Option<EditResult>) {
+        // Whilst recording a macro, the record key on its own stops the recording (like in Vim)
+        if self.keystroke.is_empty()
+            && self.macros.recording_register().is_some()
+            && self.keymap.get(&c.to_string()) == Some(&KeyStroke::Record)
+        {
+            self.keystroke.push(c);
+            let (should_quit, result) = self.execute_action(Action::StopRecording);
+            return (should_quit, Some(result));
+        }
+        // If we're not in the middle of a keystroke and the cursor has textual contents that `c`
+        // can be appended to (e.g. a digit whilst a number is selected), then `c` edits the
+        // cursor rather than starting a new keystroke.  Other chars (like `.` or `e`) only do so
+        // straight after typing into a node, so that `.` still repeats the last edit.
+        let typing_path = self.typing_path();
+        let target = match &typing_path {
+            Some(path) => path.cursor(self.tree().root()),
+            None => self.tree().cursor(),
+        };
+        if self.keystroke.is_empty()
+            && self.visual.is_none()
+            && (c.is_ascii_digit() || typing_path.is_some())
+            && target.push_char(c).is_some()
+        {
+            let (should_quit, result) = self.type_char(c);
+            return (should_quit, Some(result));
+        }
+        // On a node with text (or a field, whose key has text), the key that inserts before the
+        // cursor types into the text instead
+        if self.keystroke.is_empty()
+            && self.visual.is_none()
+            && self.keymap.get(&c.to_string()) == Some(&KeyStroke::InsertBefore)
+            && self.text_path().is_some()
+        {
+            self.keystroke.push(c);
+            let (should_quit, result) = self.execute_action(Action::StartInsert);
+            return (should_quit, Some(result));
+        }
+        // Add the new keypress to the keystroke
+        self.keystroke.push(c);
+        self.parse_keystroke_buffer()
+    }
+
+    /// Parses the keystroke buffer, and executes the keystroke if it's complete
+    fn parse_keystroke_buffer(&mut self) -> (bool, Option<EditResult>) {
+        match parse_keystroke(&self.keymap, &self.keystroke) {
+            Some(action) => {
+                let (should_quit, result) = self.execute_action(action);
+                (should_quit, Some(result))
+            }
+            None => (false, None),
+        }
+    }
+
+    /// Executes an [`Action`] that was generated by the contents of the keystroke buffer (using
+    /// the count at the start of the buffer, if there is one), then logs the keystroke and clears
+    /// the keystroke buffer.
+    fn execute_action(&mut self, action: Action) -> (bool, EditResult) {
+        let keystroke = std::mem::take(&mut self.keystroke);
+        let (should_quit, result) = self.execute_keystroke_action(&action, &keystroke);
+        let command = keystroke_binding(&self.keymap, &keystroke).map(|(stroke, _)| stroke.name());
+        self.keystroke_log
+            .push(keystroke, command, &action, result.is_ok());
+        (should_quit, result)
+    }
+
+    /// Returns the path of the node that chars are still being typed into (see
+    /// [`typed_into`](Self::typed_into)), if there is one
+    fn typing_path(&self) -> Option<CursorPath> {
+        let (path, node) = self.typed_into.as_ref()?;
+        let found = path.resolve(self.tree().root())?;
+        std::ptr::eq(found, *node).then(|| path.clone())
+    }
+
+    /// Returns the path of the node that `action` typed into or made out of a digit or `-` (if it
+    /// did either), given the [`typing_path`](Self::typing_path) from before it was performed
+    fn typed_path(&self, action: &Action, typing_path: Option<CursorPath>) -> Option<CursorPath> {
+        let mut path = self.tree().cursor_path().clone();
+        match *action {
+            Action::PushChar(_) | Action::PopChar => return Some(typing_path.unwrap_or(path)),
+            Action::Replace(c)
+            | Action::InsertChild(c)
+            | Action::InsertBefore(c)
+            | Action::InsertAfter(c)
+                if !c.is_ascii_digit() && c != '-' =>
+            {
+                return None
+            }
+            // Inserting before the cursor leaves the cursor's path pointing at the new node
+            Action::Replace(_) | Action::InsertBefore(_) => {}
+            Action::InsertChild(_) => path.push(self.tree().cursor().children().len() - 1),
+            Action::InsertAfter(_) => *path.last_mut()? += 1,
+            _ => return None,
+        }
+        Some(path)
+    }
+
+    /// Types `c` into the cursor (e.g. a digit into a number), as a keystroke of its own.  `c`
+    /// isn't a count even if it's a digit, so it's only typed once.
+    fn type_char(&mut self, c: char) -> (bool, EditResult) {
+        let action = Action::PushChar(c);
+        let (should_quit, result) = self.execute_keystroke_action(&action, "");
+        self.keystroke_log
+            .push(c.to_string(), None, &action, result.is_ok());
+        (should_quit, result)
+    }
+
+    /// Executes an [`Action`] which was typed as `keystroke`, without logging it
+    fn execute_keystroke_action(&mut self, action: &Action, keystroke: &str) -> (bool, EditResult) {
+        let (count, register, _) = split_prefix(keystroke);
+        let register = register.unwrap_or(registers::UNNAMED);
+        let (action, count) = match action {
+            // Repeat the last edit, with the new count replacing the old one if one was given
+            Action::Repeat => match self.last_edit.clone() {
+                Some((last_action, last_count)) => (last_action, count.unwrap_or(last_count)),
+                None => return (false, Err(EditErr::NothingToRepeat)),
+            },
+            _ => (action.clone(), count.unwrap_or(1)),
+        };
+        // The tree itself turns away every edit if it's read-only, but insert mode shouldn't even
+        // start (since none of the text could be kept)
+        if action == Action::StartInsert && self.tree().is_read_only() {
+            return (false, Err(EditErr::ReadOnly));
+        }
+        // Editing a folded node expands it, so that the user can see what they're changing
+        if action.is_edit() && !self.tree().is_read_only() {
+            let (buffer, window) = self.buffer_and_window_mut();
+            window.folds.unfold(buffer.tree.cursor_path());
+        }
+        let root_before = self.tree().root();
+        let visual = self.visual.is_some();
+        let typing_path = self.typing_path();
+        let (should_quit, mut result) = if visual {
+            self.perform_visual_action(action.clone(), count, register)
+        } else {
+            self.perform_action(action.clone(), count, register)
+        };
+        // Show what moving through the undo history changed
+        let is_history = matches!(
+            action,
+            Action::Undo | Action::Redo | Action::Older | Action::Newer
+        );
+        if result.is_ok() && is_history {
+            let root = self.tree().root();
+            self.change_highlight = Some(ChangeHighlight::new(root_before, root, Instant::now()));
+        }
+        // Making a number out of a digit or `-` (e.g. with `r0` or `a-`) counts as typing that
+        // char into it
+        self.typed_into = match result.is_ok() && !visual {
+            true => self.typed_path(&action, typing_path).map(|path| {
+                let node = path.cursor(self.tree().root());
+                (path, node)
+            }),
+            false => None,
+        };
+        // Remember successful edits so that they can be repeated (apart from edits of a
+        // selection, since the same siblings won't be selected again)
+        if result.is_ok() && action.is_edit() && !visual {
+            self.last_edit = Some((action, count));
+            // Catch any bugs in the `Ast`'s tokens before they garble the screen
+            if cfg!(debug_assertions) {
+                if let Err(e) = validate_token_stream(self.tree().root(), &self.format_style) {
+                    result = Err(EditErr::InvalidTokens(e));
+                }
+            }
+        }
+        self.follow_tree_changes();
+        (should_quit, result)
+    }
+
+    /// Pastes every node of `register` `count` times, on `side` of the cursor.  This is one
+    /// [transaction](DAG::transact), so either every node is pasted or none of them are.
+    fn paste(&mut self, side: Side, count: usize, register: char) -> EditResult {
+        let nodes = self.register_nodes(register)?;
+        let first = *nodes.first().ok_or(EditErr::EmptyRegister)?;
+        self.tree_mut().transact(|tx| {
+            // The cursor stays on the first of the nodes next to it, so pasting the nodes in
+            // reverse keeps them in the order that they were yanked
+            for _ in 0..count {
+                for node in nodes.iter().rev() {
+                    tx.paste(node, side)?;
+                }
+            }
+            Ok(EditSuccess::Paste {
+                side,
+                name: first.display_name(),
+                count: nodes.len() * count,
+            })
+        })
+    }
+
+    /// Puts `nodes` into `register`.  Yanking into the clipboard also copies the text of the nodes
+    /// (one after another, as they'd be saved) to the system clipboard.
+    fn yank_into(
+        &mut self,
+        register: char,
+        nodes: Vec<&'arena Node>,
+    ) -> std::result::Result<(), EditErr> {
+        if register == registers::CLIPBOARD {
+            let mut format_style = self.format_style.clone();
+            format_style.set_kind(self.options.format_kind());
+            let text = nodes
+                .iter()
+                .map(|node| node.to_text(&format_style))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let terminal = self.term.is_some();
+            self.clipboard
+                .copy(self.clipboard_backend(), &text, terminal)
+                .map_err(|e| EditErr::Clipboard(e.to_string()))?;
+        }
+        self.registers.yank(register, self.language, nodes);
+        Ok(())
+    }
+
+    /// Returns the nodes that pasting from `register` inserts.  The text on the clipboard is
+    /// parsed as the language being edited.
+    fn register_nodes(&self, register: char) -> std::result::Result<Vec<&'arena Node>, EditErr> {
+        if register != registers::CLIPBOARD {
+            return Ok(self.registers.nodes(register, self.language)?.to_vec());
+        }
+        let text = self
+            .clipboard
+            .paste(self.clipboard_backend())
+            .map_err(|e| EditErr::Clipboard(e.to_string()))?;
+        let node = self
+            .loader
+            .parse_node(&text)
+            .map_err(|message| EditErr::ClipboardParse {
+                language: self.language.name().to_string(),
+                message,
+            })?;
+        Ok(vec![node])
+    }
+
+    /// Returns how the clipboard is reached, as chosen by the `clipboard` option
+    fn clipboard_backend(&self) -> Backend {
+        // The option has already been checked, so it's always a backend
+        Backend::named(&self.options.clipboard).unwrap_or(Backend::System)
+    }
+
+    /// Performs an [`Action`] whilst siblings are selected in visual mode.  Deleting, yanking and
+    /// pasting act on the whole selection (and then stop selecting), and the actions that only
+    /// move the cursor or change the view work as usual, as long as the cursor stays among the
+    /// selected siblings.  Anything else is an error.
+    fn perform_visual_action(
+        &mut self,
+        action: Action,
+        count: usize,
+        register: char,
+    ) -> (bool, EditResult) {
+        let selection = self.visual.clone().expect("siblings are being selected");
+        match action {
+            Action::StartVisual => {
+                self.visual = None;
+                (false, Ok(EditSuccess::StopVisual))
+            }
+            Action::Delete | Action::Yank | Action::PasteBefore | Action::PasteAfter => {
+                let result = self.act_on_selection(&selection, action, count, register);
+                if result.is_ok() {
+                    self.visual = None;
+                }
+                (false, result)
+            }
+            Action::Quit
+            | Action::Save
+            | Action::SaveAndQuit
+            | Action::MoveCursor(_)
+            | Action::SearchMatch(_)
+            | Action::JumpToMark(_)
+            | Action::ScrollHalfPage(_)
+            | Action::CentreCursor
+            | Action::ToggleFold
+            | Action::ToggleDebugHighlighting
+            | Action::NextTheme
+            | Action::Suspend
+            | Action::ToggleLogView
+            | Action::ShowHelp => {
+                let cursor_path = self.tree().cursor_path().clone();
+                let (should_quit, result) = self.perform_action(action, count, register);
+                // The cursor can't leave the anchor's siblings
+                if let Err(e) = selection.range(self.tree().cursor_path()) {
+                    self.tree_mut().set_cursor_path(cursor_path);
+                    return (should_quit, Err(e));
+                }
+                (should_quit, result)
+            }
+            _ => (
+                false,
+                Err(EditErr::InvalidInVisualMode {
+                    action: action.description(),
+                }),
+            ),
+        }
+    }
+
+    /// Deletes, yanks or pastes next to the selected siblings
+    fn act_on_selection(
+        &mut self,
+        selection: &Selection,
+        action: Action,
+        count: usize,
+        register: char,
+    ) -> EditResult {
+        let cursor_path = self.tree().cursor_path().clone();
+        let (parent, range) = selection.range(&cursor_path)?;
+        let children = parent.cursor(self.tree().root()).children();
+        let nodes = children[range.clone()].to_vec();
+        let mut first = parent.clone();
+        first.push(range.start);
+        match action {
+            // Deleting cuts the selected siblings into the register
+            Action::Delete => {
+                let result = self.tree_mut().transact(|tx| {
+                    tx.goto(first)?;
+                    tx.delete(range.len())
+                });
+                result.and_then(|success| self.yank_into(register, nodes).map(|()| success))
+            }
+            Action::Yank => {
+                self.tree_mut().set_cursor_path(first);
+                let name = nodes[0].display_name();
+                self.yank_into(register, nodes)?;
+                Ok(EditSuccess::Yank {
+                    name,
+                    count: range.len(),
+                })
+            }
+            // Pasting goes before the first selected sibling or after the last one
+            _ => {
+                let side = match action {
+                    Action::PasteBefore => Side::Prev,
+                    _ => Side::Next,
+                };
+                let mut edge = parent;
+                edge.push(match side {
+                    Side::Prev => range.start,
+                    Side::Next => range.end - 1,
+                });
+                self.tree_mut().set_cursor_path(edge);
+                let result = self.paste(side, count, register);
+                if result.is_err() {
+                    self.tree_mut().set_cursor_path(cursor_path);
+                }
+                result
+            }
+        }
+    }
+
+    /// Performs an [`Action`] a given number of times.  Yanking, cutting and pasting use the
+    /// register called `register`.
+    fn perform_action(
+        &mut self,
+        action: Action,
+        count: usize,
+        register: char,
+    ) -> (bool, EditResult) {
+        match action {
+            // Quitting would lose the changes to every other buffer, so is handled by the editor.
+            // Like `:q`, this refuses to quit if any buffer has unsaved changes.
+            Action::Quit => match self.check_unsaved() {
+                Ok(()) => (true, Ok(EditSuccess::Quit)),
+                Err(e) => (false, Err(e)),
+            },
+            // Saving needs to know about the file, so is handled by the editor
+            Action::Save => (false, self.save()),
+            // Like `:wq`, except that the file is only written if the buffer has unsaved changes
+            Action::SaveAndQuit => {
+                let result = if self.buffer().is_dirty() {
+                    self.save()
+                } else {
+                    Ok(EditSuccess::Quit)
+                };
+                if let Some(change) = &mut self.disk_change {
+                    change.saving = Some(true);
+                    return (false, result);
+                }
+                let (should_quit, result) = self.finish_write(result, true);
+                (should_quit, result.unwrap_or(Ok(EditSuccess::Quit)))
+            }
+            // The registers belong to the editor, so yanking and pasting are handled here
+            Action::Yank => {
+                let cursor = self.tree().cursor();
+                if let Err(e) = self.yank_into(register, vec![cursor]) {
+                    return (false, Err(e));
+                }
+                (
+                    false,
+                    Ok(EditSuccess::Yank {
+                        name: cursor.display_name(),
+                        count: 1,
+                    }),
+                )
+            }
+            Action::PasteBefore => (false, self.paste(Side::Prev, count, register)),
+            Action::PasteAfter => (false, self.paste(Side::Next, count, register)),
+            // Straight after making a number next to the cursor, chars are typed into that number
+            Action::PushChar(_) | Action::PopChar => match self.typing_path() {
+                Some(path) => {
+                    let c = match action {
+                        Action::PushChar(c) => Some(c),
+                        _ => None,
+                    };
+                    (false, self.tree_mut().type_at(path, c))
+                }
+                None => self.tree_mut().execute_action_with_count(action, count),
+            },
+            // Deleting a node cuts it into the register, so that it can be pasted elsewhere.  If
+            // many nodes are deleted at once, then the first one is kept.
+            Action::Delete => {
+                let cursor = self.tree().cursor();
+                let (should_quit, result) =
+                    self.tree_mut().execute_action_with_count(action, count);
+                let result = result
+                    .and_then(|success| self.yank_into(register, vec![cursor]).map(|()| success));
+                (should_quit, result)
+            }
+            Action::StartVisual => match Selection::new(self.tree().cursor_path().clone()) {
+                Ok(selection) => {
+                    self.visual = Some(selection);
+                    (false, Ok(EditSuccess::StartVisual))
+                }
+                Err(e) => (false, Err(e)),
+            },
+            // Macros are made of keys, which only the editor knows about
+            Action::StartRecording(register) => {
+                self.macros.start_recording(register);
+                (false, Ok(EditSuccess::StartRecording(register)))
+            }
+            Action::StopRecording => {
+                // Don't include the key that stopped the recording in the macro
+                self.macros.unrecord_last();
+                match self.macros.stop_recording() {
+                    Some(register) => (false, Ok(EditSuccess::StopRecording(register))),
+                    None => (false, Err(EditErr::Invalid("q".to_string()))),
+                }
+            }
+            Action::Replay(register) => self.replay_macro(register, count),
+            // Searching needs a prompt, which only the editor can show
+            Action::StartSearch => {
+                self.search_prompt = Some(SearchPrompt {
+                    query: String::new(),
+                    origin: self.tree().cursor_path().clone(),
+                });
+                (false, Ok(EditSuccess::StartSearch))
+            }
+            Action::StartCommand => {
+                self.command_prompt = Some(LineEditor::new());
+                (false, Ok(EditSuccess::StartCommand))
+            }
+            Action::StartInsert => (false, self.start_insert()),
+            Action::SetMark(c) => {
+                let path = self.tree().cursor_path().clone();
+                let result = Ok(EditSuccess::SetMark {
+                    c,
+                    path: path.to_string(),
+                });
+                let buffer = self.buffer_mut();
+                buffer.marks.set(c, Mark::new(path, buffer.tree.root()));
+                (false, result)
+            }
+            Action::JumpToMark(c) => (false, self.jump_to_mark(c)),
+            // Folding only changes how the tree is rendered
+            Action::ToggleFold => {
+                // Unfolding a node that hasn't been parsed yet parses it
+                if let Some(result) = self.buffer_mut().parse_cursor() {
+                    let name = self.tree().cursor().display_name();
+                    let result = result.map(|()| EditSuccess::Fold {
+                        name,
+                        folded: false,
+                    });
+                    return (false, result);
+                }
+                let cursor = self.tree().cursor();
+                if cursor.children().is_empty() {
+                    return (
+                        false,
+                        Err(EditErr::CannotFold {
+                            kind: cursor.node_kind(),
+                        }),
+                    );
+                }
+                let (buffer, window) = self.buffer_and_window_mut();
+                let folded = window
+                    .folds
+                    .toggle(buffer.tree.cursor_path(), buffer.tree.root());
+                let name = cursor.display_name();
+                (false, Ok(EditSuccess::Fold { name, folded }))
+            }
+            Action::ScrollHalfPage(side) => (false, self.scroll_half_page(side)),
+            Action::SplitWindow(split) => (false, self.split_window(split)),
+            Action::CycleWindow => {
+                let order = self.split_tree.windows();
+                let next = order[(self.window_position() + count) % order.len()];
+                self.focus_window(next);
+                (false, Ok(self.window_success()))
+            }
+            Action::CloseWindow => (false, self.close_window()),
+            Action::ToggleLogView => {
+                self.log_view = match self.log_view {
+                    Some(_) => None,
+                    None => Some(LogView {
+                        errors_only: false,
+                        scroll: 0,
+                    }),
+                };
+                (false, Ok(EditSuccess::LogView(self.log_view.is_some())))
+            }
+            Action::ShowHelp => {
+                let (cursor, parent) = self.tree().cursor_and_parent();
+                self.help = Some(Help::new(cursor, parent, &self.keymap));
+                (false, Ok(EditSuccess::ShowHelp))
+            }
+            Action::CentreCursor => {
+                self.window_mut().scroll_offset =
+                    viewport::centre(self.cursor_position().0, self.focused_rect().height);
+                (
+                    false,
+                    Ok(EditSuccess::Scroll {
+                        line: self.window().scroll_offset,
+                    }),
+                )
+            }
+            Action::SearchMatch(side) => {
+                let mut result = self.move_to_search_match(side);
+                for _ in 1..count {
+                    if result.is_err() {
+                        break;
+                    }
+                    result = self.move_to_search_match(side);
+                }
+                (false, result)
+            }
+            Action::MoveToChange(side) => {
+                let mut result = self.move_to_change(side);
+                for _ in 1..count {
+                    if result.is_err() {
+                        break;
+                    }
+                    result = self.move_to_change(side);
+                }
+                (false, result)
+            }
+            Action::MoveToError(side) => {
+                let mut result = self.move_to_error(side);
+                for _ in 1..count {
+                    if result.is_err() {
+                        break;
+                    }
+                    result = self.move_to_error(side);
+                }
+                (false, result)
+            }
+            Action::ToggleDebugHighlighting => {
+                self.options.debug_highlighting = !self.options.debug_highlighting;
+                (
+                    false,
+                    Ok(EditSuccess::DebugHighlighting(
+                        self.options.debug_highlighting,
+                    )),
+                )
+            }
+            Action::NextTheme => (false, self.next_theme()),
+            Action::Suspend => (false, self.suspend()),
+            _ => self.tree_mut().execute_action_with_count(action, count),
+        }
+    }
+
+    /// Shows the user the result of an action (if it needs showing), and logs its message.
+    fn report_result(&mut self, result: EditResult) {
+        self.status_message = match &result {
+            Err(e) => Some(e.to_string()),
+            Ok(EditSuccess::Save { path }) => Some(format!("Saved to {}", path)),
+            Ok(EditSuccess::Reload { path }) => Some(format!("Reloaded {} from disk", path)),
+            Ok(EditSuccess::KeepOwnVersion { name }) => Some(format!(
+                "Keeping your version of {} (the next save overwrites the file)",
+                name
+            )),
+            Ok(EditSuccess::OpenDiskVersion { name }) => {
+                Some(format!("Opened the version of {} on disk", name))
+            }
+            Ok(EditSuccess::Export { format, path }) => {
+                Some(format!("Exported {} to {}", format, path))
+            }
+            Ok(EditSuccess::DebugHighlighting(true)) => {
+                Some("Debug highlighting on: colours now show node identity".to_string())
+            }
+            Ok(EditSuccess::DebugHighlighting(false)) => Some("Debug highlighting off".to_string()),
+            Ok(EditSuccess::Search {
+                query,
+                matches,
+                unparsed,
+            }) => Some(format!(
+                "{} match(es) for '{}'{}",
+                matches,
+                query,
+                search::unparsed_note(*unparsed)
+            )),
+            Ok(EditSuccess::SearchMatch { index, matches }) => {
+                Some(format!("Match {} of {}", index + 1, matches))
+            }
+            Ok(EditSuccess::MoveToChange { index, count }) => {
+                Some(format!("Change {} of {}", index + 1, count))
+            }
+            Ok(EditSuccess::MoveToError {
+                index,
+                count,
+                message,
+            }) => Some(format!("Error {} of {}: {}", index + 1, count, message)),
+            Ok(EditSuccess::JumpToMark {
+                c,
+                path,
+                exact: false,
+            }) => Some(format!(
+                "Mark '{}' no longer exists, so jumped to its closest ancestor at {}",
+                c, path
+            )),
+            Ok(EditSuccess::ListErrors { errors }) if errors.is_empty() => {
+                Some("No errors.".to_string())
+            }
+            // The errors themselves are listed in a box
+            Ok(EditSuccess::ListErrors { errors }) => Some(format!("{} error(s)", errors.len())),
+            Ok(EditSuccess::ListRegisters { registers }) if registers.is_empty() => {
+                Some("Nothing has been yanked or cut yet.".to_string())
+            }
+            Ok(EditSuccess::ListRegisters { registers }) => Some(
+                registers
+                    .iter()
+                    .map(|(c, text)| format!("\"{} {}", c, text))
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            ),
+            Ok(EditSuccess::Pipe { command, name }) => Some(format!(
+                "Replaced the cursor with {} from '{}'",
+                name, command
+            )),
+            Ok(EditSuccess::Shell {
+                command,
+                status: Some(status),
+            }) => Some(format!("':!{}' exited with status {}", command, status)),
+            Ok(EditSuccess::Shell {
+                command,
+                status: None,
+            }) => Some(format!("':!{}' was killed by a signal", command)),
+            Ok(EditSuccess::ListMarks { marks }) if marks.is_empty() => {
+                Some("No marks have been set.".to_string())
+            }
+            Ok(EditSuccess::ListMarks { marks }) => Some(
+                marks
+                    .iter()
+                    .map(|(c, path, name)| format!("'{} {} {}", c, path, name))
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            ),
+            Ok(EditSuccess::ShowOption { option, value }) => Some(format!("{}={}", option, value)),
+            Ok(EditSuccess::SwitchBuffer { index, count, name }) => {
+                Some(format!("Buffer {}/{}: {}", index + 1, count, name))
+            }
+            // Like Vim's `:ls`, the buffer being edited is marked with `%` and buffers with
+            // unsaved changes are marked with `[+]`
+            Ok(EditSuccess::ListBuffers { buffers }) => Some(
+                buffers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, dirty, active))| {
+                        let active = if *active { "%" } else { "" };
+                        let dirty = if *dirty { " [+]" } else { "" };
+                        format!("{}{} {}{}", active, i + 1, name, dirty)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            ),
+            Ok(EditSuccess::Messages { count }) => Some(format!("{} message(s)", count)),
+            // The theme being used is marked with `%`, like the current buffer in `:ls`
+            Ok(EditSuccess::ListThemes { themes, current }) => Some(
+                themes
+                    .iter()
+                    .map(|name| {
+                        let mark = if name == current { "%" } else { "" };
+                        format!("{}{}", mark, name)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            ),
+            Ok(EditSuccess::Stats {
+                nodes,
+                bytes,
+                live_nodes,
+            }) => Some(format!(
+                "{} nodes allocated ({}), {} in the current tree",
+                nodes,
+                format_bytes(*bytes),
+                live_nodes
+            )),
+            Ok(EditSuccess::Map { keys, keystroke }) => {
+                Some(format!("'{}' is mapped to {}", keys, keystroke))
+            }
+            Ok(EditSuccess::Unmap { keys, keystroke }) => {
+                Some(format!("'{}' is no longer mapped to {}", keys, keystroke))
+            }
+            Ok(EditSuccess::TreeStats { stats, lines }) => {
+                let (path, width) = &stats.widest;
+                let mut parts = vec![format!(
+                    "{} nodes, {} lines, {} levels deep, widest: {} ({} children)",
+                    stats.nodes, lines, stats.depth, path, width
+                )];
+                for (kind, count) in stats.kinds_by_count() {
+                    parts.push(format!("{}: {}", kind, count));
+                }
+                Some(parts.join(" | "))
+            }
+            Ok(EditSuccess::ListChanges { changes }) if changes.is_empty() => {
+                Some("No changes since the last save.".to_string())
+            }
+            Ok(EditSuccess::ListChanges { changes }) => Some(changes.join(" | ")),
+            Ok(EditSuccess::ReplaceText {
+                count, unparsed, ..
+            }) => Some(format!(
+                "Replaced text in {} node(s){}",
+                count,
+                search::unparsed_note(*unparsed)
+            )),
+            Ok(_) => None,
+        };
+        result.log_message();
+    }
+
+    /// Responds to a single key pressed by the user (or replayed from a macro).
+    ///
+    /// This returns a tuple of:
+    /// 1. A [`bool`] value that determines whether or not Sapling should quit
+    /// 2. The [`EditResult`] of the edit, or `None` if the key didn't complete a keystroke
+    fn interpret_key(&mut self, key: Key) -> (bool, Option<EditResult>) {
+        // The log view takes every key until it's closed
+        if self.log_view.is_some() {
+            self.handle_log_key(key);
+            return (false, None);
+        }
+        // Whilst the user is being asked about a file that another program has changed, only the
+        // answers do anything
+        if self.disk_change.is_some() {
+            return self.handle_disk_change_key(key);
+        }
+        // The help box is closed by whichever key is pressed next (unless it scrolls the box)
+        if self.help.is_some() {
+            self.handle_help_key(key);
+            return (false, None);
+        }
+        // Whilst text is being typed, every key goes into the text
+        if self.insert.is_some() {
+            let result = self.handle_insert_key(key);
+            self.follow_tree_changes();
+            return (false, result);
+        }
+        // Whilst a search query or command is being typed (or replacements are being confirmed),
+        // keys are sent to the prompt instead of being keystrokes
+        if self.search_prompt.is_some()
+            || self.command_prompt.is_some()
+            || self.replace_confirm.is_some()
+        {
+            let (should_quit, result) = if self.search_prompt.is_some() {
+                (false, self.handle_search_key(key))
+            } else if self.command_prompt.is_some() {
+                self.handle_command_key(key)
+            } else {
+                (false, self.handle_confirm_key(key))
+            };
+            self.follow_tree_changes();
+            return (should_quit, result);
+        }
+        match key {
+            // Whilst siblings are selected, `d` deletes them (like in Vim) as well as `x`
+            Key::Char('d')
+                if self.visual.is_some() && split_prefix(&self.keystroke).2.is_empty() =>
+            {
+                self.keystroke.push('d');
+                let (should_quit, result) = self.execute_action(Action::Delete);
+                (should_quit, Some(result))
+            }
+            Key::Char(c) => self.consume_keystroke(c),
+            // Escape cancels the current keystroke, stops selecting siblings and dismisses the
+            // search highlighting
+            Key::ESC => {
+                self.keystroke.clear();
+                self.visual = None;
+                self.search = None;
+                (false, None)
+            }
+            // Terminals can send these without any key being pressed
+            Key::Null | Key::CursorPos(..) => (false, None),
+            // Any other key is looked up in the keymap by its name (e.g. `<C-r>`, which redoes
+            // like in Vim)
+            key => {
+                self.keystroke.push_str(&script::key_notation(key));
+                self.parse_keystroke_buffer()
+            }
+        }
+    }
+
+    /// Keeps the search matches, marks, folds and every window showing the buffer being edited
+    /// up to date with any changes to the tree (or to the cursor's position)
+    fn follow_tree_changes(&mut self) {
+        let active = self.active();
+        let buffer = &mut self.buffers[active];
+        let root = buffer.tree.root();
+        let cursor_path = buffer.tree.cursor_path();
+        if let Some(search) = &mut self.search {
+            search.update_root(root, &self.format_style);
+        }
+        buffer.marks.update(root);
+        buffer.validation.update_root(root);
+        for (index, window) in self.windows.iter_mut().enumerate() {
+            if window.buffer != active {
+                continue;
+            }
+            if index == self.focused {
+                window.set_cursor(cursor_path.clone(), root);
+                window.folds.update(root);
+                // The cursor would be invisible inside a folded node, so moving into a fold
+                // opens it
+                window.folds.unfold_ancestors_of(cursor_path);
+            } else {
+                // The other windows' cursors follow their nodes through the edit
+                window.update(root);
+            }
+        }
+    }
+
+    /* ===== SEARCH FUNCTIONS ===== */
+
+    /// Responds to a key pressed whilst the user is typing a search query.  The search is updated
+    /// after every key, so the cursor jumps to the first match as the query is typed.  Returns
+    /// the result of the search once the query is confirmed with `<Enter>`.
+    fn handle_search_key(&mut self, key: Key) -> Option<EditResult> {
+        let prompt = self.search_prompt.as_mut()?;
+        match key {
+            Key::Char(c) => prompt.query.push(c),
+            // Deleting from an empty query cancels the search, like in Vim
+            Key::Backspace if !prompt.query.is_empty() => {
+                prompt.query.pop();
+            }
+            Key::Backspace | Key::ESC => {
+                let origin = prompt.origin.clone();
+                self.search_prompt = None;
+                self.search = None;
+                self.tree_mut().set_cursor_path(origin);
+                return None;
+            }
+            Key::Enter => {
+                let prompt = self.search_prompt.take()?;
+                let result = match &self.search {
+                    Some(search) if !search.matches().is_empty() => Ok(EditSuccess::Search {
+                        query: prompt.query.clone(),
+                        matches: search.matches().len(),
+                        unparsed: search.unparsed(),
+                    }),
+                    _ => Err(EditErr::NoMatches {
+                        query: prompt.query.clone(),
+                        unparsed: self.search.as_ref().map_or(0, Search::unparsed),
+                    }),
+                };
+                if result.is_err() {
+                    self.search = None;
+                }
+                return Some(result);
+            }
+            _ => return None,
+        }
+        // Update the matches and jump to the first one after where the search started
+        let (query, origin) = (prompt.query.clone(), prompt.origin.clone());
+        let root = self.tree().root();
+        match &mut self.search {
+            Some(search) => {
+                search.update_root(root, &self.format_style);
+                search.set_query(&query, &self.format_style);
+            }
+            None => self.search = Some(Search::new(root, &query, &self.format_style)),
+        }
+        let search = self.search.as_ref().unwrap();
+        let new_cursor = match search.next_match(&origin, Side::Next) {
+            Some(index) => search.matches()[index].clone(),
+            None => origin,
+        };
+        self.tree_mut().set_cursor_path(new_cursor);
+        None
+    }
+
+    /// Moves the cursor to the next match of the current search in a given direction
+    fn move_to_search_match(&mut self, side: Side) -> EditResult {
+        let search = self.search.as_ref().ok_or(EditErr::NoSearch)?;
+        let index = search
+            .next_match(self.tree().cursor_path(), side)
+            .ok_or_else(|| EditErr::NoMatches {
+                query: search.query().to_string(),
+                unparsed: search.unparsed(),
+            })?;
+        let matches = search.matches().len();
+        let path = search.matches()[index].clone();
+        self.tree_mut().set_cursor_path(path);
+        Ok(EditSuccess::SearchMatch { index, matches })
+    }
+
+    /// Searches for the nodes that `query` finds (for `:query`), and moves the cursor to the
+    /// first one.  The other matches are then found with `n` and `N`, like the matches of any
+    /// other search.  If `list` is `true` (for `:query!`), every match is also listed in a box.
+    fn run_query(&mut self, query: Query, list: bool) -> EditResult {
+        if !self.language.supports_queries() {
+            return Err(EditErr::UnsupportedQuery {
+                language: self.language.name().to_string(),
+            });
+        }
+        let root = self.tree().root();
+        let search = Search::from_query(root, query, &self.format_style);
+        let (query, matches) = (search.query().to_string(), search.matches().to_vec());
+        let unparsed = search.unparsed();
+        let first = match matches.first() {
+            Some(first) => first.clone(),
+            None => {
+                self.search = None;
+                return Err(EditErr::NoMatches { query, unparsed });
+            }
+        };
+        self.tree_mut().set_cursor_path(first);
+        if list {
+            self.help = Some(Help::query_matches(
+                &query,
+                root,
+                &matches,
+                &self.format_style,
+            ));
+        }
+        self.search = Some(search);
+        Ok(EditSuccess::Search {
+            query,
+            matches: matches.len(),
+            unparsed,
+        })
+    }
+
+    /* ===== INSERT FUNCTIONS ===== */
+
+    /// Returns the path of the node whose text would be typed into by entering insert mode.  This
+    /// is the cursor if it has text, or the key of the cursor if it has a key with text.
+    fn text_path(&self) -> Option<CursorPath> {
+        let cursor = self.tree().cursor();
+        let mut path = self.tree().cursor_path().clone();
+        if cursor.text().is_none() {
+            let index = (0..cursor.children().len()).find(|&i| cursor.is_key_child(i))?;
+            cursor.children()[index].text()?;
+            path.push(index);
+        }
+        Some(path)
+    }
+
+    /// Starts typing into the text of the cursor (or of its key), moving the cursor onto the node
+    /// whose text is being typed
+    fn start_insert(&mut self) -> EditResult {
+        let path = self.text_path().ok_or_else(|| EditErr::NoText {
+            kind: self.tree().cursor().node_kind(),
+        })?;
+        self.tree_mut().set_cursor_path(path);
+        let text = self.tree().cursor().text().unwrap_or_default().to_string();
+        self.insert = Some(TextInsert {
+            text: StringEditor::new(text),
+            preview: self.tree().root(),
+        });
+        Ok(EditSuccess::StartInsert)
+    }
+
+    /// Responds to a key pressed whilst text is being typed.  Chars are typed at the text cursor,
+    /// which is moved with the left and right arrow keys (and `<Home>` and `<End>`).  `<BS>` and
+    /// `<Del>` remove the grapheme before and under the text cursor.  `<ESC>` (or `<Enter>`)
+    /// finishes the text, replacing the cursor's text as a single change, and returns the result
+    /// of doing so.
+    fn handle_insert_key(&mut self, key: Key) -> Option<EditResult> {
+        let insert = self.insert.as_mut()?;
+        let old_len = insert.text.text().len();
+        match key {
+            Key::Char(c) => insert.text.insert(c),
+            Key::Backspace => insert.text.backspace(),
+            Key::Delete => insert.text.delete(),
+            Key::Left => insert.text.move_left(),
+            Key::Right => insert.text.move_right(),
+            Key::Home => insert.text.move_to_start(),
+            Key::End => insert.text.move_to_end(),
+            Key::ESC | Key::Enter => {
+                let insert = self.insert.take()?;
+                return Some(self.tree_mut().set_cursor_text(insert.text.text()));
+            }
+            _ => {}
+        }
+        // Every key which changes the text also changes its length
+        if insert.text.text().len() != old_len {
+            let text = insert.text.text().to_string();
+            if let Some(preview) = self.tree_mut().preview_cursor_text(&text) {
+                self.insert.as_mut()?.preview = preview;
+            }
+        }
+        None
+    }
+
+    /* ===== LOG FUNCTIONS ===== */
+
+    /// Responds to a key pressed whilst the full-screen keystroke log is shown.  `e` toggles
+    /// whether only errors are shown, `j`/`k` scroll, and `q`, `L` or `<ESC>` close the log.
+    fn handle_log_key(&mut self, key: Key) {
+        // The log covers every row except the bottom bar and the log's title
+        let height = self.screen_size().1.saturating_sub(2);
+        let view = match &mut self.log_view {
+            Some(view) => view,
+            None => return,
+        };
+        let entries = self.keystroke_log.filtered(view.errors_only).count();
+        match key {
+            Key::Char('e') => {
+                view.errors_only = !view.errors_only;
+                view.scroll = 0;
+            }
+            Key::Char('j') | Key::Down => {
+                view.scroll = (view.scroll + 1).min(entries.saturating_sub(height));
+            }
+            Key::Char('k') | Key::Up => view.scroll = view.scroll.saturating_sub(1),
+            Key::Char('q') | Key::Char('L') | Key::ESC => self.log_view = None,
+            _ => {}
+        }
+    }
+
+    /// Responds to a key pressed whilst the help box is shown.  If the box is too small for the
+    /// help, `j`/`k` scroll it.  Any other key closes the box.
+    fn handle_help_key(&mut self, key: Key) {
+        let (width, height) = self.screen_size();
+        let help = match &mut self.help {
+            Some(help) => help,
+            None => return,
+        };
+        let box_height = help.rect(width, height).height;
+        match key {
+            Key::Char('j') | Key::Down if !help.fits(box_height) => help.scroll(1, box_height),
+            Key::Char('k') | Key::Up if !help.fits(box_height) => help.scroll(-1, box_height),
+            _ => self.help = None,
+        }
+    }
+
+    /* ===== MARK FUNCTIONS ===== */
+
+    /// Moves the cursor to the mark in a given register.  If the marked position no longer
+    /// exists, the cursor goes to its closest ancestor that does.
+    fn jump_to_mark(&mut self, c: char) -> EditResult {
+        let target = self
+            .buffer()
+            .marks
+            .get(c)
+            .ok_or(EditErr::NoSuchMark(c))?
+            .locate(self.tree().root());
+        let exact = matches!(target, MarkTarget::Exact(_));
+        let path = target.path().clone();
+        let success = EditSuccess::JumpToMark {
+            c,
+            path: path.to_string(),
+            exact,
+        };
+        self.tree_mut().set_cursor_path(path);
+        Ok(success)
+    }
+
+    /* ===== COMMAND FUNCTIONS ===== */
+
+    /// Responds to a key pressed whilst the user is typing a command after `:`.  Returns the
+    /// result of the command once it's confirmed with `<Enter>`, along with whether or not
+    /// Sapling should quit.
+    ///
+    /// The command can be edited with `<BS>`, `Ctrl-u` (which deletes everything before the
+    /// cursor) and the left and right arrow keys, and the up and down arrow keys move through
+    /// the commands that were run before.
+    fn handle_command_key(&mut self, key: Key) -> (bool, Option<EditResult>) {
+        let line = match self.command_prompt.as_mut() {
+            Some(line) => line,
+            None => return (false, None),
+        };
+        match key {
+            Key::Char(c) => line.insert(c),
+            // Deleting from an empty command cancels it, like in Vim
+            Key::Backspace if line.backspace() => {}
+            Key::Backspace | Key::ESC => self.command_prompt = None,
+            Key::Ctrl('u') => line.delete_to_start(),
+            Key::Left => line.move_left(),
+            Key::Right => line.move_right(),
+            Key::Home => line.move_to_start(),
+            Key::End => line.move_to_end(),
+            Key::Up => line.history_prev(&self.command_history),
+            Key::Down => line.history_next(&self.command_history),
+            Key::Enter => {
+                let command = line.text().to_string();
+                self.command_prompt = None;
+                self.command_history.push(&command);
+                return self.run_command(&command);
+            }
+            _ => {}
+        }
+        (false, None)
+    }
+
+    /// Parses and runs a command typed after `:`.  Returns `None` (instead of the result) if the
+    /// command is waiting for the user to confirm each replacement, along with whether or not
+    /// Sapling should quit.
+    fn run_command(&mut self, command: &str) -> (bool, Option<EditResult>) {
+        log::debug!("Running :{}", command);
+        let command = match self.commands.parse(command) {
+            Ok(command) => command,
+            Err(e) => return (false, Some(Err(e))),
+        };
+        let result = match command {
+            Command::Substitute {
+                pattern,
+                replacement,
+                target,
+                confirm,
+            } => {
+                // Don't ask about each replacement if none of them could be made
+                if self.tree().is_read_only() {
+                    return (false, Some(Err(EditErr::ReadOnly)));
+                }
+                let candidates = replace::candidates(self.tree().root(), &pattern, target);
+                if candidates.is_empty() {
+                    let unparsed = search::unparsed_count(self.tree().root());
+                    let error = EditErr::NoMatches {
+                        query: pattern,
+                        unparsed,
+                    };
+                    return (false, Some(Err(error)));
+                }
+                if !confirm {
+                    let result = self
+                        .tree_mut()
+                        .replace_text(&candidates, &pattern, &replacement);
+                    return (false, Some(result));
+                }
+                self.tree_mut().set_cursor_path(candidates[0].clone());
+                self.replace_confirm = Some(ReplaceConfirm {
+                    pattern,
+                    replacement,
+                    candidates,
+                    next: 0,
+                    accepted: Vec::new(),
+                });
+                return (false, None);
+            }
+            Command::Goto(path) => self.tree_mut().goto(path),
+            Command::Marks => {
+                let root = self.tree().root();
+                let marks = self
+                    .buffer()
+                    .marks
+                    .iter()
+                    .map(|(c, mark)| {
+                        let path = mark.locate(root).path().clone();
+                        let name = path.cursor(root).display_name();
+                        (c, path.to_string(), name)
+                    })
+                    .collect();
+                Ok(EditSuccess::ListMarks { marks })
+            }
+            Command::Registers => {
+                let mut format_style = self.format_style.clone();
+                format_style.set_kind(FormatKind::Compact);
+                // Leave room for the name of each register
+                let width = self.screen_size().0.saturating_sub(3);
+                let registers = self
+                    .registers
+                    .iter()
+                    .map(|(c, register)| {
+                        let text = register.text(&format_style);
+                        (c, status_bar::truncate(&text, width))
+                    })
+                    .collect();
+                Ok(EditSuccess::ListRegisters { registers })
+            }
+            Command::Errors => {
+                let root = self.tree().root();
+                let errors: Vec<_> = self
+                    .buffer()
+                    .validation
+                    .problems()
+                    .iter()
+                    .map(|problem| {
+                        // Queries can say where a node is far more readably than its indices
+                        let path = if self.language.supports_queries() {
+                            query::path_string(root, &problem.path)
+                        } else {
+                            problem.path.to_string()
+                        };
+                        (path, problem.error.to_string())
+                    })
+                    .collect();
+                if !errors.is_empty() {
+                    self.help = Some(Help::errors(&errors));
+                }
+                Ok(EditSuccess::ListErrors { errors })
+            }
+            Command::Write { path, quit, force } => {
+                let result = match (path, force) {
+                    (Some(path), _) => self.write_as(path, force),
+                    (None, true) => self.write_to_file(),
+                    (None, false) => self.save(),
+                };
+                // If another program has changed the file, then nothing is written (and Sapling
+                // doesn't quit) until the user has said what to do about it
+                if let Some(change) = &mut self.disk_change {
+                    change.saving = Some(quit);
+                    return (false, None);
+                }
+                return self.finish_write(result, quit);
+            }
+            Command::Quit { force } => {
+                if !force {
+                    if let Err(e) = self.check_unsaved() {
+                        return (false, Some(Err(e)));
+                    }
+                }
+                return (true, None);
+            }
+            Command::Set { option, value } if option == "language" => {
+                return self.set_language(value.as_deref());
+            }
+            Command::Set { option, value } if option == "readonly" => {
+                self.set_read_only(value.as_deref())
+            }
+            Command::Set { option, value } => self.set_option(&option, value.as_deref()),
+            Command::Edit(path) => self.open_buffer(path),
+            Command::Shell(command) => self.run_shell(command),
+            Command::Pipe(command) => self.pipe_cursor(command),
+            Command::Query { query, list } => self.run_query(query, list),
+            Command::SwitchBuffer(side) => {
+                let count = self.buffers.len();
+                let index = match side {
+                    Side::Next => (self.active() + 1) % count,
+                    Side::Prev => (self.active() + count - 1) % count,
+                };
+                self.switch_to_buffer(index)
+            }
+            Command::Export { format, path } => self.export(format, path),
+            Command::Map { keys, keystroke } => self.map_keys(&keys, &keystroke),
+            Command::Unmap(keys) => self.unmap_keys(&keys),
+            Command::ListBuffers => Ok(EditSuccess::ListBuffers {
+                buffers: self
+                    .buffers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, buffer)| (buffer.name(), buffer.is_dirty(), i == self.active()))
+                    .collect(),
+            }),
+            #[cfg(feature = "testing")]
+            Command::Crash => panic!("Simulated crash (from :crash)"),
+            Command::Messages => {
+                let messages = logging::messages();
+                self.help = Some(Help::messages(&messages));
+                Ok(EditSuccess::Messages {
+                    count: messages.len(),
+                })
+            }
+            Command::ListThemes => Ok(EditSuccess::ListThemes {
+                themes: THEME_NAMES.to_vec(),
+                current: self.options.theme.clone(),
+            }),
+            Command::Stats => {
+                let arena = self.tree().arena();
+                Ok(EditSuccess::Stats {
+                    nodes: arena.len(),
+                    bytes: arena.bytes_allocated(),
+                    live_nodes: self.tree().root().node_count(),
+                })
+            }
+            Command::TreeStats => {
+                let root = self.tree().root();
+                Ok(EditSuccess::TreeStats {
+                    stats: TreeStats::of(root),
+                    lines: self.sizes.size(root, &self.format_style).lines() + 1,
+                })
+            }
+            Command::Changes => match self.buffer().saved_root() {
+                Some(saved_root) => Ok(EditSuccess::ListChanges {
+                    changes: diff(saved_root, self.tree().root())
+                        .iter()
+                        .map(|edit| edit.to_string())
+                        .collect(),
+                }),
+                None => Err(EditErr::NoSavedTree),
+            },
+        };
+        (false, Some(result))
+    }
+
+    /* ===== BUFFER FUNCTIONS ===== */
+
+    /// Quits after the current buffer has been written with `:wq` (if `quit` is `true` and the
+    /// write succeeded), unless any other buffer has unsaved changes
+    fn finish_write(&mut self, result: EditResult, quit: bool) -> (bool, Option<EditResult>) {
+        if !quit || result.is_err() {
+            return (false, Some(result));
+        }
+        // Saving this buffer doesn't save any of the others
+        match self.check_unsaved() {
+            Ok(()) => (true, Some(result)),
+            Err(e) => (false, Some(Err(e))),
+        }
+    }
+
+    /// Asks the user what to do if another program has changed the file of the buffer being
+    /// edited, unless they've already been asked about this version of the file.  This happens
+    /// every few seconds whilst Sapling is running in a terminal, but never whilst the user is
+    /// in the middle of doing something else.  The sides of a diff aren't checked, since
+    /// reloading one would leave the diff comparing the wrong trees.
+    pub fn check_disk(&mut self) {
+        let busy = self.insert.is_some()
+            || self.search_prompt.is_some()
+            || self.command_prompt.is_some()
+            || self.replace_confirm.is_some()
+            || self.disk_change.is_some()
+            || self.help.is_some()
+            || self.log_view.is_some()
+            || !self.keystroke.is_empty();
+        let active = self.active();
+        if busy
+            || self
+                .diff
+                .as_ref()
+                .is_some_and(|d| d.buffers().contains(&active))
+        {
+            return;
+        }
+        let buffer = self.buffer_mut();
+        if let Some(version) = buffer.disk_version().filter(|v| !buffer.is_ignored(v)) {
+            self.disk_change = Some(DiskChange {
+                version,
+                saving: None,
+            });
+        }
+    }
+
+    /// Responds to a key pressed whilst the user is being asked about a file that another program
+    /// has changed.  `r` reloads the tree from the file, `o` keeps the user's version (and writes
+    /// it, if they were saving), `b` opens the other program's version next to it and `<ESC>`
+    /// (or `q`) stops asking.  Whatever the answer, the user isn't asked about that version of
+    /// the file again unless they save.
+    fn handle_disk_change_key(&mut self, key: Key) -> (bool, Option<EditResult>) {
+        if !matches!(key, Key::Char('r' | 'o' | 'b' | 'q') | Key::ESC) {
+            return (false, None);
+        }
+        let change = match self.disk_change.take() {
+            Some(change) => change,
+            None => return (false, None),
+        };
+        self.buffer_mut().ignore(&change.version);
+        let name = self.buffer().name();
+        match key {
+            Key::Char('r') => (false, Some(self.reload(change.version))),
+            Key::Char('o') => {
+                self.buffer_mut().keep_own_version(&change.version);
+                match change.saving {
+                    Some(quit) => {
+                        let result = self.write_to_file();
+                        self.finish_write(result, quit)
+                    }
+                    None => (false, Some(Ok(EditSuccess::KeepOwnVersion { name }))),
+                }
+            }
+            Key::Char('b') => (false, Some(self.open_disk_version(change.version))),
+            _ => match change.saving {
+                Some(_) => (false, Some(Err(EditErr::ChangedOnDisk { name }))),
+                None => (false, None),
+            },
+        }
+    }
+
+    /// Replaces the tree being edited with the one parsed from `version` of its file.  This is
+    /// a single change, so undoing it gets the user's edits back (unless the buffer is
+    /// read-only, in which case its history starts again).
+    fn reload(&mut self, version: DiskVersion) -> EditResult {
+        let path = self.buffer().name();
+        let root = self
+            .loader
+            .parse_in(&version.text, self.tree().arena())
+            .map_err(|message| EditErr::LoadFailed {
+                path: path.clone(),
+                message,
+            })?;
+        self.buffer_mut().reload(root, version);
+        // The selection was made in the user's version of the tree
+        self.visual = None;
+        self.follow_tree_changes();
+        Ok(EditSuccess::Reload { path })
+    }
+
+    /// Opens `version` of the file of the buffer being edited in a new read-only buffer, shown
+    /// in a window to the right of the user's version so that the two can be compared
+    fn open_disk_version(&mut self, version: DiskVersion) -> EditResult {
+        let name = self.buffer().name();
+        let file_path = self.buffer().file_path.clone();
+        let mut buffer = self
+            .loader
+            .parse(&version.text, file_path)
+            .map_err(|message| EditErr::LoadFailed {
+                path: name.clone(),
+                message,
+            })?;
+        buffer.tree.set_read_only(true);
+        let window = Window::new(
+            self.buffers.len(),
+            buffer.tree.cursor_path().clone(),
+            buffer.tree.root(),
+        );
+        self.push_buffer(buffer);
+        // As with diffs, the split leaves the window on the right to show the new buffer
+        let right = self.focused;
+        self.split_window(Split::Vertical)?;
+        self.windows[right] = window;
+        Ok(EditSuccess::OpenDiskVersion { name })
+    }
+
+    /// Returns an error if any buffer has unsaved changes, listing every such buffer.  The first
+    /// of them becomes the buffer being edited, so that the user can look at (and save) its
+    /// changes.
+    fn check_unsaved(&mut self) -> std::result::Result<(), EditErr> {
+        let dirty: Vec<usize> = (0..self.buffers.len())
+            .filter(|&i| self.buffers[i].is_dirty())
+            .collect();
+        let first = match dirty.first() {
+            Some(&first) => first,
+            None => return Ok(()),
+        };
+        let buffers = dirty.iter().map(|&i| self.buffers[i].name()).collect();
+        self.switch_to_buffer(first)?;
+        Err(EditErr::UnsavedChanges { buffers })
+    }
+
+    /// Opens the file at `path` in a new buffer and starts editing it.  If the file is already
+    /// open, then its buffer is edited instead (so changes to it aren't lost).
+    fn open_buffer(&mut self, path: PathBuf) -> EditResult {
+        let index = self.load_buffer(path)?;
+        self.switch_to_buffer(index)
+    }
+
+    /// Opens the file at `path` in a new buffer without editing it (e.g. for the other files
+    /// given on the command line), and returns the index of its buffer.  If the file is already
+    /// open, then the index of its buffer is returned instead.  Files whose extensions don't
+    /// belong to any language are read in the language of the other buffers.
+    pub fn load_buffer(&mut self, path: PathBuf) -> std::result::Result<usize, EditErr> {
+        let existing = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.file_path.as_ref() == Some(&path));
+        if let Some(index) = existing {
+            return Ok(index);
+        }
+        match self.languages.from_path(&path) {
+            Some(language) if language != self.language => {
+                return Err(EditErr::WrongLanguage {
+                    path: path.to_string_lossy().into_owned(),
+                    language: language.name().to_string(),
+                    current: self.language.name().to_string(),
+                });
+            }
+            _ => {}
+        }
+        let mut buffer = self.loader.load(&path)?;
+        buffer.tree.set_read_only(self.read_only);
+        Ok(self.push_buffer(buffer))
+    }
+
+    /// Adds a buffer to the ones that are open (checking it against the schema, if there is one),
+    /// and returns its index
+    fn push_buffer(&mut self, mut buffer: Buffer<'arena, Node>) -> usize {
+        if self.schema.is_some() {
+            buffer.validation.set_schema(self.schema.clone());
+        }
+        self.buffers.push(buffer);
+        self.buffers.len() - 1
+    }
+
+    /// Makes the buffer at `index` the one being edited, by showing it in the focused window.
+    /// The window starts at the buffer's cursor, with nothing folded.
+    fn switch_to_buffer(&mut self, index: usize) -> EditResult {
+        let tree = &self.buffers[index].tree;
+        self.windows[self.focused] = Window::new(index, tree.cursor_path().clone(), tree.root());
+        // Anything that was being typed or confirmed was meant for the old buffer
+        self.keystroke.clear();
+        self.replace_confirm = None;
+        Ok(EditSuccess::SwitchBuffer {
+            index,
+            count: self.buffers.len(),
+            name: self.buffer().name(),
+        })
+    }
+
+    /* ===== DIFF FUNCTIONS ===== */
+
+    /// Compares the buffer being edited with `new`, showing the two side by side: the buffer
+    /// being edited stays on the left and `new` is opened on the right.  Both buffers become
+    /// read-only, since the highlighting would no longer match an edited tree.
+    pub fn show_diff(&mut self, mut new: Buffer<'arena, Node>) {
+        let old = self.active();
+        self.tree_mut().set_read_only(true);
+        new.tree.set_read_only(true);
+        let view = DiffView::new(
+            [old, self.buffers.len()],
+            self.tree().root(),
+            new.tree.root(),
+        );
+        let window = Window::new(
+            self.buffers.len(),
+            new.tree.cursor_path().clone(),
+            new.tree.root(),
+        );
+        self.push_buffer(new);
+        // The split puts a copy of the focused window on the left, so the window on the right is
+        // the one that shows the new buffer
+        let right = self.focused;
+        let _ = self.split_window(Split::Vertical);
+        self.windows[right] = window;
+        self.status_message = Some(format!(
+            "{} difference(s) in {} hunk(s)",
+            view.difference_count(),
+            view.hunks().len()
+        ));
+        self.diff = Some(view);
+    }
+
+    /// Moves the cursor to the next hunk of the diff in a given direction, along with the cursor
+    /// of the other side of the diff (scrolling its window to show the hunk)
+    fn move_to_change(&mut self, side: Side) -> EditResult {
+        let diff = self.diff.as_ref().ok_or(EditErr::NoDiff)?;
+        let buffers = diff.buffers();
+        let tree = buffers
+            .iter()
+            .position(|&b| b == self.active())
+            .ok_or(EditErr::NoDiff)?;
+        let index = diff
+            .next_hunk(tree, self.tree().cursor_path(), side)
+            .ok_or(EditErr::NoMoreChanges)?;
+        let count = diff.hunks().len();
+        let paths = self.diff.as_mut().unwrap().move_to(index).paths.clone();
+
+        let other = 1 - tree;
+        let focused = self.focused;
+        match self.windows.iter().position(|w| w.buffer == buffers[other]) {
+            // Move the other window's cursor as if it was focused, so that it scrolls
+            Some(window) => {
+                self.focus_window(window);
+                self.tree_mut().set_cursor_path(paths[other].clone());
+                self.follow_tree_changes();
+                self.scroll_to_cursor();
+                self.focus_window(focused);
+            }
+            None => self.buffers[buffers[other]]
+                .tree
+                .set_cursor_path(paths[other].clone()),
         }
+        self.tree_mut().set_cursor_path(paths[tree].clone());
+        Ok(EditSuccess::MoveToChange { index, count })
     }
 
-    /// Render the tree to the screen
-    fn render_tree(&self, row: usize, col: usize) {
-        // Mutable variables to track where the terminal cursor should go
-        let mut row = row;
-        let mut col = col;
-        let mut indentation_amount = 0;
-
-        let cols = [
-            Color::MAGENTA,
-            Color::RED,
-            Color::YELLOW,
-            Color::GREEN,
-            Color::CYAN,
-            Color::BLUE,
-            Color::WHITE,
-            Color::LIGHT_RED,
-            Color::LIGHT_BLUE,
-            Color::LIGHT_CYAN,
-            Color::LIGHT_GREEN,
-            Color::LIGHT_YELLOW,
-            Color::LIGHT_MAGENTA,
-            Color::LIGHT_WHITE,
-        ];
-
-        /// A cheeky macro to print a string to the terminal
-        macro_rules! term_print {
-            ($string: expr) => {{
-                let string = $string;
-                // Print the string
-                self.term.print(row, col, string).unwrap();
-                // Move the cursor to the end of the string
-                let size = size::Size::from(string);
-                if size.lines() == 0 {
-                    col += size.last_line_length();
-                } else {
-                    row += size.lines();
-                    col = size.last_line_length();
-                }
-            }};
-            ($string: expr, $attr: expr) => {{
-                let string = $string;
-                // Print the string
-                self.term.print_with_attr(row, col, string, $attr).unwrap();
-                // Move the cursor to the end of the string
-                let size = size::Size::from(string);
-                if size.lines() == 0 {
-                    col += size.last_line_length();
-                } else {
-                    row += size.lines();
-                    col = size.last_line_length();
-                }
-            }};
+    /* ===== VALIDATION FUNCTIONS ===== */
+
+    /// Moves the cursor to the next problem with the tree in a given direction (to the second of
+    /// two keys that clash, or to the node that doesn't match the schema).  Like `]c`, this
+    /// doesn't wrap around past the first or last problem.
+    fn move_to_error(&mut self, side: Side) -> EditResult {
+        let problems = self.buffer().validation.problems();
+        if problems.is_empty() {
+            return Err(EditErr::NoErrors);
+        }
+        // A problem's culprit can come after the problems inside it (e.g. a field which isn't
+        // allowed comes after the problems in the fields before it), so the errors are moved
+        // between in the order of the nodes that the cursor moves to
+        let mut targets: Vec<_> = problems.iter().map(|p| (p.target(), p)).collect();
+        targets.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let cursor = self.tree().cursor_path();
+        let index = match side {
+            Side::Next => targets.iter().position(|(path, _)| path > cursor),
+            Side::Prev => targets.iter().rposition(|(path, _)| path < cursor),
+        }
+        .ok_or(EditErr::NoMoreErrors)?;
+        let (path, problem) = &targets[index];
+        let (count, path, message) = (targets.len(), path.clone(), problem.error.to_string());
+        self.tree_mut().set_cursor_path(path);
+        Ok(EditSuccess::MoveToError {
+            index,
+            count,
+            message,
+        })
+    }
+
+    /// Reads the JSON Schema at the path given by the `schema` option (which can also be a
+    /// `file://` URL), and checks every buffer against it.  If the option is empty, buffers stop
+    /// being checked against a schema.
+    fn load_schema(&mut self) -> std::result::Result<(), EditErr> {
+        let path = self.options.schema.clone();
+        let schema = if path.is_empty() {
+            None
+        } else {
+            if !self.language.supports_schemas() {
+                return Err(EditErr::UnsupportedSchema {
+                    language: self.language.name().to_string(),
+                });
+            }
+            let failed = |message: String| EditErr::SchemaFailed {
+                path: path.clone(),
+                message,
+            };
+            let file = path.strip_prefix("file://").unwrap_or(&path);
+            let text = std::fs::read_to_string(file).map_err(|e| failed(e.to_string()))?;
+            Some(Rc::new(Schema::parse(&text).map_err(failed)?))
         };
+        for buffer in &mut self.buffers {
+            buffer.validation.set_schema(schema.clone());
+        }
+        self.schema = schema;
+        Ok(())
+    }
 
-        for (node, tok) in self.tree.root().display_tokens(&self.format_style) {
-            match tok {
-                DisplayToken::Text(s) => {
-                    // Hash the ref to decide on the colour
-                    let col = {
-                        let mut hasher = DefaultHasher::new();
-                        node.hash(&mut hasher);
-                        let hash = hasher.finish();
-                        cols[hash as usize % cols.len()]
-                    };
-                    // Generate the display attributes depending on if the node is selected
-                    let attr = if std::ptr::eq(node, self.tree.cursor()) {
-                        Attr::default().fg(Color::BLACK).bg(col)
-                    } else {
-                        Attr::default().fg(col)
-                    };
-                    // Print the token
-                    term_print!(s.as_str(), attr);
+    /* ===== WINDOW FUNCTIONS ===== */
+
+    /// Returns the position of the focused window in the order that windows are cycled through
+    fn window_position(&self) -> usize {
+        let order = self.split_tree.windows();
+        order.iter().position(|&w| w == self.focused).unwrap()
+    }
+
+    /// Returns the [`EditSuccess`] that reports which window is focused
+    fn window_success(&self) -> EditSuccess {
+        EditSuccess::FocusWindow {
+            index: self.window_position(),
+            count: self.windows.len(),
+        }
+    }
+
+    /// Moves the focus to the window at `index`, so that its buffer is the one being edited
+    fn focus_window(&mut self, index: usize) {
+        if index == self.focused {
+            return;
+        }
+        // Remember where the cursor was in the window being left
+        let (buffer, window) = self.buffer_and_window_mut();
+        window.set_cursor(buffer.tree.cursor_path().clone(), buffer.tree.root());
+        self.focused = index;
+        let path = self.window().cursor_path().clone();
+        self.tree_mut().set_cursor_path(path);
+    }
+
+    /// Splits the focused window in two, both of which show the same part of the same buffer.
+    /// The new window (above or left of the old one) becomes the focused window.
+    fn split_window(&mut self, split: Split) -> EditResult {
+        let new_window = self.windows.len();
+        let (buffer, window) = self.buffer_and_window_mut();
+        window.set_cursor(buffer.tree.cursor_path().clone(), buffer.tree.root());
+        let copy = window.clone();
+        self.windows.push(copy);
+        self.split_tree.split(self.focused, new_window, split);
+        self.focused = new_window;
+        Ok(self.window_success())
+    }
+
+    /// Closes the focused window, focusing the window before it (or after it, if it was the
+    /// first window).  The last window can't be closed.
+    fn close_window(&mut self) -> EditResult {
+        if self.windows.len() == 1 {
+            return Err(EditErr::CloseLastWindow);
+        }
+        let order = self.split_tree.windows();
+        let position = self.window_position();
+        let next = match position {
+            0 => order[1],
+            p => order[p - 1],
+        };
+        let closed = self.focused;
+        self.windows.remove(closed);
+        self.split_tree.remove(closed);
+        self.focused = if next > closed { next - 1 } else { next };
+        let path = self.window().cursor_path().clone();
+        self.tree_mut().set_cursor_path(path);
+        Ok(self.window_success())
+    }
+
+    /// Makes the format style follow the `format` option, for a terminal which is `screen_width`
+    /// columns wide.  The `fit` format is narrowed to the width of the terminal, so that nodes
+    /// are only kept on one line if they fit on the screen.  If the style changes, then the
+    /// sizes of the nodes are measured again.
+    fn update_format_style(&mut self, screen_width: usize) {
+        let kind = screen_format_kind(self.options.format_kind(), screen_width);
+        if kind != self.format_kind {
+            self.format_kind = kind;
+            self.format_style.set_kind(kind);
+            self.sizes = SizeCache::new();
+        }
+    }
+
+    /// Rebuilds the colour scheme from the `theme` option, with the `[colors]` of the config file
+    /// on top.  Any styles that the new scheme is missing are logged, and listed by `:messages`.
+    fn update_color_scheme(&mut self) {
+        self.color_scheme = ColorScheme::named(&self.options.theme)
+            .unwrap_or_default()
+            .merged(&self.color_overrides)
+            .for_language(self.language)
+            .quantized(ColorSupport::detect());
+        self.color_scheme.validate();
+    }
+
+    /// Switches to the built-in theme after the current one (see [`THEME_NAMES`]), wrapping
+    /// around to the first one after the last
+    fn next_theme(&mut self) -> EditResult {
+        let index = THEME_NAMES
+            .iter()
+            .position(|name| *name == self.options.theme)
+            .map_or(0, |i| (i + 1) % THEME_NAMES.len());
+        self.set_option("theme", Some(THEME_NAMES[index]))
+    }
+
+    /// Sets one of the [`Options`] to a value parsed from `value`, or shows its current value if
+    /// `value` is `None`.  The change takes effect straight away.
+    fn set_option(&mut self, option: &str, value: Option<&str>) -> EditResult {
+        if let Some(value) = value {
+            let old_schema = self.options.schema.clone();
+            self.options.set(option, value)?;
+            match options::canonical_name(option) {
+                "indent_width" | "tab_expand" => {
+                    self.format_style.set_indent(self.options.indent());
+                    // The sizes were measured with the old indentation
+                    self.sizes = SizeCache::new();
                 }
-                DisplayToken::Whitespace(n) => {
-                    col += n;
+                name @ ("trailing_commas" | "self_closing") => {
+                    if name == "trailing_commas"
+                        && self.options.trailing_commas
+                        && !self.language.allows_trailing_commas()
+                    {
+                        self.options.trailing_commas = false;
+                        return Err(OptionError::InvalidValue {
+                            option: option.to_string(),
+                            value: value.to_string(),
+                            expected: format!(
+                                "false, since {} doesn't allow trailing commas",
+                                self.language.name()
+                            ),
+                        }
+                        .into());
+                    }
+                    // Only the language's own style knows which of these options it has
+                    self.format_style
+                        .apply_options(&self.options, self.language);
+                    self.sizes = SizeCache::new();
                 }
-                DisplayToken::Newline => {
-                    row += 1;
-                    col = indentation_amount;
+                "format" | "max_width" => {
+                    let width = self.screen_size().0;
+                    self.update_format_style(width);
                 }
-                DisplayToken::Indent => {
-                    indentation_amount += 4;
+                // Give buffers that failed to auto-save another chance
+                "autosave" | "autosave_to" => {
+                    for buffer in &mut self.buffers {
+                        buffer.autosave.retry();
+                    }
                 }
-                DisplayToken::Dedent => {
-                    indentation_amount -= 4;
+                "theme" => self.update_color_scheme(),
+                // A schema that can't be loaded leaves the buffers checked against the old one
+                "schema" => {
+                    if let Err(e) = self.load_schema() {
+                        self.options.schema = old_schema;
+                        return Err(e);
+                    }
                 }
+                _ => {}
             }
         }
+        Ok(EditSuccess::ShowOption {
+            option: option.to_string(),
+            value: self.options.get(option)?,
+        })
     }
 
-    /* ===== MAIN FUNCTIONS ===== */
-
-    /// Update the terminal UI display
-    fn update_display(&self) {
-        // Put the terminal size into some convenient variables
-        let (width, height) = self.term.term_size().unwrap();
-        // Clear the terminal
-        self.term.clear().unwrap();
-
-        /* RENDER MAIN TEXT VIEW */
-
-        self.render_tree(0, 0);
+    /// Makes the buffer being edited read-only (or writable again), or shows whether it's
+    /// read-only if `value` is `None`.  Buffers can only be made writable if their file can be
+    /// written to, and the sides of a diff can never be made writable.
+    fn set_read_only(&mut self, value: Option<&str>) -> EditResult {
+        if let Some(value) = value {
+            let read_only: bool = value.parse().map_err(|_| OptionError::InvalidValue {
+                option: "readonly".to_string(),
+                value: value.to_string(),
+                expected: "a boolean ('true' or 'false')".to_string(),
+            })?;
+            let name = self.buffer().name();
+            let cant_make_writable = |reason: &str| EditErr::CantMakeWritable {
+                name: name.clone(),
+                reason: reason.to_string(),
+            };
+            let active = self.active();
+            if !read_only
+                && self
+                    .diff
+                    .as_ref()
+                    .is_some_and(|d| d.buffers().contains(&active))
+            {
+                return Err(cant_make_writable("it's one side of a diff"));
+            }
+            if !read_only
+                && self
+                    .buffer()
+                    .file_path
+                    .as_deref()
+                    .is_some_and(file::is_read_only)
+            {
+                return Err(cant_make_writable("its file is read-only"));
+            }
+            self.tree_mut().set_read_only(read_only);
+        }
+        Ok(EditSuccess::ShowOption {
+            option: "readonly".to_string(),
+            value: self.tree().is_read_only().to_string(),
+        })
+    }
 
-        /* RENDER LOG SECTION */
+    /// Binds `keys` (written like in the `[keys]` table of the config file) to the keystroke
+    /// called `name`.  Keys that are already bound are rebound, but keys can't be bound if they
+    /// start with other keys (or other keys start with them), since one of them could never be
+    /// typed.
+    fn map_keys(&mut self, keys: &str, name: &str) -> EditResult {
+        let invalid = |reason: String| EditErr::InvalidMapping {
+            keys: keys.to_string(),
+            reason,
+        };
+        let stroke = KeyStroke::from_name(name)
+            .ok_or_else(|| invalid(format!("there's no keystroke called '{}'", name)))?;
+        let parsed = parse_keys(keys).map_err(invalid)?;
+        let conflicts = conflicting_keys(&parsed, self.keymap.keys());
+        if let Some(other) = conflicts.into_iter().find(|other| **other != parsed) {
+            let other_name = self.keymap[other].name();
+            return Err(invalid(format!(
+                "they conflict with '{}' ({})",
+                other, other_name
+            )));
+        }
+        self.keymap.insert(parsed.clone(), stroke);
+        Ok(EditSuccess::Map {
+            keys: parsed,
+            keystroke: stroke.name(),
+        })
+    }
 
-        self.keystroke_log.render(&self.term, 0, width / 2);
+    /// Stops `keys` (written like in the `[keys]` table of the config file) from being bound to
+    /// anything
+    fn unmap_keys(&mut self, keys: &str) -> EditResult {
+        let parsed = parse_keys(keys).map_err(|reason| EditErr::InvalidMapping {
+            keys: keys.to_string(),
+            reason,
+        })?;
+        match self.keymap.remove(&parsed) {
+            Some(stroke) => Ok(EditSuccess::Unmap {
+                keys: parsed,
+                keystroke: stroke.name(),
+            }),
+            None => Err(EditErr::NotMapped(parsed)),
+        }
+    }
 
-        /* RENDER BOTTOM BAR */
+    /// Shows the language of the buffers, or (if `value` is the name of a language) switches the
+    /// current buffer to the language called `value` by parsing its text in that language.
+    /// Every buffer of an editor has the same language, so the editor has to be replaced by one
+    /// for the new language: this returns `true` (like quitting) once the text has been checked,
+    /// and only the current buffer is kept.  The other buffers can't have unsaved changes.
+    fn set_language(&mut self, value: Option<&str>) -> (bool, Option<EditResult>) {
+        let show = |language: Language| EditSuccess::ShowOption {
+            option: "language".to_string(),
+            value: language.name().to_string(),
+        };
+        let name = match value {
+            Some(name) => name,
+            None => return (false, Some(Ok(show(self.language)))),
+        };
+        let language = match self.languages.from_name(name) {
+            Some(language) => language,
+            None => {
+                let names: Vec<_> = self.languages.names().collect();
+                let error = OptionError::InvalidValue {
+                    option: "language".to_string(),
+                    value: name.to_string(),
+                    expected: format!("one of the languages ({})", names.join(", ")),
+                };
+                return (false, Some(Err(error.into())));
+            }
+        };
+        if language == self.language {
+            return (false, Some(Ok(show(language))));
+        }
 
-        // Add the `Press 'q' to exit.` message
-        self.term
-            .print(height - 1, 0, "Press 'q' to exit.")
-            .unwrap();
-        // Draw the current keystroke buffer
-        self.term
-            .print(
-                height - 1,
-                width - 5 - self.keystroke.chars().count(),
-                &self.keystroke,
-            )
-            .unwrap();
+        // Only the current buffer is kept, so the others have to be saved first
+        let active = self.active();
+        let others_dirty: Vec<String> = (0..self.buffers.len())
+            .filter(|&i| i != active && self.buffers[i].is_dirty())
+            .map(|i| self.buffers[i].name())
+            .collect();
+        if !others_dirty.is_empty() {
+            let error = EditErr::CannotSwitchLanguage {
+                language: name.to_string(),
+                message: format!("{} must be saved first", others_dirty.join(", ")),
+            };
+            return (false, Some(Err(error)));
+        }
+        // The text is what would be saved, so switching back and forth doesn't change the file
+        let mut format_style = self.format_style.clone();
+        format_style.set_kind(self.options.format_kind());
+        let text = self.tree().to_text(&format_style);
+        if let Err(message) = language.check(&text) {
+            let error = EditErr::CannotSwitchLanguage {
+                language: name.to_string(),
+                message,
+            };
+            return (false, Some(Err(error)));
+        }
+        // The registers can only be kept as text, since their nodes belong to this language
+        format_style.set_kind(FormatKind::Compact);
+        self.exit = Some(Exit::SwitchLanguage {
+            language,
+            text,
+            file_path: self.buffer().file_path.clone(),
+            dirty: self.buffer().is_dirty(),
+            registers: self.registers.save(&format_style),
+        });
+        (true, Some(Ok(show(language))))
+    }
 
-        /* UPDATE THE TERMINAL SCREEN */
+    /// Responds to a key pressed whilst the user is confirming replacements.  `y` replaces the
+    /// match under the cursor, `n` skips it, `a` replaces it and every match after it, and `q`
+    /// (or `<ESC>`) stops asking.  Every accepted replacement is made as a single change once the
+    /// user has stopped being asked, and the result of that change is returned.
+    fn handle_confirm_key(&mut self, key: Key) -> Option<EditResult> {
+        let confirm = self.replace_confirm.as_mut()?;
+        let finished = match key {
+            Key::Char('y') => {
+                confirm
+                    .accepted
+                    .push(confirm.candidates[confirm.next].clone());
+                confirm.next += 1;
+                confirm.next == confirm.candidates.len()
+            }
+            Key::Char('n') => {
+                confirm.next += 1;
+                confirm.next == confirm.candidates.len()
+            }
+            Key::Char('a') => {
+                let remaining = confirm.candidates[confirm.next..].iter().cloned();
+                confirm.accepted.extend(remaining);
+                true
+            }
+            Key::Char('q') | Key::ESC => true,
+            _ => return None,
+        };
+        if !finished {
+            let path = confirm.candidates[confirm.next].clone();
+            self.tree_mut().set_cursor_path(path);
+            return None;
+        }
+        let confirm = self.replace_confirm.take()?;
+        Some(self.tree_mut().replace_text(
+            &confirm.accepted,
+            &confirm.pattern,
+            &confirm.replacement,
+        ))
+    }
 
-        self.term.present().unwrap();
+    /// Replays the macro in a given register `count` times, by feeding its keys back through
+    /// [`interpret_key`](Editor::interpret_key).  Replaying stops at the first keystroke that fails.
+    ///
+    /// This returns a [`bool`] value that determines whether or not Sapling should quit, along
+    /// with the result of the replay.
+    fn replay_macro(&mut self, register: char, count: usize) -> (bool, EditResult) {
+        let keys = match self.macros.get(register) {
+            Some(keys) => keys.to_vec(),
+            None => return (false, Err(EditErr::EmptyMacro(register))),
+        };
+        if self.macro_depth >= macros::MAX_MACRO_DEPTH {
+            return (false, Err(EditErr::MacroRecursionLimit { register }));
+        }
+        self.macro_depth += 1;
+        let mut outcome = (false, Ok(EditSuccess::Replay { register, count }));
+        'replays: for iterations in 0..count {
+            for &key in &keys {
+                let (should_quit, result) = self.interpret_key(key);
+                if let Some(Err(e)) = result {
+                    let error = match e {
+                        // Errors from nested macros already describe where they stopped
+                        EditErr::MacroRecursionLimit { .. } | EditErr::MacroFailed { .. } => e,
+                        e => EditErr::MacroFailed {
+                            register,
+                            iterations,
+                            count,
+                            error: Box::new(e),
+                        },
+                    };
+                    outcome = (should_quit, Err(error));
+                    break 'replays;
+                }
+                if should_quit {
+                    outcome.0 = true;
+                    break 'replays;
+                }
+            }
+        }
+        self.macro_depth -= 1;
+        outcome
     }
 
-    /// Consumes a [`char`] and adds it to the keystroke buffer.  If the keystroke buffer contains a
-    /// valid keystroke, then execute that keystroke.
+    /// Responds to a key from the terminal (or a script or test), which can come from either the
+    /// keyboard or the mouse.  The result of the key is shown in the bottom bar, exactly as if
+    /// the key had been pressed whilst the editor was running.
     ///
     /// This returns a tuple of:
     /// 1. A [`bool`] value that determines whether or not Sapling should quit
-    /// 2. The [`EditResult`] of the edit, or `None` if the keystroke is incomplete
-    fn consume_keystroke(&mut self, c: char) -> (bool, Option<EditResult>) {
-        // Add the new keypress to the keystroke
-        self.keystroke.push(c);
-        // Attempt to parse the keystroke, and take action if the keystroke is
-        // complete
-        match parse_keystroke(&self.keymap, &self.keystroke) {
-            Some(action) => {
-                let (should_quit, result) = self.tree.execute_action(action);
-                // Add the keystroke to the keystroke log and clear the keystroke buffer
-                self.keystroke_log
-                    .push(self.keystroke.clone(), &self.keymap);
-                self.keystroke.clear();
-                // Return the result of the action
-                (should_quit, Some(result))
+    /// 2. The [`EditResult`] of the key, or `None` if the key didn't complete a keystroke
+    pub fn handle_key(&mut self, key: Key) -> (bool, Option<EditResult>) {
+        let (should_quit, result) = if is_mouse_event(key) {
+            // Mouse events aren't keystrokes, so aren't recorded into macros
+            (false, self.handle_mouse(key))
+        } else {
+            // Add the key to the macro being recorded (if there is one).  The key that stops the
+            // recording is removed again once it has been executed.
+            self.macros.record(key);
+            self.interpret_key(key)
+        };
+        // Start waiting to show the hints again whenever an unfinished keystroke is typed into
+        self.pending_since = if self.keystroke.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        // Write the result's message to the log if the keystroke was complete
+        if let Some(res) = &result {
+            self.report_result(res.clone());
+        }
+        self.write_swap_files();
+        let now = Instant::now();
+        for buffer in &mut self.buffers {
+            buffer.autosave.update(buffer.tree.root(), now);
+        }
+        (should_quit, result)
+    }
+
+    /// Returns the hints for the unfinished keystroke in the keystroke buffer, if they should be
+    /// shown.  They're shown once the user has waited `which_key_delay` milliseconds since
+    /// typing the last key of the keystroke.
+    fn hints(&self) -> Option<Hints> {
+        let since = self.pending_since?;
+        if !self.options.which_key || since.elapsed() < self.which_key_delay() {
+            return None;
+        }
+        let entries = match pending_keys(&self.keymap, &self.keystroke)? {
+            Pending::Keys(entries) => entries,
+            Pending::NodeChar(stroke) => {
+                let (cursor, parent) = self.tree().cursor_and_parent();
+                match stroke {
+                    KeyStroke::InsertChild => {
+                        help::char_entries(cursor, cursor.insert_chars().collect())
+                    }
+                    KeyStroke::InsertBefore | KeyStroke::InsertAfter => {
+                        let parent = parent?;
+                        help::char_entries(parent, parent.insert_chars().collect())
+                    }
+                    // Wrapping the cursor would put the new node in the arena, so instead this
+                    // guesses that the cursor can be wrapped in any node which can have children
+                    KeyStroke::Wrap => {
+                        let wrappers = cursor.replace_chars().filter(|&c| {
+                            cursor
+                                .from_char(c)
+                                .is_some_and(|node| node.insert_chars().next().is_some())
+                        });
+                        help::char_entries(cursor, wrappers.collect())
+                    }
+                    _ => help::char_entries(cursor, cursor.replace_chars().collect()),
+                }
             }
-            None => (false, None),
+        };
+        if entries.is_empty() {
+            return None;
+        }
+        Some(Hints::new(self.keystroke.clone(), entries))
+    }
+
+    /// Returns how long after `now` the hints for the unfinished keystroke will appear, or `None`
+    /// if there's no unfinished keystroke or its hints have already appeared
+    fn hints_remaining(&self, now: Instant) -> Option<Duration> {
+        if !self.options.which_key {
+            return None;
         }
+        (self.pending_since? + self.which_key_delay())
+            .checked_duration_since(now)
+            .filter(|d| !d.is_zero())
     }
 
-    fn mainloop(&mut self) {
+    /// Returns how long to wait during an unfinished keystroke before showing its hints
+    fn which_key_delay(&self) -> Duration {
+        Duration::from_millis(self.options.which_key_delay as u64)
+    }
+
+    /// Handles events from the terminal until Sapling quits, returning `true` if the user quit
+    /// and `false` if the terminal stopped sending events
+    fn mainloop(&mut self) -> bool {
         log::trace!("Starting mainloop");
         // Sit in the infinte mainloop
-        while let Ok(event) = self.term.poll_event() {
+        loop {
+            // Signals are only noticed between events, so they can wait until the next timeout
+            if let Some(signal) = signals::take_pending() {
+                if let Some(quit) = self.handle_signal(signal) {
+                    return quit;
+                }
+            }
+            // Whilst changes are highlighted, wake up when the highlight should disappear.  Whilst
+            // a keystroke is unfinished, wake up when its hints should appear.  Either way, wake
+            // up in time to check the file for changes made by other programs.
+            let now = Instant::now();
+            let highlight_remaining = self
+                .change_highlight
+                .as_ref()
+                .and_then(|h| h.remaining(now));
+            let disk_check_remaining = (self.last_disk_check + DISK_CHECK_INTERVAL)
+                .saturating_duration_since(now)
+                .max(Duration::from_millis(1));
+            let timeout = [
+                highlight_remaining,
+                self.hints_remaining(now),
+                self.autosave_remaining(now),
+            ]
+            .iter()
+            .flatten()
+            .fold(disk_check_remaining, |timeout, &d| timeout.min(d));
+            let event = self.term().peek_event(timeout);
             /* RESPOND TO THE USER'S INPUT */
-            if let Event::Key(key) = event {
-                match key {
-                    Key::Char(c) => {
-                        // Consume the new keystroke
-                        let (should_quit, result) = self.consume_keystroke(c);
-                        // Write the result's message to the log if the keystroke was complete
-                        if let Some(res) = result {
-                            res.log_message();
-                        }
-                        // `self.add_char_to_keystroke` returns `true` if the editor should quit
-                        if should_quit {
-                            break;
-                        }
+            match event {
+                Ok(Event::Key(key)) => {
+                    if self.handle_key(key).0 {
+                        return true;
                     }
-                    Key::ESC => {
-                        self.keystroke.clear();
+                }
+                Ok(Event::Resize { width, height }) => self.handle_resize(width, height),
+                Ok(_) => {}
+                // Waiting timed out, so either the highlight should disappear, the hints should
+                // appear (which redrawing the screen does) or the file should be checked
+                Err(_) if now.elapsed() >= timeout => {
+                    let now = Instant::now();
+                    if matches!(&self.change_highlight, Some(h) if h.remaining(now).is_none()) {
+                        self.change_highlight = None;
                     }
-                    _ => {}
                 }
+                // The terminal stopped sending events before the wait was over (e.g. because it
+                // was closed)
+                Err(_) => return false,
+            }
+            self.autosave(Instant::now());
+            if self.last_disk_check.elapsed() >= DISK_CHECK_INTERVAL {
+                self.check_disk();
+                self.last_disk_check = Instant::now();
             }
-
-            // Make sure that the logger isn't taller than the screen
-            self.keystroke_log
-                .set_max_entries(self.term.term_size().unwrap().1.min(10));
 
             // Update the screen after every input (if this becomes a bottleneck then we can
             // optimise the number of calls to `update_display` but for now it's not worth the
@@ -233,54 +3593,638 @@ impl<'arena, Node: Ast<'arena> + 'arena> Editor<'arena, Node> {
         }
     }
 
-    /// Start the editor and enter the mainloop
-    pub fn run(mut self) {
+    /// Responds to a [`Signal`] that was caught whilst the editor was running.  `SIGCONT` means
+    /// that Sapling was stopped by something else (e.g. `kill -STOP`), which may have drawn over
+    /// the screen, so the screen is drawn again.  `SIGINT` is treated like `:q`, so it only quits
+    /// if there are no unsaved changes.  `SIGHUP` and
+    /// `SIGTERM` can't wait for the user to save anything, so every buffer with unsaved changes
+    /// is written to its [recovery file](crash::recovery_path), just as when Sapling crashes.
+    /// Returns `None` if the editor should carry on, or otherwise whether the mainloop should
+    /// return as if the user quit (`true`) or the terminal went away (`false`), which leaves the
+    /// unsaved changes in their swap files too.
+    pub fn handle_signal(&mut self, signal: Signal) -> Option<bool> {
+        log::info!("Caught {}", signal.name());
+        match signal {
+            Signal::Continue => {
+                self.redraw_from_scratch();
+                None
+            }
+            Signal::Interrupt => {
+                let (should_quit, result) = self.perform_action(Action::Quit, 1, '"');
+                self.report_result(result);
+                should_quit.then_some(true)
+            }
+            Signal::HangUp | Signal::Terminate => {
+                for (name, result) in self.write_crash_recovery() {
+                    match result {
+                        Ok(path) => {
+                            log::warn!("The unsaved changes to {} were written to {}", name, path)
+                        }
+                        Err(e) => {
+                            log::error!("Couldn't write the unsaved changes to {}: {}", name, e)
+                        }
+                    }
+                }
+                Some(false)
+            }
+        }
+    }
+
+    /// Responds to the terminal being resized to `width` columns and `height` lines.  The tree
+    /// is formatted again for the new width, and every window is scrolled to keep its cursor in
+    /// view, before the whole screen is drawn again.
+    fn handle_resize(&mut self, width: usize, height: usize) {
+        log::debug!("Resized the terminal to {}x{}", width, height);
+        self.redraw_from_scratch();
+    }
+
+    /// Gets ready to draw the whole screen again from scratch (rather than only what's changed
+    /// since the last frame), for a screen that may have changed size since the last frame
+    fn redraw_from_scratch(&mut self) {
+        let (width, height) = self.screen_size();
+        if !fits_on_screen(width, height) {
+            return;
+        }
+        self.prepare_frame();
+        let focused = self.focused;
+        for window in 0..self.windows.len() {
+            self.focused = window;
+            self.scroll_to_cursor();
+        }
+        self.focused = focused;
+        if let Some(term) = &self.term {
+            term.clear().unwrap();
+        }
+    }
+
+    /// Stops Sapling (giving the terminal back to the shell) until the shell carries it on, and
+    /// then draws the screen again
+    fn suspend(&mut self) -> EditResult {
+        let term = self.term.as_ref().ok_or_else(|| EditErr::CannotSuspend {
+            reason: "it isn't running in a terminal".to_string(),
+        })?;
+        log::info!("Suspending Sapling");
+        let result = shell::OutsideTui::leave(term).and_then(|_outside| shell::suspend());
+        // Carrying on takes the terminal back and draws the screen again, so there's nothing left
+        // for the `SIGCONT` to do
+        signals::forget(Signal::Continue);
+        self.redraw_from_scratch();
+        result
+            .map(|()| EditSuccess::Suspend)
+            .map_err(|reason| EditErr::CannotSuspend { reason })
+    }
+
+    /// Feeds the text of the cursor to a shell command (from `:pipe`), and replaces the cursor
+    /// with the tree parsed from what the command prints, as a single change.  If the command
+    /// fails or its output can't be parsed, then the tree is left as it was.
+    fn pipe_cursor(&mut self, command: String) -> EditResult {
+        if self.tree().is_read_only() {
+            return Err(EditErr::ReadOnly);
+        }
+        let failed = |message: String| EditErr::PipeFailed {
+            command: command.clone(),
+            message,
+        };
+        let expanded =
+            shell::expand_file(&command, self.buffer().file_path.as_deref()).map_err(failed)?;
+        let mut input = self.tree().cursor().to_text(&self.file_format_style());
+        input.push('\n');
+        log::info!("Piping the cursor through '{}'", expanded);
+        let output = shell::pipe(&expanded, &input).map_err(failed)?;
+        let node = self
+            .loader
+            .parse_in(&output, self.tree().arena())
+            .map_err(|message| failed(format!("couldn't parse its output: {}", message)))?;
+        let name = self.tree_mut().replace_cursor_with_node(node)?;
+        Ok(EditSuccess::Pipe { command, name })
+    }
+
+    /// Runs a shell command (from `:!`) with the terminal, and then draws the screen again.
+    /// Without a terminal (e.g. whilst running a script), the command runs without one.
+    fn run_shell(&mut self, command: String) -> EditResult {
+        let failed = |message: String| EditErr::ShellFailed {
+            command: command.clone(),
+            message,
+        };
+        let expanded =
+            shell::expand_file(&command, self.buffer().file_path.as_deref()).map_err(failed)?;
+        log::info!("Running '{}' in the shell", expanded);
+        let status = match &self.term {
+            Some(term) => {
+                let status = shell::OutsideTui::leave(term)
+                    .and_then(|_outside| shell::run_attached(&expanded));
+                // `Ctrl-C` stops the command, but it shouldn't quit Sapling too
+                signals::forget(Signal::Interrupt);
+                self.redraw_from_scratch();
+                status
+            }
+            None => shell::run_detached(&expanded),
+        }
+        .map_err(failed)?;
+        Ok(EditSuccess::Shell {
+            command,
+            status: status.code(),
+        })
+    }
+
+    /// Shows a message to the user in the bottom bar, until the next keystroke is executed
+    pub fn set_status_message(&mut self, message: String) {
+        self.status_message = Some(message);
+    }
+
+    /// Start the editor and enter the mainloop, returning why the editor stopped
+    pub fn run(mut self) -> Exit {
+        let options = TermOptions::default().mouse_enabled(self.mouse);
+        // Panic messages wait until the terminal has been given back, so that they can be read
+        let guard = crash::TerminalGuard::new();
+        // Signals are handled by the mainloop instead of killing Sapling straight away
+        let signal_guard = signals::SignalGuard::new();
+        self.term = Some(Term::with_options(options).unwrap());
+        let exit = self.catch_crash(Self::run_in_terminal);
+        drop(signal_guard);
+        drop(guard);
+        exit
+    }
+
+    /// Runs `f` on the editor.  If it panics, then the terminal is given back and every buffer
+    /// with unsaved changes is written to its [recovery file](crash::recovery_path) before the
+    /// panic carries on.
+    pub fn catch_crash<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let payload = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self))) {
+            Ok(value) => return value,
+            Err(payload) => payload,
+        };
+        // Dropping the terminal leaves raw mode and the alternate screen
+        self.term = None;
+        if let Some(message) = crash::take_deferred() {
+            eprintln!("{}", message);
+        }
+        for (name, result) in self.write_crash_recovery() {
+            match result {
+                Ok(path) => eprintln!("The unsaved changes to {} were written to {}", name, path),
+                Err(e) => eprintln!("Couldn't write the unsaved changes to {}: {}", name, e),
+            }
+        }
+        std::panic::resume_unwind(payload)
+    }
+
+    /// Writes the text of every buffer with unsaved changes to its
+    /// [recovery file](crash::recovery_path) (or, if it has no file, to a recovery file in the
+    /// [state directory](logging::state_dir)).  Returns the name of each buffer along with
+    /// where it was written, or why it couldn't be.
+    pub fn write_crash_recovery(&self) -> Vec<(String, std::result::Result<String, String>)> {
+        let format_style = self.file_format_style();
+        self.buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.is_dirty())
+            .map(|(i, buffer)| {
+                let path = match &buffer.file_path {
+                    Some(path) => Some(crash::recovery_path(path)),
+                    None => logging::state_dir()
+                        .map(|dir| dir.join(format!("buffer-{}.crash-recovery", i + 1))),
+                };
+                let result = match path {
+                    Some(path) => {
+                        let mut text = buffer.tree.to_text(&format_style);
+                        text.push('\n');
+                        let written = match path.parent() {
+                            Some(dir) => std::fs::create_dir_all(dir),
+                            None => Ok(()),
+                        };
+                        written
+                            .and_then(|()| file::write_atomically(&path, &text))
+                            .map(|()| path.to_string_lossy().into_owned())
+                            .map_err(|e| e.to_string())
+                    }
+                    None => Err("there is nowhere to write it".to_string()),
+                };
+                (buffer.name(), result)
+            })
+            .collect()
+    }
+
+    /// Runs the editor in the terminal (which has just been started) until it stops
+    fn run_in_terminal(&mut self) -> Exit {
+        self.enable_swap_files();
         // Start the mainloop
-        self.mainloop();
+        let hung_up = !self.mainloop();
+        // Switching language carries on with the same buffer, so only quitting ends the session
+        if self.exit.is_none() {
+            self.write_session();
+        }
+        // Sapling didn't crash, so nothing needs recovering (and the next editor starts new swap
+        // files if the language is being switched).  If the terminal went away without the user
+        // quitting, then the unsaved changes are left in their swap files to be recovered.
+        for buffer in &mut self.buffers {
+            if !hung_up || !buffer.is_dirty() {
+                buffer.remove_swap();
+            }
+        }
         log::trace!("Making the cursor reappear.");
         // Show the cursor before closing so that the cursor isn't permanently disabled
         // (see issue https://github.com/lotabout/tuikit/issues/28)
-        self.term.show_cursor(true).unwrap();
-        self.term.present().unwrap();
+        self.term().show_cursor(true).unwrap();
+        self.term().present().unwrap();
+        self.exit.take().unwrap_or(Exit::Quit)
+    }
+
+    /// Feeds the keys of a script to the editor one by one, exactly as if they had been typed
+    /// (but without a terminal), and then returns the text of the buffer being edited (or the
+    /// patch of its changes, depending on `output`).  The script stops early if a key quits
+    /// Sapling.  If a key fails (or the script ends partway through something), then the rest of
+    /// the script isn't run and the error says which key was at fault.
+    pub fn run_script(
+        mut self,
+        script: &Script,
+        output: ScriptOutput,
+    ) -> std::result::Result<String, ScriptError> {
+        let finish = |editor: &Self| match output {
+            ScriptOutput::Text => match editor.buffer().check_writable() {
+                Ok(()) => Ok(editor.text()),
+                Err(e) => Err(script.error(script.len(), e.to_string())),
+            },
+            ScriptOutput::Patch => editor
+                .patch()
+                .map_err(|e| script.error(script.len(), e.to_string())),
+        };
+        self.prepare_frame();
+        for &(position, key) in script.keys() {
+            let (should_quit, result) = self.handle_key(key);
+            if let Some(Err(e)) = result {
+                return Err(script.error(position, e.to_string()));
+            }
+            if self.exit.is_some() {
+                let message = "scripts can't switch languages".to_string();
+                return Err(script.error(position, message));
+            }
+            if should_quit {
+                return finish(&self);
+            }
+            // Nothing is drawn, but the editor is kept in the state that it would be drawn in
+            self.prepare_frame();
+        }
+        let unfinished = if self.insert.is_some() {
+            Some("the script ended whilst typing text (which <Esc> would finish)")
+        } else if self.command_prompt.is_some() || self.search_prompt.is_some() {
+            Some("the script ended whilst typing a command or search (which <CR> would run)")
+        } else if self.replace_confirm.is_some() {
+            Some("the script ended whilst confirming replacements")
+        } else if self.disk_change.is_some() {
+            Some("the script ended whilst being asked about a file that changed on disk")
+        } else if !self.keystroke.is_empty() {
+            Some("the script ended partway through a keystroke")
+        } else {
+            None
+        };
+        match unfinished {
+            Some(message) => Err(script.error(script.len(), message.to_string())),
+            None => finish(&self),
+        }
+    }
+
+    /// Returns the text of the buffer being edited, formatted as it's saved to its file
+    pub fn text(&self) -> String {
+        let mut text = self.tree().to_text(&self.file_format_style());
+        // Text files should end with a newline
+        text.push('\n');
+        text
+    }
+
+    /// Returns the format that files are saved in, which is what the options say however wide the
+    /// terminal is
+    fn file_format_style(&self) -> Node::FormatStyle {
+        let mut format_style = self.format_style.clone();
+        format_style.set_kind(self.options.format_kind());
+        format_style
+    }
+
+    /// Returns the path from the root of the tree being edited to the cursor
+    pub fn cursor_path(&self) -> &CursorPath {
+        self.tree().cursor_path()
+    }
+
+    /// Returns the message shown in the bottom bar (usually the result of the last keystroke), if
+    /// there is one
+    pub fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    /// Draws the screen exactly as it would be drawn to a terminal that is `width` chars wide and
+    /// `height` lines tall, and returns it as text (see [`frame::screen_to_string`]).  If
+    /// `colored` is `true`, then the colours and effects of the text are marked with ANSI escape
+    /// sequences.  Without a terminal, the editor is laid out for a screen of this size from now
+    /// on.
+    pub fn render_to_string(&mut self, width: usize, height: usize, colored: bool) -> String {
+        self.headless_size = (width, height);
+        self.prepare_frame();
+        let mut screen = Screen::new(width, height);
+        self.render(&mut screen);
+        frame::screen_to_string(&screen, colored)
+    }
+}
+
+/// Returns `true` if a screen of the given size is at least [`MIN_SCREEN_SIZE`]
+fn fits_on_screen(width: usize, height: usize) -> bool {
+    width >= MIN_SCREEN_SIZE.0 && height >= MIN_SCREEN_SIZE.1
+}
+
+/// Draws the message shown instead of the editor on a screen that's smaller than
+/// [`MIN_SCREEN_SIZE`], cut short to fit on the screen
+fn render_too_small(canvas: &mut dyn Canvas, width: usize, height: usize) {
+    let lines = [
+        "Terminal too small".to_string(),
+        format!("(needs {}x{})", MIN_SCREEN_SIZE.0, MIN_SCREEN_SIZE.1),
+    ];
+    for (row, line) in lines.iter().take(height).enumerate() {
+        let text: String = line.chars().take(width).collect();
+        canvas.print(row, 0, &text).unwrap();
+    }
+}
+
+impl<'arena, Node: Ast<'arena> + 'arena> Draw for Editor<'arena, Node> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> tuikit::Result<()> {
+        self.render(canvas);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::normal_mode::{default_keymap, parse_keystroke, Action};
-    use crate::editable_tree::Direction;
+    use super::normal_mode::{
+        conflicting_keys, default_keymap, key_tokens, parse_keys, parse_keystroke,
+        parse_window_key, pending_keys, Action, KeyStroke, Pending,
+    };
+    use super::window::Split;
+    use super::{format_bytes, normal_mode, screen_format_kind};
+    use crate::ast::display_token::FormatKind;
+    use crate::editable_tree::{Direction, Side};
+
+    #[test]
+    fn narrow_screens() {
+        let fit = |max_width| FormatKind::Fit { max_width };
+        assert_eq!(screen_format_kind(fit(80), 120), fit(80));
+        assert_eq!(screen_format_kind(fit(80), 50), fit(50));
+        assert_eq!(
+            screen_format_kind(FormatKind::Pretty, 50),
+            FormatKind::Pretty
+        );
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 + 400 * 1024), "3.4 MiB");
+        assert_eq!(format_bytes(5 << 40), "5.0 TiB");
+        assert_eq!(format_bytes(3000 << 40), "3000.0 TiB");
+    }
 
     #[test]
     fn parse_keystroke_complete() {
         let keymap = default_keymap();
         for (keystroke, expected_effect) in &[
-            ("q", Action::Quit),
+            ("Q", Action::Quit),
+            ("qa", Action::StartRecording('a')),
+            ("@a", Action::Replay('a')),
+            ("10@a", Action::Replay('a')),
+            (".", Action::Repeat),
+            ("3.", Action::Repeat),
+            ("/", Action::StartSearch),
+            ("v", Action::StartVisual),
+            ("n", Action::SearchMatch(Side::Next)),
+            ("N", Action::SearchMatch(Side::Prev)),
+            ("]c", Action::MoveToChange(Side::Next)),
+            ("2[c", Action::MoveToChange(Side::Prev)),
+            ("]x", Action::Undefined("]x".to_string())),
+            (":", Action::StartCommand),
+            ("ma", Action::SetMark('a')),
+            ("'a", Action::JumpToMark('a')),
+            ("za", Action::ToggleFold),
+            ("zz", Action::CentreCursor),
+            ("zx", Action::Undefined("zx".to_string())),
+            ("L", Action::ToggleLogView),
+            ("?", Action::ShowHelp),
             ("x", Action::Delete),
             ("d", Action::Undefined("d".to_string())),
             ("h", Action::MoveCursor(Direction::Prev)),
-            ("j", Action::MoveCursor(Direction::Next)),
-            ("k", Action::MoveCursor(Direction::Prev)),
+            ("j", Action::MoveCursor(Direction::Down)),
+            ("k", Action::MoveCursor(Direction::Up)),
             ("l", Action::MoveCursor(Direction::Next)),
-            ("pajlbsi", Action::MoveCursor(Direction::Up)),
-            ("Pxx", Action::Undefined("Pxx".to_string())),
-            ("Qsx", Action::Undefined("Qsx".to_string())),
+            ("g", Action::MoveCursor(Direction::Root)),
+            ("G", Action::MoveCursor(Direction::LastLeaf)),
+            ("kajlbsi", Action::MoveCursor(Direction::Up)),
+            ("Xxx", Action::Undefined("Xxx".to_string())),
+            ("y", Action::Yank),
+            ("p", Action::PasteAfter),
+            ("Pxx", Action::PasteBefore),
+            ("Zsx", Action::Undefined("Zsx".to_string())),
             ("ra", Action::Replace('a')),
             ("rg", Action::Replace('g')),
             ("oX", Action::InsertChild('X')),
             ("oP", Action::InsertChild('P')),
+            ("sa", Action::Wrap('a')),
+            ("so", Action::Wrap('o')),
+            ("S", Action::Unwrap),
+            ("u", Action::Undo),
+            ("R", Action::Redo),
+            ("{", Action::Older),
+            ("}", Action::Newer),
+            ("3x", Action::Delete),
+            ("\"ay", Action::Yank),
+            ("3\"zp", Action::PasteAfter),
+            ("\"\"P", Action::PasteBefore),
+            ("\"Ay", Action::Undefined("\"Ay".to_string())),
+            ("0", Action::Undefined("0".to_string())),
+            ("<C-r>", Action::Redo),
+            ("<C-w>v", Action::SplitWindow(Split::Vertical)),
+            ("2<C-w><C-w>", Action::CycleWindow),
+            ("<C-w>x", Action::Undefined("<C-w>x".to_string())),
+            ("r<C-x>", Action::Undefined("r<C-x>".to_string())),
+            (
+                "<A-k>",
+                Action::MoveNode {
+                    side: Side::Prev,
+                    count: 1,
+                },
+            ),
+            ("<C-d>", Action::ScrollHalfPage(Side::Next)),
+            ("<BS>", Action::PopChar),
+            ("<C-x>", Action::Undefined("<C-x>".to_string())),
         ] {
             assert_eq!(
-                parse_keystroke(&keymap, *keystroke),
+                parse_keystroke(&keymap, keystroke),
                 Some(expected_effect.clone())
             );
         }
     }
 
+    #[test]
+    fn parse_window_keys() {
+        for (c, expected) in &[
+            ('s', Some(Action::SplitWindow(Split::Horizontal))),
+            ('v', Some(Action::SplitWindow(Split::Vertical))),
+            ('w', Some(Action::CycleWindow)),
+            ('q', Some(Action::CloseWindow)),
+            ('x', None),
+        ] {
+            assert_eq!(parse_window_key(*c), expected.clone());
+        }
+    }
+
+    #[test]
+    fn split_count() {
+        for (keystroke, expected_split) in &[
+            ("", (None, "")),
+            ("x", (None, "x")),
+            ("3", (Some(3), "")),
+            ("3<A-j>", (Some(3), "<A-j>")),
+            ("120ra", (Some(120), "ra")),
+            ("0", (None, "0")),
+        ] {
+            assert_eq!(normal_mode::split_count(keystroke), *expected_split);
+        }
+    }
+
+    #[test]
+    fn split_register() {
+        for (keystroke, expected_split) in &[
+            ("", (None, "")),
+            ("y", (None, "y")),
+            ("\"", (None, "\"")),
+            ("\"a", (Some('a'), "")),
+            ("\"ap", (Some('a'), "p")),
+        ] {
+            assert_eq!(normal_mode::split_register(keystroke), *expected_split);
+        }
+    }
+
+    #[test]
+    fn split_prefix() {
+        for (keystroke, expected_split) in &[
+            ("3x", (Some(3), None, "x")),
+            ("\"a3x", (Some(3), Some('a'), "x")),
+            ("2\"a3p", (Some(6), Some('a'), "p")),
+            ("\"\"12", (Some(12), Some('"'), "")),
+            ("\"", (None, None, "\"")),
+            // A count only follows a register, not a keystroke
+            ("x3", (None, None, "x3")),
+        ] {
+            assert_eq!(normal_mode::split_prefix(keystroke), *expected_split);
+        }
+    }
+
+    #[test]
+    fn repeatable_actions() {
+        // Only actions that edit the tree should be repeated by `.`, so that moving the cursor
+        // before repeating doesn't forget the edit
+        for action in &[
+            Action::InsertChild('t'),
+            Action::Delete,
+            Action::Replace('n'),
+            Action::Wrap('a'),
+            Action::PasteAfter,
+        ] {
+            assert!(action.is_edit(), "{:?}", action);
+        }
+        for action in &[
+            Action::MoveCursor(Direction::Next),
+            Action::Undo,
+            Action::Yank,
+            Action::Repeat,
+            Action::Replay('a'),
+        ] {
+            assert!(!action.is_edit(), "{:?}", action);
+        }
+    }
+
     #[test]
     fn parse_keystroke_incomplete() {
         let keymap = super::normal_mode::default_keymap();
-        for keystroke in &["", "r", "o"] {
-            assert_eq!(parse_keystroke(&keymap, *keystroke), None);
+        for keystroke in &[
+            "", "r", "o", "s", "q", "@", "m", "'", "z", "]", "[", "3", "12", "2r", "\"", "\"a",
+            "2\"b", "<C-w>", "3<C-w>",
+        ] {
+            assert_eq!(parse_keystroke(&keymap, keystroke), None);
+        }
+    }
+
+    #[test]
+    fn keystroke_names() {
+        let mut names: Vec<&str> = KeyStroke::ALL.iter().map(|s| s.name()).collect();
+        for stroke in &KeyStroke::ALL {
+            assert_eq!(KeyStroke::from_name(stroke.name()), Some(*stroke));
+        }
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), KeyStroke::ALL.len());
+        assert_eq!(KeyStroke::from_name("move-sideways"), None);
+        // Every keystroke has some default keys
+        let keymap = default_keymap();
+        for stroke in &KeyStroke::ALL {
+            assert!(keymap.values().any(|s| s == stroke), "{:?}", stroke);
+        }
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            key_tokens("g<C-k>x<lt"),
+            vec!["g", "<C-k>", "x", "<", "l", "t"]
+        );
+        assert_eq!(parse_keys("g g"), Ok("gg".to_string()));
+        assert_eq!(parse_keys("gg"), Ok("gg".to_string()));
+        assert_eq!(parse_keys("ctrl-K"), Ok("<C-k>".to_string()));
+        assert_eq!(parse_keys("alt-j <C-x>"), Ok("<A-j><C-x>".to_string()));
+        assert_eq!(parse_keys("<Space>"), Ok(" ".to_string()));
+        assert!(parse_keys("   ").is_err());
+        assert!(parse_keys("<Esc>").is_err());
+        assert!(parse_keys("3x").is_err());
+        assert!(parse_keys("\"a").is_err());
+        assert_eq!(
+            parse_keys("g <C-x><Nope>"),
+            Err(
+                "unknown special key (a '<' on its own is written '<lt>') (at '<Nope>')"
+                    .to_string()
+            )
+        );
+        // `0` isn't a count on its own, so it can be bound
+        assert_eq!(parse_keys("0"), Ok("0".to_string()));
+
+        let others = ["g", "gg", "gx", "<C-g>", "x"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(conflicting_keys("g", &others), vec!["g", "gg", "gx"]);
+        assert_eq!(conflicting_keys("ggx", &others), vec!["g", "gg"]);
+        assert!(conflicting_keys("<C-", &others).is_empty());
+        assert!(conflicting_keys("y", &others).is_empty());
+    }
+
+    #[test]
+    fn pending_hints() {
+        let keymap = default_keymap();
+        let keys = |keystroke| match pending_keys(&keymap, keystroke) {
+            Some(Pending::Keys(entries)) => entries.into_iter().map(|(k, _)| k).collect(),
+            _ => Vec::new(),
+        };
+        assert_eq!(
+            pending_keys(&keymap, "2r"),
+            Some(Pending::NodeChar(KeyStroke::Replace))
+        );
+        assert_eq!(
+            pending_keys(&keymap, "\"ao"),
+            Some(Pending::NodeChar(KeyStroke::InsertChild))
+        );
+        assert_eq!(keys("\""), vec!["\"", "+", "a-z"]);
+        assert_eq!(keys("3<C-w>"), vec!["s", "v", "w", "q"]);
+        assert_eq!(keys("z"), vec!["a", "z"]);
+        assert_eq!(keys("]"), vec!["c", "e"]);
+        assert_eq!(keys("q"), vec!["<c>"]);
+        // Any keystroke can follow a count or a register, so there's nothing to hint at
+        for keystroke in &["", "3", "\"a", "x", "rt"] {
+            assert_eq!(pending_keys(&keymap, keystroke), None);
         }
     }
 }
@@ -1,66 +1,136 @@
-use crate::editable_tree::Direction;
+use super::registers;
+use super::script::{key_notation, special_key, KeyPress};
+use super::window::Split;
+use crate::editable_tree::{Direction, Side};
+use tuikit::prelude::Key;
 
 pub mod keystroke_log {
     //! A utility datastructure to store and render a log of keystrokes.  This is mostly used to give
     //! the viewers of my streams feedback for what I'm typing.
 
     use super::ActionCategory;
+    use crate::ast::display_token::SyntaxCategory;
+    use crate::config::color::Style;
+    use crate::config::ColorScheme;
+    use std::collections::VecDeque;
+    use std::time::{Duration, Instant};
     use tuikit::prelude::*;
 
-    /// Returns the [`Color`] that all [`Action`]s of a given [`ActionCategory`] should be
-    /// displayed.  This is not implemented as a method on [`ActionCategory`], because doing so
-    /// would require [`ActionCategory`] to rely on the specific terminal backend used.  This way,
-    /// we keep the terminal backend as encapsulated as possible.
-    pub fn term_color(category: ActionCategory) -> Color {
+    /// How many entries the log remembers before it starts forgetting the oldest ones
+    pub const MAX_LOG_ENTRIES: usize = 1000;
+
+    /// Returns the [`SyntaxCategory`] whose style (in the [`ColorScheme`]) all [`Action`]s of a
+    /// given [`ActionCategory`] should be displayed.  This is not implemented as a method on
+    /// [`ActionCategory`], because the link between the two is only a matter of how the log looks.
+    ///
+    /// [`Action`]: super::Action
+    pub fn syntax_category(category: &ActionCategory) -> SyntaxCategory {
         match category {
-            ActionCategory::Move => Color::LIGHT_BLUE,
-            ActionCategory::History => Color::LIGHT_YELLOW,
-            ActionCategory::Insert => Color::LIGHT_GREEN,
-            ActionCategory::Replace => Color::CYAN,
-            ActionCategory::Delete => Color::RED,
-            ActionCategory::Quit => Color::MAGENTA,
-            ActionCategory::Undefined => Color::LIGHT_RED,
+            ActionCategory::Move => SyntaxCategory::Ident,
+            ActionCategory::History => SyntaxCategory::Keyword,
+            ActionCategory::Insert => SyntaxCategory::Type,
+            ActionCategory::Replace => SyntaxCategory::Comment,
+            ActionCategory::Delete => SyntaxCategory::Special,
+            ActionCategory::Quit => SyntaxCategory::Preproc,
+            ActionCategory::File => SyntaxCategory::Preproc,
+            ActionCategory::View => SyntaxCategory::Default,
+            ActionCategory::Yank => SyntaxCategory::Literal,
+            ActionCategory::Macro => SyntaxCategory::Const,
+            ActionCategory::Undefined => SyntaxCategory::Error,
         }
     }
 
     /// One entry in the log.  This usually represts a single keystroke, but could represent an
     /// accumulation of many identical keystrokes that are executed consecutively.
-    struct Entry {
-        count: usize,
-        keystroke: String,
-        description: String,
-        color: Color,
+    #[derive(Debug, Clone)]
+    pub struct Entry {
+        /// How many times the keystroke was executed in a row
+        pub count: usize,
+        pub keystroke: String,
+        /// The [`name`](super::KeyStroke::name) of the keystroke that the keys are bound to, if
+        /// they're bound to one
+        pub command: Option<&'static str>,
+        /// A description of the [`Action`](super::Action) that the keystroke was interpreted as
+        pub description: String,
+        pub category: ActionCategory,
+        /// `true` if the keystroke's [`Action`](super::Action) succeeded
+        pub succeeded: bool,
+        /// When the keystroke was most recently executed
+        pub time: Instant,
+    }
+
+    impl Entry {
+        /// Returns the keystroke followed by the name of the command it's bound to (if any), so
+        /// that users can find out what to call commands when rebinding them
+        pub fn label(&self) -> String {
+            match self.command {
+                Some(command) => format!("{} ({})", self.keystroke, command),
+                None => self.keystroke.clone(),
+            }
+        }
+
+        /// Returns the [`Style`] that this entry should be displayed in.  Failures are all shown
+        /// in the style of [`Error`](SyntaxCategory::Error), so that they stand out.
+        pub fn style(&self, color_scheme: &ColorScheme) -> Style {
+            if self.succeeded {
+                color_scheme.style(syntax_category(&self.category))
+            } else {
+                color_scheme.style(SyntaxCategory::Error)
+            }
+        }
     }
 
     /// A utility struct to store and display a log of which keystrokes have been executed recently.
     /// This is mostly used to give the viewers of my streams feedback for what I'm typing.
     pub struct KeyStrokeLog {
-        /// A list of keystrokes that have been run
-        keystrokes: Vec<Entry>,
+        /// The keystrokes that have been run, oldest first.  At most [`MAX_LOG_ENTRIES`] are kept.
+        keystrokes: VecDeque<Entry>,
+        /// How many of the most recent entries are drawn in the corner of the screen
         max_entries: usize,
     }
 
     impl KeyStrokeLog {
-        /// Create a new (empty) keystroke log
+        /// Create a new (empty) keystroke log, which shows `max_entries` entries in the corner of
+        /// the screen
         pub fn new(max_entries: usize) -> KeyStrokeLog {
             KeyStrokeLog {
-                keystrokes: vec![],
+                keystrokes: VecDeque::new(),
                 max_entries,
             }
         }
 
-        /// Sets and enforces the max entry limit
+        /// Sets how many entries are drawn in the corner of the screen
         pub fn set_max_entries(&mut self, max_entries: usize) {
             self.max_entries = max_entries;
-            self.enforce_entry_limit();
         }
 
-        /// Draw a log of recent keystrokes to a given terminal at a given location
-        pub fn render(&self, term: &Term, row: usize, col: usize) {
+        /// Returns every entry that the log remembers, oldest first
+        pub fn entries(&self) -> &VecDeque<Entry> {
+            &self.keystrokes
+        }
+
+        /// Returns the entries that should be shown in the full-screen log view, newest first.  If
+        /// `errors_only` is `true`, then only the entries that failed are returned.
+        pub fn filtered(&self, errors_only: bool) -> impl Iterator<Item = &Entry> {
+            self.keystrokes
+                .iter()
+                .rev()
+                .filter(move |e| !errors_only || !e.succeeded)
+        }
+
+        /// Draw a log of recent keystrokes to a given canvas at a given location
+        pub fn render(
+            &self,
+            canvas: &mut dyn Canvas,
+            row: usize,
+            col: usize,
+            color_scheme: &ColorScheme,
+        ) {
+            let skipped = self.keystrokes.len().saturating_sub(self.max_entries);
+            let entries: Vec<&Entry> = self.keystrokes.iter().skip(skipped).collect();
             // Calculate how wide the numbers column should be, enforcing that it is at least two
             // chars wide.
-            let count_col_width = self
-                .keystrokes
+            let count_col_width = entries
                 .iter()
                 .map(|e| match e.count {
                     1 => 0,
@@ -71,82 +141,242 @@ pub mod keystroke_log {
                 .max(2);
             // Calculate the width of the keystroke column, and make sure that it is at least two
             // chars wide.
-            let cmd_col_width = self
-                .keystrokes
+            let cmd_col_width = entries
                 .iter()
-                .map(|e| e.keystroke.len())
+                .map(|e| e.label().chars().count())
                 .max()
                 .unwrap_or(0)
                 .max(2);
             // Render the keystrokes
-            for (i, e) in self.keystrokes.iter().enumerate() {
+            for (i, e) in entries.iter().enumerate() {
                 // Print the count if greater than 1
                 if e.count > 1 {
-                    term.print(row + i, col, &format!("{}x", e.count)).unwrap();
+                    canvas
+                        .print(row + i, col, &format!("{}x", e.count))
+                        .unwrap();
                 }
                 // Print the keystrokes in one column
-                term.print_with_attr(
-                    row + i,
-                    col + count_col_width + 1,
-                    &e.keystroke,
-                    Attr::default().fg(Color::WHITE),
-                )
-                .unwrap();
+                canvas
+                    .print_with_attr(
+                        row + i,
+                        col + count_col_width + 1,
+                        &e.label(),
+                        Attr::default().fg(Color::WHITE),
+                    )
+                    .unwrap();
                 // Print a `=>`
-                term.print(row + i, col + count_col_width + 1 + cmd_col_width + 1, "=>")
+                canvas
+                    .print(row + i, col + count_col_width + 1 + cmd_col_width + 1, "=>")
                     .unwrap();
                 // Print the meanings in another column
-                term.print_with_attr(
-                    row + i,
-                    col + count_col_width + 1 + cmd_col_width + 4,
-                    &e.description,
-                    Attr::default().fg(e.color),
-                )
-                .unwrap();
+                canvas
+                    .print_with_attr(
+                        row + i,
+                        col + count_col_width + 1 + cmd_col_width + 4,
+                        &e.description,
+                        e.style(color_scheme).attr(),
+                    )
+                    .unwrap();
             }
         }
 
-        /// Repeatedly remove keystrokes until the entry limit is satisfied
-        fn enforce_entry_limit(&mut self) {
-            while self.keystrokes.len() > self.max_entries {
-                self.keystrokes.remove(0);
+        /// Draws the whole log over the top `height` rows of the screen, newest entry first.  The
+        /// first `scroll` entries are scrolled off the top of the screen.
+        pub fn render_full(
+            &self,
+            canvas: &mut dyn Canvas,
+            height: usize,
+            scroll: usize,
+            errors_only: bool,
+            color_scheme: &ColorScheme,
+        ) {
+            let title = if errors_only {
+                "Keystroke log (errors only)"
+            } else {
+                "Keystroke log"
+            };
+            canvas
+                .print_with_attr(0, 0, title, Attr::default().effect(Effect::BOLD))
+                .unwrap();
+            let now = Instant::now();
+            let rows = height.saturating_sub(1);
+            for (i, e) in self
+                .filtered(errors_only)
+                .skip(scroll)
+                .take(rows)
+                .enumerate()
+            {
+                let count = match e.count {
+                    1 => String::new(),
+                    c => format!("{}x", c),
+                };
+                let age = format_age(now.saturating_duration_since(e.time));
+                let text = format!("{:>8} {:>5} {:<24} => ", age, count, e.label());
+                canvas.print(i + 1, 0, &text).unwrap();
+                canvas
+                    .print_with_attr(
+                        i + 1,
+                        text.len(),
+                        &e.description,
+                        e.style(color_scheme).attr(),
+                    )
+                    .unwrap();
             }
         }
 
-        /// Pushes a new keystroke to the log.
-        pub fn push(&mut self, keystroke: String, keymap: &super::KeyMap) {
-            // If the keystroke is identical to the last log entry, incrememnt that counter by one
-            if Some(&keystroke) == self.keystrokes.last().map(|e| &e.keystroke) {
-                // We can safely unwrap here, because the guard of the `if` statement guaruntees
-                // that `self.keystroke.last()` is `Some(_)`
-                self.keystrokes.last_mut().unwrap().count += 1;
-                return;
+        /// Pushes a new keystroke to the log, along with the name of the command it's bound to,
+        /// the [`Action`](super::Action) that it was interpreted as and whether or not that action
+        /// succeeded.
+        pub fn push(
+            &mut self,
+            keystroke: String,
+            command: Option<&'static str>,
+            action: &super::Action,
+            succeeded: bool,
+        ) {
+            // If the keystroke (and its outcome) is identical to the last log entry, incrememnt
+            // that counter by one
+            if let Some(last) = self.keystrokes.back_mut() {
+                if last.keystroke == keystroke && last.succeeded == succeeded {
+                    last.count += 1;
+                    last.time = Instant::now();
+                    return;
+                }
             }
             // If the keystroke is different, then we should add a new entry for it
-            let (description, color) = if keystroke.is_empty() {
+            let (description, category) = if keystroke.is_empty() {
                 log::error!("Empty keystroke executed!");
-                ("<empty keystroke>".to_string(), Color::LIGHT_RED)
-            } else if let Some(action) = super::parse_keystroke(&keymap, &keystroke) {
-                (action.description(), term_color(action.category()))
+                ("<empty keystroke>".to_string(), ActionCategory::Undefined)
             } else {
-                log::error!("Incomplete keystroke executed!");
-                ("<incomplete keystroke>".to_string(), Color::LIGHT_RED)
+                (action.description(), action.category())
             };
-            self.keystrokes.push(Entry {
+            self.keystrokes.push_back(Entry {
                 count: 1,
                 keystroke,
+                command,
                 description,
-                color,
+                category,
+                succeeded,
+                time: Instant::now(),
             });
-            // Since we added an item, we should enforce the entry limit
-            self.enforce_entry_limit();
+            // Forget the oldest entry, so that the log doesn't grow forever
+            if self.keystrokes.len() > MAX_LOG_ENTRIES {
+                self.keystrokes.pop_front();
+            }
+        }
+    }
+
+    /// Formats how long ago something happened, in the largest unit that fits (e.g. `5s ago` or
+    /// `3m ago`)
+    pub fn format_age(age: Duration) -> String {
+        let secs = age.as_secs();
+        match secs {
+            0..=59 => format!("{}s ago", secs),
+            60..=3599 => format!("{}m ago", secs / 60),
+            _ => format!("{}h ago", secs / 3600),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{format_age, KeyStrokeLog, MAX_LOG_ENTRIES};
+        use crate::arena::Arena;
+        use crate::ast::display_token::SyntaxCategory;
+        use crate::ast::test_json::TestJSON as J;
+        use crate::config::default_color_scheme;
+        use crate::editable_tree::{cursor_path::CursorPath, DAG};
+        use crate::editor::normal_mode::{
+            default_keymap, keystroke_binding, parse_keystroke, Action,
+        };
+        use std::time::Duration;
+
+        #[test]
+        fn scripted_keystrokes() {
+            let arena = Arena::new();
+            let root = J::Array(vec![J::True, J::False]).add_to_arena(&arena);
+            let mut tree = DAG::new(&arena, root, CursorPath::root());
+            let keymap = default_keymap();
+            let mut log = KeyStrokeLog::new(10);
+            for keystroke in &["j", "l", "l", "l", "x", "d", "u", "u"] {
+                let action = parse_keystroke(&keymap, keystroke).unwrap();
+                let result = tree.execute_action(action.clone()).1;
+                let command = keystroke_binding(&keymap, keystroke).map(|(s, _)| s.name());
+                log.push(keystroke.to_string(), command, &action, result.is_ok());
+            }
+            let entries = log
+                .entries()
+                .iter()
+                .map(|e| (e.count, e.keystroke.as_str(), e.succeeded))
+                .collect::<Vec<_>>();
+            // `l` only succeeds once, since the cursor is then on the last child
+            assert_eq!(
+                entries,
+                vec![
+                    (1, "j", true),
+                    (1, "l", true),
+                    (2, "l", false),
+                    (1, "x", true),
+                    (1, "d", false),
+                    (1, "u", true),
+                    (1, "u", false),
+                ]
+            );
+            assert_eq!(log.entries()[3].description, "delete cursor");
+            assert_eq!(log.entries()[3].label(), "x (delete-node)");
+            assert_eq!(log.entries()[4].label(), "d");
+
+            // Filtering shows only the failures, newest first
+            let errors = log
+                .filtered(true)
+                .map(|e| e.keystroke.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(errors, vec!["u", "d", "l"]);
+            assert_eq!(log.filtered(false).count(), 7);
+
+            // Failures are coloured as errors, whatever their category
+            let scheme = default_color_scheme();
+            assert_eq!(
+                log.entries()[2].style(&scheme),
+                scheme.style(SyntaxCategory::Error)
+            );
+            assert_eq!(
+                log.entries()[1].style(&scheme),
+                scheme.style(SyntaxCategory::Ident)
+            );
+            assert_eq!(
+                log.entries()[3].style(&scheme),
+                scheme.style(SyntaxCategory::Special)
+            );
+        }
+
+        #[test]
+        fn bounded() {
+            let mut log = KeyStrokeLog::new(10);
+            for i in 0..MAX_LOG_ENTRIES + 50 {
+                log.push(i.to_string(), None, &Action::Delete, true);
+            }
+            // The oldest entries are forgotten first
+            assert_eq!(log.entries().len(), MAX_LOG_ENTRIES);
+            assert_eq!(log.entries()[0].keystroke, "50");
+            // Showing fewer entries on screen doesn't forget any
+            log.set_max_entries(3);
+            assert_eq!(log.entries().len(), MAX_LOG_ENTRIES);
+        }
+
+        #[test]
+        fn ages() {
+            assert_eq!(format_age(Duration::from_millis(300)), "0s ago");
+            assert_eq!(format_age(Duration::from_secs(59)), "59s ago");
+            assert_eq!(format_age(Duration::from_secs(150)), "2m ago");
+            assert_eq!(format_age(Duration::from_secs(7300)), "2h ago");
         }
     }
 }
 
 /// The possible keystroke typed by user without any parameters.
-/// It can be mapped to a single key.
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+/// It can be mapped to any sequence of keys, and has a [`name`](KeyStroke::name) which the config
+/// file and `:map` use to rebind it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum KeyStroke {
     /// Quit Sapling
     Quit,
@@ -160,6 +390,10 @@ pub enum KeyStroke {
     InsertAfter,
     /// Delete the cursor
     Delete,
+    /// Wrap the cursor in a new node, expects an argument
+    Wrap,
+    /// Replace the cursor with its only child
+    Unwrap,
     /// Move cursor in given direction.  The direction is part of the keystroke, since the directions
     /// all correspond to single key presses.
     MoveCursor(Direction),
@@ -167,9 +401,177 @@ pub enum KeyStroke {
     Undo,
     /// Redo a change
     Redo,
+    /// Move to the previous tree in time, ignoring the branches of the undo history
+    Older,
+    /// Move to the next tree in time, ignoring the branches of the undo history
+    Newer,
+    /// Write the tree to the file it was loaded from
+    Save,
+    /// Write the tree to its file (if it has unsaved changes) and then quit Sapling
+    SaveAndQuit,
+    /// Copy the cursor into the register
+    Yank,
+    /// Paste the register before the cursor
+    PasteBefore,
+    /// Paste the register after the cursor
+    PasteAfter,
+    /// Start recording a macro, expects an argument
+    Record,
+    /// Replay a macro, expects an argument
+    Replay,
+    /// Repeat the last edit
+    Repeat,
+    /// Start typing a search query
+    Search,
+    /// Move to a search match in a given direction
+    SearchMatch(Side),
+    /// Start typing a command
+    Command,
+    /// Mark the cursor's position, expects an argument
+    SetMark,
+    /// Jump to a marked position, expects an argument
+    JumpToMark,
+    /// Fold commands, expects an argument (only `a` is valid, which toggles)
+    Fold,
+    /// Show or hide the full-screen keystroke log
+    Log,
+    /// Move to a change of a diff (`c`) or a problem with the tree (`e`) in a given direction,
+    /// expects an argument
+    Change(Side),
+    /// Start (or stop) selecting a range of siblings
+    Visual,
+    /// Show the keys that can be pressed on the cursor
+    Help,
+    /// Start a command on the windows, expects an argument (see [`parse_window_key`])
+    Window,
+    /// Toggle debug highlighting
+    ToggleDebugHighlighting,
+    /// Switch to the next built-in theme
+    NextTheme,
+    /// Suspend Sapling and go back to the shell (like `Ctrl-Z` in other programs)
+    Suspend,
+    /// Scroll half a screen in a given direction
+    ScrollHalfPage(Side),
+    /// Move the cursor through its siblings in a given direction
+    MoveNode(Side),
+    /// Remove the last char of the cursor's text
+    PopChar,
 }
 
 impl KeyStroke {
+    /// Every keystroke, in the order that they're listed to the user
+    pub const ALL: [KeyStroke; 47] = [
+        KeyStroke::Quit,
+        KeyStroke::Replace,
+        KeyStroke::InsertChild,
+        KeyStroke::InsertBefore,
+        KeyStroke::InsertAfter,
+        KeyStroke::Delete,
+        KeyStroke::Wrap,
+        KeyStroke::Unwrap,
+        KeyStroke::MoveCursor(Direction::Down),
+        KeyStroke::MoveCursor(Direction::Up),
+        KeyStroke::MoveCursor(Direction::Prev),
+        KeyStroke::MoveCursor(Direction::Next),
+        KeyStroke::MoveCursor(Direction::Root),
+        KeyStroke::MoveCursor(Direction::LastLeaf),
+        KeyStroke::Undo,
+        KeyStroke::Redo,
+        KeyStroke::Older,
+        KeyStroke::Newer,
+        KeyStroke::Save,
+        KeyStroke::SaveAndQuit,
+        KeyStroke::Yank,
+        KeyStroke::PasteBefore,
+        KeyStroke::PasteAfter,
+        KeyStroke::Record,
+        KeyStroke::Replay,
+        KeyStroke::Repeat,
+        KeyStroke::Search,
+        KeyStroke::SearchMatch(Side::Next),
+        KeyStroke::SearchMatch(Side::Prev),
+        KeyStroke::Command,
+        KeyStroke::SetMark,
+        KeyStroke::JumpToMark,
+        KeyStroke::Fold,
+        KeyStroke::Log,
+        KeyStroke::Change(Side::Next),
+        KeyStroke::Change(Side::Prev),
+        KeyStroke::Visual,
+        KeyStroke::Help,
+        KeyStroke::Window,
+        KeyStroke::ToggleDebugHighlighting,
+        KeyStroke::NextTheme,
+        KeyStroke::Suspend,
+        KeyStroke::ScrollHalfPage(Side::Next),
+        KeyStroke::ScrollHalfPage(Side::Prev),
+        KeyStroke::MoveNode(Side::Next),
+        KeyStroke::MoveNode(Side::Prev),
+        KeyStroke::PopChar,
+    ];
+
+    /// Returns the name that this keystroke is bound to keys by, in the `[keys]` table of the
+    /// config file and in `:map`
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyStroke::Quit => "quit",
+            KeyStroke::Replace => "replace",
+            KeyStroke::InsertChild => "insert-child",
+            KeyStroke::InsertBefore => "insert-before",
+            KeyStroke::InsertAfter => "insert-after",
+            KeyStroke::Delete => "delete-node",
+            KeyStroke::Wrap => "wrap",
+            KeyStroke::Unwrap => "unwrap",
+            KeyStroke::MoveCursor(Direction::Down) => "move-child",
+            KeyStroke::MoveCursor(Direction::Up) => "move-parent",
+            KeyStroke::MoveCursor(Direction::Prev) => "move-prev",
+            KeyStroke::MoveCursor(Direction::Next) => "move-next",
+            KeyStroke::MoveCursor(Direction::Root) => "move-root",
+            KeyStroke::MoveCursor(Direction::LastLeaf) => "move-last-leaf",
+            KeyStroke::Undo => "undo",
+            KeyStroke::Redo => "redo",
+            KeyStroke::Older => "older",
+            KeyStroke::Newer => "newer",
+            KeyStroke::Save => "save",
+            KeyStroke::SaveAndQuit => "save-and-quit",
+            KeyStroke::Yank => "yank",
+            KeyStroke::PasteBefore => "paste-before",
+            KeyStroke::PasteAfter => "paste-after",
+            KeyStroke::Record => "record-macro",
+            KeyStroke::Replay => "replay-macro",
+            KeyStroke::Repeat => "repeat",
+            KeyStroke::Search => "search",
+            KeyStroke::SearchMatch(Side::Next) => "next-match",
+            KeyStroke::SearchMatch(Side::Prev) => "prev-match",
+            KeyStroke::Command => "command",
+            KeyStroke::SetMark => "set-mark",
+            KeyStroke::JumpToMark => "jump-to-mark",
+            KeyStroke::Fold => "fold",
+            KeyStroke::Log => "log",
+            KeyStroke::Change(Side::Next) => "next-change",
+            KeyStroke::Change(Side::Prev) => "prev-change",
+            KeyStroke::Visual => "visual",
+            KeyStroke::Help => "help",
+            KeyStroke::Window => "window",
+            KeyStroke::ToggleDebugHighlighting => "toggle-debug-highlighting",
+            KeyStroke::NextTheme => "next-theme",
+            KeyStroke::Suspend => "suspend",
+            KeyStroke::ScrollHalfPage(Side::Next) => "scroll-down",
+            KeyStroke::ScrollHalfPage(Side::Prev) => "scroll-up",
+            KeyStroke::MoveNode(Side::Next) => "move-node-forward",
+            KeyStroke::MoveNode(Side::Prev) => "move-node-back",
+            KeyStroke::PopChar => "delete-char",
+        }
+    }
+
+    /// Returns the keystroke called `name`, if there is one
+    pub fn from_name(name: &str) -> Option<KeyStroke> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|stroke| stroke.name() == name)
+    }
+
     /// Returns a lower-case summary string of the given keystroke
     pub fn summary_string(&self) -> &'static str {
         match self {
@@ -179,12 +581,47 @@ impl KeyStroke {
             KeyStroke::InsertBefore => "insert before",
             KeyStroke::InsertAfter => "insert after",
             KeyStroke::Delete => "delete",
+            KeyStroke::Wrap => "wrap",
+            KeyStroke::Unwrap => "unwrap",
             KeyStroke::MoveCursor(Direction::Down) => "move to first child",
             KeyStroke::MoveCursor(Direction::Up) => "move to parent",
             KeyStroke::MoveCursor(Direction::Prev) => "move to previous sibling",
             KeyStroke::MoveCursor(Direction::Next) => "move to next sibling",
+            KeyStroke::MoveCursor(Direction::Root) => "move to root",
+            KeyStroke::MoveCursor(Direction::LastLeaf) => "move to last leaf",
             KeyStroke::Undo => "undo",
             KeyStroke::Redo => "redo",
+            KeyStroke::Older => "older tree",
+            KeyStroke::Newer => "newer tree",
+            KeyStroke::Save => "save",
+            KeyStroke::SaveAndQuit => "save and quit",
+            KeyStroke::Yank => "yank",
+            KeyStroke::PasteBefore => "paste before",
+            KeyStroke::PasteAfter => "paste after",
+            KeyStroke::Record => "record macro",
+            KeyStroke::Replay => "replay macro",
+            KeyStroke::Repeat => "repeat edit",
+            KeyStroke::Search => "search",
+            KeyStroke::SearchMatch(Side::Next) => "next match",
+            KeyStroke::SearchMatch(Side::Prev) => "previous match",
+            KeyStroke::Command => "command",
+            KeyStroke::SetMark => "set mark",
+            KeyStroke::JumpToMark => "jump to mark",
+            KeyStroke::Fold => "fold",
+            KeyStroke::Log => "keystroke log",
+            KeyStroke::Change(Side::Next) => "next change or error",
+            KeyStroke::Change(Side::Prev) => "previous change or error",
+            KeyStroke::Visual => "visual mode",
+            KeyStroke::Help => "help",
+            KeyStroke::Window => "window command",
+            KeyStroke::ToggleDebugHighlighting => "toggle debug highlighting",
+            KeyStroke::NextTheme => "next theme",
+            KeyStroke::Suspend => "suspend to the shell",
+            KeyStroke::ScrollHalfPage(Side::Next) => "scroll down",
+            KeyStroke::ScrollHalfPage(Side::Prev) => "scroll up",
+            KeyStroke::MoveNode(Side::Next) => "move node forward",
+            KeyStroke::MoveNode(Side::Prev) => "move node back",
+            KeyStroke::PopChar => "delete last char",
         }
     }
 }
@@ -194,7 +631,7 @@ impl KeyStroke {
 pub enum ActionCategory {
     /// An [`Action`] that moves the cursor
     Move,
-    /// Either [`Action::Undo`] or [`Action::Redo`]
+    /// An [`Action`] that moves through the undo history
     History,
     /// An [`Action`] that inserts extra nodes into the tree
     Insert,
@@ -204,33 +641,84 @@ pub enum ActionCategory {
     Delete,
     /// The [`Action`] was to [`Quit`](Action::Quit)
     Quit,
+    /// An [`Action`] that reads or writes files
+    File,
+    /// An [`Action`] that changes how the tree is displayed, without changing the tree
+    View,
+    /// An [`Action`] that copies nodes into the register, without changing the tree
+    Yank,
+    /// An [`Action`] that records or replays a macro
+    Macro,
     /// The [`Action`] was [`Undefined`](Action::Undefined)
     Undefined,
 }
 
 impl ActionCategory {}
 
-/// Mapping of keys to keystrokes.
-/// Shortcut definition, also allows us to change the type if needed.
-pub type KeyMap = std::collections::HashMap<char, KeyStroke>;
+/// Mapping of key sequences to keystrokes.  The keys are written in the same notation as the
+/// keystroke buffer: chars as themselves and any other keys as they're written in scripts (e.g.
+/// `<C-r>`, see [`key_notation`]).
+pub type KeyMap = std::collections::HashMap<String, KeyStroke>;
 
+/// Returns the keys that Sapling binds keystrokes to if the config file doesn't rebind them.
+/// Like in Vim, `Ctrl-r` redoes, `Ctrl-w` starts a command on the windows and `Ctrl-d` and
+/// `Ctrl-u` scroll half a screen.
 pub fn default_keymap() -> KeyMap {
-    hmap::hmap! {
-        'q' => KeyStroke::Quit,
-        'i' => KeyStroke::InsertBefore,
-        'a' => KeyStroke::InsertAfter,
-        'o' => KeyStroke::InsertChild,
-        'r' => KeyStroke::Replace,
-        'x' => KeyStroke::Delete,
-        'c' => KeyStroke::MoveCursor(Direction::Down),
-        'p' => KeyStroke::MoveCursor(Direction::Up),
-        'h' => KeyStroke::MoveCursor(Direction::Prev),
-        'j' => KeyStroke::MoveCursor(Direction::Next),
-        'k' => KeyStroke::MoveCursor(Direction::Prev),
-        'l' => KeyStroke::MoveCursor(Direction::Next),
-        'u' => KeyStroke::Undo,
-        'R' => KeyStroke::Redo
-    }
+    let bindings: [(&str, KeyStroke); 49] = [
+        ("Q", KeyStroke::Quit),
+        ("ZZ", KeyStroke::SaveAndQuit),
+        ("q", KeyStroke::Record),
+        ("@", KeyStroke::Replay),
+        (".", KeyStroke::Repeat),
+        ("/", KeyStroke::Search),
+        ("n", KeyStroke::SearchMatch(Side::Next)),
+        ("N", KeyStroke::SearchMatch(Side::Prev)),
+        (":", KeyStroke::Command),
+        ("m", KeyStroke::SetMark),
+        ("z", KeyStroke::Fold),
+        ("L", KeyStroke::Log),
+        ("]", KeyStroke::Change(Side::Next)),
+        ("[", KeyStroke::Change(Side::Prev)),
+        ("'", KeyStroke::JumpToMark),
+        ("v", KeyStroke::Visual),
+        ("?", KeyStroke::Help),
+        ("T", KeyStroke::NextTheme),
+        ("i", KeyStroke::InsertBefore),
+        ("a", KeyStroke::InsertAfter),
+        ("o", KeyStroke::InsertChild),
+        ("r", KeyStroke::Replace),
+        ("x", KeyStroke::Delete),
+        ("s", KeyStroke::Wrap),
+        ("S", KeyStroke::Unwrap),
+        ("h", KeyStroke::MoveCursor(Direction::Prev)),
+        ("j", KeyStroke::MoveCursor(Direction::Down)),
+        ("k", KeyStroke::MoveCursor(Direction::Up)),
+        ("l", KeyStroke::MoveCursor(Direction::Next)),
+        ("g", KeyStroke::MoveCursor(Direction::Root)),
+        ("G", KeyStroke::MoveCursor(Direction::LastLeaf)),
+        ("u", KeyStroke::Undo),
+        ("R", KeyStroke::Redo),
+        ("{", KeyStroke::Older),
+        ("}", KeyStroke::Newer),
+        ("w", KeyStroke::Save),
+        ("y", KeyStroke::Yank),
+        ("P", KeyStroke::PasteBefore),
+        ("p", KeyStroke::PasteAfter),
+        ("<C-c>", KeyStroke::Quit),
+        ("<C-r>", KeyStroke::Redo),
+        ("<C-w>", KeyStroke::Window),
+        ("<C-t>", KeyStroke::ToggleDebugHighlighting),
+        ("<C-z>", KeyStroke::Suspend),
+        ("<C-d>", KeyStroke::ScrollHalfPage(Side::Next)),
+        ("<C-u>", KeyStroke::ScrollHalfPage(Side::Prev)),
+        ("<A-j>", KeyStroke::MoveNode(Side::Next)),
+        ("<A-k>", KeyStroke::MoveNode(Side::Prev)),
+        ("<BS>", KeyStroke::PopChar),
+    ];
+    bindings
+        .iter()
+        .map(|(keys, stroke)| (keys.to_string(), *stroke))
+        .collect()
 }
 
 /// The possible meanings of a user-typed keystroke
@@ -251,12 +739,88 @@ pub enum Action {
     InsertAfter(char),
     /// Remove the node under the cursor
     Delete,
+    /// Replace the selected node with a new node (given by some [`char`]) that contains it
+    Wrap(char),
+    /// Replace the selected node with its only child
+    Unwrap,
     /// Move the node in a given direction
     MoveCursor(Direction),
     /// Undo the last change
     Undo,
     /// Redo a change
     Redo,
+    /// Move to the previous tree in time, ignoring the branches of the undo history
+    Older,
+    /// Move to the next tree in time, ignoring the branches of the undo history
+    Newer,
+    /// Append a [`char`] to the textual contents of the selected node
+    PushChar(char),
+    /// Remove the last [`char`] from the textual contents of the selected node
+    PopChar,
+    /// Write the tree to the file it was loaded from
+    Save,
+    /// Write the tree to its file (if it has unsaved changes) and then quit Sapling
+    SaveAndQuit,
+    /// Switch between normal syntax highlighting and colouring every node by its hash
+    ToggleDebugHighlighting,
+    /// Switch to the built-in theme after the current one
+    NextTheme,
+    /// Stop Sapling until the shell carries it on, giving the terminal back in the meantime
+    Suspend,
+    /// Copy the selected node into the register
+    Yank,
+    /// Insert a copy of the register's contents before the cursor
+    PasteBefore,
+    /// Insert a copy of the register's contents after the cursor
+    PasteAfter,
+    /// Move the selected node a number of places through its siblings, keeping the cursor on it
+    MoveNode { side: Side, count: usize },
+    /// Start recording keystrokes into the macro register given by some [`char`]
+    StartRecording(char),
+    /// Stop recording the current macro
+    StopRecording,
+    /// Replay the keystrokes stored in the macro register given by some [`char`]
+    Replay(char),
+    /// Repeat the last [`Action`] that edited the tree, at the current cursor location
+    Repeat,
+    /// Start typing a query to search the tree for
+    StartSearch,
+    /// Move the cursor to the next match of the current search in a given direction
+    SearchMatch(Side),
+    /// Move the cursors of both sides of a diff to the next change in a given direction
+    MoveToChange(Side),
+    /// Move the cursor to the next problem with the tree (see [`Validation`]) in a given
+    /// direction
+    ///
+    /// [`Validation`]: crate::editable_tree::validate::Validation
+    MoveToError(Side),
+    /// Start typing a command (e.g. a find-and-replace)
+    StartCommand,
+    /// Start typing the text of the selected node (e.g. the contents of a string)
+    StartInsert,
+    /// Start selecting the siblings between the cursor and where it moves to, or stop selecting
+    /// if siblings are already being selected
+    StartVisual,
+    /// Store the cursor's position in the mark register given by some [`char`]
+    SetMark(char),
+    /// Move the cursor to the position stored in the mark register given by some [`char`]
+    JumpToMark(char),
+    /// Collapse the cursor so that only a summary of it is shown, or expand it if it's collapsed
+    ToggleFold,
+    /// Scroll half a screen in a given direction, moving the cursor by the same amount
+    ScrollHalfPage(Side),
+    /// Scroll so that the cursor is in the middle of the screen
+    CentreCursor,
+    /// Show the keystroke log over the whole screen, or hide it if it's being shown
+    ToggleLogView,
+    /// Show a box listing the keys that can be pressed on the cursor, until the next key
+    ShowHelp,
+    /// Split the focused window in two
+    SplitWindow(Split),
+    /// Move the focus to the next window
+    CycleWindow,
+    /// Close the focused window
+    CloseWindow,
 }
 
 impl Action {
@@ -271,12 +835,116 @@ impl Action {
             Action::InsertBefore(c) => format!("insert '{}' before cursor", c),
             Action::InsertAfter(c) => format!("insert '{}' after cursor", c),
             Action::Delete => "delete cursor".to_string(),
+            Action::Wrap(c) => format!("wrap cursor in '{}'", c),
+            Action::Unwrap => "unwrap cursor".to_string(),
+            Action::StartRecording(c) => format!("record macro '{}'", c),
+            Action::StopRecording => "stop recording macro".to_string(),
+            Action::Replay(c) => format!("replay macro '{}'", c),
+            Action::Repeat => "repeat last edit".to_string(),
+            Action::StartSearch => "search".to_string(),
+            Action::SearchMatch(Side::Next) => "move to next match".to_string(),
+            Action::SearchMatch(Side::Prev) => "move to previous match".to_string(),
+            Action::MoveToChange(Side::Next) => "move to next change".to_string(),
+            Action::MoveToChange(Side::Prev) => "move to previous change".to_string(),
+            Action::MoveToError(Side::Next) => "move to next error".to_string(),
+            Action::MoveToError(Side::Prev) => "move to previous error".to_string(),
+            Action::StartCommand => "start command".to_string(),
+            Action::StartInsert => "insert text".to_string(),
+            Action::StartVisual => "select siblings".to_string(),
+            Action::SetMark(c) => format!("set mark '{}'", c),
+            Action::JumpToMark(c) => format!("jump to mark '{}'", c),
+            Action::ToggleFold => "toggle fold".to_string(),
+            Action::ScrollHalfPage(Side::Next) => "scroll half a page down".to_string(),
+            Action::ScrollHalfPage(Side::Prev) => "scroll half a page up".to_string(),
+            Action::CentreCursor => "centre cursor on screen".to_string(),
+            Action::ToggleLogView => "toggle keystroke log".to_string(),
+            Action::ShowHelp => "show help".to_string(),
+            Action::SplitWindow(Split::Horizontal) => "split window horizontally".to_string(),
+            Action::SplitWindow(Split::Vertical) => "split window vertically".to_string(),
+            Action::CycleWindow => "move to next window".to_string(),
+            Action::CloseWindow => "close window".to_string(),
             Action::MoveCursor(Direction::Down) => "move to first child".to_string(),
             Action::MoveCursor(Direction::Up) => "move to parent".to_string(),
             Action::MoveCursor(Direction::Prev) => "move to previous sibling".to_string(),
             Action::MoveCursor(Direction::Next) => "move to next sibling".to_string(),
+            Action::MoveCursor(Direction::Root) => "move to root".to_string(),
+            Action::MoveCursor(Direction::LastLeaf) => "move to last leaf".to_string(),
             Action::Undo => "undo a change".to_string(),
             Action::Redo => "redo a change".to_string(),
+            Action::Older => "move to older tree".to_string(),
+            Action::Newer => "move to newer tree".to_string(),
+            Action::PushChar(c) => format!("append '{}' to cursor", c),
+            Action::PopChar => "remove last char of cursor".to_string(),
+            Action::Save => "save to file".to_string(),
+            Action::SaveAndQuit => "save to file and quit Sapling".to_string(),
+            Action::ToggleDebugHighlighting => "toggle debug highlighting".to_string(),
+            Action::NextTheme => "switch to next theme".to_string(),
+            Action::Suspend => "suspend to the shell".to_string(),
+            Action::Yank => "yank cursor".to_string(),
+            Action::PasteBefore => "paste before cursor".to_string(),
+            Action::PasteAfter => "paste after cursor".to_string(),
+            Action::MoveNode { side, count } => format!(
+                "move cursor {} place(s) {}",
+                count,
+                match side {
+                    Side::Prev => "back",
+                    Side::Next => "forward",
+                }
+            ),
+        }
+    }
+
+    /// Returns `true` if this `Action` edits the tree, and so can be repeated with
+    /// [`Action::Repeat`].  Moving the cursor and undoing don't count as edits, so repeating
+    /// after either of them will repeat the edit made before them.
+    pub fn is_edit(&self) -> bool {
+        match self {
+            Action::Replace(_)
+            | Action::InsertChild(_)
+            | Action::InsertBefore(_)
+            | Action::InsertAfter(_)
+            | Action::Delete
+            | Action::Wrap(_)
+            | Action::Unwrap
+            | Action::PushChar(_)
+            | Action::PopChar
+            | Action::PasteBefore
+            | Action::PasteAfter
+            | Action::MoveNode { .. } => true,
+            Action::Undefined(_)
+            | Action::Quit
+            | Action::MoveCursor(_)
+            | Action::Undo
+            | Action::Redo
+            | Action::Older
+            | Action::Newer
+            | Action::Save
+            | Action::SaveAndQuit
+            | Action::ToggleDebugHighlighting
+            | Action::NextTheme
+            | Action::Suspend
+            | Action::Yank
+            | Action::StartRecording(_)
+            | Action::StopRecording
+            | Action::Replay(_)
+            | Action::Repeat
+            | Action::StartSearch
+            | Action::SearchMatch(_)
+            | Action::MoveToChange(_)
+            | Action::MoveToError(_)
+            | Action::StartCommand
+            | Action::StartInsert
+            | Action::StartVisual
+            | Action::SetMark(_)
+            | Action::JumpToMark(_)
+            | Action::ToggleFold
+            | Action::ScrollHalfPage(_)
+            | Action::CentreCursor
+            | Action::ToggleLogView
+            | Action::ShowHelp
+            | Action::SplitWindow(_)
+            | Action::CycleWindow
+            | Action::CloseWindow => false,
         }
     }
 
@@ -285,50 +953,396 @@ impl Action {
         match self {
             Action::Undefined(_) => ActionCategory::Undefined,
             Action::Quit => ActionCategory::Quit,
-            Action::Replace(_) => ActionCategory::Replace,
-            Action::InsertChild(_) | Action::InsertBefore(_) | Action::InsertAfter(_) => {
-                ActionCategory::Insert
+            // The only command is find-and-replace
+            Action::Replace(_)
+            | Action::PushChar(_)
+            | Action::PopChar
+            | Action::StartCommand
+            | Action::StartInsert => ActionCategory::Replace,
+            Action::InsertChild(_)
+            | Action::InsertBefore(_)
+            | Action::InsertAfter(_)
+            | Action::Wrap(_) => ActionCategory::Insert,
+            Action::Delete | Action::Unwrap => ActionCategory::Delete,
+            Action::MoveCursor(_)
+            | Action::MoveNode { .. }
+            | Action::StartSearch
+            | Action::SearchMatch(_)
+            | Action::MoveToChange(_)
+            | Action::MoveToError(_)
+            | Action::StartVisual
+            | Action::SetMark(_)
+            | Action::JumpToMark(_) => ActionCategory::Move,
+            Action::Undo | Action::Redo | Action::Older | Action::Newer => ActionCategory::History,
+            Action::Save => ActionCategory::File,
+            Action::SaveAndQuit => ActionCategory::Quit,
+            Action::ToggleDebugHighlighting
+            | Action::NextTheme
+            | Action::Suspend
+            | Action::ToggleFold
+            | Action::ScrollHalfPage(_)
+            | Action::CentreCursor
+            | Action::ToggleLogView
+            | Action::ShowHelp
+            | Action::SplitWindow(_)
+            | Action::CycleWindow
+            | Action::CloseWindow => ActionCategory::View,
+            Action::Yank => ActionCategory::Yank,
+            Action::StartRecording(_) | Action::StopRecording | Action::Replay(_) => {
+                ActionCategory::Macro
+            }
+            Action::Repeat => ActionCategory::History,
+            Action::PasteBefore | Action::PasteAfter => ActionCategory::Insert,
+        }
+    }
+}
+
+/// Splits the count and register given at the start of a keystroke (e.g. the `3` and `a` of
+/// `3"ay`) from the rest of the keystroke.  Like in Vim, the count can also be typed after the
+/// register (`"a3y`), which is how a count is given whilst a number is selected (since digits
+/// typed at the start of a keystroke edit the number).  If both are given, they're multiplied.
+pub fn split_prefix(keystroke: &str) -> (Option<usize>, Option<char>, &str) {
+    let (count, rest) = split_count(keystroke);
+    let (register, rest) = split_register(rest);
+    if register.is_none() {
+        return (count, None, rest);
+    }
+    let (register_count, rest) = split_count(rest);
+    let count = match (count, register_count) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, b) => a.or(b),
+    };
+    (count, register, rest)
+}
+
+/// Splits a keystroke into the count typed before it (e.g. the `3` of `3<A-j>`) and the rest of
+/// the keystroke.  Counts can't start with `0`, and the count is `None` if no digits were typed.
+pub fn split_count(keystroke: &str) -> (Option<usize>, &str) {
+    let digits_end = keystroke
+        .char_indices()
+        .find(|&(i, c)| !c.is_ascii_digit() || (i == 0 && c == '0'))
+        .map_or(keystroke.len(), |(i, _)| i);
+    let (digits, rest) = keystroke.split_at(digits_end);
+    (digits.parse().ok(), rest)
+}
+
+/// Splits the register given at the start of a keystroke (e.g. the `"a` of `"ay`) from the rest
+/// of the keystroke.  The register is `None` if the keystroke doesn't start with one.
+pub fn split_register(keystroke: &str) -> (Option<char>, &str) {
+    let mut chars = keystroke.chars();
+    match (chars.next(), chars.next()) {
+        (Some('"'), Some(register)) => (Some(register), chars.as_str()),
+        _ => (None, keystroke),
+    }
+}
+
+/// Splits keys written in the notation of the keystroke buffer into one string per key.  A `<`
+/// only starts a special key (like `<C-r>`) if it's followed by the name of one and a `>`, so a
+/// `<` typed on its own is a key by itself.
+pub fn key_tokens(keys: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        let special_len = rest
+            .strip_prefix('<')
+            .and_then(|after| {
+                after
+                    .find('>')
+                    .filter(|&end| special_key(&after[..end]).is_some())
+            })
+            .map(|end| end + 2);
+        let len = special_len.unwrap_or_else(|| c.len_utf8());
+        let (token, after) = rest.split_at(len);
+        tokens.push(token);
+        rest = after;
+    }
+    tokens
+}
+
+/// How the start of a keystroke (after its count and register) matches the keys of a [`KeyMap`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Binding<'k> {
+    /// The keystroke starts with the keys bound to a [`KeyStroke`].  The keys typed after them
+    /// (which are the keystroke's arguments) are also given.
+    Bound(KeyStroke, &'k str),
+    /// The keystroke is the start of the keys bound to at least one [`KeyStroke`], so more keys
+    /// are needed
+    Partial,
+    /// The keystroke doesn't start with the keys of any [`KeyStroke`]
+    Unbound,
+}
+
+/// Finds the [`KeyStroke`] whose keys `command` starts with (see [`Binding`])
+pub fn find_binding<'k>(keymap: &KeyMap, command: &'k str) -> Binding<'k> {
+    let tokens = key_tokens(command);
+    let mut binding = Binding::Unbound;
+    let mut bound_len = 0;
+    for (keys, stroke) in keymap {
+        let keys_tokens = key_tokens(keys);
+        if tokens.starts_with(&keys_tokens) {
+            // If several keys match (which can only happen if the keymap has conflicts), the
+            // longest wins
+            if keys.len() > bound_len {
+                binding = Binding::Bound(*stroke, &command[keys.len()..]);
+                bound_len = keys.len();
+            }
+        } else if keys_tokens.starts_with(&tokens) && binding == Binding::Unbound {
+            binding = Binding::Partial;
+        }
+    }
+    binding
+}
+
+/// Returns the keys out of `others` that conflict with `keys`, because one of them is the start of
+/// the other (so one of them could never be typed).  Keys conflict with themselves.
+pub fn conflicting_keys<'k>(
+    keys: &str,
+    others: impl IntoIterator<Item = &'k String>,
+) -> Vec<&'k String> {
+    let tokens = key_tokens(keys);
+    let mut conflicts: Vec<_> = others
+        .into_iter()
+        .filter(|other| {
+            let other_tokens = key_tokens(other);
+            other_tokens.starts_with(&tokens) || tokens.starts_with(&other_tokens)
+        })
+        .collect();
+    conflicts.sort();
+    conflicts
+}
+
+/// Parses keys as they're written in the `[keys]` table of the config file and in `:map`, and
+/// returns them in the notation of the keystroke buffer.  Keys are separated by spaces, where each
+/// key is either `ctrl-<c>`, `alt-<c>` or keys written like they are in scripts (e.g. `g g`,
+/// `gg`, `ctrl-k` and `<C-k>` can all be bound).  Returns the reason why if the keys can't be
+/// parsed or bound.
+pub fn parse_keys(text: &str) -> Result<String, String> {
+    let mut keys = String::new();
+    for word in text.split_whitespace() {
+        // Returns the char after `prefix` (ignoring case), if that's all the rest of the word
+        let modified = |prefix: &str| {
+            let start = word.get(..prefix.len())?;
+            let mut chars = word[prefix.len()..].chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if start.eq_ignore_ascii_case(prefix) => Some(c),
+                _ => None,
+            }
+        };
+        let word_keys = if let Some(c) = modified("ctrl-") {
+            vec![Key::Ctrl(c.to_ascii_lowercase())]
+        } else if let Some(c) = modified("alt-") {
+            vec![Key::Alt(c)]
+        } else {
+            // Point out which part of the word is wrong, if it isn't the whole word
+            let sequence = KeyPress::parse_sequence(word).map_err(|e| match e.key {
+                Some(key) if key != word => format!("{} (at '{}')", e.message, key),
+                _ => e.message,
+            })?;
+            sequence.into_iter().map(KeyPress::key).collect()
+        };
+        for key in word_keys {
+            match key {
+                Key::ESC => return Err("<Esc> always cancels the keystroke".to_string()),
+                Key::Char(c) => keys.push(c),
+                key => keys.push_str(&key_notation(key)),
             }
-            Action::Delete => ActionCategory::Delete,
-            Action::MoveCursor(_) => ActionCategory::Move,
-            Action::Undo | Action::Redo => ActionCategory::History,
         }
     }
+    match keys.chars().next() {
+        None => Err("expected some keys".to_string()),
+        Some(c @ '1'..='9') | Some(c @ '"') => Err(format!(
+            "keys can't start with '{}', which starts a {}",
+            c,
+            if c == '"' { "register" } else { "count" }
+        )),
+        Some(_) => Ok(keys),
+    }
+}
+
+/// Returns the [`KeyStroke`] that `keystroke` (the contents of the keystroke buffer) starts
+/// with, along with the keys it was bound to.  Returns `None` if the keystroke doesn't start with
+/// the keys of a [`KeyStroke`].
+pub fn keystroke_binding<'k>(keymap: &KeyMap, keystroke: &'k str) -> Option<(KeyStroke, &'k str)> {
+    let command = split_prefix(keystroke).2;
+    match find_binding(keymap, command) {
+        Binding::Bound(stroke, args) => Some((stroke, &command[..command.len() - args.len()])),
+        Binding::Partial | Binding::Unbound => None,
+    }
+}
+
+/// Converts the key typed after `Ctrl-w` into the [`Action`] on windows that it stands for.  Like
+/// in Vim, `s` and `v` split the focused window, `w` moves to the next window and `q` closes the
+/// focused window.  Returns `None` if the key doesn't stand for anything.
+pub fn parse_window_key(c: char) -> Option<Action> {
+    Some(match c {
+        's' => Action::SplitWindow(Split::Horizontal),
+        'v' => Action::SplitWindow(Split::Vertical),
+        'w' => Action::CycleWindow,
+        'q' => Action::CloseWindow,
+        _ => return None,
+    })
 }
 
 /// Attempt to convert a keystroke as a `&`[`str`] into an [`Action`].
 /// This parses the string from the start, and returns when it finds a valid keystroke.
 ///
-/// Therefore, `"q489flshb"` will be treated like `"q"`, and will return `Some(Action::Quit)` even
-/// though `"q489flshb"` is not technically valid.
+/// Therefore, `"Q489flshb"` will be treated like `"Q"`, and will return `Some(Action::Quit)` even
+/// though `"Q489flshb"` is not technically valid.
 /// This function is run every time the user types a keystroke character, and so the user would not
-/// be able to input `"q489flshb"` to this function because doing so would require them to first
-/// input every possible prefix of `"q489flshb"`, including `"q"`.
+/// be able to input `"Q489flshb"` to this function because doing so would require them to first
+/// input every possible prefix of `"Q489flshb"`, including `"Q"`.
+///
+/// Whilst a macro is being recorded, `q` on its own stops the recording.  This depends on the
+/// state of the [`Editor`](super::Editor), so is handled there rather than here.
 ///
 /// This returns:
 /// - [`None`] if the keystroke is incomplete.
 /// - [`Action::Undefined`] if the keystroke is not defined (like the keystroke "X").
 /// - The corresponding [`Action`], otherwise.
 pub fn parse_keystroke(keymap: &KeyMap, keystroke: &str) -> Option<Action> {
-    let mut keystroke_char_iter = keystroke.chars();
-
-    // Consume the first char of the keystroke
-    let c = keystroke_char_iter.next()?;
+    // Skip over any count typed before the keystroke, and the register that it uses
+    let (_, register, command) = split_prefix(keystroke);
+    match register {
+        // Wait for the name of the register
+        None if command == "\"" => return None,
+        Some(c) if !registers::is_register_name(c) => {
+            return Some(Action::Undefined(keystroke.to_string()))
+        }
+        _ => {}
+    }
+    let (stroke, args) = match find_binding(keymap, command) {
+        Binding::Bound(stroke, args) => (stroke, args),
+        Binding::Partial => return None,
+        Binding::Unbound => return Some(Action::Undefined(keystroke.to_string())),
+    };
+    // The arguments of keystrokes are chars, so any other key (like `<C-x>`) is undefined.  The
+    // only exception is that `<C-w>` can follow the keys that start a command on the windows.
+    match key_tokens(args).first() {
+        Some(&"<C-w>") if stroke == KeyStroke::Window => return Some(Action::CycleWindow),
+        Some(arg) if arg.chars().nth(1).is_some() => {
+            return Some(Action::Undefined(keystroke.to_string()))
+        }
+        _ => {}
+    }
+    let mut keystroke_char_iter = args.chars();
 
-    match keymap.get(&c) {
+    match stroke {
         // "q" quits Sapling
-        Some(KeyStroke::Quit) => Some(Action::Quit),
+        KeyStroke::Quit => Some(Action::Quit),
         // this pattern is used several times: `keystroke_char_iter.next().map()
         // This consumes the second char of the iterator and, if it exists, returns
         // Some(Action::ThisAction(char))
-        Some(KeyStroke::InsertChild) => keystroke_char_iter.next().map(Action::InsertChild),
-        Some(KeyStroke::InsertBefore) => keystroke_char_iter.next().map(Action::InsertBefore),
-        Some(KeyStroke::InsertAfter) => keystroke_char_iter.next().map(Action::InsertAfter),
-        Some(KeyStroke::Delete) => Some(Action::Delete),
-        Some(KeyStroke::Replace) => keystroke_char_iter.next().map(Action::Replace),
-        Some(KeyStroke::MoveCursor(direction)) => Some(Action::MoveCursor(*direction)),
-        Some(KeyStroke::Undo) => Some(Action::Undo),
-        Some(KeyStroke::Redo) => Some(Action::Redo),
-        None => Some(Action::Undefined(keystroke.to_string())),
+        KeyStroke::InsertChild => keystroke_char_iter.next().map(Action::InsertChild),
+        KeyStroke::InsertBefore => keystroke_char_iter.next().map(Action::InsertBefore),
+        KeyStroke::InsertAfter => keystroke_char_iter.next().map(Action::InsertAfter),
+        KeyStroke::Delete => Some(Action::Delete),
+        KeyStroke::Wrap => keystroke_char_iter.next().map(Action::Wrap),
+        KeyStroke::Unwrap => Some(Action::Unwrap),
+        KeyStroke::Replace => keystroke_char_iter.next().map(Action::Replace),
+        KeyStroke::MoveCursor(direction) => Some(Action::MoveCursor(direction)),
+        KeyStroke::Undo => Some(Action::Undo),
+        KeyStroke::Redo => Some(Action::Redo),
+        KeyStroke::Older => Some(Action::Older),
+        KeyStroke::Newer => Some(Action::Newer),
+        KeyStroke::Save => Some(Action::Save),
+        KeyStroke::SaveAndQuit => Some(Action::SaveAndQuit),
+        KeyStroke::Yank => Some(Action::Yank),
+        KeyStroke::PasteBefore => Some(Action::PasteBefore),
+        KeyStroke::PasteAfter => Some(Action::PasteAfter),
+        KeyStroke::Record => keystroke_char_iter.next().map(Action::StartRecording),
+        KeyStroke::Replay => keystroke_char_iter.next().map(Action::Replay),
+        KeyStroke::Repeat => Some(Action::Repeat),
+        KeyStroke::Search => Some(Action::StartSearch),
+        KeyStroke::SearchMatch(side) => Some(Action::SearchMatch(side)),
+        KeyStroke::Command => Some(Action::StartCommand),
+        KeyStroke::SetMark => keystroke_char_iter.next().map(Action::SetMark),
+        KeyStroke::JumpToMark => keystroke_char_iter.next().map(Action::JumpToMark),
+        KeyStroke::Log => Some(Action::ToggleLogView),
+        KeyStroke::Visual => Some(Action::StartVisual),
+        KeyStroke::Help => Some(Action::ShowHelp),
+        KeyStroke::Fold => keystroke_char_iter.next().map(|c| match c {
+            'a' => Action::ToggleFold,
+            'z' => Action::CentreCursor,
+            _ => Action::Undefined(keystroke.to_string()),
+        }),
+        KeyStroke::Change(side) => keystroke_char_iter.next().map(|c| match c {
+            'c' => Action::MoveToChange(side),
+            'e' => Action::MoveToError(side),
+            _ => Action::Undefined(keystroke.to_string()),
+        }),
+        KeyStroke::Window => keystroke_char_iter.next().map(|c| {
+            parse_window_key(c).unwrap_or_else(|| Action::Undefined(keystroke.to_string()))
+        }),
+        KeyStroke::ToggleDebugHighlighting => Some(Action::ToggleDebugHighlighting),
+        KeyStroke::NextTheme => Some(Action::NextTheme),
+        KeyStroke::Suspend => Some(Action::Suspend),
+        KeyStroke::ScrollHalfPage(side) => Some(Action::ScrollHalfPage(side)),
+        KeyStroke::MoveNode(side) => Some(Action::MoveNode { side, count: 1 }),
+        KeyStroke::PopChar => Some(Action::PopChar),
+    }
+}
+
+/// What the next key of an unfinished keystroke can be, which is shown as a hint if the user
+/// waits before typing it
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Pending {
+    /// A char standing for a node, which finishes the given [`KeyStroke`].  Which chars these are
+    /// depends on the cursor, so they are worked out by the [`Editor`](super::Editor).
+    NodeChar(KeyStroke),
+    /// One of a fixed set of keys, each of which is given along with what it does
+    Keys(Vec<(String, String)>),
+}
+
+/// Returns what the next key of `keystroke` can be, if [`parse_keystroke`] says that it's
+/// unfinished.  Returns `None` if the keystroke is finished, or if it can be followed by any
+/// keystroke (e.g. when only a count has been typed).
+pub fn pending_keys(keymap: &KeyMap, keystroke: &str) -> Option<Pending> {
+    let (_, register, command) = split_prefix(keystroke);
+    // Any register can be given, and then any keystroke
+    if register.is_none() && command == "\"" {
+        return Some(Pending::Keys(vec![
+            ("\"".to_string(), "the unnamed register".to_string()),
+            ("+".to_string(), "the system clipboard".to_string()),
+            ("a-z".to_string(), "a named register".to_string()),
+        ]));
+    }
+    let stroke = match find_binding(keymap, command) {
+        Binding::Bound(stroke, "") => stroke,
+        _ => return None,
+    };
+    // The keys that finish a keystroke which takes a fixed set of keys
+    let fixed = |suffixes: &str| {
+        let keys = suffixes.chars().filter_map(|c| {
+            let action = parse_keystroke(keymap, &format!("{}{}", command, c))?;
+            Some((c.to_string(), action.description()))
+        });
+        Some(Pending::Keys(keys.collect()))
+    };
+    // The keystrokes which can be finished by any char
+    let any = |description: &str| {
+        Some(Pending::Keys(vec![(
+            "<c>".to_string(),
+            description.to_string(),
+        )]))
+    };
+    match stroke {
+        KeyStroke::Replace
+        | KeyStroke::InsertChild
+        | KeyStroke::InsertBefore
+        | KeyStroke::InsertAfter
+        | KeyStroke::Wrap => Some(Pending::NodeChar(stroke)),
+        KeyStroke::Window => {
+            let keys = "svwq".chars().filter_map(|c| {
+                parse_window_key(c).map(|action| (c.to_string(), action.description()))
+            });
+            Some(Pending::Keys(keys.collect()))
+        }
+        KeyStroke::Fold => fixed("az"),
+        KeyStroke::Change(_) => fixed("ce"),
+        KeyStroke::Record => any("record a macro into register <c>"),
+        KeyStroke::Replay => any("replay the macro in register <c>"),
+        KeyStroke::SetMark => any("set mark <c> to the cursor"),
+        KeyStroke::JumpToMark => any("jump to mark <c>"),
+        _ => None,
     }
 }
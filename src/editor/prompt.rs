@@ -0,0 +1,242 @@
+//! A single line of text that the user types at the bottom of the screen (e.g. a command after
+//! `:`), along with the history of lines that were typed before it.
+
+use crate::ast::size;
+
+/// How many lines a [`History`] remembers before it starts forgetting the oldest ones
+pub const MAX_HISTORY: usize = 100;
+
+/// The lines that the user has previously entered into a prompt, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    /// Creates a new (empty) `History`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a line to the end of the history.  Empty lines and lines which are the same as the
+    /// previous one aren't added.
+    pub fn push(&mut self, line: &str) {
+        if line.is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Returns every line in the history, oldest first
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+}
+
+/// A line of text being typed, with a cursor that can be moved through it
+#[derive(Debug, Clone, Default)]
+pub struct LineEditor {
+    text: String,
+    /// The byte index of the char that the cursor is on (or `text.len()` if the cursor is past
+    /// the end)
+    cursor: usize,
+    /// The entry of the [`History`] being shown, if the user has moved back through the history
+    history_index: Option<usize>,
+    /// The text that was typed before the user started moving through the history, which is
+    /// restored if they move past the newest entry
+    draft: String,
+}
+
+impl LineEditor {
+    /// Creates a new (empty) `LineEditor`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the text that has been typed
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns how many columns of the text are before the cursor
+    pub fn cursor_col(&self) -> usize {
+        size::char_widths(&self.text[..self.cursor], 1)
+            .map(|(_, w)| w)
+            .sum()
+    }
+
+    /// Returns the char that the cursor is on, or `None` if it's past the end of the text
+    pub fn char_at_cursor(&self) -> Option<char> {
+        self.text[self.cursor..].chars().next()
+    }
+
+    /// Types a char at the cursor
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Deletes the char before the cursor.  Returns `false` if the line is empty (so there's
+    /// nothing to delete), which usually means that the prompt should be cancelled.
+    pub fn backspace(&mut self) -> bool {
+        if self.text.is_empty() {
+            return false;
+        }
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.text.remove(self.cursor);
+        }
+        true
+    }
+
+    /// Deletes everything before the cursor (like `Ctrl-U` in Vim and most shells)
+    pub fn delete_to_start(&mut self) {
+        self.text.replace_range(..self.cursor, "");
+        self.cursor = 0;
+    }
+
+    /// Moves the cursor one char to the left, if it isn't at the start of the line
+    pub fn move_left(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    /// Moves the cursor one char to the right, if it isn't past the end of the line
+    pub fn move_right(&mut self) {
+        if let Some(c) = self.char_at_cursor() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    /// Moves the cursor to the start of the line
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Moves the cursor past the end of the line
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Replaces the text with the previous (i.e. older) entry of `history`, if there is one
+    pub fn history_prev(&mut self, history: &History) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if history.entries().is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                history.entries().len() - 1
+            }
+        };
+        self.show_history_entry(history, Some(index));
+    }
+
+    /// Replaces the text with the next (i.e. newer) entry of `history`, or with whatever had
+    /// been typed before moving through the history if there are no newer entries
+    pub fn history_next(&mut self, history: &History) {
+        match self.history_index {
+            None => {}
+            Some(i) if i + 1 < history.entries().len() => {
+                self.show_history_entry(history, Some(i + 1))
+            }
+            Some(_) => self.show_history_entry(history, None),
+        }
+    }
+
+    /// Shows a given entry of the history (or the draft if `index` is `None`), with the cursor
+    /// at the end of it
+    fn show_history_entry(&mut self, history: &History, index: Option<usize>) {
+        self.text = match index {
+            Some(i) => history.entries()[i].clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.history_index = index;
+        self.move_to_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, LineEditor, MAX_HISTORY};
+
+    fn typed(text: &str) -> LineEditor {
+        let mut line = LineEditor::new();
+        text.chars().for_each(|c| line.insert(c));
+        line
+    }
+
+    #[test]
+    fn editing() {
+        let mut line = typed("goto 1");
+        assert_eq!((line.text(), line.cursor_col()), ("goto 1", 6));
+        line.move_left();
+        line.move_left();
+        line.insert('x');
+        assert_eq!((line.text(), line.cursor_col()), ("gotox 1", 5));
+        assert_eq!(line.char_at_cursor(), Some(' '));
+        assert!(line.backspace());
+        assert!(line.backspace());
+        assert_eq!(line.text(), "got 1");
+        // Ctrl-U deletes everything before the cursor
+        line.delete_to_start();
+        assert_eq!((line.text(), line.cursor_col()), (" 1", 0));
+        line.move_left();
+        assert!(line.backspace());
+        assert_eq!(line.text(), " 1");
+        line.move_to_end();
+        line.move_right();
+        assert_eq!(line.char_at_cursor(), None);
+        assert!(line.backspace());
+        assert!(line.backspace());
+        assert!(!line.backspace());
+
+        // Wide chars are moved over in one go, and take up two columns
+        let mut line = typed("日本");
+        line.move_left();
+        assert_eq!(line.cursor_col(), 2);
+        line.insert('x');
+        assert_eq!(line.text(), "日x本");
+        line.move_to_start();
+        line.move_right();
+        assert_eq!(line.char_at_cursor(), Some('x'));
+    }
+
+    #[test]
+    fn history() {
+        let mut history = History::new();
+        for line in &["w", "", "goto 1", "goto 1", "set tabwidth 2"] {
+            history.push(line);
+        }
+        assert_eq!(history.entries(), ["w", "goto 1", "set tabwidth 2"]);
+
+        let mut line = typed("go");
+        line.history_prev(&history);
+        assert_eq!(line.text(), "set tabwidth 2");
+        line.history_prev(&history);
+        line.history_prev(&history);
+        line.history_prev(&history);
+        assert_eq!(line.text(), "w");
+        line.history_next(&history);
+        assert_eq!(line.text(), "goto 1");
+        line.insert('0');
+        assert_eq!(line.text(), "goto 10");
+        // Moving past the newest entry brings back what was being typed
+        line.history_next(&history);
+        line.history_next(&history);
+        assert_eq!((line.text(), line.cursor_col()), ("go", 2));
+        line.history_next(&history);
+        assert_eq!(line.text(), "go");
+        LineEditor::new().history_prev(&History::new());
+
+        for i in 0..MAX_HISTORY + 5 {
+            history.push(&i.to_string());
+        }
+        assert_eq!(history.entries().len(), MAX_HISTORY);
+        assert_eq!(history.entries()[0], "5");
+    }
+}
@@ -0,0 +1,211 @@
+//! The registers that yanked and cut nodes are kept in, like Vim's.  `"ay` yanks into the
+//! register `a` and `"ap` pastes from it, whereas a plain `y` or `p` uses the unnamed register
+//! `"`.  Whatever is yanked into a named register is also put in the unnamed register, so a
+//! plain `p` always pastes whatever was yanked or cut last.
+//!
+//! The register `+` is the system clipboard (see [`clipboard`](super::clipboard)), which the
+//! editor reads and writes itself, so it's never kept here.
+//!
+//! Registers remember the language of the buffer that their nodes came from.  The registers are
+//! kept when Sapling switches to editing a different language, but only as text, so they can be
+//! listed but not pasted.
+
+use crate::ast::Ast;
+use crate::editable_tree::EditErr;
+use crate::language::Language;
+use std::collections::BTreeMap;
+
+/// The register used when no register is given
+pub const UNNAMED: char = '"';
+
+/// The register which is the system clipboard
+pub const CLIPBOARD: char = '+';
+
+/// Returns `true` if `c` is the name of a register: either a lower-case letter, the unnamed
+/// register or the clipboard
+pub fn is_register_name(c: char) -> bool {
+    c == UNNAMED || c == CLIPBOARD || c.is_ascii_lowercase()
+}
+
+/// What a register holds
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Contents<'arena, Node: Ast<'arena>> {
+    /// The nodes that were yanked, in order
+    Nodes(Vec<&'arena Node>),
+    /// The text of nodes that were yanked whilst Sapling was editing a different language
+    Text(String),
+}
+
+/// One register, along with the language of the buffer that it was yanked from
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Register<'arena, Node: Ast<'arena>> {
+    pub language: Language,
+    pub contents: Contents<'arena, Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Register<'arena, Node> {
+    /// Returns the text of this register's nodes on one line, separated by spaces
+    pub fn text(&self, format_style: &Node::FormatStyle) -> String {
+        match &self.contents {
+            Contents::Nodes(nodes) => nodes
+                .iter()
+                .map(|node| {
+                    let text = node.to_text(format_style);
+                    text.lines().map(str::trim).collect::<Vec<_>>().join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Contents::Text(text) => text.clone(),
+        }
+    }
+}
+
+/// The contents of a register once its nodes have been written as text, so that it can be kept
+/// when Sapling switches language
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SavedRegister {
+    pub name: char,
+    pub language: Language,
+    pub text: String,
+}
+
+/// Every register that something has been yanked into
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Registers<'arena, Node: Ast<'arena>> {
+    registers: BTreeMap<char, Register<'arena, Node>>,
+}
+
+impl<'arena, Node: Ast<'arena>> Registers<'arena, Node> {
+    /// Creates a set of registers which are all empty
+    pub fn new() -> Self {
+        Registers {
+            registers: BTreeMap::new(),
+        }
+    }
+
+    /// Puts `nodes` (which are from a buffer of `language`) into the register `name`, and into
+    /// the unnamed register.  Nodes yanked to the clipboard only go into the unnamed register.
+    pub fn yank(&mut self, name: char, language: Language, nodes: Vec<&'arena Node>) {
+        let register = Register {
+            language,
+            contents: Contents::Nodes(nodes),
+        };
+        if name != UNNAMED && name != CLIPBOARD {
+            self.registers.insert(name, register.clone());
+        }
+        self.registers.insert(UNNAMED, register);
+    }
+
+    /// Returns the nodes in the register `name`, so that they can be pasted into a buffer of
+    /// `language`.  Returns an error if the register is empty, or if it holds nodes from a
+    /// different language.
+    pub fn nodes(&self, name: char, language: Language) -> Result<&[&'arena Node], EditErr> {
+        let register = match self.registers.get(&name) {
+            Some(register) => register,
+            None if name == UNNAMED => return Err(EditErr::EmptyRegister),
+            None => return Err(EditErr::EmptyNamedRegister(name)),
+        };
+        match &register.contents {
+            Contents::Nodes(nodes) if register.language == language => Ok(nodes),
+            _ => Err(EditErr::RegisterLanguage {
+                register: name,
+                language: register.language.name().to_string(),
+            }),
+        }
+    }
+
+    /// Returns every register that isn't empty, in order of their names (so the unnamed register
+    /// comes first)
+    pub fn iter(&self) -> impl Iterator<Item = (char, &Register<'arena, Node>)> {
+        self.registers
+            .iter()
+            .map(|(name, register)| (*name, register))
+    }
+
+    /// Writes the nodes of every register as text, so that they can be [`restore`]d into the
+    /// registers of a different language
+    ///
+    /// [`restore`]: Registers::restore
+    pub fn save(&self, format_style: &Node::FormatStyle) -> Vec<SavedRegister> {
+        self.iter()
+            .map(|(name, register)| SavedRegister {
+                name,
+                language: register.language,
+                text: register.text(format_style),
+            })
+            .collect()
+    }
+
+    /// Fills these registers with registers that were [`save`](Registers::save)d by an editor of
+    /// a different language
+    pub fn restore(&mut self, saved: Vec<SavedRegister>) {
+        for register in saved {
+            self.registers.insert(
+                register.name,
+                Register {
+                    language: register.language,
+                    contents: Contents::Text(register.text),
+                },
+            );
+        }
+    }
+}
+
+impl<'arena, Node: Ast<'arena>> Default for Registers<'arena, Node> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_register_name, Registers, UNNAMED};
+    use crate::arena::Arena;
+    use crate::ast::json::{Dialect, JSONFormat, JSON};
+    use crate::ast::Ast;
+    use crate::editable_tree::EditErr;
+    use crate::language::Language;
+
+    #[test]
+    fn registers() {
+        let arena = Arena::new();
+        let root = JSON::parse("[1, [true, null]]", &arena).unwrap();
+        let json = Language::JSON(Dialect::JSON);
+        let mut registers = Registers::new();
+        assert!(is_register_name('a') && is_register_name(UNNAMED));
+        assert!(!is_register_name('A') && !is_register_name('1'));
+        assert_eq!(registers.nodes(UNNAMED, json), Err(EditErr::EmptyRegister));
+        assert_eq!(
+            registers.nodes('a', json),
+            Err(EditErr::EmptyNamedRegister('a'))
+        );
+
+        // Yanking into a named register also fills the unnamed register
+        registers.yank('a', json, root.children().to_vec());
+        registers.yank(UNNAMED, json, vec![root.children()[0]]);
+        assert_eq!(registers.nodes('a', json).unwrap(), root.children());
+        assert_eq!(
+            registers.nodes(UNNAMED, json).unwrap(),
+            &root.children()[..1]
+        );
+        let texts: Vec<_> = registers
+            .iter()
+            .map(|(name, register)| (name, register.text(&JSONFormat::Compact)))
+            .collect();
+        let expected = vec![
+            (UNNAMED, "1".to_string()),
+            ('a', "1 [true,null]".to_string()),
+        ];
+        assert_eq!(texts, expected);
+
+        // Registers from another language can be listed, but not pasted
+        let mut restored = Registers::<JSON>::new();
+        restored.restore(registers.save(&JSONFormat::Compact));
+        let error = EditErr::RegisterLanguage {
+            register: 'a',
+            language: "json".to_string(),
+        };
+        assert_eq!(restored.nodes('a', Language::Expr), Err(error));
+        assert_eq!(restored.iter().count(), 2);
+    }
+}
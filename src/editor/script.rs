@@ -0,0 +1,427 @@
+//! Scripts of keystrokes, which are run without a terminal by `--script` (see
+//! [`Editor::run_script`](super::Editor::run_script)).
+//!
+//! A script is written like the keys of a Vim mapping: every char is the key that types it,
+//! apart from special keys, which are written between `<` and `>` (e.g. `<Esc>`, `<CR>`, `<BS>`
+//! or `<C-r>`).  A `<` that can't be typed on its own is written as `<lt>`.  The same notation
+//! is used for the keys in the `[keys]` table of the config file, and for `:map`.
+
+use std::str::FromStr;
+use tuikit::prelude::Key;
+
+/// The names of the special keys that can be written between `<` and `>` (which are matched
+/// ignoring case), other than the `<C-x>` and `<A-x>` modifiers.  Keys with more than one name
+/// are written with their first name.
+const KEY_NAMES: [(&str, Key); 27] = [
+    ("Esc", Key::ESC),
+    ("CR", Key::Enter),
+    ("Enter", Key::Enter),
+    ("Return", Key::Enter),
+    ("BS", Key::Backspace),
+    ("Tab", Key::Tab),
+    ("Del", Key::Delete),
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("Insert", Key::Insert),
+    ("S-Tab", Key::BackTab),
+    ("S-Up", Key::ShiftUp),
+    ("S-Down", Key::ShiftDown),
+    ("S-Left", Key::ShiftLeft),
+    ("S-Right", Key::ShiftRight),
+    ("C-Up", Key::CtrlUp),
+    ("C-Down", Key::CtrlDown),
+    ("C-Left", Key::CtrlLeft),
+    ("C-Right", Key::CtrlRight),
+    ("Space", Key::Char(' ')),
+    ("lt", Key::Char('<')),
+];
+
+/// Returns the key written as `<name>` in a script, if there is one
+pub fn special_key(name: &str) -> Option<Key> {
+    if let Some((_, key)) = KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        return Some(*key);
+    }
+    // Function keys are written `<F1>`, `<F2>` and so on
+    if let Some(number) = name.strip_prefix(|c| c == 'F' || c == 'f') {
+        if number.starts_with(|c: char| c.is_ascii_digit()) {
+            return number.parse().ok().filter(|&n| n > 0).map(Key::F);
+        }
+    }
+    // Modifiers can only be applied to a single char, which keeps its case (so `<A-J>` isn't
+    // `<A-j>`)
+    let (modifier, rest) = name.split_at(name.find('-')? + 1);
+    let mut chars = rest.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    match modifier.to_lowercase().as_str() {
+        "c-" => Some(Key::Ctrl(c.to_ascii_lowercase())),
+        "a-" | "m-" => Some(Key::Alt(c)),
+        _ => None,
+    }
+}
+
+/// Returns how a script would write `key` (keys that scripts can't contain are written with
+/// the name that `tuikit` gives them, e.g. `<AltShiftUp>`)
+pub fn key_notation(key: Key) -> String {
+    match key {
+        Key::Char('<') => "<lt>".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{}>", c),
+        Key::Alt(c) => format!("<A-{}>", c),
+        Key::F(n) => format!("<F{}>", n),
+        key => match KEY_NAMES.iter().find(|(_, k)| *k == key) {
+            Some((name, _)) => format!("<{}>", name),
+            None => format!("<{:?}>", key),
+        },
+    }
+}
+
+/// A single key, which is written in the notation of a script.  This converts to and from the
+/// [`Key`]s that `tuikit` reads from the terminal.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct KeyPress(pub Key);
+
+impl KeyPress {
+    /// Returns the `tuikit` key that this stands for
+    pub fn key(self) -> Key {
+        self.0
+    }
+
+    /// Parses a sequence of keys (e.g. `3x<Esc>:w<CR>`), returning an error at the first `<`
+    /// which doesn't start the name of a special key
+    pub fn parse_sequence(text: &str) -> Result<Vec<KeyPress>, ScriptError> {
+        let script = Script::parse(text)?;
+        Ok(script
+            .keys
+            .into_iter()
+            .map(|(_, key)| KeyPress(key))
+            .collect())
+    }
+}
+
+impl From<Key> for KeyPress {
+    fn from(key: Key) -> Self {
+        KeyPress(key)
+    }
+}
+
+impl From<KeyPress> for Key {
+    fn from(key: KeyPress) -> Self {
+        key.0
+    }
+}
+
+impl std::fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&key_notation(self.0))
+    }
+}
+
+impl FromStr for KeyPress {
+    type Err = ScriptError;
+
+    /// Parses the notation of exactly one key
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let script = Script::parse(text)?;
+        match script.keys() {
+            [(_, key)] => Ok(KeyPress(*key)),
+            [] => Err(script.error(script.len(), "expected a key".to_string())),
+            [_, (position, _), ..] => {
+                Err(script.error(*position, "expected only one key".to_string()))
+            }
+        }
+    }
+}
+
+/// What a script outputs once it has finished
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ScriptOutput {
+    /// The text of the buffer being edited
+    Text,
+    /// A JSON Patch of the changes to the buffer being edited (with `--emit-patch`)
+    Patch,
+}
+
+/// A script of keys that has been parsed, along with where each key was written
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Script {
+    /// The text of the script, as it was written
+    text: String,
+    /// Every key of the script, along with the position (in chars) where it was written
+    keys: Vec<(usize, Key)>,
+}
+
+impl Script {
+    /// Parses the text of a script, returning an error at the first `<` which doesn't start the
+    /// name of a special key
+    pub fn parse(text: &str) -> Result<Script, ScriptError> {
+        let mut script = Script {
+            text: text.to_string(),
+            keys: Vec::new(),
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let mut position = 0;
+        while position < chars.len() {
+            let c = chars[position];
+            if c != '<' {
+                script.keys.push((position, Key::Char(c)));
+                position += 1;
+                continue;
+            }
+            let name_len = match chars[position + 1..].iter().position(|&c| c == '>') {
+                Some(len) => len,
+                None => {
+                    let message = "unknown special key, since there's no '>' to end it (a '<' \
+                                   on its own is written '<lt>')";
+                    return Err(script.error(position, message.to_string()));
+                }
+            };
+            let name: String = chars[position + 1..position + 1 + name_len]
+                .iter()
+                .collect();
+            match special_key(&name) {
+                Some(key) => {
+                    script.keys.push((position, key));
+                    position += name_len + 2;
+                }
+                None => {
+                    let message = "unknown special key (a '<' on its own is written '<lt>')";
+                    return Err(script.error(position, message.to_string()));
+                }
+            }
+        }
+        Ok(script)
+    }
+
+    /// Returns every key of the script, along with the position (in chars) that it starts at
+    pub fn keys(&self) -> &[(usize, Key)] {
+        &self.keys
+    }
+
+    /// Returns the length of the script's text, in chars.  This is the position of the end of
+    /// the script.
+    pub fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    /// Returns `true` if the script doesn't contain any keys
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Creates an error at a given position of the script
+    pub fn error(&self, position: usize, message: String) -> ScriptError {
+        // The key at the position, as it was written in the script
+        let key = if position >= self.len() {
+            None
+        } else {
+            let rest: String = self.text.chars().skip(position).collect();
+            let len = match rest.starts_with('<') {
+                true => rest.find('>').map_or(1, |i| i + 1),
+                false => rest.chars().next().map_or(0, char::len_utf8),
+            };
+            Some(rest[..len].to_string())
+        };
+        ScriptError {
+            position,
+            key,
+            message,
+        }
+    }
+}
+
+/// The reason that a script failed, along with where it failed
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ScriptError {
+    /// The position (in chars) of the key that failed
+    pub position: usize,
+    /// The key that failed, as it was written in the script.  This is `None` if the script
+    /// failed because it ended too early.
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.key {
+            Some(key) => write!(
+                f,
+                "Script failed at position {} ('{}'): {}",
+                self.position, key, self.message
+            ),
+            None => write!(
+                f,
+                "Script failed at the end (position {}): {}",
+                self.position, self.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{key_notation, KeyPress, Script, KEY_NAMES};
+    use tuikit::prelude::Key;
+
+    #[test]
+    fn parse_keys() {
+        for (text, expected_keys) in &[
+            ("", vec![]),
+            ("jx", vec![(0, Key::Char('j')), (1, Key::Char('x'))]),
+            (
+                "ia<Esc>",
+                vec![(0, Key::Char('i')), (1, Key::Char('a')), (2, Key::ESC)],
+            ),
+            (
+                ":w<CR>u<c-R>",
+                vec![
+                    (0, Key::Char(':')),
+                    (1, Key::Char('w')),
+                    (2, Key::Enter),
+                    (6, Key::Char('u')),
+                    (7, Key::Ctrl('r')),
+                ],
+            ),
+            ("<A-j><A-J>", vec![(0, Key::Alt('j')), (5, Key::Alt('J'))]),
+            ("<lt><BS>", vec![(0, Key::Char('<')), (4, Key::Backspace)]),
+            ("é>", vec![(0, Key::Char('é')), (1, Key::Char('>'))]),
+        ] {
+            let script = Script::parse(text).unwrap();
+            assert_eq!(script.keys(), expected_keys.as_slice(), "{}", text);
+            assert_eq!(script.is_empty(), expected_keys.is_empty());
+        }
+    }
+
+    #[test]
+    fn notation() {
+        for (key, expected) in &[
+            (Key::Char('x'), "x"),
+            (Key::Char(' '), " "),
+            (Key::Char('<'), "<lt>"),
+            (Key::ESC, "<Esc>"),
+            (Key::Enter, "<CR>"),
+            (Key::PageDown, "<PageDown>"),
+            (Key::Ctrl('r'), "<C-r>"),
+            (Key::Alt('J'), "<A-J>"),
+            (Key::F(1), "<F1>"),
+            (Key::BackTab, "<S-Tab>"),
+            (Key::AltShiftUp, "<AltShiftUp>"),
+        ] {
+            assert_eq!(key_notation(*key), *expected);
+            // Writing a key and then parsing it gives back the same key
+            if let Ok(script) = Script::parse(expected) {
+                assert_eq!(script.keys(), &[(0, *key)]);
+            }
+        }
+    }
+
+    #[test]
+    fn key_presses() {
+        let mut keys: Vec<Key> = KEY_NAMES.iter().map(|(_, key)| *key).collect();
+        keys.extend("aZ0 é<>".chars().map(Key::Char));
+        keys.extend(&[
+            Key::Ctrl('r'),
+            Key::Alt('j'),
+            Key::Alt('J'),
+            Key::F(1),
+            Key::F(12),
+        ]);
+        // Every key that a script can contain is written in a way that parses back to it
+        for key in keys {
+            let written = KeyPress(key).to_string();
+            assert_eq!(written.parse(), Ok(KeyPress(key)), "{}", written);
+        }
+        assert_eq!("<s-tab>".parse(), Ok(KeyPress(Key::BackTab)));
+        assert_eq!("<Space>".parse(), Ok(KeyPress(Key::Char(' '))));
+        assert_eq!(Key::from(KeyPress(Key::Enter)), Key::Enter);
+        assert_eq!(KeyPress::from(Key::ESC).to_string(), "<Esc>");
+
+        assert_eq!(
+            KeyPress::parse_sequence("3x<Esc>:w<CR>"),
+            Ok(vec![
+                KeyPress(Key::Char('3')),
+                KeyPress(Key::Char('x')),
+                KeyPress(Key::ESC),
+                KeyPress(Key::Char(':')),
+                KeyPress(Key::Char('w')),
+                KeyPress(Key::Enter),
+            ])
+        );
+        for (text, expected_error) in &[
+            ("", "Script failed at the end (position 0): expected a key"),
+            (
+                "x<CR>",
+                "Script failed at position 1 ('<CR>'): expected only one key",
+            ),
+            (
+                "<F0>",
+                "Script failed at position 0 ('<F0>'): unknown special key",
+            ),
+            (
+                "<Fx>",
+                "Script failed at position 0 ('<Fx>'): unknown special key",
+            ),
+        ] {
+            let error = text.parse::<KeyPress>().unwrap_err().to_string();
+            assert!(error.starts_with(expected_error), "{}: {}", text, error);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, expected_error) in &[
+            (
+                "j<",
+                "Script failed at position 1 ('<'): unknown special key, since there's no '>'",
+            ),
+            (
+                "<Esc",
+                "Script failed at position 0 ('<'): unknown special key, since there's no '>'",
+            ),
+            (
+                "ab<Nope>",
+                "Script failed at position 2 ('<Nope>'): unknown special key",
+            ),
+            (
+                "<C-ab>",
+                "Script failed at position 0 ('<C-ab>'): unknown special key",
+            ),
+            (
+                "<>",
+                "Script failed at position 0 ('<>'): unknown special key",
+            ),
+        ] {
+            let error = Script::parse(text).unwrap_err().to_string();
+            assert!(error.starts_with(expected_error), "{}: {}", text, error);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let script = Script::parse("dé<CR>").unwrap();
+        assert_eq!(
+            script.error(1, "oops".to_string()).to_string(),
+            "Script failed at position 1 ('é'): oops"
+        );
+        assert_eq!(
+            script.error(2, "oops".to_string()).to_string(),
+            "Script failed at position 2 ('<CR>'): oops"
+        );
+        assert_eq!(
+            script
+                .error(script.len(), "too short".to_string())
+                .to_string(),
+            "Script failed at the end (position 6): too short"
+        );
+    }
+}
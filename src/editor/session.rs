@@ -0,0 +1,318 @@
+//! Saving the editor's session when Sapling quits, so that it can be restored the next time the
+//! same file is opened.  A session contains the tree of the buffer being edited, along with its
+//! whole undo history, its cursor and the folds of its window (but not any other buffers, or how
+//! the screen was split into windows).
+//!
+//! Sessions are saved as JSON to `$XDG_DATA_HOME/sapling/session.json` (or
+//! `~/.local/share/sapling/session.json`).  That one file holds a session for each of the last
+//! [`MAX_SESSIONS`] files that were edited, so quitting whilst editing one file doesn't throw
+//! away the session of another.  Only the sessions of languages whose trees can be serialized
+//! (see [`Ast::to_owned_node`](crate::ast::Ast::to_owned_node)) are saved.
+
+use super::file;
+use crate::ast::owned::{OwnedNode, OwnedTreeError};
+use crate::editable_tree::history::SavedHistory;
+use crate::language::Language;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// How many sessions are kept in the session file.  Once there are more, the sessions which
+/// were saved longest ago are thrown away.
+pub const MAX_SESSIONS: usize = 20;
+
+/// The contents of the session file: the session of every file that's been edited recently,
+/// from the least to the most recently saved.  There's at most one session per file.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub sessions: Vec<SavedSession>,
+}
+
+impl SessionFile {
+    /// Reads the sessions from the file at `path`
+    pub fn read(path: &Path) -> Result<Self, SessionError> {
+        let text = std::fs::read_to_string(path).map_err(|e| SessionError::Read(e.to_string()))?;
+        serde_json::from_str(&text).map_err(|e| SessionError::Read(e.to_string()))
+    }
+
+    /// Writes these sessions to the file at `path`, creating its directory if it doesn't exist
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let text = serde_json::to_string(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        file::write_atomically(path, &text)
+    }
+
+    /// Returns the session that can be restored into the file at `path` in `language` (see
+    /// [`SavedSession::is_for`]), if there is one
+    pub fn session_for(&self, path: &Path, language: Language) -> Option<&SavedSession> {
+        self.sessions
+            .iter()
+            .rev()
+            .find(|session| session.is_for(path, language))
+    }
+
+    /// Adds `session` as the most recently saved session, replacing any older session of the
+    /// same file and throwing away the oldest sessions if there are more than [`MAX_SESSIONS`]
+    pub fn insert(&mut self, session: SavedSession) {
+        self.sessions
+            .retain(|saved| saved.file_path != session.file_path);
+        self.sessions.push(session);
+        let excess = self.sessions.len().saturating_sub(MAX_SESSIONS);
+        self.sessions.drain(..excess);
+    }
+}
+
+/// Everything that's saved about a session.  The trees of the history (along with the roots
+/// that were loaded and saved) are written as one list of nodes, so that the nodes they share
+/// are only written once.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SavedSession {
+    /// The [`name`](Language::name) of the buffer's language
+    pub language: String,
+    /// The (canonical) path of the buffer's file
+    pub file_path: PathBuf,
+    /// The [`file_hash`] of the file's contents when the session was saved, so that the session
+    /// isn't restored if the file has been changed since
+    pub file_hash: String,
+    /// Where the cursor was in the current tree of the history
+    pub cursor_path: Vec<usize>,
+    /// The paths of the folded nodes in the current tree
+    pub folds: Vec<Vec<usize>>,
+    /// The index of the tree that was last loaded or saved, unless there were unsaved changes
+    /// from before the file was loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_root: Option<usize>,
+    /// The index of the tree that was first loaded
+    pub loaded_root: usize,
+    pub history: SavedHistory,
+    pub nodes: Vec<OwnedNode>,
+}
+
+impl SavedSession {
+    /// Returns `true` if this session was saved whilst editing the file at `path` in `language`,
+    /// and the file hasn't changed since.  A session with no undo history at all (which Sapling
+    /// never saves, so the session file must be corrupt) isn't for any file.
+    pub fn is_for(&self, path: &Path, language: Language) -> bool {
+        if self.history.snapshots.is_empty() {
+            return false;
+        }
+        let same_file = path.canonicalize().is_ok_and(|path| path == self.file_path);
+        let unchanged =
+            || std::fs::read_to_string(path).is_ok_and(|text| file_hash(&text) == self.file_hash);
+        self.language == language.name() && same_file && unchanged()
+    }
+
+    /// Asks the user whether or not to restore this session, by writing a question to `output`
+    /// and reading the answer from `input`.  An empty answer (or no answer at all) means yes.
+    pub fn prompt(
+        &self,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> std::io::Result<bool> {
+        let changes = self.history.snapshots.len().saturating_sub(1);
+        let plural = if changes == 1 { "" } else { "s" };
+        write!(
+            output,
+            "Restore the last session of {} ({} change{} in its undo history)? [Y/n] ",
+            self.file_path.to_string_lossy(),
+            changes,
+            plural
+        )?;
+        output.flush()?;
+        let mut answer = String::new();
+        input.read_line(&mut answer)?;
+        Ok(!answer.trim().to_lowercase().starts_with('n'))
+    }
+}
+
+/// The reasons that a session can't be restored
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SessionError {
+    /// The session file couldn't be read or parsed
+    Read(String),
+    /// The session was saved for a different file or language to the buffer it's restored into
+    WrongBuffer,
+    /// The trees of the session aren't valid trees
+    InvalidTree(OwnedTreeError),
+    /// The history or cursor of the session don't fit its trees
+    InvalidHistory,
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Read(message) => write!(f, "{}", message),
+            SessionError::WrongBuffer => write!(f, "the session is for a different file"),
+            SessionError::InvalidTree(e) => write!(f, "{}", e),
+            SessionError::InvalidHistory => write!(f, "the undo history is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<OwnedTreeError> for SessionError {
+    fn from(e: OwnedTreeError) -> Self {
+        SessionError::InvalidTree(e)
+    }
+}
+
+/// Returns the path that sessions are saved to: `$XDG_DATA_HOME/sapling/session.json` (or
+/// `~/.local/share/sapling/session.json` if `$XDG_DATA_HOME` isn't set).  Returns `None` if
+/// neither variable is set.
+pub fn session_path() -> Option<PathBuf> {
+    let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_dir.join("sapling").join("session.json"))
+}
+
+/// Returns a hash of `text` which is the same every time Sapling runs (unlike the hashes of the
+/// standard library, which can change between versions of Rust).  This is [`file::fnv1a`],
+/// written as hex.
+pub fn file_hash(text: &str) -> String {
+    hash_to_hex(file::fnv1a(text.as_bytes()))
+}
+
+/// Writes a [`file::fnv1a`] hash as hex, in the same way as [`file_hash`]
+pub fn hash_to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_hash, SavedSession, SessionFile, MAX_SESSIONS};
+    use crate::ast::json::Dialect;
+    use crate::ast::owned::OwnedNode;
+    use crate::editable_tree::history::{SavedHistory, SavedSnapshot};
+    use crate::language::Language;
+
+    #[test]
+    fn hashes() {
+        // The test vectors of FNV-1a
+        assert_eq!(file_hash(""), "cbf29ce484222325");
+        assert_eq!(file_hash("a"), "af63dc4c8601ec8c");
+        assert_eq!(file_hash("foobar"), "85944171f73967e8");
+    }
+
+    #[test]
+    fn prompt() {
+        let snapshot = |parent| SavedSnapshot {
+            root: 0,
+            cursor_path: vec![],
+            parent,
+            redo_child: None,
+        };
+        let session = SavedSession {
+            language: "json".to_string(),
+            file_path: "/data.json".into(),
+            file_hash: file_hash("null"),
+            cursor_path: vec![],
+            folds: vec![],
+            saved_root: Some(0),
+            loaded_root: 0,
+            history: SavedHistory {
+                current: 1,
+                snapshots: vec![snapshot(None), snapshot(Some(0))],
+            },
+            nodes: vec![OwnedNode::new("null", None)],
+        };
+        let ask = |answer: &str| {
+            let mut output = Vec::new();
+            let restore = session.prompt(&mut answer.as_bytes(), &mut output).unwrap();
+            (restore, String::from_utf8(output).unwrap())
+        };
+        let question =
+            "Restore the last session of /data.json (1 change in its undo history)? [Y/n] ";
+        assert_eq!(ask("\n"), (true, question.to_string()));
+        assert_eq!(ask(""), (true, question.to_string()));
+        assert!(ask("y\n").0);
+        assert!(!ask("No\n").0);
+        // Sessions can be written as JSON and read back
+        let text = serde_json::to_string(&session).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SavedSession>(&text).unwrap(),
+            session
+        );
+    }
+
+    #[test]
+    fn empty_history() {
+        // A corrupt session file can have no snapshots at all, which can't be restored
+        let dir = std::env::temp_dir().join(format!("sapling-test-empty-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        std::fs::write(&path, "null").unwrap();
+        let mut session = SavedSession {
+            language: "json".to_string(),
+            file_path: path.canonicalize().unwrap(),
+            file_hash: file_hash("null"),
+            cursor_path: vec![],
+            folds: vec![],
+            saved_root: Some(0),
+            loaded_root: 0,
+            history: SavedHistory {
+                current: 0,
+                snapshots: vec![SavedSnapshot {
+                    root: 0,
+                    cursor_path: vec![],
+                    parent: None,
+                    redo_child: None,
+                }],
+            },
+            nodes: vec![OwnedNode::new("null", None)],
+        };
+        let json = Language::JSON(Dialect::JSON);
+        assert!(session.is_for(&path, json));
+        session.history.snapshots.clear();
+        assert!(!session.is_for(&path, json));
+        let sessions = SessionFile {
+            sessions: vec![session.clone()],
+        };
+        assert_eq!(sessions.session_for(&path, json), None);
+        // Asking about it anyway doesn't panic
+        let mut output = Vec::new();
+        assert!(session.prompt(&mut "n\n".as_bytes(), &mut output).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn session_file() {
+        let session = |file: &str, hash: &str| SavedSession {
+            language: "json".to_string(),
+            file_path: file.into(),
+            file_hash: hash.to_string(),
+            cursor_path: vec![],
+            folds: vec![],
+            saved_root: Some(0),
+            loaded_root: 0,
+            history: SavedHistory {
+                current: 0,
+                snapshots: vec![SavedSnapshot {
+                    root: 0,
+                    cursor_path: vec![],
+                    parent: None,
+                    redo_child: None,
+                }],
+            },
+            nodes: vec![OwnedNode::new("null", None)],
+        };
+        let mut sessions = SessionFile::default();
+        sessions.insert(session("/a.json", "1"));
+        sessions.insert(session("/b.json", "2"));
+        // Saving a file's session again replaces its old session, and makes it the newest
+        sessions.insert(session("/a.json", "3"));
+        let hashes: Vec<_> = sessions.sessions.iter().map(|s| &s.file_hash).collect();
+        assert_eq!(hashes, vec!["2", "3"]);
+        // Only the newest sessions are kept
+        for i in 0..MAX_SESSIONS {
+            sessions.insert(session(&format!("/{}.json", i), "4"));
+        }
+        assert_eq!(sessions.sessions.len(), MAX_SESSIONS);
+        assert_eq!(sessions.sessions[0].file_path.to_str(), Some("/0.json"));
+    }
+}
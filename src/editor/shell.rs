@@ -0,0 +1,189 @@
+//! Dropping out of the editor to the shell, either to suspend Sapling (`Ctrl-Z`) or to run a
+//! shell command (`:!<cmd>`).  Both give the terminal back with an [`OutsideTui`] guard, which
+//! takes the terminal back over (and leaves the editor to redraw it) once it's dropped.  `:pipe`
+//! also runs shell commands, but without the terminal (see [`pipe`]).
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use tuikit::prelude::Term;
+
+/// The most chars of what a failed command printed to stderr that are shown in the bottom bar
+pub const MAX_ERROR_CHARS: usize = 120;
+
+/// Gives the terminal back to the shell (leaving raw mode and the alternate screen) whilst it
+/// exists, and takes it back over when it's dropped.  The screen then needs to be drawn again
+/// from scratch, since whatever ran in the meantime may have drawn over it or resized it.
+pub struct OutsideTui<'term> {
+    term: &'term Term,
+}
+
+impl<'term> OutsideTui<'term> {
+    /// Gives the terminal back to the shell, returning why not if it can't be
+    pub fn leave(term: &'term Term) -> Result<Self, String> {
+        term.pause().map_err(|e| e.to_string())?;
+        Ok(OutsideTui { term })
+    }
+}
+
+impl Drop for OutsideTui<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.term.restart() {
+            log::error!("Couldn't take the terminal back over: {}", e);
+        }
+    }
+}
+
+/// Stops Sapling with `SIGTSTP` (just as if `Ctrl-Z` had been typed into a terminal that isn't
+/// in raw mode), and returns once the shell carries it on with `SIGCONT`
+pub fn suspend() -> Result<(), String> {
+    // SAFETY: `SIGTSTP` isn't caught, so raising it only stops the process until `SIGCONT`
+    if unsafe { libc::raise(libc::SIGTSTP) } == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "couldn't raise SIGTSTP: {}",
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+/// Runs `command` with `sh`, attached to the terminal, and then waits for Enter to be pressed
+/// so that its output can be read before the editor is drawn over it
+pub fn run_attached(command: &str) -> Result<ExitStatus, String> {
+    let status = shell(command)
+        .status()
+        .map_err(|e| format!("couldn't run sh: {}", e))?;
+    print!("\nPress Enter to return to Sapling");
+    let _ = std::io::stdout().flush();
+    // Standard input may have been the file being edited, so the key is read from the terminal
+    if let Ok(tty) = std::fs::File::open("/dev/tty") {
+        let _ = BufReader::new(tty).read_line(&mut String::new());
+    }
+    Ok(status)
+}
+
+/// Runs `command` with `sh` without a terminal (e.g. whilst running a script), throwing away
+/// what it prints
+pub fn run_detached(command: &str) -> Result<ExitStatus, String> {
+    shell(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("couldn't run sh: {}", e))
+}
+
+/// Runs `command` with `sh` without a terminal, writing `input` to its stdin, and returns what
+/// it prints to stdout.  If it fails, the reason (with the start of what it printed to stderr)
+/// is returned instead.
+pub fn pipe(command: &str, input: &str) -> Result<String, String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't run sh: {}", e))?;
+    // The input is written from another thread, so that a command which prints a lot before it
+    // has read everything can't leave both sides waiting for each other
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    // A command that doesn't read all of its input (e.g. `echo`) is fine, so write errors are
+    // ignored
+    let _ = writer.join();
+    let status = output.status;
+    if !status.success() {
+        let reason = match status.code() {
+            Some(code) => format!("it exited with status {}", code),
+            None => "it was killed by a signal".to_string(),
+        };
+        return Err(match shorten(&String::from_utf8_lossy(&output.stderr)) {
+            error if error.is_empty() => reason,
+            error => format!("{}: {}", reason, error),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| "its output isn't UTF-8".to_string())
+}
+
+/// Joins the lines of what a command printed to stderr, and cuts it short to
+/// [`MAX_ERROR_CHARS`] so that it fits in the bottom bar
+fn shorten(stderr: &str) -> String {
+    let text = stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.chars().count() <= MAX_ERROR_CHARS {
+        return text;
+    }
+    let mut short: String = text.chars().take(MAX_ERROR_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Returns a [`Command`] that runs `command` with `sh`
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Puts the path of the file being edited in place of every `%` in a shell command (like Vim
+/// does), so that `:!git diff %` compares the file with the last commit.  `\%` is a `%` that
+/// stays as it is.  Returns why not if there's a `%` but no file.
+pub fn expand_file(command: &str, path: Option<&Path>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'%') => expanded.push(chars.next().unwrap()),
+            '%' => {
+                let path = path.ok_or("the buffer has no file to put in place of '%'")?;
+                expanded.push_str(&quote(&path.to_string_lossy()));
+            }
+            c => expanded.push(c),
+        }
+    }
+    Ok(expanded)
+}
+
+/// Quotes `text` so that `sh` reads it as one word, whatever it contains
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_file, pipe, shorten, MAX_ERROR_CHARS};
+    use std::path::Path;
+
+    #[test]
+    fn expand() {
+        let path = Some(Path::new("data/it's.json"));
+        assert_eq!(
+            expand_file("git diff %", path),
+            Ok("git diff 'data/it'\\''s.json'".to_string())
+        );
+        assert_eq!(
+            expand_file("echo 100\\%", None),
+            Ok("echo 100%".to_string())
+        );
+        assert!(expand_file("cat %", None).is_err());
+    }
+
+    #[test]
+    fn pipes() {
+        assert_eq!(pipe("tr a-z A-Z", "[true]"), Ok("[TRUE]".to_string()));
+        assert_eq!(
+            pipe("echo oops >&2; exit 2", ""),
+            Err("it exited with status 2: oops".to_string())
+        );
+        assert_eq!(shorten("a\n\n  b \n"), "a b");
+        let long = shorten(&"x".repeat(500));
+        assert_eq!(long.chars().count(), MAX_ERROR_CHARS);
+        assert!(long.ends_with('…'));
+    }
+}
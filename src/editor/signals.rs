@@ -0,0 +1,159 @@
+//! The signals that would otherwise kill Sapling without giving it a chance to look after the
+//! unsaved changes.  Whilst the editor is using the terminal, a [`SignalGuard`] catches them, and
+//! the mainloop handles them between keys (see [`Editor::handle_signal`](super::Editor)):
+//! `SIGINT` is treated like `:q` (so it refuses to quit whilst there are unsaved changes), and
+//! `SIGHUP` and `SIGTERM` stop the editor as if the terminal had gone away, keeping the unsaved
+//! changes to be recovered.  Whilst the terminal is in raw mode, `Ctrl-C` is read as a key rather
+//! than sending `SIGINT`, so `SIGINT` only comes from other programs (e.g. `kill -INT`).
+//! `SIGCONT` doesn't stop anything, but it's caught so that the screen can be drawn again once
+//! Sapling carries on after being stopped.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The signals that have been caught but not handled yet, with one bit per signal (in the order
+/// of [`Signal::ALL`])
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+/// A signal that Sapling catches whilst a [`SignalGuard`] exists
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Signal {
+    /// `SIGHUP`: the terminal was closed
+    HangUp,
+    /// `SIGINT`: the user wants Sapling to stop
+    Interrupt,
+    /// `SIGTERM`: Sapling is being shut down (e.g. by `kill`)
+    Terminate,
+    /// `SIGCONT`: Sapling was stopped (e.g. by `Ctrl-Z`), and has now been carried on
+    Continue,
+}
+
+impl Signal {
+    /// Every signal that is caught
+    const ALL: [Signal; 4] = [
+        Signal::HangUp,
+        Signal::Interrupt,
+        Signal::Terminate,
+        Signal::Continue,
+    ];
+
+    /// Returns the name of this signal, as it's written in the log
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::HangUp => "SIGHUP",
+            Signal::Interrupt => "SIGINT",
+            Signal::Terminate => "SIGTERM",
+            Signal::Continue => "SIGCONT",
+        }
+    }
+
+    /// Returns the number of this signal
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::HangUp => libc::SIGHUP,
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Continue => libc::SIGCONT,
+        }
+    }
+
+    /// Returns the bit of this signal in [`PENDING`]
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Catches [`Signal`]s whilst it exists, so that they can be handled (with [`take_pending`])
+/// instead of killing Sapling.  When it's dropped, the signals go back to their default
+/// behaviour.
+#[derive(Debug)]
+pub struct SignalGuard {
+    _private: (),
+}
+
+impl SignalGuard {
+    /// Starts catching every [`Signal`]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        for signal in Signal::ALL {
+            set_handler(
+                signal,
+                remember as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        SignalGuard { _private: () }
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        for signal in Signal::ALL {
+            set_handler(signal, libc::SIG_DFL);
+        }
+    }
+}
+
+/// Returns one of the signals that have been caught but not handled yet (in the order of
+/// [`Signal::ALL`]), or `None` if there aren't any.  Once it's been returned, it counts as
+/// handled.
+pub fn take_pending() -> Option<Signal> {
+    let pending = PENDING.load(Ordering::SeqCst);
+    let signal = Signal::ALL
+        .iter()
+        .copied()
+        .find(|s| pending & s.bit() != 0)?;
+    forget(signal);
+    Some(signal)
+}
+
+/// Forgets that `signal` was caught, if it hasn't been handled yet.  For example, whilst a shell
+/// command is using the terminal, `Ctrl-C` sends `SIGINT` to Sapling as well as to the command,
+/// but it's only meant to stop the command.
+pub fn forget(signal: Signal) {
+    PENDING.fetch_and(!signal.bit(), Ordering::SeqCst);
+}
+
+/// The signal handler, which only remembers the signal (since almost nothing else is safe to do
+/// inside a signal handler)
+extern "C" fn remember(number: libc::c_int) {
+    if let Some(signal) = Signal::ALL.iter().find(|s| s.number() == number) {
+        PENDING.fetch_or(signal.bit(), Ordering::SeqCst);
+    }
+}
+
+/// Makes `handler` handle `signal`, logging why not if it can't
+fn set_handler(signal: Signal, handler: libc::sighandler_t) {
+    // SAFETY: `handler` is either `SIG_DFL` or `remember`, which only stores to an atomic
+    if unsafe { libc::signal(signal.number(), handler) } == libc::SIG_ERR {
+        log::warn!(
+            "Couldn't set the handler of {}: {}",
+            signal.name(),
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{forget, take_pending, Signal, SignalGuard};
+
+    #[test]
+    fn catch_signals() {
+        let guard = SignalGuard::new();
+        assert_eq!(take_pending(), None);
+        for signal in Signal::ALL {
+            // SAFETY: the guard is catching the signal, so raising it doesn't stop the tests
+            assert_eq!(unsafe { libc::raise(signal.number()) }, 0);
+            assert_eq!(take_pending(), Some(signal));
+            assert_eq!(take_pending(), None);
+        }
+        // Signals that arrive together are all handled, and they can be forgotten one at a time
+        for signal in [Signal::Continue, Signal::Interrupt, Signal::Terminate] {
+            unsafe { libc::raise(signal.number()) };
+        }
+        forget(Signal::Interrupt);
+        assert_eq!(take_pending(), Some(Signal::Terminate));
+        assert_eq!(take_pending(), Some(Signal::Continue));
+        assert_eq!(take_pending(), None);
+        drop(guard);
+    }
+}
@@ -0,0 +1,225 @@
+//! The status bar at the bottom of the screen, whose contents are set by a format string (like
+//! Vim's `statusline`).
+
+use crate::ast::size;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// The format that Sapling's status bar uses if no other format is given
+pub const DEFAULT_STATUS_FORMAT: &str = "%f %m  %p  %n  %c nodes";
+
+/// One piece of a [`StatusFormat`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum StatusItem {
+    /// Some text that is shown as-is
+    Text(String),
+    /// `%f`: the name of the file being edited
+    FileName,
+    /// `%m`: `[+]` if there are unsaved changes, along with any other flags (like whether a macro
+    /// is being recorded)
+    Flags,
+    /// `%p`: the path of the cursor
+    Path,
+    /// `%n`: the display name of the node under the cursor
+    NodeName,
+    /// `%c`: how many nodes are in the tree
+    NodeCount,
+}
+
+/// The contents of the status bar, parsed from a format string where `%f` is the file name, `%m`
+/// shows whether there are unsaved changes, `%p` is the cursor's path, `%n` is the name of the
+/// node under the cursor, `%c` is the number of nodes in the tree and `%%` is a literal `%`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct StatusFormat {
+    items: Vec<StatusItem>,
+}
+
+/// The values that can be shown in the status bar
+#[derive(Debug, Clone, Copy)]
+pub struct StatusInfo<'a> {
+    pub file_name: &'a str,
+    /// The flags like `[+]`, which are shown for `%m`
+    pub flags: &'a [String],
+    pub path: &'a CursorPath,
+    pub node_name: &'a str,
+    pub node_count: usize,
+}
+
+impl StatusFormat {
+    /// Fills in the format with some [`StatusInfo`].  Any spaces left over at the end (e.g.
+    /// because `%m` had no flags to show) are removed.
+    pub fn render(&self, info: &StatusInfo) -> String {
+        let mut text = String::new();
+        for item in &self.items {
+            match item {
+                StatusItem::Text(s) => text.push_str(s),
+                StatusItem::FileName => text.push_str(info.file_name),
+                StatusItem::Flags => text.push_str(&info.flags.join(" ")),
+                StatusItem::Path => text.push_str(&info.path.to_string()),
+                StatusItem::NodeName => text.push_str(info.node_name),
+                StatusItem::NodeCount => text.push_str(&info.node_count.to_string()),
+            }
+        }
+        text.truncate(text.trim_end().len());
+        text
+    }
+
+    /// Returns `true` if the format shows the number of nodes in the tree (which takes time
+    /// proportional to the size of the tree to count)
+    pub fn shows_node_count(&self) -> bool {
+        self.items.contains(&StatusItem::NodeCount)
+    }
+}
+
+impl Default for StatusFormat {
+    fn default() -> Self {
+        DEFAULT_STATUS_FORMAT.parse().unwrap()
+    }
+}
+
+impl std::str::FromStr for StatusFormat {
+    type Err = String;
+
+    /// Parses a format string, returning the reason why it's invalid if it can't be parsed
+    fn from_str(format: &str) -> Result<Self, String> {
+        let mut items = Vec::new();
+        let mut text = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+            let item = match chars.next() {
+                Some('%') => {
+                    text.push('%');
+                    continue;
+                }
+                Some('f') => StatusItem::FileName,
+                Some('m') => StatusItem::Flags,
+                Some('p') => StatusItem::Path,
+                Some('n') => StatusItem::NodeName,
+                Some('c') => StatusItem::NodeCount,
+                Some(c) => return Err(format!("unknown item '%{}'", c)),
+                None => return Err("'%' at the end of the format".to_string()),
+            };
+            if !text.is_empty() {
+                items.push(StatusItem::Text(std::mem::take(&mut text)));
+            }
+            items.push(item);
+        }
+        if !text.is_empty() {
+            items.push(StatusItem::Text(text));
+        }
+        Ok(StatusFormat { items })
+    }
+}
+
+/// Shortens `text` so that it fits into `width` columns, replacing the end of it with `…` if any
+/// of it had to be removed
+pub fn truncate(text: &str, width: usize) -> String {
+    if size::Size::from_str(text).last_line_length() <= width {
+        return text.to_string();
+    }
+    let mut col = 0;
+    let mut truncated = String::new();
+    for (c, (_, char_width)) in text.chars().zip(size::char_widths(text, 1)) {
+        // Leave one column for the `…`
+        if col + char_width + 1 > width {
+            break;
+        }
+        truncated.push(c);
+        col += char_width;
+    }
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// Fits the status text and a message side by side into `width` columns, with a gap of two
+/// columns between them.  The message is more important (it's usually feedback about what the
+/// user just did), so the status is shortened first.  Returns the text of the status and the
+/// message, along with the column that the message starts on.
+pub fn fit(status: &str, message: &str, width: usize) -> (String, usize, String) {
+    let message = truncate(message, width);
+    let message_width = size::Size::from_str(&message).last_line_length();
+    let status_width = width.saturating_sub(message_width + 2);
+    let status = if status_width == 0 {
+        String::new()
+    } else {
+        truncate(status, status_width)
+    };
+    let message_col = match size::Size::from_str(&status).last_line_length() {
+        0 => 0,
+        w => w + 2,
+    };
+    (status, message_col, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fit, truncate, StatusFormat, StatusInfo};
+    use crate::editable_tree::cursor_path::CursorPath;
+
+    #[test]
+    fn formatting() {
+        let path = CursorPath::from_vec(vec![0, 2]);
+        let info = StatusInfo {
+            file_name: "data.json",
+            flags: &["[+]".to_string()],
+            path: &path,
+            node_name: "object",
+            node_count: 42,
+        };
+        let render = |format: &str| format.parse::<StatusFormat>().unwrap().render(&info);
+        assert_eq!(
+            StatusFormat::default().render(&info),
+            "data.json [+]  0.2  object  42 nodes"
+        );
+        assert_eq!(render("%f %m  %p  %n"), "data.json [+]  0.2  object");
+        assert_eq!(render("%n at %p (100%%)"), "object at 0.2 (100%)");
+        assert_eq!(render(""), "");
+        // Trailing spaces are removed if there are no flags
+        let info = StatusInfo { flags: &[], ..info };
+        let format: StatusFormat = "%f %m".parse().unwrap();
+        assert_eq!(format.render(&info), "data.json");
+        assert!(!format.shows_node_count());
+        assert!(StatusFormat::default().shows_node_count());
+
+        assert_eq!(
+            "%f %x".parse::<StatusFormat>(),
+            Err("unknown item '%x'".to_string())
+        );
+        assert!("50%".parse::<StatusFormat>().is_err());
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncate("data.json", 20), "data.json");
+        assert_eq!(truncate("data.json", 9), "data.json");
+        assert_eq!(truncate("data.json", 8), "data.js…");
+        assert_eq!(truncate("data.json", 1), "…");
+        assert_eq!(truncate("data.json", 0), "");
+        // Double-width chars are never split
+        assert_eq!(truncate("日本語", 5), "日本…");
+        assert_eq!(truncate("日本語", 4), "日…");
+
+        assert_eq!(
+            fit("data.json [+]", "Saved", 80),
+            ("data.json [+]".to_string(), 15, "Saved".to_string())
+        );
+        // The status is shortened before the message
+        assert_eq!(
+            fit("data.json [+]", "Saved", 12),
+            ("data…".to_string(), 7, "Saved".to_string())
+        );
+        assert_eq!(
+            fit("data.json [+]", "Can't delete the root.", 10),
+            (String::new(), 0, "Can't del…".to_string())
+        );
+        assert_eq!(
+            fit("", "Saved", 80),
+            (String::new(), 0, "Saved".to_string())
+        );
+    }
+}
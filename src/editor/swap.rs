@@ -0,0 +1,720 @@
+//! Swap files, which let Sapling recover the unsaved changes to a file if it crashes (or is
+//! killed) whilst the file is being edited.
+//!
+//! Whilst a buffer has unsaved changes, every change to its tree is appended to a journal next
+//! to its file (`<file>.sapling-swp`, like Vim's swap files).  The swap file is deleted once the
+//! changes are saved or Sapling quits normally, so a swap file that's still there when a file is
+//! opened means that the changes to it were lost, and they can be recovered by replaying the
+//! journal (see [`SwapFile::replay`]).
+//!
+//! A swap file is a list of frames, each of which is the length (4 bytes) and the
+//! [`fnv1a`](file::fnv1a) hash (8 bytes) of its contents, little-endian, followed by those
+//! contents as TOML.  The first frame is a header, and every frame after that is one change to
+//! the tree.  A change only contains the nodes that it created, along with which nodes of the
+//! previous tree it reused (so a change to a huge tree is still small), apart from every
+//! [`SNAPSHOT_INTERVAL`]th change which contains the whole tree.  If Sapling dies whilst a frame
+//! is being written, the frame is cut short and its hash no longer matches, so that frame (and
+//! anything after it) is dropped when the swap file is read.
+//!
+//! Writing a frame is one `write` call, but the file is only synced to the disk when the last
+//! sync was more than [`SYNC_INTERVAL`] ago, so that typing quickly doesn't wait for the disk on
+//! every key.
+
+use super::file;
+use crate::arena::Arena;
+use crate::ast::owned::{OwnedNode, OwnedReader, OwnedTreeError, OwnedWriter};
+use crate::ast::Ast;
+use crate::editable_tree::compact::Compactor;
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::history::EditHistory;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The version of the format of swap files, which is checked when they're read
+const VERSION: u32 = 1;
+/// The number of bytes before the contents of every frame
+const FRAME_HEADER_LEN: usize = 12;
+/// The longest that a change waits before it's synced to the disk
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+/// How often a change is written as a snapshot of the whole tree, so that the swap file can be
+/// recovered (from its last snapshot) even if the file it belongs to has been changed
+pub const SNAPSHOT_INTERVAL: usize = 100;
+
+/// Returns the path of the swap file of the file at `path`
+pub fn swap_path(path: &Path) -> PathBuf {
+    let mut swap_path = path.as_os_str().to_owned();
+    swap_path.push(".sapling-swp");
+    PathBuf::from(swap_path)
+}
+
+/// The first frame of a swap file
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    /// The [`file_hash`](super::session::file_hash) of the text whose tree the first change
+    /// was made to, or `None` if the first change is a snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_hash: Option<String>,
+}
+
+/// Consecutive children of a node of the previous tree, which a [`Change`] reuses
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+struct Reused {
+    /// The path of the node in the previous tree
+    parent: Vec<usize>,
+    start: usize,
+    len: usize,
+}
+
+/// A node created by a [`Change`].  Its children are written as `[start, len]` runs of
+/// consecutive indices, so a node which reuses most of the children of a huge array is small.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+struct ChangeNode {
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<(usize, usize)>,
+}
+
+impl ChangeNode {
+    fn new(owned: OwnedNode) -> Self {
+        let mut children: Vec<(usize, usize)> = Vec::new();
+        for index in owned.children {
+            match children.last_mut() {
+                Some((start, len)) if *start + *len == index => *len += 1,
+                _ => children.push((index, 1)),
+            }
+        }
+        ChangeNode {
+            kind: owned.kind,
+            text: owned.text,
+            children,
+        }
+    }
+
+    /// Returns this node as an [`OwnedNode`], or `None` if it has children past `node_count`
+    fn to_owned_node(&self, node_count: usize) -> Option<OwnedNode> {
+        let mut owned = OwnedNode::new(&self.kind, self.text.clone());
+        for &(start, len) in &self.children {
+            if start.checked_add(len)? > node_count {
+                return None;
+            }
+            owned.children.extend(start..start + len);
+        }
+        Some(owned)
+    }
+}
+
+/// One change to the tree, which is one frame of the swap file.  The nodes are indexed with the
+/// reused nodes first (in order), and then the new nodes in `nodes`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+struct Change {
+    /// Where the cursor was after the change
+    cursor_path: Vec<usize>,
+    /// The index of the new root
+    root: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reused: Vec<Reused>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    nodes: Vec<ChangeNode>,
+}
+
+impl Change {
+    /// Creates the `Change` from the tree under `old` to the tree under `new`.  If there isn't
+    /// an `old` tree, then the change is a snapshot of the whole of `new`.
+    fn new<'arena, Node: Ast<'arena>>(
+        old: Option<&'arena Node>,
+        new: &'arena Node,
+        cursor_path: &CursorPath,
+    ) -> Result<Self, OwnedTreeError> {
+        let (reused, existing) = match old {
+            Some(old) => reused_nodes(old, new),
+            None => (Vec::new(), Vec::new()),
+        };
+        let mut writer = OwnedWriter::with_existing(&existing);
+        let root = writer.add(new)?;
+        Ok(Change {
+            cursor_path: cursor_path.iter().copied().collect(),
+            root,
+            reused,
+            nodes: writer.finish().into_iter().map(ChangeNode::new).collect(),
+        })
+    }
+
+    /// Returns `true` if this change doesn't depend on the tree before it
+    fn is_snapshot(&self) -> bool {
+        self.reused.is_empty()
+    }
+
+    /// Makes this change to the tree under `previous`, returning the new root and cursor
+    fn apply<'arena, Node: Ast<'arena>>(
+        &self,
+        previous: Option<&'arena Node>,
+        arena: &'arena Arena<Node>,
+    ) -> Option<(&'arena Node, CursorPath)> {
+        let mut existing = Vec::new();
+        for run in &self.reused {
+            let path = CursorPath::from_vec(run.parent.clone());
+            let (parent, depth) = path.resolve_longest(previous?);
+            if depth != path.depth() {
+                return None;
+            }
+            let end = run.start.checked_add(run.len)?;
+            existing.extend_from_slice(parent.children().get(run.start..end)?);
+        }
+        let node_count = existing.len() + self.nodes.len();
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| node.to_owned_node(node_count))
+            .collect::<Option<Vec<_>>>()?;
+        let root = OwnedReader::with_existing(existing, &nodes, arena)
+            .and_then(|reader| reader.get(self.root))
+            .ok()?;
+        let cursor_path = CursorPath::from_vec(self.cursor_path.clone());
+        if cursor_path.resolve_longest(root).1 != cursor_path.depth() {
+            return None;
+        }
+        Some((root, cursor_path))
+    }
+}
+
+/// Finds the nodes of the tree under `old` that the tree under `new` reuses, returning them
+/// (along with the runs of children that they are) in the order that they were found.  Only the
+/// nodes that `new` has copied are searched, so this takes time proportional to the size of
+/// those nodes rather than the size of the tree.
+fn reused_nodes<'arena, Node: Ast<'arena>>(
+    old: &'arena Node,
+    new: &'arena Node,
+) -> (Vec<Reused>, Vec<&'arena Node>) {
+    let mut runs: Vec<Reused> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut found = HashSet::new();
+    let mut stack = vec![(old, Vec::new(), new)];
+    while let Some((old, path, new)) = stack.pop() {
+        let old_children: HashMap<*const Node, usize> = old
+            .children()
+            .iter()
+            .enumerate()
+            .map(|(i, &child)| (child as *const Node, i))
+            .collect();
+        for (i, &child) in new.children().iter().enumerate() {
+            match old_children.get(&(child as *const Node)) {
+                Some(&index) => {
+                    if !found.insert(child as *const Node) {
+                        continue;
+                    }
+                    match runs.last_mut() {
+                        Some(run) if run.parent == path && run.start + run.len == index => {
+                            run.len += 1
+                        }
+                        _ => runs.push(Reused {
+                            parent: path.clone(),
+                            start: index,
+                            len: 1,
+                        }),
+                    }
+                    nodes.push(child);
+                }
+                // A new child is usually a copy of the old child in the same place, so it's
+                // searched for the nodes that it reuses
+                None => {
+                    if let Some(&old_child) = old.children().get(i) {
+                        let mut child_path = path.clone();
+                        child_path.push(i);
+                        stack.push((old_child, child_path, child));
+                    }
+                }
+            }
+        }
+    }
+    (runs, nodes)
+}
+
+/// Appends a frame containing `contents` to `file`
+fn write_frame(file: &mut File, contents: &str) -> std::io::Result<()> {
+    let len = u32::try_from(contents.len())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + contents.len());
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&file::fnv1a(contents.as_bytes()).to_le_bytes());
+    frame.extend_from_slice(contents.as_bytes());
+    file.write_all(&frame)
+}
+
+/// Splits `bytes` into the contents of its frames, stopping at the first frame which is cut
+/// short or doesn't match its hash.  Along with the frames, this returns the number of bytes
+/// that they take up.
+fn read_frames(bytes: &[u8]) -> (Vec<&str>, usize) {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + FRAME_HEADER_LEN) {
+        let (len, hash) = header.split_at(4);
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let hash = u64::from_le_bytes(hash.try_into().unwrap());
+        let start = offset + FRAME_HEADER_LEN;
+        let contents = match bytes.get(start..start + len) {
+            Some(contents) if file::fnv1a(contents) == hash => contents,
+            _ => break,
+        };
+        match std::str::from_utf8(contents) {
+            Ok(contents) => frames.push(contents),
+            Err(_) => break,
+        }
+        offset = start + len;
+    }
+    (frames, offset)
+}
+
+/// Converts a TOML error into an I/O error, for writing frames
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+/// The reasons that a swap file can't be recovered
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SwapError {
+    /// The swap file couldn't be read, or isn't a swap file
+    Read(String),
+    /// The swap file belongs to a different file than the buffer it's recovered into
+    WrongBuffer,
+    /// The file has changed since the swap file was started, and the swap file doesn't have a
+    /// snapshot to recover from instead
+    FileChanged,
+    /// The change at `index` doesn't fit the tree before it
+    InvalidChange { index: usize },
+}
+
+impl std::fmt::Display for SwapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SwapError::Read(message) => write!(f, "{}", message),
+            SwapError::WrongBuffer => write!(f, "the swap file is for a different file"),
+            SwapError::FileChanged => write!(f, "the file has changed since it was edited"),
+            SwapError::InvalidChange { index } => write!(f, "change {} is corrupt", index + 1),
+        }
+    }
+}
+
+impl std::error::Error for SwapError {}
+
+/// A swap file that has been read, so that the changes in it can be recovered
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SwapFile {
+    path: PathBuf,
+    header: Header,
+    changes: Vec<Change>,
+    /// The number of bytes taken up by `header` and `changes`.  Anything after them is a frame
+    /// that was only partly written.
+    valid_len: u64,
+    /// The number of bytes in the file
+    len: u64,
+}
+
+impl SwapFile {
+    /// Reads the swap file at `path`, ignoring any frames after the first one that is corrupt
+    pub fn read(path: &Path) -> Result<Self, SwapError> {
+        let bytes = std::fs::read(path).map_err(|e| SwapError::Read(e.to_string()))?;
+        let (frames, mut valid_len) = read_frames(&bytes);
+        let not_swap = || SwapError::Read(format!("{} isn't a swap file", path.display()));
+        let header: Header = frames
+            .first()
+            .and_then(|frame| toml::from_str(frame).ok())
+            .ok_or_else(not_swap)?;
+        if header.version != VERSION {
+            return Err(SwapError::Read(format!(
+                "{} was written by a different version of Sapling",
+                path.display()
+            )));
+        }
+        // A frame that matches its hash but can't be parsed is treated like a corrupt frame
+        let mut changes = Vec::new();
+        let mut offset = FRAME_HEADER_LEN + frames[0].len();
+        for frame in &frames[1..] {
+            match toml::from_str(frame) {
+                Ok(change) => changes.push(change),
+                Err(_) => {
+                    valid_len = offset;
+                    break;
+                }
+            }
+            offset += FRAME_HEADER_LEN + frame.len();
+        }
+        Ok(SwapFile {
+            path: path.to_owned(),
+            header,
+            changes,
+            valid_len: valid_len as u64,
+            len: bytes.len() as u64,
+        })
+    }
+
+    /// Returns the path that this swap file was read from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of changes that can be read from the swap file
+    pub fn change_count(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns `true` if the end of the swap file is corrupt (usually because Sapling died
+    /// whilst it was being written), so will be cut off when it's recovered
+    pub fn is_truncated(&self) -> bool {
+        self.valid_len < self.len
+    }
+
+    /// Asks the user whether or not to recover this swap file, by writing a question to `output`
+    /// and reading the answer from `input`.  An empty answer (or no answer at all) means yes.
+    pub fn prompt(
+        &self,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> std::io::Result<bool> {
+        let changes = self.change_count();
+        let plural = if changes == 1 { "" } else { "s" };
+        write!(
+            output,
+            "Found the swap file {}, so Sapling may have crashed.  Recover {} unsaved change{}? \
+             [Y/n] ",
+            self.path.to_string_lossy(),
+            changes,
+            plural
+        )?;
+        output.flush()?;
+        let mut answer = String::new();
+        input.read_line(&mut answer)?;
+        Ok(!answer.trim().to_lowercase().starts_with('n'))
+    }
+
+    /// Replays the changes in this swap file, returning an undo history which has one state for
+    /// each change.  `base` is the root of the file's tree along with the
+    /// [`file_hash`](super::session::file_hash) of its text: if the swap file was started from
+    /// the same text, then the history starts from `base`.  Otherwise, the history starts from
+    /// the last snapshot in the swap file.
+    pub fn replay<'arena, Node: Ast<'arena>>(
+        &self,
+        base: Option<(&'arena Node, &str)>,
+        arena: &'arena Arena<Node>,
+    ) -> Result<EditHistory<'arena, Node>, SwapError> {
+        let base = base
+            .filter(|(_, hash)| self.header.file_hash.as_deref() == Some(hash))
+            .map(|(root, _)| root);
+        let (mut history, first) = match base {
+            Some(root) => (EditHistory::new(root, CursorPath::root()), 0),
+            None => {
+                let index = (self.changes.iter())
+                    .rposition(Change::is_snapshot)
+                    .ok_or(SwapError::FileChanged)?;
+                let (root, cursor_path) = self.changes[index]
+                    .apply(None, arena)
+                    .ok_or(SwapError::InvalidChange { index })?;
+                (EditHistory::new(root, cursor_path), index + 1)
+            }
+        };
+        for (index, change) in self.changes.iter().enumerate().skip(first) {
+            let (root, cursor_path) = change
+                .apply(Some(history.root()), arena)
+                .ok_or(SwapError::InvalidChange { index })?;
+            history.push(root, cursor_path);
+        }
+        Ok(history)
+    }
+
+    /// Deletes this swap file
+    pub fn remove(&self) -> std::io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+}
+
+/// Appends the changes to a buffer's tree to its swap file
+#[derive(Debug)]
+pub struct Journal<'arena, Node: Ast<'arena>> {
+    path: PathBuf,
+    file: File,
+    /// The root of the tree that the next change will be made to (which is `None` if the next
+    /// change has to be a snapshot)
+    last_root: Option<&'arena Node>,
+    /// The number of changes since the last snapshot
+    since_snapshot: usize,
+    /// When the file was last synced to the disk
+    last_sync: Instant,
+}
+
+impl<'arena, Node: Ast<'arena>> Journal<'arena, Node> {
+    /// Starts a new swap file at `path`, whose first change will be made to `base` (the root of
+    /// the tree of some text, along with that text's
+    /// [`file_hash`](super::session::file_hash)).  If there's no `base`, then the first change
+    /// is a snapshot.  A swap file which is already at `path` is never overwritten, since it
+    /// could hold the changes of a Sapling that crashed (or is still running).
+    pub fn create(path: PathBuf, base: Option<(&'arena Node, &str)>) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let header = Header {
+            version: VERSION,
+            file_hash: base.map(|(_, hash)| hash.to_string()),
+        };
+        write_frame(&mut file, &toml::to_string(&header).map_err(invalid_data)?)?;
+        file.sync_all()?;
+        Ok(Journal {
+            path,
+            file,
+            last_root: base.map(|(root, _)| root),
+            since_snapshot: 0,
+            last_sync: Instant::now(),
+        })
+    }
+
+    /// Carries on writing a swap file that has been [`replay`](SwapFile::replay)ed into a tree
+    /// whose root is `root`.  Anything after the last valid change is cut off.
+    pub fn resume(swap: &SwapFile, root: &'arena Node) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new().write(true).open(&swap.path)?;
+        file.set_len(swap.valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+        file.sync_all()?;
+        let since_snapshot = match swap.changes.iter().rposition(Change::is_snapshot) {
+            Some(index) => swap.changes.len() - index - 1,
+            None => swap.changes.len(),
+        };
+        Ok(Journal {
+            path: swap.path.clone(),
+            file,
+            last_root: Some(root),
+            since_snapshot,
+            last_sync: Instant::now(),
+        })
+    }
+
+    /// Returns the path of the swap file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the change from the last tree to the tree under `root` (if that is a different
+    /// tree), where `cursor_path` is the location of the cursor.  If the file was last synced
+    /// longer than [`SYNC_INTERVAL`] before `now`, then it's synced again.
+    pub fn record(
+        &mut self,
+        root: &'arena Node,
+        cursor_path: &CursorPath,
+        now: Instant,
+    ) -> std::io::Result<()> {
+        if self.last_root.is_some_and(|last| std::ptr::eq(last, root)) {
+            return Ok(());
+        }
+        let old = self
+            .last_root
+            .filter(|_| self.since_snapshot + 1 < SNAPSHOT_INTERVAL);
+        let change = Change::new(old, root, cursor_path).map_err(invalid_data)?;
+        write_frame(
+            &mut self.file,
+            &toml::to_string(&change).map_err(invalid_data)?,
+        )?;
+        self.last_root = Some(root);
+        self.since_snapshot = if change.is_snapshot() {
+            0
+        } else {
+            self.since_snapshot + 1
+        };
+        if now.saturating_duration_since(self.last_sync) >= SYNC_INTERVAL {
+            self.file.sync_data()?;
+            self.last_sync = now;
+        }
+        Ok(())
+    }
+
+    /// Makes the journal refer to the copies of its nodes once they have been compacted, so that
+    /// compacting the tree doesn't look like a change to it
+    pub fn compact(&mut self, compactor: &mut Compactor<'arena, Node>) {
+        self.last_root = self.last_root.map(|root| compactor.copy(root));
+    }
+
+    /// Deletes the swap file (e.g. because its changes have been saved)
+    pub fn remove(self) -> std::io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_frames, swap_path, write_frame, Journal, SwapError, SwapFile};
+    use crate::arena::Arena;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::DAG;
+    use crate::editor::normal_mode::Action;
+    use crate::editor::session::file_hash;
+    use crate::testing::{random_json, Bounds, Edit, Rng};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    /// Returns a path for a test's swap file, which doesn't exist yet
+    fn test_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "sapling-test-{}-{}.json.sapling-swp",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn frames() {
+        assert_eq!(
+            swap_path(Path::new("dir/data.json")),
+            Path::new("dir/data.json.sapling-swp")
+        );
+        let path = test_path("frames");
+        let mut file = std::fs::File::create(&path).unwrap();
+        for contents in ["a", "", "ü"] {
+            write_frame(&mut file, contents).unwrap();
+        }
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(read_frames(&bytes), (vec!["a", "", "ü"], bytes.len()));
+        // Frames that are cut short or garbled are dropped, along with everything after them
+        for cut in 0..3 {
+            assert_eq!(
+                read_frames(&bytes[..bytes.len() - cut - 1]).0,
+                vec!["a", ""]
+            );
+        }
+        let mut garbled = bytes.clone();
+        garbled[12] = b'b';
+        assert_eq!(read_frames(&garbled), (vec![], 0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn random_edits() {
+        let mut rng = Rng::new(68);
+        let bounds = Bounds {
+            max_depth: 4,
+            max_nodes: 40,
+        };
+        for test in 0..50 {
+            let path = test_path(&format!("random_edits_{}", test));
+            let arena = Arena::new();
+            let base = random_json(&mut rng, &arena, bounds);
+            let hash = file_hash(&base.to_text(&JSONFormat::Compact));
+            let mut tree = DAG::new(&arena, base, CursorPath::root());
+            let mut journal = Journal::create(path.clone(), Some((base, &hash))).unwrap();
+            let mut roots = vec![base];
+            let mut cursor_path = CursorPath::root();
+            // Enough edits to write a snapshot part of the way through
+            for _ in 0..150 {
+                let _ = Edit::random(&mut rng, tree.root()).apply(&mut tree);
+                journal
+                    .record(tree.root(), tree.cursor_path(), Instant::now())
+                    .unwrap();
+                if !std::ptr::eq(*roots.last().unwrap(), tree.root()) {
+                    roots.push(tree.root());
+                    cursor_path = tree.cursor_path().clone();
+                }
+            }
+            let swap = SwapFile::read(&path).unwrap();
+            assert_eq!(swap.change_count(), roots.len() - 1);
+            assert!(!swap.is_truncated());
+
+            let new_arena = Arena::new();
+            let history = swap.replay(Some((base, &hash)), &new_arena).unwrap();
+            assert_eq!(history.root(), tree.root());
+            assert_eq!(history.cursor_path(), &cursor_path);
+            // If the file has changed, then the swap file is replayed from its last snapshot
+            let changed = swap.replay(Some((base, "changed")), &new_arena);
+            match changed {
+                Ok(history) => assert_eq!(history.root(), tree.root()),
+                Err(e) => assert_eq!((e, roots.len() < 100), (SwapError::FileChanged, true)),
+            }
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn small_changes() {
+        let path = test_path("small_changes");
+        let arena = Arena::new();
+        let text = format!("[{}]", vec!["{\"a\": [1, 2]}"; 10_000].join(", "));
+        let base = JSON::parse(&text, &arena).unwrap();
+        let mut tree = DAG::new(&arena, base, CursorPath::root());
+        let mut journal = Journal::create(path.clone(), Some((base, &file_hash(&text)))).unwrap();
+        let len = || std::fs::metadata(&path).unwrap().len();
+        let header_len = len();
+        // Replace a number deep inside the array, and then delete one of its elements.  The changes
+        // only depend on how deep the edits are, not on how big the array is.
+        let mut edit = |path: Vec<usize>, action| {
+            tree.set_cursor_path(CursorPath::from_vec(path));
+            tree.execute_action(action).1.unwrap();
+            journal
+                .record(tree.root(), tree.cursor_path(), Instant::now())
+                .unwrap();
+            tree.root()
+        };
+        let replaced = edit(vec![5000, 0, 1, 1], Action::Replace('n'));
+        assert!(len() - header_len < 1000, "{}", len() - header_len);
+        edit(vec![1234], Action::Delete);
+        assert!(len() - header_len < 2000, "{}", len() - header_len);
+
+        // A swap file can't be created on top of another one
+        assert!(Journal::<JSON>::create(path.clone(), None).is_err());
+        // The end of the file being cut off only loses the last change
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len() - 1).unwrap();
+        let swap = SwapFile::read(&path).unwrap();
+        assert_eq!((swap.change_count(), swap.is_truncated()), (1, true));
+        let new_arena = Arena::new();
+        let history = swap
+            .replay(Some((base, &file_hash(&text))), &new_arena)
+            .unwrap();
+        assert_eq!(history.root(), replaced);
+        // Garbage at the end of the file is ignored too
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"garbage").unwrap();
+        assert_eq!(SwapFile::read(&path).unwrap().change_count(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prompt() {
+        let path = test_path("prompt");
+        let arena = Arena::new();
+        let root = arena.alloc(JSON::Null);
+        let mut journal = Journal::create(path.clone(), None).unwrap();
+        journal
+            .record(root, &CursorPath::root(), Instant::now())
+            .unwrap();
+        let swap = SwapFile::read(&path).unwrap();
+        let ask = |answer: &str| {
+            let mut output = Vec::new();
+            let recover = swap.prompt(&mut answer.as_bytes(), &mut output).unwrap();
+            (recover, String::from_utf8(output).unwrap())
+        };
+        let question = format!(
+            "Found the swap file {}, so Sapling may have crashed.  Recover 1 unsaved change? \
+             [Y/n] ",
+            path.display()
+        );
+        assert_eq!(ask("\n"), (true, question));
+        assert!(!ask("n\n").0);
+        // A swap file which starts with a snapshot can be replayed without the file
+        let history = swap.replay::<JSON>(None, &arena).unwrap();
+        assert_eq!(history.root(), root);
+        journal.remove().unwrap();
+        assert!(!path.exists());
+        assert!(SwapFile::read(&path).is_err());
+    }
+}
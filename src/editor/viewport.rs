@@ -0,0 +1,452 @@
+//! Calculations for vertical scrolling, i.e. deciding which lines of the tree are on the screen.
+
+use super::folds::{FoldedTokens, Step};
+use crate::ast::display_token::DisplayToken;
+use crate::ast::size::{self, Size, SizeCache};
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// The default number of lines that are kept between the cursor and the top or bottom of the
+/// screen (like Vim's `scrolloff`)
+pub const DEFAULT_SCROLL_OFF: usize = 5;
+
+/// The number of columns that are kept between the cursor and the left or right edge of the
+/// screen when scrolling horizontally (like Vim's `sidescrolloff`)
+pub const SIDE_SCROLL_OFF: usize = 5;
+
+/// Which subtrees a [`Layout`] can skip over without generating their tokens
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Skip {
+    /// Skip every subtree that ends before a given line
+    BeforeLine(usize),
+    /// Skip every subtree that doesn't contain the node at a given path
+    Outside(CursorPath),
+}
+
+/// A lazy iterator over the [`DisplayToken`]s of a tree, along with the line and column of the
+/// screen (before scrolling) on which each token starts
+pub struct Layout<'s, 'arena, Node: Ast<'arena>> {
+    tokens: FoldedTokens<'s, 'arena, Node>,
+    /// The cached node sizes used to step over skipped subtrees, and which subtrees to skip
+    skip: Option<(&'s SizeCache<'arena, Node>, Skip)>,
+    /// How many columns each indentation level occupies
+    indent_columns: usize,
+    /// How many columns a tab character occupies
+    tab_width: usize,
+    line: usize,
+    col: usize,
+    indentation_amount: usize,
+}
+
+impl<'s, 'arena, Node: Ast<'arena>> Layout<'s, 'arena, Node> {
+    /// Lays out some tokens, where each indentation level occupies `indent_columns` columns
+    pub fn new(
+        tokens: FoldedTokens<'s, 'arena, Node>,
+        indent_columns: usize,
+        tab_width: usize,
+    ) -> Self {
+        Layout {
+            tokens,
+            indent_columns,
+            tab_width,
+            skip: None,
+            line: 0,
+            col: 0,
+            indentation_amount: 0,
+        }
+    }
+
+    /// Makes this layout jump over the subtrees picked out by `skip`, using `sizes` to work out
+    /// where each skipped subtree ends instead of generating its tokens.  This means that the
+    /// work done to reach a given node is proportional to its depth (rather than to the number
+    /// of nodes that come before it), as long as the sizes of the skipped subtrees are already
+    /// cached.  Skipped subtrees are measured with tabs one column wide, so skipping is only
+    /// exact for trees whose text doesn't contain tabs.
+    pub fn skipping(mut self, sizes: &'s SizeCache<'arena, Node>, skip: Skip) -> Self {
+        self.skip = Some((sizes, skip));
+        self
+    }
+
+    /// Returns the path of the node that generated the token most recently returned by
+    /// [`next`](Iterator::next)
+    pub fn path(&self) -> &CursorPath {
+        self.tokens.path()
+    }
+
+    /// Returns `true` if the token most recently returned by [`next`](Iterator::next) is one of
+    /// its node's delimiters (see [`FoldedTokens::is_delimiter`])
+    pub fn is_delimiter(&self) -> bool {
+        self.tokens.is_delimiter()
+    }
+
+    /// Returns how many columns a tab character occupies in this layout
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Returns the next token, or the next subtree that was skipped
+    fn next_step(&mut self) -> Option<Step<'arena, Node>> {
+        let (sizes, skip) = match &self.skip {
+            Some((sizes, skip)) => (*sizes, skip),
+            None => return self.tokens.next_or_skip(|_, _| false),
+        };
+        let format_style = self.tokens.format_style();
+        let line = self.line;
+        self.tokens.next_or_skip(|node, path| match skip {
+            Skip::BeforeLine(target) => line + sizes.size(node, format_style).lines() < *target,
+            Skip::Outside(target) => !target.starts_with(path),
+        })
+    }
+}
+
+impl<'s, 'arena, Node: Ast<'arena>> Iterator for Layout<'s, 'arena, Node> {
+    type Item = ((usize, usize), &'arena Node, DisplayToken);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, tok) = loop {
+            match self.next_step()? {
+                Step::Token(node, tok) => break (node, tok),
+                Step::Skipped(node) => {
+                    // Nodes are only skipped if `self.skip` is set
+                    let sizes = self.skip.as_ref().unwrap().0;
+                    let size = sizes.size(node, self.tokens.format_style());
+                    if size.lines() == 0 {
+                        self.col += size.last_line_length();
+                    } else {
+                        self.line += size.lines();
+                        self.col = self.indentation_amount + size.last_line_length();
+                    }
+                }
+            }
+        };
+        let position = (self.line, self.col);
+        match &tok {
+            DisplayToken::Text(s, _) => {
+                let size = Size::from_str_with_tab_width(s, self.tab_width);
+                if size.lines() == 0 {
+                    self.col += size.last_line_length();
+                } else {
+                    self.line += size.lines();
+                    self.col = size.last_line_length();
+                }
+            }
+            DisplayToken::Whitespace(n) => self.col += n,
+            DisplayToken::Newline => {
+                self.line += 1;
+                self.col = self.indentation_amount;
+            }
+            DisplayToken::Indent => self.indentation_amount += self.indent_columns,
+            // An unmatched dedent is a bug in the `Ast`, but shouldn't crash the editor
+            DisplayToken::Dedent => {
+                self.indentation_amount =
+                    self.indentation_amount.saturating_sub(self.indent_columns)
+            }
+        }
+        Some((position, node, tok))
+    }
+}
+
+/// Returns the part of a single line of text (which starts at column `start`) that is visible
+/// on a screen that is `width` columns wide and scrolled `offset` columns to the right, along
+/// with the column of the screen that the visible part starts at.  The text is only ever split
+/// between `char`s (which are measured by [`size::char_widths`]), and `char`s that are partly
+/// off the screen aren't shown.  Returns `None` if
+/// none of the text is visible.
+pub fn clip(
+    text: &str,
+    start: usize,
+    offset: usize,
+    width: usize,
+    tab_width: usize,
+) -> Option<(usize, &str)> {
+    let mut col = start;
+    let mut begin = None;
+    let mut end = text.len();
+    for (i, char_width) in size::char_widths(text, tab_width) {
+        if col + char_width > offset + width {
+            end = i;
+            break;
+        }
+        if begin.is_none() && col >= offset {
+            begin = Some((i, col - offset));
+        }
+        col += char_width;
+    }
+    let (begin, screen_col) = begin?;
+    (begin < end).then(|| (screen_col, &text[begin..end]))
+}
+
+/// Returns the new scroll offset of a window which is `height` lines tall and currently scrolled
+/// `offset` lines down, so that at least `scroll_off` lines are visible either side of
+/// `cursor_line`.  The window scrolls as little as possible, and if the window is too short to
+/// fit the margins, they are shrunk so that the cursor is still visible.
+pub fn scroll_to_show(
+    offset: usize,
+    cursor_line: usize,
+    height: usize,
+    scroll_off: usize,
+) -> usize {
+    let scroll_off = scroll_off.min(height.saturating_sub(1) / 2);
+    if cursor_line < offset + scroll_off {
+        cursor_line.saturating_sub(scroll_off)
+    } else if cursor_line + scroll_off >= offset + height {
+        (cursor_line + scroll_off + 1).saturating_sub(height)
+    } else {
+        offset
+    }
+}
+
+/// Returns the scroll offset which puts `cursor_line` in the middle of a window that is `height`
+/// lines tall
+pub fn centre(cursor_line: usize, height: usize) -> usize {
+    cursor_line.saturating_sub(height / 2)
+}
+
+/// Returns the line and column on which the node at `path` starts, or `None` if that node isn't
+/// rendered (e.g. because it's inside a fold).  This only generates tokens as far as the node.
+pub fn position_of<'arena, Node: Ast<'arena>>(
+    mut layout: Layout<'_, 'arena, Node>,
+    path: &CursorPath,
+) -> Option<(usize, usize)> {
+    while let Some((position, _, _)) = layout.next() {
+        if layout.path().starts_with(path) {
+            return Some(position);
+        }
+    }
+    None
+}
+
+/// Returns the path of the first node that starts on or after `line`, along with the line that
+/// it does start on.  If every node starts before `line`, the last node to start is returned.
+/// A node starts on the line of its first token, so this finds outermost nodes (e.g. a whole
+/// JSON field rather than its key).
+pub fn first_node_from<'arena, Node: Ast<'arena>>(
+    mut layout: Layout<'_, 'arena, Node>,
+    line: usize,
+) -> (CursorPath, usize) {
+    let mut last_start = (CursorPath::root(), 0);
+    let mut prev_path: Option<CursorPath> = None;
+    while let Some(((current_line, _), _, _)) = layout.next() {
+        let path = layout.path();
+        // Every node on `path` which isn't on the previous token's path has started with this
+        // token, so the outermost of those is the one that we want
+        let started = match &prev_path {
+            None => Some(CursorPath::root()),
+            Some(prev) => {
+                let common = prev
+                    .iter()
+                    .zip(path.iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let new_nodes = path.iter().copied().take(common + 1);
+                (common < path.depth()).then(|| CursorPath::from_vec(new_nodes.collect()))
+            }
+        };
+        if let Some(started) = started {
+            if current_line >= line {
+                return (started, current_line);
+            }
+            last_start = (started, current_line);
+        }
+        prev_path = Some(path.clone());
+    }
+    last_start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{centre, clip, first_node_from, position_of, scroll_to_show, Layout, Skip};
+    use crate::arena::Arena;
+    use crate::ast::display_token::{DisplayToken, IndentKind};
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::size::SizeCache;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::editable_tree::{cursor_path::CursorPath, DAG};
+    use crate::editor::folds::FoldSet;
+    use crate::editor::normal_mode::Action;
+
+    #[test]
+    fn scrolling() {
+        // The window is 10 lines tall, and keeps 2 lines around the cursor
+        let scroll = |offset, cursor_line| scroll_to_show(offset, cursor_line, 10, 2);
+        assert_eq!(scroll(0, 0), 0);
+        assert_eq!(scroll(0, 7), 0);
+        assert_eq!(scroll(0, 8), 1);
+        assert_eq!(scroll(0, 100), 93);
+        assert_eq!(scroll(93, 95), 93);
+        assert_eq!(scroll(93, 94), 92);
+        assert_eq!(scroll(93, 1), 0);
+        // The margin can't be more than half the window
+        assert_eq!(scroll_to_show(0, 3, 4, 10), 1);
+        assert_eq!(scroll_to_show(0, 3, 1, 10), 3);
+        assert_eq!(centre(100, 10), 95);
+        assert_eq!(centre(3, 10), 0);
+    }
+
+    #[test]
+    fn positions() {
+        let arena = Arena::new();
+        // Pretty printed, this is:
+        // 0 {
+        // 1     "a": [
+        // 2         true,
+        // 3         false
+        // 4     ],
+        // 5     "b": null
+        // 6 }
+        let root = J::Object(vec![
+            ("a".to_string(), J::Array(vec![J::True, J::False])),
+            ("b".to_string(), J::Null),
+        ])
+        .add_to_arena(&arena);
+        let format = JSONFormat::Pretty {
+            indent: IndentKind::default(),
+            trailing_commas: false,
+        };
+        let path = CursorPath::from_vec;
+        let mut folds = FoldSet::new();
+        macro_rules! layout {
+            () => {
+                Layout::new(folds.display_tokens(root, &format), 4, 8)
+            };
+        }
+
+        assert_eq!(position_of(layout!(), &path(vec![])), Some((0, 0)));
+        assert_eq!(position_of(layout!(), &path(vec![0])), Some((1, 4)));
+        assert_eq!(position_of(layout!(), &path(vec![0, 1])), Some((1, 9)));
+        assert_eq!(position_of(layout!(), &path(vec![0, 1, 1])), Some((3, 8)));
+        assert_eq!(position_of(layout!(), &path(vec![1, 1])), Some((5, 9)));
+        // Fields are found instead of their keys
+        assert_eq!(first_node_from(layout!(), 0), (path(vec![]), 0));
+        assert_eq!(first_node_from(layout!(), 1), (path(vec![0]), 1));
+        assert_eq!(first_node_from(layout!(), 3), (path(vec![0, 1, 1]), 3));
+        // Line 4 only contains the end of a node, so the next node is found
+        assert_eq!(first_node_from(layout!(), 4), (path(vec![1]), 5));
+        assert_eq!(first_node_from(layout!(), 6), (path(vec![1, 1]), 5));
+
+        // Folding a node moves everything after it up, and hides its contents
+        folds.toggle(&path(vec![0, 1]), root);
+        assert_eq!(position_of(layout!(), &path(vec![0, 1, 1])), None);
+        assert_eq!(position_of(layout!(), &path(vec![1, 1])), Some((2, 9)));
+        assert_eq!(first_node_from(layout!(), 2), (path(vec![1]), 2));
+    }
+
+    #[test]
+    fn unicode_positions() {
+        let arena = Arena::new();
+        let strings = ["日本語", "👨\u{200D}👩\u{200D}👧", "cafe\u{301}", ""];
+        let root =
+            J::Array(strings.iter().map(|s| J::Str(s.to_string())).collect()).add_to_arena(&arena);
+        let folds = FoldSet::new();
+        let format = JSONFormat::Compact;
+        let col_of = |index| {
+            let layout = Layout::new(folds.display_tokens(root, &format), 4, 8);
+            position_of(layout, &CursorPath::from_vec(vec![index]))
+                .unwrap()
+                .1
+        };
+        // `["日本語","👨‍👩‍👧","café",""]`
+        assert_eq!(col_of(0), 1);
+        assert_eq!(col_of(1), 10);
+        assert_eq!(col_of(2), 15);
+        assert_eq!(col_of(3), 22);
+    }
+
+    #[test]
+    fn clipping() {
+        // The screen shows columns 10 to 15
+        let clip = |text, start| clip(text, start, 10, 6, 4);
+        assert_eq!(clip("abc", 0), None);
+        assert_eq!(clip("abc", 7), None);
+        assert_eq!(clip("abcd", 7), Some((0, "d")));
+        assert_eq!(clip("abc", 12), Some((2, "abc")));
+        assert_eq!(clip("abcdefghij", 8), Some((0, "cdefgh")));
+        assert_eq!(clip("abc", 16), None);
+        assert_eq!(clip("", 12), None);
+        // Multi-byte chars are never split, and double-width chars are only shown if they fit
+        assert_eq!(clip("日本語のテキスト", 8), Some((0, "本語の")));
+        assert_eq!(clip("日本語のテキスト", 9), Some((1, "本語")));
+        assert_eq!(clip("€€€€€€€€€", 5), Some((0, "€€€€")));
+        // A tab that is partly off the screen isn't shown
+        assert_eq!(clip("a\tb", 8), Some((2, "b")));
+        assert_eq!(clip("a\tb", 10), Some((0, "a\tb")));
+    }
+
+    /// Returns the position of the node at `path`, and how many tokens were generated to find it
+    fn find<'arena>(
+        mut layout: Layout<'_, 'arena, JSON<'arena>>,
+        path: &CursorPath,
+    ) -> (Option<(usize, usize)>, usize) {
+        let mut tokens = 0;
+        while let Some((position, _, _)) = layout.next() {
+            tokens += 1;
+            if layout.path().starts_with(path) {
+                return (Some(position), tokens);
+            }
+        }
+        (None, tokens)
+    }
+
+    /// Returns the tokens which start on lines 4000 to 4019
+    fn on_screen<'arena>(
+        layout: Layout<'_, 'arena, JSON<'arena>>,
+    ) -> Vec<((usize, usize), &'arena JSON<'arena>, DisplayToken)> {
+        layout
+            .skip_while(|((line, _), _, _)| *line < 4000)
+            .take_while(|((line, _), _, _)| *line < 4020)
+            .collect()
+    }
+
+    /// Builds a tree of arrays where every array has 4 children, and every leaf is `null`
+    fn balanced(depth: usize) -> J {
+        match depth {
+            0 => J::Null,
+            _ => J::Array(vec![balanced(depth - 1); 4]),
+        }
+    }
+
+    #[test]
+    fn skipping() {
+        let arena = Arena::new();
+        // 21845 nodes, of which 16384 are leaves
+        let root = balanced(7).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::root());
+        let format = JSONFormat::Pretty {
+            indent: IndentKind::default(),
+            trailing_commas: false,
+        };
+        let folds = FoldSet::new();
+        let sizes = SizeCache::new();
+        let target = CursorPath::from_vec(vec![3; 7]);
+        macro_rules! layout {
+            () => {
+                Layout::new(folds.display_tokens(tree.root(), &format), 4, 8)
+            };
+        }
+
+        // Skipping finds the same position, but only generates the tokens of the target's
+        // ancestors, of which there are a few per level of the tree
+        let (position, all_tokens) = find(layout!(), &target);
+        let skipping = layout!().skipping(&sizes, Skip::Outside(target.clone()));
+        let (skipped_position, tokens) = find(skipping, &target);
+        assert_eq!(skipped_position, position);
+        assert!(all_tokens > 50_000);
+        assert!(tokens < 20 * 7, "{}", tokens);
+        // Every node except the target and its ancestors has been skipped, so has been measured
+        assert_eq!(sizes.cached_count(), 21845 - 8);
+
+        // Skipping the lines above the screen gives the same tokens on the screen
+        let skipping = layout!().skipping(&sizes, Skip::BeforeLine(4000));
+        assert_eq!(on_screen(skipping), on_screen(layout!()));
+
+        // After an edit, only the new nodes between the root and the edit have to be measured
+        let size = sizes.size(tree.root(), &format);
+        assert_eq!(sizes.cached_count(), 21845);
+        tree.set_cursor_path(target.clone());
+        tree.execute_action(Action::Replace('t')).1.unwrap();
+        assert_eq!(sizes.size(tree.root(), &format), size);
+        assert_eq!(sizes.cached_count(), 21845 + 8);
+        let skipping = layout!().skipping(&sizes, Skip::Outside(target.clone()));
+        assert_eq!(find(skipping, &target).0, find(layout!(), &target).0);
+    }
+}
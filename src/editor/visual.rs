@@ -0,0 +1,95 @@
+//! Visual mode, where a range of consecutive siblings is selected so that they can be deleted,
+//! yanked or pasted after all at once.  The selection runs from the node that visual mode was
+//! started on (the anchor) to the cursor, so the cursor can only move through the anchor's
+//! siblings whilst anything is selected.
+
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::EditErr;
+use std::ops::Range;
+
+/// The siblings selected in visual mode, which are the ones between the anchor and the cursor
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Selection {
+    anchor: CursorPath,
+}
+
+impl Selection {
+    /// Starts a selection at the node at `anchor`.  The root has no siblings, so can't be the
+    /// anchor of a selection.
+    pub fn new(anchor: CursorPath) -> Result<Self, EditErr> {
+        if anchor.is_root() {
+            return Err(EditErr::SelectRoot);
+        }
+        Ok(Selection { anchor })
+    }
+
+    /// Returns the path of the parent of the selected siblings, along with the range of their
+    /// child indices, if the cursor is at `cursor`.  Returns an error if the cursor isn't a
+    /// sibling of the anchor.
+    pub fn range(&self, cursor: &CursorPath) -> Result<(CursorPath, Range<usize>), EditErr> {
+        let mut parent = self.anchor.clone();
+        let anchor_index = parent.pop().expect("the anchor is never the root");
+        let mut cursor_parent = cursor.clone();
+        match cursor_parent.pop() {
+            Some(cursor_index) if cursor_parent == parent => {
+                let start = anchor_index.min(cursor_index);
+                let end = anchor_index.max(cursor_index) + 1;
+                Ok((parent, start..end))
+            }
+            _ => Err(EditErr::SelectionAcrossParents),
+        }
+    }
+
+    /// Returns `true` if the node at `path` is one of the selected siblings (or is inside one of
+    /// them), if the cursor is at `cursor`
+    pub fn contains(&self, cursor: &CursorPath, path: &CursorPath) -> bool {
+        match self.range(cursor) {
+            Ok((parent, range)) => {
+                path.starts_with(&parent)
+                    && path
+                        .iter()
+                        .nth(parent.depth())
+                        .is_some_and(|index| range.contains(index))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selection;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::EditErr;
+
+    #[test]
+    fn ranges() {
+        let path = |indices: &[usize]| CursorPath::from_vec(indices.to_vec());
+        assert_eq!(Selection::new(CursorPath::root()), Err(EditErr::SelectRoot));
+        let selection = Selection::new(path(&[1, 3])).unwrap();
+        // The range covers the anchor and the cursor, whichever way round they are
+        assert_eq!(selection.range(&path(&[1, 3])), Ok((path(&[1]), 3..4)));
+        assert_eq!(selection.range(&path(&[1, 6])), Ok((path(&[1]), 3..7)));
+        assert_eq!(selection.range(&path(&[1, 0])), Ok((path(&[1]), 0..4)));
+        // The cursor has to stay among the anchor's siblings
+        for cursor in &[
+            path(&[1]),
+            path(&[1, 3, 0]),
+            path(&[0, 3]),
+            CursorPath::root(),
+        ] {
+            assert_eq!(
+                selection.range(cursor),
+                Err(EditErr::SelectionAcrossParents)
+            );
+        }
+        // The selected siblings and everything inside them are selected
+        let cursor = path(&[1, 5]);
+        assert!(selection.contains(&cursor, &path(&[1, 3])));
+        assert!(selection.contains(&cursor, &path(&[1, 5, 0, 2])));
+        assert!(!selection.contains(&cursor, &path(&[1, 6])));
+        assert!(!selection.contains(&cursor, &path(&[1])));
+        assert!(!selection.contains(&cursor, &path(&[0, 4])));
+        assert!(!selection.contains(&path(&[1, 5, 0]), &path(&[1, 3])));
+    }
+}
@@ -0,0 +1,361 @@
+//! Windows, which each show part of a buffer in their own rectangle of the screen.  The screen
+//! can be split between many windows, which can show the same buffer or different ones.
+
+use super::folds::FoldSet;
+use super::marks::Mark;
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+
+/// A rectangle of the screen, measured in rows and columns
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Rect {
+    pub row: usize,
+    pub col: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Returns `true` if the given position of the screen is inside this `Rect`
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        (self.row..self.row + self.height).contains(&row)
+            && (self.col..self.col + self.width).contains(&col)
+    }
+}
+
+/// The way that a window is split in two
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Split {
+    /// The windows are one above the other (`Ctrl-w s`)
+    Horizontal,
+    /// The windows are side by side (`Ctrl-w v`)
+    Vertical,
+}
+
+/// One view of a buffer.  Every window has its own cursor, scroll position and folds, but
+/// windows showing the same buffer are all views of the same tree, so an edit made in one of
+/// them is seen in all of them.
+#[derive(Debug, Clone)]
+pub struct Window<'arena, Node: Ast<'arena>> {
+    /// The index of the buffer that this window shows
+    pub buffer: usize,
+    /// Where the cursor is in this window.  This follows its node as the tree is edited (like a
+    /// [`Mark`]), so the cursors of the windows that aren't focused stay on the same node.
+    cursor: Mark<'arena, Node>,
+    /// How many lines of the tree are scrolled off the top of the window
+    pub scroll_offset: usize,
+    /// How many columns of the tree are scrolled off the left of the window
+    pub col_offset: usize,
+    /// The nodes that are folded in this window, so are rendered as a one-line summary
+    pub folds: FoldSet<'arena, Node>,
+}
+
+impl<'arena, Node: Ast<'arena>> Window<'arena, Node> {
+    /// Creates a `Window` showing the buffer at index `buffer` (whose tree is under `root`), with
+    /// the cursor at `cursor`
+    pub fn new(buffer: usize, cursor: CursorPath, root: &'arena Node) -> Self {
+        Window {
+            buffer,
+            cursor: Mark::new(cursor, root),
+            scroll_offset: 0,
+            col_offset: 0,
+            folds: FoldSet::new(),
+        }
+    }
+
+    /// Returns the path of this window's cursor
+    pub fn cursor_path(&self) -> &CursorPath {
+        self.cursor.path()
+    }
+
+    /// Moves this window's cursor to `path` in the tree under `root`
+    pub fn set_cursor(&mut self, path: CursorPath, root: &'arena Node) {
+        self.cursor = Mark::new(path, root);
+    }
+
+    /// Follows an edit that was made to this window's buffer (in another window), so that the
+    /// tree under `root` is the buffer's new tree.  If the node under the cursor has been
+    /// removed, the cursor moves to whatever is at its path (or to the closest ancestor of its
+    /// path that still exists).
+    pub fn update(&mut self, root: &'arena Node) {
+        let path = self.cursor.locate(root).path().clone();
+        self.cursor = Mark::new(path, root);
+        self.folds.update(root);
+    }
+}
+
+/// How the screen is split between windows.  Every split divides a rectangle in two, with a
+/// one-cell divider between the halves, and either half can be split again.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SplitTree {
+    /// The whole rectangle is the window with the given index
+    Window(usize),
+    /// The rectangle is split in two, with `first` above or left of `second`
+    Split {
+        split: Split,
+        first: Box<SplitTree>,
+        second: Box<SplitTree>,
+    },
+}
+
+/// The rectangles of the screen that a [`SplitTree`] gives each window, along with the
+/// rectangles of the dividers between them
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Partition {
+    /// The index and rectangle of each window, in the order that the windows are cycled through
+    pub windows: Vec<(usize, Rect)>,
+    /// Every divider, along with the way that it splits the space around it
+    pub dividers: Vec<(Split, Rect)>,
+}
+
+impl Partition {
+    /// Returns the rectangle given to the window with index `window`
+    pub fn rect_of(&self, window: usize) -> Option<Rect> {
+        self.windows
+            .iter()
+            .find(|(w, _)| *w == window)
+            .map(|(_, rect)| *rect)
+    }
+
+    /// Returns the index and rectangle of the window which covers a given position of the
+    /// screen, if there is one
+    pub fn window_at(&self, row: usize, col: usize) -> Option<(usize, Rect)> {
+        self.windows
+            .iter()
+            .find(|(_, rect)| rect.contains(row, col))
+            .copied()
+    }
+}
+
+impl SplitTree {
+    /// Divides `area` between the windows
+    pub fn partition(&self, area: Rect) -> Partition {
+        let mut partition = Partition {
+            windows: Vec::new(),
+            dividers: Vec::new(),
+        };
+        self.partition_into(area, &mut partition);
+        partition
+    }
+
+    fn partition_into(&self, area: Rect, partition: &mut Partition) {
+        let (split, first, second) = match self {
+            SplitTree::Window(window) => {
+                partition.windows.push((*window, area));
+                return;
+            }
+            SplitTree::Split {
+                split,
+                first,
+                second,
+            } => (*split, first, second),
+        };
+        // The first half gets the extra line (or column) if the space can't be split evenly
+        let (first_area, divider, second_area) = match split {
+            Split::Horizontal => {
+                let first_height = area.height / 2;
+                let second_height = area.height.saturating_sub(first_height + 1);
+                (
+                    Rect {
+                        height: first_height,
+                        ..area
+                    },
+                    Rect {
+                        row: area.row + first_height,
+                        height: area.height.min(1),
+                        ..area
+                    },
+                    Rect {
+                        row: area.row + first_height + 1,
+                        height: second_height,
+                        ..area
+                    },
+                )
+            }
+            Split::Vertical => {
+                let first_width = area.width / 2;
+                let second_width = area.width.saturating_sub(first_width + 1);
+                (
+                    Rect {
+                        width: first_width,
+                        ..area
+                    },
+                    Rect {
+                        col: area.col + first_width,
+                        width: area.width.min(1),
+                        ..area
+                    },
+                    Rect {
+                        col: area.col + first_width + 1,
+                        width: second_width,
+                        ..area
+                    },
+                )
+            }
+        };
+        first.partition_into(first_area, partition);
+        partition.dividers.push((split, divider));
+        second.partition_into(second_area, partition);
+    }
+
+    /// Returns the index of every window, in order from the top left to the bottom right
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            SplitTree::Window(window) => vec![*window],
+            SplitTree::Split { first, second, .. } => {
+                let mut windows = first.windows();
+                windows.extend(second.windows());
+                windows
+            }
+        }
+    }
+
+    /// Splits the window with index `window` in two, putting the window with index `new_window`
+    /// above or left of it
+    pub fn split(&mut self, window: usize, new_window: usize, split: Split) {
+        match self {
+            SplitTree::Window(w) if *w == window => {
+                *self = SplitTree::Split {
+                    split,
+                    first: Box::new(SplitTree::Window(new_window)),
+                    second: Box::new(SplitTree::Window(window)),
+                };
+            }
+            SplitTree::Window(_) => {}
+            SplitTree::Split { first, second, .. } => {
+                first.split(window, new_window, split);
+                second.split(window, new_window, split);
+            }
+        }
+    }
+
+    /// Removes the window with index `window`, giving its space to the window (or windows) that
+    /// it was split from.  Every window with a greater index has its index reduced by one, so
+    /// that the indices match a `Vec` of windows that `window` has been removed from.  The last
+    /// window can't be removed, so nothing happens if `window` is the only window.
+    pub fn remove(&mut self, window: usize) {
+        let (first, second) = match self {
+            SplitTree::Window(w) => {
+                if *w > window {
+                    *w -= 1;
+                }
+                return;
+            }
+            SplitTree::Split { first, second, .. } => (first, second),
+        };
+        // Replace the split with whichever half isn't being removed
+        let remaining = match (first.as_ref(), second.as_ref()) {
+            (SplitTree::Window(w), _) if *w == window => second.as_ref().clone(),
+            (_, SplitTree::Window(w)) if *w == window => first.as_ref().clone(),
+            _ => {
+                first.remove(window);
+                second.remove(window);
+                return;
+            }
+        };
+        *self = remaining;
+        self.remove(window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rect, Split, SplitTree, Window};
+    use crate::arena::Arena;
+    use crate::ast::test_json::TestJSON as J;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::DAG;
+    use crate::editor::normal_mode::Action;
+
+    fn rect(row: usize, col: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            row,
+            col,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn partition() {
+        let screen = rect(0, 0, 80, 24);
+        let mut tree = SplitTree::Window(0);
+        assert_eq!(tree.partition(screen).windows, vec![(0, screen)]);
+        // `Ctrl-w s`, then `Ctrl-w v` in the new (top) window
+        tree.split(0, 1, Split::Horizontal);
+        tree.split(1, 2, Split::Vertical);
+        assert_eq!(tree.windows(), vec![2, 1, 0]);
+        let partition = tree.partition(screen);
+        assert_eq!(
+            partition.windows,
+            vec![
+                (2, rect(0, 0, 40, 12)),
+                (1, rect(0, 41, 39, 12)),
+                (0, rect(13, 0, 80, 11)),
+            ]
+        );
+        assert_eq!(
+            partition.dividers,
+            vec![
+                (Split::Vertical, rect(0, 40, 1, 12)),
+                (Split::Horizontal, rect(12, 0, 80, 1)),
+            ]
+        );
+        assert_eq!(partition.rect_of(1), Some(rect(0, 41, 39, 12)));
+        assert_eq!(partition.window_at(5, 45).map(|(w, _)| w), Some(1));
+        assert_eq!(partition.window_at(12, 45), None);
+        assert_eq!(partition.window_at(20, 45).map(|(w, _)| w), Some(0));
+        // Tiny screens don't cause any overflows
+        let tiny = SplitTree::Split {
+            split: Split::Horizontal,
+            first: Box::new(SplitTree::Window(0)),
+            second: Box::new(SplitTree::Window(1)),
+        };
+        assert_eq!(
+            tiny.partition(rect(0, 0, 5, 0)).windows,
+            vec![(0, rect(0, 0, 5, 0)), (1, rect(1, 0, 5, 0))]
+        );
+
+        // Closing a window gives its space back to its neighbour
+        tree.remove(1);
+        assert_eq!(
+            tree,
+            SplitTree::Split {
+                split: Split::Horizontal,
+                first: Box::new(SplitTree::Window(1)),
+                second: Box::new(SplitTree::Window(0)),
+            }
+        );
+        tree.remove(0);
+        assert_eq!(tree, SplitTree::Window(0));
+        tree.remove(0);
+        assert_eq!(tree, SplitTree::Window(0));
+    }
+
+    #[test]
+    fn cursors_follow_edits() {
+        let arena = Arena::new();
+        let root = J::Array(vec![J::Array(vec![J::True]), J::False, J::Null]).add_to_arena(&arena);
+        let mut tree = DAG::new(&arena, root, CursorPath::from_vec(vec![1]));
+        let mut on_array = Window::new(0, CursorPath::from_vec(vec![0]), root);
+        let mut on_null = Window::new(0, CursorPath::from_vec(vec![2]), root);
+        on_array.folds.toggle(&CursorPath::from_vec(vec![0]), root);
+
+        // Deleting `false` in another window moves the other windows' cursors with their nodes,
+        // and keeps their folds
+        tree.execute_action(Action::Delete).1.unwrap();
+        on_array.update(tree.root());
+        on_null.update(tree.root());
+        assert_eq!(on_array.cursor_path(), &CursorPath::from_vec(vec![0]));
+        assert!(on_array.folds.is_folded(&CursorPath::from_vec(vec![0])));
+        assert_eq!(on_null.cursor_path(), &CursorPath::from_vec(vec![1]));
+        // Deleting the node under a window's cursor moves it to the parent if nothing took its
+        // place
+        tree.set_cursor_path(CursorPath::from_vec(vec![1]));
+        tree.execute_action(Action::Delete).1.unwrap();
+        on_null.update(tree.root());
+        assert_eq!(on_null.cursor_path(), &CursorPath::root());
+        on_null.set_cursor(CursorPath::from_vec(vec![0, 0]), tree.root());
+        assert_eq!(on_null.cursor_path(), &CursorPath::from_vec(vec![0, 0]));
+    }
+}
@@ -0,0 +1,993 @@
+//! The languages that Sapling can edit, and how the language of a file is chosen.
+//!
+//! Every [`Editor`] edits trees of a single [`Ast`] type, so a [`Language`] is what turns the
+//! choice of language (which is only known once Sapling is running) into an editor of the right
+//! type.  The [`LanguageRegistry`] maps the names given to `--language` and the extensions of
+//! files to the languages that they mean.
+
+use crate::arena::Arena;
+use crate::ast::ansi::write_ansi;
+use crate::ast::display_token::{IndentKind, SyntaxCategory};
+use crate::ast::expr::{Expr, ExprFormat};
+use crate::ast::json::{Dialect, JSONFormat, JSON};
+use crate::ast::sexpr::{SExpr, SExprFormat};
+use crate::ast::test_json::TestJSON;
+use crate::ast::toml::{TOMLFormat, TOML};
+use crate::ast::xml::{XMLFormat, XML};
+use crate::ast::Ast;
+use crate::config::options::Options;
+use crate::config::{ColorScheme, Config};
+use crate::editor::buffer::{Buffer, BufferLoader, ParseFn};
+use crate::editor::registers::SavedRegister;
+use crate::editor::script::{Script, ScriptError, ScriptOutput};
+use crate::editor::session::SavedSession;
+use crate::editor::swap::SwapFile;
+use crate::editor::Editor;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The languages that Sapling can edit
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Language {
+    Expr,
+    JSON(Dialect),
+    SExpr,
+    TOML,
+    XML,
+}
+
+/// The language that files are read as if nothing else chooses one
+pub const DEFAULT_LANGUAGE: Language = Language::JSON(Dialect::JSON);
+
+impl Language {
+    /// Returns every language, in the order they're listed to the user
+    pub fn all() -> [Language; 6] {
+        [
+            Language::JSON(Dialect::JSON),
+            Language::JSON(Dialect::JSONC),
+            Language::SExpr,
+            Language::TOML,
+            Language::XML,
+            Language::Expr,
+        ]
+    }
+
+    /// Returns the name of this language, which is how it's chosen with `--language` and
+    /// `:set language`
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Expr => "expr",
+            Language::JSON(Dialect::JSON) => "json",
+            Language::JSON(Dialect::JSONC) => "jsonc",
+            Language::SExpr => "sexpr",
+            Language::TOML => "toml",
+            Language::XML => "xml",
+        }
+    }
+
+    /// Returns `true` if this language allows a comma after the last element of an array (and
+    /// of an object), so that the `trailing_commas` option can be used.  Strict JSON doesn't.
+    pub fn allows_trailing_commas(self) -> bool {
+        !matches!(self, Language::JSON(Dialect::JSON))
+    }
+
+    /// Returns `true` if `:query` can be run on trees of this language.
+    /// [Queries](crate::ast::json::query) only need fields to have key children, but languages
+    /// have to opt in once their trees have been checked to give sensible results.
+    pub fn supports_queries(self) -> bool {
+        matches!(self, Language::JSON(_))
+    }
+
+    /// Returns `true` if buffers of this language can be checked against a JSON Schema (see
+    /// [`Schema`](crate::ast::json::schema::Schema)), which describes JSON values
+    pub fn supports_schemas(self) -> bool {
+        matches!(self, Language::JSON(_))
+    }
+
+    /// Returns the syntax categories that only this language uses, along with the shared
+    /// categories that they fall back on (see [`Ast::CATEGORIES`])
+    pub fn categories(self) -> &'static [(SyntaxCategory, SyntaxCategory)] {
+        match self {
+            Language::Expr => Expr::CATEGORIES,
+            Language::JSON(_) => JSON::CATEGORIES,
+            Language::SExpr => SExpr::CATEGORIES,
+            Language::TOML => TOML::CATEGORIES,
+            Language::XML => XML::CATEGORIES,
+        }
+    }
+
+    /// Returns every syntax category that text in this language is drawn in (see
+    /// [`Ast::TOKEN_CATEGORIES`])
+    pub fn token_categories(self) -> &'static [SyntaxCategory] {
+        match self {
+            Language::Expr => Expr::TOKEN_CATEGORIES,
+            Language::JSON(_) => JSON::TOKEN_CATEGORIES,
+            Language::SExpr => SExpr::TOKEN_CATEGORIES,
+            Language::TOML => TOML::TOKEN_CATEGORIES,
+            Language::XML => XML::TOKEN_CATEGORIES,
+        }
+    }
+
+    /// Parses `text` in this language, and runs `task` on the resulting tree (in this language's
+    /// default format style).  Returns why not if the text can't be parsed.
+    fn parse_and_run<T: TreeTask>(self, text: &str, task: T) -> Result<T::Output, String> {
+        fn run<'arena, Node: Ast<'arena>, E: ToString, T: TreeTask>(
+            root: Result<&'arena Node, E>,
+            format_style: Node::FormatStyle,
+            task: T,
+        ) -> Result<T::Output, String> {
+            let root = root.map_err(|e| e.to_string())?;
+            Ok(task.run(root, format_style))
+        }
+        let indent = IndentKind::default();
+        match self {
+            Language::Expr => run(Expr::parse(text, &Arena::new()), ExprFormat::Pretty, task),
+            Language::JSON(dialect) => run(
+                JSON::parse_dialect(text, &Arena::new(), dialect),
+                JSONFormat::Pretty {
+                    indent,
+                    trailing_commas: false,
+                },
+                task,
+            ),
+            Language::SExpr => run(
+                SExpr::parse(text, &Arena::new()),
+                SExprFormat::Pretty { indent },
+                task,
+            ),
+            Language::TOML => run(
+                TOML::parse(text, &Arena::new()),
+                TOMLFormat::Pretty {
+                    indent,
+                    trailing_commas: false,
+                },
+                task,
+            ),
+            Language::XML => run(
+                XML::parse(text, &Arena::new()),
+                XMLFormat::Pretty {
+                    indent,
+                    self_closing: true,
+                },
+                task,
+            ),
+        }
+    }
+
+    /// Parses `text` in this language, returning why not if it can't be parsed.  The tree isn't
+    /// kept.
+    pub fn check(self, text: &str) -> Result<(), String> {
+        self.parse_and_run(text, Check)
+    }
+
+    /// Parses `text` in this language, and writes a DOT graph of its tree to `out` (see
+    /// [`Ast::write_dot`]).  Returns why not if the text can't be parsed or written.
+    pub fn write_dot(self, text: &str, out: &mut impl Write, colored: bool) -> Result<(), String> {
+        self.parse_and_run(text, WriteDot { out, colored })?
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parses `text` in this language, and writes it back to `out` formatted as `options` say
+    /// (see [`write_ansi`]).  The text is coloured by `color_scheme` (using this language's
+    /// styles), unless it's `None`.
+    /// Returns why not if the text can't be parsed or written.
+    pub fn print(
+        self,
+        text: &str,
+        options: &Options,
+        color_scheme: Option<&ColorScheme>,
+        out: &mut impl Write,
+    ) -> Result<(), String> {
+        let color_scheme = color_scheme.map(|scheme| scheme.for_language(self));
+        let task = Print {
+            language: self,
+            options,
+            color_scheme: color_scheme.as_ref(),
+            out,
+        };
+        self.parse_and_run(text, task)?.map_err(|e| e.to_string())
+    }
+
+    /// Runs an editor for this language until the user either quits or asks to edit the buffer
+    /// in a different language.  The editor starts by editing `start`, restoring `restore` into
+    /// it (if it's given).  If `start` can't be loaded, then Sapling exits with an error.
+    pub fn edit(
+        self,
+        start: Start,
+        config: Config,
+        languages: LanguageRegistry,
+        status_message: Option<String>,
+        restore: Option<Restore>,
+    ) -> Exit {
+        let session = Session {
+            start,
+            config,
+            languages,
+            language: self,
+            status_message,
+            restore,
+        };
+        match self.run_session(session, Interactive) {
+            Ok(exit) => exit,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Runs a [`Script`] with an editor for this language (without a terminal), returning the
+    /// text of the buffer (or the patch of its changes) once the script has finished.  The
+    /// editor starts by editing `start`.
+    pub fn run_script(
+        self,
+        start: Start,
+        config: Config,
+        languages: LanguageRegistry,
+        script: &Script,
+        output: ScriptOutput,
+    ) -> Result<String, String> {
+        let session = Session {
+            start,
+            config,
+            languages,
+            language: self,
+            status_message: None,
+            restore: None,
+        };
+        self.run_session(session, RunScript(script, output))?
+            .map_err(|e| e.to_string())
+    }
+
+    /// Opens the buffer of a [`Session`] with an editor for this language, and then does a task
+    /// with that editor
+    fn run_session<T: EditorTask>(self, session: Session, task: T) -> Result<T::Output, String> {
+        let indent = session.config.options.indent();
+        match self {
+            Language::Expr => session.run(
+                &typed_arena::Arena::new(),
+                |text, arena| Expr::parse(text, arena).map_err(|e| e.to_string()),
+                ExprFormat::Pretty,
+                |arena| Expr::parse("-(x + 1) * 2", arena).unwrap(),
+                task,
+            ),
+            Language::JSON(dialect) => session.run(
+                &typed_arena::Arena::new(),
+                match dialect {
+                    Dialect::JSON => {
+                        |text, arena| JSON::parse(text, arena).map_err(|e| e.to_string())
+                    }
+                    Dialect::JSONC => |text, arena| {
+                        JSON::parse_dialect(text, arena, Dialect::JSONC).map_err(|e| e.to_string())
+                    },
+                },
+                JSONFormat::Pretty {
+                    indent,
+                    trailing_commas: false,
+                },
+                |arena| {
+                    TestJSON::Array(vec![
+                        TestJSON::True,
+                        TestJSON::False,
+                        TestJSON::Object(vec![("value".to_string(), TestJSON::True)]),
+                    ])
+                    .add_to_arena(arena)
+                },
+                task,
+            ),
+            Language::SExpr => session.run(
+                &typed_arena::Arena::new(),
+                |text, arena| SExpr::parse(text, arena).map_err(|e| e.to_string()),
+                SExprFormat::Pretty { indent },
+                |arena| SExpr::parse("(define (square x) (* x x))", arena).unwrap(),
+                task,
+            ),
+            Language::TOML => session.run(
+                &typed_arena::Arena::new(),
+                |text, arena| TOML::parse(text, arena).map_err(|e| e.to_string()),
+                TOMLFormat::Pretty {
+                    indent,
+                    trailing_commas: false,
+                },
+                |arena| TOML::parse("[package]\nname = \"sapling\"", arena).unwrap(),
+                task,
+            ),
+            Language::XML => session.run(
+                &typed_arena::Arena::new(),
+                |text, arena| XML::parse(text, arena).map_err(|e| e.to_string()),
+                XMLFormat::Pretty {
+                    indent,
+                    self_closing: true,
+                },
+                |arena| {
+                    XML::parse("<note to=\"Sapling\"><body>Hello!</body></note>", arena).unwrap()
+                },
+                task,
+            ),
+        }
+    }
+}
+
+/// Something that can be done with a tree, whichever language it's written in (see
+/// [`Language::parse_and_run`])
+trait TreeTask {
+    type Output;
+
+    /// Does this task with the tree under `root`, which would be formatted with `format_style`
+    fn run<'arena, Node: Ast<'arena>>(
+        self,
+        root: &'arena Node,
+        format_style: Node::FormatStyle,
+    ) -> Self::Output;
+}
+
+/// Does nothing, so that the only thing checked is whether or not the tree could be parsed
+struct Check;
+
+impl TreeTask for Check {
+    type Output = ();
+
+    fn run<'arena, Node: Ast<'arena>>(self, _root: &'arena Node, _: Node::FormatStyle) {}
+}
+
+/// Writes a DOT graph of the tree (see [`Ast::write_dot`])
+struct WriteDot<'o, W: Write> {
+    out: &'o mut W,
+    colored: bool,
+}
+
+impl<W: Write> TreeTask for WriteDot<'_, W> {
+    type Output = std::io::Result<()>;
+
+    fn run<'arena, Node: Ast<'arena>>(
+        self,
+        root: &'arena Node,
+        _: Node::FormatStyle,
+    ) -> std::io::Result<()> {
+        root.write_dot(self.out, self.colored)
+    }
+}
+
+/// Writes the text of the tree, formatted as some [`Options`] say (see [`write_ansi`])
+struct Print<'o, W: Write> {
+    language: Language,
+    options: &'o Options,
+    color_scheme: Option<&'o ColorScheme>,
+    out: &'o mut W,
+}
+
+impl<W: Write> TreeTask for Print<'_, W> {
+    type Output = std::io::Result<()>;
+
+    fn run<'arena, Node: Ast<'arena>>(
+        self,
+        root: &'arena Node,
+        mut format_style: Node::FormatStyle,
+    ) -> std::io::Result<()> {
+        self.options.apply_to(&mut format_style, self.language);
+        write_ansi(root, &format_style, self.color_scheme, self.out)
+    }
+}
+
+/// Something that can be done with an editor, whichever language it edits (see
+/// [`Language::run_session`])
+trait EditorTask {
+    type Output;
+
+    /// Does this task with an editor which has just been created
+    fn run<'arena, Node: Ast<'arena> + 'arena>(self, editor: Editor<'arena, Node>) -> Self::Output;
+}
+
+/// Runs the editor in the terminal, so that the user can edit the buffer (see [`Editor::run`])
+struct Interactive;
+
+impl EditorTask for Interactive {
+    type Output = Exit;
+
+    fn run<'arena, Node: Ast<'arena> + 'arena>(self, editor: Editor<'arena, Node>) -> Exit {
+        editor.run()
+    }
+}
+
+/// Feeds the keys of a script to the editor (see [`Editor::run_script`])
+struct RunScript<'s>(&'s Script, ScriptOutput);
+
+impl EditorTask for RunScript<'_> {
+    type Output = Result<String, ScriptError>;
+
+    fn run<'arena, Node: Ast<'arena> + 'arena>(
+        self,
+        editor: Editor<'arena, Node>,
+    ) -> Result<String, ScriptError> {
+        editor.run_script(self.0, self.1)
+    }
+}
+
+/// What an editor starts by editing
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Start {
+    /// An example tree, which isn't saved anywhere until the user saves it with `:w <path>`
+    Example,
+    /// The file at a given path
+    File(PathBuf),
+    /// Several files, which are each opened in their own buffer.  The first file is edited, and
+    /// `:bn` switches to the others.
+    Files(Vec<PathBuf>),
+    /// Some text which has already been read (e.g. the text of a buffer that was being edited in
+    /// another language), which will be saved to `file_path`.  If `dirty` is `true`, then the
+    /// text hasn't been saved.
+    Text {
+        text: String,
+        file_path: Option<PathBuf>,
+        dirty: bool,
+    },
+    /// Two files, which are compared side by side (see [`Editor::show_diff`])
+    Diff { old: PathBuf, new: PathBuf },
+    /// The text that was piped into Sapling's standard input.  It has no file, so it can only be
+    /// saved with `:w <path>`, and it starts off unsaved so that quitting warns about it.
+    Stdin(String),
+}
+
+/// Something that's restored into the buffer that an editor starts by editing
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Restore {
+    /// The session that was saved when Sapling last quit whilst editing the same file
+    Session(SavedSession),
+    /// The changes in the swap file of the buffer's file, which were never saved because
+    /// Sapling crashed
+    Swap(SwapFile),
+    /// The registers of the editor for the language that the user switched from
+    Registers(Vec<SavedRegister>),
+}
+
+/// The reason that an [`Editor`] stopped running
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Exit {
+    /// The user quit Sapling
+    Quit,
+    /// The user asked to edit the current buffer as a different language (with `:set
+    /// language=<name>`).  The text of the buffer is given, since the tree can't be kept.
+    SwitchLanguage {
+        language: Language,
+        text: String,
+        file_path: Option<PathBuf>,
+        dirty: bool,
+        registers: Vec<SavedRegister>,
+    },
+}
+
+/// Everything needed to run an editor, apart from the things that depend on its language
+struct Session {
+    start: Start,
+    config: Config,
+    languages: LanguageRegistry,
+    language: Language,
+    status_message: Option<String>,
+    /// A saved session or swap file of the buffer, which is restored once it has been loaded
+    restore: Option<Restore>,
+}
+
+impl Session {
+    /// Opens a buffer (containing whatever `self.start` says, or the tree made by
+    /// `default_root` for [`Start::Example`]) and does `task` with an editor of it.  For
+    /// [`Start::Diff`], the editor shows both buffers side by side.
+    fn run<'arena, Node: Ast<'arena> + 'arena, T: EditorTask>(
+        self,
+        arenas: &'arena typed_arena::Arena<Arena<Node>>,
+        parse: ParseFn<'arena, Node>,
+        format_style: Node::FormatStyle,
+        default_root: impl FnOnce(&'arena Arena<Node>) -> &'arena Node,
+        task: T,
+    ) -> Result<T::Output, String> {
+        let loader = BufferLoader::new(arenas, parse);
+        let load = |path: &Path| {
+            log::info!("Loading {}", path.to_string_lossy());
+            loader.load(path).map_err(|e| e.to_string())
+        };
+        let mut diff_with = None;
+        let mut other_files = Vec::new();
+        let buffer = match self.start {
+            Start::Example => {
+                let arena = loader.new_arena();
+                Buffer::new(arena, default_root(arena), None)
+            }
+            Start::File(path) => load(&path)?,
+            Start::Files(mut paths) => {
+                let first = paths.remove(0);
+                other_files = paths;
+                load(&first)?
+            }
+            Start::Diff { old, new } => {
+                diff_with = Some(load(&new)?);
+                load(&old)?
+            }
+            Start::Stdin(text) => {
+                log::info!("Loading standard input");
+                let mut buffer = loader
+                    .parse(&text, None)
+                    .map_err(|e| format!("Couldn't parse standard input: {}", e))?;
+                buffer.mark_unsaved();
+                buffer
+            }
+            // The text has already been checked, so it can't fail to parse
+            Start::Text {
+                text,
+                file_path,
+                dirty,
+            } => {
+                let mut buffer = loader.parse(&text, file_path).unwrap();
+                if dirty {
+                    buffer.mark_unsaved();
+                }
+                buffer
+            }
+        };
+
+        let mut editor = Editor::new(
+            buffer,
+            loader,
+            format_style,
+            self.config,
+            self.language,
+            self.languages,
+        );
+        if let Some(new) = diff_with {
+            editor.show_diff(new);
+        }
+        for path in other_files {
+            log::info!("Loading {}", path.to_string_lossy());
+            editor.load_buffer(path).map_err(|e| e.to_string())?;
+        }
+        let message = match self.restore {
+            Some(Restore::Session(saved)) => Some(match editor.restore_session(&saved) {
+                Ok(()) => "Restored the last session".to_string(),
+                Err(e) => format!("Couldn't restore the last session: {}", e),
+            }),
+            Some(Restore::Swap(swap)) => Some(match editor.recover_swap(&swap) {
+                Ok(count) => {
+                    let plural = if count == 1 { "" } else { "s" };
+                    format!("Recovered {} change{} from the swap file", count, plural)
+                }
+                Err(e) => format!("Couldn't recover the swap file: {}", e),
+            }),
+            Some(Restore::Registers(saved)) => {
+                editor.restore_registers(saved);
+                None
+            }
+            None => None,
+        };
+        if let Some(message) = message {
+            editor.set_status_message(message);
+        }
+        if let Some(message) = self.status_message {
+            editor.set_status_message(message);
+        }
+        Ok(task.run(editor))
+    }
+}
+
+/// The languages that can be chosen by name (with `--language` or `:set language`), and the
+/// languages of files with each extension.  Languages are added by
+/// [`register`](LanguageRegistry::register)ing them, and the [`Default`] registry contains every
+/// language that Sapling supports along with their usual extensions.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LanguageRegistry {
+    names: BTreeMap<&'static str, Language>,
+    /// The languages of files, indexed by their (lowercase) extensions
+    extensions: BTreeMap<String, Language>,
+}
+
+impl LanguageRegistry {
+    /// Creates a `LanguageRegistry` which doesn't contain any languages
+    pub fn empty() -> Self {
+        LanguageRegistry {
+            names: BTreeMap::new(),
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a language, which can then be chosen by its [`name`](Language::name) and is the
+    /// language of files ending in any of `extensions`.  Extensions that already belonged to a
+    /// language now belong to this one.
+    pub fn register(&mut self, language: Language, extensions: &[&str]) {
+        self.names.insert(language.name(), language);
+        for extension in extensions {
+            self.extensions.insert(extension.to_lowercase(), language);
+        }
+    }
+
+    /// Returns the name of every language, in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.names.keys().copied()
+    }
+
+    /// Returns the language called `name`, if there is one
+    pub fn from_name(&self, name: &str) -> Option<Language> {
+        self.names.get(name).copied()
+    }
+
+    /// Returns the language of the file at `path` according to its extension (ignoring case), or
+    /// `None` if the extension doesn't belong to any language
+    pub fn from_path(&self, path: &Path) -> Option<Language> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.extensions.get(&extension).copied()
+    }
+
+    /// Guesses the language that `text` is written in, for text with no file extension to go by
+    /// (e.g. text piped into standard input).  This is the first language that can parse `text`,
+    /// or `None` if none of them can.  The strictest languages are tried first.  Almost anything
+    /// is a list of S-expressions, so they're only tried (last) if `text` starts with a list or a
+    /// comment.
+    pub fn sniff(&self, text: &str) -> Option<Language> {
+        let could_be_sexpr = text.trim_start().starts_with(['(', ';']);
+        [
+            Language::JSON(Dialect::JSON),
+            Language::JSON(Dialect::JSONC),
+            Language::XML,
+            Language::TOML,
+            Language::Expr,
+            Language::SExpr,
+        ]
+        .iter()
+        .copied()
+        .filter(|language| self.names.contains_key(language.name()))
+        .filter(|language| *language != Language::SExpr || could_be_sexpr)
+        .find(|language| language.check(text).is_ok())
+    }
+
+    /// Asks the user which language the file at `path` is written in (because its extension
+    /// doesn't say), by writing a question to `output` and reading the answer from `input`.  An
+    /// empty answer (or no answer at all) means [`DEFAULT_LANGUAGE`], and the question is asked
+    /// again until the answer is the name of a language.
+    pub fn prompt(
+        &self,
+        path: &Path,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> std::io::Result<Language> {
+        let names: Vec<_> = self.names().collect();
+        writeln!(
+            output,
+            "Sapling doesn't know which language {} is written in.",
+            path.to_string_lossy()
+        )?;
+        loop {
+            write!(
+                output,
+                "Language ({}) [{}]: ",
+                names.join(", "),
+                DEFAULT_LANGUAGE.name()
+            )?;
+            output.flush()?;
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                return Ok(DEFAULT_LANGUAGE);
+            }
+            let answer = answer.trim();
+            if answer.is_empty() {
+                return Ok(DEFAULT_LANGUAGE);
+            }
+            match self.from_name(answer) {
+                Some(language) => return Ok(language),
+                None => writeln!(output, "Unknown language '{}'", answer)?,
+            }
+        }
+    }
+}
+
+impl Default for LanguageRegistry {
+    fn default() -> Self {
+        let mut registry = LanguageRegistry::empty();
+        registry.register(Language::JSON(Dialect::JSON), &["json"]);
+        registry.register(Language::JSON(Dialect::JSONC), &["jsonc"]);
+        registry.register(Language::SExpr, &["lisp", "scm"]);
+        registry.register(Language::TOML, &["toml"]);
+        registry.register(Language::XML, &["xml", "xhtml", "svg"]);
+        registry.register(Language::Expr, &[]);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Language, LanguageRegistry, Start, DEFAULT_LANGUAGE};
+    use crate::ast::json::Dialect;
+    use crate::config::options::Options;
+    use crate::config::{default_color_scheme, Config};
+    use crate::editor::script::{Script, ScriptOutput};
+    use std::path::Path;
+
+    #[test]
+    fn names() {
+        let registry = LanguageRegistry::default();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec!["expr", "json", "jsonc", "sexpr", "toml", "xml"]
+        );
+        // Every language can be chosen by its name
+        for language in &Language::all() {
+            assert_eq!(registry.from_name(language.name()), Some(*language));
+        }
+        assert_eq!(registry.from_name("JSON"), None);
+        assert_eq!(registry.from_name(""), None);
+    }
+
+    #[test]
+    fn extensions() {
+        let mut registry = LanguageRegistry::default();
+        for (path, language) in &[
+            ("file.json", Some(Language::JSON(Dialect::JSON))),
+            (
+                "dir.toml/settings.jsonc",
+                Some(Language::JSON(Dialect::JSONC)),
+            ),
+            ("Cargo.TOML", Some(Language::TOML)),
+            ("image.svg", Some(Language::XML)),
+            ("init.scm", Some(Language::SExpr)),
+            ("archive.tar.xml", Some(Language::XML)),
+            ("notes.txt", None),
+            ("Makefile", None),
+            (".json", None),
+        ] {
+            assert_eq!(registry.from_path(Path::new(path)), *language, "{}", path);
+        }
+        // Registering an extension again moves it to the new language
+        registry.register(Language::JSON(Dialect::JSONC), &["JSON", "code-workspace"]);
+        assert_eq!(
+            registry.from_path(Path::new("a.json")),
+            Some(Language::JSON(Dialect::JSONC))
+        );
+        assert_eq!(
+            registry.from_path(Path::new("a.code-workspace")),
+            Some(Language::JSON(Dialect::JSONC))
+        );
+        assert_eq!(
+            LanguageRegistry::empty().from_path(Path::new("a.json")),
+            None
+        );
+    }
+
+    #[test]
+    fn sniff() {
+        let registry = LanguageRegistry::default();
+        for (text, language) in &[
+            ("{\"a\": [1, null]}", Some(Language::JSON(Dialect::JSON))),
+            (
+                "// settings\n{\"a\": 1,}",
+                Some(Language::JSON(Dialect::JSONC)),
+            ),
+            ("(define x 1)", Some(Language::SExpr)),
+            ("[package]\nname = \"sapling\"", Some(Language::TOML)),
+            ("<a><b/></a>", Some(Language::XML)),
+            ("1 + x * 2", Some(Language::Expr)),
+            ("; a program\n(a b)", Some(Language::SExpr)),
+            ("(a", None),
+            // Broken JSON would parse as S-expressions, but it doesn't look like a list
+            ("{\"a\": }", None),
+        ] {
+            assert_eq!(registry.sniff(text), *language, "{}", text);
+        }
+        // Only registered languages are guessed
+        let mut registry = LanguageRegistry::empty();
+        registry.register(Language::XML, &["xml"]);
+        assert_eq!(registry.sniff("[1]"), None);
+    }
+
+    #[test]
+    fn check() {
+        for (language, valid, invalid) in &[
+            (Language::JSON(Dialect::JSON), "[1, 2]", "[1, 2,]"),
+            (Language::JSON(Dialect::JSONC), "[1, 2,] // ok", "[1, 2"),
+            (Language::SExpr, "(a b)", "(a b"),
+            (Language::TOML, "a = 1", "a = "),
+            (Language::XML, "<a/>", "<a>"),
+            (Language::Expr, "1 + x", "1 +"),
+        ] {
+            assert_eq!(language.check(valid), Ok(()), "{:?}", language);
+            assert!(language.check(invalid).is_err(), "{:?}", language);
+        }
+        assert_eq!(
+            Language::Expr.check("1 +"),
+            Err("1:4: expected an expression, found end of input".to_string())
+        );
+    }
+
+    #[test]
+    fn dot_graphs() {
+        let dot = |language: Language, text: &str| {
+            let mut out = Vec::new();
+            language
+                .write_dot(text, &mut out, false)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+        for (language, text, edge_count) in &[
+            (Language::JSON(Dialect::JSON), "[1, 2]", 2),
+            (Language::SExpr, "(a (b c))", 5),
+            (Language::Expr, "1 + x", 2),
+        ] {
+            let graph = dot(*language, text).unwrap();
+            assert!(graph.starts_with("digraph ast {\n"), "{:?}", language);
+            assert_eq!(graph.matches(" -> ").count(), *edge_count, "{:?}", language);
+        }
+        assert_eq!(
+            dot(Language::Expr, "1 +"),
+            Err("1:4: expected an expression, found end of input".to_string())
+        );
+    }
+
+    #[test]
+    fn printing() {
+        let print = |language: Language, text: &str, options: &Options| {
+            let mut out = Vec::new();
+            language
+                .print(text, options, None, &mut out)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+        let mut options = Options::default();
+        assert_eq!(
+            print(Language::JSON(Dialect::JSON), "[1,{}]", &options),
+            Ok("[\n    1,\n    {}\n]\n".to_string())
+        );
+        assert_eq!(
+            print(Language::TOML, "a=1", &options),
+            Ok("a = 1\n".to_string())
+        );
+        // The text is formatted as the options say
+        options.set("format", "compact").unwrap();
+        assert_eq!(
+            print(Language::JSON(Dialect::JSON), "[1, {}]", &options),
+            Ok("[1,{}]\n".to_string())
+        );
+        assert!(print(Language::XML, "<a>", &options).is_err());
+        // Colours are only used if there's a colour scheme
+        let mut out = Vec::new();
+        let color_scheme = default_color_scheme();
+        Language::SExpr
+            .print("(a)", &options, Some(&color_scheme), &mut out)
+            .unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with('\x1b'));
+    }
+
+    /// Runs a script on some text, returning the text that it ends with (or the patch of the
+    /// changes that it made)
+    fn run_script(
+        language: Language,
+        text: &str,
+        script: &str,
+        output: ScriptOutput,
+    ) -> Result<String, String> {
+        let start = Start::Text {
+            text: text.to_string(),
+            file_path: None,
+            dirty: false,
+        };
+        let script = Script::parse(script).map_err(|e| e.to_string())?;
+        let languages = LanguageRegistry::default();
+        language.run_script(start, Config::default(), languages, &script, output)
+    }
+
+    #[test]
+    fn scripts() {
+        let json = Language::JSON(Dialect::JSON);
+        for (language, text, script, expected) in &[
+            (json, "[1, 2, 3]", "", "[\n    1,\n    2,\n    3\n]\n"),
+            (json, "[1, [2, 3], 4]", "jlx", "[\n    1,\n    4\n]\n"),
+            (json, "[true]", "jrf", "[\n    false\n]\n"),
+            (json, "[1, 2]", "jxu<C-r>", "[\n    2\n]\n"),
+            (json, "[]", "oa", "[\n    []\n]\n"),
+            (json, "[1]", "jisiFoo<Esc>", "[\n    \"Foo\",\n    1\n]\n"),
+            (json, "[1]", ":set format=compact<CR>", "[1]\n"),
+            // Keys after `:q!` (which quits) are never run
+            (Language::SExpr, "(a b)", "jjx:q!<CR>x", "(b)\n"),
+            (Language::SExpr, "(a b)", "Qx", "(a b)\n"),
+            (Language::TOML, "a = 1\nb = 2", "jx", "b = 2\n"),
+        ] {
+            assert_eq!(
+                run_script(*language, text, script, ScriptOutput::Text),
+                Ok(expected.to_string()),
+                "{}",
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn script_errors() {
+        let json = Language::JSON(Dialect::JSON);
+        for (script, expected) in &[
+            (
+                "jjx",
+                "Script failed at position 1 ('j'): Can't move down if the cursor has no \
+                 children.",
+            ),
+            (
+                "j<C-x>",
+                "Script failed at position 1 ('<C-x>'): Invalid command '<C-x>'",
+            ),
+            (
+                "ia",
+                "Script failed at position 1 ('a'): Can't add siblings to the root.",
+            ),
+            (
+                "jisiab",
+                "Script failed at the end (position 6): the script ended whilst typing text \
+                 (which <Esc> would finish)",
+            ),
+            (
+                ":w",
+                "Script failed at the end (position 2): the script ended whilst typing a \
+                 command or search (which <CR> would run)",
+            ),
+            (
+                "jxQ",
+                "Script failed at position 2 ('Q'): No write since last change (add ! to \
+                 override).",
+            ),
+            (
+                ":set language=sexpr<CR>",
+                "Script failed at position 19 ('<CR>'): scripts can't switch languages",
+            ),
+        ] {
+            assert_eq!(
+                run_script(json, "[1]", script, ScriptOutput::Text),
+                Err(expected.to_string()),
+                "{}",
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn patch_scripts() {
+        let json = Language::JSON(Dialect::JSON);
+        let patch = |text, script| run_script(json, text, script, ScriptOutput::Patch);
+        assert_eq!(patch("[1, 2]", ""), Ok("[]\n".to_string()));
+        assert_eq!(
+            patch(r#"{"a/b": [1, 2]}"#, "jjljrtlx"),
+            Ok(
+                "[\n  {\"op\": \"replace\", \"path\": \"/a~1b/0\", \"value\": true},\n  \
+                {\"op\": \"remove\", \"path\": \"/a~1b/1\"}\n]\n"
+                    .to_string()
+            )
+        );
+        // Only JSON has JSON Patches
+        assert_eq!(
+            run_script(Language::TOML, "a = 1", "", ScriptOutput::Patch),
+            Err("Script failed at the end (position 0): Can't export toml as 'patch'.".to_string())
+        );
+    }
+
+    #[test]
+    fn prompt() {
+        let registry = LanguageRegistry::default();
+        let path = Path::new("notes.txt");
+        let ask = |answers: &str| {
+            let mut output = Vec::new();
+            let language = registry
+                .prompt(path, &mut answers.as_bytes(), &mut output)
+                .unwrap();
+            (language, String::from_utf8(output).unwrap())
+        };
+        let question = "Language (expr, json, jsonc, sexpr, toml, xml) [json]: ";
+        assert_eq!(
+            ask("toml\n"),
+            (
+                Language::TOML,
+                format!(
+                    "Sapling doesn't know which language notes.txt is written in.\n{}",
+                    question
+                )
+            )
+        );
+        // Unknown languages are asked about again
+        let (language, output) = ask("yaml\n  xml \n");
+        assert_eq!(language, Language::XML);
+        assert!(output.ends_with(&format!("Unknown language 'yaml'\n{}", question)));
+        // Not answering means the default language
+        assert_eq!(ask("\n").0, DEFAULT_LANGUAGE);
+        assert_eq!(ask("").0, DEFAULT_LANGUAGE);
+        assert_eq!(ask("yaml").0, DEFAULT_LANGUAGE);
+    }
+}
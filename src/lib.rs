@@ -0,0 +1,16 @@
+//! Sapling, a highly experimental code editor where you edit code, not text.
+//!
+//! The `sapling` binary (`src/main.rs`) only parses the command line and then hands over to these
+//! modules, so everything that it can do (including running an [`Editor`](editor::Editor)
+//! without a terminal) can also be done from tests.
+
+pub mod arena;
+pub mod ast;
+pub mod cli;
+pub mod config;
+pub mod editable_tree;
+pub mod editor;
+pub mod language;
+pub mod logging;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
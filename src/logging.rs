@@ -0,0 +1,278 @@
+//! Sapling's logger, which keeps the most recent log messages in memory (so that `:messages` can
+//! show them without leaving the editor) and, if `--log-file` or `RUST_LOG` is given, also
+//! appends them to a file with timestamps.  Nothing is ever written to the terminal, which would
+//! draw over the editor.
+//!
+//! `RUST_LOG` sets how much is logged (`error`, `warn`, `info`, `debug`, `trace` or `off`).  By
+//! default everything up to `debug` is kept.  Other crates only ever get to log warnings and
+//! errors, so that the messages are about Sapling.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The most messages that are kept in memory.  Once there are this many, each new message pushes
+/// out the oldest one.
+pub const MAX_MESSAGES: usize = 1000;
+
+/// The level that's logged if `RUST_LOG` isn't set
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// One message that has been logged
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Message {
+    pub level: Level,
+    /// The module that logged the message
+    pub target: String,
+    pub text: String,
+    /// When the message was logged, in milliseconds since the Unix epoch
+    pub time: u64,
+}
+
+impl Message {
+    /// Returns the message as one line of the log file, e.g.
+    /// `2021-03-04 05:06:07.089 WARN  sapling::config: ...`
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {:5} {}: {}",
+            timestamp(self.time),
+            self.level,
+            self.target,
+            self.text
+        )
+    }
+
+    /// Returns the message as it's shown by `:messages`, which only gives the time of day
+    pub fn to_short_line(&self) -> String {
+        let stamp = timestamp(self.time);
+        format!("{} {:5} {}", &stamp[11..19], self.level, self.text)
+    }
+}
+
+/// The most recent messages, oldest first, which drop the oldest message once there are more than
+/// `capacity` of them
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RingBuffer {
+    messages: VecDeque<Message>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    /// Creates an empty `RingBuffer` which holds at most `capacity` messages
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            messages: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Adds a message, pushing out the oldest one if the buffer is full
+    pub fn push(&mut self, message: Message) {
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        if self.capacity > 0 {
+            self.messages.push_back(message);
+        }
+    }
+
+    /// Returns the messages, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+}
+
+/// The logger that's given to the `log` crate by [`init`]
+struct Logger {
+    level: LevelFilter,
+    messages: Mutex<RingBuffer>,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let level = if metadata.target().starts_with("sapling") {
+            self.level
+        } else {
+            self.level.min(LevelFilter::Warn)
+        };
+        metadata.level() <= level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = Message {
+            level: record.level(),
+            target: record.target().to_string(),
+            text: record.args().to_string(),
+            time: now(),
+        };
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                // There's nowhere to report that the log couldn't be written
+                let _ = writeln!(file, "{}", message.to_line());
+            }
+        }
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push(message);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// The logger, once [`init`] has been called
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Starts logging messages up to `level`, appending them to the file at `log_file` as well if
+/// it's given.  Returns why not if the file can't be opened or logging has already been started.
+pub fn init(level: LevelFilter, log_file: Option<&Path>) -> Result<(), String> {
+    let file = match log_file {
+        Some(path) => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?;
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+    let logger = Logger {
+        level,
+        messages: Mutex::new(RingBuffer::new(MAX_MESSAGES)),
+        file,
+    };
+    if LOGGER.set(logger).is_err() {
+        return Err("Logging has already been started".to_string());
+    }
+    log::set_logger(LOGGER.get().unwrap()).map_err(|e| e.to_string())?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Returns the messages that have been logged, oldest first.  This is empty if [`init`] hasn't
+/// been called.
+pub fn messages() -> Vec<Message> {
+    LOGGER
+        .get()
+        .and_then(|logger| logger.messages.lock().ok())
+        .map_or(Vec::new(), |messages| {
+            messages.messages().cloned().collect()
+        })
+}
+
+/// Parses the level given by `RUST_LOG` (e.g. `debug`), ignoring case.  Returns `None` if it
+/// isn't a level.
+pub fn parse_level(text: &str) -> Option<LevelFilter> {
+    text.trim().parse().ok()
+}
+
+/// Returns the directory that Sapling keeps its logs in: `sapling` in `$XDG_STATE_HOME` (or
+/// `~/.local/state` if that isn't set)
+pub fn state_dir() -> Option<PathBuf> {
+    let state = match std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state.join("sapling"))
+}
+
+/// Returns the file that messages are logged to if `RUST_LOG` is set without `--log-file`:
+/// `sapling.log` in the [`state_dir`]
+pub fn log_path() -> Option<PathBuf> {
+    Some(state_dir()?.join("sapling.log"))
+}
+
+/// Returns the number of milliseconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64)
+}
+
+/// Writes a number of milliseconds since the Unix epoch as a UTC date and time, e.g.
+/// `2021-03-04 05:06:07.089`
+pub fn timestamp(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        millis % 1000
+    )
+}
+
+/// Returns the year, month and day of the date that's `days` days after 1970-01-01 (using
+/// Howard Hinnant's `civil_from_days`)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_level, timestamp, Message, RingBuffer};
+    use log::{Level, LevelFilter};
+
+    #[test]
+    fn messages() {
+        assert_eq!(timestamp(0), "1970-01-01 00:00:00.000");
+        assert_eq!(timestamp(951_827_696_789), "2000-02-29 12:34:56.789");
+        assert_eq!(timestamp(1_791_936_000_000), "2026-10-14 00:00:00.000");
+        assert_eq!(parse_level("DEBUG"), Some(LevelFilter::Debug));
+        assert_eq!(parse_level("off"), Some(LevelFilter::Off));
+        assert_eq!(parse_level("loud"), None);
+
+        let message = |text: &str| Message {
+            level: Level::Warn,
+            target: "sapling::config".to_string(),
+            text: text.to_string(),
+            time: 3_723_004,
+        };
+        assert_eq!(
+            message("Oops").to_line(),
+            "1970-01-01 01:02:03.004 WARN  sapling::config: Oops"
+        );
+        assert_eq!(message("Oops").to_short_line(), "01:02:03 WARN  Oops");
+
+        // Once the buffer is full, the oldest messages are dropped
+        let mut buffer = RingBuffer::new(2);
+        for text in &["a", "b", "c"] {
+            buffer.push(message(text));
+        }
+        let texts: Vec<_> = buffer.messages().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["b", "c"]);
+    }
+}
@@ -1,37 +1,275 @@
-pub mod arena;
-pub mod ast;
-pub mod editable_tree;
-pub mod editor;
-
-use crate::arena::Arena;
-use crate::ast::json::JSONFormat;
-use crate::ast::test_json::TestJSON;
-use crate::editable_tree::{cursor_path::CursorPath, DAG};
-use crate::editor::Editor;
+use sapling::cli::{self, Arguments, Input, Mode};
+use sapling::config::{self, Config};
+use sapling::editor::{
+    self,
+    session::{self, SessionFile},
+    swap::{self, SwapFile},
+};
+use sapling::language::{Exit, LanguageRegistry, Restore, Start, DEFAULT_LANGUAGE};
+use sapling::logging;
+use std::io::{IsTerminal, Write};
 
 fn main() {
-    // Initialise the logging and startup
-    pretty_env_logger::formatted_builder()
-        .filter_level(log::LevelFilter::Debug)
-        .init();
-    log::info!("Starting up...");
-
-    // Create an empty arena for Sapling to use
-    log::trace!("Creating arena");
-    let arena = Arena::new();
-    // For the time being, start the editor with some pre-made JSON
-    let root = TestJSON::Array(vec![
-        TestJSON::True,
-        TestJSON::False,
-        TestJSON::Object(vec![("value".to_string(), TestJSON::True)]),
-    ])
-    .add_to_arena(&arena);
-
-    let mut tree = DAG::new(&arena, root, CursorPath::root());
-    let editor = Editor::new(
-        &mut tree,
-        JSONFormat::Pretty,
-        editor::normal_mode::default_keymap(),
-    );
-    editor.run();
+    // Parse the command line arguments (see `sapling --help`), which override the options in the
+    // config file
+    let languages = LanguageRegistry::default();
+    let stdin_is_terminal = std::io::stdin().is_terminal();
+    let arguments = match Arguments::parse(std::env::args().skip(1), &languages, stdin_is_terminal)
+    {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Run 'sapling --help' to see every option.");
+            std::process::exit(cli::USAGE_EXIT_CODE);
+        }
+    };
+    match arguments.mode {
+        Mode::Help => {
+            println!("{}", cli::help(&languages));
+            return;
+        }
+        Mode::Version => {
+            println!("{}", cli::VERSION);
+            return;
+        }
+        _ => {}
+    }
+
+    // Start logging.  The log is always kept for `:messages`, but it's only written to a file if
+    // the user asks for one (with `--log-file` or `RUST_LOG`).
+    let rust_log = std::env::var("RUST_LOG").ok();
+    let level = rust_log
+        .as_deref()
+        .and_then(logging::parse_level)
+        .unwrap_or(logging::DEFAULT_LEVEL);
+    let log_file = arguments
+        .log_file
+        .clone()
+        .or_else(|| rust_log.as_ref().and_then(|_| logging::log_path()));
+    if let Err(e) = logging::init(level, log_file.as_deref()) {
+        eprintln!("Couldn't start logging: {}", e);
+        std::process::exit(1);
+    }
+    log::info!("Starting {}", cli::VERSION);
+    // If Sapling crashes, the terminal is given back before the panic is reported, and the report
+    // (with a backtrace) also goes to the crash log
+    editor::crash::install_hook(editor::crash::crash_log_path());
+
+    // Standard input is read if it's given as the file (`-`), or if something is piped into
+    // Sapling without a file to edit.  The editor reads keys from the terminal itself, so it
+    // still works once standard input has been used up.
+    let stdin_text = if arguments.input == Input::Stdin {
+        match std::io::read_to_string(std::io::stdin()) {
+            // There's no tree to edit (or guess the language of) in nothing at all
+            Ok(text) if text.trim().is_empty() => {
+                eprintln!("Standard input is empty, so there's no tree to read.");
+                std::process::exit(1);
+            }
+            Ok(text) => Some(text),
+            Err(e) => {
+                eprintln!("Couldn't read standard input: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    // If no language was given, then it's chosen by the file's extension (or the extension of
+    // the old file of a diff).  If the extension doesn't belong to any language, then the user is
+    // asked (or JSON is used, if there's no one to ask).  Standard input has no extension, so its
+    // language is guessed from its text.
+    let mut language = match (arguments.language, arguments.first_path()) {
+        (Some(language), _) => language,
+        (None, _) if stdin_text.is_some() => stdin_text
+            .as_deref()
+            .and_then(|text| languages.sniff(text))
+            .unwrap_or(DEFAULT_LANGUAGE),
+        (None, None) => DEFAULT_LANGUAGE,
+        (None, Some(path)) => match languages.from_path(path) {
+            Some(language) => language,
+            None if stdin_is_terminal => {
+                let stdin = std::io::stdin();
+                languages
+                    .prompt(path, &mut stdin.lock(), &mut std::io::stdout())
+                    .unwrap_or(DEFAULT_LANGUAGE)
+            }
+            None => DEFAULT_LANGUAGE,
+        },
+    };
+
+    // Load the config file (`--config`, or the default one if it exists)
+    let mut config_warnings = Vec::new();
+    let config_path = match &arguments.config_path {
+        Some(path) => Some(path.clone()),
+        None => config::config_path().filter(|path| path.exists()),
+    };
+    let mut config = match config_path {
+        Some(path) => match Config::load(&path) {
+            Ok((config, warnings)) => {
+                log::info!("Loaded config from {}", path.to_string_lossy());
+                for w in &warnings {
+                    log::warn!("{}: {}", path.to_string_lossy(), w);
+                }
+                config_warnings = warnings;
+                config
+            }
+            Err(e) => {
+                eprintln!("Couldn't load config {}: {}", path.to_string_lossy(), e);
+                std::process::exit(1);
+            }
+        },
+        None => Config::default(),
+    };
+    arguments.apply_to(&mut config);
+
+    // Only use colours that the terminal can actually display
+    config.color_scheme = config
+        .color_scheme
+        .quantized(config::color::ColorSupport::detect());
+
+    // `--dump-dot` and `--print` write something about the file to stdout instead of editing it
+    if let Mode::DumpDot | Mode::Print { .. } = arguments.mode {
+        // The arguments have been checked, so there's exactly one thing to read
+        let (name, text) = match (&stdin_text, arguments.first_path()) {
+            (Some(text), _) => ("standard input".to_string(), Ok(text.clone())),
+            (None, Some(path)) => (
+                path.to_string_lossy().into_owned(),
+                std::fs::read_to_string(path).map_err(|e| e.to_string()),
+            ),
+            (None, None) => unreachable!("--print and --dump-dot always have a file to read"),
+        };
+        let stdout = std::io::stdout();
+        let result = text.and_then(|text| {
+            let mut out = stdout.lock();
+            match arguments.mode {
+                Mode::Print { color } => {
+                    let color = color.unwrap_or_else(|| {
+                        config::color::use_color(
+                            std::env::var_os("NO_COLOR").as_deref(),
+                            std::io::stdout().is_terminal(),
+                        )
+                    });
+                    let color_scheme = Some(&config.color_scheme).filter(|_| color);
+                    language.print(&text, &config.options, color_scheme, &mut out)
+                }
+                _ => {
+                    let colored = config.options.debug_highlighting;
+                    language.write_dot(&text, &mut out, colored)
+                }
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Couldn't read {}: {}", name, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Edit the files (or compare the two files) that were given, or an example tree if there
+    // aren't any
+    let start = match (stdin_text, arguments.input) {
+        (Some(text), _) => Start::Stdin(text),
+        (None, Input::Diff { old, new }) => Start::Diff { old, new },
+        (None, Input::Files(mut files)) if files.len() == 1 => Start::File(files.remove(0)),
+        (None, Input::Files(files)) => Start::Files(files),
+        (None, Input::Example) | (None, Input::Stdin) => Start::Example,
+    };
+
+    // `--script` edits the file without a terminal, and then writes the result (or the patch of
+    // its changes) to `--output` (or stdout)
+    if let Mode::Script {
+        script,
+        output,
+        output_path,
+    } = arguments.mode
+    {
+        let result = language
+            .run_script(start, config, languages, &script, output)
+            .and_then(|text| match &output_path {
+                Some(path) => editor::file::write_atomically(path, &text)
+                    .map_err(|e| format!("Couldn't write {}: {}", path.to_string_lossy(), e)),
+                None => std::io::stdout()
+                    .write_all(text.as_bytes())
+                    .map_err(|e| e.to_string()),
+            });
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Make sure that the user knows about any problems with their config
+    let status_message = if config_warnings.is_empty() {
+        None
+    } else {
+        let messages: Vec<_> = config_warnings.iter().map(|w| w.to_string()).collect();
+        Some(format!("Config warnings: {}", messages.join("; ")))
+    };
+
+    // If Sapling crashed whilst editing the same file, offer to recover the changes from its swap
+    // file.  Otherwise, if Sapling last quit whilst editing the same file, offer to carry on
+    // where it left off.
+    let recover = match &start {
+        Start::File(path) if stdin_is_terminal => {
+            SwapFile::read(&swap::swap_path(path)).ok().filter(|swap| {
+                let stdin = std::io::stdin();
+                let recover = swap
+                    .prompt(&mut stdin.lock(), &mut std::io::stdout())
+                    .unwrap_or(false);
+                // Otherwise the changes are thrown away, so that a new swap file can be started
+                if !recover {
+                    if let Err(e) = swap.remove() {
+                        eprintln!("Couldn't delete {}: {}", swap.path().display(), e);
+                    }
+                }
+                recover
+            })
+        }
+        _ => None,
+    };
+    let mut restore = match (&start, session::session_path()) {
+        _ if recover.is_some() => recover.map(Restore::Swap),
+        (Start::File(path), Some(session_path)) if stdin_is_terminal => {
+            SessionFile::read(&session_path)
+                .ok()
+                .and_then(|sessions| sessions.session_for(path, language).cloned())
+                .filter(|saved| {
+                    let stdin = std::io::stdin();
+                    saved
+                        .prompt(&mut stdin.lock(), &mut std::io::stdout())
+                        .unwrap_or(false)
+                })
+                .map(Restore::Session)
+        }
+        _ => None,
+    };
+
+    // Run an editor for the chosen language, and then for every language that the user switches
+    // to
+    let mut start = start;
+    let mut status_message = status_message;
+    while let Exit::SwitchLanguage {
+        language: new_language,
+        text,
+        file_path,
+        dirty,
+        registers,
+    } = language.edit(
+        start,
+        config.clone(),
+        languages.clone(),
+        status_message,
+        restore.take(),
+    ) {
+        log::info!("Switching to {}", new_language.name());
+        status_message = Some(format!("Now editing as {}", new_language.name()));
+        language = new_language;
+        restore = Some(Restore::Registers(registers));
+        start = Start::Text {
+            text,
+            file_path,
+            dirty,
+        };
+    }
 }
@@ -0,0 +1,305 @@
+//! Generators of pseudo-random trees and edits, and checks of the invariants that every edit has
+//! to keep, for testing [`Ast`] implementations.  This is only compiled with the `testing`
+//! feature (or for Sapling's own tests), so that other implementations of [`Ast`] can reuse it
+//! from their tests.
+//!
+//! Everything here is driven by a seeded [`Rng`], so a failure can always be reproduced by
+//! running with the same seed again.
+
+use crate::arena::Arena;
+use crate::ast::display_token::validate_token_stream;
+use crate::ast::json::JSON;
+use crate::ast::test_json::random_string;
+use crate::ast::Ast;
+use crate::editable_tree::cursor_path::CursorPath;
+use crate::editable_tree::{EditResult, DAG};
+use crate::editor::normal_mode::Action;
+
+pub use crate::ast::test_json::Rng;
+
+/// Limits on how big a generated tree can get
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Bounds {
+    /// The maximum [`depth`](Ast::depth) of the tree
+    pub max_depth: usize,
+    /// The maximum number of nodes in the tree (though a tree always contains at least its root)
+    pub max_nodes: usize,
+}
+
+/// Generates a pseudo-random [`JSON`] tree within some [`Bounds`], allocating its nodes straight
+/// into `arena`.  Strings are generated in the same way as [`TestJSON::random`] generates them.
+///
+/// [`TestJSON::random`]: crate::ast::test_json::TestJSON::random
+pub fn random_json<'arena>(
+    rng: &mut Rng,
+    arena: &'arena Arena<JSON<'arena>>,
+    bounds: Bounds,
+) -> &'arena JSON<'arena> {
+    // The root is always generated, even if it goes over the budget
+    let mut budget = bounds.max_nodes.saturating_sub(1);
+    random_json_node(rng, arena, bounds.max_depth, &mut budget)
+}
+
+/// Generates a node which has at most `depth` levels below it, taking the nodes of its
+/// descendants out of `budget`
+fn random_json_node<'arena>(
+    rng: &mut Rng,
+    arena: &'arena Arena<JSON<'arena>>,
+    depth: usize,
+    budget: &mut usize,
+) -> &'arena JSON<'arena> {
+    // The value of a field is two levels below its object, so objects can only be generated
+    // where there's room for that
+    let kinds = match depth {
+        0 => 5,
+        1 => 6,
+        _ => 7,
+    };
+    let node = match rng.below(kinds) {
+        0 => JSON::True,
+        1 => JSON::False,
+        2 => JSON::Null,
+        3 => JSON::Number((rng.next_u64() % 100_000).to_string()),
+        4 => JSON::Str(random_string(rng)),
+        5 => {
+            let mut children = Vec::new();
+            for _ in 0..rng.below(5) {
+                if *budget == 0 {
+                    break;
+                }
+                *budget -= 1;
+                children.push(random_json_node(rng, arena, depth - 1, budget));
+            }
+            JSON::Array(children)
+        }
+        _ => {
+            let mut fields = Vec::new();
+            // Every field costs three nodes: the field itself, its key and its value
+            for _ in 0..rng.below(5) {
+                if *budget < 3 {
+                    break;
+                }
+                *budget -= 3;
+                let key = arena.alloc(JSON::Str(random_string(rng)));
+                let value = random_json_node(rng, arena, depth - 2, budget);
+                fields.push(arena.alloc(JSON::Field([key, value])));
+            }
+            JSON::Object(fields)
+        }
+    };
+    arena.alloc(node)
+}
+
+/// An edit to make to a tree: moving the cursor to `path` and then executing `action`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Edit {
+    pub path: CursorPath,
+    pub action: Action,
+}
+
+impl Edit {
+    /// Generates a pseudo-random insertion, deletion or replacement of a random node of the tree
+    /// under `root`.  The path always exists in the tree, and the chars are usually ones that
+    /// the nodes involved say they accept (but not always, so that edits which fail get tested
+    /// too).
+    pub fn random<'arena, Node: Ast<'arena>>(rng: &mut Rng, root: &'arena Node) -> Edit {
+        // Walk down from the root, stopping at each node with as much chance as going into any
+        // one of its children
+        let mut path = CursorPath::root();
+        let mut parent = None;
+        let mut cursor = root;
+        loop {
+            let children = cursor.children();
+            let index = rng.below(children.len() + 1);
+            if index == children.len() {
+                break;
+            }
+            path.push(index);
+            parent = Some(cursor);
+            cursor = children[index];
+        }
+
+        let kind = rng.below(5);
+        let mut random_char = |chars: Box<dyn Iterator<Item = char>>| -> char {
+            let chars: Vec<char> = chars.collect();
+            if chars.is_empty() || rng.below(8) == 0 {
+                // Any printable ASCII char
+                (b' ' + rng.below(95) as u8) as char
+            } else {
+                chars[rng.below(chars.len())]
+            }
+        };
+        let sibling_chars = parent.unwrap_or(root).insert_chars();
+        let action = match kind {
+            0 => Action::InsertBefore(random_char(sibling_chars)),
+            1 => Action::InsertAfter(random_char(sibling_chars)),
+            2 => Action::InsertChild(random_char(cursor.insert_chars())),
+            3 => Action::Replace(random_char(cursor.replace_chars())),
+            _ => Action::Delete,
+        };
+        Edit { path, action }
+    }
+
+    /// Makes this edit to a tree, returning the result of executing the [`Action`]
+    pub fn apply<'arena, Node: Ast<'arena>>(&self, tree: &mut DAG<'arena, Node>) -> EditResult {
+        tree.goto(self.path.clone())?;
+        tree.execute_action(self.action.clone()).1
+    }
+}
+
+impl std::fmt::Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} at {}", self.action, self.path)
+    }
+}
+
+/// Checks that a tree is valid: every node is a valid child of its parent (see
+/// [`Ast::is_valid_child`]), and the display tokens of every node are balanced (see
+/// [`validate_token_stream`]).
+pub fn check_tree<'arena, Node: Ast<'arena>>(
+    root: &'arena Node,
+    format_style: &Node::FormatStyle,
+) -> Result<(), String> {
+    for node in root.iter_pre_order() {
+        for (index, child) in node.children().iter().enumerate() {
+            if !node.is_valid_child(index, child) {
+                return Err(format!(
+                    "{} can't be child {} of {}",
+                    child.display_name(),
+                    index,
+                    node.display_name()
+                ));
+            }
+        }
+    }
+    validate_token_stream(root, format_style).map_err(|e| e.to_string())
+}
+
+/// Makes an edit to a tree, checking that either:
+/// - the edit succeeds, leaving a valid tree (see [`check_tree`]), and undoing it restores the
+///   previous text (after which it is redone), or
+/// - the edit fails, leaving the tree untouched.
+///
+/// This returns the result of the edit, or describes which of these didn't hold.
+pub fn check_edit<'arena, Node: Ast<'arena>>(
+    tree: &mut DAG<'arena, Node>,
+    edit: &Edit,
+    format_style: &Node::FormatStyle,
+) -> Result<EditResult, String> {
+    let root_before = tree.root();
+    let text_before = tree.to_text(format_style);
+    let result = edit.apply(tree);
+    let text_after = tree.to_text(format_style);
+    match &result {
+        Err(e) => {
+            if !std::ptr::eq(tree.root(), root_before) || text_after != text_before {
+                return Err(format!("{} failed ({}) but changed the tree", edit, e));
+            }
+        }
+        Ok(_) => {
+            check_tree(tree.root(), format_style)
+                .map_err(|e| format!("{} left an invalid tree: {}", edit, e))?;
+            // Edits that don't change the tree don't get added to the undo history
+            if !std::ptr::eq(tree.root(), root_before) {
+                tree.undo()
+                    .map_err(|e| format!("{} couldn't be undone: {}", edit, e))?;
+                if tree.to_text(format_style) != text_before {
+                    return Err(format!("undoing {} didn't restore the text", edit));
+                }
+                tree.redo()
+                    .map_err(|e| format!("{} couldn't be redone: {}", edit, e))?;
+                if tree.to_text(format_style) != text_after {
+                    return Err(format!("redoing {} didn't restore the text", edit));
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_edit, check_tree, random_json, Bounds, Edit, Rng};
+    use crate::arena::Arena;
+    use crate::ast::display_token::IndentKind;
+    use crate::ast::json::{JSONFormat, JSON};
+    use crate::ast::size::Size;
+    use crate::ast::Ast;
+    use crate::editable_tree::cursor_path::CursorPath;
+    use crate::editable_tree::DAG;
+
+    const BOUNDS: Bounds = Bounds {
+        max_depth: 4,
+        max_nodes: 40,
+    };
+
+    fn formats() -> Vec<JSONFormat> {
+        vec![
+            JSONFormat::Compact,
+            JSONFormat::Pretty {
+                indent: IndentKind::Spaces(2),
+                trailing_commas: false,
+            },
+            JSONFormat::Fit {
+                indent: IndentKind::Tabs,
+                max_width: 30,
+                trailing_commas: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn bounds() {
+        let mut rng = Rng::new(0);
+        for &(max_depth, max_nodes) in &[(0, 10), (1, 10), (3, 1), (4, 40), (10, 200)] {
+            let bounds = Bounds {
+                max_depth,
+                max_nodes,
+            };
+            for _ in 0..200 {
+                let arena = Arena::new();
+                let root = random_json(&mut rng, &arena, bounds);
+                assert!(root.depth() <= max_depth);
+                assert!(root.node_count() <= max_nodes.max(1));
+                assert_eq!(check_tree(root, &JSONFormat::Compact), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Rng::new(0x5eed);
+        for _ in 0..300 {
+            let arena = Arena::new();
+            let root = random_json(&mut rng, &arena, BOUNDS);
+            for format in &formats() {
+                let text = root.to_text(format);
+                assert_eq!(JSON::parse(&text, &arena).as_ref(), Ok(&root), "{}", text);
+                assert_eq!(root.size(format), Size::from(text.as_str()), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn random_edits() {
+        let mut rng = Rng::new(42);
+        let format = JSONFormat::Compact;
+        let (mut successes, mut failures) = (0, 0);
+        for _ in 0..200 {
+            let arena = Arena::new();
+            let root = random_json(&mut rng, &arena, BOUNDS);
+            let mut tree = DAG::new(&arena, root, CursorPath::root());
+            for _ in 0..30 {
+                let edit = Edit::random(&mut rng, tree.root());
+                match check_edit(&mut tree, &edit, &format) {
+                    Ok(Ok(_)) => successes += 1,
+                    Ok(Err(_)) => failures += 1,
+                    Err(e) => panic!("{}\nin {}", e, tree.to_text(&format)),
+                }
+            }
+        }
+        // Both kinds of edit should be common enough to be tested properly
+        assert!(successes > 1000, "{}", successes);
+        assert!(failures > 500, "{}", failures);
+    }
+}
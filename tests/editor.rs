@@ -0,0 +1,1725 @@
+//! Scenarios which drive an [`Editor`] with keys, exactly as if they had been typed into the
+//! terminal, and check the text, cursor and messages that it ends up with.
+
+use sapling::arena::Arena;
+use sapling::ast::display_token::IndentKind;
+use sapling::ast::json::{Dialect, JSONFormat, JSON};
+use sapling::config::Config;
+use sapling::editable_tree::{EditErr, Side};
+use sapling::editor::buffer::{Buffer, BufferLoader};
+use sapling::editor::script::Script;
+use sapling::editor::session::{SessionError, SessionFile};
+use sapling::editor::swap::{self, SwapError, SwapFile};
+use sapling::editor::Editor;
+use sapling::language::{Language, LanguageRegistry};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tuikit::prelude::{Key, MouseButton};
+
+/// The arenas that hold the nodes of every buffer of an editor of JSON
+type Arenas<'arena> = typed_arena::Arena<Arena<JSON<'arena>>>;
+
+/// Parses JSON into a tree, for the buffers of an editor
+fn parse<'arena>(
+    text: &str,
+    arena: &'arena Arena<JSON<'arena>>,
+) -> Result<&'arena JSON<'arena>, String> {
+    JSON::parse(text, arena).map_err(|e| e.to_string())
+}
+
+/// Creates an editor of a JSON tree, with the default config
+fn editor<'arena>(arenas: &'arena Arenas<'arena>, text: &str) -> Editor<'arena, JSON<'arena>> {
+    editor_with_config(arenas, text, Config::default())
+}
+
+/// Creates an editor of a JSON tree
+fn editor_with_config<'arena>(
+    arenas: &'arena Arenas<'arena>,
+    text: &str,
+    config: Config,
+) -> Editor<'arena, JSON<'arena>> {
+    let loader = BufferLoader::new(arenas, parse);
+    let buffer = loader.parse(text, None).unwrap();
+    editor_of_buffer(loader, buffer, config)
+}
+
+/// Creates an editor of the JSON file at `path`, with the default config
+fn editor_of_file<'arena>(
+    arenas: &'arena Arenas<'arena>,
+    path: &Path,
+) -> Editor<'arena, JSON<'arena>> {
+    let loader = BufferLoader::new(arenas, parse);
+    let buffer = loader.load(path).unwrap();
+    editor_of_buffer(loader, buffer, Config::default())
+}
+
+/// Creates an editor which starts by editing `buffer`
+fn editor_of_buffer<'arena>(
+    loader: BufferLoader<'arena, JSON<'arena>>,
+    buffer: Buffer<'arena, JSON<'arena>>,
+    config: Config,
+) -> Editor<'arena, JSON<'arena>> {
+    let format_style = JSONFormat::Pretty {
+        indent: IndentKind::Spaces(4),
+        trailing_commas: false,
+    };
+    Editor::new(
+        buffer,
+        loader,
+        format_style,
+        config,
+        Language::JSON(Dialect::JSON),
+        LanguageRegistry::default(),
+    )
+}
+
+/// Presses every key of `keys` (which are written like a `--script`), returning the error of
+/// the first key that failed (if any did) and whether any key quit Sapling
+fn press<'arena>(editor: &mut Editor<'arena, JSON<'arena>>, keys: &str) -> (Option<EditErr>, bool) {
+    let mut first_error = None;
+    for &(_, key) in Script::parse(keys).unwrap().keys() {
+        let (should_quit, result) = editor.handle_key(key);
+        if let Some(Err(e)) = result {
+            first_error = first_error.or(Some(e));
+        }
+        if should_quit {
+            return (first_error, true);
+        }
+    }
+    (first_error, false)
+}
+
+/// Asserts that a sequence of keys leaves the editor with some text and its cursor at a given
+/// path, without any keys failing
+fn assert_keys(text: &str, keys: &str, expected_text: &str, expected_cursor: &str) {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, text);
+    let (error, _) = press(&mut editor, keys);
+    assert_eq!(error, None, "{}", keys);
+    assert_eq!(editor.text(), expected_text, "{}", keys);
+    assert_eq!(
+        editor.cursor_path().to_string(),
+        expected_cursor,
+        "{}",
+        keys
+    );
+}
+
+/// Asserts that a sequence of keys fails at its last keystroke, and that the bottom bar shows
+/// the error
+fn assert_error(text: &str, keys: &str, expected_message: &str) {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, text);
+    let (error, _) = press(&mut editor, keys);
+    assert!(error.is_some(), "{}", keys);
+    assert_eq!(editor.status_message(), Some(expected_message), "{}", keys);
+}
+
+/* ===== INSERTION ===== */
+
+#[test]
+fn insert_child() {
+    // The cursor stays where it was
+    assert_keys("[]", "ot", "[\n    true\n]\n", "root");
+}
+
+#[test]
+fn insert_siblings() {
+    assert_keys(
+        "[true]",
+        "jafin",
+        "[\n    null,\n    true,\n    false\n]\n",
+        "0",
+    );
+}
+
+#[test]
+fn insert_invalid_child() {
+    // A yanked field can be pasted into an object, but not into an array
+    assert_error("[{\"a\": 1}]", "jjykp", "field can't be a child of array");
+}
+
+#[test]
+fn type_into_string() {
+    assert_keys("[1]", "jisiab<BS>c<Esc>", "[\n    \"ac\",\n    1\n]\n", "0");
+}
+
+#[test]
+fn type_into_number() {
+    // Each digit is typed once, rather than being read as a count
+    assert_keys("[1, 2.50]", "jl5", "[\n    1,\n    2.505\n]\n", "1");
+    assert_keys("[1, 2, 3]", "j2", "[\n    12,\n    2,\n    3\n]\n", "0");
+    // Backspace removes the last digit, and typing then carries on from there
+    assert_keys("[12, 3]", "j<BS>5", "[\n    15,\n    3\n]\n", "0");
+    assert_keys(
+        "[1, 2, 3]",
+        "jl2<A-k>",
+        "[\n    22,\n    1,\n    3\n]\n",
+        "0",
+    );
+    assert_keys("[1, 2, 3]", "j3x", "[\n    2,\n    3\n]\n", "0");
+    // A count is given after a register instead
+    assert_keys("[1, 2, 3, 4]", "j\"\"3x", "[\n    4\n]\n", "0");
+    assert_keys(
+        "[1, 2, 3, 4]",
+        "j\"\"2l",
+        "[\n    1,\n    2,\n    3,\n    4\n]\n",
+        "2",
+    );
+    // `.`, `e`, `+` and `-` only carry on typing into a number, so otherwise `.` repeats the last
+    // edit as usual
+    assert_keys("[1, 2, 3]", "jx.", "[\n    3\n]\n", "0");
+    assert_keys(
+        "[1, 2, 3]",
+        "j5.5e-1",
+        "[\n    15.5e-1,\n    2,\n    3\n]\n",
+        "0",
+    );
+    assert_keys("[1, 2, 3]", "j5x.", "[\n    3\n]\n", "0");
+    // A number made out of a digit can be typed into straight away
+    assert_keys("[1, 2]", "jr0.5<Esc>", "[\n    0.5,\n    2\n]\n", "0");
+    assert_keys("[1, 2]", "ja0e3", "[\n    1,\n    0e3,\n    2\n]\n", "0");
+    assert_keys("[1, 2]", "ji5-", "[\n    -5,\n    1,\n    2\n]\n", "0");
+    assert_keys("[]", "o1.5", "[\n    1.5\n]\n", "root");
+    // `-` starts a negative number, which the digits after it are typed into
+    assert_keys("[1, 2]", "ja-5", "[\n    1,\n    -5,\n    2\n]\n", "0");
+    assert_keys("[1, 2]", "jr-0.5", "[\n    -0.5,\n    2\n]\n", "0");
+    assert_keys("[]", "o-12<BS>3", "[\n    -13\n]\n", "root");
+    // Backspace also edits the new number, and undoing leaves the cursor where it was
+    assert_keys("[1, 2]", "ja12<BS>3", "[\n    1,\n    13,\n    2\n]\n", "0");
+    assert_keys("[1, 2]", "jla12u", "[\n    1,\n    2,\n    1\n]\n", "1");
+    assert_keys("[1, 2]", "jr0x.", "[]\n", "root");
+    assert_error("[1, 2]", "j.", "There's no edit to repeat yet.");
+}
+
+#[test]
+fn move_node() {
+    assert_keys(
+        "[true, false, null]",
+        "j<A-j>",
+        "[\n    false,\n    true,\n    null\n]\n",
+        "1",
+    );
+    // Moving past the first or last sibling does nothing
+    assert_keys(
+        "[true, false, null]",
+        "jll<A-j>",
+        "[\n    true,\n    false,\n    null\n]\n",
+        "2",
+    );
+    assert_keys(
+        "[true, false, null]",
+        "j<A-k>",
+        "[\n    true,\n    false,\n    null\n]\n",
+        "0",
+    );
+    // Counted moves stop at the first or last sibling
+    assert_keys(
+        "[true, false, null]",
+        "j5<A-j>",
+        "[\n    false,\n    null,\n    true\n]\n",
+        "2",
+    );
+    assert_keys(
+        "[true, false, null]",
+        "jl5<A-k>",
+        "[\n    false,\n    true,\n    null\n]\n",
+        "0",
+    );
+}
+
+#[test]
+fn wrap_and_unwrap() {
+    assert_keys("[1]", "jsa", "[\n    [\n        1\n    ]\n]\n", "0");
+    assert_keys("[[1]]", "jS", "[\n    1\n]\n", "0");
+}
+
+#[test]
+fn nesting_limit() {
+    // Nodes can't be nested deeply enough to overflow the stack, and counted edits that would go
+    // too deep leave the tree alone
+    let message = "Can't nest nodes more than 256 levels below the root.";
+    assert_error("[\"x\"]", "j10000sa", message);
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[\"x\"]");
+    assert_eq!(press(&mut editor, "j10000sa").0, Some(EditErr::TooDeep));
+    assert_eq!(editor.text(), "[\n    \"x\"\n]\n");
+    // The string can be wrapped until it's as deep as it can go ...
+    assert_eq!(press(&mut editor, "255sa").0, None);
+    let text = editor.text();
+    // ... and then neither wrapping the nodes above it nor pasting them any deeper is allowed
+    assert_eq!(press(&mut editor, "jsa").0, Some(EditErr::TooDeep));
+    assert_eq!(press(&mut editor, "yjp").0, Some(EditErr::TooDeep));
+    assert_eq!(editor.text(), text);
+}
+
+/* ===== DELETION ===== */
+
+#[test]
+fn delete() {
+    assert_keys("[1, 2, 3]", "jlx", "[\n    1,\n    3\n]\n", "1");
+    assert_keys("[1, 2, 3]", "Gx", "[\n    1,\n    2\n]\n", "1");
+}
+
+#[test]
+fn yank_and_paste() {
+    assert_keys(
+        "[1, [2]]",
+        "jylp",
+        "[\n    1,\n    [\n        2\n    ],\n    1\n]\n",
+        "1",
+    );
+}
+
+#[test]
+fn visual_mode() {
+    // Deleting a selection cuts every selected sibling, as one change
+    let numbers = "[\n    1,\n    2,\n    3,\n    4\n]\n";
+    assert_keys("[1, 2, 3, 4]", "jlvld", "[\n    1,\n    4\n]\n", "1");
+    assert_keys("[1, 2, 3, 4]", "jlvldu", numbers, "2");
+    // ... so that they can be pasted somewhere else, in the same order
+    assert_keys(
+        "[1, 2, 3, 4]",
+        "jllvlxhP",
+        "[\n    3,\n    4,\n    1,\n    2\n]\n",
+        "0",
+    );
+    // Yanking moves the cursor to the first selected sibling, and pasting a selection goes after
+    // its last sibling (or before its first)
+    assert_keys(
+        "[1, 2, 3]",
+        "jvlylvp",
+        "[\n    1,\n    2,\n    1,\n    2,\n    3\n]\n",
+        "1",
+    );
+    assert_keys(
+        "[1, 2, 3]",
+        "jvlyllvhP",
+        "[\n    1,\n    1,\n    2,\n    2,\n    3\n]\n",
+        "1",
+    );
+    // Escape (or `v`) stops selecting
+    assert_keys("[1, 2, 3]", "jvl<Esc>x", "[\n    1,\n    3\n]\n", "1");
+    assert_keys("[1, 2, 3]", "jvlvx", "[\n    1,\n    3\n]\n", "1");
+}
+
+#[test]
+fn invalid_selections() {
+    assert_error("[1]", "v", "The root has no siblings to select.");
+    // The cursor can't leave the siblings that the selection started among
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[[1], 2]");
+    let (error, _) = press(&mut editor, "jvj");
+    assert_eq!(
+        error.unwrap().to_string(),
+        "Can't select nodes with different parents (press Esc to stop selecting)."
+    );
+    assert_eq!(editor.cursor_path().to_string(), "0");
+    let (error, _) = press(&mut editor, "oa");
+    assert_eq!(
+        error.unwrap().to_string(),
+        "Can't insert 'a' as last child whilst nodes are selected."
+    );
+    // A field needs both its key and its value, so they can't be deleted
+    assert_error(
+        "{\"a\": 1}",
+        "jjvld",
+        "Node type field can't have fewer than 2 children.",
+    );
+}
+
+#[test]
+fn registers() {
+    // Named registers keep what was yanked into them, whatever is yanked afterwards
+    assert_keys(
+        "[1, 2, 3]",
+        "j\"ayly\"ap",
+        "[\n    1,\n    2,\n    1,\n    3\n]\n",
+        "1",
+    );
+    // ... and a plain `p` pastes whatever was yanked or cut last
+    assert_keys(
+        "[1, 2, 3]",
+        "j\"aylxp",
+        "[\n    1,\n    3,\n    2\n]\n",
+        "1",
+    );
+    assert_error(
+        "[1]",
+        "j\"bp",
+        "Nothing has been yanked into register 'b' yet.",
+    );
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1, [true, null]]");
+    press(&mut editor, ":registers<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("Nothing has been yanked or cut yet.")
+    );
+    press(&mut editor, "j\"zyl\"by:registers<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("\"\" [true,null] | \"b [true,null] | \"z 1")
+    );
+}
+
+#[test]
+fn clipboard() {
+    // Without a terminal, OSC 52 can't reach the real clipboard, so pasting gives back whatever
+    // was copied
+    let osc52 = ":set clipboard=osc52<CR>";
+    assert_keys(
+        "[1, [true, null]]",
+        &format!("{}j\"+yl\"+p", osc52),
+        "[\n    1,\n    [\n        true,\n        null\n    ],\n    1\n]\n",
+        "1",
+    );
+    // The clipboard holds text, which is parsed into new nodes every time it's pasted
+    assert_keys(
+        "[1, [true, null]]",
+        &format!("{}jl\"+yhP", osc52),
+        "[\n    [\n        true,\n        null\n    ],\n    1,\n    [\n        true,\n        null\n    ]\n]\n",
+        "0",
+    );
+    assert_error(
+        "[1]",
+        &format!("{}j\"+p", osc52),
+        "Couldn't use the clipboard: the terminal's clipboard can't be read, and nothing has \
+         been copied to it yet.",
+    );
+    assert_error(
+        "{\"a\": 1}",
+        &format!("{}j\"+y\"+p", osc52),
+        "Couldn't parse the clipboard as json: 1:4: expected end of input, found ':'",
+    );
+}
+
+/* ===== UNDO ===== */
+
+#[test]
+fn undo_and_redo() {
+    // Undoing puts the cursor back where it was before the change
+    assert_keys("[1, 2]", "jxu", "[\n    1,\n    2\n]\n", "0");
+    assert_keys("[1, 2]", "jlxu", "[\n    1,\n    2\n]\n", "1");
+    assert_keys("[1, 2]", "jxu<C-r>", "[\n    2\n]\n", "0");
+    assert_keys("[1, 2]", "jxxuu", "[\n    1,\n    2\n]\n", "0");
+    // ... even if the cursor moved between the changes
+    assert_keys(
+        "[1, [2, 3]]",
+        "jxjlxuu",
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n",
+        "0",
+    );
+    assert_keys(
+        "[1, [2, 3]]",
+        "jxjlxu",
+        "[\n    [\n        2,\n        3\n    ]\n]\n",
+        "0.1",
+    );
+}
+
+#[test]
+fn nothing_to_undo() {
+    assert_error("[1]", "u", "No changes to undo.");
+}
+
+/* ===== NAVIGATION ===== */
+
+#[test]
+fn move_cursor() {
+    assert_keys(
+        "[1, [2, 3]]",
+        "jlj",
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n",
+        "1.0",
+    );
+    assert_keys(
+        "[1, [2, 3]]",
+        "G",
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n",
+        "1.1",
+    );
+    assert_keys(
+        "[1, [2, 3]]",
+        "jljg",
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n",
+        "root",
+    );
+    assert_keys(
+        "[1, [2, 3]]",
+        "Gk",
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n",
+        "1",
+    );
+}
+
+#[test]
+fn move_past_the_end() {
+    assert_error(
+        "[1]",
+        "jj",
+        "Can't move down if the cursor has no children.",
+    );
+}
+
+/* ===== ERROR MESSAGES ===== */
+
+#[test]
+fn undefined_keystrokes() {
+    assert_error("[1]", "jK", "Invalid command 'K'");
+    assert_error("[1]", "<C-x>", "Invalid command '<C-x>'");
+}
+
+#[test]
+fn failed_edits_leave_the_tree_alone() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1]");
+    let (error, _) = press(&mut editor, "ia");
+    assert!(error.is_some());
+    assert_eq!(editor.text(), "[\n    1\n]\n");
+    assert_eq!(editor.cursor_path().to_string(), "root");
+}
+
+#[test]
+fn failed_counted_edits_leave_the_tree_alone() {
+    // Repeating an edit is all or nothing: the third unwrap fails (since numbers can't be
+    // unwrapped), so the first two are thrown away
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[[[1]]]");
+    let (error, _) = press(&mut editor, "j3S");
+    assert_eq!(
+        error.unwrap().to_string(),
+        "Can't unwrap number, since it doesn't have one child."
+    );
+    let text = "[\n    [\n        [\n            1\n        ]\n    ]\n]\n";
+    assert_eq!(editor.text(), text);
+    assert_eq!(editor.cursor_path().to_string(), "0");
+    let (error, _) = press(&mut editor, "u");
+    assert_eq!(error.unwrap().to_string(), "No changes to undo.");
+    // When every repeat succeeds, they're undone together
+    assert_keys("[[[1]]]", "j2Su", text, "0");
+}
+
+#[test]
+fn transactions() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1, 2]");
+    press(&mut editor, "jma");
+    // A transaction that fails changes nothing, so the mark still points at the first number
+    let error = editor.transact(|tx| {
+        tx.delete(1)?;
+        tx.insert('t', Side::Prev)?;
+        tx.insert_child('n')
+    });
+    assert_eq!(
+        error.unwrap_err().to_string(),
+        "'n' cannot be a child of true"
+    );
+    assert_eq!(editor.text(), "[\n    1,\n    2\n]\n");
+    press(&mut editor, "l'a");
+    assert_eq!(editor.cursor_path().to_string(), "0");
+    // A transaction that succeeds is undone in one go
+    editor
+        .transact(|tx| {
+            tx.replace('t')?;
+            tx.insert('f', Side::Next)
+        })
+        .unwrap();
+    assert_eq!(editor.text(), "[\n    true,\n    false,\n    2\n]\n");
+    press(&mut editor, "u");
+    assert_eq!(editor.text(), "[\n    1,\n    2\n]\n");
+}
+
+#[test]
+fn unknown_commands() {
+    assert_error(
+        "[1]",
+        ":frobnicate<CR>",
+        "Invalid command ':frobnicate': unknown command",
+    );
+}
+
+/* ===== COMMANDS AND QUITTING ===== */
+
+#[test]
+fn stats() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1, 2]");
+    press(&mut editor, ":stats<CR>");
+    let message = editor.status_message().unwrap().to_string();
+    assert!(message.starts_with("3 nodes allocated ("), "{}", message);
+    assert!(message.ends_with("), 3 in the current tree"), "{}", message);
+    // Deleting a node allocates a new root, and the old nodes stay in the arena
+    press(&mut editor, "jx:stats<CR>");
+    let message = editor.status_message().unwrap();
+    assert!(message.starts_with("4 nodes allocated ("), "{}", message);
+    assert!(message.ends_with("), 2 in the current tree"), "{}", message);
+}
+
+#[test]
+fn tree_stats() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, r#"{"a": [1, 2, 3], "b": "x"}"#);
+    press(&mut editor, ":stats tree<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("10 nodes, 8 lines, 3 levels deep, widest: 0.1 (3 children) | number: 3 | string: 3 | field: 2 | array: 1 | object: 1")
+    );
+    assert_error(
+        "[]",
+        ":stats memory<CR>",
+        "Invalid command ':stats memory': unknown statistics 'memory' (expected 'tree')",
+    );
+}
+
+#[test]
+fn changes() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1, [2], 3]");
+    press(&mut editor, ":changes<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("No changes since the last save.")
+    );
+    press(&mut editor, "jxot:changes<CR>");
+    assert_eq!(editor.status_message(), Some("deleted 0 | inserted 0.1"));
+    // Undoing a change makes it disappear from the list
+    press(&mut editor, "u:changes<CR>");
+    assert_eq!(editor.status_message(), Some("deleted 0"));
+}
+
+#[test]
+fn set_options() {
+    assert_keys("[1, {}]", ":set format=compact<CR>", "[1,{}]\n", "root");
+    // Strict JSON doesn't allow trailing commas, so they can't be turned on by `:set` and are
+    // left out even if the config file turns them on
+    assert_error(
+        "[1, 2]",
+        ":set trailing_commas=true<CR>",
+        "Can't set 'trailing_commas' to 'true': expected false, since json doesn't allow trailing \
+         commas.",
+    );
+    let arenas = Arenas::new();
+    let mut config = Config::default();
+    config.options.trailing_commas = true;
+    let editor = editor_with_config(&arenas, "[1, 2]", config);
+    assert_eq!(editor.text(), "[\n    1,\n    2\n]\n");
+}
+
+#[test]
+fn shell_commands() {
+    let arenas = Arenas::new();
+    let dir = std::env::temp_dir().join(format!("sapling-test-shell-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    std::fs::write(&path, "[1]").unwrap();
+    let mut editor = editor_of_file(&arenas, &path);
+    // Without a terminal, commands run without one and only their exit status is shown
+    press(&mut editor, ":!true<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("':!true' exited with status 0")
+    );
+    press(&mut editor, ":!exit 3<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("':!exit 3' exited with status 3")
+    );
+    // `%` is the buffer's file
+    press(&mut editor, ":!test -f %<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("':!test -f %' exited with status 0")
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_error(
+        "[]",
+        ":!cat %<CR>",
+        "Couldn't run 'cat %': the buffer has no file to put in place of '%'.",
+    );
+    assert_error(
+        "[]",
+        "<C-z>",
+        "Can't suspend Sapling: it isn't running in a terminal.",
+    );
+}
+
+#[test]
+fn pipe_through_commands() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1, [2, 3]]");
+    press(&mut editor, "jl:pipe tr 23 45<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("Replaced the cursor with array from 'tr 23 45'")
+    );
+    assert_eq!(
+        editor.text(),
+        "[\n    1,\n    [\n        4,\n        5\n    ]\n]\n"
+    );
+    // The whole replacement is one change
+    press(&mut editor, "u");
+    assert_eq!(
+        editor.text(),
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n"
+    );
+
+    // Commands which fail (or print something that can't be parsed) leave the tree alone
+    let (error, _) = press(&mut editor, ":pipe echo oops >&2; exit 4<CR>");
+    assert!(error.is_some());
+    assert_eq!(
+        editor.status_message(),
+        Some("Couldn't pipe through 'echo oops >&2; exit 4': it exited with status 4: oops")
+    );
+    let (error, _) = press(&mut editor, ":pipe echo '{'<CR>");
+    assert!(error.is_some());
+    let message = editor.status_message().unwrap();
+    assert!(message.contains("couldn't parse its output"), "{}", message);
+    assert_eq!(
+        editor.text(),
+        "[\n    1,\n    [\n        2,\n        3\n    ]\n]\n"
+    );
+}
+
+#[test]
+fn queries() {
+    let arenas = Arenas::new();
+    let text = r#"{"store": {"book": [{"title": "A", "price": 8}, {"title": "B", "price": 12}]}}"#;
+    let mut editor = editor(&arenas, text);
+    press(&mut editor, ":query $.store.book[1].title<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("1 match(es) for '$.store.book[1].title'")
+    );
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0.1.1.0.1");
+
+    // `n` and `N` move between the matches, like after searching with `/`
+    press(&mut editor, ":query ..price<CR>");
+    assert_eq!(editor.status_message(), Some("2 match(es) for '$..price'"));
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0.1.0.1.1");
+    press(&mut editor, "n");
+    assert_eq!(editor.status_message(), Some("Match 2 of 2"));
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0.1.1.1.1");
+    press(&mut editor, "n");
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0.1.0.1.1");
+
+    // `:query!` also lists the matches
+    press(&mut editor, ":query! $..title<CR>");
+    let frame = editor.render_to_string(100, 40, false);
+    assert!(frame.contains("│ Matches of $..title (2)"), "{}", frame);
+    assert!(
+        frame.contains("│   1  $.store.book[0].title  \"A\""),
+        "{}",
+        frame
+    );
+    assert!(
+        frame.contains("│   2  $.store.book[1].title  \"B\""),
+        "{}",
+        frame
+    );
+    // The box is closed by the next key
+    press(&mut editor, "n");
+    assert!(!editor
+        .render_to_string(100, 40, false)
+        .contains("│ Matches of"));
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0.1.0.0.1");
+
+    // Queries which find nothing (or can't be parsed) leave the cursor where it was
+    let (error, _) = press(&mut editor, ":query $.store.pen<CR>");
+    assert!(error.is_some());
+    assert_eq!(
+        editor.status_message(),
+        Some("Nothing matches '$.store.pen'.")
+    );
+    let (error, _) = press(&mut editor, ":query $.store[<CR>");
+    assert!(error.is_some());
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0.1.0.0.1");
+}
+
+#[test]
+fn schemas() {
+    let arenas = Arenas::new();
+    let dir = std::env::temp_dir().join(format!("sapling-test-schema-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("schema.json");
+    std::fs::write(
+        &path,
+        r#"{
+            "type": "object",
+            "required": ["name", "id"],
+            "properties": {"name": {"type": "string"}, "port": {"maximum": 65535}},
+            "additionalProperties": false
+        }"#,
+    )
+    .unwrap();
+    let text = r#"{"name": 3, "port": 80, "x": true}"#;
+    let mut editor = editor(&arenas, text);
+    press(&mut editor, ":errors<CR>");
+    assert_eq!(editor.status_message(), Some("No errors."));
+    let set = format!(":set schema=file://{}<CR>", path.display());
+    press(&mut editor, &set);
+    press(&mut editor, ":errors<CR>");
+    assert_eq!(editor.status_message(), Some("3 error(s)"));
+    let frame = editor.render_to_string(100, 40, false);
+    assert!(frame.contains("│ Errors (3)"), "{}", frame);
+    assert!(
+        frame.contains(r#"│   $       Missing the required property "id""#),
+        "{}",
+        frame
+    );
+    assert!(
+        frame.contains("│   $.name  Expected string, but found integer"),
+        "{}",
+        frame
+    );
+
+    // `]e` and `[e` move between the errors, without wrapping around
+    press(&mut editor, "<Esc>]e");
+    assert_eq!(
+        editor.status_message(),
+        Some("Error 2 of 3: Expected string, but found integer")
+    );
+    assert_eq!(editor.cursor_path().to_string(), "0.1");
+    press(&mut editor, "]e");
+    assert_eq!(
+        editor.status_message(),
+        Some(r#"Error 3 of 3: The property "x" isn't allowed"#)
+    );
+    assert_eq!(editor.cursor_path().to_string(), "2");
+    let (error, _) = press(&mut editor, "]e");
+    assert_eq!(error, Some(EditErr::NoMoreErrors));
+    // Fixing an error takes it off the list
+    press(&mut editor, "x[e");
+    assert_eq!(editor.cursor_path().to_string(), "0.1");
+    press(&mut editor, ":errors<CR>");
+    assert_eq!(editor.status_message(), Some("2 error(s)"));
+
+    // A schema that can't be loaded leaves the old one in place
+    let (error, _) = press(&mut editor, ":set schema=missing.json<CR>");
+    assert!(error.is_some());
+    press(&mut editor, ":set schema?<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some(format!("schema=file://{}", path.display()).as_str())
+    );
+    std::fs::write(&path, r#"{"type": "text"}"#).unwrap();
+    press(&mut editor, &set);
+    assert_eq!(
+        editor.status_message(),
+        Some(
+            format!(
+                "Couldn't load the schema file://{}: /type: expected a type or an array of types",
+                path.display()
+            )
+            .as_str()
+        )
+    );
+    // Unsetting the schema stops checking against it
+    press(&mut editor, ":set schema=<CR>:errors<CR>");
+    assert_eq!(editor.status_message(), Some("No errors."));
+    let (error, _) = press(&mut editor, "]e");
+    assert_eq!(error, Some(EditErr::NoErrors));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn themes() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1]");
+    press(&mut editor, ":set theme=solarized-dark<CR>:themes<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("default | mono | dark | light | %solarized-dark | gruvbox")
+    );
+    // `T` cycles through the themes, wrapping around after the last one
+    press(&mut editor, "T");
+    assert_eq!(editor.status_message(), Some("theme=gruvbox"));
+    press(&mut editor, "T");
+    assert_eq!(editor.status_message(), Some("theme=default"));
+    // The old name of the option still works
+    press(&mut editor, ":set color_scheme=mono<CR>");
+    assert_eq!(editor.status_message(), Some("color_scheme=mono"));
+    assert_error(
+        "[]",
+        ":set theme=neon<CR>",
+        "Can't set 'theme' to 'neon': expected one of the themes (default, mono, dark, light, \
+         solarized-dark, gruvbox).",
+    );
+}
+
+#[test]
+fn map_keys() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[[1, 2]]");
+    assert_eq!(
+        press(&mut editor, ":map ctrl-k move-child<CR>"),
+        (None, false)
+    );
+    assert_eq!(
+        editor.status_message(),
+        Some("'<C-k>' is mapped to move-child")
+    );
+    press(&mut editor, "<C-k><C-k>x");
+    assert_eq!(editor.text(), "[\n    [\n        2\n    ]\n]\n");
+    // Mapping keys which are already bound rebinds them
+    press(&mut editor, ":map x move-root<CR>x");
+    assert_eq!(editor.cursor_path().to_string(), "root");
+    press(&mut editor, ":unmap ctrl-k<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("'<C-k>' is no longer mapped to move-child")
+    );
+    assert_eq!(
+        press(&mut editor, "<C-k>").0,
+        Some(EditErr::Invalid("<C-k>".to_string()))
+    );
+
+    assert_error(
+        "[]",
+        ":map gx undo<CR>",
+        "Can't map 'gx': they conflict with 'g' (move-root).",
+    );
+    assert_error(
+        "[]",
+        ":map gx move-sideways<CR>",
+        "Can't map 'gx': there's no keystroke called 'move-sideways'.",
+    );
+    assert_error("[]", ":unmap gx<CR>", "'gx' isn't mapped to anything.");
+}
+
+#[test]
+fn quit() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[1]");
+    assert_eq!(press(&mut editor, "Q"), (None, true));
+    // Quitting with unsaved changes is refused, however Sapling is asked to quit
+    assert_eq!(press(&mut editor, "jx"), (None, false));
+    for keys in &["Q", "<C-c>", ":q<CR>", ":qa<CR>"] {
+        let (error, should_quit) = press(&mut editor, keys);
+        assert!(error.is_some() && !should_quit, "{}", keys);
+        assert_eq!(
+            editor.status_message(),
+            Some("No write since last change (add ! to override)."),
+            "{}",
+            keys
+        );
+    }
+    assert_eq!(press(&mut editor, ":q!<CR>"), (None, true));
+    assert_eq!(press(&mut editor, ":qa!<CR>"), (None, true));
+}
+
+#[test]
+fn quit_with_unsaved_files() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-quit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (first, second) = (dir.join("first.json"), dir.join("second.json"));
+    std::fs::write(&first, "[1]").unwrap();
+    std::fs::write(&second, "[2]").unwrap();
+
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &first);
+    assert_eq!(editor.load_buffer(second.clone()), Ok(1));
+    press(&mut editor, "jx:bn<CR>jx");
+    // Every buffer with unsaved changes is named, and the first is shown so it can be saved
+    let (error, should_quit) = press(&mut editor, "Q");
+    assert!(error.is_some() && !should_quit);
+    assert_eq!(
+        editor.status_message(),
+        Some(
+            format!(
+                "No write since last change to {}, {} (add ! to override).",
+                first.display(),
+                second.display()
+            )
+            .as_str()
+        )
+    );
+    assert_eq!(editor.text(), "[]\n");
+    // Refusing to quit doesn't touch the files on disk
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "[1]");
+    assert_eq!(std::fs::read_to_string(&second).unwrap(), "[2]");
+    // `ZZ` only saves the buffer being edited, so the other one still stops Sapling quitting
+    let (error, should_quit) = press(&mut editor, "ZZ");
+    assert!(error.is_some() && !should_quit);
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "[]\n");
+    assert_eq!(std::fs::read_to_string(&second).unwrap(), "[2]");
+    assert_eq!(
+        editor.status_message(),
+        Some("No write since last change (add ! to override).")
+    );
+    assert_eq!(press(&mut editor, "ZZ"), (None, true));
+    assert_eq!(std::fs::read_to_string(&second).unwrap(), "[]\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn signals() {
+    use sapling::editor::crash;
+    use sapling::editor::signals::Signal;
+
+    let dir = std::env::temp_dir().join(format!("sapling-test-signals-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    std::fs::write(&path, "[1, 2]").unwrap();
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    // `SIGINT` is like `:q`, so it refuses to quit whilst there are unsaved changes ...
+    press(&mut editor, "jx");
+    assert_eq!(editor.handle_signal(Signal::Interrupt), None);
+    assert_eq!(
+        editor.status_message(),
+        Some("No write since last change (add ! to override).")
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1, 2]");
+    // ... whereas `SIGTERM` (and `SIGHUP`) stop the editor, keeping the changes in a recovery file
+    assert_eq!(editor.handle_signal(Signal::Terminate), Some(false));
+    assert_eq!(
+        std::fs::read_to_string(crash::recovery_path(&path)).unwrap(),
+        "[\n    2\n]\n"
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1, 2]");
+    // Once the changes are saved, `SIGINT` quits
+    press(&mut editor, ":w<CR>");
+    assert_eq!(editor.handle_signal(Signal::Interrupt), Some(true));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unfinished_numbers() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-unfinished-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    std::fs::write(&path, "[1, 2, 3]").unwrap();
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    // A half-typed number isn't valid JSON, so it's an error that stops the tree being written
+    let (error, _) = press(&mut editor, "j2e:w<CR>");
+    assert_eq!(
+        editor.status_message(),
+        Some("Can't write the tree, since 12e isn't finished (at 0).")
+    );
+    assert!(matches!(error, Some(EditErr::Unwritable { .. })));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1, 2, 3]");
+    assert!(editor.patch().is_err());
+    let export = dir.join("data.html");
+    let (error, _) = press(
+        &mut editor,
+        &format!(":export html {}<CR>", export.display()),
+    );
+    assert!(error.is_some());
+    assert!(!export.exists());
+    press(&mut editor, "k]e");
+    assert_eq!(
+        editor.status_message(),
+        Some("Error 1 of 1: 12e isn't finished")
+    );
+    // Once the number is finished, the tree can be written again
+    let (error, _) = press(&mut editor, "5:w<CR>");
+    assert_eq!(error, None);
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(text, "[\n    12e5,\n    2,\n    3\n]\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_only_files() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-read-only-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (first, second) = (dir.join("first.json"), dir.join("second.json"));
+    std::fs::write(&first, "[1]").unwrap();
+    std::fs::write(&second, "[2]").unwrap();
+
+    let arenas = Arenas::new();
+    let loader = BufferLoader::new(&arenas, parse);
+    let buffer = loader.load(&first).unwrap();
+    let config = Config {
+        read_only: true,
+        ..Config::default()
+    };
+    let mut editor = editor_of_buffer(loader, buffer, config);
+    // The other files are opened without being switched to
+    assert_eq!(editor.load_buffer(second.clone()), Ok(1));
+    assert_eq!(editor.load_buffer(first.clone()), Ok(0));
+    assert_eq!(editor.text(), "[\n    1\n]\n");
+    // Every buffer is read-only, including the ones opened later
+    let (error, _) = press(&mut editor, "jx");
+    assert_eq!(error, Some(EditErr::ReadOnly));
+    let (error, _) = press(&mut editor, ":bn<CR>jx");
+    assert_eq!(error, Some(EditErr::ReadOnly));
+    assert_eq!(editor.text(), "[\n    2\n]\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_only_mode() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-readonly-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    std::fs::write(&path, "[1, 2]").unwrap();
+
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    assert_eq!(press(&mut editor, ":set readonly<CR>"), (None, false));
+    assert!(editor.render_to_string(100, 6, false).contains("[RO]"));
+    press(&mut editor, ":set readonly?<CR>");
+    assert_eq!(editor.status_message(), Some("readonly=true"));
+    // Moving still works, but nothing can be changed
+    let text = editor.text();
+    assert_eq!(press(&mut editor, "j"), (None, false));
+    assert_eq!(press(&mut editor, "x"), (Some(EditErr::ReadOnly), false));
+    assert_eq!(press(&mut editor, "u"), (Some(EditErr::ReadOnly), false));
+    assert_eq!(editor.text(), text);
+    // Writing has to be forced
+    let (error, _) = press(&mut editor, ":w<CR>");
+    assert!(error.is_some());
+    let message = editor.status_message().unwrap();
+    assert!(
+        message.ends_with("data.json is read-only (add ! to write it anyway)."),
+        "{}",
+        message
+    );
+    assert_eq!(press(&mut editor, ":w!<CR>"), (None, false));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+    // The buffer can be made writable again, since its file is
+    assert_eq!(press(&mut editor, ":set noreadonly<CR>x"), (None, false));
+    assert!(!editor.render_to_string(100, 6, false).contains("[RO]"));
+
+    // ... unless the file itself is read-only, which even `:w!` won't overwrite
+    let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(&path, permissions).unwrap();
+    press(&mut editor, ":set readonly<CR>");
+    let (error, _) = press(&mut editor, ":set noreadonly<CR>");
+    assert!(error.is_some());
+    let (error, _) = press(&mut editor, ":w!<CR>");
+    assert!(error.is_some());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_changed_on_disk() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-disk-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    std::fs::write(&path, "[1, 2]").unwrap();
+    let read = || std::fs::read_to_string(&path).unwrap();
+
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    press(&mut editor, "jx");
+    let edited = editor.text();
+    // Saving asks what to do rather than overwriting the other program's changes
+    std::fs::write(&path, "[1, 2, 3]").unwrap();
+    assert_eq!(press(&mut editor, ":w<CR>"), (None, false));
+    assert!(editor
+        .render_to_string(100, 6, false)
+        .contains("changed on disk.  r: reload  o: overwrite it"));
+    assert_eq!(read(), "[1, 2, 3]");
+    // Other keys don't answer, and Esc gives up on saving
+    let (error, _) = press(&mut editor, "jx<Esc>");
+    assert!(matches!(error, Some(EditErr::ChangedOnDisk { .. })));
+    assert_eq!(
+        (editor.text(), read()),
+        (edited.clone(), "[1, 2, 3]".to_string())
+    );
+    // Overwriting writes the user's version, and `:wq` quits afterwards
+    assert_eq!(press(&mut editor, ":wq<CR>"), (None, false));
+    assert_eq!(press(&mut editor, "o"), (None, true));
+    assert_eq!(read(), edited);
+
+    // Reloading reads the file again as one change, which can be undone
+    std::fs::write(&path, "[true, false, null]").unwrap();
+    assert_eq!(press(&mut editor, "x:w<CR>r"), (None, false));
+    assert_eq!(editor.text(), "[\n    true,\n    false,\n    null\n]\n");
+    assert!(!editor.render_to_string(100, 6, false).contains("[+]"));
+    press(&mut editor, "u");
+    assert_eq!(editor.text(), "[]\n");
+    press(&mut editor, "<C-r>");
+
+    // Changes are also noticed in the background, but only asked about once
+    std::fs::write(&path, "[\"other\"]").unwrap();
+    editor.check_disk();
+    assert!(editor
+        .render_to_string(160, 6, false)
+        .contains("o: keep mine  b: compare  Esc: decide later"));
+    // Comparing opens the other version next to the user's
+    assert_eq!(press(&mut editor, "b"), (None, false));
+    let mine = editor.text();
+    press(&mut editor, "<C-w><C-w>");
+    assert_eq!(editor.text(), "[\n    \"other\"\n]\n");
+    assert_eq!(press(&mut editor, "x"), (Some(EditErr::ReadOnly), false));
+    press(&mut editor, "<C-w><C-w>");
+    assert_eq!(editor.text(), mine);
+    editor.check_disk();
+    assert_eq!(press(&mut editor, "jx"), (None, false));
+    // ... though saving still asks, since the user didn't choose which version to keep
+    assert_eq!(press(&mut editor, ":w<CR>"), (None, false));
+    assert_eq!(press(&mut editor, "o"), (None, false));
+    assert_eq!(read(), editor.text());
+    // Once saved, nothing is asked until the file changes again
+    editor.check_disk();
+    assert_eq!(press(&mut editor, ":w<CR>"), (None, false));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn autosave() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-autosave-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    let shadow = dir.join("data.json.autosave");
+    std::fs::write(&path, "[1, 2, 3]").unwrap();
+    let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+    let later = || Instant::now() + Duration::from_secs(3);
+
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    // Nothing is auto-saved until it's turned on
+    press(&mut editor, "jx");
+    editor.autosave(later());
+    assert_eq!(read(&path), "[1, 2, 3]");
+    // ... and then only once the tree hasn't changed for long enough
+    assert_eq!(press(&mut editor, ":set autosave=2000<CR>x"), (None, false));
+    editor.autosave(Instant::now());
+    assert_eq!(read(&path), "[1, 2, 3]");
+    editor.autosave(later());
+    assert_eq!(read(&path), editor.text());
+    assert!(!editor.render_to_string(100, 6, false).contains("[+]"));
+
+    // Shadow files leave the file alone, and are deleted once the file is saved
+    press(&mut editor, ":set autosave_to=shadow<CR>x");
+    editor.autosave(later());
+    assert_eq!(read(&shadow), editor.text());
+    assert_eq!(read(&path), "[\n    3\n]\n");
+    press(&mut editor, ":w<CR>");
+    assert!(!shadow.exists());
+
+    // A buffer that can't be auto-saved is warned about once, and then left alone
+    std::fs::create_dir(&shadow).unwrap();
+    press(&mut editor, "u");
+    editor.autosave(later());
+    let message = editor.status_message().unwrap().to_string();
+    assert!(message.starts_with("Stopped auto-saving"), "{}", message);
+    press(&mut editor, "u");
+    editor.autosave(later());
+    assert_eq!(editor.status_message(), None);
+    // Until auto-saving is set up again
+    std::fs::remove_dir(&shadow).unwrap();
+    press(&mut editor, ":set autosave=1000<CR>");
+    editor.autosave(later());
+    assert_eq!(read(&shadow), editor.text());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/* ===== DIFFS ===== */
+
+/// Presses `keys` in an editor showing a diff, without any of them failing, and returns the
+/// paths of the cursors of the left and right windows
+fn diff_cursors<'arena>(editor: &mut Editor<'arena, JSON<'arena>>, keys: &str) -> (String, String) {
+    assert_eq!(press(editor, keys), (None, false), "{}", keys);
+    let left = editor.cursor_path().to_string();
+    press(editor, "<C-w><C-w>");
+    let right = editor.cursor_path().to_string();
+    press(editor, "<C-w><C-w>");
+    (left, right)
+}
+
+#[test]
+fn diff_view() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, r#"{"a": 1, "b": [true, false], "c": null}"#);
+    let new = BufferLoader::new(&arenas, parse)
+        .parse(r#"{"b": [true, null], "c": null, "d": 2}"#, None)
+        .unwrap();
+    editor.show_diff(new);
+    assert_eq!(
+        editor.status_message(),
+        Some("3 difference(s) in 3 hunk(s)")
+    );
+    // The fields are matched up by their keys, and both cursors move through the changes
+    let cursor_pair = |left: &str, right: &str| (left.to_string(), right.to_string());
+    assert_eq!(diff_cursors(&mut editor, "]c"), cursor_pair("0", "root"));
+    assert_eq!(
+        diff_cursors(&mut editor, "]c"),
+        cursor_pair("1.1.1", "0.1.1")
+    );
+    assert_eq!(diff_cursors(&mut editor, "]c"), cursor_pair("root", "2"));
+    assert_eq!(diff_cursors(&mut editor, "2[c"), cursor_pair("0", "root"));
+
+    press(&mut editor, "]c");
+    assert_eq!(editor.status_message(), Some("Change 2 of 3"));
+    let (error, _) = press(&mut editor, "[c[c");
+    assert_eq!(error, Some(EditErr::NoMoreChanges));
+    // Neither side can be edited
+    let text = editor.text();
+    let (error, _) = press(&mut editor, "]cx");
+    assert_eq!(editor.status_message(), Some("The buffer is read-only."));
+    assert!(error.is_some());
+    assert_eq!(editor.text(), text);
+}
+
+#[test]
+fn no_diff() {
+    assert_error(
+        "[1]",
+        "]c",
+        "This buffer isn't being compared with another.",
+    );
+    assert_error("[1]", "]x", "Invalid command ']x'");
+}
+
+/* ===== SESSIONS ===== */
+
+#[test]
+fn sessions() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-sessions-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    let other_path = dir.join("other.json");
+    let session_path = dir.join("session.json");
+    std::fs::write(&path, "[[1, 2], true, 3]").unwrap();
+    std::fs::write(&other_path, "[]").unwrap();
+    let json = Language::JSON(Dialect::JSON);
+
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    press(&mut editor, "jzalxrn");
+    let text = editor.text();
+    let mut sessions = SessionFile::default();
+    sessions.insert(editor.save_session().unwrap());
+    // Saving the session of another file keeps the session of the first
+    let mut other = editor_of_file(&arenas, &other_path);
+    press(&mut other, "it");
+    sessions.insert(other.save_session().unwrap());
+    sessions.write(&session_path).unwrap();
+    let sessions = SessionFile::read(&session_path).unwrap();
+    assert_eq!(sessions.sessions.len(), 2);
+    let other_saved = sessions.session_for(&other_path, json).unwrap();
+    assert_eq!(other_saved.file_path, other_path.canonicalize().unwrap());
+
+    // A new editor of the same file carries on exactly where the old one left off
+    let saved = sessions.session_for(&path, json).unwrap().clone();
+    assert_eq!(saved.file_path, path.canonicalize().unwrap());
+    let new_arenas = Arenas::new();
+    let mut editor = editor_of_file(&new_arenas, &path);
+    editor.restore_session(&saved).unwrap();
+    assert_eq!(editor.text(), text);
+    assert_eq!(editor.cursor_path().to_string(), "1");
+    // Including the fold
+    let frame = editor.render_to_string(40, 6, false);
+    assert!(
+        frame.starts_with("[\n    […] (2 items),\n    null\n]"),
+        "{}",
+        frame
+    );
+    press(&mut editor, "uu");
+    assert_eq!(
+        editor.text(),
+        "[\n    [\n        1,\n        2\n    ],\n    true,\n    3\n]\n"
+    );
+    let (error, _) = press(&mut editor, "u");
+    assert_eq!(error, Some(EditErr::NoChangesToUndo));
+
+    // Sessions aren't restored into other buffers, or once their file has changed
+    let mut other = editor_of_buffer(
+        BufferLoader::new(&new_arenas, parse),
+        BufferLoader::new(&new_arenas, parse)
+            .parse("[]", None)
+            .unwrap(),
+        Config::default(),
+    );
+    assert_eq!(
+        other.restore_session(&saved),
+        Err(SessionError::WrongBuffer)
+    );
+    std::fs::write(&path, "[]").unwrap();
+    assert!(!saved.is_for(&path, json));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn swap_files() {
+    let dir = std::env::temp_dir().join(format!("sapling-test-swap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    let swap_path = swap::swap_path(&path);
+    let original = "[\n    [\n        1,\n        2\n    ],\n    true,\n    3\n]\n";
+    std::fs::write(&path, original).unwrap();
+
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    editor.enable_swap_files();
+    // The swap file is only started once there are unsaved changes
+    press(&mut editor, "jl");
+    assert!(!swap_path.exists());
+    press(&mut editor, "xrn");
+    let text = editor.text();
+    // Sapling is killed (so the swap file is left behind), partway through writing a change
+    std::mem::forget(editor);
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&swap_path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"\x40\0\0\0cut short").unwrap();
+    let swap = SwapFile::read(&swap_path).unwrap();
+    assert_eq!((swap.change_count(), swap.is_truncated()), (2, true));
+
+    // A new editor of the same file recovers both changes, and cuts off the corrupt change
+    let new_arenas = Arenas::new();
+    let mut editor = editor_of_file(&new_arenas, &path);
+    editor.enable_swap_files();
+    assert_eq!(editor.recover_swap(&swap), Ok(2));
+    assert_eq!(editor.text(), text);
+    assert_eq!(editor.cursor_path().to_string(), "1");
+    assert!(!SwapFile::read(&swap_path).unwrap().is_truncated());
+    // The recovered changes can be undone, and new changes carry on being written
+    press(&mut editor, "u");
+    assert_eq!(SwapFile::read(&swap_path).unwrap().change_count(), 3);
+    press(&mut editor, "u");
+    assert_eq!(editor.text(), original);
+    let (error, _) = press(&mut editor, "u");
+    assert_eq!(error, Some(EditErr::NoChangesToUndo));
+    // Saving deletes the swap file
+    press(&mut editor, "<C-r>");
+    editor.save().unwrap();
+    assert!(!swap_path.exists());
+
+    // Swap files aren't recovered into other buffers
+    let mut other = editor_of_buffer(
+        BufferLoader::new(&new_arenas, parse),
+        BufferLoader::new(&new_arenas, parse)
+            .parse("[]", None)
+            .unwrap(),
+        Config::default(),
+    );
+    assert_eq!(other.recover_swap(&swap), Err(SwapError::WrongBuffer));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/* ===== RENDERING ===== */
+
+#[test]
+fn render_frame() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[true, false]");
+    press(&mut editor, "jl");
+    let frame = editor.render_to_string(40, 6, false);
+    let lines: Vec<&str> = frame.lines().collect();
+    assert_eq!(lines.len(), 6);
+    // The keystroke log is drawn over the right half of the screen
+    assert_eq!(lines[0], "[                      j (move-child) =>");
+    assert_eq!(lines[1], "    true,              l (move-next)  =>");
+    assert_eq!(lines[2..4], ["    false", "]"]);
+    assert!(lines[5].contains("Press 'Q' to exit."), "{}", frame);
+    // The cursor is only marked when colours are shown
+    let colored = editor.render_to_string(40, 6, true);
+    assert!(colored.contains("\x1b[30m"), "{}", colored);
+}
+
+#[test]
+fn render_narrow_frame() {
+    let arenas = Arenas::new();
+    let mut config = Config::default();
+    config.options.set("format", "fit").unwrap();
+    let mut editor = editor_with_config(&arenas, "[1, 2, 3, 4, 5, 6, 7, 8]", config);
+    // The tree is only spread over several lines if it doesn't fit on the screen
+    let frame = editor.render_to_string(80, 5, false);
+    assert!(
+        frame.starts_with("[1, 2, 3, 4, 5, 6, 7, 8]\n\n"),
+        "{}",
+        frame
+    );
+    let frame = editor.render_to_string(20, 12, false);
+    assert!(frame.starts_with("[\n    1,\n    2,\n"), "{}", frame);
+}
+
+#[test]
+fn render_resized() {
+    let arenas = Arenas::new();
+    let numbers: Vec<_> = (1..=40).map(|i| i.to_string()).collect();
+    let mut editor = editor(&arenas, &format!("[{}]", numbers.join(", ")));
+    press(&mut editor, "G");
+    assert!(editor.render_to_string(60, 50, false).contains("    40"));
+    // Shrinking the screen scrolls the tree to keep the cursor in view
+    let frame = editor.render_to_string(60, 8, false);
+    assert!(frame.contains("    40"), "{}", frame);
+    assert!(!frame.contains("    30"), "{}", frame);
+    // A screen that's too small only says so, until it's big enough again
+    let frame = editor.render_to_string(19, 5, false);
+    assert!(
+        frame.starts_with("Terminal too small\n(needs 20x5)"),
+        "{}",
+        frame
+    );
+    assert_eq!(editor.render_to_string(8, 1, false), "Terminal\n");
+    press(&mut editor, "gj");
+    let frame = editor.render_to_string(60, 8, false);
+    assert!(frame.contains("    1,"), "{}", frame);
+}
+
+#[test]
+fn render_line_numbers() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[[1, 2], 3]");
+    press(&mut editor, ":set number<CR>");
+    // Only the left of each line is checked, since the keystroke log covers the right
+    fn left<'arena>(editor: &mut Editor<'arena, JSON<'arena>>) -> Vec<String> {
+        let frame = editor.render_to_string(60, 9, false);
+        frame
+            .lines()
+            .take(7)
+            .map(|line| {
+                line.chars()
+                    .take(14)
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+    assert_eq!(
+        left(&mut editor),
+        vec![
+            "  1 [",
+            "  2     [",
+            "  3         1,",
+            "  4         2",
+            "  5     ],",
+            "  6     3",
+            "  7 ]"
+        ]
+    );
+    // Clicks are measured from the left of the tree, not of the gutter
+    editor.handle_key(Key::SingleClick(MouseButton::Left, 2, 12));
+    assert_eq!(editor.cursor_path().to_string(), "0.0");
+    // A fold is numbered by its first line, and the lines after it carry on after the lines
+    // that it hides
+    press(&mut editor, "kza");
+    let lines = left(&mut editor);
+    assert!(lines[1].starts_with("  2     […]"), "{:?}", lines);
+    assert_eq!(lines[2..], ["  6     3", "  7 ]", "", "", ""]);
+    // Relative numbers count lines on the screen from the cursor's line
+    press(&mut editor, ":set relativenumber<CR>");
+    let lines = left(&mut editor);
+    assert!(lines[1].starts_with("  2     […]"), "{:?}", lines);
+    assert_eq!(lines[0], "  1 [");
+    assert_eq!(lines[2..4], ["  1     3", "  2 ]"]);
+    press(&mut editor, ":set nonumber<CR>");
+    assert!(left(&mut editor)[1].starts_with("  0     […]"));
+    press(&mut editor, ":set norelativenumber<CR>");
+    assert_eq!(left(&mut editor)[0], "[");
+}
+
+#[test]
+fn render_enclosing_brackets() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[[1, 2], 3]");
+    press(&mut editor, "jj");
+    let frame = editor.render_to_string(20, 12, true);
+    let lines: Vec<_> = frame.lines().collect();
+    let (bold, plain) = ("\x1b[0m\x1b[37m\x1b[1m", "\x1b[0m\x1b[37m");
+    // The brackets of both arrays around the cursor are bold, but their commas aren't
+    assert!(lines[0].starts_with(&format!("{}[\x1b[0m", bold)));
+    assert!(lines[1].starts_with(&format!("    {}[\x1b[0m", bold)));
+    assert!(lines[4].starts_with(&format!("    {}]{},\x1b[0m", bold, plain)));
+    assert!(!lines[5].contains("\x1b[1m"));
+    // Moving out of the inner array leaves only the outer one
+    press(&mut editor, "kl");
+    let frame = editor.render_to_string(20, 12, true);
+    let lines: Vec<_> = frame.lines().collect();
+    assert!(lines[0].starts_with(&format!("{}[\x1b[0m", bold)));
+    assert!(lines[1].starts_with(&format!("    {}[\x1b[0m", plain)));
+}
+
+#[test]
+fn messages() {
+    // Every test shares the same logger, so other tests' messages might also be shown
+    let _ = sapling::logging::init(log::LevelFilter::Debug, None);
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[true, false]");
+    press(&mut editor, "jx:messages<CR>");
+    let frame = editor.render_to_string(100, 80, false);
+    assert!(frame.contains("│ Messages ("), "{}", frame);
+    assert!(frame.contains("DEBUG Committed an edit"), "{}", frame);
+    assert!(frame.contains("DEBUG Running :messages"), "{}", frame);
+    // Every built-in theme has every style, so there's nothing to warn about
+    assert!(!frame.contains("has no style"), "{}", frame);
+    assert!(
+        frame.contains("Press any key to close the messages."),
+        "{}",
+        frame
+    );
+    // The box is read-only, so the next key closes it without doing anything else
+    press(&mut editor, "x");
+    assert!(!editor
+        .render_to_string(100, 80, false)
+        .contains("│ Messages ("));
+    assert_eq!(editor.text(), "[\n    false\n]\n");
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn crash_recovery() {
+    use sapling::editor::crash::{self, TerminalGuard};
+
+    let dir = std::env::temp_dir().join(format!("sapling-test-crash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data.json");
+    let crash_log = dir.join("crash.log");
+    std::fs::write(&path, "[1, 2]").unwrap();
+    let arenas = Arenas::new();
+    let mut editor = editor_of_file(&arenas, &path);
+    press(&mut editor, "jx");
+
+    crash::install_hook(Some(crash_log.clone()));
+    let guard = TerminalGuard::new();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        editor.catch_crash(|editor| press(editor, ":crash<CR>"))
+    }));
+    drop(guard);
+    // Put back the default hook, so that the other tests' panics are reported as usual
+    let _ = std::panic::take_hook();
+    assert!(result.is_err());
+    // The unsaved changes were kept, but the file itself was left alone
+    assert_eq!(
+        std::fs::read_to_string(crash::recovery_path(&path)).unwrap(),
+        "[\n    2\n]\n"
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1, 2]");
+    let report = std::fs::read_to_string(&crash_log).unwrap();
+    assert!(
+        report.contains("Simulated crash (from :crash)"),
+        "{}",
+        report
+    );
+    // The message was printed straight away by the editor, so there's nothing left to print
+    assert_eq!(crash::take_deferred(), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn render_help() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[true, false]");
+    press(&mut editor, "j?");
+    let frame = editor.render_to_string(60, 80, false);
+    assert!(frame.contains("│ Help for true"), "{}", frame);
+    assert!(frame.contains("│   0-9  number"), "{}", frame);
+    assert!(
+        frame.contains("Press any key to close the help."),
+        "{}",
+        frame
+    );
+    // A small screen can only show some of the help, which can then be scrolled
+    let frame = editor.render_to_string(60, 10, false);
+    assert!(frame.contains("j/k: scroll"), "{}", frame);
+    assert!(frame.contains("└ 7/74 ─"), "{}", frame);
+    press(&mut editor, "jj");
+    let frame = editor.render_to_string(60, 10, false);
+    assert!(
+        frame.lines().nth(1).unwrap().contains("│ r<c>: replace"),
+        "{}",
+        frame
+    );
+    // Any other key closes the help without doing anything else
+    press(&mut editor, "x");
+    let frame = editor.render_to_string(60, 10, false);
+    assert!(!frame.contains("Help for"), "{}", frame);
+    assert_eq!(editor.text(), "[\n    true,\n    false\n]\n");
+    press(&mut editor, "x");
+    assert_eq!(editor.text(), "[\n    false\n]\n");
+}
+
+#[test]
+fn render_hints() {
+    let arenas = Arenas::new();
+    let mut editor = editor(&arenas, "[true, false]");
+    press(&mut editor, ":set which_key_delay=0<CR>jr");
+    let frame = editor.render_to_string(60, 20, false);
+    assert!(frame.contains("── r ─"), "{}", frame);
+    assert!(frame.contains("0-9  number"), "{}", frame);
+    // The hints don't take the next key, which finishes the keystroke as normal
+    press(&mut editor, "n");
+    let frame = editor.render_to_string(60, 20, false);
+    assert!(!frame.contains("── r ─"), "{}", frame);
+    assert_eq!(editor.text(), "[\n    null,\n    false\n]\n");
+    // The hints can be turned off
+    press(&mut editor, ":set which_key=false<CR>\"");
+    let frame = editor.render_to_string(60, 20, false);
+    assert!(!frame.contains("the system clipboard"), "{}", frame);
+    press(&mut editor, "<ESC>:set which_key=true<CR>\"");
+    let frame = editor.render_to_string(60, 20, false);
+    assert!(frame.contains("+    the system clipboard"), "{}", frame);
+}
+
+/* ===== LARGE FILES ===== */
+
+/// Parses JSON in large-file mode, however short it is
+fn parse_large<'arena>(
+    text: &str,
+    arena: &'arena Arena<JSON<'arena>>,
+) -> Result<&'arena JSON<'arena>, String> {
+    JSON::parse_large(text, arena, Dialect::JSON).map_err(|e| e.to_string())
+}
+
+#[test]
+fn large_file() {
+    let arenas = Arenas::new();
+    // Lay the text out as the editor would, so that it's written back exactly as it was
+    let text = editor(
+        &arenas,
+        r#"{"records": [{"id": 1, "name": "the first record, which is long"}, {"id": 2}], "a": [1]}"#,
+    )
+    .text();
+    let loader = BufferLoader::new(&arenas, parse_large);
+    let buffer = loader.parse(&text, None).unwrap();
+    let mut editor = editor_of_buffer(loader, buffer, Config::default());
+    assert_eq!(editor.text(), text);
+    let frame = editor.render_to_string(100, 8, false);
+    assert!(
+        frame.starts_with("{\n    \"records\": [… 143 bytes, not parsed yet],\n    \"a\": [\n"),
+        "{}",
+        frame
+    );
+
+    // Searching and replacing can't look inside unparsed nodes, so they say what they missed.
+    // Searches still match unparsed nodes as a whole, but not the nodes inside them.
+    let note = "(without looking inside 1 unparsed node, which za unfolds)";
+    for keys in &[":%s/first/last<CR>", ":query $..name<CR>"] {
+        assert!(press(&mut editor, keys).0.is_some(), "{}", keys);
+        assert!(
+            editor
+                .status_message()
+                .unwrap()
+                .ends_with(&format!("{}.", note)),
+            "{:?}",
+            editor.status_message()
+        );
+    }
+    assert_eq!(press(&mut editor, "/first<CR>").0, None);
+    assert_eq!(
+        editor.status_message(),
+        Some(format!("1 match(es) for 'first' {}", note).as_str())
+    );
+    assert_eq!(editor.cursor_path().to_string(), "0.1");
+    assert_eq!(press(&mut editor, "<Esc>gg"), (None, false));
+    // Nothing inside an unparsed node can be reached until it's unfolded
+    assert!(press(&mut editor, "jjlj").0.is_some());
+    assert_eq!(editor.cursor_path().to_string(), "0.1");
+    assert_eq!(press(&mut editor, "zajza"), (None, false));
+    assert_eq!(editor.cursor_path().to_string(), "0.1.0");
+    // Parsing a node doesn't change the text, so there's nothing to save or undo
+    assert_eq!(editor.text(), text);
+    let frame = editor.render_to_string(100, 20, false);
+    assert!(!frame.contains("[+]"), "{}", frame);
+    // Short arrays and objects are parsed straight away
+    assert_eq!(press(&mut editor, "ljkh"), (None, false));
+    assert_eq!(press(&mut editor, "jx"), (None, false));
+    assert!(editor
+        .text()
+        .starts_with("{\n    \"records\": [\n        {\n            \"name\""));
+    assert_eq!(press(&mut editor, "u"), (None, false));
+    assert_eq!(editor.text(), text);
+}